@@ -37,6 +37,7 @@ impl XmlComponentTrait for ImgComponent {
             data_format,
             pixels: RawImageData::empty(data_format),
             tag: im_info,
+            source: None,
         };
 
         let im = Dom::image(image.to_internal()).style(CssApiWrapper::empty());
@@ -0,0 +1,64 @@
+use printpdf::*;
+
+fn main() {
+    let mut doc = PdfDocument::new("Overprint");
+
+    let square = |x: f32, y: f32| Polygon {
+        rings: vec![vec![
+            (Point::new(Mm(x), Mm(y)), false),
+            (Point::new(Mm(x + 80.0), Mm(y)), false),
+            (Point::new(Mm(x + 80.0), Mm(y + 80.0)), false),
+            (Point::new(Mm(x), Mm(y + 80.0)), false),
+        ]],
+        mode: PaintMode::Fill,
+        winding_order: WindingOrder::NonZero,
+    };
+
+    // overprint the magenta square, so the cyan ink underneath is not knocked out
+    // (only visible in a viewer with overprint preview or on a printing press)
+    let overprint = doc.add_graphics_state(
+        ExtendedGraphicsState::builder()
+            .with_overprint(true)
+            .with_overprint_mode(OverprintMode::KeepUnderlying)
+            .build(),
+    );
+
+    // semi-transparent square, identical states are only added once
+    let transparent = ExtendedGraphicsState::builder()
+        .with_alpha(0.5)
+        .with_blend_mode(BlendMode::multiply())
+        .build();
+    let transparent_id = doc.add_graphics_state(transparent.clone());
+    assert_eq!(transparent_id, doc.add_graphics_state(transparent));
+
+    let ops = vec![
+        Op::SetFillColor {
+            col: Color::Cmyk(Cmyk::new(1.0, 0.0, 0.0, 0.0, None)),
+        },
+        Op::DrawPolygon {
+            polygon: square(20.0, 180.0),
+        },
+        Op::SaveGraphicsState,
+        Op::LoadGraphicsState { gs: overprint },
+        Op::SetFillColor {
+            col: Color::Cmyk(Cmyk::new(0.0, 1.0, 0.0, 0.0, None)),
+        },
+        Op::DrawPolygon {
+            polygon: square(60.0, 140.0),
+        },
+        Op::RestoreGraphicsState,
+        Op::SaveGraphicsState,
+        Op::LoadGraphicsState { gs: transparent_id },
+        Op::SetFillColor {
+            col: Color::Cmyk(Cmyk::new(0.0, 0.0, 1.0, 0.0, None)),
+        },
+        Op::DrawPolygon {
+            polygon: square(100.0, 100.0),
+        },
+        Op::RestoreGraphicsState,
+    ];
+
+    let page = PdfPage::new(Mm(210.0), Mm(297.0), ops);
+    let bytes = doc.with_pages(vec![page]).save(&PdfSaveOptions::default());
+    let _ = std::fs::write("overprint.pdf", bytes);
+}
@@ -130,8 +130,6 @@ fn main() {
     ]);
 
     let svg = Svg::parse(SVG).unwrap();
-    let rotation_center_x = Px((svg.width.unwrap_or_default().0 as f32 / 2.0) as usize);
-    let rotation_center_y = Px((svg.height.unwrap_or_default().0 as f32 / 2.0) as usize);
     let xobject_id = doc.add_xobject(&svg);
 
     let svg_layer = doc.add_layer(&Layer::new("SVG content"));
@@ -141,16 +139,13 @@ fn main() {
 
     for i in 0..10 {
         let transform = XObjectTransform {
-            rotate: Some(XObjectRotation {
-                angle_ccw_degrees: i as f32 * 36.0,
-                rotation_center_x,
-                rotation_center_y,
-            }),
+            rotate: Some(XObjectRotation::around_center(i as f32 * 36.0)),
             translate_x: Some(Mm(i as f32 * 20.0 % 50.0).into()),
             translate_y: Some(Mm(i as f32 * 30.0).into()),
             dpi: Some(300.0),
             scale_x: None,
             scale_y: None,
+            rendering_intent: None,
         };
 
         ops.extend_from_slice(&[Op::UseXObject {
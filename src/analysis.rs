@@ -0,0 +1,537 @@
+//! Document analysis: statistics and ink coverage / total area coverage (TAC) for
+//! print production
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    color::Color,
+    graphics::{PaintMode, Point},
+    image::RawImageData,
+    ops::Op,
+    PdfDocument, PdfPage, PdfSaveOptions, StreamCompression, XObject,
+};
+
+/// Summary of the document contents, see `PdfDocument::stats`
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PdfDocumentStats {
+    /// Number of pages
+    pub page_count: usize,
+    /// Number of ops of every type (i.e. `"DrawLine"`) on all pages, see `Op::get_name`
+    pub op_counts: BTreeMap<&'static str, usize>,
+    /// Number of fonts
+    pub font_count: usize,
+    /// Size of the font files in bytes (before subsetting)
+    pub font_bytes: usize,
+    /// Number of image XObjects
+    pub image_count: usize,
+    /// Size of the image data in bytes (the encoded source or the decoded pixels)
+    pub image_bytes: usize,
+    /// Number of other XObjects (forms, groups, embedded pages)
+    pub xobject_count: usize,
+    /// Number of annotations (including links)
+    pub annotation_count: usize,
+    /// Number of link annotations
+    pub link_count: usize,
+    /// Number of form fields
+    pub form_field_count: usize,
+    /// Estimated size of the saved document with the default `PdfSaveOptions` in bytes,
+    /// see `PdfDocument::estimate_saved_size`
+    pub estimated_size: usize,
+}
+
+impl PdfDocument {
+    /// Counts the pages, ops, fonts, images and annotations of the document, i.e. for
+    /// dashboards or to decide whether a document should be optimized
+    pub fn stats(&self) -> PdfDocumentStats {
+        let mut stats = PdfDocumentStats {
+            page_count: self.pages.len(),
+            font_count: self.resources.fonts.map.len(),
+            font_bytes: self
+                .resources
+                .fonts
+                .map
+                .values()
+                .map(|f| f.original_bytes.len())
+                .sum(),
+            form_field_count: self.resources.forms.map.len(),
+            ..Default::default()
+        };
+
+        for op in self.pages.iter().flat_map(|p| p.ops.iter()) {
+            *stats.op_counts.entry(op.get_name()).or_default() += 1;
+            match op {
+                Op::LinkAnnotation { .. } => {
+                    stats.link_count += 1;
+                    stats.annotation_count += 1;
+                }
+                Op::AddAnnotation { .. } => stats.annotation_count += 1,
+                Op::AddFormField { .. } => stats.form_field_count += 1,
+                _ => {}
+            }
+        }
+
+        for xobject in self.resources.xobjects.map.values() {
+            match xobject {
+                XObject::Image(image) => {
+                    stats.image_count += 1;
+                    stats.image_bytes += match image.source.as_ref() {
+                        Some(source) if image.pixels.is_empty() => source.bytes.len(),
+                        _ => get_pixel_bytes(&image.pixels),
+                    };
+                }
+                _ => stats.xobject_count += 1,
+            }
+        }
+
+        stats.estimated_size = self.estimate_saved_size(&PdfSaveOptions::default());
+        stats
+    }
+
+    /// Estimates the size of the saved document in bytes without serializing it, i.e. to
+    /// warn users before generating oversized documents. The compression ratio of images,
+    /// forms and embedded files is measured on a sample of their data, the sizes of the
+    /// content streams and font subsets are approximated from the ops and used glyphs.
+    pub fn estimate_saved_size(&self, opts: &PdfSaveOptions) -> usize {
+        let compression = opts.compression;
+        // catalog, document info, page tree, page dictionaries and cross-reference table
+        let mut structure = 1500 + self.pages.len() * 350;
+        let mut streams = 0;
+
+        let mut glyphs = BTreeMap::new();
+        let mut builtin_fonts = BTreeSet::new();
+        for op in self.pages.iter().flat_map(|p| p.ops.iter()) {
+            match op {
+                Op::WriteText { text, font, .. } => glyphs
+                    .entry(font)
+                    .or_insert_with(BTreeSet::new)
+                    .extend(text.chars().map(|c| c as u32)),
+                Op::WriteCodepoints { font, cp, .. } => glyphs
+                    .entry(font)
+                    .or_insert_with(BTreeSet::new)
+                    .extend(cp.iter().map(|(gid, _)| *gid as u32)),
+                Op::WriteCodepointsWithKerning { font, cpk, .. } => glyphs
+                    .entry(font)
+                    .or_insert_with(BTreeSet::new)
+                    .extend(cpk.iter().map(|(_, gid, _)| *gid as u32)),
+                Op::WriteTextBuiltinFont { font, .. } => {
+                    builtin_fonts.insert(*font);
+                }
+                Op::AddAnnotation { .. } | Op::AddFormField { .. } => structure += 600,
+                Op::LinkAnnotation { .. } => structure += 200,
+                _ => {}
+            }
+        }
+
+        for page in self.pages.iter() {
+            let content = get_ops_size(&page.ops);
+            streams += if opts.compress_content_streams {
+                get_estimated_content_size(content, compression)
+            } else {
+                content
+            };
+        }
+
+        // font streams are not compressed, the subset contains the used glyphs and the
+        // tables, the font dictionaries the widths and the ToUnicode map
+        for (id, font) in self.resources.fonts.map.iter() {
+            let used = glyphs.get(id).map(|g| g.len()).unwrap_or(0);
+            let original = font.original_bytes.len();
+            let subset = original * used / (font.num_glyphs as usize).max(1) + 4096;
+            streams += if opts.subset_fonts {
+                subset.min(original)
+            } else {
+                original
+            };
+            structure += 1000 + used * 20;
+        }
+        structure += builtin_fonts.len() * 150;
+
+        for xobject in self.resources.xobjects.map.values() {
+            structure += 250;
+            streams += match xobject {
+                XObject::Image(image) => match image.source.as_ref() {
+                    Some(source) if image.pixels.is_empty() => source.bytes.len(),
+                    _ => get_estimated_image_size(&image.pixels, compression),
+                },
+                XObject::Form(f) => get_estimated_stream_size(&f.bytes, compression),
+                XObject::Ops(o) => get_estimated_content_size(get_ops_size(&o.ops), compression),
+                XObject::Group(g) => get_estimated_content_size(get_ops_size(&g.ops), compression),
+                XObject::External(_) => 0,
+            };
+        }
+
+        for profile in self.resources.icc_profiles.map.values() {
+            structure += 200;
+            streams += get_estimated_stream_size(&profile.icc, compression);
+        }
+        for file in self.attachments.iter() {
+            structure += 400;
+            streams += get_estimated_stream_size(&file.bytes, compression);
+        }
+
+        if opts.use_object_streams {
+            structure = structure * 3 / 4;
+        }
+        structure + streams
+    }
+}
+
+/// Number of bytes of a stream that are compressed to estimate its compression ratio
+const SAMPLE_SIZE: usize = 32 * 1024;
+
+/// Approximate size of the ops in a content stream
+fn get_ops_size(ops: &[Op]) -> usize {
+    ops.iter()
+        .map(|op| match op {
+            // glyph IDs are written as hex strings
+            Op::WriteText { text, .. } => text.chars().count() * 4 + 20,
+            Op::WriteTextBuiltinFont { text, .. } => text.len() * 2 + 20,
+            Op::WriteCodepoints { cp, .. } => cp.len() * 4 + 20,
+            Op::WriteCodepointsWithKerning { cpk, .. } => cpk.len() * 10 + 20,
+            Op::DrawLine { line } => line.points.len() * 20 + 10,
+            Op::DrawPolygon { polygon } => {
+                polygon.rings.iter().map(|r| r.len() * 20).sum::<usize>() + 10
+            }
+            Op::UseXObject { .. } => 80,
+            Op::LinkAnnotation { .. } | Op::AddAnnotation { .. } | Op::AddFormField { .. } => 0,
+            _ => 16,
+        })
+        .sum()
+}
+
+/// Content streams (ASCII operators and numbers) compress to about a third with Flate
+fn get_estimated_content_size(size: usize, compression: StreamCompression) -> usize {
+    match compression {
+        StreamCompression::None | StreamCompression::Flate { level: 0 } => size,
+        StreamCompression::Lzw => size / 2,
+        StreamCompression::Flate { .. } => size / 3,
+    }
+}
+
+/// Compresses the start of the stream and extrapolates the size
+fn get_estimated_stream_size(bytes: &[u8], compression: StreamCompression) -> usize {
+    let sample = &bytes[..bytes.len().min(SAMPLE_SIZE)];
+    if sample.is_empty() {
+        return 0;
+    }
+    let encoded = match compression {
+        StreamCompression::None => return bytes.len(),
+        StreamCompression::Flate { level } => crate::filters::flate_encode(sample, level),
+        StreamCompression::Lzw => crate::filters::lzw_encode(sample),
+    };
+    // streams that don't get smaller are written uncompressed
+    let ratio = (encoded.len() as f64 / sample.len() as f64).min(1.0);
+    (bytes.len() as f64 * ratio) as usize
+}
+
+/// Size of the image stream (8-bit samples or 16-bit samples for `RawImageData::U16`)
+fn get_estimated_image_size(pixels: &RawImageData, compression: StreamCompression) -> usize {
+    let (sample, total) = match pixels {
+        RawImageData::U8(v) => return get_estimated_stream_size(v, compression),
+        RawImageData::U16(v) => (
+            v.iter()
+                .take(SAMPLE_SIZE / 2)
+                .flat_map(|s| s.to_be_bytes())
+                .collect::<Vec<_>>(),
+            v.len() * 2,
+        ),
+        RawImageData::F32(v) => (
+            v.iter()
+                .take(SAMPLE_SIZE)
+                .map(|s| (s.clamp(0.0, 1.0) * 255.0) as u8)
+                .collect::<Vec<_>>(),
+            v.len(),
+        ),
+    };
+    let estimate = get_estimated_stream_size(&sample, compression);
+    (total as f64 * estimate as f64 / sample.len().max(1) as f64) as usize
+}
+
+/// Size of the decoded pixels in bytes
+fn get_pixel_bytes(pixels: &RawImageData) -> usize {
+    match pixels {
+        RawImageData::U8(v) => v.len(),
+        RawImageData::U16(v) => v.len() * 2,
+        RawImageData::F32(v) => v.len() * 4,
+    }
+}
+
+/// Ink coverage of a single page
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PageInkCoverage {
+    /// Page index (0-based)
+    pub page: usize,
+    /// Highest total area coverage (C + M + Y + K, in percent, 0 - 400) of any color
+    /// used for filling or stroking on this page
+    pub max_tac: f32,
+    /// Average ink coverage over the page area per channel (C, M, Y, K, in percent)
+    pub average_ink: [f32; 4],
+}
+
+impl PageInkCoverage {
+    /// Average total ink coverage over the whole page area (sum of all channels, in percent)
+    pub fn get_average_tac(&self) -> f32 {
+        self.average_ink.iter().sum()
+    }
+
+    /// Returns whether any color on the page exceeds the given TAC limit (i.e. 300.0)
+    pub fn exceeds(&self, tac_limit: f32) -> bool {
+        self.max_tac > tac_limit
+    }
+}
+
+impl PdfDocument {
+    /// Computes the ink coverage for every page
+    ///
+    /// NOTE: This is an estimate based on the colors of vector fills, strokes and text:
+    /// RGB and greyscale colors are converted to CMYK without color management, images
+    /// are not analyzed and overlapping shapes are not rasterized.
+    pub fn get_ink_coverage(&self) -> Vec<PageInkCoverage> {
+        self.pages
+            .iter()
+            .enumerate()
+            .map(|(i, p)| page_ink_coverage(i, p))
+            .collect()
+    }
+
+    /// Returns the indices of all pages that use colors exceeding the given TAC limit
+    pub fn get_pages_exceeding_tac(&self, tac_limit: f32) -> Vec<usize> {
+        self.get_ink_coverage()
+            .into_iter()
+            .filter(|c| c.exceeds(tac_limit))
+            .map(|c| c.page)
+            .collect()
+    }
+}
+
+fn page_ink_coverage(page_idx: usize, page: &PdfPage) -> PageInkCoverage {
+    let page_area = (page.media_box.width.0 * page.media_box.height.0).abs();
+
+    let black = [0.0, 0.0, 0.0, 1.0];
+    let mut fill = black;
+    let mut stroke = black;
+    let mut stack = Vec::new();
+
+    let mut max_tac: f32 = 0.0;
+    let mut ink = [0.0_f32; 4];
+
+    let tac = |c: &[f32; 4]| c.iter().sum::<f32>() * 100.0;
+
+    for op in page.ops.iter() {
+        match op {
+            Op::SaveGraphicsState => stack.push((fill, stroke)),
+            Op::RestoreGraphicsState => {
+                if let Some((f, s)) = stack.pop() {
+                    fill = f;
+                    stroke = s;
+                }
+            }
+            Op::SetFillColor { col } => fill = color_to_cmyk(col),
+            Op::SetOutlineColor { col } => stroke = color_to_cmyk(col),
+            Op::WriteText { .. }
+            | Op::WriteTextBuiltinFont { .. }
+            | Op::WriteCodepoints { .. }
+            | Op::WriteCodepointsWithKerning { .. } => {
+                max_tac = max_tac.max(tac(&fill));
+            }
+            Op::DrawLine { .. } => {
+                max_tac = max_tac.max(tac(&stroke));
+            }
+            Op::DrawPolygon { polygon } => {
+                let fills = matches!(polygon.mode, PaintMode::Fill | PaintMode::FillStroke);
+                let strokes = matches!(polygon.mode, PaintMode::Stroke | PaintMode::FillStroke);
+                if strokes {
+                    max_tac = max_tac.max(tac(&stroke));
+                }
+                if fills {
+                    max_tac = max_tac.max(tac(&fill));
+                    let area = polygon_area(&polygon.rings);
+                    for (i, channel) in ink.iter_mut().enumerate() {
+                        *channel += fill[i] * area;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let average_ink = if page_area > 0.0 {
+        ink.map(|c| (c / page_area * 100.0).min(100.0))
+    } else {
+        [0.0; 4]
+    };
+
+    PageInkCoverage {
+        page: page_idx,
+        max_tac,
+        average_ink,
+    }
+}
+
+/// Area of all rings (holes with the opposite winding direction are subtracted)
+fn polygon_area(rings: &[Vec<(Point, bool)>]) -> f32 {
+    let signed = rings
+        .iter()
+        .map(|ring| {
+            let n = ring.len();
+            (0..n)
+                .map(|i| {
+                    let (a, _) = &ring[i];
+                    let (b, _) = &ring[(i + 1) % n];
+                    a.x.0 * b.y.0 - b.x.0 * a.y.0
+                })
+                .sum::<f32>()
+                / 2.0
+        })
+        .sum::<f32>();
+    signed.abs()
+}
+
+/// Converts a color to CMYK (0.0 - 1.0) without color management
+fn color_to_cmyk(col: &Color) -> [f32; 4] {
+    match col {
+        Color::Cmyk(c) => [c.c, c.m, c.y, c.k],
+        Color::SpotColor(c) => [c.c, c.m, c.y, c.k],
+        Color::Greyscale(g) => [0.0, 0.0, 0.0, 1.0 - g.percent],
+        Color::Indexed(_) | Color::DeviceN(_) => color_to_cmyk(&col.get_device_color()),
+        Color::Rgb(rgb) => {
+            let k = 1.0 - rgb.r.max(rgb.g).max(rgb.b);
+            if k >= 1.0 {
+                [0.0, 0.0, 0.0, 1.0]
+            } else {
+                [
+                    (1.0 - rgb.r - k) / (1.0 - k),
+                    (1.0 - rgb.g - k) / (1.0 - k),
+                    (1.0 - rgb.b - k) / (1.0 - k),
+                    k,
+                ]
+            }
+        }
+    }
+}
+
+#[test]
+fn test_ink_coverage() {
+    use crate::{Cmyk, Mm, Polygon, Pt, WindingOrder};
+
+    let half_page = Polygon {
+        rings: vec![vec![
+            (Point::new(Mm(0.0), Mm(0.0)), false),
+            (Point::new(Mm(100.0), Mm(0.0)), false),
+            (Point::new(Mm(100.0), Mm(100.0)), false),
+            (Point::new(Mm(0.0), Mm(100.0)), false),
+        ]],
+        mode: PaintMode::Fill,
+        winding_order: WindingOrder::NonZero,
+    };
+
+    let mut doc = PdfDocument::new("tac");
+    doc.pages.push(PdfPage::new(
+        Mm(200.0),
+        Mm(100.0),
+        vec![
+            Op::SetFillColor {
+                col: Color::Cmyk(Cmyk::new(1.0, 1.0, 1.0, 0.5, None)),
+            },
+            Op::DrawPolygon { polygon: half_page },
+        ],
+    ));
+
+    let coverage = doc.get_ink_coverage();
+    assert_eq!(coverage.len(), 1);
+    assert_eq!(Pt(coverage[0].max_tac), Pt(350.0));
+    assert_eq!(Pt(coverage[0].average_ink[0]), Pt(50.0));
+    assert_eq!(Pt(coverage[0].get_average_tac()), Pt(175.0));
+    assert_eq!(doc.get_pages_exceeding_tac(300.0), vec![0]);
+    assert!(doc.get_pages_exceeding_tac(400.0).is_empty());
+}
+
+#[test]
+fn test_stats() {
+    use crate::{LinkAnnotation, Mm, RawImage, RawImageFormat, Rect};
+
+    let mut doc = PdfDocument::new("stats");
+    doc.add_image(&RawImage {
+        pixels: RawImageData::U16(vec![0; 12]),
+        width: 2,
+        height: 2,
+        data_format: RawImageFormat::RGB16,
+        tag: Vec::new(),
+        source: None,
+    });
+    let link = LinkAnnotation::new(
+        Rect::from_wh(Mm(10.0).into(), Mm(10.0).into()),
+        crate::Actions::URI("https://example.com".to_string()),
+        None,
+        None,
+        None,
+    );
+    for _ in 0..2 {
+        doc.pages.push(PdfPage::new(
+            Mm(210.0),
+            Mm(297.0),
+            vec![
+                Op::SaveGraphicsState,
+                Op::LinkAnnotation { link: link.clone() },
+                Op::RestoreGraphicsState,
+            ],
+        ));
+    }
+
+    let stats = doc.stats();
+    assert_eq!(stats.page_count, 2);
+    assert_eq!(stats.op_counts["SaveGraphicsState"], 2);
+    assert_eq!(stats.op_counts["LinkAnnotation"], 2);
+    assert_eq!(stats.op_counts.get("DrawLine"), None);
+    assert_eq!(stats.annotation_count, 2);
+    assert_eq!(stats.link_count, 2);
+    assert_eq!((stats.image_count, stats.image_bytes), (1, 24));
+    assert_eq!(stats.font_count, 0);
+    assert!(stats.estimated_size > 0);
+}
+
+#[test]
+fn test_estimate_saved_size() {
+    use crate::{BuiltinFont, Mm, Pt, RawImage, RawImageFormat};
+
+    let mut doc = PdfDocument::new("estimate");
+    // one well and one badly compressible image
+    let gradient = (0..128 * 128 * 3)
+        .map(|i| (i / 384) as u8)
+        .collect::<Vec<_>>();
+    let noise = (0..128 * 128 * 3u64)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 11) as u8)
+        .collect::<Vec<_>>();
+    for pixels in [gradient, noise] {
+        doc.add_image(&RawImage {
+            pixels: RawImageData::U8(pixels),
+            width: 128,
+            height: 128,
+            data_format: RawImageFormat::RGB8,
+            tag: Vec::new(),
+            source: None,
+        });
+    }
+    for i in 0..5 {
+        let ops = vec![Op::WriteTextBuiltinFont {
+            text: format!("page {i}"),
+            size: Pt(12.0),
+            font: BuiltinFont::Helvetica,
+        }];
+        doc.pages.push(PdfPage::new(Mm(210.0), Mm(297.0), ops));
+    }
+
+    for compression in [StreamCompression::default(), StreamCompression::None] {
+        let opts = PdfSaveOptions {
+            compression,
+            ..Default::default()
+        };
+        let estimate = doc.estimate_saved_size(&opts);
+        let actual = doc.save(&opts).len();
+        assert!(estimate > actual / 2 && estimate < actual * 2);
+    }
+    assert_eq!(
+        doc.stats().estimated_size,
+        doc.estimate_saved_size(&PdfSaveOptions::default())
+    );
+}
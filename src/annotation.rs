@@ -1,6 +1,25 @@
-//! Bookmarks, page and link annotations
+//! Bookmarks, page, link and markup (review comment) annotations
 
-use crate::graphics::Rect;
+use lopdf::content::{Content, Operation as LoOp};
+use lopdf::Object::{
+    Array, Boolean, Dictionary, Integer, Name, Real, Reference, String as LoString,
+};
+use lopdf::StringFormat::Literal;
+use lopdf::{Dictionary as LoDictionary, ObjectId, Stream as LoStream};
+use std::collections::BTreeMap;
+
+use crate::{
+    date::OffsetDateTime,
+    forms::{
+        color_to_array, color_to_da, color_to_ops, decode_text_string, parse_color,
+        parse_default_appearance, parse_numbers, parse_rect, resolve, text_string,
+    },
+    graphics::{Point, Rect},
+    outline::{parse_action, parse_destination},
+    units::Pt,
+    utils::{parse_pdf_date, to_pdf_time_stamp_metadata},
+    BuiltinFont, Op, PdfPage,
+};
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct PageAnnotation {
@@ -36,6 +55,855 @@ impl LinkAnnotation {
             highlighting: highlighting.unwrap_or_default(),
         }
     }
+
+    /// Link to a web page, without a visible border
+    pub fn web(rect: Rect, url: &str) -> Self {
+        Self::borderless(rect, Actions::uri(url.to_string()))
+    }
+
+    /// Link to a page of the document (1-based, like `Destination`), without a visible
+    /// border. The viewer keeps the current scroll position if `zoom` is `None`.
+    pub fn internal(rect: Rect, page: usize, zoom: Option<f32>) -> Self {
+        let destination = Destination::XYZ {
+            page,
+            left: None,
+            top: None,
+            zoom,
+        };
+        Self::borderless(rect, Actions::go_to(destination))
+    }
+
+    /// Link that opens the mail program, without a visible border
+    pub fn mailto(rect: Rect, address: &str) -> Self {
+        let uri = if address.starts_with("mailto:") {
+            address.to_string()
+        } else {
+            format!("mailto:{address}")
+        };
+        Self::borderless(rect, Actions::uri(uri))
+    }
+
+    fn borderless(rect: Rect, actions: Actions) -> Self {
+        Self::new(
+            rect,
+            actions,
+            Some(BorderArray::Solid([0.0, 0.0, 0.0])),
+            Some(ColorArray::Transparent),
+            None,
+        )
+    }
+
+    /// Draws a solid border with the given width and color around the link
+    pub fn with_border(mut self, width: f32, color: ColorArray) -> Self {
+        self.border = BorderArray::Solid([0.0, 0.0, width]);
+        self.color = color;
+        self
+    }
+
+    /// Sets how the link is highlighted when it is clicked
+    pub fn with_highlighting(mut self, highlighting: HighlightingMode) -> Self {
+        self.highlighting = highlighting;
+        self
+    }
+}
+
+impl PdfPage {
+    /// Adds a link to a web page (see `LinkAnnotation::web`)
+    pub fn add_web_link(&mut self, rect: Rect, url: &str) {
+        let link = LinkAnnotation::web(rect, url);
+        self.ops.push(Op::LinkAnnotation { link });
+    }
+
+    /// Adds a link to a page of the document (see `LinkAnnotation::internal`)
+    pub fn add_internal_link(&mut self, rect: Rect, page: usize, zoom: Option<f32>) {
+        let link = LinkAnnotation::internal(rect, page, zoom);
+        self.ops.push(Op::LinkAnnotation { link });
+    }
+
+    /// Adds a link that opens the mail program (see `LinkAnnotation::mailto`)
+    pub fn add_mailto_link(&mut self, rect: Rect, address: &str) {
+        let link = LinkAnnotation::mailto(rect, address);
+        self.ops.push(Op::LinkAnnotation { link });
+    }
+}
+
+/// Markup annotation (review comment), placed on a page with `Op::AddAnnotation`
+#[derive(Debug, PartialEq, Clone)]
+pub enum MarkupAnnotation {
+    /// Text displayed directly on the page
+    FreeText(FreeTextAnnotation),
+    /// Sticky note: an icon that opens a popup window with the comment
+    Popup(PopupAnnotation),
+    /// Freehand drawing
+    Ink(InkAnnotation),
+    /// Closed polygon or open polyline
+    Polygon(PolygonAnnotation),
+}
+
+impl MarkupAnnotation {
+    /// Returns the position of the annotation on the page (for ink and polygon
+    /// annotations the bounding box of the points, including the line width)
+    pub fn get_rect(&self) -> Rect {
+        match self {
+            MarkupAnnotation::FreeText(a) => a.rect.clone(),
+            MarkupAnnotation::Popup(a) => a.rect.clone(),
+            MarkupAnnotation::Ink(a) => get_bounding_box(a.ink_list.iter().flatten(), a.line_width),
+            MarkupAnnotation::Polygon(a) => get_bounding_box(a.vertices.iter(), a.line_width),
+        }
+    }
+}
+
+/// Free text annotation (`/Subtype /FreeText`): a comment written directly on the page
+#[derive(Debug, PartialEq, Clone)]
+pub struct FreeTextAnnotation {
+    /// Position of the text box on the page
+    pub rect: Rect,
+    /// Text of the comment (lines are separated with `\n`)
+    pub contents: String,
+    /// Author of the comment (`/T`)
+    pub author: Option<String>,
+    /// Creation date of the comment
+    pub creation_date: Option<OffsetDateTime>,
+    /// Font of the text
+    pub font: BuiltinFont,
+    /// Font size of the text
+    pub font_size: Pt,
+    /// Color of the text
+    pub text_color: ColorArray,
+    /// Background color of the text box (`None` = transparent)
+    pub background_color: Option<ColorArray>,
+}
+
+impl FreeTextAnnotation {
+    /// Creates a new free text annotation (10pt Helvetica, black)
+    pub fn new(rect: Rect, contents: &str) -> Self {
+        Self {
+            rect,
+            contents: contents.to_string(),
+            author: None,
+            creation_date: None,
+            font: BuiltinFont::Helvetica,
+            font_size: Pt(10.0),
+            text_color: ColorArray::Gray([0.0]),
+            background_color: None,
+        }
+    }
+
+    /// Sets the author of the comment
+    pub fn with_author(mut self, author: &str) -> Self {
+        self.author = Some(author.to_string());
+        self
+    }
+
+    /// Sets the creation date of the comment
+    pub fn with_creation_date(mut self, date: OffsetDateTime) -> Self {
+        self.creation_date = Some(date);
+        self
+    }
+
+    /// Sets the font and font size of the text
+    pub fn with_font(mut self, font: BuiltinFont, font_size: Pt) -> Self {
+        self.font = font;
+        self.font_size = font_size;
+        self
+    }
+
+    /// Sets the color of the text
+    pub fn with_text_color(mut self, color: ColorArray) -> Self {
+        self.text_color = color;
+        self
+    }
+
+    /// Sets the background color of the text box
+    pub fn with_background_color(mut self, color: ColorArray) -> Self {
+        self.background_color = Some(color);
+        self
+    }
+}
+
+/// Sticky note (`/Subtype /Text` with a `/Popup` annotation): the comment is shown
+/// in a popup window when the note icon is clicked
+#[derive(Debug, PartialEq, Clone)]
+pub struct PopupAnnotation {
+    /// Position of the note icon on the page
+    pub rect: Rect,
+    /// Text of the comment
+    pub contents: String,
+    /// Author of the comment (`/T`), shown in the title bar of the popup
+    pub author: Option<String>,
+    /// Creation date of the comment
+    pub creation_date: Option<OffsetDateTime>,
+    /// Whether the popup window is initially open
+    pub open: bool,
+    /// Color of the icon and the popup title bar
+    pub color: Option<ColorArray>,
+    /// Position of the popup window (`None` = chosen by the viewer)
+    pub popup_rect: Option<Rect>,
+}
+
+impl PopupAnnotation {
+    /// Creates a new, closed sticky note
+    pub fn new(rect: Rect, contents: &str) -> Self {
+        Self {
+            rect,
+            contents: contents.to_string(),
+            author: None,
+            creation_date: None,
+            open: false,
+            color: None,
+            popup_rect: None,
+        }
+    }
+
+    /// Sets the author of the comment
+    pub fn with_author(mut self, author: &str) -> Self {
+        self.author = Some(author.to_string());
+        self
+    }
+
+    /// Sets the creation date of the comment
+    pub fn with_creation_date(mut self, date: OffsetDateTime) -> Self {
+        self.creation_date = Some(date);
+        self
+    }
+
+    /// Opens the popup window when the document is opened
+    pub fn with_open(mut self, open: bool) -> Self {
+        self.open = open;
+        self
+    }
+
+    /// Sets the color of the icon and the popup title bar
+    pub fn with_color(mut self, color: ColorArray) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    /// Sets the position of the popup window
+    pub fn with_popup_rect(mut self, rect: Rect) -> Self {
+        self.popup_rect = Some(rect);
+        self
+    }
+}
+
+/// Freehand ink annotation (`/Subtype /Ink`), one stroke per list of points
+#[derive(Debug, PartialEq, Clone)]
+pub struct InkAnnotation {
+    /// Strokes, each a list of points on the page
+    pub ink_list: Vec<Vec<Point>>,
+    /// Comment shown in the popup of the annotation
+    pub contents: String,
+    /// Author of the annotation (`/T`)
+    pub author: Option<String>,
+    /// Creation date of the annotation
+    pub creation_date: Option<OffsetDateTime>,
+    /// Color of the strokes
+    pub color: ColorArray,
+    /// Width of the strokes
+    pub line_width: Pt,
+}
+
+impl InkAnnotation {
+    /// Creates a new ink annotation (1pt, black)
+    pub fn new(ink_list: Vec<Vec<Point>>) -> Self {
+        Self {
+            ink_list,
+            contents: String::new(),
+            author: None,
+            creation_date: None,
+            color: ColorArray::Gray([0.0]),
+            line_width: Pt(1.0),
+        }
+    }
+
+    /// Sets the comment of the annotation
+    pub fn with_contents(mut self, contents: &str) -> Self {
+        self.contents = contents.to_string();
+        self
+    }
+
+    /// Sets the author of the annotation
+    pub fn with_author(mut self, author: &str) -> Self {
+        self.author = Some(author.to_string());
+        self
+    }
+
+    /// Sets the creation date of the annotation
+    pub fn with_creation_date(mut self, date: OffsetDateTime) -> Self {
+        self.creation_date = Some(date);
+        self
+    }
+
+    /// Sets the color and width of the strokes
+    pub fn with_stroke(mut self, color: ColorArray, line_width: Pt) -> Self {
+        self.color = color;
+        self.line_width = line_width;
+        self
+    }
+}
+
+/// Polygon (`/Subtype /Polygon`) or polyline (`/Subtype /PolyLine`) annotation,
+/// i.e. an area or a distance marked up by measurement software
+#[derive(Debug, PartialEq, Clone)]
+pub struct PolygonAnnotation {
+    /// Vertices on the page
+    pub vertices: Vec<Point>,
+    /// Closed polygon (`true`) or open polyline (`false`)
+    pub closed: bool,
+    /// Comment shown in the popup of the annotation
+    pub contents: String,
+    /// Author of the annotation (`/T`)
+    pub author: Option<String>,
+    /// Creation date of the annotation
+    pub creation_date: Option<OffsetDateTime>,
+    /// Color of the outline
+    pub color: ColorArray,
+    /// Fill color of a closed polygon (`None` = not filled)
+    pub interior_color: Option<ColorArray>,
+    /// Width of the outline
+    pub line_width: Pt,
+}
+
+impl PolygonAnnotation {
+    /// Creates a closed polygon (1pt, black, not filled)
+    pub fn polygon(vertices: Vec<Point>) -> Self {
+        Self {
+            vertices,
+            closed: true,
+            contents: String::new(),
+            author: None,
+            creation_date: None,
+            color: ColorArray::Gray([0.0]),
+            interior_color: None,
+            line_width: Pt(1.0),
+        }
+    }
+
+    /// Creates an open polyline (1pt, black)
+    pub fn polyline(vertices: Vec<Point>) -> Self {
+        Self {
+            closed: false,
+            ..Self::polygon(vertices)
+        }
+    }
+
+    /// Sets the comment of the annotation
+    pub fn with_contents(mut self, contents: &str) -> Self {
+        self.contents = contents.to_string();
+        self
+    }
+
+    /// Sets the author of the annotation
+    pub fn with_author(mut self, author: &str) -> Self {
+        self.author = Some(author.to_string());
+        self
+    }
+
+    /// Sets the creation date of the annotation
+    pub fn with_creation_date(mut self, date: OffsetDateTime) -> Self {
+        self.creation_date = Some(date);
+        self
+    }
+
+    /// Sets the color and width of the outline
+    pub fn with_stroke(mut self, color: ColorArray, line_width: Pt) -> Self {
+        self.color = color;
+        self.line_width = line_width;
+        self
+    }
+
+    /// Sets the fill color of the polygon
+    pub fn with_interior_color(mut self, color: ColorArray) -> Self {
+        self.interior_color = Some(color);
+        self
+    }
+}
+
+/// Bounding box of the points, enlarged by half the line width on each side
+fn get_bounding_box<'a>(points: impl Iterator<Item = &'a Point>, line_width: Pt) -> Rect {
+    let mut bounds: Option<(f32, f32, f32, f32)> = None;
+    for p in points {
+        let (x0, y0, x1, y1) = bounds.unwrap_or((p.x.0, p.y.0, p.x.0, p.y.0));
+        bounds = Some((x0.min(p.x.0), y0.min(p.y.0), x1.max(p.x.0), y1.max(p.y.0)));
+    }
+    let Some((x0, y0, x1, y1)) = bounds else {
+        return Rect::from_wh(Pt(0.0), Pt(0.0));
+    };
+    let half = line_width.0 / 2.0;
+    Rect {
+        x: Pt(x0 - half),
+        y: Pt(y0 - half),
+        width: Pt(x1 - x0 + line_width.0),
+        height: Pt(y1 - y0 + line_width.0),
+    }
+}
+
+fn points_to_array<'a>(points: impl Iterator<Item = &'a Point>) -> lopdf::Object {
+    Array(points.flat_map(|p| [Real(p.x.0), Real(p.y.0)]).collect())
+}
+
+fn parse_points(doc: &lopdf::Document, obj: &lopdf::Object) -> Option<Vec<Point>> {
+    let numbers = parse_numbers(doc, obj)?;
+    Some(
+        numbers
+            .chunks_exact(2)
+            .map(|c| Point {
+                x: Pt(c[0]),
+                y: Pt(c[1]),
+            })
+            .collect(),
+    )
+}
+
+/// Appearance stream of ink and polygon annotations, in page coordinates
+fn path_appearance(
+    rect: &Rect,
+    paths: &[&[Point]],
+    close: bool,
+    color: &ColorArray,
+    fill: Option<&ColorArray>,
+    line_width: Pt,
+) -> LoStream {
+    let mut ops = vec![LoOp::new("w", vec![Real(line_width.0)])];
+    // round line caps and joins, like a pen
+    ops.push(LoOp::new("J", vec![Integer(1)]));
+    ops.push(LoOp::new("j", vec![Integer(1)]));
+    ops.extend(color_to_ops(color, true));
+    if let Some(fill) = fill {
+        ops.extend(color_to_ops(fill, false));
+    }
+    for path in paths {
+        for (i, p) in path.iter().enumerate() {
+            let op = if i == 0 { "m" } else { "l" };
+            ops.push(LoOp::new(op, vec![Real(p.x.0), Real(p.y.0)]));
+        }
+        if close {
+            ops.push(LoOp::new("h", vec![]));
+        }
+    }
+    let paint = match (fill.is_some(), close) {
+        (true, true) => "B",
+        _ => "S",
+    };
+    ops.push(LoOp::new(paint, vec![]));
+
+    let dict = LoDictionary::from_iter(vec![
+        ("Type", Name("XObject".into())),
+        ("Subtype", Name("Form".into())),
+        ("BBox", rect_to_array(rect)),
+    ]);
+    let content = Content { operations: ops }.encode().unwrap_or_default();
+    LoStream::new(dict, content)
+}
+
+fn rect_to_array(rect: &Rect) -> lopdf::Object {
+    let rect = rect.normalize();
+    let ll = rect.lower_left();
+    let ur = rect.upper_right();
+    Array(vec![Real(ll.x.0), Real(ll.y.0), Real(ur.x.0), Real(ur.y.0)])
+}
+
+/// Keys shared by all markup annotations
+fn markup_base_dict(
+    subtype: &str,
+    rect: &Rect,
+    contents: &str,
+    author: Option<&String>,
+    creation_date: Option<&OffsetDateTime>,
+    page_id: lopdf::ObjectId,
+) -> LoDictionary {
+    let mut dict = LoDictionary::from_iter(vec![
+        ("Type", Name("Annot".into())),
+        ("Subtype", Name(subtype.into())),
+        ("Rect", rect_to_array(rect)),
+        ("Contents", text_string(contents)),
+        ("P", Reference(page_id)),
+        // print the annotation
+        ("F", Integer(4)),
+    ]);
+    if let Some(author) = author {
+        dict.set("T", text_string(author));
+    }
+    if let Some(date) = creation_date {
+        let date = to_pdf_time_stamp_metadata(date);
+        dict.set("CreationDate", LoString(date.clone().into_bytes(), Literal));
+        dict.set("M", LoString(date.into_bytes(), Literal));
+    }
+    dict
+}
+
+/// Appearance stream of a free text annotation: the lines of the text, without wrapping
+fn free_text_appearance(a: &FreeTextAnnotation) -> LoStream {
+    let rect = a.rect.normalize();
+    let (width, height) = (rect.width.0, rect.height.0);
+    let font_id = a.font.get_pdf_id();
+    let padding = 2.0;
+
+    let mut ops = Vec::new();
+    if let Some(bg) = a.background_color.as_ref() {
+        ops.extend(color_to_ops(bg, false));
+        ops.push(LoOp::new(
+            "re",
+            vec![Real(0.0), Real(0.0), Real(width), Real(height)],
+        ));
+        ops.push(LoOp::new("f", vec![]));
+    }
+    ops.push(LoOp::new("BT", vec![]));
+    ops.push(LoOp::new(
+        "Tf",
+        vec![Name(font_id.into()), Real(a.font_size.0)],
+    ));
+    ops.push(LoOp::new("TL", vec![Real(a.font_size.0 * 1.2)]));
+    ops.extend(color_to_ops(&a.text_color, false));
+    ops.push(LoOp::new(
+        "Td",
+        vec![Real(padding), Real(height - padding - a.font_size.0)],
+    ));
+    for (i, line) in a.contents.lines().enumerate() {
+        if i > 0 {
+            ops.push(LoOp::new("T*", vec![]));
+        }
+        let bytes =
+            lopdf::Document::encode_text(&lopdf::Encoding::SimpleEncoding("WinAnsiEncoding"), line);
+        ops.push(LoOp::new("Tj", vec![LoString(bytes, Literal)]));
+    }
+    ops.push(LoOp::new("ET", vec![]));
+
+    let font = LoDictionary::from_iter(vec![
+        ("Type", Name("Font".into())),
+        ("Subtype", Name("Type1".into())),
+        ("BaseFont", Name(a.font.get_id().into())),
+        ("Encoding", Name("WinAnsiEncoding".into())),
+    ]);
+    let resources = LoDictionary::from_iter(vec![(
+        "Font",
+        Dictionary(LoDictionary::from_iter(vec![(font_id, Dictionary(font))])),
+    )]);
+    let dict = LoDictionary::from_iter(vec![
+        ("Type", Name("XObject".into())),
+        ("Subtype", Name("Form".into())),
+        (
+            "BBox",
+            Array(vec![Real(0.0), Real(0.0), Real(width), Real(height)]),
+        ),
+        ("Resources", Dictionary(resources)),
+    ]);
+    let content = Content { operations: ops }.encode().unwrap_or_default();
+    LoStream::new(dict, content)
+}
+
+/// Writes the annotation (and its popup) to the document, returns the objects
+/// that have to be added to the `/Annots` of the page
+pub(crate) fn add_markup_annotation(
+    annotation: &MarkupAnnotation,
+    page_id: lopdf::ObjectId,
+    doc: &mut lopdf::Document,
+) -> Vec<lopdf::ObjectId> {
+    match annotation {
+        MarkupAnnotation::FreeText(a) => {
+            let mut dict = markup_base_dict(
+                "FreeText",
+                &a.rect,
+                &a.contents,
+                a.author.as_ref(),
+                a.creation_date.as_ref(),
+                page_id,
+            );
+            let da = format!(
+                "/{} {} Tf {}",
+                a.font.get_pdf_id(),
+                a.font_size.0,
+                color_to_da(&a.text_color)
+            );
+            dict.set("DA", LoString(da.into_bytes(), Literal));
+            if let Some(bg) = a.background_color.as_ref() {
+                dict.set("C", color_to_array(bg));
+            }
+            let ap = doc.add_object(free_text_appearance(a));
+            dict.set(
+                "AP",
+                Dictionary(LoDictionary::from_iter(vec![("N", Reference(ap))])),
+            );
+            vec![doc.add_object(dict)]
+        }
+        MarkupAnnotation::Popup(a) => {
+            let text_id = doc.new_object_id();
+            let popup_id = doc.new_object_id();
+
+            let mut text = markup_base_dict(
+                "Text",
+                &a.rect,
+                &a.contents,
+                a.author.as_ref(),
+                a.creation_date.as_ref(),
+                page_id,
+            );
+            text.set("Name", Name("Comment".into()));
+            text.set("Open", Boolean(a.open));
+            text.set("Popup", Reference(popup_id));
+            if let Some(color) = a.color.as_ref() {
+                text.set("C", color_to_array(color));
+            }
+
+            // next to the icon, if no position is given
+            let popup_rect = a.popup_rect.clone().unwrap_or_else(|| {
+                let icon = a.rect.normalize();
+                Rect {
+                    x: icon.x + icon.width,
+                    y: icon.y + icon.height - Pt(100.0),
+                    width: Pt(200.0),
+                    height: Pt(100.0),
+                }
+            });
+            let popup = LoDictionary::from_iter(vec![
+                ("Type", Name("Annot".into())),
+                ("Subtype", Name("Popup".into())),
+                ("Rect", rect_to_array(&popup_rect)),
+                ("Parent", Reference(text_id)),
+                ("Open", Boolean(a.open)),
+                ("P", Reference(page_id)),
+            ]);
+
+            doc.objects.insert(text_id, Dictionary(text));
+            doc.objects.insert(popup_id, Dictionary(popup));
+            vec![text_id, popup_id]
+        }
+        MarkupAnnotation::Ink(a) => {
+            let rect = annotation.get_rect();
+            let mut dict = markup_base_dict(
+                "Ink",
+                &rect,
+                &a.contents,
+                a.author.as_ref(),
+                a.creation_date.as_ref(),
+                page_id,
+            );
+            let ink_list = a.ink_list.iter().map(|s| points_to_array(s.iter()));
+            dict.set("InkList", Array(ink_list.collect()));
+            dict.set("C", color_to_array(&a.color));
+            dict.set("BS", Dictionary(border_style(a.line_width)));
+            let paths = a.ink_list.iter().map(|s| s.as_slice()).collect::<Vec<_>>();
+            let ap = path_appearance(&rect, &paths, false, &a.color, None, a.line_width);
+            let ap = doc.add_object(ap);
+            dict.set(
+                "AP",
+                Dictionary(LoDictionary::from_iter(vec![("N", Reference(ap))])),
+            );
+            vec![doc.add_object(dict)]
+        }
+        MarkupAnnotation::Polygon(a) => {
+            let rect = annotation.get_rect();
+            let subtype = if a.closed { "Polygon" } else { "PolyLine" };
+            let mut dict = markup_base_dict(
+                subtype,
+                &rect,
+                &a.contents,
+                a.author.as_ref(),
+                a.creation_date.as_ref(),
+                page_id,
+            );
+            dict.set("Vertices", points_to_array(a.vertices.iter()));
+            dict.set("C", color_to_array(&a.color));
+            if let Some(ic) = a.interior_color.as_ref() {
+                dict.set("IC", color_to_array(ic));
+            }
+            dict.set("BS", Dictionary(border_style(a.line_width)));
+            let fill = a.interior_color.as_ref().filter(|_| a.closed);
+            let ap = path_appearance(
+                &rect,
+                &[a.vertices.as_slice()],
+                a.closed,
+                &a.color,
+                fill,
+                a.line_width,
+            );
+            let ap = doc.add_object(ap);
+            dict.set(
+                "AP",
+                Dictionary(LoDictionary::from_iter(vec![("N", Reference(ap))])),
+            );
+            vec![doc.add_object(dict)]
+        }
+    }
+}
+
+fn border_style(line_width: Pt) -> LoDictionary {
+    LoDictionary::from_iter(vec![
+        ("Type", Name("Border".into())),
+        ("W", Real(line_width.0)),
+        ("S", Name("S".into())),
+    ])
+}
+
+/// Parses a `/FreeText`, `/Text` (sticky note), `/Ink`, `/Polygon` or `/PolyLine`
+/// annotation, other annotation types return `None`
+pub(crate) fn parse_markup_annotation(
+    doc: &lopdf::Document,
+    dict: &LoDictionary,
+) -> Option<MarkupAnnotation> {
+    let rect = dict.get(b"Rect").ok().and_then(|r| parse_rect(doc, r))?;
+    let line_width = dict
+        .get(b"BS")
+        .ok()
+        .and_then(|bs| resolve(doc, bs).as_dict().ok())
+        .and_then(|bs| bs.get(b"W").ok())
+        .and_then(|w| match resolve(doc, w) {
+            Integer(i) => Some(Pt(*i as f32)),
+            Real(r) => Some(Pt(*r)),
+            _ => None,
+        });
+    let get_text = |d: &LoDictionary, key: &[u8]| {
+        d.get(key)
+            .ok()
+            .and_then(|o| resolve(doc, o).as_str().ok())
+            .map(decode_text_string)
+    };
+    let contents = get_text(dict, b"Contents").unwrap_or_default();
+    let author = get_text(dict, b"T");
+    let creation_date = get_text(dict, b"CreationDate").and_then(|d| parse_pdf_date(&d));
+    let color = dict.get(b"C").ok().and_then(|c| parse_color(doc, c));
+
+    match dict.get(b"Subtype").and_then(|s| s.as_name()).ok()? {
+        b"FreeText" => {
+            let mut a = FreeTextAnnotation::new(rect, &contents);
+            a.author = author;
+            a.creation_date = creation_date;
+            a.background_color = color;
+            // the font name of /DA refers to the resources of the appearance stream
+            let fonts = dict
+                .get(b"AP")
+                .ok()
+                .and_then(|ap| resolve(doc, ap).as_dict().ok())
+                .and_then(|ap| ap.get(b"N").ok())
+                .and_then(|n| resolve(doc, n).as_stream().ok())
+                .and_then(|n| n.dict.get(b"Resources").ok())
+                .and_then(|r| resolve(doc, r).as_dict().ok())
+                .and_then(|r| r.get(b"Font").ok())
+                .and_then(|f| resolve(doc, f).as_dict().ok());
+            if let Some(da) = get_text(dict, b"DA") {
+                let appearance = parse_default_appearance(doc, fonts, &da);
+                a.font = appearance.font.unwrap_or(a.font);
+                a.font_size = appearance.font_size.unwrap_or(a.font_size);
+                a.text_color = appearance.color.unwrap_or(a.text_color);
+            }
+            Some(MarkupAnnotation::FreeText(a))
+        }
+        b"Text" => {
+            let popup = dict
+                .get(b"Popup")
+                .ok()
+                .and_then(|p| resolve(doc, p).as_dict().ok());
+            let mut a = PopupAnnotation::new(rect, &contents);
+            a.author = author;
+            a.creation_date = creation_date;
+            a.color = color;
+            a.open = [Some(dict), popup]
+                .into_iter()
+                .flatten()
+                .any(|d| matches!(d.get(b"Open"), Ok(Boolean(true))));
+            a.popup_rect = popup
+                .and_then(|p| p.get(b"Rect").ok())
+                .and_then(|r| parse_rect(doc, r));
+            Some(MarkupAnnotation::Popup(a))
+        }
+        b"Ink" => {
+            let ink_list = dict
+                .get(b"InkList")
+                .ok()
+                .and_then(|l| resolve(doc, l).as_array().ok())?
+                .iter()
+                .filter_map(|s| parse_points(doc, s))
+                .collect();
+            let mut a = InkAnnotation::new(ink_list).with_contents(&contents);
+            a.author = author;
+            a.creation_date = creation_date;
+            a.color = color.unwrap_or(a.color);
+            a.line_width = line_width.unwrap_or(a.line_width);
+            Some(MarkupAnnotation::Ink(a))
+        }
+        subtype @ (b"Polygon" | b"PolyLine") => {
+            let vertices = dict
+                .get(b"Vertices")
+                .ok()
+                .and_then(|v| parse_points(doc, v))?;
+            let mut a = if subtype == b"Polygon" {
+                PolygonAnnotation::polygon(vertices)
+            } else {
+                PolygonAnnotation::polyline(vertices)
+            }
+            .with_contents(&contents);
+            a.author = author;
+            a.creation_date = creation_date;
+            a.color = color.unwrap_or(a.color);
+            a.interior_color = dict.get(b"IC").ok().and_then(|c| parse_color(doc, c));
+            a.line_width = line_width.unwrap_or(a.line_width);
+            Some(MarkupAnnotation::Polygon(a))
+        }
+        _ => None,
+    }
+}
+
+/// Parses a `/Subtype /Link` annotation, the action is read from `/A` or `/Dest`
+pub(crate) fn parse_link_annotation(
+    doc: &lopdf::Document,
+    dict: &LoDictionary,
+    page_numbers: &BTreeMap<ObjectId, usize>,
+) -> Option<LinkAnnotation> {
+    if dict.get(b"Subtype").and_then(|s| s.as_name()).ok()? != b"Link" {
+        return None;
+    }
+    let rect = dict.get(b"Rect").ok().and_then(|r| parse_rect(doc, r))?;
+    let actions = match (dict.get(b"A"), dict.get(b"Dest")) {
+        (Ok(a), _) => parse_action(doc, a, page_numbers)?,
+        (_, Ok(d)) => Actions::GoTo(parse_destination(doc, d, page_numbers)?),
+        _ => return None,
+    };
+
+    let border = dict
+        .get(b"Border")
+        .ok()
+        .and_then(|b| resolve(doc, b).as_array().ok())
+        .and_then(|b| {
+            let widths = parse_numbers(doc, &Array(b.get(..3)?.to_vec()))?;
+            let widths = [widths[0], widths[1], widths[2]];
+            // other producers write a dash array, `BorderArray::to_array` writes the phase
+            let dash_phase = match b.get(3).map(|d| resolve(doc, d)) {
+                Some(Array(_)) => DashPhase {
+                    dash_array: parse_numbers(doc, &b[3]).unwrap_or_default(),
+                    phase: 0.0,
+                },
+                Some(Integer(i)) => DashPhase {
+                    dash_array: Vec::new(),
+                    phase: *i as f32,
+                },
+                Some(Real(r)) => DashPhase {
+                    dash_array: Vec::new(),
+                    phase: *r,
+                },
+                _ => return Some(BorderArray::Solid(widths)),
+            };
+            Some(BorderArray::Dashed(widths, dash_phase))
+        });
+    let color = match dict.get(b"C").ok().map(|c| resolve(doc, c)) {
+        Some(Array(a)) if a.is_empty() => Some(ColorArray::Transparent),
+        Some(c) => parse_color(doc, c),
+        None => None,
+    };
+    let highlighting = match dict.get(b"H").and_then(|h| h.as_name()).ok() {
+        Some(b"N") => Some(HighlightingMode::None),
+        Some(b"O") => Some(HighlightingMode::Outline),
+        Some(b"P") => Some(HighlightingMode::Push),
+        Some(b"I") => Some(HighlightingMode::Invert),
+        _ => None,
+    };
+
+    Some(LinkAnnotation::new(
+        rect,
+        actions,
+        border,
+        color,
+        highlighting,
+    ))
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -109,6 +977,67 @@ pub enum Destination {
         top: Option<f32>,
         zoom: Option<f32>,
     },
+    /// Display `page` with its contents magnified just enough to fit the entire page within
+    /// the window both horizontally and vertically.
+    Fit { page: usize },
+    /// Display `page` with the vertical coordinate `top` positioned at the top edge of the
+    /// window and the contents magnified just enough to fit the entire width of the page.
+    FitH { page: usize, top: Option<f32> },
+    /// Display `page` with the horizontal coordinate `left` positioned at the left edge of the
+    /// window and the contents magnified just enough to fit the entire height of the page.
+    FitV { page: usize, left: Option<f32> },
+    /// Display `page` with the contents magnified just enough to fit the rectangle
+    /// (`left`, `bottom`, `right`, `top`) entirely within the window.
+    FitR {
+        page: usize,
+        left: f32,
+        bottom: f32,
+        right: f32,
+        top: f32,
+    },
+    /// Like `Fit`, but fits the bounding box of the page contents (PDF 1.1)
+    FitB { page: usize },
+    /// Like `FitH`, but fits the width of the bounding box of the page contents (PDF 1.1)
+    FitBH { page: usize, top: Option<f32> },
+    /// Like `FitV`, but fits the height of the bounding box of the page contents (PDF 1.1)
+    FitBV { page: usize, left: Option<f32> },
+    /// Destination registered with `PdfDocument::add_named_destination`. Links and outline
+    /// entries that target a name stay valid when pages are reordered.
+    Named(String),
+}
+
+impl Destination {
+    /// Returns the (1-based) page number, `None` for named destinations
+    pub fn get_page(&self) -> Option<usize> {
+        use self::Destination::*;
+        match self {
+            XYZ { page, .. }
+            | Fit { page }
+            | FitH { page, .. }
+            | FitV { page, .. }
+            | FitR { page, .. }
+            | FitB { page }
+            | FitBH { page, .. }
+            | FitBV { page, .. } => Some(*page),
+            Named(_) => None,
+        }
+    }
+
+    /// Replaces the (1-based) page number, named destinations are unchanged
+    pub(crate) fn set_page(&mut self, new_page: usize) {
+        use self::Destination::*;
+        match self {
+            XYZ { page, .. }
+            | Fit { page }
+            | FitH { page, .. }
+            | FitV { page, .. }
+            | FitR { page, .. }
+            | FitB { page }
+            | FitBH { page, .. }
+            | FitBV { page, .. } => *page = new_page,
+            Named(_) => {}
+        }
+    }
 }
 
 /*
@@ -135,6 +1064,9 @@ pub enum Destination {
 pub enum Actions {
     GoTo(Destination),
     URI(String),
+    /// Action predefined by the viewer (PDF 1.2), i.e. "Print", "NextPage", "PrevPage",
+    /// "FirstPage" or "LastPage"
+    Named(String),
 }
 
 impl Actions {
@@ -146,6 +1078,7 @@ impl Actions {
         match self {
             Actions::GoTo(_) => "GoTo",
             Actions::URI(_) => "URI",
+            Actions::Named(_) => "Named",
         }
     }
 
@@ -156,6 +1089,26 @@ impl Actions {
     pub fn uri(uri: String) -> Self {
         Self::URI(uri)
     }
+
+    pub fn named(name: &str) -> Self {
+        Self::Named(name.to_string())
+    }
+}
+
+/// Actions that are run when a page is opened or closed (`/AA` of the page)
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct PageActions {
+    /// Run when the page is opened (`/O`)
+    pub open: Option<Actions>,
+    /// Run when the page is closed (`/C`)
+    pub close: Option<Actions>,
+}
+
+impl PageActions {
+    /// Returns whether no action is set
+    pub fn is_empty(&self) -> bool {
+        self.open.is_none() && self.close.is_none()
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Copy, Default)]
@@ -178,3 +1131,129 @@ impl HighlightingMode {
         }
     }
 }
+
+#[test]
+fn test_markup_annotation_roundtrip() {
+    use crate::{Mm, PdfDocument, PdfSaveOptions};
+
+    let rect = |x: f32, y: f32, width: f32, height: f32| Rect {
+        x: Pt(x),
+        y: Pt(y),
+        width: Pt(width),
+        height: Pt(height),
+    };
+    let date = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+    let free_text = MarkupAnnotation::FreeText(
+        FreeTextAnnotation::new(rect(50.0, 700.0, 200.0, 40.0), "Typo\nin line 3")
+            .with_author("Reviewer")
+            .with_creation_date(date)
+            .with_font(BuiltinFont::Courier, Pt(12.0))
+            .with_text_color(ColorArray::RGB([1.0, 0.0, 0.0]))
+            .with_background_color(ColorArray::Gray([0.9])),
+    );
+    let note = MarkupAnnotation::Popup(
+        PopupAnnotation::new(rect(300.0, 500.0, 20.0, 20.0), "Please rephrase")
+            .with_author("Editor")
+            .with_open(true)
+            .with_color(ColorArray::RGB([1.0, 1.0, 0.0]))
+            .with_popup_rect(rect(320.0, 420.0, 200.0, 100.0)),
+    );
+
+    let point = |x: f32, y: f32| Point { x: Pt(x), y: Pt(y) };
+    let ink = MarkupAnnotation::Ink(
+        InkAnnotation::new(vec![
+            vec![point(10.0, 10.0), point(20.0, 15.0), point(30.0, 10.0)],
+            vec![point(10.0, 30.0), point(30.0, 30.0)],
+        ])
+        .with_stroke(ColorArray::RGB([0.0, 0.0, 1.0]), Pt(2.0)),
+    );
+    let area = MarkupAnnotation::Polygon(
+        PolygonAnnotation::polygon(vec![
+            point(100.0, 100.0),
+            point(200.0, 100.0),
+            point(150.0, 180.0),
+        ])
+        .with_contents("Area: 4000 pt²")
+        .with_interior_color(ColorArray::RGB([1.0, 0.0, 0.0])),
+    );
+    let distance = MarkupAnnotation::Polygon(
+        PolygonAnnotation::polyline(vec![point(300.0, 100.0), point(400.0, 120.0)])
+            .with_author("Measure tool"),
+    );
+    assert_eq!(ink.get_rect(), rect(9.0, 9.0, 22.0, 22.0));
+
+    let mut doc = PdfDocument::new("review");
+    doc.pages.push(PdfPage::new(
+        Mm(210.0),
+        Mm(297.0),
+        vec![
+            Op::AddAnnotation {
+                annotation: Box::new(free_text.clone()),
+            },
+            Op::AddAnnotation {
+                annotation: Box::new(note.clone()),
+            },
+            Op::AddAnnotation {
+                annotation: Box::new(ink.clone()),
+            },
+            Op::AddAnnotation {
+                annotation: Box::new(area.clone()),
+            },
+            Op::AddAnnotation {
+                annotation: Box::new(distance.clone()),
+            },
+        ],
+    ));
+    let bytes = doc.save(&PdfSaveOptions::default());
+
+    let parsed = PdfDocument::parse(&bytes, &Default::default()).unwrap();
+    let annotations = parsed.pages[0]
+        .ops
+        .iter()
+        .filter_map(|op| match op {
+            Op::AddAnnotation { annotation } => Some(&**annotation),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(annotations, vec![&free_text, &note, &ink, &area, &distance]);
+}
+
+#[test]
+fn test_link_builders() {
+    use crate::Mm;
+
+    let rect = Rect::from_wh(Pt(100.0), Pt(20.0));
+    let mut page = PdfPage::new(Mm(210.0), Mm(297.0), Vec::new());
+    page.add_web_link(rect.clone(), "https://example.com");
+    page.add_internal_link(rect.clone(), 3, Some(2.0));
+    page.add_mailto_link(rect.clone(), "info@example.com");
+
+    let links = page
+        .ops
+        .iter()
+        .filter_map(|op| match op {
+            Op::LinkAnnotation { link } => Some(link),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(links.len(), 3);
+    assert_eq!(links[0].border.to_array(), vec![0.0, 0.0, 0.0]);
+    assert_eq!(links[0].color, ColorArray::Transparent);
+    assert_eq!(
+        links[1].actions,
+        Actions::GoTo(Destination::XYZ {
+            page: 3,
+            left: None,
+            top: None,
+            zoom: Some(2.0),
+        })
+    );
+    assert_eq!(
+        links[2].actions,
+        Actions::URI("mailto:info@example.com".to_string())
+    );
+    assert_eq!(
+        LinkAnnotation::mailto(rect, "mailto:a@b.c").actions,
+        Actions::URI("mailto:a@b.c".to_string())
+    );
+}
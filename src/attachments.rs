@@ -0,0 +1,187 @@
+//! Embedded files (`/EmbeddedFiles` name tree + `/AF` associated files), i.e. for
+//! ZUGFeRD / Factur-X e-invoices, which embed the invoice XML in a PDF/A-3 document
+
+use lopdf::Object::{Array, Dictionary, Integer, Name, Reference, String as LoString};
+use lopdf::StringFormat::Literal;
+use lopdf::{Dictionary as LoDictionary, Stream as LoStream};
+
+use crate::{forms::text_string, OffsetDateTime, PdfDocument};
+
+/// Relationship of an embedded file to the document (`/AFRelationship`, PDF/A-3)
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AfRelationship {
+    /// Original source of the document (i.e. the word processor file)
+    Source,
+    /// Data used to derive the visual presentation (i.e. invoice XML for ZUGFeRD "BASIC" and up)
+    Data,
+    /// Alternative representation of the content (i.e. invoice XML for ZUGFeRD "EXTENDED")
+    Alternative,
+    /// Supplemental representation of the original source or data
+    Supplement,
+    /// Encrypted payload document
+    EncryptedPayload,
+    /// Data of a form
+    FormData,
+    /// Schema definition of the associated object
+    Schema,
+    /// Unknown relationship
+    Unspecified,
+}
+
+impl AfRelationship {
+    /// Returns the value of the `/AFRelationship` key (i.e. "Data")
+    pub fn get_id(&self) -> &'static str {
+        use self::AfRelationship::*;
+        match self {
+            Source => "Source",
+            Data => "Data",
+            Alternative => "Alternative",
+            Supplement => "Supplement",
+            EncryptedPayload => "EncryptedPayload",
+            FormData => "FormData",
+            Schema => "Schema",
+            Unspecified => "Unspecified",
+        }
+    }
+}
+
+/// File that is embedded in the document, see `PdfDocument::attach_file`
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmbeddedFile {
+    /// File name, i.e. "factur-x.xml"
+    pub name: String,
+    /// Contents of the file
+    pub bytes: Vec<u8>,
+    /// MIME type, i.e. "text/xml"
+    pub mime_type: String,
+    /// Relationship of the file to the document
+    pub relationship: AfRelationship,
+    /// Description shown in the attachments panel of the viewer
+    pub description: Option<String>,
+}
+
+impl EmbeddedFile {
+    /// Creates a new embedded file
+    pub fn new(name: &str, bytes: Vec<u8>, mime_type: &str, relationship: AfRelationship) -> Self {
+        Self {
+            name: name.to_string(),
+            bytes,
+            mime_type: mime_type.to_string(),
+            relationship,
+            description: None,
+        }
+    }
+
+    /// Sets the description of the file
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+}
+
+impl PdfDocument {
+    /// Embeds a file in the document. The file is listed in the `/EmbeddedFiles` name tree
+    /// and associated with the document (`/AF`), as required by PDF/A-3.
+    ///
+    /// NOTE: ZUGFeRD / Factur-X additionally require the conformance level to be set to
+    /// PDF/A-3 and XMP metadata with the Factur-X extension schema.
+    pub fn attach_file(
+        &mut self,
+        name: &str,
+        bytes: &[u8],
+        mime_type: &str,
+        af_relationship: AfRelationship,
+    ) -> &mut Self {
+        // replace files with the same name, file names in the name tree have to be unique
+        self.attachments.retain(|f| f.name != name);
+        self.attachments.push(EmbeddedFile::new(
+            name,
+            bytes.to_vec(),
+            mime_type,
+            af_relationship,
+        ));
+        self
+    }
+}
+
+/// Writes the embedded files, returns the `/EmbeddedFiles` name tree and the `/AF` array
+/// (references to the file specifications) for the catalog
+pub(crate) fn write_embedded_files(
+    files: &[EmbeddedFile],
+    modification_date: &OffsetDateTime,
+    doc: &mut lopdf::Document,
+) -> (LoDictionary, Vec<lopdf::Object>) {
+    let mod_date = crate::utils::to_pdf_time_stamp_metadata(modification_date);
+
+    // names in a name tree have to be sorted
+    let mut sorted = files.iter().collect::<Vec<_>>();
+    sorted.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut names = Vec::new();
+    let mut af = Vec::new();
+    for file in sorted {
+        let stream_dict = LoDictionary::from_iter(vec![
+            ("Type", Name("EmbeddedFile".into())),
+            ("Subtype", Name(file.mime_type.as_bytes().to_vec())),
+            (
+                "Params",
+                Dictionary(LoDictionary::from_iter(vec![
+                    ("Size", Integer(file.bytes.len() as i64)),
+                    ("ModDate", LoString(mod_date.clone().into_bytes(), Literal)),
+                ])),
+            ),
+        ]);
+        let stream_id = doc.add_object(LoStream::new(stream_dict, file.bytes.clone()));
+
+        let mut filespec = LoDictionary::from_iter(vec![
+            ("Type", Name("Filespec".into())),
+            ("F", text_string(&file.name)),
+            ("UF", text_string(&file.name)),
+            (
+                "EF",
+                Dictionary(LoDictionary::from_iter(vec![
+                    ("F", Reference(stream_id)),
+                    ("UF", Reference(stream_id)),
+                ])),
+            ),
+            ("AFRelationship", Name(file.relationship.get_id().into())),
+        ]);
+        if let Some(desc) = file.description.as_ref() {
+            filespec.set("Desc", text_string(desc));
+        }
+        let filespec_id = doc.add_object(Dictionary(filespec));
+
+        names.push(text_string(&file.name));
+        names.push(Reference(filespec_id));
+        af.push(Reference(filespec_id));
+    }
+
+    let name_tree = LoDictionary::from_iter(vec![("Names", Array(names))]);
+    (name_tree, af)
+}
+
+#[test]
+fn test_embedded_files() {
+    let mut pdf = PdfDocument::new("invoice");
+    pdf.attach_file("z.txt", b"z", "text/plain", AfRelationship::Supplement);
+    pdf.attach_file("factur-x.xml", b"<a/>", "text/xml", AfRelationship::Data);
+    pdf.attach_file("z.txt", b"zz", "text/plain", AfRelationship::Supplement);
+    assert_eq!(pdf.attachments.len(), 2);
+
+    let mut doc = lopdf::Document::with_version("1.7");
+    let (name_tree, af) =
+        write_embedded_files(&pdf.attachments, &OffsetDateTime::now_utc(), &mut doc);
+    assert_eq!(af.len(), 2);
+
+    let names = name_tree.get(b"Names").unwrap().as_array().unwrap();
+    assert_eq!(names.len(), 4);
+    assert_eq!(names[0], text_string("factur-x.xml"));
+
+    let filespec = doc
+        .get_dictionary(names[1].as_reference().unwrap())
+        .unwrap();
+    assert_eq!(
+        filespec.get(b"AFRelationship").unwrap(),
+        &Name("Data".into())
+    );
+}
@@ -1,3 +1,8 @@
+use lopdf::Object::{Array, Integer, Name, Real, String as LoString};
+use lopdf::StringFormat::Hexadecimal;
+
+use std::collections::BTreeMap;
+
 use crate::IccProfileId;
 
 /// Color space (enum for marking the number of bits a color has)
@@ -62,6 +67,10 @@ pub enum Color {
     Cmyk(Cmyk),
     Greyscale(Greyscale),
     SpotColor(SpotColor),
+    /// Palette color (`/Indexed` color space)
+    Indexed(IndexedColor),
+    /// Multi-ink color (`/DeviceN` color space)
+    DeviceN(DeviceNColor),
 }
 
 impl Color {
@@ -80,6 +89,44 @@ impl Color {
             Color::SpotColor(spot) => {
                 vec![spot.c, spot.m, spot.y, spot.k]
             }
+            Color::Indexed(indexed) => {
+                vec![indexed.index as f32]
+            }
+            Color::DeviceN(devicen) => devicen.tints.clone(),
+        }
+    }
+
+    /// Converts palette and multi-ink colors into the base / alternate device color,
+    /// other colors are returned as-is
+    pub fn get_device_color(&self) -> Color {
+        match self {
+            Color::Indexed(c) => c.color_space.get_color(c.index),
+            Color::DeviceN(c) => c.color_space.get_alternate_color(&c.tints),
+            other => other.clone(),
+        }
+    }
+
+    /// Color space that has to be written to the resources of the page
+    pub(crate) fn get_color_space_resource(&self) -> Option<ColorSpaceResource<'_>> {
+        match self {
+            Color::Indexed(c) => Some(ColorSpaceResource::Indexed(&c.color_space)),
+            Color::DeviceN(c) => Some(ColorSpaceResource::DeviceN(&c.color_space)),
+            Color::Rgb(Rgb {
+                icc_profile: Some(id),
+                ..
+            }) => Some(ColorSpaceResource::IccBased(id, DeviceColorSpace::Rgb)),
+            Color::Cmyk(Cmyk {
+                icc_profile: Some(id),
+                ..
+            }) => Some(ColorSpaceResource::IccBased(id, DeviceColorSpace::Cmyk)),
+            Color::Greyscale(Greyscale {
+                icc_profile: Some(id),
+                ..
+            }) => Some(ColorSpaceResource::IccBased(
+                id,
+                DeviceColorSpace::Greyscale,
+            )),
+            _ => None,
         }
     }
 
@@ -89,7 +136,7 @@ impl Color {
             Color::Rgb(ref rgb) => Some(&rgb.icc_profile),
             Color::Cmyk(ref cmyk) => Some(&cmyk.icc_profile),
             Color::Greyscale(ref gs) => Some(&gs.icc_profile),
-            Color::SpotColor(_) => None,
+            Color::SpotColor(_) | Color::Indexed(_) | Color::DeviceN(_) => None,
         }
     }
 }
@@ -169,6 +216,273 @@ impl SpotColor {
     }
 }
 
+/// Device color space of the palette of an indexed color space or the alternate
+/// color space of a multi-ink color space
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DeviceColorSpace {
+    Rgb,
+    Cmyk,
+    Greyscale,
+}
+
+impl DeviceColorSpace {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            DeviceColorSpace::Rgb => "DeviceRGB",
+            DeviceColorSpace::Cmyk => "DeviceCMYK",
+            DeviceColorSpace::Greyscale => "DeviceGray",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "DeviceRGB" => Some(DeviceColorSpace::Rgb),
+            "DeviceCMYK" => Some(DeviceColorSpace::Cmyk),
+            "DeviceGray" => Some(DeviceColorSpace::Greyscale),
+            _ => None,
+        }
+    }
+
+    /// Number of color components
+    pub fn get_components(&self) -> usize {
+        match self {
+            DeviceColorSpace::Rgb => 3,
+            DeviceColorSpace::Cmyk => 4,
+            DeviceColorSpace::Greyscale => 1,
+        }
+    }
+
+    /// Creates a color from the components (0.0 - 1.0), missing components are 0
+    pub fn make_color(&self, components: &[f32]) -> Color {
+        let c = |i: usize| components.get(i).copied().unwrap_or(0.0);
+        match self {
+            DeviceColorSpace::Rgb => Color::Rgb(Rgb::new(c(0), c(1), c(2), None)),
+            DeviceColorSpace::Cmyk => Color::Cmyk(Cmyk::new(c(0), c(1), c(2), c(3), None)),
+            DeviceColorSpace::Greyscale => Color::Greyscale(Greyscale::new(c(0), None)),
+        }
+    }
+}
+
+/// Palette (`/Indexed`) color space with up to 256 colors
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedColorSpace {
+    /// Color space of the palette entries
+    pub base: DeviceColorSpace,
+    /// Components of the palette entries as bytes (i.e. 3 bytes per color for RGB)
+    pub palette: Vec<u8>,
+}
+
+impl IndexedColorSpace {
+    pub fn new(base: DeviceColorSpace, palette: Vec<u8>) -> Self {
+        Self { base, palette }
+    }
+
+    /// Number of colors in the palette (at most 256)
+    pub fn get_num_colors(&self) -> usize {
+        (self.palette.len() / self.base.get_components()).min(256)
+    }
+
+    /// Returns the palette entry, indices outside of the palette are clamped to the last entry
+    pub fn get_color(&self, index: u8) -> Color {
+        let n = self.base.get_components();
+        let index = (index as usize).min(self.get_num_colors().saturating_sub(1));
+        let entry = self
+            .palette
+            .get(index * n..(index + 1) * n)
+            .unwrap_or_default()
+            .iter()
+            .map(|b| *b as f32 / 255.0)
+            .collect::<Vec<_>>();
+        self.base.make_color(&entry)
+    }
+
+    /// `[/Indexed base hival <palette>]` array
+    pub(crate) fn to_object(&self) -> lopdf::Object {
+        let n = self.base.get_components();
+        let num_colors = self.get_num_colors().max(1);
+        let mut palette = self.palette.clone();
+        palette.resize(num_colors * n, 0);
+        Array(vec![
+            Name("Indexed".into()),
+            Name(self.base.get_name().into()),
+            Integer(num_colors as i64 - 1),
+            LoString(palette, Hexadecimal),
+        ])
+    }
+}
+
+/// Palette color: index into the palette of the color space
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedColor {
+    pub color_space: IndexedColorSpace,
+    pub index: u8,
+}
+
+impl IndexedColor {
+    pub fn new(color_space: IndexedColorSpace, index: u8) -> Self {
+        Self { color_space, index }
+    }
+}
+
+/// Ink of a multi-ink color space
+#[derive(Debug, Clone, PartialEq)]
+pub struct Colorant {
+    /// Name of the ink (i.e. "PANTONE 185 C")
+    pub name: String,
+    /// Appearance of the ink at full tint in the alternate color space
+    pub full_tint: Vec<f32>,
+}
+
+/// Multi-ink (`/DeviceN`) color space. Devices that don't have the inks print the
+/// colors in the alternate color space: CMYK and gray tints of the inks are added,
+/// RGB inks are multiplied (like layers of transparent ink).
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceNColorSpace {
+    pub colorants: Vec<Colorant>,
+    pub alternate: DeviceColorSpace,
+}
+
+impl DeviceNColorSpace {
+    /// Creates a color space without colorants
+    pub fn new(alternate: DeviceColorSpace) -> Self {
+        Self {
+            colorants: Vec::new(),
+            alternate,
+        }
+    }
+
+    /// Adds an ink with its appearance at full tint in the alternate color space
+    pub fn with_colorant(mut self, name: &str, full_tint: Vec<f32>) -> Self {
+        self.colorants.push(Colorant {
+            name: name.to_string(),
+            full_tint,
+        });
+        self
+    }
+
+    /// Converts the tints (0.0 - 1.0, one per colorant) into the alternate color space,
+    /// same as the tint transform function of the PDF
+    pub fn get_alternate_color(&self, tints: &[f32]) -> Color {
+        let components = (0..self.alternate.get_components())
+            .map(|j| {
+                let inks = self.colorants.iter().zip(tints).map(|(colorant, t)| {
+                    let full = colorant.full_tint.get(j).copied().unwrap_or(0.0);
+                    (t.clamp(0.0, 1.0), full)
+                });
+                match self.alternate {
+                    DeviceColorSpace::Cmyk => inks.map(|(t, full)| t * full).sum::<f32>().min(1.0),
+                    _ => inks.map(|(t, full)| 1.0 - t * (1.0 - full)).product(),
+                }
+            })
+            .collect::<Vec<_>>();
+        self.alternate.make_color(&components)
+    }
+
+    /// Type 4 (PostScript calculator) function of `get_alternate_color`
+    fn get_tint_transform_code(&self) -> String {
+        let n = self.colorants.len();
+        let m = self.alternate.get_components();
+        let mut code = String::from("{");
+        for j in 0..m {
+            for (i, colorant) in self.colorants.iter().enumerate() {
+                let full = colorant.full_tint.get(j).copied().unwrap_or(0.0);
+                // stack: tints, finished outputs, partial result (if i > 0)
+                let depth = (n - 1 - i) + j + usize::from(i > 0);
+                code.push_str(&match self.alternate {
+                    DeviceColorSpace::Cmyk => format!(" {depth} index {full} mul"),
+                    _ => format!(" {depth} index {} mul 1 exch sub", 1.0 - full),
+                });
+                if i > 0 {
+                    code.push_str(match self.alternate {
+                        DeviceColorSpace::Cmyk => " add",
+                        _ => " mul",
+                    });
+                }
+            }
+            code.push_str(match (n, self.alternate) {
+                (0, DeviceColorSpace::Cmyk) => " 0",
+                (0, _) => " 1",
+                (_, DeviceColorSpace::Cmyk) => " dup 1 gt { pop 1 } if",
+                _ => "",
+            });
+        }
+        // move the outputs below the tints and remove the tints
+        code.push_str(&format!(" {} {m} roll", n + m));
+        code.push_str(&" pop".repeat(n));
+        code.push_str(" }");
+        code
+    }
+
+    /// `[/DeviceN [names] alternate tintTransform]` array, the function is added to the document
+    pub(crate) fn to_object(&self, doc: &mut lopdf::Document) -> lopdf::Object {
+        let unit_ranges =
+            |count: usize| Array((0..count).flat_map(|_| [Real(0.0), Real(1.0)]).collect());
+        let function = lopdf::Stream::new(
+            lopdf::Dictionary::from_iter(vec![
+                ("FunctionType", Integer(4)),
+                ("Domain", unit_ranges(self.colorants.len())),
+                ("Range", unit_ranges(self.alternate.get_components())),
+            ]),
+            self.get_tint_transform_code().into_bytes(),
+        );
+        let function_id = doc.add_object(function);
+        Array(vec![
+            Name("DeviceN".into()),
+            Array(
+                self.colorants
+                    .iter()
+                    .map(|c| Name(c.name.clone().into_bytes()))
+                    .collect(),
+            ),
+            Name(self.alternate.get_name().into()),
+            lopdf::Object::Reference(function_id),
+        ])
+    }
+}
+
+/// Multi-ink color: one tint (0.0 - 1.0) per colorant of the color space
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceNColor {
+    pub color_space: DeviceNColorSpace,
+    pub tints: Vec<f32>,
+}
+
+impl DeviceNColor {
+    pub fn new(color_space: DeviceNColorSpace, tints: Vec<f32>) -> Self {
+        Self { color_space, tints }
+    }
+}
+
+/// Color space of a color that has to be written as a page resource (`/ColorSpace`)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum ColorSpaceResource<'a> {
+    Indexed(&'a IndexedColorSpace),
+    DeviceN(&'a DeviceNColorSpace),
+    /// `[/ICCBased stream]`, the profile stream is shared between all pages. Falls back
+    /// to the device color space if the profile is not part of the document.
+    IccBased(&'a IccProfileId, DeviceColorSpace),
+}
+
+impl ColorSpaceResource<'_> {
+    pub(crate) fn to_object(
+        &self,
+        doc: &mut lopdf::Document,
+        icc_profiles: &BTreeMap<IccProfileId, lopdf::ObjectId>,
+    ) -> lopdf::Object {
+        match self {
+            ColorSpaceResource::Indexed(cs) => cs.to_object(),
+            ColorSpaceResource::DeviceN(cs) => cs.to_object(doc),
+            ColorSpaceResource::IccBased(id, device) => match icc_profiles.get(*id) {
+                Some(stream_id) => Array(vec![
+                    Name("ICCBased".into()),
+                    lopdf::Object::Reference(*stream_id),
+                ]),
+                None => Name(device.get_name().into()),
+            },
+        }
+    }
+}
+
 /// Type of the icc profile
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum IccProfileType {
@@ -177,6 +491,17 @@ pub enum IccProfileType {
     Greyscale,
 }
 
+impl IccProfileType {
+    /// Device color space with the same number of components
+    pub fn get_device_color_space(&self) -> DeviceColorSpace {
+        match self {
+            IccProfileType::Cmyk => DeviceColorSpace::Cmyk,
+            IccProfileType::Rgb => DeviceColorSpace::Rgb,
+            IccProfileType::Greyscale => DeviceColorSpace::Greyscale,
+        }
+    }
+}
+
 /// Icc profile
 #[derive(Debug, Clone, PartialEq)]
 pub struct IccProfile {
@@ -216,3 +541,89 @@ impl IccProfile {
         self
     }
 }
+
+#[test]
+fn test_indexed_and_devicen_colors() {
+    use crate::{Mm, Op, PdfDocument, PdfPage, PdfSaveOptions};
+
+    let palette = IndexedColorSpace::new(DeviceColorSpace::Rgb, vec![255, 0, 0, 0, 0, 255]);
+    assert_eq!(palette.get_num_colors(), 2);
+    assert_eq!(
+        palette.get_color(1),
+        Color::Rgb(Rgb::new(0.0, 0.0, 1.0, None))
+    );
+    // out of range indices use the last entry
+    assert_eq!(palette.get_color(7), palette.get_color(1));
+
+    let inks = DeviceNColorSpace::new(DeviceColorSpace::Greyscale)
+        .with_colorant("Silver", vec![0.5])
+        .with_colorant("Varnish", vec![1.0]);
+    assert_eq!(
+        inks.get_alternate_color(&[1.0, 0.5]),
+        Color::Greyscale(Greyscale::new(0.5, None))
+    );
+    assert_eq!(
+        inks.get_tint_transform_code(),
+        "{ 1 index 0.5 mul 1 exch sub 1 index 0 mul 1 exch sub mul 3 1 roll pop pop }"
+    );
+
+    let mut doc = PdfDocument::new("colors");
+    doc.pages.push(PdfPage::new(
+        Mm(210.0),
+        Mm(297.0),
+        vec![
+            Op::SetFillColor {
+                col: Color::DeviceN(DeviceNColor::new(inks.clone(), vec![1.0, 0.0])),
+            },
+            Op::SetOutlineColor {
+                col: Color::Indexed(IndexedColor::new(palette.clone(), 0)),
+            },
+            Op::SetFillColor {
+                col: Color::DeviceN(DeviceNColor::new(inks, vec![0.0, 1.0])),
+            },
+        ],
+    ));
+    let bytes = doc.save(&PdfSaveOptions::default());
+    let text = String::from_utf8_lossy(&bytes);
+    assert!(text.contains("/DeviceN"));
+    assert!(text.contains("/Indexed"));
+    assert_eq!(text.matches("/CS0 cs").count(), 2);
+    assert!(text.contains("/CS1 CS"));
+}
+
+#[test]
+fn test_icc_based_colors() {
+    use crate::{Mm, Op, PdfDocument, PdfPage, PdfSaveOptions};
+
+    let mut doc = PdfDocument::new("icc");
+    let icc = IccProfile::new(
+        include_bytes!("./res/CoatedFOGRA39.icc").to_vec(),
+        IccProfileType::Cmyk,
+    );
+    let id = doc.add_icc_profile(&icc);
+    doc.pages.push(PdfPage::new(
+        Mm(210.0),
+        Mm(297.0),
+        vec![
+            Op::SetFillColor {
+                col: Color::Cmyk(Cmyk::new(0.0, 1.0, 1.0, 0.0, Some(id.clone()))),
+            },
+            Op::SetOutlineColor {
+                col: Color::Cmyk(Cmyk::new(1.0, 0.0, 0.0, 0.0, Some(id.clone()))),
+            },
+            // CMYK profile on an RGB color: written as DeviceRGB
+            Op::SetFillColor {
+                col: Color::Rgb(Rgb::new(1.0, 0.0, 0.0, Some(id))),
+            },
+        ],
+    ));
+    let mut warnings = Vec::new();
+    let bytes = doc.save_with_warnings(&PdfSaveOptions::default(), &mut warnings);
+    let text = String::from_utf8_lossy(&bytes);
+    assert_eq!(text.matches("/ICCBased").count(), 1);
+    assert!(text.contains("/CS0 cs"));
+    assert!(text.contains("/CS0 CS"));
+    assert!(text.contains(" rg"));
+    let icc_warnings = warnings.iter().filter(|w| w.msg.contains("ICC profile"));
+    assert_eq!(icc_warnings.count(), 1);
+}
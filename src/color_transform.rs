@@ -0,0 +1,340 @@
+//! Document-wide conversion of RGB colors and images to CMYK or greyscale on save,
+//! see `PdfSaveOptions::color_transform`
+
+use std::io::Write;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+    color::{
+        Cmyk, Color, DeviceColorSpace, Greyscale, IccProfile, IccProfileType, IndexedColorSpace,
+    },
+    gradient::Gradient,
+    image::{EncodedImage, RawImage, RawImageData, RawImageFormat, StreamFilter},
+    ops::Op,
+    xobject::XObject,
+    IccProfileId, PdfDocument, PdfWarnCode, PdfWarnMsg,
+};
+
+/// Output color space of a `ColorTransform`
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorTransformTarget {
+    Cmyk,
+    Greyscale,
+}
+
+/// Converts all RGB fill / stroke colors, gradients and images of the document
+///
+/// The colors are converted with the device formulas (no color management), the
+/// converted colors and images are tagged with the ICC profile (`/ICCBased`).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ColorTransform {
+    pub target: ColorTransformTarget,
+    /// Output ICC profile, must match the target (4 components for CMYK, 1 for greyscale)
+    pub icc_profile: Vec<u8>,
+}
+
+impl ColorTransform {
+    pub fn new(target: ColorTransformTarget, icc_profile: Vec<u8>) -> Self {
+        Self {
+            target,
+            icc_profile,
+        }
+    }
+
+    fn get_color_space(&self) -> DeviceColorSpace {
+        match self.target {
+            ColorTransformTarget::Cmyk => DeviceColorSpace::Cmyk,
+            ColorTransformTarget::Greyscale => DeviceColorSpace::Greyscale,
+        }
+    }
+
+    /// Converts RGB components (0.0 - 1.0) into the components of the target
+    fn convert_rgb(&self, r: f32, g: f32, b: f32) -> Vec<f32> {
+        match self.target {
+            ColorTransformTarget::Greyscale => vec![0.299 * r + 0.587 * g + 0.114 * b],
+            ColorTransformTarget::Cmyk => {
+                let k = 1.0 - r.max(g).max(b);
+                if k >= 1.0 {
+                    vec![0.0, 0.0, 0.0, 1.0]
+                } else {
+                    vec![
+                        (1.0 - r - k) / (1.0 - k),
+                        (1.0 - g - k) / (1.0 - k),
+                        (1.0 - b - k) / (1.0 - k),
+                        k,
+                    ]
+                }
+            }
+        }
+    }
+
+    /// Converts RGB colors (also the palette of indexed and the alternate color of
+    /// DeviceN colors), other colors are returned as-is
+    fn convert_color(&self, col: &Color, icc_profile: Option<&IccProfileId>) -> Color {
+        let target = self.get_color_space();
+        match col {
+            Color::Rgb(rgb) => match target.make_color(&self.convert_rgb(rgb.r, rgb.g, rgb.b)) {
+                Color::Cmyk(c) => Color::Cmyk(Cmyk {
+                    icc_profile: icc_profile.cloned(),
+                    ..c
+                }),
+                Color::Greyscale(g) => Color::Greyscale(Greyscale {
+                    icc_profile: icc_profile.cloned(),
+                    ..g
+                }),
+                other => other,
+            },
+            Color::Indexed(c) if c.color_space.base == DeviceColorSpace::Rgb => {
+                let palette = c
+                    .color_space
+                    .palette
+                    .chunks_exact(3)
+                    .flat_map(|p| {
+                        let [r, g, b] = [p[0], p[1], p[2]].map(|v| v as f32 / 255.0);
+                        self.convert_rgb(r, g, b)
+                    })
+                    .map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8)
+                    .collect();
+                let mut c = c.clone();
+                c.color_space = IndexedColorSpace::new(target, palette);
+                Color::Indexed(c)
+            }
+            Color::DeviceN(c) if c.color_space.alternate == DeviceColorSpace::Rgb => {
+                let mut c = c.clone();
+                c.color_space.alternate = target;
+                for colorant in c.color_space.colorants.iter_mut() {
+                    let t = |i: usize| colorant.full_tint.get(i).copied().unwrap_or(0.0);
+                    colorant.full_tint = self.convert_rgb(t(0), t(1), t(2));
+                }
+                Color::DeviceN(c)
+            }
+            other => other.clone(),
+        }
+    }
+
+    /// Converts the fill, stroke and gradient colors of the operations
+    fn convert_ops(&self, ops: &mut [Op], icc_profile: &IccProfileId) {
+        for op in ops.iter_mut() {
+            match op {
+                Op::SetFillColor { col } | Op::SetOutlineColor { col } => {
+                    *col = self.convert_color(col, Some(icc_profile));
+                }
+                // shadings are written in device color spaces
+                Op::DrawGradient { gradient, .. } => {
+                    let stops = match gradient {
+                        Gradient::Linear(g) => &mut g.stops,
+                        Gradient::Radial(g) => &mut g.stops,
+                    };
+                    for stop in stops.iter_mut() {
+                        stop.color = self.convert_color(&stop.color, None);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Converts an RGB image into a Flate encoded image in the target color space.
+    /// Returns `Ok(None)` if the image doesn't need to be converted.
+    pub(crate) fn convert_image(&self, image: &RawImage) -> Result<Option<RawImage>, String> {
+        if let Some(source) = image.source.as_ref() {
+            if source.color_space != "DeviceRGB" {
+                return Ok(None);
+            }
+        }
+        let format = match image.source.as_ref() {
+            Some(source) => source.get_decoded_format(),
+            None => image.data_format,
+        };
+        let (channels, bgr) = match format {
+            RawImageFormat::R8 | RawImageFormat::R16 => return Ok(None),
+            RawImageFormat::RG8 | RawImageFormat::RG16 => return Ok(None),
+            RawImageFormat::RGB8 | RawImageFormat::RGB16 | RawImageFormat::RGBF32 => (3, false),
+            RawImageFormat::BGR8 => (3, true),
+            RawImageFormat::RGBA8 | RawImageFormat::RGBA16 | RawImageFormat::RGBAF32 => {
+                return Err("images with an alpha channel are not converted".to_string());
+            }
+            RawImageFormat::BGRA8 => {
+                return Err("images with an alpha channel are not converted".to_string());
+            }
+        };
+
+        let pixels = image.pixels()?;
+        let samples: Vec<f32> = match pixels.as_ref() {
+            RawImageData::U8(v) => v.iter().map(|s| *s as f32 / 255.0).collect(),
+            RawImageData::U16(v) => v.iter().map(|s| *s as f32 / 65535.0).collect(),
+            RawImageData::F32(v) => v.clone(),
+        };
+        if samples.len() < image.width * image.height * channels {
+            return Err(format!(
+                "image data too short: {} samples, expected {}",
+                samples.len(),
+                image.width * image.height * channels
+            ));
+        }
+
+        let converted = samples
+            .chunks_exact(channels)
+            .take(image.width * image.height)
+            .flat_map(|p| {
+                let (r, g, b) = if bgr {
+                    (p[2], p[1], p[0])
+                } else {
+                    (p[0], p[1], p[2])
+                };
+                self.convert_rgb(r, g, b)
+            })
+            .map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect::<Vec<_>>();
+
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&converted).map_err(|e| e.to_string())?;
+        let bytes = encoder.finish().map_err(|e| e.to_string())?;
+
+        let source = EncodedImage {
+            bytes,
+            filters: vec![StreamFilter::new("FlateDecode")],
+            color_space: self.get_color_space().get_name().to_string(),
+            icc_profile: Some(self.icc_profile.clone()),
+            bits_per_component: 8,
+            decode: None,
+        };
+        let data_format = source.get_decoded_format();
+        Ok(Some(RawImage {
+            pixels: RawImageData::empty(data_format),
+            width: image.width,
+            height: image.height,
+            data_format,
+            tag: image.tag.clone(),
+            source: Some(source),
+        }))
+    }
+}
+
+/// Returns a copy of the document with all RGB colors and images converted
+pub(crate) fn apply_color_transform(
+    pdf: &PdfDocument,
+    transform: &ColorTransform,
+    warnings: &mut Vec<PdfWarnMsg>,
+) -> PdfDocument {
+    let mut doc = pdf.clone();
+    let icc_type = match transform.target {
+        ColorTransformTarget::Cmyk => IccProfileType::Cmyk,
+        ColorTransformTarget::Greyscale => IccProfileType::Greyscale,
+    };
+    let icc_id = doc.add_icc_profile(&IccProfile::new(transform.icc_profile.clone(), icc_type));
+
+    for page in doc.pages.iter_mut() {
+        transform.convert_ops(&mut page.ops, &icc_id);
+    }
+
+    for (id, xobject) in doc.resources.xobjects.map.iter_mut() {
+        match xobject {
+            XObject::Image(image) => match transform.convert_image(image) {
+                Ok(Some(converted)) => *image = converted,
+                Ok(None) => {}
+                Err(e) => warnings.push(
+                    PdfWarnMsg::warning(None, format!("image {:?} was not converted: {e}", id.0))
+                        .with_code(PdfWarnCode::ImageNotConverted)
+                        .with_resource(&id.0),
+                ),
+            },
+            XObject::Group(group) => transform.convert_ops(&mut group.ops, &icc_id),
+            XObject::Ops(form) => transform.convert_ops(&mut form.ops, &icc_id),
+            _ => warnings.push(
+                PdfWarnMsg::warning(
+                    None,
+                    format!("colors of the XObject {:?} were not converted", id.0),
+                )
+                .with_code(PdfWarnCode::XObjectNotConverted)
+                .with_resource(&id.0),
+            ),
+        }
+    }
+
+    doc
+}
+
+#[test]
+fn test_color_transform() {
+    use crate::{DeviceNColor, DeviceNColorSpace, IndexedColor, Mm, PdfPage, PdfSaveOptions, Rgb};
+
+    let icc = include_bytes!("./res/CoatedFOGRA39.icc").to_vec();
+    let transform = ColorTransform::new(ColorTransformTarget::Cmyk, icc);
+
+    let mut doc = PdfDocument::new("transform");
+    let image = RawImage {
+        pixels: RawImageData::U8(vec![255, 0, 0, 0, 0, 0]),
+        width: 2,
+        height: 1,
+        data_format: RawImageFormat::RGB8,
+        tag: Vec::new(),
+        source: None,
+    };
+    let image_id = doc.add_image(&image);
+    let palette = IndexedColorSpace::new(DeviceColorSpace::Rgb, vec![255, 255, 255]);
+    let inks =
+        DeviceNColorSpace::new(DeviceColorSpace::Rgb).with_colorant("Red", vec![1.0, 0.0, 0.0]);
+    doc.pages.push(PdfPage::new(
+        Mm(210.0),
+        Mm(297.0),
+        vec![
+            Op::SetFillColor {
+                col: Color::Rgb(Rgb::new(1.0, 0.0, 0.0, None)),
+            },
+            Op::SetOutlineColor {
+                col: Color::Indexed(IndexedColor::new(palette, 0)),
+            },
+            Op::SetFillColor {
+                col: Color::DeviceN(DeviceNColor::new(inks, vec![1.0])),
+            },
+        ],
+    ));
+
+    let mut warnings = Vec::new();
+    let converted = apply_color_transform(&doc, &transform, &mut warnings);
+    assert!(warnings.is_empty());
+    let icc_id = converted.resources.icc_profiles.map.keys().next().cloned();
+    let ops = &converted.pages[0].ops;
+    assert_eq!(
+        ops[0],
+        Op::SetFillColor {
+            col: Color::Cmyk(Cmyk::new(0.0, 1.0, 1.0, 0.0, icc_id))
+        }
+    );
+    let Op::SetOutlineColor {
+        col: Color::Indexed(indexed),
+    } = &ops[1]
+    else {
+        panic!("expected an indexed color");
+    };
+    assert_eq!(indexed.color_space.palette, vec![0, 0, 0, 0]);
+    let Op::SetFillColor {
+        col: Color::DeviceN(devicen),
+    } = &ops[2]
+    else {
+        panic!("expected a DeviceN color");
+    };
+    assert_eq!(
+        devicen.color_space.colorants[0].full_tint,
+        vec![0.0, 1.0, 1.0, 0.0]
+    );
+
+    let XObject::Image(image) = &converted.resources.xobjects.map[&image_id] else {
+        panic!("expected an image");
+    };
+    let source = image.source.as_ref().unwrap();
+    assert_eq!(source.color_space, "DeviceCMYK");
+    let (pixels, _) = crate::filters::decode_filters(&source.bytes, &source.filters).unwrap();
+    assert_eq!(pixels, vec![0, 255, 255, 0, 0, 0, 0, 255]);
+
+    let opts = PdfSaveOptions {
+        color_transform: Some(transform),
+        ..Default::default()
+    };
+    let text = String::from_utf8_lossy(&doc.save(&opts)).to_string();
+    assert!(text.contains("/ICCBased"));
+    assert!(!text.contains("/DeviceRGB"));
+}
@@ -0,0 +1,952 @@
+//! Reusable HTML components (calendars, schedule grids, invoices, ...) that are expanded
+//! into plain XML before the layout is solved
+
+use crate::html::STRUCT_CLASS_PREFIX;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+
+/// Component that is expanded into plain XML (`div`, `p`, ...) before the layout is solved
+///
+/// In difference to an `XmlComponent` (which renders directly into a DOM node), these
+/// components are text templates: the generated markup is laid out and paginated like
+/// any other content, so they can produce large nested structures such as tables.
+pub trait HtmlComponent: fmt::Debug {
+    /// Tag name of the component, i.e. `calendar` for `<calendar year="2024" month="5" />`
+    fn get_tag_name(&self) -> &str;
+    /// Expands the component, given the attributes and the raw XML content of the node
+    fn expand(&self, args: &BTreeMap<String, String>, content: &str) -> Result<String, String>;
+}
+
+/// Map of components (by tag name) that are expanded before rendering the HTML
+#[derive(Debug)]
+pub struct HtmlComponentMap {
+    pub map: BTreeMap<String, Box<dyn HtmlComponent>>,
+}
+
+impl Default for HtmlComponentMap {
+    /// Returns a map with all builtin components registered
+    fn default() -> Self {
+        let mut map = Self::empty();
+        map.register(Box::new(CalendarComponent::default()));
+        map.register(Box::new(ScheduleGridComponent::default()));
+        map.register(Box::new(AddressBlockComponent::default()));
+        map.register(Box::new(LineItemsComponent::default()));
+        #[cfg(feature = "qrcode")]
+        map.register(Box::new(PaymentQrComponent::default()));
+        map
+    }
+}
+
+impl HtmlComponentMap {
+    /// Returns a map without any components (not even the builtin ones)
+    pub fn empty() -> Self {
+        Self {
+            map: BTreeMap::new(),
+        }
+    }
+
+    /// Registers a component, replacing any component with the same tag name
+    pub fn register(&mut self, component: Box<dyn HtmlComponent>) {
+        self.map
+            .insert(component.get_tag_name().to_string(), component);
+    }
+}
+
+/// Expands all registered components in the XML string (including components that
+/// are generated by other components)
+pub(crate) fn expand_components(
+    xml: &str,
+    components: &HtmlComponentMap,
+) -> Result<String, String> {
+    /// Upper limit of expansions, protects against components that expand into themselves
+    const MAX_EXPANSIONS: usize = 10_000;
+
+    let mut xml = xml.to_string();
+    if components.map.is_empty() {
+        return Ok(xml);
+    }
+
+    for _ in 0..MAX_EXPANSIONS {
+        let node = match find_first_component(&xml, components) {
+            Some(s) => s,
+            None => return Ok(xml),
+        };
+        let component = match components.map.get(&node.tag) {
+            Some(s) => s,
+            None => return Ok(xml),
+        };
+        let expanded = component
+            .expand(&node.args, &xml[node.content.clone()])
+            .map_err(|e| format!("Error expanding component <{}>: {e}", node.tag))?;
+        xml.replace_range(node.range, &expanded);
+    }
+
+    Err(format!(
+        "Error expanding components: more than {MAX_EXPANSIONS} expansions (recursive component?)"
+    ))
+}
+
+struct ComponentNode {
+    tag: String,
+    args: BTreeMap<String, String>,
+    /// Range of the entire node, including start and end tag
+    range: Range<usize>,
+    /// Range of the content between start and end tag
+    content: Range<usize>,
+}
+
+fn find_first_component(xml: &str, components: &HtmlComponentMap) -> Option<ComponentNode> {
+    use xmlparser::{ElementEnd, Token, Tokenizer};
+
+    let mut found: Option<ComponentNode> = None;
+    let mut in_start_tag = false;
+    let mut in_nested_start_tag = false;
+    let mut depth = 0_usize;
+
+    for token in Tokenizer::from(xml) {
+        // invalid XML: leave it to the XML parser to report the error
+        let token = token.ok()?;
+        match token {
+            Token::ElementStart { local, span, .. } => match found.as_ref() {
+                None if components.map.contains_key(local.as_str()) => {
+                    found = Some(ComponentNode {
+                        tag: local.as_str().to_string(),
+                        args: BTreeMap::new(),
+                        range: span.start()..span.end(),
+                        content: span.end()..span.end(),
+                    });
+                    in_start_tag = true;
+                }
+                Some(f) if f.tag == local.as_str() => {
+                    depth += 1;
+                    in_nested_start_tag = true;
+                }
+                _ => {}
+            },
+            Token::Attribute { local, value, .. } if in_start_tag => {
+                if let Some(f) = found.as_mut() {
+                    f.args
+                        .insert(local.as_str().to_string(), unescape_xml(value.as_str()));
+                }
+            }
+            Token::ElementEnd { end, span } => {
+                let f = match found.as_mut() {
+                    Some(s) => s,
+                    None => continue,
+                };
+                match end {
+                    ElementEnd::Empty if in_start_tag => {
+                        f.range.end = span.end();
+                        f.content = span.end()..span.end();
+                        return found;
+                    }
+                    ElementEnd::Open if in_start_tag => {
+                        in_start_tag = false;
+                        f.content = span.end()..span.end();
+                    }
+                    ElementEnd::Empty if in_nested_start_tag => {
+                        depth -= 1;
+                    }
+                    ElementEnd::Close(_, local) if local.as_str() == f.tag => {
+                        if depth == 0 {
+                            f.content.end = span.start();
+                            f.range.end = span.end();
+                            return found;
+                        }
+                        depth -= 1;
+                    }
+                    _ => {}
+                }
+                in_nested_start_tag = false;
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Escapes text so that it can be inserted into generated XML
+pub fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+pub(crate) fn unescape_xml(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+const ROW_STYLE: &str = "display:flex;flex-direction:row;page-break-inside:avoid;";
+const CELL_STYLE: &str = "flex-grow:1;width:0px;border:1px solid black;padding:2px;";
+
+/// Start of the header row group (`THead`) of the calendar and schedule grid tables
+fn table_header_start() -> String {
+    format!(
+        "<div class=\"{STRUCT_CLASS_PREFIX}THead\" style=\"display:flex;flex-direction:column;\">"
+    )
+}
+
+/// Start of the body row group (`TBody`), see `table_header_start`
+fn table_body_start() -> String {
+    format!(
+        "<div class=\"{STRUCT_CLASS_PREFIX}TBody\" style=\"display:flex;flex-direction:column;\">"
+    )
+}
+
+/// Monthly calendar, expanded into a table with a header row and one row per week
+///
+/// ```xml
+/// <calendar year="2024" month="5" first-weekday="sunday">
+///     3: Team meeting
+///     17: Deadline
+/// </calendar>
+/// ```
+///
+/// Arguments:
+///
+/// - `year`, `month` (1 - 12): required
+/// - `first-weekday`: `monday` (default) or `sunday`
+/// - `day-names`: comma-separated names of the weekdays, starting at monday
+/// - `title`: title of the calendar (default: "May 2024")
+///
+/// Each line of the content is an entry in the form of `day: text`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct CalendarComponent {}
+
+impl HtmlComponent for CalendarComponent {
+    fn get_tag_name(&self) -> &str {
+        "calendar"
+    }
+
+    fn expand(&self, args: &BTreeMap<String, String>, content: &str) -> Result<String, String> {
+        const MONTH_NAMES: [&str; 12] = [
+            "January",
+            "February",
+            "March",
+            "April",
+            "May",
+            "June",
+            "July",
+            "August",
+            "September",
+            "October",
+            "November",
+            "December",
+        ];
+
+        let year = parse_required_arg::<i32>(args, "year")?;
+        let month = parse_required_arg::<u32>(args, "month")?;
+        if !(1..=12).contains(&month) {
+            return Err(format!("invalid month {month}, expected 1 - 12"));
+        }
+
+        let starts_on_sunday = match args.get("first-weekday").map(|s| s.trim()) {
+            None | Some("monday") => false,
+            Some("sunday") => true,
+            Some(o) => return Err(format!("invalid first-weekday \"{o}\"")),
+        };
+
+        let mut day_names = match args.get("day-names") {
+            Some(s) => s.split(',').map(|s| s.trim().to_string()).collect(),
+            None => ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>(),
+        };
+        if day_names.len() != 7 {
+            return Err(format!(
+                "day-names must contain 7 names, found {}",
+                day_names.len()
+            ));
+        }
+        if starts_on_sunday {
+            day_names.rotate_right(1);
+        }
+
+        let title = args
+            .get("title")
+            .cloned()
+            .unwrap_or_else(|| format!("{} {year}", MONTH_NAMES[month as usize - 1]));
+
+        let mut entries = BTreeMap::<u32, Vec<String>>::new();
+        for line in content.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            let (day, text) = line.split_once(':').ok_or_else(|| {
+                format!("invalid calendar entry \"{line}\", expected \"day: text\"")
+            })?;
+            let day = day
+                .trim()
+                .parse::<u32>()
+                .map_err(|e| format!("invalid day in calendar entry \"{line}\": {e}"))?;
+            entries
+                .entry(day)
+                .or_default()
+                .push(unescape_xml(text.trim()));
+        }
+
+        let num_days = days_in_month(year, month);
+        // 0 = first column of the calendar
+        let offset = (day_of_week(year, month, 1) + usize::from(starts_on_sunday)) % 7;
+
+        let mut s = String::new();
+        s.push_str(&format!(
+            "<div class=\"calendar {STRUCT_CLASS_PREFIX}Table\" style=\"display:flex;flex-direction:column;\">"
+        ));
+        s.push_str(&format!(
+            "<p class=\"calendar-title {STRUCT_CLASS_PREFIX}Caption\">{}</p>",
+            escape_xml(&title)
+        ));
+
+        s.push_str(&table_header_start());
+        s.push_str(&format!(
+            "<div class=\"calendar-row calendar-header {STRUCT_CLASS_PREFIX}TR\" style=\"{ROW_STYLE}\">"
+        ));
+        for name in day_names.iter() {
+            s.push_str(&format!(
+                "<div class=\"calendar-cell {STRUCT_CLASS_PREFIX}TH_Column\" style=\"{CELL_STYLE}\"><p>{}</p></div>",
+                escape_xml(name)
+            ));
+        }
+        s.push_str("</div></div>");
+        s.push_str(&table_body_start());
+
+        let num_weeks = (offset + num_days as usize).div_ceil(7);
+        for week in 0..num_weeks {
+            s.push_str(&format!(
+                "<div class=\"calendar-row {STRUCT_CLASS_PREFIX}TR\" style=\"{ROW_STYLE}\">"
+            ));
+            for col in 0..7 {
+                let day = (week * 7 + col) as i64 - offset as i64 + 1;
+                if day < 1 || day > num_days as i64 {
+                    s.push_str(&format!(
+                        "<div class=\"calendar-cell calendar-cell-empty {STRUCT_CLASS_PREFIX}TD\" style=\"{CELL_STYLE}\"></div>"
+                    ));
+                    continue;
+                }
+                s.push_str(&format!(
+                    "<div class=\"calendar-cell {STRUCT_CLASS_PREFIX}TD\" style=\"{CELL_STYLE}min-height:60px;\"><p class=\"calendar-day\">{day}</p>"
+                ));
+                for entry in entries.get(&(day as u32)).into_iter().flatten() {
+                    s.push_str(&format!(
+                        "<p class=\"calendar-entry\">{}</p>",
+                        escape_xml(entry)
+                    ));
+                }
+                s.push_str("</div>");
+            }
+            s.push_str("</div>");
+        }
+
+        s.push_str("</div></div>");
+        Ok(s)
+    }
+}
+
+/// Schedule grid (i.e. a weekly timetable), expanded into a table with a header row and
+/// one row per time slot
+///
+/// ```xml
+/// <schedule-grid columns="Mon,Tue,Wed" start="08:00" end="12:00" step="30">
+///     Mon 09:00-10:30 Standup
+///     Wed 11:00-12:00 Review
+/// </schedule-grid>
+/// ```
+///
+/// Arguments:
+///
+/// - `columns`: comma-separated column names (required)
+/// - `start`, `end`: time range of the grid (default: 08:00 - 18:00)
+/// - `step`: length of a time slot in minutes (default: 60)
+///
+/// Each line of the content is an entry in the form of `column HH:MM-HH:MM text`.
+/// The text is placed in the slot that the entry starts in, following slots that
+/// are covered by the entry are marked with the `schedule-cell-busy` class.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct ScheduleGridComponent {}
+
+struct ScheduleEntry {
+    column: usize,
+    start: u32,
+    end: u32,
+    text: String,
+}
+
+impl HtmlComponent for ScheduleGridComponent {
+    fn get_tag_name(&self) -> &str {
+        "schedule-grid"
+    }
+
+    fn expand(&self, args: &BTreeMap<String, String>, content: &str) -> Result<String, String> {
+        let columns = args
+            .get("columns")
+            .ok_or_else(|| "missing required argument \"columns\"".to_string())?
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .collect::<Vec<_>>();
+
+        let start = parse_time(args.get("start").map(|s| s.as_str()).unwrap_or("08:00"))?;
+        let end = parse_time(args.get("end").map(|s| s.as_str()).unwrap_or("18:00"))?;
+        let step = match args.get("step") {
+            Some(s) => s
+                .trim()
+                .parse::<u32>()
+                .map_err(|e| format!("invalid step \"{s}\": {e}"))?,
+            None => 60,
+        };
+        if step == 0 || end <= start {
+            return Err(format!(
+                "invalid time range {start} - {end} (step {step} min)"
+            ));
+        }
+
+        let mut entries = Vec::new();
+        for line in content.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            let invalid = || {
+                format!("invalid schedule entry \"{line}\", expected \"column HH:MM-HH:MM text\"")
+            };
+            let mut parts = line.splitn(3, char::is_whitespace);
+            let column_name = parts.next().ok_or_else(invalid)?;
+            let range = parts.next().ok_or_else(invalid)?;
+            let text = parts.next().unwrap_or("").trim();
+            let column = columns
+                .iter()
+                .position(|c| c == column_name)
+                .ok_or_else(|| format!("unknown column \"{column_name}\" in entry \"{line}\""))?;
+            let (entry_start, entry_end) = range.split_once('-').ok_or_else(invalid)?;
+            entries.push(ScheduleEntry {
+                column,
+                start: parse_time(entry_start)?,
+                end: parse_time(entry_end)?,
+                text: unescape_xml(text),
+            });
+        }
+
+        let mut s = String::new();
+        s.push_str(&format!(
+            "<div class=\"schedule-grid {STRUCT_CLASS_PREFIX}Table\" style=\"display:flex;flex-direction:column;\">"
+        ));
+
+        s.push_str(&table_header_start());
+        s.push_str(&format!(
+            "<div class=\"schedule-row schedule-header {STRUCT_CLASS_PREFIX}TR\" style=\"{ROW_STYLE}\">"
+        ));
+        s.push_str(&format!(
+            "<div class=\"schedule-cell schedule-time {STRUCT_CLASS_PREFIX}TD\" style=\"{CELL_STYLE}\"></div>"
+        ));
+        for c in columns.iter() {
+            s.push_str(&format!(
+                "<div class=\"schedule-cell {STRUCT_CLASS_PREFIX}TH_Column\" style=\"{CELL_STYLE}\"><p>{}</p></div>",
+                escape_xml(c)
+            ));
+        }
+        s.push_str("</div></div>");
+        s.push_str(&table_body_start());
+
+        let mut slot_start = start;
+        while slot_start < end {
+            let slot_end = slot_start + step;
+            s.push_str(&format!(
+                "<div class=\"schedule-row {STRUCT_CLASS_PREFIX}TR\" style=\"{ROW_STYLE}\">"
+            ));
+            s.push_str(&format!(
+                "<div class=\"schedule-cell schedule-time {STRUCT_CLASS_PREFIX}TH_Row\" style=\"{CELL_STYLE}\"><p>{}</p></div>",
+                format_time(slot_start)
+            ));
+            for col in 0..columns.len() {
+                let starting = entries
+                    .iter()
+                    .filter(|e| e.column == col && e.start >= slot_start && e.start < slot_end)
+                    .collect::<Vec<_>>();
+                let busy = entries
+                    .iter()
+                    .any(|e| e.column == col && e.start < slot_start && e.end > slot_start);
+
+                let class = if starting.is_empty() && busy {
+                    "schedule-cell schedule-cell-busy"
+                } else {
+                    "schedule-cell"
+                };
+                s.push_str(&format!(
+                    "<div class=\"{class} {STRUCT_CLASS_PREFIX}TD\" style=\"{CELL_STYLE}\">"
+                ));
+                for e in starting {
+                    s.push_str(&format!(
+                        "<p class=\"schedule-entry\">{} - {} {}</p>",
+                        format_time(e.start),
+                        format_time(e.end),
+                        escape_xml(&e.text)
+                    ));
+                }
+                s.push_str("</div>");
+            }
+            s.push_str("</div>");
+            slot_start = slot_end;
+        }
+
+        s.push_str("</div></div>");
+        Ok(s)
+    }
+}
+
+/// Address block (sender / recipient of a letter or invoice)
+///
+/// ```xml
+/// <address-block title="Bill to">
+///     ACME Corp.
+///     221B Baker Street
+///     London NW1 6XE
+/// </address-block>
+/// ```
+///
+/// Each line of the content is one line of the address, the optional `title`
+/// is rendered above the address.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct AddressBlockComponent {}
+
+impl HtmlComponent for AddressBlockComponent {
+    fn get_tag_name(&self) -> &str {
+        "address-block"
+    }
+
+    fn expand(&self, args: &BTreeMap<String, String>, content: &str) -> Result<String, String> {
+        let mut s = String::new();
+        s.push_str("<div class=\"address-block\" style=\"display:flex;flex-direction:column;\">");
+        if let Some(title) = args.get("title") {
+            s.push_str(&format!(
+                "<p class=\"address-block-title\">{}</p>",
+                escape_xml(title)
+            ));
+        }
+        for line in content.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            s.push_str(&format!(
+                "<p class=\"address-block-line\">{}</p>",
+                escape_xml(&unescape_xml(line))
+            ));
+        }
+        s.push_str("</div>");
+        Ok(s)
+    }
+}
+
+/// Table of line items with subtotal, tax and total
+///
+/// ```xml
+/// <line-items currency="EUR" tax-rate="19">
+///     Consulting (hours) | 12.5 | 95.00
+///     Travel expenses    | 1    | 120.00
+/// </line-items>
+/// ```
+///
+/// Arguments:
+///
+/// - `currency`: appended to all amounts (default: none)
+/// - `tax-rate`: tax in percent, added to the subtotal (default: 0, hides the tax row)
+/// - `labels`: comma-separated column headers (default: "Description,Quantity,Unit price,Amount")
+/// - `subtotal-label`, `tax-label`, `total-label`: labels for the summary rows
+///
+/// Each line of the content is an item in the form of `description | quantity | unit price`.
+/// Amounts are rounded to cents for each line, so the totals match the printed numbers.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct LineItemsComponent {}
+
+struct LineItem {
+    description: String,
+    quantity: f64,
+    unit_price: i64,
+    amount: i64,
+}
+
+impl HtmlComponent for LineItemsComponent {
+    fn get_tag_name(&self) -> &str {
+        "line-items"
+    }
+
+    fn expand(&self, args: &BTreeMap<String, String>, content: &str) -> Result<String, String> {
+        let currency = args
+            .get("currency")
+            .map(|s| format!(" {}", s.trim()))
+            .unwrap_or_default();
+        let tax_rate = match args.get("tax-rate") {
+            Some(s) => s
+                .trim()
+                .parse::<f64>()
+                .map_err(|e| format!("invalid tax-rate \"{s}\": {e}"))?,
+            None => 0.0,
+        };
+        let labels = match args.get("labels") {
+            Some(s) => s.split(',').map(|s| s.trim().to_string()).collect(),
+            None => ["Description", "Quantity", "Unit price", "Amount"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>(),
+        };
+        if labels.len() != 4 {
+            return Err(format!(
+                "labels must contain 4 names, found {}",
+                labels.len()
+            ));
+        }
+        let get_label = |key: &str, default: &str| {
+            args.get(key)
+                .cloned()
+                .unwrap_or_else(|| default.to_string())
+        };
+
+        let items = content
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty())
+            .map(parse_line_item)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let subtotal = items.iter().map(|i| i.amount).sum::<i64>();
+        let tax = (subtotal as f64 * tax_rate / 100.0).round() as i64;
+        let total = subtotal + tax;
+
+        let mut s = String::new();
+        // the "__printpdf_struct_*" classes tag the rows and cells as a table for screen readers
+        s.push_str(&format!(
+            "<div class=\"line-items {STRUCT_CLASS_PREFIX}Table\" style=\"display:flex;flex-direction:column;\">"
+        ));
+
+        s.push_str(&format!(
+            "<div class=\"line-items-row line-items-header {STRUCT_CLASS_PREFIX}TR\" style=\"{ROW_STYLE}\">"
+        ));
+        for (i, label) in labels.iter().enumerate() {
+            s.push_str(&line_items_cell(i, "TH_Column", &escape_xml(label)));
+        }
+        s.push_str("</div>");
+
+        for item in items.iter() {
+            s.push_str(&format!(
+                "<div class=\"line-items-row {STRUCT_CLASS_PREFIX}TR\" style=\"{ROW_STYLE}\">"
+            ));
+            s.push_str(&line_items_cell(0, "TD", &escape_xml(&item.description)));
+            s.push_str(&line_items_cell(1, "TD", &item.quantity.to_string()));
+            s.push_str(&line_items_cell(
+                2,
+                "TD",
+                &format!("{}{}", format_cents(item.unit_price), escape_xml(&currency)),
+            ));
+            s.push_str(&line_items_cell(
+                3,
+                "TD",
+                &format!("{}{}", format_cents(item.amount), escape_xml(&currency)),
+            ));
+            s.push_str("</div>");
+        }
+
+        let mut summary = vec![(
+            "line-items-subtotal",
+            get_label("subtotal-label", "Subtotal"),
+            subtotal,
+        )];
+        if tax_rate != 0.0 {
+            summary.push((
+                "line-items-tax",
+                get_label("tax-label", &format!("Tax ({tax_rate}%)")),
+                tax,
+            ));
+        }
+        summary.push(("line-items-total", get_label("total-label", "Total"), total));
+
+        for (class, label, value) in summary {
+            s.push_str(&format!(
+                "<div class=\"line-items-row {class} {STRUCT_CLASS_PREFIX}TR\" style=\"{ROW_STYLE}justify-content:flex-end;\">"
+            ));
+            s.push_str(&line_items_cell(2, "TH_Row", &escape_xml(&label)));
+            s.push_str(&line_items_cell(
+                3,
+                "TD",
+                &format!("{}{}", format_cents(value), escape_xml(&currency)),
+            ));
+            s.push_str("</div>");
+        }
+
+        s.push_str("</div>");
+        Ok(s)
+    }
+}
+
+/// `role` is the structure type of the cell ("TD", "TH_Column" or "TH_Row")
+fn line_items_cell(column: usize, role: &str, text: &str) -> String {
+    // description column takes up the remaining space, numbers are right-aligned
+    let style = match column {
+        0 => "flex-grow:1;padding:2px;",
+        _ => "width:80px;padding:2px;text-align:right;",
+    };
+    format!(
+        "<div class=\"line-items-cell {STRUCT_CLASS_PREFIX}{role}\" style=\"{style}\"><p>{text}</p></div>"
+    )
+}
+
+fn parse_line_item(line: &str) -> Result<LineItem, String> {
+    let parts = line.split('|').map(|s| s.trim()).collect::<Vec<_>>();
+    let (description, quantity, unit_price) = match parts.as_slice() {
+        [d, q, p] => (d, q, p),
+        _ => {
+            return Err(format!(
+                "invalid line item \"{line}\", expected \"description | quantity | unit price\""
+            ))
+        }
+    };
+    let quantity = quantity
+        .parse::<f64>()
+        .map_err(|e| format!("invalid quantity in line item \"{line}\": {e}"))?;
+    let unit_price = parse_cents(unit_price)
+        .ok_or_else(|| format!("invalid unit price in line item \"{line}\""))?;
+    Ok(LineItem {
+        description: unescape_xml(description),
+        quantity,
+        unit_price,
+        amount: (quantity * unit_price as f64).round() as i64,
+    })
+}
+
+/// Parses "12.50" into 1250 cents
+fn parse_cents(s: &str) -> Option<i64> {
+    let value = s.trim().parse::<f64>().ok()?;
+    if !value.is_finite() {
+        return None;
+    }
+    Some((value * 100.0).round() as i64)
+}
+
+/// Formats 1250 cents as "12.50"
+fn format_cents(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    let cents = cents.unsigned_abs();
+    format!("{sign}{}.{:02}", cents / 100, cents % 100)
+}
+
+/// Payment QR code (i.e. an EPC / "GiroCode" QR code for SEPA transfers)
+///
+/// ```xml
+/// <payment-qr iban="DE89370400440532013000" name="ACME Corp." amount="1234.50" reference="Invoice 2024-001" />
+/// <payment-qr data="https://pay.example.com/invoice/2024-001" caption="Pay online" />
+/// ```
+///
+/// Arguments:
+///
+/// - `data`: raw content of the QR code, or:
+/// - `iban`, `name` (required), `bic`, `amount` (in EUR), `reference`: fields of an EPC QR code
+/// - `module-size`: size of a single QR module in px (default: 2)
+/// - `caption`: text below the code
+///
+/// Only available with the `qrcode` feature.
+#[cfg(feature = "qrcode")]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct PaymentQrComponent {}
+
+#[cfg(feature = "qrcode")]
+impl HtmlComponent for PaymentQrComponent {
+    fn get_tag_name(&self) -> &str {
+        "payment-qr"
+    }
+
+    fn expand(&self, args: &BTreeMap<String, String>, _content: &str) -> Result<String, String> {
+        let data = match args.get("data") {
+            Some(s) => s.clone(),
+            None => epc_qr_payload(args)?,
+        };
+        let module_size = match args.get("module-size") {
+            Some(s) => s
+                .trim()
+                .parse::<f32>()
+                .map_err(|e| format!("invalid module-size \"{s}\": {e}"))?,
+            None => 2.0,
+        };
+
+        let code = qrcode::QrCode::new(data.as_bytes())
+            .map_err(|e| format!("could not generate QR code: {e}"))?;
+        let width = code.width();
+        let colors = code.to_colors();
+
+        let mut s = String::new();
+        s.push_str(&format!(
+            "<div class=\"payment-qr\" style=\"display:flex;flex-direction:column;padding:{}px;\">",
+            module_size * 4.0
+        ));
+        for row in colors.chunks(width) {
+            s.push_str(&format!(
+                "<div class=\"payment-qr-row\" style=\"display:flex;flex-direction:row;height:{module_size}px;\">"
+            ));
+            // merge runs of modules with the same color to keep the DOM small
+            let mut x = 0;
+            while x < row.len() {
+                let color = row[x];
+                let run = row[x..].iter().take_while(|c| **c == color).count();
+                let background = match color {
+                    qrcode::Color::Dark => "black",
+                    qrcode::Color::Light => "white",
+                };
+                s.push_str(&format!(
+                    "<div style=\"width:{}px;height:{module_size}px;background:{background};\"></div>",
+                    module_size * run as f32
+                ));
+                x += run;
+            }
+            s.push_str("</div>");
+        }
+        if let Some(caption) = args.get("caption") {
+            s.push_str(&format!(
+                "<p class=\"payment-qr-caption\">{}</p>",
+                escape_xml(caption)
+            ));
+        }
+        s.push_str("</div>");
+        Ok(s)
+    }
+}
+
+/// Builds the payload of an EPC QR code (EPC069-12, version 002)
+#[cfg(feature = "qrcode")]
+fn epc_qr_payload(args: &BTreeMap<String, String>) -> Result<String, String> {
+    let get = |key: &str| args.get(key).map(|s| s.trim()).unwrap_or("");
+    let iban = get("iban").replace(' ', "");
+    let name = get("name");
+    if iban.is_empty() || name.is_empty() {
+        return Err("either \"data\" or \"iban\" and \"name\" are required".to_string());
+    }
+    let amount = match args.get("amount") {
+        Some(s) => {
+            let cents = parse_cents(s).ok_or_else(|| format!("invalid amount \"{s}\""))?;
+            format!("EUR{}", format_cents(cents))
+        }
+        None => String::new(),
+    };
+
+    Ok([
+        "BCD",
+        "002",
+        "1",
+        "SCT",
+        get("bic"),
+        name,
+        iban.as_str(),
+        amount.as_str(),
+        "",
+        "",
+        get("reference"),
+    ]
+    .join("\n"))
+}
+
+fn parse_required_arg<T: std::str::FromStr>(
+    args: &BTreeMap<String, String>,
+    key: &str,
+) -> Result<T, String> {
+    let val = args
+        .get(key)
+        .ok_or_else(|| format!("missing required argument \"{key}\""))?;
+    val.trim()
+        .parse::<T>()
+        .map_err(|_| format!("invalid value \"{val}\" for argument \"{key}\""))
+}
+
+/// Parses "HH:MM" into minutes since midnight
+fn parse_time(s: &str) -> Result<u32, String> {
+    let (h, m) = s
+        .trim()
+        .split_once(':')
+        .ok_or_else(|| format!("invalid time \"{s}\", expected HH:MM"))?;
+    let h = h
+        .parse::<u32>()
+        .map_err(|e| format!("invalid time \"{s}\": {e}"))?;
+    let m = m
+        .parse::<u32>()
+        .map_err(|e| format!("invalid time \"{s}\": {e}"))?;
+    if h > 24 || m > 59 {
+        return Err(format!("invalid time \"{s}\""));
+    }
+    Ok(h * 60 + m)
+}
+
+fn format_time(minutes: u32) -> String {
+    format!("{:02}:{:02}", minutes / 60, minutes % 60)
+}
+
+fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Returns the day of the week (0 = monday, 6 = sunday)
+fn day_of_week(year: i32, month: u32, day: u32) -> usize {
+    // Sakamoto's method, returns 0 = sunday
+    const T: [i32; 12] = [0, 3, 2, 5, 0, 3, 5, 1, 4, 6, 2, 4];
+    let y = if month < 3 { year - 1 } else { year };
+    let dow = (y + y.div_euclid(4) - y.div_euclid(100)
+        + y.div_euclid(400)
+        + T[month as usize - 1]
+        + day as i32)
+        .rem_euclid(7);
+    ((dow + 6) % 7) as usize
+}
+
+#[test]
+fn test_calendar_component() {
+    // 2024-02-01 is a thursday, 2024 is a leap year
+    assert_eq!(day_of_week(2024, 2, 1), 3);
+    assert_eq!(days_in_month(2024, 2), 29);
+    assert_eq!(days_in_month(1900, 2), 28);
+
+    let xml =
+        "<body><calendar year=\"2024\" month=\"2\">14: Valentine's &amp; co</calendar></body>";
+    let expanded = expand_components(xml, &HtmlComponentMap::default()).unwrap();
+    assert!(expanded.starts_with("<body><div class=\"calendar\""));
+    assert!(expanded.ends_with("</div></body>"));
+    assert!(expanded.contains("<p class=\"calendar-day\">29</p>"));
+    assert!(!expanded.contains("<p class=\"calendar-day\">30</p>"));
+    assert!(expanded.contains("Valentine&apos;s &amp; co"));
+    assert!(!expanded.contains("<calendar"));
+    // header, 5 weeks
+    assert_eq!(expanded.matches("__printpdf_struct_TR").count(), 6);
+    assert_eq!(expanded.matches("__printpdf_struct_TH_Column").count(), 7);
+    assert!(expanded.contains("calendar-cell __printpdf_struct_TD"));
+}
+
+#[test]
+fn test_schedule_grid_component() {
+    let xml = "<schedule-grid columns=\"Mon,Tue\" start=\"08:00\" end=\"10:00\">
+        Tue 08:00-09:30 Standup
+    </schedule-grid>";
+    let expanded = expand_components(xml, &HtmlComponentMap::default()).unwrap();
+    assert!(expanded.starts_with("<div class=\"schedule-grid __printpdf_struct_Table\""));
+    assert!(expanded.contains("<p class=\"schedule-entry\">08:00 - 09:30 Standup</p>"));
+    assert!(expanded.contains("schedule-cell schedule-cell-busy __printpdf_struct_TD"));
+    // header, 2 slots
+    assert_eq!(expanded.matches("__printpdf_struct_TR").count(), 3);
+    assert_eq!(expanded.matches("__printpdf_struct_TH_Row").count(), 2);
+}
+
+#[test]
+fn test_line_items_component() {
+    let xml = "<line-items currency=\"EUR\" tax-rate=\"19\">
+        Consulting | 12.5 | 95.00
+        Travel &amp; hotel | 1 | 120.10
+    </line-items>";
+    let expanded = expand_components(xml, &HtmlComponentMap::default()).unwrap();
+    // 12.5 * 95.00 + 120.10 = 1307.60, 19% tax = 248.44
+    assert!(expanded.contains("<p>1187.50 EUR</p>"));
+    assert!(expanded.contains("<p>Travel &amp; hotel</p>"));
+    assert!(expanded.contains("<p>1307.60 EUR</p>"));
+    assert!(expanded.contains("<p>248.44 EUR</p>"));
+    assert!(expanded.contains("<p>1556.04 EUR</p>"));
+    assert!(expanded.contains("line-items-cell __printpdf_struct_TH_Column"));
+}
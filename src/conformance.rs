@@ -6,6 +6,10 @@
 //!
 //! [PDF/A Versions](https://en.wikipedia.org/wiki/PDF/A)
 
+use std::collections::BTreeSet;
+
+use crate::{Color, Op, PdfDocument, PdfWarnCode, PdfWarnMsg, XObject};
+
 /// List of (relevant) PDF versions
 /// Please note the difference between **PDF/A** (archiving), **PDF/UA** (universal acessibility),
 /// **PDF/X** (printing), **PDF/E** (engineering / CAD), **PDF/VT** (large volume transactions with
@@ -233,6 +237,14 @@ impl PdfConformance {
         }
     }
 
+    /// Does this conformance level allow the builtin (non-embedded) PDF fonts
+    pub fn is_default_fonts_allowed(&self) -> bool {
+        match *self {
+            PdfConformance::Custom(ref c) => c.allows_default_fonts,
+            _ => false,
+        }
+    }
+
     /// Check if the conformance level must have an ICC Profile
     pub fn must_have_icc_profile(&self) -> bool {
         // todo
@@ -243,6 +255,59 @@ impl PdfConformance {
         }
     }
 
+    /// Does this conformance level allow password protection (PDF/A and PDF/X forbid it)
+    pub fn is_encryption_allowed(&self) -> bool {
+        matches!(
+            self,
+            PdfConformance::UA_2014_PDF_1_6 | PdfConformance::Custom(_)
+        )
+    }
+
+    /// Does this conformance level allow the `/LZWDecode` filter (PDF/A forbids it)
+    pub fn is_lzw_allowed(&self) -> bool {
+        !matches!(
+            self,
+            PdfConformance::A1B_2005_PDF_1_4
+                | PdfConformance::A1A_2005_PDF_1_4
+                | PdfConformance::A2_2011_PDF_1_7
+                | PdfConformance::A2A_2011_PDF_1_7
+                | PdfConformance::A2B_2011_PDF_1_7
+                | PdfConformance::A2U_2011_PDF_1_7
+                | PdfConformance::A3_2012_PDF_1_7
+        )
+    }
+
+    /// Does this conformance level allow embedded files (PDF/A-1 forbids them,
+    /// PDF/A-2 only allows embedded PDF/A documents)
+    pub fn is_embedded_files_allowed(&self) -> bool {
+        !matches!(
+            self,
+            PdfConformance::A1B_2005_PDF_1_4
+                | PdfConformance::A1A_2005_PDF_1_4
+                | PdfConformance::A2_2011_PDF_1_7
+                | PdfConformance::A2A_2011_PDF_1_7
+                | PdfConformance::A2B_2011_PDF_1_7
+                | PdfConformance::A2U_2011_PDF_1_7
+                | PdfConformance::X1A_2001_PDF_1_3
+                | PdfConformance::X1A_2003_PDF_1_4
+        )
+    }
+
+    /// Does this conformance level allow object streams and cross-reference streams
+    /// (PDF 1.5), the standards based on PDF 1.3 / 1.4 forbid them
+    pub fn is_object_streams_allowed(&self) -> bool {
+        match self {
+            PdfConformance::A1B_2005_PDF_1_4
+            | PdfConformance::A1A_2005_PDF_1_4
+            | PdfConformance::X1A_2001_PDF_1_3
+            | PdfConformance::X3_2002_PDF_1_3
+            | PdfConformance::X1A_2003_PDF_1_4
+            | PdfConformance::X3_2003_PDF_1_4
+            | PdfConformance::X4_2010_PDF_1_4 => false,
+            _ => true,
+        }
+    }
+
     /// __STUB__: Detects if the PDF has layering (optional content groups),
     /// but the conformance to the given PDF standard does not allow it.
     pub fn is_layering_allowed(&self) -> bool {
@@ -256,3 +321,318 @@ impl PdfConformance {
         }
     }
 }
+
+/// Kind of a conformance violation, see `PdfDocument::validate`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ConformanceViolationKind {
+    /// The conformance level requires XMP metadata
+    MissingXmpMetadata,
+    /// The conformance level requires a document title
+    MissingTitle,
+    /// The conformance level requires the natural language of the document (`/Lang`)
+    MissingLanguage,
+    /// The conformance level requires an output intent, but none is written
+    MissingOutputIntent,
+    /// A builtin font is used, but all fonts have to be embedded
+    NonEmbeddedFont,
+    /// Device-dependent colors are used without an output intent
+    DeviceColorWithoutOutputIntent,
+    /// DeviceRGB colors are used, but the output intent is CMYK
+    RgbColor,
+    /// An RGB image is used, but the output intent is CMYK
+    RgbImage,
+    /// Layers (optional content groups) are not allowed
+    Layers,
+    /// Embedded files are not allowed
+    EmbeddedFiles,
+}
+
+impl ConformanceViolationKind {
+    /// Code of the `PdfWarnMsg` that is emitted on save
+    pub fn get_warn_code(&self) -> PdfWarnCode {
+        use ConformanceViolationKind::*;
+        match self {
+            MissingXmpMetadata => PdfWarnCode::MissingXmpMetadata,
+            MissingTitle => PdfWarnCode::MissingTitle,
+            MissingLanguage => PdfWarnCode::MissingLanguage,
+            MissingOutputIntent => PdfWarnCode::MissingOutputIntent,
+            NonEmbeddedFont => PdfWarnCode::NonEmbeddedFont,
+            DeviceColorWithoutOutputIntent => PdfWarnCode::DeviceColorWithoutOutputIntent,
+            RgbColor => PdfWarnCode::RgbColor,
+            RgbImage => PdfWarnCode::RgbImage,
+            Layers => PdfWarnCode::LayersNotAllowed,
+            EmbeddedFiles => PdfWarnCode::EmbeddedFilesNotAllowed,
+        }
+    }
+}
+
+/// Violation of a conformance level, found by `PdfDocument::validate`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ConformanceViolation {
+    /// What kind of rule is violated
+    pub kind: ConformanceViolationKind,
+    /// Page of the violation (0-based), `None` for document-level violations
+    pub page: Option<usize>,
+    /// Index of the violating operation in `PdfPage::ops`
+    pub op: Option<usize>,
+    /// Human-readable description of the violation
+    pub msg: String,
+}
+
+impl ConformanceViolation {
+    fn new(
+        kind: ConformanceViolationKind,
+        page: Option<usize>,
+        op: Option<usize>,
+        msg: String,
+    ) -> Self {
+        Self {
+            kind,
+            page,
+            op,
+            msg,
+        }
+    }
+}
+
+impl PdfDocument {
+    /// Checks the document against the given conformance level and returns every
+    /// violation with its page and operation index.
+    ///
+    /// Custom conformances are only checked for the options they restrict
+    /// (builtin fonts and layers).
+    pub fn validate(&self, conformance: &PdfConformance) -> Vec<ConformanceViolation> {
+        use self::ConformanceViolationKind::*;
+
+        let is_custom = matches!(conformance, PdfConformance::Custom(_));
+        let id = conformance.get_identifier_string();
+        let is_pdf_a = !is_custom && (id.starts_with("PDF/A") || id.starts_with("PDF/UA"));
+        let is_pdf_x = !is_custom && id.starts_with("PDF/X");
+        let has_output_intent = conformance.must_have_icc_profile();
+
+        let mut violations = Vec::new();
+
+        if is_pdf_a && !conformance.must_have_xmp_metadata() {
+            violations.push(ConformanceViolation::new(
+                MissingXmpMetadata,
+                None,
+                None,
+                format!("{id} requires XMP metadata, but no XMP metadata is written for this conformance level"),
+            ));
+        }
+
+        if (is_pdf_x || id.starts_with("PDF/UA")) && self.metadata.info.document_title.is_empty() {
+            violations.push(ConformanceViolation::new(
+                MissingTitle,
+                None,
+                None,
+                format!("{id} requires a document title"),
+            ));
+        }
+
+        if id.starts_with("PDF/UA") && self.metadata.language.is_none() {
+            violations.push(ConformanceViolation::new(
+                MissingLanguage,
+                None,
+                None,
+                format!("{id} requires the document language (PdfMetadata::language)"),
+            ));
+        }
+
+        if is_pdf_x && !has_output_intent {
+            violations.push(ConformanceViolation::new(
+                MissingOutputIntent,
+                None,
+                None,
+                format!("{id} requires an output intent, but none is written for this conformance level"),
+            ));
+        }
+
+        if !self.resources.layers.map.is_empty() && !conformance.is_layering_allowed() {
+            violations.push(ConformanceViolation::new(
+                Layers,
+                None,
+                None,
+                format!("{id} does not allow layers (optional content groups)"),
+            ));
+        }
+
+        if !self.attachments.is_empty() && !conformance.is_embedded_files_allowed() {
+            violations.push(ConformanceViolation::new(
+                EmbeddedFiles,
+                None,
+                None,
+                format!("{id} does not allow embedded files (use PDF/A-3 for e-invoices)"),
+            ));
+        }
+
+        for (page_idx, page) in self.pages.iter().enumerate() {
+            // only the first use of every font / color space is reported per page
+            let mut builtin_fonts = BTreeSet::new();
+            let mut first_rgb = None;
+            let mut first_device_color = None;
+
+            for (op_idx, op) in page.ops.iter().enumerate() {
+                match op {
+                    Op::WriteTextBuiltinFont { font, .. } => {
+                        if !conformance.is_default_fonts_allowed() && builtin_fonts.insert(*font) {
+                            violations.push(ConformanceViolation::new(
+                                NonEmbeddedFont,
+                                Some(page_idx),
+                                Some(op_idx),
+                                format!(
+                                    "builtin font {} is not embedded, but {id} requires all fonts to be embedded",
+                                    font.get_id()
+                                ),
+                            ));
+                        }
+                    }
+                    Op::SetFillColor { col } | Op::SetOutlineColor { col } => {
+                        // palette and multi-ink colors are checked by their device color space
+                        let (is_rgb, is_device) = match col.get_device_color() {
+                            Color::Rgb(rgb) => {
+                                (rgb.icc_profile.is_none(), rgb.icc_profile.is_none())
+                            }
+                            Color::Cmyk(cmyk) => (false, cmyk.icc_profile.is_none()),
+                            Color::Greyscale(grey) => (false, grey.icc_profile.is_none()),
+                            Color::SpotColor(_) | Color::Indexed(_) | Color::DeviceN(_) => {
+                                (false, false)
+                            }
+                        };
+                        if is_rgb {
+                            first_rgb.get_or_insert(op_idx);
+                        }
+                        if is_device {
+                            first_device_color.get_or_insert(op_idx);
+                        }
+                    }
+                    Op::UseXObject { id: xobject_id, .. } if !is_custom && has_output_intent => {
+                        if let Some(XObject::Image(image)) =
+                            self.resources.xobjects.map.get(xobject_id)
+                        {
+                            if image.data_format.is_rgb() {
+                                violations.push(ConformanceViolation::new(
+                                    RgbImage,
+                                    Some(page_idx),
+                                    Some(op_idx),
+                                    format!(
+                                        "RGB image {} is used, but the output intent of {id} is CMYK",
+                                        xobject_id.0
+                                    ),
+                                ));
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            if is_custom {
+                continue;
+            }
+
+            if let (false, Some(op_idx)) = (has_output_intent, first_device_color) {
+                violations.push(ConformanceViolation::new(
+                    DeviceColorWithoutOutputIntent,
+                    Some(page_idx),
+                    Some(op_idx),
+                    format!("device-dependent colors are used, but {id} writes no output intent"),
+                ));
+            } else if let Some(op_idx) = first_rgb {
+                violations.push(ConformanceViolation::new(
+                    RgbColor,
+                    Some(page_idx),
+                    Some(op_idx),
+                    format!("DeviceRGB colors are used, but the output intent of {id} is CMYK"),
+                ));
+            }
+        }
+
+        violations
+    }
+}
+
+/// Returns a warning for every feature of the document that violates its conformance level
+/// (only checked for the predefined PDF/A, PDF/X, etc. standards, not for custom conformances)
+pub(crate) fn get_conformance_warnings(doc: &PdfDocument) -> Vec<PdfWarnMsg> {
+    let conformance = &doc.metadata.info.conformance;
+    if let PdfConformance::Custom(_) = conformance {
+        return Vec::new();
+    }
+
+    let mut warnings = doc
+        .validate(conformance)
+        .into_iter()
+        .map(|v| PdfWarnMsg::warning(v.page, v.msg).with_code(v.kind.get_warn_code()))
+        .collect::<Vec<_>>();
+
+    if doc.metadata.xmp.is_some() && !conformance.must_have_xmp_metadata() {
+        warnings.push(
+            PdfWarnMsg::info(
+                None,
+                format!(
+                    "XMP metadata is set, but ignored on save for {}",
+                    conformance.get_identifier_string()
+                ),
+            )
+            .with_code(PdfWarnCode::IgnoredXmpMetadata),
+        );
+    }
+
+    warnings
+}
+
+#[test]
+fn test_conformance_warnings() {
+    use crate::{BuiltinFont, PdfPage, Pt, Rgb};
+
+    let mut doc = PdfDocument::new("test");
+    doc.metadata.info.conformance = PdfConformance::A2B_2011_PDF_1_7;
+    doc.pages.push(PdfPage::new(
+        crate::Mm(210.0),
+        crate::Mm(297.0),
+        vec![
+            Op::SetFillColor {
+                col: Color::Rgb(Rgb::new(1.0, 0.0, 0.0, None)),
+            },
+            Op::WriteTextBuiltinFont {
+                text: "Hello".to_string(),
+                size: Pt(12.0),
+                font: BuiltinFont::Helvetica,
+            },
+        ],
+    ));
+
+    let warnings = get_conformance_warnings(&doc);
+    assert!(warnings.iter().any(|w| w.msg.contains("Helvetica")));
+    assert!(warnings.iter().any(|w| w.msg.contains("DeviceRGB")));
+    assert!(warnings.iter().any(|w| w.msg.contains("XMP")));
+    assert!(warnings
+        .iter()
+        .all(|w| w.page.is_none() || w.page == Some(0)));
+
+    doc.metadata.info.conformance = PdfConformance::default();
+    assert!(get_conformance_warnings(&doc).is_empty());
+
+    let violations = doc.validate(&PdfConformance::X1A_2001_PDF_1_3);
+    let font = violations
+        .iter()
+        .find(|v| v.kind == ConformanceViolationKind::NonEmbeddedFont)
+        .unwrap();
+    assert_eq!((font.page, font.op), (Some(0), Some(1)));
+    assert!(violations
+        .iter()
+        .any(|v| v.kind == ConformanceViolationKind::MissingOutputIntent));
+
+    let is_missing_language =
+        |v: &ConformanceViolation| v.kind == ConformanceViolationKind::MissingLanguage;
+    assert!(doc
+        .validate(&PdfConformance::UA_2014_PDF_1_6)
+        .iter()
+        .any(is_missing_language));
+    doc.metadata.language = Some("en-US".to_string());
+    assert!(!doc
+        .validate(&PdfConformance::UA_2014_PDF_1_6)
+        .iter()
+        .any(is_missing_language));
+}
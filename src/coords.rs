@@ -0,0 +1,115 @@
+//! Opt-in top-left origin coordinates: PDF coordinates start at the lower left corner
+//! of the page and grow upwards, `TopLeftOrigin` converts coordinates measured from the
+//! top left corner (like in HTML or image editors) into PDF coordinates
+
+use crate::{
+    graphics::{Point, Rect},
+    matrix::TextMatrix,
+    units::{Mm, Pt, Px},
+    PdfPage, XObjectTransform,
+};
+
+/// Converts top-left origin coordinates (`y` grows downwards) of a page
+/// into PDF coordinates, see `PdfPage::top_left_origin`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TopLeftOrigin {
+    /// Left edge of the page (x of the media box)
+    pub left: Pt,
+    /// Top edge of the page (y + height of the media box)
+    pub top: Pt,
+}
+
+impl PdfPage {
+    /// Returns a converter for coordinates measured from the top left corner of the page
+    pub fn top_left_origin(&self) -> TopLeftOrigin {
+        TopLeftOrigin {
+            left: self.media_box.x,
+            top: Pt(self.media_box.y.0 + self.media_box.height.0),
+        }
+    }
+}
+
+impl TopLeftOrigin {
+    /// Converts a point (`y` measured downwards from the top edge)
+    pub fn point(&self, x: Mm, y: Mm) -> Point {
+        self.point_pt(x.into_pt(), y.into_pt())
+    }
+
+    /// Converts a point given in points
+    pub fn point_pt(&self, x: Pt, y: Pt) -> Point {
+        Point {
+            x: Pt(self.left.0 + x.0),
+            y: Pt(self.top.0 - y.0),
+        }
+    }
+
+    /// Converts a point given in pixels at the given DPI
+    pub fn point_px(&self, x: Px, y: Px, dpi: f32) -> Point {
+        self.point_pt(x.into_pt(dpi), y.into_pt(dpi))
+    }
+
+    /// Converts a rectangle given by its top left corner and its size
+    /// into a PDF rectangle (from the lower left corner)
+    pub fn rect(&self, x: Mm, y: Mm, width: Mm, height: Mm) -> Rect {
+        let (width, height) = (width.into_pt(), height.into_pt());
+        let top_left = self.point(x, y);
+        Rect {
+            x: top_left.x,
+            y: Pt(top_left.y.0 - height.0),
+            width,
+            height,
+        }
+    }
+
+    /// Text matrix that places the baseline of the text at (`x`, `y`), for `Op::SetTextMatrix`
+    pub fn text_matrix(&self, x: Mm, y: Mm) -> TextMatrix {
+        let p = self.point(x, y);
+        TextMatrix::Translate(p.x, p.y)
+    }
+
+    /// Adjusts the translation of the transform, so that the top left corner of an image
+    /// (`width` x `height` pixels) is placed at (`x`, `y`). The scale and DPI of the
+    /// transform are taken into account, a rotation is applied after the placement.
+    pub fn image_transform(
+        &self,
+        x: Mm,
+        y: Mm,
+        image_height: Px,
+        transform: XObjectTransform,
+    ) -> XObjectTransform {
+        let dpi = transform.dpi.unwrap_or(300.0);
+        let height = image_height.into_pt(dpi).0 * transform.scale_y.unwrap_or(1.0);
+        let top_left = self.point(x, y);
+        XObjectTransform {
+            translate_x: Some(top_left.x),
+            translate_y: Some(Pt(top_left.y.0 - height)),
+            ..transform
+        }
+    }
+}
+
+#[test]
+fn test_top_left_origin() {
+    let page = PdfPage::new(Mm(210.0), Mm(297.0), Vec::new());
+    let origin = page.top_left_origin();
+
+    let p = origin.point(Mm(10.0), Mm(20.0));
+    assert_eq!(p, Point::new(Mm(10.0), Mm(277.0)));
+
+    let r = origin.rect(Mm(10.0), Mm(20.0), Mm(50.0), Mm(30.0));
+    assert_eq!(r.y, Mm(247.0).into_pt());
+    assert_eq!(r.height, Mm(30.0).into_pt());
+
+    // 300 px at 300 dpi = 1 inch, scaled by 2
+    let t = origin.image_transform(
+        Mm(0.0),
+        Mm(0.0),
+        Px(300),
+        XObjectTransform {
+            scale_y: Some(2.0),
+            ..Default::default()
+        },
+    );
+    assert_eq!(t.translate_y, Some(Mm(297.0 - 2.0 * 25.4).into_pt()));
+    assert_eq!(t.scale_y, Some(2.0));
+}
@@ -1,5 +1,2189 @@
-use crate::PdfDocument;
+use std::collections::{BTreeMap, BTreeSet};
 
-pub fn parse_pdf_from_bytes(bytes: &[u8]) -> Result<PdfDocument, String> {
-    Ok(PdfDocument::new("parsed"))
+use lopdf::ObjectId;
+
+use crate::{
+    annotation::{parse_link_annotation, parse_markup_annotation},
+    color::DeviceColorSpace,
+    forms::{parse_rect, resolve},
+    graphics::{
+        BlendMode, ExtendedGraphicsState, ExtendedGraphicsStateBuilder, LineCapStyle,
+        LineJoinStyle, OverprintMode, RenderingIntent, SoftMask, SoftMaskFunction,
+    },
+    matrix::CurTransMat,
+    outline::{parse_action, parse_destination},
+    xobject::{FormType, FormXObject, GroupXObject},
+    Actions, DecodeParms, EncodedImage, ExtendedGraphicsStateId, ImageDecodeResult, ImageDecoder,
+    ImageStream, Mm, Op, OpOrigin, OpSource, PageActions, PageRotation, PdfDocument, PdfPage,
+    PdfWarnCategory, PdfWarnCode, PdfWarnMsg, PdfWarnSeverity, RawImage, RawImageData,
+    RawImageFormat, RawResourceMap, StreamFilter, XObject, XObjectId, XObjectTransform,
+    XmpMetadata,
+};
+use serde_derive::{Deserialize, Serialize};
+
+/// Options for parsing. The limits protect against malicious files (decompression bombs,
+/// deeply nested or huge documents): exceeding the page, object or image limits fails
+/// the parsing with an error, structures nested deeper than `max_depth` are cut off.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(default)]
+pub struct PdfParseOptions {
+    /// User or owner password for encrypted documents
+    /// (if `None`, the empty user password is tried)
+    pub password: Option<String>,
+    /// Maximum number of pages
+    pub max_pages: usize,
+    /// Maximum number of objects in the document (checked against the `/Size` of the
+    /// cross-reference sections before the file is loaded, and again after loading)
+    pub max_objects: usize,
+    /// Maximum width and height of images in pixels
+    pub max_image_dimensions: (usize, usize),
+    /// Maximum size of a decoded stream (including decoded image pixels) in bytes.
+    /// Object streams and cross-reference streams are decoded while the file is loaded
+    /// and are not covered by this limit, only by `max_objects`.
+    pub max_stream_size: usize,
+    /// Maximum nesting depth of outlines, form fields and name trees
+    pub max_depth: usize,
+    /// Decode the image pixels while parsing. By default the encoded streams are kept
+    /// and only decoded on first use (`RawImage::pixels`), which makes opening large
+    /// scanned documents fast. With the `rayon` feature, the images are decoded in parallel.
+    /// Images that fail to decode are kept encoded and reported as a warning.
+    pub decode_images: bool,
+    /// Record where the parsed ops come from in the source file: the operation in the
+    /// page content stream or the annotation (`PdfPage::op_sources`)
+    pub record_op_sources: bool,
+    /// Lowest severity of the warnings returned by `PdfDocument::parse_with_warnings`
+    pub min_severity: PdfWarnSeverity,
+    /// Categories of the returned warnings, all categories if empty
+    pub warn_categories: Vec<PdfWarnCategory>,
+}
+
+impl Default for PdfParseOptions {
+    fn default() -> Self {
+        Self {
+            password: None,
+            max_pages: 100_000,
+            max_objects: 10_000_000,
+            max_image_dimensions: (30_000, 30_000),
+            max_stream_size: 512 * 1024 * 1024,
+            max_depth: 64,
+            decode_images: false,
+            record_op_sources: false,
+            min_severity: PdfWarnSeverity::Info,
+            warn_categories: Vec::new(),
+        }
+    }
+}
+
+impl PdfParseOptions {
+    /// Returns whether the message passes the `min_severity` and `warn_categories` filters
+    pub fn is_warning_enabled(&self, warning: &PdfWarnMsg) -> bool {
+        warning.severity >= self.min_severity
+            && (self.warn_categories.is_empty() || self.warn_categories.contains(&warning.category))
+    }
+}
+
+pub fn parse_pdf_from_bytes(
+    bytes: &[u8],
+    opts: &PdfParseOptions,
+    warnings: &mut Vec<PdfWarnMsg>,
+) -> Result<PdfDocument, String> {
+    parse_pdf_with_image_decoder(bytes, opts, None, warnings)
+}
+
+/// Parses the document, `decoder` is called for every image XObject. Only the warnings
+/// enabled by the options are added to `warnings`.
+pub(crate) fn parse_pdf_with_image_decoder(
+    bytes: &[u8],
+    opts: &PdfParseOptions,
+    decoder: Option<&mut dyn ImageDecoder>,
+    warnings: &mut Vec<PdfWarnMsg>,
+) -> Result<PdfDocument, String> {
+    let _span = trace_span!("parse_pdf", bytes = bytes.len());
+    // the object table is allocated while loading, so check its declared size first
+    if let Some(size) = get_declared_object_count(bytes).filter(|s| *s > opts.max_objects) {
+        return Err(format!(
+            "PDF declares {size} objects, the limit is {}",
+            opts.max_objects
+        ));
+    }
+    let mut doc =
+        lopdf::Document::load_mem(bytes).map_err(|e| format!("failed to parse PDF: {e}"))?;
+
+    // strings and streams have to be decrypted before anything else is parsed
+    if doc.trailer.get(b"Encrypt").is_ok() {
+        trace_debug!("decrypting document");
+        let password = opts.password.as_deref().unwrap_or_default();
+        crate::encryption::decrypt_document(&mut doc, password)
+            .map_err(|e| format!("failed to decrypt PDF: {e}"))?;
+    }
+
+    if doc.objects.len() > opts.max_objects {
+        return Err(format!(
+            "PDF has {} objects, the limit is {}",
+            doc.objects.len(),
+            opts.max_objects
+        ));
+    }
+    let page_ids = collect_page_refs(&doc, opts.max_depth);
+    if page_ids.len() > opts.max_pages {
+        return Err(format!(
+            "PDF has {} pages, the limit is {}",
+            page_ids.len(),
+            opts.max_pages
+        ));
+    }
+    let page_numbers = page_ids
+        .iter()
+        .enumerate()
+        .map(|(i, id)| (*id, i + 1))
+        .collect();
+
+    let mut pdf = PdfDocument::new("parsed");
+    pdf.resources.forms = crate::forms::parse_acroform(&doc, opts.max_depth);
+    pdf.outline = crate::outline::parse_outline(&doc, opts.max_depth);
+    pdf.named_destinations = crate::outline::parse_named_destinations(&doc, opts.max_depth);
+
+    let mut parse_warnings = Vec::new();
+    pdf.metadata.xmp = parse_xmp_metadata(&doc, opts, &mut parse_warnings);
+    let mut names = std::iter::repeat_with(PageResourceNames::default)
+        .take(page_ids.len())
+        .collect::<Vec<_>>();
+    pdf.resources.xobjects.map = parse_xobjects(
+        &doc,
+        &page_ids,
+        opts,
+        decoder,
+        &mut names,
+        &mut parse_warnings,
+    )?;
+    if opts.decode_images {
+        decode_images(&mut pdf.resources.xobjects.map, &mut parse_warnings);
+    }
+    pdf.resources.raw.map = parse_raw_resources(&doc, &page_ids, opts, &mut names);
+    pdf.resources.extgstates.map = parse_extgstates(
+        &doc,
+        &page_ids,
+        opts,
+        &mut names,
+        &mut pdf.resources.xobjects.map,
+    );
+
+    pdf.pages = parse_pages(&doc, &page_ids, &page_numbers, opts.record_op_sources);
+    let contents = parse_page_contents(
+        &doc,
+        &page_ids,
+        &names,
+        &pdf.resources.xobjects.map,
+        opts,
+        &mut parse_warnings,
+    );
+    // the content is drawn below the annotations
+    for (page, (ops, sources)) in pdf.pages.iter_mut().zip(contents) {
+        for source in page.op_sources.iter_mut() {
+            source.op_index += ops.len();
+        }
+        page.op_sources.splice(0..0, sources);
+        page.ops.splice(0..0, ops);
+    }
+    pdf.open_action = doc
+        .catalog()
+        .ok()
+        .and_then(|c| c.get(b"OpenAction").ok())
+        .and_then(|a| match resolve(&doc, a) {
+            // an explicit destination instead of an action dictionary
+            lopdf::Object::Array(_) => parse_destination(&doc, a, &page_numbers).map(Actions::GoTo),
+            _ => parse_action(&doc, a, &page_numbers),
+        });
+    warnings.extend(
+        parse_warnings
+            .into_iter()
+            .filter(|w| opts.is_warning_enabled(w)),
+    );
+    Ok(pdf)
+}
+
+/// Parses the XMP packet of the `/Metadata` stream of the catalog
+fn parse_xmp_metadata(
+    doc: &lopdf::Document,
+    opts: &PdfParseOptions,
+    warnings: &mut Vec<PdfWarnMsg>,
+) -> Option<XmpMetadata> {
+    let metadata = doc.catalog().ok()?.get(b"Metadata").ok()?;
+    let stream = resolve(doc, metadata).as_stream().ok()?;
+    let filters = parse_filters(doc, &stream.dict)?;
+    let xml =
+        crate::filters::decode_filters_limited(&stream.content, &filters, opts.max_stream_size)
+            .ok()
+            .filter(|(_, remaining)| remaining.is_empty())
+            .map(|(bytes, _)| String::from_utf8_lossy(&bytes).to_string())?;
+    XmpMetadata::parse(&xml)
+        .map_err(|e| {
+            warnings.push(PdfWarnMsg::warning(
+                None,
+                format!("XMP metadata not parsed: {e}"),
+            ))
+        })
+        .ok()
+}
+
+/// Object IDs of the pages in document order. The page tree is walked with a visited set,
+/// so `/Kids` pointing back to an ancestor (reference cycles) or listing a node twice are
+/// ignored, branches nested deeper than `max_depth` levels are cut off.
+pub(crate) fn collect_page_refs(doc: &lopdf::Document, max_depth: usize) -> Vec<ObjectId> {
+    let mut pages = Vec::new();
+    let Some(root) = doc
+        .catalog()
+        .ok()
+        .and_then(|c| c.get(b"Pages").ok())
+        .and_then(|p| p.as_reference().ok())
+    else {
+        return pages;
+    };
+    let mut visited = BTreeSet::new();
+    collect_page_tree(doc, root, &mut visited, max_depth, &mut pages);
+    pages
+}
+
+fn collect_page_tree(
+    doc: &lopdf::Document,
+    node_id: ObjectId,
+    visited: &mut BTreeSet<ObjectId>,
+    max_depth: usize,
+    pages: &mut Vec<ObjectId>,
+) {
+    let Some(max_depth) = max_depth.checked_sub(1) else {
+        return;
+    };
+    if !visited.insert(node_id) {
+        return;
+    }
+    let Ok(node) = doc.get_dictionary(node_id) else {
+        return;
+    };
+
+    let kids = node
+        .get(b"Kids")
+        .ok()
+        .and_then(|k| resolve(doc, k).as_array().ok());
+    let is_page = node.get(b"Type").and_then(|t| t.as_name()).ok() == Some(&b"Page"[..]);
+    match kids {
+        Some(kids) if !is_page => {
+            for kid in kids.iter().filter_map(|k| k.as_reference().ok()) {
+                collect_page_tree(doc, kid, visited, max_depth, pages);
+            }
+        }
+        // pages without a /Type are accepted as long as they have no /Kids
+        _ => pages.push(node_id),
+    }
+}
+
+/// Returns an inheritable page attribute (`/MediaBox`, `/CropBox`, `/Rotate` or
+/// `/Resources`) of the page or of the nearest ancestor in the page tree that has it
+pub(crate) fn get_inherited<'a>(
+    doc: &'a lopdf::Document,
+    page: &'a lopdf::Dictionary,
+    key: &[u8],
+) -> Option<&'a lopdf::Object> {
+    let mut node = page;
+    let mut visited = BTreeSet::new();
+    loop {
+        if let Ok(value) = node.get(key) {
+            return Some(value);
+        }
+        let parent = node.get(b"Parent").ok()?.as_reference().ok()?;
+        if !visited.insert(parent) {
+            return None;
+        }
+        node = doc.get_dictionary(parent).ok()?;
+    }
+}
+
+/// Parses the page boxes, page actions, links and markup annotations
+/// (the page contents are parsed separately, see `parse_page_contents`)
+fn parse_pages(
+    doc: &lopdf::Document,
+    page_ids: &[ObjectId],
+    page_numbers: &BTreeMap<ObjectId, usize>,
+    record_op_sources: bool,
+) -> Vec<PdfPage> {
+    page_ids
+        .iter()
+        .map(|&page_id| {
+            let _span = trace_span!("parse_page", object = page_id.0);
+            let mut page = PdfPage::new(Mm(210.0), Mm(297.0), Vec::new());
+            let Ok(dict) = doc.get_dictionary(page_id) else {
+                return page;
+            };
+
+            let get_box = |key: &[u8]| dict.get(key).ok().and_then(|b| parse_rect(doc, b));
+            // the media box, crop box and rotation can be inherited from the page tree
+            let get_inherited_box =
+                |key: &[u8]| get_inherited(doc, dict, key).and_then(|b| parse_rect(doc, b));
+            if let Some(media_box) = get_inherited_box(b"MediaBox") {
+                page.media_box = media_box;
+            }
+            page.crop_box = get_inherited_box(b"CropBox").unwrap_or_else(|| page.media_box.clone());
+            page.trim_box = get_box(b"TrimBox").unwrap_or_else(|| page.crop_box.clone());
+            page.bleed_box = get_box(b"BleedBox");
+            page.art_box = get_box(b"ArtBox");
+            if let Some(degrees) =
+                get_inherited(doc, dict, b"Rotate").and_then(|r| resolve(doc, r).as_i64().ok())
+            {
+                page.rotation = PageRotation::from_degrees(degrees);
+            }
+            if let Some(user_unit) = dict
+                .get(b"UserUnit")
+                .ok()
+                .and_then(|u| resolve(doc, u).as_float().ok())
+                .filter(|u| u.is_finite() && *u > 0.0)
+            {
+                page.user_unit = user_unit;
+            }
+
+            if let Some(aa) = dict
+                .get(b"AA")
+                .ok()
+                .and_then(|aa| resolve(doc, aa).as_dict().ok())
+            {
+                let get_action = |key: &[u8]| {
+                    aa.get(key)
+                        .ok()
+                        .and_then(|a| parse_action(doc, a, page_numbers))
+                };
+                page.actions = PageActions {
+                    open: get_action(b"O"),
+                    close: get_action(b"C"),
+                };
+            }
+
+            let annots = dict
+                .get(b"Annots")
+                .ok()
+                .and_then(|a| resolve(doc, a).as_array().ok())
+                .map(|a| a.as_slice())
+                .unwrap_or_default();
+            for (annot_index, annot) in annots.iter().enumerate() {
+                let Ok(dict) = resolve(doc, annot).as_dict() else {
+                    continue;
+                };
+                if let Some(link) = parse_link_annotation(doc, dict, page_numbers) {
+                    page.ops.push(Op::LinkAnnotation { link });
+                } else if let Some(annotation) = parse_markup_annotation(doc, dict) {
+                    page.ops.push(Op::AddAnnotation {
+                        annotation: Box::new(annotation),
+                    });
+                } else {
+                    continue;
+                }
+                if record_op_sources {
+                    let object_id = annot.as_reference().ok();
+                    page.op_sources.push(OpSource {
+                        op_index: page.ops.len() - 1,
+                        origin: OpOrigin::Annotation {
+                            annot_index,
+                            object_id,
+                            byte_offset: object_id.and_then(|id| get_byte_offset(doc, id)),
+                        },
+                    });
+                }
+            }
+            page
+        })
+        .collect()
+}
+
+/// Largest `/Size` of the trailers and cross-reference streams, without loading the file
+fn get_declared_object_count(bytes: &[u8]) -> Option<usize> {
+    bytes
+        .windows(5)
+        .enumerate()
+        .filter(|(_, w)| *w == b"/Size")
+        .filter_map(|(i, _)| {
+            let rest = &bytes[i + 5..];
+            let start = rest.iter().position(|b| !b.is_ascii_whitespace())?;
+            let len = rest[start..]
+                .iter()
+                .take_while(|b| b.is_ascii_digit())
+                .count();
+            std::str::from_utf8(&rest[start..start + len])
+                .ok()?
+                .parse()
+                .ok()
+        })
+        .max()
+}
+
+/// Offset of an object in the source file, from the cross-reference table
+fn get_byte_offset(doc: &lopdf::Document, id: ObjectId) -> Option<usize> {
+    match doc.reference_table.get(id.0)? {
+        lopdf::xref::XrefEntry::Normal { offset, generation } if *generation == id.1 => {
+            Some(*offset as usize)
+        }
+        _ => None,
+    }
+}
+
+/// Names of the resources of one page to their IDs in the document. Names are only
+/// unique within a page, the content streams are parsed with these names.
+#[derive(Debug, Default)]
+struct PageResourceNames {
+    xobjects: BTreeMap<Vec<u8>, XObjectId>,
+    extgstates: BTreeMap<Vec<u8>, ExtendedGraphicsStateId>,
+    /// Resource type and name to the name in the `RawResourceMap`
+    raw: BTreeMap<(&'static str, Vec<u8>), String>,
+}
+
+/// Collects the images and form XObjects of the page resources. The encoded stream and
+/// filter chain of images are kept as-is, so re-saving the document doesn't decode or
+/// re-encode them (the pixels are decoded lazily, see `RawImage::pixels`). The `decoder`
+/// can replace or skip each image, unsupported images are skipped with a warning.
+fn parse_xobjects(
+    doc: &lopdf::Document,
+    page_ids: &[ObjectId],
+    opts: &PdfParseOptions,
+    mut decoder: Option<&mut dyn ImageDecoder>,
+    names: &mut [PageResourceNames],
+    warnings: &mut Vec<PdfWarnMsg>,
+) -> Result<BTreeMap<XObjectId, XObject>, String> {
+    let mut xobjects = BTreeMap::new();
+    // XObjects used on several pages are parsed (or reported) once, `None` if skipped
+    let mut seen = BTreeMap::<ObjectId, Option<XObjectId>>::new();
+    for (page, page_id) in page_ids.iter().enumerate() {
+        let Some(xobject_dict) = doc
+            .get_dictionary(*page_id)
+            .ok()
+            .and_then(|p| get_inherited(doc, p, b"Resources"))
+            .and_then(|r| resolve(doc, r).as_dict().ok())
+            .and_then(|r| r.get(b"XObject").ok())
+            .and_then(|x| resolve(doc, x).as_dict().ok())
+        else {
+            continue;
+        };
+
+        for (name, obj) in xobject_dict.iter() {
+            let object_id = obj.as_reference().ok();
+            if let Some(parsed) = object_id.and_then(|r| seen.get(&r)) {
+                if let Some(id) = parsed {
+                    names[page].xobjects.insert(name.clone(), id.clone());
+                }
+                continue;
+            }
+            let id = XObjectId(get_unique_name(name, |n| {
+                xobjects.contains_key(&XObjectId(n.to_string()))
+            }));
+            let xobject = parse_xobject(doc, obj, &id, opts, &mut decoder, page, warnings)?;
+            if let Some(object_id) = object_id {
+                seen.insert(object_id, xobject.as_ref().map(|_| id.clone()));
+            }
+            if let Some(xobject) = xobject {
+                names[page].xobjects.insert(name.clone(), id.clone());
+                xobjects.insert(id, xobject);
+            }
+        }
+    }
+    Ok(xobjects)
+}
+
+/// Collects the resources that are not parsed (see `RawResourceMap`). Resources that are
+/// used on several pages are kept once, different resources with the same name are renamed
+/// like the XObjects (see `get_unique_name`).
+fn parse_raw_resources(
+    doc: &lopdf::Document,
+    page_ids: &[ObjectId],
+    opts: &PdfParseOptions,
+    names: &mut [PageResourceNames],
+) -> BTreeMap<(String, String), lopdf::Object> {
+    let mut resources = BTreeMap::new();
+    // resources are inlined once per object
+    let mut seen = BTreeMap::<(&str, ObjectId), String>::new();
+    for (page, page_id) in page_ids.iter().enumerate() {
+        let Some(page_resources) = doc
+            .get_dictionary(*page_id)
+            .ok()
+            .and_then(|p| get_inherited(doc, p, b"Resources"))
+            .and_then(|r| resolve(doc, r).as_dict().ok())
+        else {
+            continue;
+        };
+
+        for resource_type in RawResourceMap::TYPES {
+            let Some(dict) = page_resources
+                .get(resource_type.as_bytes())
+                .ok()
+                .and_then(|d| resolve(doc, d).as_dict().ok())
+            else {
+                continue;
+            };
+            for (name, obj) in dict.iter() {
+                let object_id = obj.as_reference().ok();
+                let seen_name = object_id.and_then(|r| seen.get(&(resource_type, r)));
+                let unique = match seen_name {
+                    Some(unique) => unique.clone(),
+                    None => {
+                        let resource = inline_object(doc, obj, opts.max_depth);
+                        // equal resources (i.e. direct color space arrays) are shared
+                        let unique = get_unique_name(name, |n| {
+                            let key = (resource_type.to_string(), n.to_string());
+                            resources.get(&key).is_some_and(|r| *r != resource)
+                        });
+                        resources
+                            .entry((resource_type.to_string(), unique.clone()))
+                            .or_insert(resource);
+                        if let Some(object_id) = object_id {
+                            seen.insert((resource_type, object_id), unique.clone());
+                        }
+                        unique
+                    }
+                };
+                names[page]
+                    .raw
+                    .insert((resource_type, name.clone()), unique);
+            }
+        }
+    }
+    resources
+}
+
+/// Parses an image or form XObject of the page resources, `None` if it is skipped
+fn parse_xobject(
+    doc: &lopdf::Document,
+    obj: &lopdf::Object,
+    id: &XObjectId,
+    opts: &PdfParseOptions,
+    decoder: &mut Option<&mut dyn ImageDecoder>,
+    page: usize,
+    warnings: &mut Vec<PdfWarnMsg>,
+) -> Result<Option<XObject>, String> {
+    let Ok(stream) = resolve(doc, obj).as_stream() else {
+        return Ok(None);
+    };
+    if !is_image_stream(doc, stream) {
+        let form = parse_form_xobject(doc, stream, opts);
+        if form.is_none() {
+            trace_debug!(xobject = %id.0, "skipped XObject (unsupported)");
+        }
+        return Ok(form.map(|f| XObject::Form(Box::new(f))));
+    }
+
+    let mut image = parse_encoded_image(doc, stream, opts.max_stream_size);
+    if let Some(decoder) = decoder.as_mut() {
+        let image_stream = get_image_stream(doc, stream, image.as_ref());
+        image = match decoder.decode(id, &image_stream) {
+            ImageDecodeResult::Default => image,
+            ImageDecodeResult::Image(decoded) => Some(decoded),
+            ImageDecodeResult::Skip => return Ok(None),
+        };
+    }
+    let Some(image) = image else {
+        warnings.push(
+            PdfWarnMsg::info(
+                Some(page),
+                format!("image /{} is not supported by the parser, skipped", id.0),
+            )
+            .with_code(PdfWarnCode::UnsupportedImage)
+            .with_resource(&id.0),
+        );
+        return Ok(None);
+    };
+    check_image_limits(id, &image, opts)?;
+    trace_debug!(
+        xobject = %id.0,
+        width = image.width,
+        height = image.height,
+        "parsed image"
+    );
+    Ok(Some(XObject::Image(image)))
+}
+
+/// Document-wide ID for the resource `name` of a page: the name itself, or the name with
+/// a number if it is already used by another resource (i.e. `Im0_2` for the `/Im0` of
+/// the second page of a scan)
+fn get_unique_name(name: &[u8], is_taken: impl Fn(&str) -> bool) -> String {
+    let name = String::from_utf8_lossy(name).to_string();
+    if !is_taken(&name) {
+        return name;
+    }
+    (2..)
+        .map(|i| format!("{name}_{i}"))
+        .find(|n| !is_taken(n))
+        .unwrap_or(name)
+}
+
+/// Decodes the pixels of all parsed images (in parallel with the `rayon` feature). Images
+/// that fail to decode keep their encoded stream, with a warning.
+fn decode_images(xobjects: &mut BTreeMap<XObjectId, XObject>, warnings: &mut Vec<PdfWarnMsg>) {
+    let decode = |(id, xobject): (&XObjectId, &mut XObject)| -> Option<PdfWarnMsg> {
+        let XObject::Image(image) = xobject else {
+            return None;
+        };
+        let source = image.source.as_ref().filter(|_| image.pixels.is_empty())?;
+        match source.decode_pixels(image.width, image.height) {
+            Ok(pixels) => {
+                image.pixels = pixels;
+                None
+            }
+            Err(e) => Some(
+                PdfWarnMsg::warning(None, format!("image /{} not decoded: {e}", id.0))
+                    .with_code(PdfWarnCode::UnsupportedImage)
+                    .with_resource(&id.0),
+            ),
+        }
+    };
+
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+        let failed = xobjects
+            .par_iter_mut()
+            .filter_map(decode)
+            .collect::<Vec<_>>();
+        warnings.extend(failed);
+    }
+    #[cfg(not(feature = "rayon"))]
+    {
+        warnings.extend(xobjects.iter_mut().filter_map(decode));
+    }
+}
+
+/// Collects the graphics states of the page resources. The transparency groups of soft
+/// masks are added to the XObjects.
+fn parse_extgstates(
+    doc: &lopdf::Document,
+    page_ids: &[ObjectId],
+    opts: &PdfParseOptions,
+    names: &mut [PageResourceNames],
+    xobjects: &mut BTreeMap<XObjectId, XObject>,
+) -> BTreeMap<ExtendedGraphicsStateId, ExtendedGraphicsState> {
+    let mut extgstates = BTreeMap::new();
+    // graphics states used on several pages are parsed once
+    let mut seen = BTreeMap::<ObjectId, ExtendedGraphicsStateId>::new();
+    for (page, page_id) in page_ids.iter().enumerate() {
+        let Some(gs_dict) = doc
+            .get_dictionary(*page_id)
+            .ok()
+            .and_then(|p| get_inherited(doc, p, b"Resources"))
+            .and_then(|r| resolve(doc, r).as_dict().ok())
+            .and_then(|r| r.get(b"ExtGState").ok())
+            .and_then(|x| resolve(doc, x).as_dict().ok())
+        else {
+            continue;
+        };
+
+        for (name, obj) in gs_dict.iter() {
+            let object_id = obj.as_reference().ok();
+            if let Some(id) = object_id.and_then(|r| seen.get(&r)) {
+                names[page].extgstates.insert(name.clone(), id.clone());
+                continue;
+            }
+            let Ok(dict) = resolve(doc, obj).as_dict() else {
+                continue;
+            };
+            let id = ExtendedGraphicsStateId(get_unique_name(name, |n| {
+                extgstates.contains_key(&ExtendedGraphicsStateId(n.to_string()))
+            }));
+            if let Some(object_id) = object_id {
+                seen.insert(object_id, id.clone());
+            }
+            names[page].extgstates.insert(name.clone(), id.clone());
+            extgstates.insert(id, parse_extgstate(doc, dict, opts, xobjects));
+        }
+    }
+    extgstates
+}
+
+/// Parses the content streams of the pages into ops (and their sources with
+/// `PdfParseOptions::record_op_sources`). Pages whose content can't be decoded or uses
+/// XObjects or graphics states that were skipped are left empty with a warning, so that
+/// the saved document doesn't reference missing resources.
+fn parse_page_contents(
+    doc: &lopdf::Document,
+    page_ids: &[ObjectId],
+    names: &[PageResourceNames],
+    xobjects: &BTreeMap<XObjectId, XObject>,
+    opts: &PdfParseOptions,
+    warnings: &mut Vec<PdfWarnMsg>,
+) -> Vec<(Vec<Op>, Vec<OpSource>)> {
+    // the streams are decoded one after another, the operators of the pages are parsed
+    // in parallel with the `rayon` feature
+    let contents = page_ids
+        .iter()
+        .map(|page_id| get_page_content(doc, *page_id, opts.max_stream_size))
+        .collect::<Vec<_>>();
+    let parse = |(content, page_names): (Result<Vec<u8>, String>, &PageResourceNames)| {
+        let content = content?;
+        let ops = parse_content_ops(&content, page_names, xobjects)?;
+        let sources = match opts.record_op_sources {
+            true => get_content_sources(&content, ops.len()),
+            false => Vec::new(),
+        };
+        Ok::<_, String>((ops, sources))
+    };
+    #[cfg(feature = "rayon")]
+    let pages = {
+        use rayon::prelude::*;
+        contents
+            .into_par_iter()
+            .zip(names.par_iter())
+            .map(parse)
+            .collect::<Vec<_>>()
+    };
+    #[cfg(not(feature = "rayon"))]
+    let pages = contents
+        .into_iter()
+        .zip(names)
+        .map(parse)
+        .collect::<Vec<_>>();
+
+    pages
+        .into_iter()
+        .enumerate()
+        .map(|(page, ops)| {
+            ops.unwrap_or_else(|e| {
+                warnings.push(
+                    PdfWarnMsg::warning(Some(page), format!("page content not parsed: {e}"))
+                        .with_code(PdfWarnCode::UnsupportedPageContent),
+                );
+                (Vec::new(), Vec::new())
+            })
+        })
+        .collect()
+}
+
+/// Decodes and concatenates the content streams of the page (`/Contents` is a stream or
+/// an array of streams)
+fn get_page_content(
+    doc: &lopdf::Document,
+    page_id: ObjectId,
+    max_size: usize,
+) -> Result<Vec<u8>, String> {
+    let contents = doc
+        .get_dictionary(page_id)
+        .ok()
+        .and_then(|p| p.get(b"Contents").ok())
+        .map(|c| resolve(doc, c));
+    let streams = match contents {
+        Some(lopdf::Object::Array(a)) => a.iter().map(|o| resolve(doc, o)).collect(),
+        Some(o) => vec![o],
+        None => Vec::new(),
+    };
+    let mut content = Vec::new();
+    for stream in streams {
+        let stream = stream
+            .as_stream()
+            .map_err(|_| "/Contents is not a stream".to_string())?;
+        let filters =
+            parse_filters(doc, &stream.dict).ok_or_else(|| "unsupported filter".to_string())?;
+        let max_size = max_size.saturating_sub(content.len());
+        let (bytes, remaining) =
+            crate::filters::decode_filters_limited(&stream.content, &filters, max_size)?;
+        if let Some(filter) = remaining.first() {
+            return Err(format!("unsupported filter /{}", filter.name));
+        }
+        content.extend(bytes);
+        // the streams are concatenated as if they were one stream
+        content.push(b'\n');
+    }
+    Ok(content)
+}
+
+/// Sources of the ops of a content stream (one op per operation), see `OpOrigin::Content`
+fn get_content_sources(content: &[u8], num_ops: usize) -> Vec<OpSource> {
+    // the byte ranges are only used if they were found for every operation of lopdf
+    let ranges = get_operation_ranges(content);
+    let ranges_match = ranges.len() == num_ops;
+    (0..num_ops)
+        .map(|i| OpSource {
+            op_index: i,
+            origin: OpOrigin::Content {
+                operator_index: i,
+                byte_range: ranges.get(i).filter(|_| ranges_match).cloned(),
+            },
+        })
+        .collect()
+}
+
+/// Byte ranges (operands and operator) of the operations of a decoded content stream.
+/// Inline images (`BI ... ID ... EI`) are one operation.
+fn get_operation_ranges(content: &[u8]) -> Vec<std::ops::Range<usize>> {
+    let is_white = |c: u8| matches!(c, b' ' | b'\t' | b'\r' | b'\n' | b'\x0c' | b'\0');
+    let is_regular = |c: u8| !is_white(c) && !b"()<>[]{}/%".contains(&c);
+
+    let mut ranges = Vec::new();
+    // start of the operands of the current operation
+    let mut operands_start = None;
+    // nesting of arrays and dictionaries
+    let mut depth = 0_usize;
+    let mut i = 0;
+    while let Some(&c) = content.get(i) {
+        let start = i;
+        match c {
+            c if is_white(c) => {
+                i += 1;
+                continue;
+            }
+            b'%' => {
+                while content.get(i).is_some_and(|c| !matches!(c, b'\r' | b'\n')) {
+                    i += 1;
+                }
+                continue;
+            }
+            b'(' => {
+                let mut nesting = 0;
+                while let Some(&c) = content.get(i) {
+                    i += 1;
+                    match c {
+                        b'\\' => i += 1,
+                        b'(' => nesting += 1,
+                        b')' => {
+                            nesting -= 1;
+                            if nesting == 0 {
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            b'<' if content.get(i + 1) == Some(&b'<') => {
+                depth += 1;
+                i += 2;
+            }
+            b'>' if content.get(i + 1) == Some(&b'>') => {
+                depth = depth.saturating_sub(1);
+                i += 2;
+            }
+            b'<' => {
+                i = content[i..]
+                    .iter()
+                    .position(|c| *c == b'>')
+                    .map_or(content.len(), |p| i + p + 1);
+            }
+            b'[' => {
+                depth += 1;
+                i += 1;
+            }
+            b']' => {
+                depth = depth.saturating_sub(1);
+                i += 1;
+            }
+            b'/' => {
+                i += 1;
+                while content.get(i).is_some_and(|c| is_regular(*c)) {
+                    i += 1;
+                }
+            }
+            c if !is_regular(c) => i += 1,
+            _ => {
+                while content.get(i).is_some_and(|c| is_regular(*c)) {
+                    i += 1;
+                }
+                let token = &content[start..i];
+                let is_operand = depth > 0
+                    || matches!(token, b"true" | b"false" | b"null")
+                    || matches!(token[0], b'0'..=b'9' | b'+' | b'-' | b'.');
+                if !is_operand {
+                    if token == b"BI" {
+                        i = get_inline_image_end(content, i);
+                    }
+                    ranges.push(operands_start.take().unwrap_or(start)..i);
+                    continue;
+                }
+            }
+        }
+        operands_start.get_or_insert(start);
+    }
+    ranges
+}
+
+/// End of the `EI` of an inline image, `start` is the position after the `BI`
+fn get_inline_image_end(content: &[u8], start: usize) -> usize {
+    let is_white = |c: &u8| matches!(c, b' ' | b'\t' | b'\r' | b'\n' | b'\x0c' | b'\0');
+    let Some(data) = content[start..]
+        .windows(2)
+        .position(|w| w == b"ID")
+        .map(|p| start + p + 2)
+    else {
+        return content.len();
+    };
+    // the image data is binary, "EI" has to be surrounded by whitespace
+    (data..content.len().saturating_sub(1))
+        .find(|&j| {
+            &content[j..j + 2] == b"EI"
+                && content.get(j - 1).is_some_and(is_white)
+                && content.get(j + 2).map_or(true, is_white)
+        })
+        .map_or(content.len(), |j| j + 2)
+}
+
+/// Converts the operations of a content stream, see `parse_content_op`
+fn parse_content_ops(
+    content: &[u8],
+    names: &PageResourceNames,
+    xobjects: &BTreeMap<XObjectId, XObject>,
+) -> Result<Vec<Op>, String> {
+    if content.iter().all(u8::is_ascii_whitespace) {
+        return Ok(Vec::new());
+    }
+    let content = lopdf::content::Content::decode(content)
+        .map_err(|e| format!("invalid content stream: {e}"))?;
+    content
+        .operations
+        .into_iter()
+        .map(|op| parse_content_op(op.operator, op.operands, names, xobjects))
+        .collect()
+}
+
+/// Converts an operation of a content stream. The graphics state, transformation and
+/// XObject operators become ops with the IDs of the resources, all other operators are
+/// kept as `Op::Unknown` (with the names of the `RawResourceMap` for operators that use
+/// fonts, color spaces, patterns, shadings or marked content properties). Fails for
+/// XObjects and graphics states that were skipped.
+fn parse_content_op(
+    operator: String,
+    operands: Vec<lopdf::Object>,
+    names: &PageResourceNames,
+    xobjects: &BTreeMap<XObjectId, XObject>,
+) -> Result<Op, String> {
+    let get_name = |i: usize| match operands.get(i) {
+        Some(lopdf::Object::Name(n)) => Some(n.as_slice()),
+        _ => None,
+    };
+    let name_str = |i: usize| String::from_utf8_lossy(get_name(i).unwrap_or_default()).to_string();
+
+    match operator.as_str() {
+        "q" => return Ok(Op::SaveGraphicsState),
+        "Q" => return Ok(Op::RestoreGraphicsState),
+        "cm" => {
+            let numbers = operands
+                .iter()
+                .map(|o| o.as_float().ok())
+                .collect::<Option<Vec<_>>>();
+            if let Some(matrix) = numbers.and_then(|n| <[f32; 6]>::try_from(n).ok()) {
+                return Ok(Op::SetTransformationMatrix {
+                    matrix: CurTransMat::Raw(matrix),
+                });
+            }
+        }
+        "gs" => {
+            return match get_name(0).and_then(|n| names.extgstates.get(n)) {
+                Some(id) => Ok(Op::LoadGraphicsState { gs: id.clone() }),
+                None => Err(format!("graphics state /{} is not parsed", name_str(0))),
+            };
+        }
+        "Do" => {
+            let id = get_name(0).and_then(|n| names.xobjects.get(n));
+            return match id.and_then(|id| Some((id, xobjects.get(id)?))) {
+                Some((id, xobject)) => Ok(Op::UseXObject {
+                    id: id.clone(),
+                    transform: get_do_transform(xobject),
+                }),
+                None => Err(format!("XObject /{} is not parsed", name_str(0))),
+            };
+        }
+        _ => {}
+    }
+
+    let mut operands = operands;
+    if let Some((resource_type, index)) = RawResourceMap::get_operand(&operator, &operands) {
+        if let Some(lopdf::Object::Name(name)) = operands.get_mut(index) {
+            // names that are missing in the page resources are kept as they are
+            if let Some(unique) = names.raw.get(&(resource_type, name.clone())) {
+                *name = unique.as_bytes().to_vec();
+            }
+        }
+    }
+    Ok(Op::Unknown {
+        key: operator,
+        value: operands,
+    })
+}
+
+/// Transform for `Op::UseXObject` that draws the XObject like a plain `Do`. Images are
+/// scaled to their size in pixels (at 72 DPI), which is undone here.
+fn get_do_transform(xobject: &XObject) -> XObjectTransform {
+    match xobject.get_width_height() {
+        Some((w, h)) if w.0 > 0 && h.0 > 0 => XObjectTransform {
+            dpi: Some(72.0),
+            scale_x: Some(1.0 / w.0 as f32),
+            scale_y: Some(1.0 / h.0 as f32),
+            ..Default::default()
+        },
+        _ => XObjectTransform::default(),
+    }
+}
+
+/// Parses an `/ExtGState` dictionary. Functions (transfer, black generation, undercolor
+/// removal), halftones, fonts and dash patterns are not parsed.
+fn parse_extgstate(
+    doc: &lopdf::Document,
+    dict: &lopdf::Dictionary,
+    opts: &PdfParseOptions,
+    xobjects: &mut BTreeMap<XObjectId, XObject>,
+) -> ExtendedGraphicsState {
+    use lopdf::Object::*;
+
+    let get = |key: &[u8]| dict.get(key).ok().map(|o| resolve(doc, o));
+    let get_f32 = |key: &[u8]| match get(key)? {
+        Integer(i) => Some(*i as f32),
+        Real(r) => Some(*r),
+        _ => None,
+    };
+    let get_int = |key: &[u8]| get(key).and_then(|o| o.as_i64().ok());
+    let get_bool = |key: &[u8]| get(key).and_then(|o| o.as_bool().ok());
+    let get_name = |key: &[u8]| get(key).and_then(|o| o.as_name_str().ok());
+
+    let mut gs = ExtendedGraphicsStateBuilder::new();
+    if let Some(lw) = get_f32(b"LW") {
+        gs = gs.with_line_width(lw);
+    }
+    let line_cap = match get_int(b"LC") {
+        Some(0) => Some(LineCapStyle::Butt),
+        Some(1) => Some(LineCapStyle::Round),
+        Some(2) => Some(LineCapStyle::ProjectingSquare),
+        _ => None,
+    };
+    if let Some(lc) = line_cap {
+        gs = gs.with_line_cap(lc);
+    }
+    let line_join = match get_int(b"LJ") {
+        Some(0) => Some(LineJoinStyle::Miter),
+        Some(1) => Some(LineJoinStyle::Round),
+        Some(2) => Some(LineJoinStyle::Limit),
+        _ => None,
+    };
+    if let Some(lj) = line_join {
+        gs = gs.with_line_join(lj);
+    }
+    if let Some(ml) = get_f32(b"ML") {
+        gs = gs.with_miter_limit(ml);
+    }
+    let rendering_intent = match get_name(b"RI") {
+        Some("AbsoluteColorimetric") => Some(RenderingIntent::AbsoluteColorimetric),
+        Some("RelativeColorimetric") => Some(RenderingIntent::RelativeColorimetric),
+        Some("Saturation") => Some(RenderingIntent::Saturation),
+        Some("Perceptual") => Some(RenderingIntent::Perceptual),
+        _ => None,
+    };
+    if let Some(ri) = rendering_intent {
+        gs = gs.with_rendering_intent(ri);
+    }
+    // "OP" sets both overprint parameters, unless "op" is present
+    if let Some(op) = get_bool(b"OP") {
+        gs = gs.with_overprint(op);
+    }
+    if let Some(op) = get_bool(b"op") {
+        gs = gs.with_overprint_fill(op);
+    }
+    match get_int(b"OPM") {
+        Some(0) => gs = gs.with_overprint_mode(OverprintMode::EraseUnderlying),
+        Some(1) => gs = gs.with_overprint_mode(OverprintMode::KeepUnderlying),
+        _ => {}
+    }
+    if let Some(fl) = get_f32(b"FL") {
+        gs = gs.with_flatness_tolerance(fl);
+    }
+    if let Some(sm) = get_f32(b"SM") {
+        gs = gs.with_smoothness_tolerance(sm);
+    }
+    if let Some(sa) = get_bool(b"SA") {
+        gs = gs.with_stroke_adjustment(sa);
+    }
+    // the blend mode can be an array of modes, the first supported one is used
+    let blend_mode = match get(b"BM") {
+        Some(Array(modes)) => modes
+            .iter()
+            .filter_map(|m| resolve(doc, m).as_name_str().ok())
+            .find_map(BlendMode::from_id),
+        Some(mode) => mode.as_name_str().ok().and_then(BlendMode::from_id),
+        None => None,
+    };
+    if let Some(bm) = blend_mode {
+        gs = gs.with_blend_mode(bm);
+    }
+    match dict.get(b"SMask").ok() {
+        Some(Name(n)) if n == b"None" => gs = gs.with_soft_mask(None),
+        Some(sm) => {
+            if let Some(mask) = parse_soft_mask(doc, sm, opts, xobjects) {
+                gs = gs.with_soft_mask(Some(mask));
+            }
+        }
+        None => {}
+    }
+    if let Some(ca) = get_f32(b"CA") {
+        gs = gs.with_current_stroke_alpha(ca);
+    }
+    if let Some(ca) = get_f32(b"ca") {
+        gs = gs.with_current_fill_alpha(ca);
+    }
+    if let Some(ais) = get_bool(b"AIS") {
+        gs = gs.with_alpha_is_shape(ais);
+    }
+    if let Some(tk) = get_bool(b"TK") {
+        gs = gs.with_text_knockout(tk);
+    }
+    gs.build()
+}
+
+/// Parses a soft mask dictionary, the transparency group is added to the XObjects
+fn parse_soft_mask(
+    doc: &lopdf::Document,
+    obj: &lopdf::Object,
+    opts: &PdfParseOptions,
+    xobjects: &mut BTreeMap<XObjectId, XObject>,
+) -> Option<SoftMask> {
+    let dict = resolve(doc, obj).as_dict().ok()?;
+    let function = dict
+        .get(b"S")
+        .ok()
+        .and_then(|s| resolve(doc, s).as_name_str().ok())
+        .and_then(SoftMaskFunction::from_id)?;
+    let group_obj = dict.get(b"G").ok()?;
+    let form = parse_form_xobject(doc, resolve(doc, group_obj).as_stream().ok()?, opts)?;
+
+    // groups shared by several masks are only added once
+    let group = match group_obj {
+        lopdf::Object::Reference(r) => XObjectId(format!("SMaskGroup{}_{}", r.0, r.1)),
+        _ => XObjectId::new(),
+    };
+    xobjects
+        .entry(group.clone())
+        .or_insert_with(|| XObject::Form(Box::new(form)));
+
+    let backdrop = dict
+        .get(b"BC")
+        .ok()
+        .and_then(|bc| crate::forms::parse_numbers(doc, bc));
+    Some(SoftMask {
+        function,
+        group,
+        backdrop,
+    })
+}
+
+/// Parses a form XObject: the content is decoded, the resources are inlined, so that
+/// they can be written into the new document
+fn parse_form_xobject(
+    doc: &lopdf::Document,
+    stream: &lopdf::Stream,
+    opts: &PdfParseOptions,
+) -> Option<FormXObject> {
+    let dict = &stream.dict;
+    let get = |key: &[u8]| dict.get(key).ok().map(|o| resolve(doc, o));
+    if get(b"Subtype").and_then(|s| s.as_name_str().ok()) != Some("Form") {
+        return None;
+    }
+
+    let filters = parse_filters(doc, dict)?;
+    let (bytes, remaining) =
+        crate::filters::decode_filters_limited(&stream.content, &filters, opts.max_stream_size)
+            .ok()?;
+    if !remaining.is_empty() {
+        return None;
+    }
+
+    let matrix = get(b"Matrix")
+        .and_then(|m| crate::forms::parse_numbers(doc, m))
+        .and_then(|m| <[f32; 6]>::try_from(m).ok())
+        .map(CurTransMat::Raw);
+    let resources = match get(b"Resources").map(|r| inline_object(doc, r, opts.max_depth)) {
+        Some(lopdf::Object::Dictionary(d)) => Some(d),
+        _ => None,
+    };
+    let group = get(b"Group")
+        .and_then(|g| g.as_dict().ok())
+        .filter(|g| g.get(b"S").and_then(|s| s.as_name_str()).ok() == Some("Transparency"))
+        .map(|g| {
+            let flag = |key: &[u8]| g.get(key).and_then(|v| v.as_bool()).unwrap_or(false);
+            GroupXObject {
+                isolated: flag(b"I"),
+                knockout: flag(b"K"),
+                color_space: g
+                    .get(b"CS")
+                    .and_then(|cs| cs.as_name_str())
+                    .ok()
+                    .and_then(DeviceColorSpace::from_name),
+                ..GroupXObject::transparency()
+            }
+        });
+
+    Some(FormXObject {
+        form_type: FormType::Type1,
+        size: None,
+        bytes,
+        bbox: get(b"BBox").and_then(|b| parse_rect(doc, b)),
+        matrix,
+        resources,
+        group,
+        ref_dict: None,
+        metadata: None,
+        piece_info: None,
+        last_modified: None,
+        struct_parent: None,
+        struct_parents: None,
+        opi: None,
+        oc: None,
+        name: None,
+    })
+}
+
+/// Resolves all references nested in the object, so that it doesn't depend on the parsed
+/// document anymore (streams stay nested, see `xobject::add_nested_streams`). References
+/// nested deeper than `depth` levels (i.e. cycles) are replaced with `null`.
+fn inline_object(doc: &lopdf::Document, obj: &lopdf::Object, depth: usize) -> lopdf::Object {
+    use lopdf::Object::*;
+
+    let inline_dict = |dict: &lopdf::Dictionary| {
+        let mut out = lopdf::Dictionary::new();
+        for (k, v) in dict.iter() {
+            out.set(k.clone(), inline_object(doc, v, depth - 1));
+        }
+        out
+    };
+    if depth == 0 {
+        return Null;
+    }
+    match resolve(doc, obj) {
+        Dictionary(dict) => Dictionary(inline_dict(dict)),
+        Stream(stream) => {
+            let mut stream = stream.clone();
+            stream.dict = inline_dict(&stream.dict);
+            Stream(stream)
+        }
+        Array(a) => Array(a.iter().map(|o| inline_object(doc, o, depth - 1)).collect()),
+        // dangling reference
+        Reference(_) => Null,
+        other => other.clone(),
+    }
+}
+
+/// Checks the dimensions and the decoded size of an image before it is decoded lazily
+fn check_image_limits(
+    id: &XObjectId,
+    image: &RawImage,
+    opts: &PdfParseOptions,
+) -> Result<(), String> {
+    let (max_width, max_height) = opts.max_image_dimensions;
+    if image.width > max_width || image.height > max_height {
+        return Err(format!(
+            "image /{} is {}x{} pixels, the limit is {max_width}x{max_height}",
+            id.0, image.width, image.height
+        ));
+    }
+    let bytes_per_pixel = match image.data_format {
+        RawImageFormat::R8 => 1,
+        RawImageFormat::R16 => 2,
+        RawImageFormat::RGB16 => 6,
+        _ => 3,
+    };
+    let decoded_size = image
+        .width
+        .saturating_mul(image.height)
+        .saturating_mul(bytes_per_pixel);
+    if decoded_size > opts.max_stream_size {
+        return Err(format!(
+            "image /{} decodes to {decoded_size} bytes, the limit is {}",
+            id.0, opts.max_stream_size
+        ));
+    }
+    Ok(())
+}
+
+fn is_image_stream(doc: &lopdf::Document, stream: &lopdf::Stream) -> bool {
+    stream
+        .dict
+        .get(b"Subtype")
+        .ok()
+        .and_then(|s| resolve(doc, s).as_name_str().ok())
+        == Some("Image")
+}
+
+/// Describes the raw image XObject `stream` for an `ImageDecoder`
+fn get_image_stream<'a>(
+    doc: &lopdf::Document,
+    stream: &'a lopdf::Stream,
+    image: Option<&'a RawImage>,
+) -> ImageStream<'a> {
+    let dict = &stream.dict;
+    let get = |key: &[u8]| dict.get(key).ok().map(|o| resolve(doc, o));
+    let get_int = |key: &[u8]| get(key).and_then(|o| o.as_i64().ok());
+    let filters = match get(b"Filter") {
+        Some(lopdf::Object::Array(a)) => a.iter().map(|o| resolve(doc, o)).collect(),
+        Some(o) => vec![o],
+        None => Vec::new(),
+    };
+    let color_space = get(b"ColorSpace").and_then(|c| match c {
+        lopdf::Object::Array(a) => a.first().map(|o| resolve(doc, o)),
+        o => Some(o),
+    });
+    ImageStream {
+        width: get_int(b"Width").unwrap_or(0).max(0) as usize,
+        height: get_int(b"Height").unwrap_or(0).max(0) as usize,
+        bits_per_component: get_int(b"BitsPerComponent").and_then(|b| u8::try_from(b).ok()),
+        color_space: color_space
+            .and_then(|c| c.as_name_str().ok())
+            .map(|c| c.to_string()),
+        filters: filters
+            .iter()
+            .filter_map(|f| f.as_name_str().ok())
+            .map(|f| f.to_string())
+            .collect(),
+        bytes: &stream.content,
+        image,
+    }
+}
+
+fn parse_encoded_image(
+    doc: &lopdf::Document,
+    stream: &lopdf::Stream,
+    max_stream_size: usize,
+) -> Option<RawImage> {
+    let dict = &stream.dict;
+    let get = |key: &[u8]| dict.get(key).ok().map(|o| resolve(doc, o));
+    let get_int = |key: &[u8]| get(key).and_then(|o| o.as_i64().ok());
+
+    if get(b"Subtype").and_then(|s| s.as_name_str().ok()) != Some("Image") {
+        return None;
+    }
+    // stencil masks and soft masks reference other objects, they are not passed through
+    let is_mask = matches!(get(b"ImageMask"), Some(lopdf::Object::Boolean(true)));
+    if is_mask || dict.has(b"SMask") || dict.has(b"Mask") {
+        return None;
+    }
+
+    let filters = parse_filters(doc, dict)?;
+    let (color_space, icc_profile) =
+        parse_image_color_space(doc, get(b"ColorSpace")?, max_stream_size)?;
+    let decode = get(b"Decode").and_then(|d| crate::forms::parse_numbers(doc, d));
+    let bits_per_component = get_int(b"BitsPerComponent")
+        .or_else(|| {
+            // JPEG 2000 images don't need /BitsPerComponent
+            filters.iter().any(|f| f.name == "JPXDecode").then_some(8)
+        })
+        .filter(|b| [1, 2, 4, 8, 16].contains(b))? as u8;
+
+    let source = EncodedImage {
+        bytes: stream.content.clone(),
+        filters,
+        color_space,
+        icc_profile,
+        bits_per_component,
+        decode,
+    };
+    let data_format = source.get_decoded_format();
+    Some(RawImage {
+        pixels: RawImageData::empty(data_format),
+        width: get_int(b"Width")? as usize,
+        height: get_int(b"Height")? as usize,
+        data_format,
+        tag: Vec::new(),
+        source: Some(source),
+    })
+}
+
+/// Parses the `/Filter` chain with the `/DecodeParms` of each filter
+fn parse_filters(doc: &lopdf::Document, dict: &lopdf::Dictionary) -> Option<Vec<StreamFilter>> {
+    let as_vec = |key: &[u8]| match dict.get(key).ok().map(|o| resolve(doc, o)) {
+        Some(lopdf::Object::Array(a)) => a.iter().map(|o| resolve(doc, o)).collect(),
+        Some(o) => vec![o],
+        None => Vec::new(),
+    };
+    let parms = as_vec(b"DecodeParms");
+
+    as_vec(b"Filter")
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let name = name.as_name_str().ok()?;
+            // JBIG2 globals and crypt filters reference other objects
+            if matches!(name, "JBIG2Decode" | "Crypt") {
+                return None;
+            }
+            Some(StreamFilter {
+                name: name.to_string(),
+                parms: parms
+                    .get(i)
+                    .and_then(|p| p.as_dict().ok())
+                    .map(DecodeParms::from_dict)
+                    .unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+/// Returns the device color space name and the ICC profile of `/ICCBased` color spaces,
+/// other color spaces (indexed, separation, ...) are not supported
+fn parse_image_color_space(
+    doc: &lopdf::Document,
+    cs: &lopdf::Object,
+    max_stream_size: usize,
+) -> Option<(String, Option<Vec<u8>>)> {
+    match cs {
+        lopdf::Object::Name(n) => match n.as_slice() {
+            b"DeviceGray" | b"DeviceRGB" | b"DeviceCMYK" => {
+                Some((String::from_utf8_lossy(n).to_string(), None))
+            }
+            _ => None,
+        },
+        lopdf::Object::Array(a) if a.len() == 2 && a[0].as_name_str().ok() == Some("ICCBased") => {
+            let icc = resolve(doc, &a[1]).as_stream().ok()?;
+            let name = match icc.dict.get(b"N").and_then(|n| n.as_i64()).ok()? {
+                1 => "DeviceGray",
+                3 => "DeviceRGB",
+                4 => "DeviceCMYK",
+                _ => return None,
+            };
+            let filters = parse_filters(doc, &icc.dict)?;
+            let (profile, remaining) =
+                crate::filters::decode_filters_limited(&icc.content, &filters, max_stream_size)
+                    .ok()?;
+            if !remaining.is_empty() {
+                return None;
+            }
+            Some((name.to_string(), Some(profile)))
+        }
+        _ => None,
+    }
+}
+
+#[test]
+fn test_parse_open_action_and_page_actions() {
+    use crate::{Destination, PdfSaveOptions};
+
+    let mut doc = PdfDocument::new("actions");
+    doc.pages
+        .push(PdfPage::new(Mm(210.0), Mm(297.0), Vec::new()));
+    doc.pages.push(
+        PdfPage::new(Mm(100.0), Mm(200.0), Vec::new()).with_actions(PageActions {
+            open: Some(Actions::named("Print")),
+            close: Some(Actions::uri("https://example.com".to_string())),
+        }),
+    );
+    doc.open_action = Some(Actions::go_to(Destination::Fit { page: 2 }));
+
+    let bytes = doc.save(&PdfSaveOptions::default());
+    let parsed = PdfDocument::parse(&bytes, &Default::default()).unwrap();
+    assert_eq!(parsed.open_action, doc.open_action);
+    assert_eq!(parsed.pages.len(), 2);
+    assert_eq!(parsed.pages[1].actions, doc.pages[1].actions);
+    assert!(parsed.pages[0].actions.is_empty());
+    // page boxes are written as integers
+    assert_eq!(parsed.pages[1].media_box.height.0, 567.0);
+}
+
+#[test]
+fn test_parse_link_annotations() {
+    use crate::{ColorArray, Destination, LinkAnnotation, PdfSaveOptions, Pt, Rect};
+
+    let rect = |y: f32| Rect {
+        x: Pt(50.0),
+        y: Pt(y),
+        width: Pt(100.0),
+        height: Pt(20.0),
+    };
+    let web = LinkAnnotation::new(
+        rect(700.0),
+        Actions::uri("https://example.com".to_string()),
+        None,
+        Some(ColorArray::Transparent),
+        None,
+    );
+    let internal = LinkAnnotation::new(
+        rect(650.0),
+        Actions::go_to(Destination::Fit { page: 2 }),
+        None,
+        None,
+        None,
+    );
+
+    let mut doc = PdfDocument::new("links");
+    doc.pages.push(PdfPage::new(
+        Mm(210.0),
+        Mm(297.0),
+        vec![
+            Op::LinkAnnotation { link: web.clone() },
+            Op::LinkAnnotation {
+                link: internal.clone(),
+            },
+        ],
+    ));
+    doc.pages
+        .push(PdfPage::new(Mm(210.0), Mm(297.0), Vec::new()));
+
+    let bytes = doc.save(&PdfSaveOptions::default());
+    let parsed = PdfDocument::parse(&bytes, &Default::default()).unwrap();
+    assert_eq!(parsed.pages[0].ops, doc.pages[0].ops);
+
+    // links survive a second round-trip
+    let bytes = parsed.save(&PdfSaveOptions::default());
+    let reparsed = PdfDocument::parse(&bytes, &Default::default()).unwrap();
+    assert_eq!(reparsed.pages[0].ops, doc.pages[0].ops);
+}
+
+#[test]
+fn test_parse_limits() {
+    use crate::{OutlineNode, PdfSaveOptions};
+
+    let mut doc = PdfDocument::new("limits");
+    for _ in 0..3 {
+        doc.pages
+            .push(PdfPage::new(Mm(210.0), Mm(297.0), Vec::new()));
+    }
+    doc.add_outline(
+        OutlineNode::page("1", 1)
+            .with_child(OutlineNode::page("1.1", 2).with_child(OutlineNode::page("1.1.1", 3))),
+    );
+    let bytes = doc.save(&PdfSaveOptions::default());
+
+    let opts = PdfParseOptions {
+        max_pages: 2,
+        ..Default::default()
+    };
+    assert!(PdfDocument::parse(&bytes, &opts).is_err());
+
+    let opts = PdfParseOptions {
+        max_objects: 3,
+        ..Default::default()
+    };
+    assert!(PdfDocument::parse(&bytes, &opts).is_err());
+
+    // a huge declared object count fails before the file is loaded
+    let size = get_declared_object_count(&bytes).unwrap();
+    assert!(size > 3 && size < 100);
+    let huge = String::from_utf8_lossy(&bytes)
+        .replace(&format!("/Size {size}"), "/Size 99999999")
+        .into_bytes();
+    let err = PdfDocument::parse(&huge, &PdfParseOptions::default()).unwrap_err();
+    assert!(err.contains("declares 99999999 objects"));
+
+    // outlines nested too deeply are cut off instead of failing
+    let opts = PdfParseOptions {
+        max_depth: 2,
+        ..Default::default()
+    };
+    let parsed = PdfDocument::parse(&bytes, &opts).unwrap();
+    assert_eq!(parsed.pages.len(), 3);
+    assert_eq!(parsed.outline[0].children.len(), 1);
+    assert!(parsed.outline[0].children[0].children.is_empty());
+}
+
+#[test]
+fn test_page_tree_cycles() {
+    use lopdf::{Dictionary as LoDictionary, Object::*};
+
+    let mut doc = lopdf::Document::with_version("1.7");
+    let root = doc.new_object_id();
+    let branch = doc.new_object_id();
+    let pages = [doc.new_object_id(), doc.new_object_id()];
+    let catalog = doc.new_object_id();
+
+    let node = |kind: &str, kids: Vec<ObjectId>| {
+        let mut dict = LoDictionary::new();
+        dict.set("Type", Name(kind.as_bytes().to_vec()));
+        if !kids.is_empty() {
+            dict.set("Kids", Array(kids.into_iter().map(Reference).collect()));
+        }
+        Dictionary(dict)
+    };
+    // the branch points back to the root and lists the first page twice
+    doc.objects
+        .insert(root, node("Pages", vec![pages[0], branch]));
+    doc.objects
+        .insert(branch, node("Pages", vec![root, pages[0], pages[1]]));
+    doc.objects.insert(pages[0], node("Page", Vec::new()));
+    doc.objects.insert(pages[1], node("Page", Vec::new()));
+    let mut catalog_dict = LoDictionary::new();
+    catalog_dict.set("Type", Name(b"Catalog".to_vec()));
+    catalog_dict.set("Pages", Reference(root));
+    doc.objects.insert(catalog, Dictionary(catalog_dict));
+    doc.trailer.set("Root", Reference(catalog));
+
+    assert_eq!(collect_page_refs(&doc, 64), pages.to_vec());
+    // the second page is nested too deeply
+    assert_eq!(collect_page_refs(&doc, 2), vec![pages[0]]);
+}
+
+#[test]
+fn test_inherited_page_attributes() {
+    use crate::{PdfSaveOptions, Pt, Rect};
+    use lopdf::{Dictionary as LoDictionary, Object::*};
+
+    let mut doc = lopdf::Document::with_version("1.7");
+    let root = doc.new_object_id();
+    let page = doc.new_object_id();
+    let mut root_dict = LoDictionary::new();
+    root_dict.set("Type", Name(b"Pages".to_vec()));
+    root_dict.set("Kids", Array(vec![Reference(page)]));
+    root_dict.set(
+        "MediaBox",
+        Array(vec![0.into(), 0.into(), 1000.into(), 500.into()]),
+    );
+    root_dict.set("Rotate", Integer(90));
+    doc.objects.insert(root, Dictionary(root_dict));
+    let mut page_dict = LoDictionary::new();
+    page_dict.set("Type", Name(b"Page".to_vec()));
+    page_dict.set("Parent", Reference(root));
+    page_dict.set("UserUnit", Real(10.0));
+    doc.objects.insert(page, Dictionary(page_dict));
+
+    let pages = parse_pages(&doc, &[page], &BTreeMap::new(), false);
+    assert_eq!(pages[0].media_box, Rect::from_wh(Pt(1000.0), Pt(500.0)));
+    assert_eq!(pages[0].crop_box, pages[0].media_box);
+    assert_eq!(pages[0].rotation, PageRotation::Clockwise90);
+    assert_eq!(pages[0].user_unit, 10.0);
+
+    // the user unit survives a round trip
+    let mut pdf = PdfDocument::new("drawing");
+    pdf.pages.push(pages[0].clone());
+    let bytes = pdf.save(&PdfSaveOptions::default());
+    let parsed = PdfDocument::parse(&bytes, &Default::default()).unwrap();
+    assert_eq!(parsed.pages[0].user_unit, 10.0);
+    assert_eq!(parsed.pages[0].media_box, pages[0].media_box);
+}
+
+#[test]
+fn test_parse_with_image_decoder() {
+    use crate::PdfSaveOptions;
+
+    struct Decoder {
+        calls: Vec<(XObjectId, usize, bool)>,
+    }
+
+    impl ImageDecoder for Decoder {
+        fn decode(&mut self, id: &XObjectId, stream: &ImageStream) -> ImageDecodeResult {
+            self.calls
+                .push((id.clone(), stream.width, stream.image.is_some()));
+            ImageDecodeResult::Image(RawImage {
+                pixels: RawImageData::U8(vec![0, 0, 255]),
+                width: 1,
+                height: 1,
+                data_format: RawImageFormat::RGB8,
+                tag: Vec::new(),
+                source: None,
+            })
+        }
+    }
+
+    let mut doc = PdfDocument::new("images");
+    doc.pages
+        .push(PdfPage::new(Mm(210.0), Mm(297.0), Vec::new()));
+    let id = doc.add_image(&RawImage {
+        pixels: RawImageData::U8(vec![255, 0, 0, 0, 0, 255]),
+        width: 2,
+        height: 1,
+        data_format: RawImageFormat::RGB8,
+        tag: Vec::new(),
+        source: None,
+    });
+    let bytes = doc.save(&PdfSaveOptions::default());
+
+    let mut decoder = Decoder { calls: Vec::new() };
+    let parsed =
+        PdfDocument::parse_with_image_decoder(&bytes, &PdfParseOptions::default(), &mut decoder)
+            .unwrap();
+    assert_eq!(decoder.calls, vec![(id.clone(), 2, true)]);
+    let XObject::Image(image) = &parsed.resources.xobjects.map[&id] else {
+        panic!("expected an image");
+    };
+    assert_eq!(image.width, 1);
+    assert_eq!(image.source, None);
+}
+
+#[test]
+fn test_parse_soft_mask() {
+    use crate::{PdfSaveOptions, Pt, Rect};
+
+    let mut doc = PdfDocument::new("smask");
+    doc.pages
+        .push(PdfPage::new(Mm(210.0), Mm(297.0), Vec::new()));
+    let mut resources = lopdf::Dictionary::new();
+    resources.set("ExtGState", lopdf::Dictionary::new());
+    let group = XObjectId::new();
+    let form = FormXObject {
+        form_type: FormType::Type1,
+        size: None,
+        bytes: b"0.5 g 0 0 100 100 re f".to_vec(),
+        bbox: Some(Rect::from_wh(Pt(100.0), Pt(100.0))),
+        matrix: None,
+        resources: Some(resources),
+        group: Some(GroupXObject {
+            isolated: true,
+            ..GroupXObject::transparency()
+        }),
+        ref_dict: None,
+        metadata: None,
+        piece_info: None,
+        last_modified: None,
+        struct_parent: None,
+        struct_parents: None,
+        opi: None,
+        oc: None,
+        name: None,
+    };
+    doc.resources
+        .xobjects
+        .map
+        .insert(group.clone(), XObject::Form(Box::new(form.clone())));
+    let mask = SoftMask::new(SoftMaskFunction::GroupLuminosity, group).with_backdrop(vec![0.0]);
+    let gs = ExtendedGraphicsStateBuilder::new()
+        .with_soft_mask(Some(mask))
+        .with_blend_mode(BlendMode::multiply())
+        .with_current_fill_alpha(0.5)
+        .build();
+    let gs_id = doc.add_graphics_state(gs);
+    let bytes = doc.save(&PdfSaveOptions::default());
+
+    let parsed = PdfDocument::parse(&bytes, &PdfParseOptions::default()).unwrap();
+    let gs = &parsed.resources.extgstates.map[&gs_id];
+    assert_eq!(gs.blend_mode, BlendMode::multiply());
+    assert_eq!(gs.current_fill_alpha, 0.5);
+    let mask = gs.soft_mask.as_ref().unwrap();
+    assert_eq!(mask.function, SoftMaskFunction::GroupLuminosity);
+    assert_eq!(mask.backdrop, Some(vec![0.0]));
+    let Some(XObject::Form(parsed_form)) = parsed.resources.xobjects.map.get(&mask.group) else {
+        panic!("expected the mask group");
+    };
+    assert_eq!(parsed_form.bytes, form.bytes);
+    assert_eq!(parsed_form.bbox, form.bbox);
+    assert_eq!(parsed_form.group, form.group);
+
+    // the parsed state is written again
+    let resaved = String::from_utf8_lossy(&parsed.save(&PdfSaveOptions::default())).to_string();
+    assert!(resaved.contains("/Luminosity"));
+}
+
+#[test]
+fn test_parse_decode_images() {
+    use crate::PdfSaveOptions;
+
+    let mut doc = PdfDocument::new("images");
+    doc.pages
+        .push(PdfPage::new(Mm(210.0), Mm(297.0), Vec::new()));
+    let pixels = RawImageData::U8(vec![255, 0, 0, 0, 0, 255]);
+    let id = doc.add_image(&RawImage {
+        pixels: pixels.clone(),
+        width: 2,
+        height: 1,
+        data_format: RawImageFormat::RGB8,
+        tag: Vec::new(),
+        source: None,
+    });
+    let bytes = doc.save(&PdfSaveOptions::default());
+
+    let get_image = |opts: &PdfParseOptions| {
+        let parsed = PdfDocument::parse(&bytes, opts).unwrap();
+        match parsed.resources.xobjects.map.get(&id) {
+            Some(XObject::Image(image)) => image.clone(),
+            _ => panic!("expected an image"),
+        }
+    };
+    // lazy by default: the pixels are decoded on first use
+    let lazy = get_image(&PdfParseOptions::default());
+    assert!(lazy.pixels.is_empty());
+    assert_eq!(lazy.pixels().unwrap().as_ref(), &pixels);
+
+    let eager = get_image(&PdfParseOptions {
+        decode_images: true,
+        ..Default::default()
+    });
+    assert_eq!(eager.pixels, pixels);
+    assert!(eager.source.is_some());
+
+    // a corrupt image doesn't fail the parse
+    let corrupt = doc.add_image(&RawImage {
+        pixels: RawImageData::empty(RawImageFormat::RGB8),
+        width: 2,
+        height: 1,
+        data_format: RawImageFormat::RGB8,
+        tag: Vec::new(),
+        source: Some(EncodedImage {
+            bytes: b"not deflated".to_vec(),
+            filters: vec![StreamFilter::new("FlateDecode")],
+            color_space: "DeviceRGB".to_string(),
+            icc_profile: None,
+            bits_per_component: 8,
+            decode: None,
+        }),
+    });
+    let bytes = doc.save(&PdfSaveOptions::default());
+    let mut warnings = Vec::new();
+    let opts = PdfParseOptions {
+        decode_images: true,
+        ..Default::default()
+    };
+    let parsed = PdfDocument::parse_with_warnings(&bytes, &opts, &mut warnings).unwrap();
+    let Some(XObject::Image(image)) = parsed.resources.xobjects.map.get(&corrupt) else {
+        panic!("expected an image");
+    };
+    assert!(image.pixels.is_empty());
+    let [warning] = warnings.as_slice() else {
+        panic!("expected one warning: {warnings:?}");
+    };
+    assert_eq!(warning.code, PdfWarnCode::UnsupportedImage);
+}
+
+#[test]
+fn test_parse_op_sources() {
+    use crate::{LinkAnnotation, PdfSaveOptions, Pt, Rect};
+
+    let link = LinkAnnotation::new(
+        Rect::from_wh(Pt(100.0), Pt(20.0)),
+        Actions::uri("https://example.com".to_string()),
+        None,
+        None,
+        None,
+    );
+    let mut doc = PdfDocument::new("sources");
+    doc.pages.push(PdfPage::new(
+        Mm(210.0),
+        Mm(297.0),
+        vec![
+            Op::SaveGraphicsState,
+            Op::RestoreGraphicsState,
+            Op::LinkAnnotation { link },
+        ],
+    ));
+    let bytes = doc.save(&PdfSaveOptions::default());
+
+    let parsed = PdfDocument::parse(&bytes, &Default::default()).unwrap();
+    assert!(parsed.pages[0].op_sources.is_empty());
+
+    let opts = PdfParseOptions {
+        record_op_sources: true,
+        ..Default::default()
+    };
+    let parsed = PdfDocument::parse(&bytes, &opts).unwrap();
+    let page = &parsed.pages[0];
+    let [content @ .., annotation] = page.op_sources.as_slice() else {
+        panic!("expected op sources");
+    };
+    // the content ops are followed by the link
+    assert_eq!(annotation.op_index, page.ops.len() - 1);
+    let OpOrigin::Annotation {
+        annot_index,
+        object_id,
+        byte_offset,
+    } = &annotation.origin
+    else {
+        panic!("expected an annotation source");
+    };
+    assert_eq!(*annot_index, 0);
+    let (num, generation) = object_id.unwrap();
+    let header = format!("{num} {generation} obj");
+    assert!(bytes[byte_offset.unwrap()..].starts_with(header.as_bytes()));
+    assert!(content.len() >= 2);
+    assert_eq!(content.len(), page.ops.len() - 1);
+    for (i, source) in content.iter().enumerate() {
+        assert_eq!(source.op_index, i);
+        assert!(matches!(
+            source.origin,
+            OpOrigin::Content { operator_index, byte_range: Some(_) } if operator_index == i
+        ));
+    }
+}
+
+#[test]
+fn test_get_operation_ranges() {
+    let content = b"q 1 0 0 1 (a) Td % comment Tj\n[(b\\) c) -20 (d)] TJ\n\
+        /P <</MCID 0>> BDC BI /W 1 /H 1 ID \x00EI\x01 EI EMC Q";
+    let ranges = get_operation_ranges(content)
+        .into_iter()
+        .map(|r| String::from_utf8_lossy(&content[r]).to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        ranges,
+        vec![
+            "q",
+            "1 0 0 1 (a) Td",
+            "[(b\\) c) -20 (d)] TJ",
+            "/P <</MCID 0>> BDC",
+            "BI /W 1 /H 1 ID \x00EI\x01 EI",
+            "EMC",
+            "Q",
+        ]
+    );
+}
+
+#[test]
+fn test_parse_warnings() {
+    use crate::PdfSaveOptions;
+
+    let mut doc = PdfDocument::new("warnings");
+    // images with an alpha channel are written with a soft mask, which isn't parsed
+    let id = doc.add_image(&RawImage {
+        pixels: RawImageData::U8(vec![255, 0, 0, 128, 0, 0, 255, 255]),
+        width: 2,
+        height: 1,
+        data_format: RawImageFormat::RGBA8,
+        tag: Vec::new(),
+        source: None,
+    });
+    for _ in 0..3 {
+        doc.pages
+            .push(PdfPage::new(Mm(210.0), Mm(297.0), Vec::new()));
+    }
+    let bytes = doc.save(&PdfSaveOptions::default());
+
+    let mut warnings = Vec::new();
+    let parsed =
+        PdfDocument::parse_with_warnings(&bytes, &PdfParseOptions::default(), &mut warnings)
+            .unwrap();
+    assert!(!parsed.resources.xobjects.map.contains_key(&id));
+    // reported once, not for every page
+    let [warning] = warnings.as_slice() else {
+        panic!("expected one warning: {warnings:?}");
+    };
+    assert_eq!(warning.code, PdfWarnCode::UnsupportedImage);
+    assert_eq!(warning.category, PdfWarnCategory::Images);
+    assert_eq!(warning.resource.as_deref(), Some(id.0.as_str()));
+
+    for opts in [
+        PdfParseOptions {
+            min_severity: PdfWarnSeverity::Warning,
+            ..Default::default()
+        },
+        PdfParseOptions {
+            warn_categories: vec![PdfWarnCategory::Colors],
+            ..Default::default()
+        },
+    ] {
+        let mut warnings = Vec::new();
+        PdfDocument::parse_with_warnings(&bytes, &opts, &mut warnings).unwrap();
+        assert!(warnings.is_empty());
+    }
+}
+
+#[test]
+fn test_parse_page_content() {
+    use crate::{DrawEvent, OpInterpreter, PdfSaveOptions};
+
+    // a scanned page (one image over the whole page) and a page with text
+    let mut doc = PdfDocument::new("scan");
+    let id = doc.add_image(&RawImage {
+        pixels: RawImageData::U8(vec![255, 0, 0, 0, 0, 255]),
+        width: 2,
+        height: 1,
+        data_format: RawImageFormat::RGB8,
+        tag: Vec::new(),
+        source: None,
+    });
+    let transform = XObjectTransform {
+        scale_x: Some(100.0),
+        scale_y: Some(50.0),
+        ..Default::default()
+    };
+    doc.pages.push(PdfPage::new(
+        Mm(210.0),
+        Mm(297.0),
+        vec![Op::UseXObject {
+            id: id.clone(),
+            transform,
+        }],
+    ));
+    let font = Op::Unknown {
+        key: "Tf".to_string(),
+        value: vec![lopdf::Object::Name(b"F1".to_vec()), 12.into()],
+    };
+    doc.pages
+        .push(PdfPage::new(Mm(210.0), Mm(297.0), vec![font]));
+    let bytes = doc.save(&PdfSaveOptions::default());
+
+    let get_image_matrices = |doc: &PdfDocument| {
+        let mut matrices = Vec::new();
+        OpInterpreter::new(doc, CurTransMat::Identity.as_array()).run(
+            &doc.pages[0].ops,
+            |_, event| {
+                if let DrawEvent::Image { matrix, .. } = event {
+                    matrices.push(matrix);
+                }
+            },
+        );
+        matrices
+    };
+    let mut warnings = Vec::new();
+    let parsed =
+        PdfDocument::parse_with_warnings(&bytes, &PdfParseOptions::default(), &mut warnings)
+            .unwrap();
+    assert!(matches!(
+        parsed.pages[0].ops.as_slice(),
+        [
+            Op::SaveGraphicsState,
+            Op::SetTransformationMatrix { .. },
+            Op::UseXObject { id: parsed_id, .. },
+            Op::RestoreGraphicsState,
+        ] if *parsed_id == id
+    ));
+
+    // the font is not parsed, the operator is kept as it is
+    assert_eq!(parsed.pages[1].ops, vec![font]);
+    assert!(warnings.is_empty(), "{warnings:?}");
+
+    // the image is drawn at the same position after a round trip
+    let bytes = parsed.save(&PdfSaveOptions::default());
+    let reparsed = PdfDocument::parse(&bytes, &Default::default()).unwrap();
+    let expected = get_image_matrices(&doc);
+    for matrices in [get_image_matrices(&parsed), get_image_matrices(&reparsed)] {
+        assert_eq!(matrices.len(), 1);
+        for (a, b) in matrices[0].iter().zip(expected[0].iter()) {
+            assert!(
+                (a - b).abs() < 0.01,
+                "{:?} != {:?}",
+                matrices[0],
+                expected[0]
+            );
+        }
+    }
+}
+
+#[test]
+fn test_resource_name_collisions() {
+    use lopdf::{Dictionary as LoDictionary, Object::*, Stream as LoStream};
+
+    // two scanned pages, both draw a different image named /Im0 and have an OCR text
+    // layer with a different font named /F1
+    let mut doc = lopdf::Document::with_version("1.7");
+    let root = doc.new_object_id();
+    let catalog = doc.new_object_id();
+    let mut kids = Vec::new();
+    for (gray, base_font) in [(0_u8, "Helvetica"), (255, "Times-Roman")] {
+        let mut image_dict = LoDictionary::new();
+        image_dict.set("Type", Name(b"XObject".to_vec()));
+        image_dict.set("Subtype", Name(b"Image".to_vec()));
+        image_dict.set("Width", Integer(1));
+        image_dict.set("Height", Integer(1));
+        image_dict.set("ColorSpace", Name(b"DeviceGray".to_vec()));
+        image_dict.set("BitsPerComponent", Integer(8));
+        let image = doc.add_object(LoStream::new(image_dict, vec![gray]));
+        let contents = doc.add_object(LoStream::new(
+            LoDictionary::new(),
+            b"q 10 0 0 10 0 0 cm /Im0 Do Q BT 3 Tr /F1 12 Tf (text) Tj ET".to_vec(),
+        ));
+
+        let mut xobject_dict = LoDictionary::new();
+        xobject_dict.set("Im0", Reference(image));
+        let mut font = LoDictionary::new();
+        font.set("Type", Name(b"Font".to_vec()));
+        font.set("Subtype", Name(b"Type1".to_vec()));
+        font.set("BaseFont", Name(base_font.as_bytes().to_vec()));
+        let mut font_dict = LoDictionary::new();
+        font_dict.set("F1", Reference(doc.add_object(Dictionary(font))));
+        let mut resources = LoDictionary::new();
+        resources.set("XObject", Dictionary(xobject_dict));
+        resources.set("Font", Dictionary(font_dict));
+        let mut page_dict = LoDictionary::new();
+        page_dict.set("Type", Name(b"Page".to_vec()));
+        page_dict.set("Parent", Reference(root));
+        page_dict.set(
+            "MediaBox",
+            Array(vec![0.into(), 0.into(), 100.into(), 100.into()]),
+        );
+        page_dict.set("Resources", Dictionary(resources));
+        page_dict.set("Contents", Reference(contents));
+        kids.push(Reference(doc.add_object(Dictionary(page_dict))));
+    }
+    let mut root_dict = LoDictionary::new();
+    root_dict.set("Type", Name(b"Pages".to_vec()));
+    root_dict.set("Count", Integer(2));
+    root_dict.set("Kids", Array(kids));
+    doc.objects.insert(root, Dictionary(root_dict));
+    let mut catalog_dict = LoDictionary::new();
+    catalog_dict.set("Type", Name(b"Catalog".to_vec()));
+    catalog_dict.set("Pages", Reference(root));
+    doc.objects.insert(catalog, Dictionary(catalog_dict));
+    doc.trailer.set("Root", Reference(catalog));
+    let mut bytes = Vec::new();
+    doc.save_to(&mut bytes).unwrap();
+
+    let parsed = PdfDocument::parse(&bytes, &Default::default()).unwrap();
+    let ids = parsed
+        .pages
+        .iter()
+        .map(|page| {
+            page.ops
+                .iter()
+                .find_map(|op| match op {
+                    Op::UseXObject { id, .. } => Some(id.0.clone()),
+                    _ => None,
+                })
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(ids, vec!["Im0".to_string(), "Im0_2".to_string()]);
+    let sources = ids
+        .iter()
+        .map(
+            |id| match &parsed.resources.xobjects.map[&XObjectId(id.clone())] {
+                XObject::Image(image) => image.source.as_ref().unwrap().bytes.clone(),
+                _ => panic!("expected an image"),
+            },
+        )
+        .collect::<Vec<_>>();
+    assert_eq!(sources, vec![vec![0], vec![255]]);
+
+    // the text layer is kept, the fonts are carried through a round trip
+    let get_fonts = |doc: &PdfDocument| {
+        let fonts = doc
+            .pages
+            .iter()
+            .map(|page| {
+                page.ops
+                    .iter()
+                    .find_map(|op| match op {
+                        Op::Unknown { key, value } if key == "Tf" => value[0].as_name_str().ok(),
+                        _ => None,
+                    })
+                    .unwrap()
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        let base_fonts = fonts
+            .iter()
+            .map(|f| {
+                let font = &doc.resources.raw.map[&("Font".to_string(), f.clone())];
+                let font = font.as_dict().unwrap();
+                font.get(b"BaseFont")
+                    .unwrap()
+                    .as_name_str()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        (fonts, base_fonts)
+    };
+    let expected = (
+        vec!["F1".to_string(), "F1_2".to_string()],
+        vec!["Helvetica".to_string(), "Times-Roman".to_string()],
+    );
+    assert_eq!(get_fonts(&parsed), expected);
+    let bytes = parsed.save(&crate::PdfSaveOptions::default());
+    let reparsed = PdfDocument::parse(&bytes, &Default::default()).unwrap();
+    assert_eq!(get_fonts(&reparsed), expected);
+}
+
+#[test]
+fn test_parse_xmp_metadata() {
+    use crate::{PdfConformance, PdfSaveOptions, XmpValue};
+
+    let pdfx = "http://ns.adobe.com/pdfx/1.3/";
+    let mut doc = PdfDocument::new("print");
+    doc.metadata.info.conformance = PdfConformance::X3_2002_PDF_1_3;
+    let mut xmp = XmpMetadata::default();
+    xmp.add_custom_property("pdfx", pdfx, "Press", XmpValue::Text("offset".to_string()));
+    doc.metadata.xmp = Some(xmp);
+    doc.pages
+        .push(PdfPage::new(Mm(210.0), Mm(297.0), Vec::new()));
+    let bytes = doc.save(&PdfSaveOptions::default());
+
+    let mut parsed = PdfDocument::parse(&bytes, &Default::default()).unwrap();
+    let xmp = parsed.metadata.xmp.as_ref().unwrap();
+    assert_eq!(
+        xmp.get_custom_property(pdfx, "Press"),
+        Some(&XmpValue::Text("offset".to_string()))
+    );
+    assert!(xmp.get_custom_property(pdfx, "GTS_PDFXVersion").is_some());
+
+    // the PDF/X version of the parsed metadata is replaced, not duplicated
+    parsed.metadata.info.conformance = PdfConformance::X3_2002_PDF_1_3;
+    let xml = parsed.metadata.xmp_metadata_string();
+    assert_eq!(xml.matches("<pdfx:GTS_PDFXVersion").count(), 1);
+    assert_eq!(xml.matches("<pdfxid:GTS_PDFXVersion").count(), 1);
 }
@@ -0,0 +1,209 @@
+//! Document part hierarchy (`/DPartRoot`, PDF/VT), groups page ranges by record
+//! (i.e. one invoice per recipient) so that print systems can split large batches
+
+use std::collections::BTreeMap;
+
+use lopdf::Object::{Array, Dictionary, Integer, Name, Reference};
+use lopdf::{Dictionary as LoDictionary, Object as LoObject};
+
+use crate::{forms::text_string, PdfWarnCode, PdfWarnMsg};
+
+/// Root of the document part hierarchy
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DocumentPartRoot {
+    /// Names of the hierarchy levels, i.e. `["Batch", "Recipient"]`
+    pub node_names: Vec<String>,
+    /// Level of the hierarchy that represents a single record (0 = top level)
+    pub record_level: Option<usize>,
+    /// Top-level document parts
+    pub parts: Vec<DocumentPart>,
+}
+
+impl DocumentPartRoot {
+    /// Creates a flat hierarchy with one document part per record,
+    /// `records` are the page ranges of each record (0-based, inclusive)
+    pub fn from_records(node_name: &str, records: &[(usize, usize)]) -> Self {
+        Self {
+            node_names: vec![node_name.to_string()],
+            record_level: Some(0),
+            parts: records
+                .iter()
+                .map(|(first, last)| DocumentPart::new(*first, *last))
+                .collect(),
+        }
+    }
+}
+
+/// A node of the document part hierarchy: either a range of pages
+/// (leaf node) or a group of child document parts
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DocumentPart {
+    /// Page range of this part (0-based, inclusive), only for leaf nodes
+    pub pages: Option<(usize, usize)>,
+    /// Child parts, only for non-leaf nodes
+    pub children: Vec<DocumentPart>,
+    /// Metadata of this part, i.e. `("CustomerId", "12345")`
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl DocumentPart {
+    /// Creates a leaf node spanning the pages `first_page..=last_page` (0-based)
+    pub fn new(first_page: usize, last_page: usize) -> Self {
+        Self {
+            pages: Some((first_page, last_page)),
+            ..Default::default()
+        }
+    }
+
+    /// Creates a node grouping the given parts
+    pub fn group(children: Vec<DocumentPart>) -> Self {
+        Self {
+            children,
+            ..Default::default()
+        }
+    }
+
+    /// Adds a metadata entry (written to the `/DPM` dictionary)
+    pub fn with_metadata(mut self, key: &str, value: &str) -> Self {
+        self.metadata.insert(key.to_string(), value.to_string());
+        self
+    }
+}
+
+/// Writes the document part hierarchy and sets the `/DPart` entry of the pages.
+/// Returns the ID of the `/DPartRoot` dictionary.
+pub(crate) fn write_document_parts(
+    root: &DocumentPartRoot,
+    page_ids: &[lopdf::ObjectId],
+    doc: &mut lopdf::Document,
+    warnings: &mut Vec<PdfWarnMsg>,
+) -> lopdf::ObjectId {
+    let root_id = doc.new_object_id();
+    let root_node_id = doc.new_object_id();
+    let root_node = DocumentPart::group(root.parts.clone());
+    write_document_part(&root_node, root_node_id, root_id, page_ids, doc, warnings);
+
+    let mut dict = LoDictionary::from_iter(vec![
+        ("Type", Name("DPartRoot".into())),
+        ("DPartRootNode", Reference(root_node_id)),
+    ]);
+    if !root.node_names.is_empty() {
+        let names = root
+            .node_names
+            .iter()
+            .map(|n| Name(n.as_bytes().to_vec()))
+            .collect();
+        dict.set("NodeNameList", Array(names));
+    }
+    if let Some(record_level) = root.record_level {
+        dict.set("RecordLevel", Integer(record_level as i64));
+    }
+    doc.objects.insert(root_id, Dictionary(dict));
+    root_id
+}
+
+fn write_document_part(
+    part: &DocumentPart,
+    self_id: lopdf::ObjectId,
+    parent: lopdf::ObjectId,
+    page_ids: &[lopdf::ObjectId],
+    doc: &mut lopdf::Document,
+    warnings: &mut Vec<PdfWarnMsg>,
+) {
+    let mut dict = LoDictionary::from_iter(vec![
+        ("Type", Name("DPart".into())),
+        ("Parent", Reference(parent)),
+    ]);
+
+    if !part.metadata.is_empty() {
+        let dpm = part
+            .metadata
+            .iter()
+            .map(|(k, v)| (k.clone(), text_string(v)))
+            .collect::<Vec<_>>();
+        dict.set("DPM", Dictionary(LoDictionary::from_iter(dpm)));
+    }
+
+    if !part.children.is_empty() {
+        let children = part
+            .children
+            .iter()
+            .map(|child| {
+                let child_id = doc.new_object_id();
+                write_document_part(child, child_id, self_id, page_ids, doc, warnings);
+                Reference(child_id)
+            })
+            .collect::<Vec<_>>();
+        // DParts is an array of arrays of document parts
+        dict.set("DParts", Array(vec![Array(children)]));
+    } else if let Some((first, last)) = part.pages {
+        match (page_ids.get(first), page_ids.get(last)) {
+            (Some(start), Some(end)) if first <= last => {
+                dict.set("Start", Reference(*start));
+                if first != last {
+                    dict.set("End", Reference(*end));
+                }
+                for page_id in &page_ids[first..=last] {
+                    if let Ok(LoObject::Dictionary(page)) = doc.get_object_mut(*page_id) {
+                        page.set("DPart", Reference(self_id));
+                    }
+                }
+            }
+            _ => warnings.push(
+                PdfWarnMsg::warning(
+                    Some(first),
+                    format!(
+                        "document part with invalid page range {}..={} (document has {} pages)",
+                        first,
+                        last,
+                        page_ids.len()
+                    ),
+                )
+                .with_code(PdfWarnCode::InvalidDocumentPart),
+            ),
+        }
+    }
+
+    doc.objects.insert(self_id, Dictionary(dict));
+}
+
+#[test]
+fn test_document_parts() {
+    let mut doc = lopdf::Document::with_version("1.3");
+    let page_ids = (0..4)
+        .map(|_| doc.add_object(LoDictionary::new()))
+        .collect::<Vec<_>>();
+
+    let root = DocumentPartRoot {
+        parts: vec![
+            DocumentPart::new(0, 1).with_metadata("CustomerId", "1"),
+            DocumentPart::new(2, 2),
+            DocumentPart::new(5, 6),
+        ],
+        ..DocumentPartRoot::from_records("Recipient", &[])
+    };
+
+    let mut warnings = Vec::new();
+    let root_id = write_document_parts(&root, &page_ids, &mut doc, &mut warnings);
+    assert_eq!(warnings.len(), 1);
+
+    let root_dict = doc.get_dictionary(root_id).unwrap();
+    assert_eq!(root_dict.get(b"RecordLevel").unwrap(), &Integer(0));
+
+    let first_part = doc
+        .get_dictionary(page_ids[1])
+        .unwrap()
+        .get(b"DPart")
+        .unwrap()
+        .as_reference()
+        .unwrap();
+    let first_part = doc.get_dictionary(first_part).unwrap();
+    assert_eq!(first_part.get(b"Start").unwrap(), &Reference(page_ids[0]));
+    assert_eq!(first_part.get(b"End").unwrap(), &Reference(page_ids[1]));
+    assert!(first_part.get(b"DPM").is_ok());
+    assert!(doc
+        .get_dictionary(page_ids[3])
+        .unwrap()
+        .get(b"DPart")
+        .is_err());
+}
@@ -0,0 +1,880 @@
+//! Password protection with the PDF standard security handler (RC4 128-bit and AES-256)
+
+use aes::cipher::{generic_array::GenericArray, BlockDecrypt, BlockEncrypt, KeyInit};
+use aes::{Aes128, Aes256};
+use lopdf::Dictionary as LoDictionary;
+use lopdf::Object::{Boolean, Dictionary, Integer, Name, Reference, Stream, String as LoString};
+use lopdf::StringFormat::Hexadecimal;
+use md5::Md5;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+/// Padding string for passwords (PDF reference, algorithm 3.2)
+const PASSWORD_PADDING: [u8; 32] = [
+    0x28, 0xBF, 0x4E, 0x5E, 0x4E, 0x75, 0x8A, 0x41, 0x64, 0x00, 0x4E, 0x56, 0xFF, 0xFA, 0x01, 0x08,
+    0x2E, 0x2E, 0x00, 0xB6, 0xD0, 0x68, 0x3E, 0x80, 0x2F, 0x0C, 0xA9, 0xFE, 0x64, 0x53, 0x69, 0x7A,
+];
+
+/// Encryption algorithm of the standard security handler
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EncryptionMethod {
+    /// RC4 with a 128-bit key (PDF 1.4, revision 3), for old viewers
+    Rc4,
+    /// AES-256 (PDF 2.0 / PDF 1.7 extension level 8, revision 6)
+    Aes256,
+}
+
+/// Operations that are allowed when the document is opened with the user password
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PdfPermissions {
+    /// Print the document (possibly in low quality, see `print_high_quality`)
+    pub print: bool,
+    /// Print the document in full quality
+    pub print_high_quality: bool,
+    /// Modify the contents of the document
+    pub modify: bool,
+    /// Copy text and graphics
+    pub copy: bool,
+    /// Add or modify annotations and fill in form fields
+    pub annotate: bool,
+    /// Fill in form fields (even if `annotate` is not set)
+    pub fill_forms: bool,
+    /// Extract text and graphics for accessibility (screen readers)
+    pub extract_for_accessibility: bool,
+    /// Insert, rotate or delete pages and create bookmarks
+    pub assemble: bool,
+}
+
+impl Default for PdfPermissions {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl PdfPermissions {
+    /// Allows every operation
+    pub fn all() -> Self {
+        Self {
+            print: true,
+            print_high_quality: true,
+            modify: true,
+            copy: true,
+            annotate: true,
+            fill_forms: true,
+            extract_for_accessibility: true,
+            assemble: true,
+        }
+    }
+
+    /// Allows nothing except for accessibility extraction
+    pub fn none() -> Self {
+        Self {
+            print: false,
+            print_high_quality: false,
+            modify: false,
+            copy: false,
+            annotate: false,
+            fill_forms: false,
+            extract_for_accessibility: true,
+            assemble: false,
+        }
+    }
+
+    /// Returns the `/P` value (PDF reference table 3.20)
+    pub fn get_flags(&self) -> i32 {
+        // bits 7, 8 and 13 - 32 are reserved and must be set
+        let mut flags: u32 = 0xFFFF_F0C0;
+        let bits = [
+            (self.print, 3),
+            (self.modify, 4),
+            (self.copy, 5),
+            (self.annotate, 6),
+            (self.fill_forms, 9),
+            (self.extract_for_accessibility, 10),
+            (self.assemble, 11),
+            (self.print_high_quality, 12),
+        ];
+        for (allowed, bit) in bits {
+            if allowed {
+                flags |= 1 << (bit - 1);
+            }
+        }
+        flags as i32
+    }
+}
+
+/// Password protection of the saved document
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PdfEncryption {
+    /// Password required to open the document (may be empty)
+    pub user_password: String,
+    /// Password required to change permissions, grants full access
+    pub owner_password: String,
+    /// Operations allowed for users that opened the document with the user password
+    pub permissions: PdfPermissions,
+    /// Encryption algorithm
+    pub method: EncryptionMethod,
+}
+
+impl PdfEncryption {
+    /// Creates new AES-256 encryption settings, allowing all operations
+    pub fn new(user_password: &str, owner_password: &str) -> Self {
+        Self {
+            user_password: user_password.to_string(),
+            owner_password: owner_password.to_string(),
+            permissions: PdfPermissions::default(),
+            method: EncryptionMethod::Aes256,
+        }
+    }
+
+    /// Sets the permissions for the user password
+    pub fn with_permissions(mut self, permissions: PdfPermissions) -> Self {
+        self.permissions = permissions;
+        self
+    }
+
+    /// Sets the encryption algorithm
+    pub fn with_method(mut self, method: EncryptionMethod) -> Self {
+        self.method = method;
+        self
+    }
+}
+
+/// Encrypts all strings and streams of the document and sets the `/Encrypt` dictionary
+/// in the trailer. `file_id` is the first element of the trailers `/ID` array.
+pub(crate) fn encrypt_document(
+    doc: &mut lopdf::Document,
+    encryption: &PdfEncryption,
+    file_id: &[u8],
+) -> Result<(), String> {
+    let (encrypt_dict, file_key) = match encryption.method {
+        EncryptionMethod::Rc4 => rc4_encrypt_dict(encryption, file_id),
+        EncryptionMethod::Aes256 => {
+            add_aes256_extension(doc);
+            aes256_encrypt_dict(encryption)?
+        }
+    };
+
+    for (id, object) in doc.objects.iter_mut() {
+        let id = *id;
+        map_object_data(object, &mut |data, _| match encryption.method {
+            EncryptionMethod::Rc4 => Ok(rc4(&rc4_object_key(&file_key, id, false), data)),
+            EncryptionMethod::Aes256 => {
+                // AESV3 uses the file key directly, with a random IV prepended to the data
+                let mut iv = [0; 16];
+                random_bytes(&mut iv)?;
+                let mut out = iv.to_vec();
+                out.extend(aes256_cbc_encrypt(&file_key, &iv, &pkcs7_pad(data)));
+                Ok(out)
+            }
+        })?;
+    }
+
+    let encrypt_id = doc.add_object(Dictionary(encrypt_dict));
+    doc.trailer.set("Encrypt", Reference(encrypt_id));
+    Ok(())
+}
+
+/// AESV3 requires PDF 2.0 or PDF 1.7 with the Adobe extension level 8, which is
+/// declared in the `/Extensions` dictionary of the catalog
+fn add_aes256_extension(doc: &mut lopdf::Document) {
+    if doc.version.as_str() >= "2.0" {
+        return;
+    }
+    doc.version = "1.7".to_string();
+    let Ok(catalog) = doc.catalog_mut() else {
+        return;
+    };
+    let mut adbe = LoDictionary::new();
+    adbe.set("BaseVersion", Name(b"1.7".to_vec()));
+    adbe.set("ExtensionLevel", Integer(8));
+    let mut extensions = match catalog.get(b"Extensions") {
+        Ok(Dictionary(d)) => d.clone(),
+        _ => LoDictionary::new(),
+    };
+    extensions.set("ADBE", Dictionary(adbe));
+    catalog.set("Extensions", Dictionary(extensions));
+}
+
+/// Cipher used for the strings or streams of an encrypted document
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Cipher {
+    Identity,
+    Rc4,
+    Aes128,
+    Aes256,
+}
+
+/// Type of the data passed to the callback of `map_object_data`
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum DataKind {
+    String,
+    Stream,
+    MetadataStream,
+}
+
+/// Decrypts all strings and streams of a parsed document with the standard security
+/// handler and removes the `/Encrypt` dictionary. `password` can be either the user
+/// or the owner password (use an empty string for documents without a user password).
+pub(crate) fn decrypt_document(doc: &mut lopdf::Document, password: &str) -> Result<(), String> {
+    let (encrypt_id, encrypt) = match doc.trailer.get(b"Encrypt") {
+        Ok(Reference(id)) => (
+            Some(*id),
+            doc.get_dictionary(*id)
+                .map_err(|e| format!("invalid /Encrypt dictionary: {e}"))?
+                .clone(),
+        ),
+        Ok(Dictionary(d)) => (None, d.clone()),
+        _ => return Ok(()),
+    };
+
+    let get_int = |key: &[u8]| encrypt.get(key).and_then(|o| o.as_i64()).ok();
+    let get_bytes = |key: &[u8]| {
+        encrypt
+            .get(key)
+            .and_then(|o| o.as_str())
+            .map(|s| s.to_vec())
+            .unwrap_or_default()
+    };
+
+    match encrypt.get(b"Filter").and_then(|f| f.as_name()) {
+        Ok(b"Standard") => {}
+        Ok(other) => {
+            return Err(format!(
+                "unsupported security handler {:?}",
+                String::from_utf8_lossy(other)
+            ))
+        }
+        Err(_) => return Err("missing security handler in /Encrypt".to_string()),
+    }
+
+    let version = get_int(b"V").unwrap_or(0);
+    let revision = get_int(b"R").unwrap_or(2);
+    let key_len = (get_int(b"Length").unwrap_or(40) / 8).clamp(5, 16) as usize;
+    let permissions = get_int(b"P").unwrap_or(0) as i32;
+    let encrypt_metadata = !matches!(encrypt.get(b"EncryptMetadata"), Ok(Boolean(false)));
+    let (o, u) = (get_bytes(b"O"), get_bytes(b"U"));
+    let file_id = doc
+        .trailer
+        .get(b"ID")
+        .and_then(|id| id.as_array())
+        .ok()
+        .and_then(|id| id.first())
+        .and_then(|id| id.as_str().ok())
+        .map(|id| id.to_vec())
+        .unwrap_or_default();
+
+    let crypt_filter = |key: &[u8]| {
+        let name = encrypt
+            .get(key)
+            .and_then(|n| n.as_name())
+            .unwrap_or(&b"Identity"[..]);
+        let cfm = encrypt
+            .get(b"CF")
+            .and_then(|cf| cf.as_dict())
+            .and_then(|cf| cf.get(name))
+            .and_then(|f| f.as_dict())
+            .and_then(|f| f.get(b"CFM"))
+            .and_then(|cfm| cfm.as_name())
+            .unwrap_or(&b"None"[..]);
+        match cfm {
+            b"V2" => Cipher::Rc4,
+            b"AESV2" => Cipher::Aes128,
+            b"AESV3" => Cipher::Aes256,
+            _ => Cipher::Identity,
+        }
+    };
+
+    let (stream_cipher, string_cipher) = match version {
+        1 | 2 => (Cipher::Rc4, Cipher::Rc4),
+        4 | 5 => (crypt_filter(b"StmF"), crypt_filter(b"StrF")),
+        v => return Err(format!("unsupported encryption version {v}")),
+    };
+
+    let file_key = match revision {
+        2..=4 => {
+            let params = Rc4Params {
+                o: &o,
+                permissions,
+                file_id: &file_id,
+                revision,
+                key_len: if version == 4 { 16 } else { key_len },
+                encrypt_metadata,
+            };
+            rc4_authenticate(password, &u, &params)
+        }
+        5 | 6 => aes256_authenticate(
+            password,
+            &o,
+            &u,
+            &get_bytes(b"OE"),
+            &get_bytes(b"UE"),
+            revision,
+        ),
+        r => return Err(format!("unsupported security handler revision {r}")),
+    }
+    .ok_or_else(|| "incorrect password".to_string())?;
+
+    for (id, object) in doc.objects.iter_mut() {
+        if Some(*id) == encrypt_id {
+            continue;
+        }
+        let id = *id;
+        map_object_data(object, &mut |data, kind| {
+            let cipher = match kind {
+                DataKind::String => string_cipher,
+                DataKind::Stream => stream_cipher,
+                DataKind::MetadataStream if encrypt_metadata => stream_cipher,
+                DataKind::MetadataStream => Cipher::Identity,
+            };
+            Ok(decrypt_data(data, cipher, &file_key, id))
+        })?;
+    }
+
+    doc.trailer.remove(b"Encrypt");
+    if let Some(id) = encrypt_id {
+        doc.objects.remove(&id);
+    }
+    Ok(())
+}
+
+fn decrypt_data(data: &[u8], cipher: Cipher, file_key: &[u8], id: lopdf::ObjectId) -> Vec<u8> {
+    let (key, aes256) = match cipher {
+        Cipher::Identity => return data.to_vec(),
+        Cipher::Rc4 => return rc4(&rc4_object_key(file_key, id, false), data),
+        Cipher::Aes128 => (rc4_object_key(file_key, id, true), false),
+        Cipher::Aes256 => (file_key.to_vec(), true),
+    };
+    // the first 16 bytes are the IV, invalid data is passed through unchanged
+    if data.len() < 32 || data.len() % 16 != 0 {
+        return data.to_vec();
+    }
+    let (iv, data) = data.split_at(16);
+    let decrypted = if aes256 {
+        aes256_cbc_decrypt(&key, iv, data)
+    } else {
+        aes128_cbc_decrypt(&key, iv, data)
+    };
+    pkcs7_unpad(decrypted)
+}
+
+/// Calls `f` for every string and stream in the object and replaces the data with the
+/// result. Cross-reference streams and the `/Contents` of signatures are skipped.
+fn map_object_data<F>(object: &mut lopdf::Object, f: &mut F) -> Result<(), String>
+where
+    F: FnMut(&[u8], DataKind) -> Result<Vec<u8>, String>,
+{
+    match object {
+        LoString(bytes, format) => {
+            *bytes = f(bytes, DataKind::String)?;
+            *format = Hexadecimal;
+        }
+        lopdf::Object::Array(items) => {
+            for item in items.iter_mut() {
+                map_object_data(item, f)?;
+            }
+        }
+        Dictionary(dict) => map_dict_data(dict, f)?,
+        Stream(stream) => {
+            let kind = match stream.dict.get(b"Type").and_then(|t| t.as_name()) {
+                Ok(b"XRef") => return Ok(()),
+                Ok(b"Metadata") => DataKind::MetadataStream,
+                _ => DataKind::Stream,
+            };
+            map_dict_data(&mut stream.dict, f)?;
+            let content = f(&stream.content, kind)?;
+            stream.set_content(content);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn map_dict_data<F>(dict: &mut LoDictionary, f: &mut F) -> Result<(), String>
+where
+    F: FnMut(&[u8], DataKind) -> Result<Vec<u8>, String>,
+{
+    // the /Contents of a signature are not encrypted, the signature is
+    // calculated over the encrypted file
+    let is_signature = matches!(dict.get(b"Type"), Ok(Name(n)) if n == b"Sig");
+    for (key, value) in dict.iter_mut() {
+        if is_signature && key == b"Contents" {
+            continue;
+        }
+        map_object_data(value, f)?;
+    }
+    Ok(())
+}
+
+/// Parameters of the RC4 / AES-128 standard security handler (revisions 2 - 4)
+struct Rc4Params<'a> {
+    o: &'a [u8],
+    permissions: i32,
+    file_id: &'a [u8],
+    revision: i64,
+    key_len: usize,
+    encrypt_metadata: bool,
+}
+
+/// RC4, 128-bit key, revision 3 (algorithms 3.2 - 3.5)
+fn rc4_encrypt_dict(encryption: &PdfEncryption, file_id: &[u8]) -> (LoDictionary, Vec<u8>) {
+    let permissions = encryption.permissions.get_flags();
+    let user = pad_password(&encryption.user_password);
+    let owner = if encryption.owner_password.is_empty() {
+        user
+    } else {
+        pad_password(&encryption.owner_password)
+    };
+
+    // algorithm 3.3: /O value
+    let owner_key = rc4_owner_key(&owner, 3, 16);
+    let mut o = rc4(&owner_key, &user);
+    for i in 1..=19_u8 {
+        let key = owner_key.iter().map(|b| b ^ i).collect::<Vec<_>>();
+        o = rc4(&key, &o);
+    }
+
+    let params = Rc4Params {
+        o: &o,
+        permissions,
+        file_id,
+        revision: 3,
+        key_len: 16,
+        encrypt_metadata: true,
+    };
+    let file_key = rc4_file_key(&user, &params);
+    let u = rc4_user_value(&file_key, &params);
+
+    let dict = LoDictionary::from_iter(vec![
+        ("Filter", Name("Standard".into())),
+        ("V", Integer(2)),
+        ("R", Integer(3)),
+        ("Length", Integer(128)),
+        ("O", LoString(o, Hexadecimal)),
+        ("U", LoString(u, Hexadecimal)),
+        ("P", Integer(permissions as i64)),
+    ]);
+
+    (dict, file_key)
+}
+
+/// Returns the file key if `password` is the user or owner password (algorithms 3.6, 3.7)
+fn rc4_authenticate(password: &str, u: &[u8], params: &Rc4Params) -> Option<Vec<u8>> {
+    // revision 3+ only compares the first 16 bytes of /U
+    let cmp_len = if params.revision == 2 { 32 } else { 16 };
+    let check_user = |padded: &[u8; 32]| {
+        let file_key = rc4_file_key(padded, params);
+        let expected = rc4_user_value(&file_key, params);
+        match u.get(..cmp_len) {
+            Some(u) if u == &expected[..cmp_len] => Some(file_key),
+            _ => None,
+        }
+    };
+
+    let padded = pad_password(password);
+    if let Some(file_key) = check_user(&padded) {
+        return Some(file_key);
+    }
+
+    // owner password: decrypting /O yields the padded user password
+    let owner_key = rc4_owner_key(&padded, params.revision, params.key_len);
+    let mut user = params.o.to_vec();
+    if params.revision == 2 {
+        user = rc4(&owner_key, &user);
+    } else {
+        for i in (0..=19_u8).rev() {
+            let key = owner_key.iter().map(|b| b ^ i).collect::<Vec<_>>();
+            user = rc4(&key, &user);
+        }
+    }
+    check_user(&user.try_into().ok()?)
+}
+
+/// Key used to encrypt the /O value (algorithm 3.3, steps 1 - 4)
+fn rc4_owner_key(owner: &[u8; 32], revision: i64, key_len: usize) -> Vec<u8> {
+    let mut key = Md5::digest(owner).to_vec();
+    if revision >= 3 {
+        for _ in 0..50 {
+            key = Md5::digest(&key).to_vec();
+        }
+    }
+    key.truncate(key_len);
+    key
+}
+
+/// File encryption key (algorithm 3.2)
+fn rc4_file_key(user: &[u8; 32], params: &Rc4Params) -> Vec<u8> {
+    let mut hasher = Md5::new();
+    hasher.update(user);
+    hasher.update(params.o);
+    hasher.update(params.permissions.to_le_bytes());
+    hasher.update(params.file_id);
+    if params.revision >= 4 && !params.encrypt_metadata {
+        hasher.update([0xFF; 4]);
+    }
+    let mut file_key = hasher.finalize().to_vec();
+    if params.revision >= 3 {
+        for _ in 0..50 {
+            file_key = Md5::digest(&file_key[..params.key_len]).to_vec();
+        }
+    }
+    file_key.truncate(params.key_len);
+    file_key
+}
+
+/// /U value (algorithms 3.4 and 3.5)
+fn rc4_user_value(file_key: &[u8], params: &Rc4Params) -> Vec<u8> {
+    if params.revision == 2 {
+        return rc4(file_key, &PASSWORD_PADDING);
+    }
+    let mut hasher = Md5::new();
+    hasher.update(PASSWORD_PADDING);
+    hasher.update(params.file_id);
+    let mut u = rc4(file_key, &hasher.finalize());
+    for i in 1..=19_u8 {
+        let key = file_key.iter().map(|b| b ^ i).collect::<Vec<_>>();
+        u = rc4(&key, &u);
+    }
+    u.resize(32, 0);
+    u
+}
+
+/// Object key for RC4 / AES-128 (algorithm 3.1)
+fn rc4_object_key(file_key: &[u8], id: lopdf::ObjectId, aes: bool) -> Vec<u8> {
+    let mut hasher = Md5::new();
+    hasher.update(file_key);
+    hasher.update(&id.0.to_le_bytes()[..3]);
+    hasher.update(id.1.to_le_bytes());
+    if aes {
+        hasher.update(b"sAlT");
+    }
+    let mut key = hasher.finalize().to_vec();
+    key.truncate((file_key.len() + 5).min(16));
+    key
+}
+
+/// AES-256, revision 6 (PDF 2.0, algorithms 8 - 10)
+fn aes256_encrypt_dict(encryption: &PdfEncryption) -> Result<(LoDictionary, Vec<u8>), String> {
+    let permissions = encryption.permissions.get_flags();
+    let user = truncate_password(&encryption.user_password);
+    let owner = if encryption.owner_password.is_empty() {
+        user
+    } else {
+        truncate_password(&encryption.owner_password)
+    };
+
+    let mut file_key = vec![0; 32];
+    random_bytes(&mut file_key)?;
+    let mut salts = [0; 32];
+    random_bytes(&mut salts)?;
+    let (user_validation_salt, rest) = salts.split_at(8);
+    let (user_key_salt, rest) = rest.split_at(8);
+    let (owner_validation_salt, owner_key_salt) = rest.split_at(8);
+
+    // algorithm 8: /U and /UE
+    let mut u = hash_r6(user, user_validation_salt, &[]);
+    u.extend_from_slice(user_validation_salt);
+    u.extend_from_slice(user_key_salt);
+    let ue = aes256_cbc_encrypt(&hash_r6(user, user_key_salt, &[]), &[0; 16], &file_key);
+
+    // algorithm 9: /O and /OE (hashed together with the /U value)
+    let mut o = hash_r6(owner, owner_validation_salt, &u);
+    o.extend_from_slice(owner_validation_salt);
+    o.extend_from_slice(owner_key_salt);
+    let oe = aes256_cbc_encrypt(&hash_r6(owner, owner_key_salt, &u), &[0; 16], &file_key);
+
+    // algorithm 10: /Perms
+    let mut perms = [0; 16];
+    perms[..4].copy_from_slice(&permissions.to_le_bytes());
+    perms[4..8].copy_from_slice(&[0xFF; 4]);
+    perms[8..12].copy_from_slice(b"Tadb");
+    random_bytes(&mut perms[12..])?;
+    let perms = aes256_cbc_encrypt(&file_key, &[0; 16], &perms);
+
+    let crypt_filter = LoDictionary::from_iter(vec![
+        ("Type", Name("CryptFilter".into())),
+        ("CFM", Name("AESV3".into())),
+        ("AuthEvent", Name("DocOpen".into())),
+        ("Length", Integer(32)),
+    ]);
+
+    let dict = LoDictionary::from_iter(vec![
+        ("Filter", Name("Standard".into())),
+        ("V", Integer(5)),
+        ("R", Integer(6)),
+        ("Length", Integer(256)),
+        (
+            "CF",
+            Dictionary(LoDictionary::from_iter(vec![(
+                "StdCF",
+                Dictionary(crypt_filter),
+            )])),
+        ),
+        ("StmF", Name("StdCF".into())),
+        ("StrF", Name("StdCF".into())),
+        ("O", LoString(o, Hexadecimal)),
+        ("U", LoString(u, Hexadecimal)),
+        ("OE", LoString(oe, Hexadecimal)),
+        ("UE", LoString(ue, Hexadecimal)),
+        ("P", Integer(permissions as i64)),
+        ("Perms", LoString(perms, Hexadecimal)),
+        ("EncryptMetadata", Boolean(true)),
+    ]);
+
+    Ok((dict, file_key))
+}
+
+/// Returns the file key if `password` is the user or owner password (algorithms 11, 12)
+fn aes256_authenticate(
+    password: &str,
+    o: &[u8],
+    u: &[u8],
+    oe: &[u8],
+    ue: &[u8],
+    revision: i64,
+) -> Option<Vec<u8>> {
+    if o.len() < 48 || u.len() < 48 || oe.len() != 32 || ue.len() != 32 {
+        return None;
+    }
+    let password = truncate_password(password);
+    // revision 5 (deprecated Adobe extension level 3) uses a plain SHA-256 hash
+    let hash = |salt: &[u8], user_key: &[u8]| {
+        if revision == 5 {
+            let mut hasher = Sha256::new();
+            hasher.update(password);
+            hasher.update(salt);
+            hasher.update(user_key);
+            hasher.finalize().to_vec()
+        } else {
+            hash_r6(password, salt, user_key)
+        }
+    };
+
+    if hash(&u[32..40], &[]) == u[..32] {
+        let key = hash(&u[40..48], &[]);
+        return Some(aes256_cbc_decrypt(&key, &[0; 16], ue));
+    }
+    if hash(&o[32..40], &u[..48]) == o[..32] {
+        let key = hash(&o[40..48], &u[..48]);
+        return Some(aes256_cbc_decrypt(&key, &[0; 16], oe));
+    }
+    None
+}
+
+/// Password hash of revision 6 (algorithm 2.B)
+fn hash_r6(password: &[u8], salt: &[u8], user_key: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(password);
+    hasher.update(salt);
+    hasher.update(user_key);
+    let mut k = hasher.finalize().to_vec();
+
+    let mut round = 0;
+    loop {
+        let mut k1 = Vec::with_capacity(64 * (password.len() + k.len() + user_key.len()));
+        for _ in 0..64 {
+            k1.extend_from_slice(password);
+            k1.extend_from_slice(&k);
+            k1.extend_from_slice(user_key);
+        }
+
+        let e = aes128_cbc_encrypt(&k[..16], &k[16..32], &k1);
+        // the first 16 bytes of E as a big-endian number, modulo 3
+        let modulo = e[..16].iter().map(|b| *b as u32).sum::<u32>() % 3;
+        k = match modulo {
+            0 => Sha256::digest(&e).to_vec(),
+            1 => Sha384::digest(&e).to_vec(),
+            _ => Sha512::digest(&e).to_vec(),
+        };
+
+        round += 1;
+        if round >= 64 && (*e.last().unwrap_or(&0) as usize) + 32 <= round {
+            break;
+        }
+    }
+
+    k.truncate(32);
+    k
+}
+
+fn pad_password(password: &str) -> [u8; 32] {
+    // passwords of revision 3 are PDFDocEncoded, non-Latin-1 characters are dropped
+    let bytes = password
+        .chars()
+        .filter_map(|c| u8::try_from(c as u32).ok())
+        .take(32)
+        .collect::<Vec<_>>();
+    let mut padded = PASSWORD_PADDING;
+    padded[..bytes.len()].copy_from_slice(&bytes);
+    padded[bytes.len()..].copy_from_slice(&PASSWORD_PADDING[..32 - bytes.len()]);
+    padded
+}
+
+fn truncate_password(password: &str) -> &[u8] {
+    // revision 6 uses UTF-8 passwords, truncated to 127 bytes
+    let mut end = password.len().min(127);
+    while !password.is_char_boundary(end) {
+        end -= 1;
+    }
+    &password.as_bytes()[..end]
+}
+
+fn rc4(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut s = [0_u8; 256];
+    for (i, v) in s.iter_mut().enumerate() {
+        *v = i as u8;
+    }
+    let mut j: u8 = 0;
+    for i in 0..256 {
+        j = j.wrapping_add(s[i]).wrapping_add(key[i % key.len()]);
+        s.swap(i, j as usize);
+    }
+
+    let mut i: u8 = 0;
+    let mut j: u8 = 0;
+    data.iter()
+        .map(|b| {
+            i = i.wrapping_add(1);
+            j = j.wrapping_add(s[i as usize]);
+            s.swap(i as usize, j as usize);
+            b ^ s[s[i as usize].wrapping_add(s[j as usize]) as usize]
+        })
+        .collect()
+}
+
+fn pkcs7_pad(data: &[u8]) -> Vec<u8> {
+    let pad = 16 - data.len() % 16;
+    let mut out = data.to_vec();
+    out.extend(std::iter::repeat(pad as u8).take(pad));
+    out
+}
+
+fn pkcs7_unpad(mut data: Vec<u8>) -> Vec<u8> {
+    let pad = data.last().copied().unwrap_or(0) as usize;
+    if (1..=16).contains(&pad) && pad <= data.len() {
+        data.truncate(data.len() - pad);
+    }
+    data
+}
+
+/// AES-256-CBC without padding (`data` must be a multiple of 16 bytes)
+fn aes256_cbc_encrypt(key: &[u8], iv: &[u8], data: &[u8]) -> Vec<u8> {
+    let cipher = Aes256::new(GenericArray::from_slice(key));
+    cbc_encrypt(|block| cipher.encrypt_block(block), iv, data)
+}
+
+/// AES-128-CBC without padding (`data` must be a multiple of 16 bytes)
+fn aes128_cbc_encrypt(key: &[u8], iv: &[u8], data: &[u8]) -> Vec<u8> {
+    let cipher = Aes128::new(GenericArray::from_slice(key));
+    cbc_encrypt(|block| cipher.encrypt_block(block), iv, data)
+}
+
+fn cbc_encrypt<F>(encrypt_block: F, iv: &[u8], data: &[u8]) -> Vec<u8>
+where
+    F: Fn(&mut GenericArray<u8, aes::cipher::consts::U16>),
+{
+    let mut out = Vec::with_capacity(data.len());
+    let mut prev = GenericArray::clone_from_slice(iv);
+    for chunk in data.chunks_exact(16) {
+        let mut block = GenericArray::clone_from_slice(chunk);
+        for (b, p) in block.iter_mut().zip(prev.iter()) {
+            *b ^= p;
+        }
+        encrypt_block(&mut block);
+        out.extend_from_slice(&block);
+        prev = block;
+    }
+    out
+}
+
+/// AES-256-CBC without padding (`data` must be a multiple of 16 bytes)
+fn aes256_cbc_decrypt(key: &[u8], iv: &[u8], data: &[u8]) -> Vec<u8> {
+    let cipher = Aes256::new(GenericArray::from_slice(key));
+    cbc_decrypt(|block| cipher.decrypt_block(block), iv, data)
+}
+
+/// AES-128-CBC without padding (`data` must be a multiple of 16 bytes)
+fn aes128_cbc_decrypt(key: &[u8], iv: &[u8], data: &[u8]) -> Vec<u8> {
+    let cipher = Aes128::new(GenericArray::from_slice(key));
+    cbc_decrypt(|block| cipher.decrypt_block(block), iv, data)
+}
+
+fn cbc_decrypt<F>(decrypt_block: F, iv: &[u8], data: &[u8]) -> Vec<u8>
+where
+    F: Fn(&mut GenericArray<u8, aes::cipher::consts::U16>),
+{
+    let mut out = Vec::with_capacity(data.len());
+    let mut prev = GenericArray::clone_from_slice(iv);
+    for chunk in data.chunks_exact(16) {
+        let cipher_block = GenericArray::clone_from_slice(chunk);
+        let mut block = cipher_block;
+        decrypt_block(&mut block);
+        for (b, p) in block.iter_mut().zip(prev.iter()) {
+            *b ^= p;
+        }
+        out.extend_from_slice(&block);
+        prev = cipher_block;
+    }
+    out
+}
+
+fn random_bytes(buf: &mut [u8]) -> Result<(), String> {
+    getrandom::getrandom(buf).map_err(|e| format!("failed to generate encryption keys: {e}"))
+}
+
+#[test]
+fn test_permission_flags() {
+    assert_eq!(PdfPermissions::all().get_flags(), -4);
+    assert_eq!(PdfPermissions::none().get_flags() as u32, 0xFFFF_F2C0);
+}
+
+#[test]
+fn test_rc4() {
+    // RFC 6229 test vector, key 0x0102030405
+    let out = rc4(&[1, 2, 3, 4, 5], &[0; 4]);
+    assert_eq!(out, vec![0xB2, 0x39, 0x63, 0x05]);
+}
+
+#[test]
+fn test_encrypt_decrypt_roundtrip() {
+    for method in [EncryptionMethod::Rc4, EncryptionMethod::Aes256] {
+        let mut doc = lopdf::Document::with_version("1.3");
+        let id = doc.add_object(LoString(b"secret text".to_vec(), Hexadecimal));
+        doc.trailer.set(
+            "ID",
+            lopdf::Object::Array(vec![LoString(b"0123456789".to_vec(), Hexadecimal)]),
+        );
+
+        let mut catalog = LoDictionary::new();
+        catalog.set("Type", Name(b"Catalog".to_vec()));
+        let catalog = doc.add_object(Dictionary(catalog));
+        doc.trailer.set("Root", Reference(catalog));
+
+        let encryption = PdfEncryption::new("user", "owner").with_method(method);
+        encrypt_document(&mut doc, &encryption, b"0123456789").unwrap();
+        let extensions = doc.catalog().unwrap().get(b"Extensions");
+        if method == EncryptionMethod::Aes256 {
+            assert_eq!(doc.version, "1.7");
+            let adbe = extensions.unwrap().as_dict().unwrap().get(b"ADBE").unwrap();
+            let level = adbe.as_dict().unwrap().get(b"ExtensionLevel").unwrap();
+            assert_eq!(level.as_i64().unwrap(), 8);
+        } else {
+            assert!(extensions.is_err());
+        }
+        assert_ne!(
+            doc.get_object(id).unwrap().as_str().unwrap(),
+            b"secret text"
+        );
+
+        assert!(decrypt_document(&mut doc.clone(), "wrong").is_err());
+        for password in ["user", "owner"] {
+            let mut decrypted = doc.clone();
+            decrypt_document(&mut decrypted, password).unwrap();
+            assert_eq!(
+                decrypted.get_object(id).unwrap().as_str().unwrap(),
+                b"secret text"
+            );
+            assert!(decrypted.trailer.get(b"Encrypt").is_err());
+        }
+    }
+}
@@ -0,0 +1,485 @@
+//! Text extraction from the page contents, optionally in the reading order of the
+//! structure tree (tagged PDF)
+
+use std::collections::BTreeMap;
+
+use crate::{
+    text::{TextCursor, TextState},
+    CurTransMat, FontId, Op, ParsedFont, PdfDocument, PdfFontMap, PdfPage, StructureElementId,
+    StructureTree,
+};
+
+/// Options for `PdfDocument::extract_text`
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct TextExtractionOptions {
+    /// Order the text by the structure tree instead of the content stream order
+    /// (untagged text is appended at the end of the page)
+    pub follow_structure: bool,
+    /// Skip artifacts (page headers, footers, page numbers, decorations)
+    pub skip_artifacts: bool,
+}
+
+impl TextExtractionOptions {
+    /// Follows the structure tree and skips artifacts
+    pub fn tagged() -> Self {
+        Self {
+            follow_structure: true,
+            skip_artifacts: true,
+        }
+    }
+}
+
+impl PdfDocument {
+    /// Extracts the text of every page (one string per page)
+    pub fn extract_text(&self, opts: &TextExtractionOptions) -> Vec<String> {
+        let reading_order = if opts.follow_structure {
+            get_reading_order(&self.structure)
+        } else {
+            BTreeMap::new()
+        };
+        self.pages
+            .iter()
+            .map(|p| extract_page_text(p, &self.resources.fonts, opts, &reading_order))
+            .collect()
+    }
+}
+
+/// Text of a marked content sequence (or of untagged content)
+struct TextChunk {
+    element: Option<StructureElementId>,
+    text: String,
+}
+
+enum MarkedContent {
+    Element(StructureElementId),
+    Artifact,
+    LanguageSpan,
+}
+
+/// Tracks the text position, so that word and line breaks can be inferred from the gaps
+/// between the glyphs (the content stream usually has no space characters between
+/// separately positioned words)
+#[derive(Debug, Default)]
+struct TextPosition {
+    /// End of the last written glyph in user space (x, y, font size in user space)
+    last: Option<(f32, f32, f32)>,
+}
+
+impl TextPosition {
+    /// Lays out the glyphs (character, advance, gap before the glyph, in text space),
+    /// returns the text with inferred spaces / line breaks. Gaps are measured along the
+    /// baseline of the current text matrix, so rotated or scaled text is handled.
+    fn write(
+        &mut self,
+        glyphs: &[(char, f32, f32)],
+        size: f32,
+        space_width: f32,
+        cursor: &mut TextCursor,
+        state: &TextState,
+        ctm: [f32; 6],
+    ) -> String {
+        let m = cursor.get_text_to_user(ctm);
+        let scale_x = m[0].hypot(m[1]).max(f32::EPSILON);
+        let scale_y = m[2].hypot(m[3]);
+        // unit vector along the baseline
+        let (dx, dy) = (m[0] / scale_x, m[1] / scale_x);
+        let origin = |cursor: &TextCursor| {
+            let m = cursor.get_text_to_user(ctm);
+            (m[2] * state.rise + m[4], m[3] * state.rise + m[5])
+        };
+
+        let threshold = space_width * 0.5;
+        let mut out = String::new();
+
+        if let Some((last_x, last_y, last_size)) = self.last {
+            let (x, y) = origin(cursor);
+            let along = (x - last_x) * dx + (y - last_y) * dy;
+            let across = (y - last_y) * dx - (x - last_x) * dy;
+            let starts_with_space = matches!(glyphs.first(), Some((c, _, _)) if c.is_whitespace());
+            if across.abs() > last_size * 0.5 || along < -last_size * 0.5 {
+                // different baseline or moved back (new line or column)
+                out.push('\n');
+            } else if along > threshold * scale_x && !starts_with_space {
+                out.push(' ');
+            }
+        }
+
+        for (c, advance, gap) in glyphs {
+            cursor.advance(*gap);
+            if *gap > threshold && !out.ends_with(char::is_whitespace) && !c.is_whitespace() {
+                out.push(' ');
+            }
+            out.push(*c);
+            cursor.advance(*advance);
+        }
+
+        let (x, y) = origin(cursor);
+        self.last = Some((x, y, size * scale_y));
+        out
+    }
+}
+
+/// Scaled horizontal advance of a glyph
+fn get_advance(font: Option<&ParsedFont>, glyph: Option<u16>, size: f32) -> f32 {
+    match (font, glyph) {
+        (Some(f), Some(g)) if f.font_metrics.units_per_em > 0 => {
+            f.get_horizontal_advance(g) as f32 / f.font_metrics.units_per_em as f32 * size
+        }
+        // no metrics available, estimate half an em
+        _ => size * 0.5,
+    }
+}
+
+fn get_space_width(fonts: &PdfFontMap, font: &FontId, size: f32) -> f32 {
+    fonts
+        .map
+        .get(font)
+        .and_then(|f| {
+            let upem = f.font_metrics.units_per_em;
+            f.get_space_width()
+                .filter(|_| upem > 0)
+                .map(|w| w as f32 / upem as f32 * size)
+        })
+        .unwrap_or(size * 0.25)
+}
+
+/// Position of every structure element in a depth-first traversal of the tree
+fn get_reading_order(tree: &StructureTree) -> BTreeMap<StructureElementId, usize> {
+    let mut order = BTreeMap::new();
+    let mut stack = tree.root.iter().rev().collect::<Vec<_>>();
+    while let Some(id) = stack.pop() {
+        if order.contains_key(id) {
+            continue;
+        }
+        order.insert(id.clone(), order.len());
+        if let Some(element) = tree.elements.get(id) {
+            stack.extend(element.children.iter().rev());
+        }
+    }
+    order
+}
+
+fn extract_page_text(
+    page: &PdfPage,
+    fonts: &PdfFontMap,
+    opts: &TextExtractionOptions,
+    reading_order: &BTreeMap<StructureElementId, usize>,
+) -> String {
+    let mut chunks = Vec::<TextChunk>::new();
+    let mut marked_content = Vec::new();
+    let mut pos = TextPosition::default();
+    let mut cursor = TextCursor::default();
+    let mut ctm = CurTransMat::Identity.as_array();
+    let mut state = TextState::default();
+    // `q` / `Q` save the transformation matrix and the text state, not the text matrix
+    let mut stack = Vec::new();
+
+    for op in page.ops.iter() {
+        let text = match op {
+            Op::BeginStructureElement { id } => {
+                marked_content.push(MarkedContent::Element(id.clone()));
+                continue;
+            }
+            Op::BeginArtifact => {
+                marked_content.push(MarkedContent::Artifact);
+                continue;
+            }
+            Op::BeginLanguageSpan { .. } => {
+                marked_content.push(MarkedContent::LanguageSpan);
+                continue;
+            }
+            Op::EndStructureElement | Op::EndArtifact | Op::EndLanguageSpan => {
+                marked_content.pop();
+                continue;
+            }
+            Op::SaveGraphicsState => {
+                stack.push((ctm, state));
+                continue;
+            }
+            Op::RestoreGraphicsState => {
+                if let Some((prev_ctm, prev_state)) = stack.pop() {
+                    ctm = prev_ctm;
+                    state = prev_state;
+                }
+                continue;
+            }
+            Op::SetTransformationMatrix { matrix } => {
+                ctm = CurTransMat::combine_matrix(matrix.as_array(), ctm);
+                continue;
+            }
+            Op::StartTextSection | Op::SetTextMatrix { .. } | Op::SetTextCursor { .. } => {
+                cursor.apply(op, &state);
+                continue;
+            }
+            Op::SetLineHeight { .. }
+            | Op::SetCharacterSpacing { .. }
+            | Op::SetWordSpacing { .. }
+            | Op::SetHorizontalScaling { .. }
+            | Op::SetLineOffset { .. } => {
+                state.apply(op);
+                continue;
+            }
+            Op::AddLineBreak => {
+                cursor.apply(op, &state);
+                pos.last = None;
+                "\n".to_string()
+            }
+            Op::WriteText { text, size, font } => {
+                let f = fonts.map.get(font);
+                let glyphs = text
+                    .chars()
+                    .map(|c| {
+                        let glyph = f.and_then(|f| f.lookup_glyph_index(c as u32));
+                        (
+                            c,
+                            state.get_advance(get_advance(f, glyph, size.0), false),
+                            0.0,
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                let space_width = get_space_width(fonts, font, size.0) * state.horizontal_scaling;
+                pos.write(&glyphs, size.0, space_width, &mut cursor, &state, ctm)
+            }
+            Op::WriteTextBuiltinFont { text, size, .. } => {
+                // builtin fonts are single-byte encoded, the word spacing applies to spaces
+                let glyphs = text
+                    .chars()
+                    .map(|c| {
+                        let advance = get_advance(None, None, size.0);
+                        (c, state.get_advance(advance, c == ' '), 0.0)
+                    })
+                    .collect::<Vec<_>>();
+                let space_width = size.0 * 0.25 * state.horizontal_scaling;
+                pos.write(&glyphs, size.0, space_width, &mut cursor, &state, ctm)
+            }
+            Op::WriteCodepoints { font, size, cp } => {
+                let f = fonts.map.get(font);
+                let glyphs = cp
+                    .iter()
+                    .map(|(g, c)| {
+                        let advance = get_advance(f, Some(*g), size.0);
+                        (*c, state.get_advance(advance, false), 0.0)
+                    })
+                    .collect::<Vec<_>>();
+                let space_width = get_space_width(fonts, font, size.0) * state.horizontal_scaling;
+                pos.write(&glyphs, size.0, space_width, &mut cursor, &state, ctm)
+            }
+            Op::WriteCodepointsWithKerning { font, size, cpk } => {
+                // kerning is in thousandths of an em, positive values move the glyph left
+                let f = fonts.map.get(font);
+                let glyphs = cpk
+                    .iter()
+                    .map(|(k, g, c)| {
+                        let gap = -(*k as f32) / 1000.0 * size.0 * state.horizontal_scaling;
+                        let advance = get_advance(f, Some(*g), size.0);
+                        (*c, state.get_advance(advance, false), gap)
+                    })
+                    .collect::<Vec<_>>();
+                let space_width = get_space_width(fonts, font, size.0) * state.horizontal_scaling;
+                pos.write(&glyphs, size.0, space_width, &mut cursor, &state, ctm)
+            }
+            _ => continue,
+        };
+
+        let is_artifact = marked_content
+            .iter()
+            .any(|m| matches!(m, MarkedContent::Artifact));
+        if is_artifact && opts.skip_artifacts {
+            continue;
+        }
+
+        // text belongs to the innermost structure element
+        let element = marked_content.iter().rev().find_map(|m| match m {
+            MarkedContent::Element(id) => Some(id.clone()),
+            MarkedContent::Artifact | MarkedContent::LanguageSpan => None,
+        });
+        match chunks.last_mut() {
+            Some(last) if last.element == element => last.text.push_str(&text),
+            _ => chunks.push(TextChunk { element, text }),
+        }
+    }
+
+    if !reading_order.is_empty() {
+        // stable sort: the content order is kept within an element
+        chunks.sort_by_key(|c| {
+            c.element
+                .as_ref()
+                .and_then(|e| reading_order.get(e))
+                .copied()
+                .unwrap_or(usize::MAX)
+        });
+    }
+
+    chunks
+        .iter()
+        .flat_map(|c| c.text.lines())
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[test]
+fn test_extract_text_structure_order() {
+    use crate::{BuiltinFont, Mm, Pt, StructureType};
+
+    let text = |s: &str| Op::WriteTextBuiltinFont {
+        text: s.to_string(),
+        size: Pt(12.0),
+        font: BuiltinFont::Helvetica,
+    };
+
+    let mut doc = PdfDocument::new("columns");
+    let left = doc.structure.add_element(None, StructureType::P);
+    let right = doc.structure.add_element(None, StructureType::P);
+
+    // the right column is written first
+    doc.pages.push(PdfPage::new(
+        Mm(210.0),
+        Mm(297.0),
+        vec![
+            Op::BeginArtifact,
+            text("Page 1"),
+            Op::AddLineBreak,
+            Op::EndArtifact,
+            Op::BeginStructureElement { id: right },
+            text("right"),
+            Op::EndStructureElement,
+            Op::AddLineBreak,
+            Op::BeginStructureElement { id: left },
+            text("left"),
+            Op::EndStructureElement,
+        ],
+    ));
+
+    let plain = doc.extract_text(&TextExtractionOptions::default());
+    assert_eq!(plain, vec!["Page 1\nright\nleft".to_string()]);
+
+    let tagged = doc.extract_text(&TextExtractionOptions::tagged());
+    assert_eq!(tagged, vec!["left\nright".to_string()]);
+}
+
+#[test]
+fn test_extract_text_whitespace() {
+    use crate::{BuiltinFont, Mm, Point, Pt, TextMatrix};
+
+    let word = |s: &str, x: f32, y: f32| {
+        vec![
+            Op::SetTextMatrix {
+                matrix: TextMatrix::Translate(Pt(x), Pt(y)),
+            },
+            Op::WriteTextBuiltinFont {
+                text: s.to_string(),
+                size: Pt(10.0),
+                font: BuiltinFont::Courier,
+            },
+        ]
+    };
+
+    let mut ops = vec![Op::StartTextSection];
+    // "Hello" is 25pt wide, then a 5pt gap
+    ops.extend(word("Hello", 100.0, 700.0));
+    ops.extend(word("world", 130.0, 700.0));
+    // directly adjacent, same word
+    ops.extend(word("wide", 155.0, 700.0));
+    // next line
+    ops.extend(word("Next", 100.0, 685.0));
+    ops.push(Op::SetTextCursor {
+        pos: Point {
+            x: Pt(0.0),
+            y: Pt(-15.0),
+        },
+    });
+    ops.push(Op::WriteCodepointsWithKerning {
+        font: FontId("missing".to_string()),
+        size: Pt(10.0),
+        cpk: vec![(0, 1, 'a'), (0, 2, 'b'), (-400, 3, 'c')],
+    });
+    ops.push(Op::EndTextSection);
+
+    let mut doc = PdfDocument::new("words");
+    doc.pages.push(PdfPage::new(Mm(210.0), Mm(297.0), ops));
+    let text = doc.extract_text(&TextExtractionOptions::default());
+    assert_eq!(text, vec!["Hello worldwide\nNext\nab c".to_string()]);
+}
+
+#[test]
+fn test_extract_text_spacing() {
+    use crate::{BuiltinFont, Mm, Pt, TextMatrix};
+
+    let word = |s: &str, x: f32| {
+        vec![
+            Op::SetTextMatrix {
+                matrix: TextMatrix::Translate(Pt(x), Pt(700.0)),
+            },
+            Op::WriteTextBuiltinFont {
+                text: s.to_string(),
+                size: Pt(10.0),
+                font: BuiltinFont::Courier,
+            },
+        ]
+    };
+
+    let mut ops = vec![Op::StartTextSection];
+    // 1pt character spacing: "Hello" is 30pt wide, so "world" directly follows it
+    ops.push(Op::SetCharacterSpacing { multiplier: 1.0 });
+    ops.extend(word("Hello", 100.0));
+    ops.extend(word("world", 130.0));
+    // 50% horizontal scaling: "ab" is 6pt wide, "cd" is separated by a gap
+    ops.push(Op::SetCharacterSpacing { multiplier: 0.0 });
+    ops.push(Op::SetHorizontalScaling { percent: 50.0 });
+    ops.extend(word("ab", 200.0));
+    ops.extend(word("cd", 210.0));
+    // raised text stays on the same line
+    ops.push(Op::SetHorizontalScaling { percent: 100.0 });
+    ops.push(Op::SetLineOffset { multiplier: 3.0 });
+    ops.extend(word("2", 212.5));
+    ops.push(Op::EndTextSection);
+
+    let mut doc = PdfDocument::new("spacing");
+    doc.pages.push(PdfPage::new(Mm(210.0), Mm(297.0), ops));
+    let text = doc.extract_text(&TextExtractionOptions::default());
+    assert_eq!(text, vec!["Helloworld ab cd2".to_string()]);
+}
+
+#[test]
+fn test_extract_text_transformed() {
+    use crate::{BuiltinFont, Mm, Pt, TextMatrix};
+
+    let text = |s: &str| Op::WriteTextBuiltinFont {
+        text: s.to_string(),
+        size: Pt(10.0),
+        font: BuiltinFont::Courier,
+    };
+    // text running upwards, once rotated by the CTM and once by the text matrix
+    let ops = vec![
+        Op::SaveGraphicsState,
+        Op::SetTransformationMatrix {
+            matrix: CurTransMat::Raw([0.0, 1.0, -1.0, 0.0, 0.0, 0.0]),
+        },
+        Op::StartTextSection,
+        Op::SetTextMatrix {
+            matrix: TextMatrix::Translate(Pt(100.0), Pt(0.0)),
+        },
+        text("Hello"),
+        Op::EndTextSection,
+        Op::RestoreGraphicsState,
+        Op::StartTextSection,
+        // 5pt above the end of "Hello" on the same baseline
+        Op::SetTextMatrix {
+            matrix: TextMatrix::Raw([0.0, 1.0, -1.0, 0.0, 0.0, 130.0]),
+        },
+        text("world"),
+        // one line further left
+        Op::SetTextMatrix {
+            matrix: TextMatrix::Raw([0.0, 1.0, -1.0, 0.0, -15.0, 100.0]),
+        },
+        text("Next"),
+        Op::EndTextSection,
+    ];
+
+    let mut doc = PdfDocument::new("rotated");
+    doc.pages.push(PdfPage::new(Mm(210.0), Mm(297.0), ops));
+    let text = doc.extract_text(&TextExtractionOptions::default());
+    assert_eq!(text, vec!["Hello world\nNext".to_string()]);
+}
@@ -0,0 +1,507 @@
+//! Decoding of stream filters (`/Filter` chains of parsed streams, TIFF compressions)
+//! and the encoders of the filters that are written on save
+
+use std::io::{Read, Write};
+
+use crate::image::{DecodeParms, StreamFilter};
+
+/// Applies the (non-image) filters of the chain in order. Stops at the first image
+/// codec (`DCTDecode`, `JPXDecode`, ...) and returns the remaining filters, which have
+/// to be decoded by an image decoder.
+pub(crate) fn decode_filters<'a>(
+    bytes: &[u8],
+    filters: &'a [StreamFilter],
+) -> Result<(Vec<u8>, &'a [StreamFilter]), String> {
+    decode_filters_limited(bytes, filters, usize::MAX)
+}
+
+/// Like `decode_filters`, but fails if the output of a filter exceeds `max_size` bytes
+/// (the output of the `FlateDecode`, `LZWDecode` and `RunLengthDecode` filters is
+/// never allocated beyond the limit)
+pub(crate) fn decode_filters_limited<'a>(
+    bytes: &[u8],
+    filters: &'a [StreamFilter],
+    max_size: usize,
+) -> Result<(Vec<u8>, &'a [StreamFilter]), String> {
+    let mut data = bytes.to_vec();
+    for (i, filter) in filters.iter().enumerate() {
+        data = match filter.name.as_str() {
+            "FlateDecode" | "Fl" => {
+                apply_predictor(flate_decode_limited(&data, max_size)?, &filter.parms)?
+            }
+            "ASCIIHexDecode" | "AHx" => ascii_hex_decode(&data)?,
+            "ASCII85Decode" | "A85" => ascii85_decode(&data)?,
+            "LZWDecode" | "LZW" => {
+                let early_change = filter.parms.early_change.unwrap_or(1) != 0;
+                apply_predictor(
+                    lzw_decode_limited(&data, early_change, max_size)?,
+                    &filter.parms,
+                )?
+            }
+            "RunLengthDecode" | "RL" => run_length_decode_limited(&data, true, max_size),
+            _ => return Ok((data, &filters[i..])),
+        };
+        if data.len() > max_size {
+            return Err(format!(
+                "{}: decoded stream exceeds the limit of {max_size} bytes",
+                filter.name
+            ));
+        }
+    }
+    Ok((data, &[]))
+}
+
+pub(crate) fn flate_decode(data: &[u8]) -> Result<Vec<u8>, String> {
+    flate_decode_limited(data, usize::MAX)
+}
+
+/// Decodes at most `max_size + 1` bytes, so that exceeding the limit can be detected
+fn flate_decode_limited(data: &[u8], max_size: usize) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let limit = (max_size as u64).saturating_add(1);
+    let mut decoder = flate2::read::ZlibDecoder::new(data).take(limit);
+    match decoder.read_to_end(&mut out) {
+        Ok(_) => Ok(out),
+        // truncated streams are common, keep what could be decoded
+        Err(_) if !out.is_empty() => Ok(out),
+        Err(e) => Err(format!("FlateDecode: {e}")),
+    }
+}
+
+/// Reverses the TIFF (2) or PNG (10 - 15) predictor of the `/DecodeParms`
+pub(crate) fn apply_predictor(data: Vec<u8>, parms: &DecodeParms) -> Result<Vec<u8>, String> {
+    let predictor = parms.predictor.unwrap_or(1);
+    if predictor <= 1 {
+        return Ok(data);
+    }
+
+    let colors = parms.colors.unwrap_or(1).max(1) as usize;
+    let bpc = parms.bits_per_component.unwrap_or(8).max(1) as usize;
+    let columns = parms.columns.unwrap_or(1).max(1) as usize;
+    // bytes per pixel (at least 1) and per row
+    let bpp = (colors * bpc).div_ceil(8);
+    let row_len = (colors * bpc * columns).div_ceil(8);
+
+    match predictor {
+        2 => Ok(tiff_predictor(data, colors, bpc, row_len)),
+        10..=15 => png_predictor(&data, bpp, row_len),
+        p => Err(format!("unknown predictor {p}")),
+    }
+}
+
+/// TIFF predictor 2: every sample is stored as the difference to the sample on its left
+fn tiff_predictor(mut data: Vec<u8>, colors: usize, bpc: usize, row_len: usize) -> Vec<u8> {
+    match bpc {
+        8 => {
+            for row in data.chunks_mut(row_len) {
+                for i in colors..row.len() {
+                    row[i] = row[i].wrapping_add(row[i - colors]);
+                }
+            }
+        }
+        16 => {
+            for row in data.chunks_mut(row_len) {
+                for i in (colors * 2..row.len().saturating_sub(1)).step_by(2) {
+                    let left = u16::from_be_bytes([row[i - colors * 2], row[i - colors * 2 + 1]]);
+                    let v = u16::from_be_bytes([row[i], row[i + 1]]).wrapping_add(left);
+                    row[i..i + 2].copy_from_slice(&v.to_be_bytes());
+                }
+            }
+        }
+        // 1, 2 and 4 bit samples
+        _ => {
+            let mask = (1u16 << bpc) - 1;
+            for row in data.chunks_mut(row_len) {
+                let samples = row.len() * 8 / bpc;
+                let get = |row: &[u8], i: usize| {
+                    let bit = i * bpc;
+                    (row[bit / 8] as u16 >> (8 - bpc - bit % 8)) & mask
+                };
+                for i in colors..samples {
+                    let v = (get(row, i) + get(row, i - colors)) & mask;
+                    let bit = i * bpc;
+                    let shift = 8 - bpc - bit % 8;
+                    row[bit / 8] = (row[bit / 8] & !((mask as u8) << shift)) | ((v as u8) << shift);
+                }
+            }
+        }
+    }
+    data
+}
+
+/// PNG predictors: every row starts with a filter type byte (the predictor value
+/// of the `/DecodeParms` only signals that the PNG filters are used)
+fn png_predictor(data: &[u8], bpp: usize, row_len: usize) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(data.len() / (row_len + 1) * row_len);
+    let mut prev = vec![0u8; row_len];
+
+    for chunk in data.chunks(row_len + 1) {
+        let (filter, encoded) = chunk.split_first().ok_or("empty PNG predictor row")?;
+        // a truncated last row is zero-padded
+        let mut row = encoded.to_vec();
+        row.resize(row_len, 0);
+
+        for i in 0..row_len {
+            let left = if i >= bpp { row[i - bpp] } else { 0 };
+            let up = prev[i];
+            let up_left = if i >= bpp { prev[i - bpp] } else { 0 };
+            row[i] = row[i].wrapping_add(match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                f => return Err(format!("invalid PNG filter type {f}")),
+            });
+        }
+
+        out.extend_from_slice(&row);
+        prev = row;
+    }
+    Ok(out)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+fn ascii_hex_decode(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut digits = Vec::new();
+    for b in data {
+        match b {
+            b'>' => break,
+            b if b.is_ascii_whitespace() => {}
+            b if b.is_ascii_hexdigit() => digits.push((*b as char).to_digit(16).unwrap_or(0) as u8),
+            b => {
+                return Err(format!(
+                    "ASCIIHexDecode: invalid character {:?}",
+                    *b as char
+                ))
+            }
+        }
+    }
+    // an odd number of digits is padded with a 0
+    Ok(digits
+        .chunks(2)
+        .map(|c| (c[0] << 4) | c.get(1).copied().unwrap_or(0))
+        .collect())
+}
+
+fn ascii85_decode(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let mut group = Vec::with_capacity(5);
+    let data = data.strip_prefix(b"<~").unwrap_or(data);
+
+    for b in data {
+        match b {
+            b'~' => break,
+            b'z' if group.is_empty() => out.extend_from_slice(&[0; 4]),
+            b'!'..=b'u' => {
+                group.push(b - b'!');
+                if group.len() == 5 {
+                    let value = group.iter().fold(0u64, |v, d| v * 85 + *d as u64);
+                    if value > u32::MAX as u64 {
+                        return Err("ASCII85Decode: group out of range".to_string());
+                    }
+                    out.extend_from_slice(&(value as u32).to_be_bytes());
+                    group.clear();
+                }
+            }
+            b if b.is_ascii_whitespace() => {}
+            b => return Err(format!("ASCII85Decode: invalid character {:?}", *b as char)),
+        }
+    }
+
+    // a final partial group of n digits is padded with 'u' and gives n - 1 bytes
+    if group.len() > 1 {
+        let n = group.len();
+        group.resize(5, b'u' - b'!');
+        let value = group.iter().fold(0u64, |v, d| v * 85 + *d as u64);
+        out.extend_from_slice(&(value as u32).to_be_bytes()[..n - 1]);
+    }
+    Ok(out)
+}
+
+/// LZW decoding (PDF `/LZWDecode` and TIFF compression 5), with variable code
+/// lengths from 9 to 12 bits. `early_change` switches to the next code length
+/// one code early (the default of PDF and TIFF).
+pub(crate) fn lzw_decode(data: &[u8], early_change: bool) -> Result<Vec<u8>, String> {
+    lzw_decode_limited(data, early_change, usize::MAX)
+}
+
+/// Stops once more than `max_size` bytes are decoded, so that exceeding the limit
+/// can be detected
+fn lzw_decode_limited(data: &[u8], early_change: bool, max_size: usize) -> Result<Vec<u8>, String> {
+    const CLEAR: usize = 256;
+    const EOD: usize = 257;
+
+    let initial_table = || {
+        let mut table = (0..=255u8).map(|b| vec![b]).collect::<Vec<_>>();
+        // placeholders for the clear and EOD codes
+        table.push(Vec::new());
+        table.push(Vec::new());
+        table
+    };
+
+    let mut out = Vec::new();
+    let mut table = initial_table();
+    let mut prev: Option<usize> = None;
+    let mut code_len = 9;
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+
+    for byte in data {
+        bits = (bits << 8) | *byte as u32;
+        bit_count += 8;
+        while bit_count >= code_len {
+            bit_count -= code_len;
+            let code = ((bits >> bit_count) & ((1 << code_len) - 1)) as usize;
+            bits &= (1 << bit_count) - 1;
+
+            match code {
+                CLEAR => {
+                    table = initial_table();
+                    prev = None;
+                    code_len = 9;
+                    continue;
+                }
+                EOD => return Ok(out),
+                _ => {}
+            }
+
+            let entry = match (table.get(code), prev) {
+                (Some(entry), _) => entry.clone(),
+                // the code that is about to be added: previous entry + its first byte
+                (None, Some(p)) if code == table.len() => {
+                    let mut entry = table[p].clone();
+                    entry.push(table[p][0]);
+                    entry
+                }
+                _ => return Err(format!("LZWDecode: invalid code {code}")),
+            };
+            out.extend_from_slice(&entry);
+            if out.len() > max_size {
+                return Ok(out);
+            }
+
+            if let Some(p) = prev {
+                if table.len() < 4096 {
+                    let mut new_entry = table[p].clone();
+                    new_entry.push(entry[0]);
+                    table.push(new_entry);
+                }
+            }
+            prev = Some(code);
+
+            code_len = match table.len() + early_change as usize {
+                n if n >= 2048 => 12,
+                n if n >= 1024 => 11,
+                n if n >= 512 => 10,
+                _ => 9,
+            };
+        }
+    }
+    Ok(out)
+}
+
+/// Deflates the bytes for `/FlateDecode` (`level` from 0 = stored to 9 = smallest)
+pub(crate) fn flate_encode(data: &[u8], level: u32) -> Vec<u8> {
+    let compression = flate2::Compression::new(level.min(9));
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), compression);
+    let _ = encoder.write_all(data);
+    encoder.finish().unwrap_or_default()
+}
+
+/// LZW encoding for `/LZWDecode` (with early change, the inverse of `lzw_decode`)
+pub(crate) fn lzw_encode(data: &[u8]) -> Vec<u8> {
+    const CLEAR: u32 = 256;
+    const EOD: u32 = 257;
+
+    // the decoder switches to the next code length one code early
+    let code_len = |next_code: u32| match next_code {
+        n if n >= 2048 => 12,
+        n if n >= 1024 => 11,
+        n if n >= 512 => 10,
+        _ => 9,
+    };
+
+    let mut out = Vec::new();
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    let mut push = |code: u32, len: u32| {
+        bits = (bits << len) | code;
+        bit_count += len;
+        while bit_count >= 8 {
+            bit_count -= 8;
+            out.push((bits >> bit_count) as u8);
+        }
+        bits &= (1 << bit_count) - 1;
+    };
+
+    let mut table = std::collections::HashMap::<(u32, u8), u32>::new();
+    let mut next_code = 258;
+    let mut prefix: Option<u32> = None;
+    push(CLEAR, 9);
+
+    for b in data.iter().copied() {
+        let Some(p) = prefix else {
+            prefix = Some(b as u32);
+            continue;
+        };
+        if let Some(code) = table.get(&(p, b)) {
+            prefix = Some(*code);
+            continue;
+        }
+        push(p, code_len(next_code));
+        table.insert((p, b), next_code);
+        next_code += 1;
+        prefix = Some(b as u32);
+        // start over before the table of the decoder is full
+        if next_code >= 4094 {
+            push(CLEAR, code_len(next_code));
+            table.clear();
+            next_code = 258;
+        }
+    }
+
+    if let Some(p) = prefix {
+        push(p, code_len(next_code));
+    }
+    push(EOD, code_len(next_code + 1));
+    // pads the last byte with zeros
+    push(0, 7);
+    out
+}
+
+/// Run length decoding (PDF `/RunLengthDecode`, TIFF PackBits). In PDF, the length
+/// byte 128 marks the end of the data, in TIFF it is skipped (`eod = false`)
+pub(crate) fn run_length_decode(data: &[u8], eod: bool) -> Vec<u8> {
+    run_length_decode_limited(data, eod, usize::MAX)
+}
+
+/// Stops once more than `max_size` bytes are decoded, so that exceeding the limit
+/// can be detected
+fn run_length_decode_limited(data: &[u8], eod: bool, max_size: usize) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    while let Some(&len) = data.get(pos) {
+        if out.len() > max_size {
+            break;
+        }
+        match len {
+            128 if eod => break,
+            128 => pos += 1,
+            0..=127 => {
+                let run = data
+                    .get(pos + 1..pos + 2 + len as usize)
+                    .unwrap_or(&data[pos + 1..]);
+                out.extend_from_slice(run);
+                pos += 2 + len as usize;
+            }
+            _ => {
+                if let Some(b) = data.get(pos + 1) {
+                    out.extend(std::iter::repeat_n(*b, 257 - len as usize));
+                }
+                pos += 2;
+            }
+        }
+    }
+    out
+}
+
+#[test]
+fn test_decode_filters() {
+    assert_eq!(ascii_hex_decode(b"48 65 6c6C6f7>").unwrap(), b"Hellop");
+    assert_eq!(ascii85_decode(b"<~87cURDZ~>").unwrap(), b"Hello");
+    assert_eq!(
+        run_length_decode(&[1, b'a', b'b', 254, b'c', 128], true),
+        b"abccc"
+    );
+
+    // example of the PDF reference: "-----A---B"
+    let lzw = [0x80, 0x0B, 0x60, 0x50, 0x22, 0x0C, 0x0C, 0x85, 0x01];
+    assert_eq!(lzw_decode(&lzw, true).unwrap(), b"-----A---B");
+    assert_eq!(lzw_encode(b"-----A---B"), lzw);
+
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(b"pixels").unwrap();
+    let deflated = encoder.finish().unwrap();
+
+    let filters = [
+        StreamFilter::new("FlateDecode"),
+        StreamFilter::new("DCTDecode"),
+    ];
+    let (data, remaining) = decode_filters(&deflated, &filters).unwrap();
+    assert_eq!(data, b"pixels");
+    assert_eq!(remaining, &filters[1..]);
+    assert!(decode_filters_limited(&deflated, &filters, 3).is_err());
+    assert_eq!(
+        flate_decode(&flate_encode(b"pixels", 9)).unwrap(),
+        b"pixels"
+    );
+
+    // long enough to use all code lengths and to reset the table
+    let text = (0..20_000u32)
+        .map(|i| (i * 7 % 251) as u8 ^ (i / 300) as u8)
+        .collect::<Vec<_>>();
+    assert_eq!(lzw_decode(&lzw_encode(&text), true).unwrap(), text);
+    assert_eq!(lzw_decode(&lzw_encode(b""), true).unwrap(), b"");
+
+    // the decoders stop right after the limit instead of decoding the whole stream
+    let limited = lzw_decode_limited(&lzw_encode(&text), true, 100).unwrap();
+    assert!(limited.len() > 100 && limited.len() < 200);
+    assert!(
+        decode_filters_limited(&lzw_encode(&text), &[StreamFilter::new("LZWDecode")], 100).is_err()
+    );
+    let run = [129, b'x'].repeat(1000);
+    assert_eq!(run_length_decode_limited(&run, true, 300).len(), 384);
+    assert!(decode_filters_limited(&run, &[StreamFilter::new("RunLengthDecode")], 300).is_err());
+}
+
+#[test]
+fn test_predictors() {
+    let parms = |predictor: i64, colors: i64, columns: i64| DecodeParms {
+        predictor: Some(predictor),
+        colors: Some(colors),
+        columns: Some(columns),
+        ..Default::default()
+    };
+
+    // 2 RGB pixels per row: None, Sub / Up, Average / Paeth
+    let png = [
+        0, 10, 20, 30, 40, 50, 60, //
+        1, 1, 2, 3, 1, 1, 1, //
+        2, 5, 5, 5, 5, 5, 5, //
+        3, 0, 0, 0, 0, 0, 0, //
+        4, 1, 1, 1, 0, 0, 0,
+    ];
+    let decoded = apply_predictor(png.to_vec(), &parms(15, 3, 2)).unwrap();
+    assert_eq!(
+        decoded,
+        vec![
+            10, 20, 30, 40, 50, 60, //
+            1, 2, 3, 2, 3, 4, //
+            6, 7, 8, 7, 8, 9, //
+            3, 3, 4, 5, 5, 6, //
+            4, 4, 5, 5, 5, 6,
+        ]
+    );
+
+    // TIFF predictor, 8 bit gray
+    let tiff = apply_predictor(vec![10, 1, 1, 250, 10, 10], &parms(2, 1, 3)).unwrap();
+    assert_eq!(tiff, vec![10, 11, 12, 250, 4, 14]);
+
+    assert!(apply_predictor(vec![9, 0], &parms(12, 1, 1)).is_err());
+}
@@ -1,4 +1,4 @@
-use crate::{FontId, Op, PdfPage};
+use crate::{FontId, Op};
 use allsorts::binary::read::ReadArray;
 use allsorts::tables::loca::LocaOffsets;
 use allsorts::tables::IndexToLocFormat;
@@ -17,6 +17,7 @@ use core::fmt;
 use lopdf::Object::{Array, Integer};
 use std::collections::{btree_map::BTreeMap, BTreeSet};
 use std::rc::Rc;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use std::vec::Vec;
 use time::error::Parse;
 
@@ -149,6 +150,28 @@ impl BuiltinFont {
         }
     }
 
+    /// Parses the PostScript name of a builtin font (i.e. "Times-Roman"), inverse of `get_id`
+    pub fn from_id(id: &str) -> Option<Self> {
+        use self::BuiltinFont::*;
+        match id {
+            "Times-Roman" => Some(TimesRoman),
+            "Times-Bold" => Some(TimesBold),
+            "Times-Italic" => Some(TimesItalic),
+            "Times-BoldItalic" => Some(TimesBoldItalic),
+            "Helvetica" => Some(Helvetica),
+            "Helvetica-Bold" => Some(HelveticaBold),
+            "Helvetica-Oblique" => Some(HelveticaOblique),
+            "Helvetica-BoldOblique" => Some(HelveticaBoldOblique),
+            "Courier" => Some(Courier),
+            "Courier-Oblique" => Some(CourierOblique),
+            "Courier-Bold" => Some(CourierBold),
+            "Courier-BoldOblique" => Some(CourierBoldOblique),
+            "Symbol" => Some(Symbol),
+            "ZapfDingbats" => Some(ZapfDingbats),
+            _ => None,
+        }
+    }
+
     pub fn get_id(&self) -> &'static str {
         use self::BuiltinFont::*;
         match self {
@@ -245,17 +268,17 @@ impl ParsedFont {
     pub(crate) fn get_used_glyph_ids(
         &self,
         font_id: &FontId,
-        pages: &[PdfPage],
+        content: &[&[Op]],
     ) -> BTreeMap<u16, char> {
         enum CharsOrCodepoint {
             Chars(String),
             Cp(Vec<(u16, char)>),
         }
 
-        let chars_or_codepoints = pages
+        let chars_or_codepoints = content
             .iter()
-            .flat_map(|p| {
-                p.ops.iter().filter_map(|s| match s {
+            .flat_map(|ops| {
+                ops.iter().filter_map(|s| match s {
                     Op::WriteText { font, text, .. } => {
                         if font_id == font {
                             Some(CharsOrCodepoint::Chars(text.clone()))
@@ -351,8 +374,16 @@ impl ParsedFont {
         })
     }
 
-    /// Generates a new font file from the used glyph IDs
+    /// Generates a new font file from the used glyph IDs, uses the global `FontCache` if it
+    /// is enabled
     pub fn subset(&self, glyph_ids: &[(u16, char)]) -> Result<SubsetFont, String> {
+        match FontCache::global() {
+            Some(cache) => cache.get_or_subset(self, glyph_ids),
+            None => self.subset_uncached(glyph_ids),
+        }
+    }
+
+    fn subset_uncached(&self, glyph_ids: &[(u16, char)]) -> Result<SubsetFont, String> {
         let glyph_mapping = glyph_ids
             .iter()
             .enumerate()
@@ -537,15 +568,39 @@ struct GlyphOutlineBuilder {
     operations: Vec<GlyphOutlineOperation>,
 }
 
-/*
 impl ttf_parser::OutlineBuilder for GlyphOutlineBuilder {
-    fn move_to(&mut self, x: f32, y: f32) { self.operations.push(GlyphOutlineOperation::MoveTo(OutlineMoveTo { x, y })); }
-    fn line_to(&mut self, x: f32, y: f32) { self.operations.push(GlyphOutlineOperation::LineTo(OutlineLineTo { x, y })); }
-    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) { self.operations.push(GlyphOutlineOperation::QuadraticCurveTo(OutlineQuadTo { ctrl_1_x: x1, ctrl_1_y: y1, end_x: x, end_y: y })); }
-    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) { self.operations.push(GlyphOutlineOperation::CubicCurveTo(OutlineCubicTo { ctrl_1_x: x1, ctrl_1_y: y1, ctrl_2_x: x2, ctrl_2_y: y2, end_x: x, end_y: y })); }
-    fn close(&mut self) { self.operations.push(GlyphOutlineOperation::ClosePath); }
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.operations
+            .push(GlyphOutlineOperation::MoveTo(OutlineMoveTo { x, y }));
+    }
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.operations
+            .push(GlyphOutlineOperation::LineTo(OutlineLineTo { x, y }));
+    }
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.operations
+            .push(GlyphOutlineOperation::QuadraticCurveTo(OutlineQuadTo {
+                ctrl_1_x: x1,
+                ctrl_1_y: y1,
+                end_x: x,
+                end_y: y,
+            }));
+    }
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.operations
+            .push(GlyphOutlineOperation::CubicCurveTo(OutlineCubicTo {
+                ctrl_1_x: x1,
+                ctrl_1_y: y1,
+                ctrl_2_x: x2,
+                ctrl_2_y: y2,
+                end_x: x,
+                end_y: y,
+            }));
+    }
+    fn close(&mut self) {
+        self.operations.push(GlyphOutlineOperation::ClosePath);
+    }
 }
-*/
 
 #[derive(Debug, Clone)]
 #[repr(C)]
@@ -579,8 +634,218 @@ impl OwnedGlyph {
     }
 }
 
+/// Cache of parsed fonts, keyed by a hash of the font bytes, and of font subsets, keyed by
+/// the font hash and the glyph set. Cloning the cache shares the same storage, so one cache
+/// can be used for many `PdfDocument`s, also from several threads (see
+/// `FontCache::get_or_parse` and `FontCache::enable_global`).
+#[derive(Default, Clone)]
+pub struct FontCache {
+    fonts: Arc<RwLock<BTreeMap<(u64, usize), CachedFont>>>,
+    subsets: Arc<RwLock<BTreeMap<SubsetKey, SubsetFont>>>,
+}
+
+impl fmt::Debug for FontCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FontCache")
+            .field("fonts", &self.len())
+            .field("subsets", &self.subset_len())
+            .finish()
+    }
+}
+
+/// (font hash, font length, font index, glyph set)
+type SubsetKey = (u64, usize, usize, Vec<(u16, char)>);
+
+/// `ParsedFont` without the layout tables, which are `Rc`s (and never set by the parser),
+/// so that the cache can be shared between threads
+#[derive(Clone)]
+struct CachedFont {
+    font_metrics: FontMetrics,
+    num_glyphs: u16,
+    hhea_table: HheaTable,
+    hmtx_data: Vec<u8>,
+    vmtx_data: Vec<u8>,
+    maxp_table: MaxpTable,
+    glyph_records_decoded: BTreeMap<u16, OwnedGlyph>,
+    space_width: Option<usize>,
+    cmap_subtable: Option<OwnedCmapSubtable>,
+    original_bytes: Vec<u8>,
+    original_index: usize,
+    /// Input bytes of fonts that were repaired before parsing
+    input_bytes: Option<Vec<u8>>,
+}
+
+impl CachedFont {
+    fn new(font: ParsedFont, input_bytes: &[u8]) -> Self {
+        let input_bytes = (font.original_bytes != input_bytes).then(|| input_bytes.to_vec());
+        Self {
+            font_metrics: font.font_metrics,
+            num_glyphs: font.num_glyphs,
+            hhea_table: font.hhea_table,
+            hmtx_data: font.hmtx_data,
+            vmtx_data: font.vmtx_data,
+            maxp_table: font.maxp_table,
+            glyph_records_decoded: font.glyph_records_decoded,
+            space_width: font.space_width,
+            cmap_subtable: font.cmap_subtable,
+            original_bytes: font.original_bytes,
+            original_index: font.original_index,
+            input_bytes,
+        }
+    }
+
+    fn into_parsed_font(self) -> ParsedFont {
+        ParsedFont {
+            font_metrics: self.font_metrics,
+            num_glyphs: self.num_glyphs,
+            hhea_table: self.hhea_table,
+            hmtx_data: self.hmtx_data,
+            vmtx_data: self.vmtx_data,
+            maxp_table: self.maxp_table,
+            gsub_cache: None,
+            gpos_cache: None,
+            opt_gdef_table: None,
+            glyph_records_decoded: self.glyph_records_decoded,
+            space_width: self.space_width,
+            cmap_subtable: self.cmap_subtable,
+            original_bytes: self.original_bytes,
+            original_index: self.original_index,
+        }
+    }
+}
+
+static GLOBAL_FONT_CACHE: RwLock<Option<FontCache>> = RwLock::new(None);
+
+impl FontCache {
+    /// Creates a new, empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the cached font with the same contents or parses and caches the font
+    pub fn get_or_parse(&self, font_bytes: &[u8], font_index: usize) -> Option<ParsedFont> {
+        let key = (hash_bytes(font_bytes), font_index);
+        if let Some(font) = read(&self.fonts).get(&key) {
+            // guard against hash collisions, repaired fonts are compared to the input
+            let cached_bytes = font.input_bytes.as_ref().unwrap_or(&font.original_bytes);
+            if cached_bytes == font_bytes {
+                return Some(font.clone().into_parsed_font());
+            }
+        }
+        let font = ParsedFont::parse(font_bytes, font_index)?;
+        write(&self.fonts).insert(key, CachedFont::new(font.clone(), font_bytes));
+        Some(font)
+    }
+
+    /// Returns the cached subset of the font for the same glyph set or subsets and caches
+    /// the font, so that batch generation of similar documents only subsets each font once
+    pub fn get_or_subset(
+        &self,
+        font: &ParsedFont,
+        glyph_ids: &[(u16, char)],
+    ) -> Result<SubsetFont, String> {
+        let key = (
+            hash_bytes(&font.original_bytes),
+            font.original_bytes.len(),
+            font.original_index,
+            glyph_ids.to_vec(),
+        );
+        if let Some(subset) = read(&self.subsets).get(&key) {
+            return Ok(subset.clone());
+        }
+        let subset = font.subset_uncached(glyph_ids)?;
+        write(&self.subsets).insert(key, subset.clone());
+        Ok(subset)
+    }
+
+    /// Number of cached fonts
+    pub fn len(&self) -> usize {
+        read(&self.fonts).len()
+    }
+
+    /// Number of cached font subsets
+    pub fn subset_len(&self) -> usize {
+        read(&self.subsets).len()
+    }
+
+    /// Returns whether the cache has neither fonts nor subsets
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0 && self.subset_len() == 0
+    }
+
+    /// Removes all fonts and subsets from the cache
+    pub fn clear(&self) {
+        write(&self.fonts).clear();
+        write(&self.subsets).clear();
+    }
+
+    /// Makes `ParsedFont::from_bytes` use this cache (on all threads)
+    pub fn enable_global(&self) {
+        *write(&GLOBAL_FONT_CACHE) = Some(self.clone());
+    }
+
+    /// Stops `ParsedFont::from_bytes` from using the global cache (on all threads)
+    pub fn disable_global() {
+        *write(&GLOBAL_FONT_CACHE) = None;
+    }
+
+    /// Returns the cache used by `ParsedFont::from_bytes`, if enabled
+    pub fn global() -> Option<Self> {
+        read(&GLOBAL_FONT_CACHE).clone()
+    }
+}
+
+// a panic while the cache was locked leaves the maps intact, so poisoning is ignored
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn hash_bytes(bytes: &[u8]) -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
 impl ParsedFont {
+    /// Parses a font, uses the global `FontCache` if it is enabled
     pub fn from_bytes(font_bytes: &[u8], font_index: usize) -> Option<Self> {
+        match FontCache::global() {
+            Some(cache) => cache.get_or_parse(font_bytes, font_index),
+            None => Self::parse(font_bytes, font_index),
+        }
+    }
+
+    /// Repairs the font with `sanitize_font` before parsing, for fonts that parse but have
+    /// broken metrics or glyphs (i.e. fonts extracted from PDF files). Not cached.
+    pub fn from_bytes_repaired(font_bytes: &[u8], font_index: usize) -> Option<Self> {
+        match crate::font_sanitize::sanitize_font(font_bytes, font_index) {
+            Ok(sanitized) if !sanitized.repairs.is_empty() => {
+                trace_debug!(repairs = ?sanitized.repairs, "repaired font");
+                Self::parse_unrepaired(&sanitized.bytes, 0)
+            }
+            _ => Self::parse(font_bytes, font_index),
+        }
+    }
+
+    /// Parses the font as-is, only fonts that fail to parse are repaired and parsed again
+    fn parse(font_bytes: &[u8], font_index: usize) -> Option<Self> {
+        if let Some(font) = Self::parse_unrepaired(font_bytes, font_index) {
+            return Some(font);
+        }
+        let sanitized = crate::font_sanitize::sanitize_font(font_bytes, font_index).ok()?;
+        if sanitized.repairs.is_empty() {
+            return None;
+        }
+        trace_debug!(repairs = ?sanitized.repairs, "repaired font");
+        Self::parse_unrepaired(&sanitized.bytes, 0)
+    }
+
+    fn parse_unrepaired(font_bytes: &[u8], font_index: usize) -> Option<Self> {
         use allsorts::tag;
 
         let scope = ReadScope::new(font_bytes);
@@ -758,17 +1023,57 @@ impl ParsedFont {
         self.glyph_records_decoded
             .get(&glyph_index)
             .map(|gi| gi.horz_advance)
+            // CFF fonts have no glyf table, read the advance from the hmtx table
+            .or_else(|| {
+                allsorts::glyph_info::advance(
+                    &self.maxp_table,
+                    &self.hhea_table,
+                    &self.hmtx_data,
+                    glyph_index,
+                )
+                .ok()
+            })
             .unwrap_or_default()
     }
 
     // get the x and y size of a glyph (unscaled units)
     pub fn get_glyph_size(&self, glyph_index: u16) -> Option<(i32, i32)> {
-        let g = self.glyph_records_decoded.get(&glyph_index)?;
-        let glyph_width = g.bounding_box.max_x as i32 - g.bounding_box.min_x as i32; // width
-        let glyph_height = g.bounding_box.max_y as i32 - g.bounding_box.min_y as i32; // height
+        let (min_x, min_y, max_x, max_y) = match self.glyph_records_decoded.get(&glyph_index) {
+            Some(g) => (
+                g.bounding_box.min_x,
+                g.bounding_box.min_y,
+                g.bounding_box.max_x,
+                g.bounding_box.max_y,
+            ),
+            None => {
+                let face =
+                    ttf_parser::Face::parse(&self.original_bytes, self.original_index as u32)
+                        .ok()?;
+                let bbox = face.glyph_bounding_box(ttf_parser::GlyphId(glyph_index))?;
+                (bbox.x_min, bbox.y_min, bbox.x_max, bbox.y_max)
+            }
+        };
+        let glyph_width = max_x as i32 - min_x as i32; // width
+        let glyph_height = max_y as i32 - min_y as i32; // height
         Some((glyph_width, glyph_height))
     }
 
+    /// Returns the outline of a glyph (unscaled units, unhinted), `None` for glyphs
+    /// without an outline (i.e. spaces or bitmap glyphs)
+    ///
+    /// Handles simple and composite TrueType glyphs as well as CFF / CFF2 charstrings.
+    pub fn get_glyph_outline(&self, glyph_index: u16) -> Option<GlyphOutline> {
+        let face =
+            ttf_parser::Face::parse(&self.original_bytes, self.original_index as u32).ok()?;
+        let mut builder = GlyphOutlineBuilder {
+            operations: Vec::new(),
+        };
+        face.outline_glyph(ttf_parser::GlyphId(glyph_index), &mut builder)?;
+        Some(GlyphOutline {
+            operations: builder.operations,
+        })
+    }
+
     pub fn lookup_glyph_index(&self, c: u32) -> Option<u16> {
         match self.cmap_subtable.as_ref()?.map_glyph(c) {
             Ok(Some(c)) => Some(c),
@@ -1269,3 +1574,76 @@ impl FontMetrics {
             .map(|s| s as f32 / self.units_per_em as f32 * target_font_size)
     }
 }
+
+#[test]
+fn test_composite_glyph_outline() {
+    let font = ParsedFont::from_bytes(
+        include_bytes!("../examples/assets/fonts/RobotoMedium.ttf"),
+        0,
+    )
+    .unwrap();
+    let contours = |c: char| {
+        let glyph = font.lookup_glyph_index(c as u32).unwrap();
+        font.get_glyph_outline(glyph)
+            .unwrap()
+            .operations
+            .iter()
+            .filter(|op| matches!(op, GlyphOutlineOperation::MoveTo(_)))
+            .count()
+    };
+    // "Ä" is a composite glyph of "A" and the dieresis
+    assert!(contours('A') > 0);
+    assert!(contours('\u{00C4}') > contours('A'));
+    assert!(font
+        .get_glyph_outline(font.lookup_glyph_index(' ' as u32).unwrap())
+        .is_none());
+}
+
+#[test]
+fn test_font_cache() {
+    let bytes = include_bytes!("../examples/assets/fonts/RobotoMedium.ttf");
+    let cache = FontCache::new();
+    let a = cache.get_or_parse(bytes, 0).unwrap();
+    let b = cache.get_or_parse(bytes, 0).unwrap();
+    assert_eq!(a, b);
+    assert_eq!(cache.len(), 1);
+
+    cache.enable_global();
+    // the global cache is shared by all threads
+    std::thread::spawn(|| {
+        let global = FontCache::global().unwrap();
+        ParsedFont::from_bytes(
+            include_bytes!("../examples/assets/fonts/RobotoMedium.ttf"),
+            0,
+        )
+        .unwrap();
+        assert!(!global.is_empty());
+    })
+    .join()
+    .unwrap();
+    assert!(cache.len() >= 1);
+    FontCache::disable_global();
+    assert!(FontCache::global().is_none());
+}
+
+#[test]
+fn test_subset_cache() {
+    let bytes = include_bytes!("../examples/assets/fonts/RobotoMedium.ttf");
+    let cache = FontCache::new();
+    let font = cache.get_or_parse(bytes, 0).unwrap();
+    let glyphs = ['a', 'b']
+        .iter()
+        .filter_map(|c| Some((font.lookup_glyph_index(*c as u32)?, *c)))
+        .collect::<Vec<_>>();
+
+    let a = cache.get_or_subset(&font, &glyphs).unwrap();
+    let b = cache.get_or_subset(&font, &glyphs).unwrap();
+    assert_eq!(a.bytes, b.bytes);
+    assert_eq!(cache.subset_len(), 1);
+
+    cache.get_or_subset(&font, &glyphs[..1]).unwrap();
+    assert_eq!(cache.subset_len(), 2);
+    cache.clear();
+    assert_eq!(cache.subset_len(), 0);
+    assert!(cache.is_empty());
+}
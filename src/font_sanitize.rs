@@ -0,0 +1,460 @@
+//! Repair pass for malformed TrueType / OpenType fonts (i.e. fonts extracted from real-world
+//! PDFs): tables outside of the file are dropped, inconsistent metrics are clamped, broken
+//! glyphs are emptied and the checksums are recomputed
+
+use std::collections::BTreeMap;
+
+/// Font file rebuilt by `sanitize_font`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SanitizedFont {
+    /// Rebuilt font (a single font, also if the input was a font collection)
+    pub bytes: Vec<u8>,
+    /// Description of each repair, empty if the font was valid
+    pub repairs: Vec<String>,
+}
+
+type Tables = BTreeMap<[u8; 4], Vec<u8>>;
+
+const VERSION_TRUETYPE: u32 = 0x0001_0000;
+const VERSION_APPLE: u32 = 0x7472_7565; // "true"
+const VERSION_CFF: u32 = 0x4F54_544F; // "OTTO"
+const HEAD_MAGIC: u32 = 0x5F0F_3CF5;
+
+/// Checks and repairs the font at `font_index` of the file. Fails if the font can't
+/// be repaired (unknown format, required tables missing or too short).
+pub fn sanitize_font(bytes: &[u8], font_index: usize) -> Result<SanitizedFont, String> {
+    let mut repairs = Vec::new();
+    let offset = get_font_offset(bytes, font_index)?;
+    let version = read_u32(bytes, offset).ok_or("font file too short")?;
+    if !matches!(version, VERSION_TRUETYPE | VERSION_APPLE | VERSION_CFF) {
+        return Err(format!("unknown font version {version:#010x}"));
+    }
+    let num_tables = read_u16(bytes, offset + 4).ok_or("font file too short")? as usize;
+
+    let mut tables = Tables::new();
+    for i in 0..num_tables {
+        let record = offset + 12 + i * 16;
+        let Some(entry) = bytes.get(record..record + 16) else {
+            repairs.push(format!(
+                "truncated table directory ({i} of {num_tables} tables)"
+            ));
+            break;
+        };
+        let tag = [entry[0], entry[1], entry[2], entry[3]];
+        let name = tag_name(&tag);
+        let checksum = read_u32(entry, 4).unwrap_or_default();
+        let start = read_u32(entry, 8).unwrap_or_default() as usize;
+        let length = read_u32(entry, 12).unwrap_or_default() as usize;
+        let Some(data) = start
+            .checked_add(length)
+            .and_then(|end| bytes.get(start..end))
+        else {
+            repairs.push(format!("dropped table '{name}' (outside of the file)"));
+            continue;
+        };
+        if data.is_empty() {
+            repairs.push(format!("dropped empty table '{name}'"));
+            continue;
+        }
+        if tables.contains_key(&tag) {
+            repairs.push(format!("dropped duplicate table '{name}'"));
+            continue;
+        }
+        if checksum != get_table_checksum(&tag, data) {
+            repairs.push(format!("fixed checksum of table '{name}'"));
+        }
+        tables.insert(tag, data.to_vec());
+    }
+
+    for tag in [b"cmap", b"head", b"hhea", b"hmtx", b"maxp"] {
+        if !tables.contains_key(tag) {
+            return Err(format!("missing required table '{}'", tag_name(tag)));
+        }
+    }
+
+    let num_glyphs = repair_maxp(&tables)?;
+    repair_head(&mut tables, &mut repairs)?;
+    repair_hhea(&mut tables, num_glyphs, &mut repairs)?;
+    if !tables.contains_key(b"CFF ") && !tables.contains_key(b"CFF2") {
+        repair_glyf(&mut tables, num_glyphs, &mut repairs)?;
+    }
+    repair_os2(&mut tables, &mut repairs);
+
+    Ok(SanitizedFont {
+        bytes: write_font(version, &tables),
+        repairs,
+    })
+}
+
+/// Offset of the table directory, selects the font of a collection (`ttcf`)
+fn get_font_offset(bytes: &[u8], font_index: usize) -> Result<usize, String> {
+    if bytes.get(0..4) != Some(b"ttcf") {
+        return Ok(0);
+    }
+    let num_fonts = read_u32(bytes, 8).ok_or("font collection too short")? as usize;
+    if font_index >= num_fonts {
+        return Err(format!(
+            "font index {font_index} out of range (collection has {num_fonts} fonts)"
+        ));
+    }
+    read_u32(bytes, 12 + font_index * 4)
+        .map(|o| o as usize)
+        .ok_or_else(|| "font collection too short".to_string())
+}
+
+fn repair_maxp(tables: &Tables) -> Result<usize, String> {
+    let maxp = &tables[b"maxp"];
+    match read_u16(maxp, 4) {
+        None => Err("maxp table too short".to_string()),
+        Some(0) => Err("font has no glyphs".to_string()),
+        Some(n) => Ok(n as usize),
+    }
+}
+
+fn repair_head(tables: &mut Tables, repairs: &mut Vec<String>) -> Result<(), String> {
+    let head = tables.get_mut(b"head").ok_or("missing head table")?;
+    if head.len() < 54 {
+        return Err(format!("head table too short ({} bytes)", head.len()));
+    }
+    if read_u32(head, 12) != Some(HEAD_MAGIC) {
+        write_u32(head, 12, HEAD_MAGIC);
+        repairs.push("fixed magic number of the head table".to_string());
+    }
+    let units_per_em = read_u16(head, 18).unwrap_or_default();
+    if !(16..=16384).contains(&units_per_em) {
+        let fixed = if units_per_em == 0 {
+            1000
+        } else {
+            units_per_em.clamp(16, 16384)
+        };
+        write_u16(head, 18, fixed);
+        repairs.push(format!("clamped unitsPerEm from {units_per_em} to {fixed}"));
+    }
+    // bounding box: xMin, yMin, xMax, yMax
+    for (min, max) in [(36, 40), (38, 42)] {
+        let (a, b) = (read_i16(head, min), read_i16(head, max));
+        if a > b {
+            write_i16(head, min, b);
+            write_i16(head, max, a);
+            repairs.push("fixed inverted bounding box of the head table".to_string());
+        }
+    }
+    Ok(())
+}
+
+fn repair_hhea(
+    tables: &mut Tables,
+    num_glyphs: usize,
+    repairs: &mut Vec<String>,
+) -> Result<(), String> {
+    let hhea = tables.get_mut(b"hhea").ok_or("missing hhea table")?;
+    if hhea.len() < 36 {
+        return Err(format!("hhea table too short ({} bytes)", hhea.len()));
+    }
+    let (ascender, descender) = (read_i16(hhea, 4), read_i16(hhea, 6));
+    if ascender < descender {
+        write_i16(hhea, 4, descender);
+        write_i16(hhea, 6, ascender);
+        repairs.push("swapped ascender and descender".to_string());
+    }
+    if read_i16(hhea, 8) < 0 {
+        write_i16(hhea, 8, 0);
+        repairs.push("clamped negative line gap".to_string());
+    }
+
+    // the hmtx table has `numberOfHMetrics` (advance, lsb) pairs and one lsb for each
+    // of the remaining glyphs
+    let hmtx_len = tables[b"hmtx"].len();
+    let stored = read_u16(&tables[b"hhea"], 34).unwrap_or_default() as usize;
+    let num_h_metrics = stored.min(num_glyphs).min(hmtx_len / 4);
+    if num_h_metrics == 0 {
+        return Err("hmtx table has no metrics".to_string());
+    }
+    if num_h_metrics != stored {
+        write_u16(tables.get_mut(b"hhea").unwrap(), 34, num_h_metrics as u16);
+        repairs.push(format!(
+            "clamped numberOfHMetrics from {stored} to {num_h_metrics}"
+        ));
+    }
+    let required = num_h_metrics * 4 + (num_glyphs - num_h_metrics) * 2;
+    if hmtx_len < required {
+        tables.get_mut(b"hmtx").unwrap().resize(required, 0);
+        repairs.push(format!(
+            "padded hmtx table from {hmtx_len} to {required} bytes"
+        ));
+    }
+
+    let hmtx = &tables[b"hmtx"];
+    let max_advance = (0..num_h_metrics)
+        .filter_map(|i| read_u16(hmtx, i * 4))
+        .max()
+        .unwrap_or_default();
+    let hhea = tables.get_mut(b"hhea").unwrap();
+    if read_u16(hhea, 10) < Some(max_advance) {
+        write_u16(hhea, 10, max_advance);
+        repairs.push(format!("fixed advanceWidthMax to {max_advance}"));
+    }
+    Ok(())
+}
+
+/// Rebuilds the `glyf` and `loca` tables: offsets are clamped to the glyph data and
+/// glyphs with a broken header are replaced by empty glyphs
+fn repair_glyf(
+    tables: &mut Tables,
+    num_glyphs: usize,
+    repairs: &mut Vec<String>,
+) -> Result<(), String> {
+    let (Some(glyf), Some(loca)) = (tables.get(b"glyf"), tables.get(b"loca")) else {
+        return Err("missing glyf or loca table".to_string());
+    };
+    let num_repairs = repairs.len();
+    let stored_format = read_i16(&tables[b"head"], 50);
+    let long = match stored_format {
+        0 => false,
+        1 => true,
+        _ => {
+            // guess the format from the size of the table
+            let long = loca.len() >= (num_glyphs + 1) * 4;
+            repairs.push(format!("fixed invalid indexToLocFormat {stored_format}"));
+            long
+        }
+    };
+    let offsets = (0..=num_glyphs)
+        .map_while(|i| match long {
+            true => read_u32(loca, i * 4).map(|o| o as usize),
+            false => read_u16(loca, i * 2).map(|o| o as usize * 2),
+        })
+        .collect::<Vec<_>>();
+    if offsets.len() < num_glyphs + 1 {
+        repairs.push(format!(
+            "loca table has {} of {} offsets",
+            offsets.len(),
+            num_glyphs + 1
+        ));
+    }
+
+    let mut new_glyf = Vec::with_capacity(glyf.len());
+    let mut new_offsets = vec![0];
+    let mut broken = 0;
+    for i in 0..num_glyphs {
+        let start = offsets.get(i).copied().unwrap_or(0);
+        let end = offsets.get(i + 1).copied().unwrap_or(start);
+        match glyf.get(start..end) {
+            Some(data) if data.is_empty() || is_valid_glyph(data) => {
+                new_glyf.extend_from_slice(data);
+            }
+            _ => broken += 1,
+        }
+        // the short format can only address even offsets
+        if !long && new_glyf.len() % 2 != 0 {
+            new_glyf.push(0);
+        }
+        new_offsets.push(new_glyf.len());
+    }
+    if broken > 0 {
+        repairs.push(format!("emptied {broken} broken glyphs"));
+    }
+
+    // the short format stores offsets / 2 in 16 bits
+    let long = long || new_glyf.len() / 2 > u16::MAX as usize;
+    let new_loca = new_offsets
+        .iter()
+        .flat_map(|o| match long {
+            true => (*o as u32).to_be_bytes().to_vec(),
+            false => ((*o / 2) as u16).to_be_bytes().to_vec(),
+        })
+        .collect::<Vec<_>>();
+    if repairs.len() == num_repairs && offsets != new_offsets {
+        repairs.push("fixed glyph offsets of the loca table".to_string());
+    }
+    write_i16(tables.get_mut(b"head").unwrap(), 50, long as i16);
+    tables.insert(*b"glyf", new_glyf);
+    tables.insert(*b"loca", new_loca);
+    Ok(())
+}
+
+/// Checks the glyph header (contours and bounding box) and the size of the glyph data
+fn is_valid_glyph(data: &[u8]) -> bool {
+    if data.len() < 10 {
+        return false;
+    }
+    let num_contours = read_i16(data, 0);
+    let (x_min, y_min, x_max, y_max) = (
+        read_i16(data, 2),
+        read_i16(data, 4),
+        read_i16(data, 6),
+        read_i16(data, 8),
+    );
+    if x_min > x_max || y_min > y_max {
+        return false;
+    }
+    match usize::try_from(num_contours) {
+        // simple glyph: end points of the contours and the instruction length
+        Ok(n) => data.len() >= 10 + n * 2 + 2,
+        // composite glyph: at least one component (flags and glyph index)
+        Err(_) => data.len() >= 14,
+    }
+}
+
+fn repair_os2(tables: &mut Tables, repairs: &mut Vec<String>) {
+    let Some(os2) = tables.get_mut(b"OS/2") else {
+        return;
+    };
+    if os2.len() < 78 {
+        tables.remove(b"OS/2");
+        repairs.push("dropped truncated OS/2 table".to_string());
+        return;
+    }
+    let version = read_u16(os2, 0).unwrap_or_default();
+    let required = match version {
+        0 => 78,
+        1 => 86,
+        2..=4 => 96,
+        _ => 100,
+    };
+    if os2.len() < required {
+        let fixed = match os2.len() {
+            96.. => 4,
+            86.. => 1,
+            _ => 0,
+        };
+        write_u16(os2, 0, fixed);
+        repairs.push(format!(
+            "downgraded OS/2 table from version {version} to {fixed}"
+        ));
+    }
+    let weight = read_u16(os2, 4).unwrap_or_default();
+    if !(1..=1000).contains(&weight) {
+        write_u16(os2, 4, 400);
+        repairs.push(format!("clamped usWeightClass {weight} to 400"));
+    }
+    let width = read_u16(os2, 6).unwrap_or_default();
+    if !(1..=9).contains(&width) {
+        write_u16(os2, 6, 5);
+        repairs.push(format!("clamped usWidthClass {width} to 5"));
+    }
+}
+
+/// Writes the table directory and the 4-byte aligned tables, recomputes the checksums
+fn write_font(version: u32, tables: &Tables) -> Vec<u8> {
+    let num_tables = tables.len();
+    let entry_selector = num_tables.max(1).ilog2() as usize;
+    let search_range = (1 << entry_selector) * 16;
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&version.to_be_bytes());
+    out.extend_from_slice(&(num_tables as u16).to_be_bytes());
+    out.extend_from_slice(&(search_range as u16).to_be_bytes());
+    out.extend_from_slice(&(entry_selector as u16).to_be_bytes());
+    out.extend_from_slice(&((num_tables * 16).saturating_sub(search_range) as u16).to_be_bytes());
+
+    let mut offset = 12 + num_tables * 16;
+    let mut head_offset = None;
+    for (tag, data) in tables.iter() {
+        out.extend_from_slice(tag);
+        out.extend_from_slice(&get_table_checksum(tag, data).to_be_bytes());
+        out.extend_from_slice(&(offset as u32).to_be_bytes());
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        if tag == b"head" {
+            head_offset = Some(offset);
+        }
+        offset += data.len().next_multiple_of(4);
+    }
+    for (tag, data) in tables.iter() {
+        let start = out.len();
+        out.extend_from_slice(data);
+        if tag == b"head" {
+            write_u32(&mut out[start..], 8, 0);
+        }
+        out.resize(start + data.len().next_multiple_of(4), 0);
+    }
+
+    if let Some(head_offset) = head_offset {
+        let adjustment = 0xB1B0_AFBA_u32.wrapping_sub(get_checksum(&out));
+        write_u32(&mut out[head_offset..], 8, adjustment);
+    }
+    out
+}
+
+/// Checksum of a table, the `checkSumAdjustment` of the head table is treated as 0
+fn get_table_checksum(tag: &[u8; 4], data: &[u8]) -> u32 {
+    let checksum = get_checksum(data);
+    match (tag, read_u32(data, 8)) {
+        (b"head", Some(adjustment)) => checksum.wrapping_sub(adjustment),
+        _ => checksum,
+    }
+}
+
+/// Sum of the big-endian u32 words (zero-padded)
+fn get_checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+fn tag_name(tag: &[u8; 4]) -> String {
+    String::from_utf8_lossy(tag).trim_end().to_string()
+}
+
+fn read_u16(data: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+fn read_i16(data: &[u8], pos: usize) -> i16 {
+    read_u16(data, pos).unwrap_or_default() as i16
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+fn write_u16(data: &mut [u8], pos: usize, value: u16) {
+    data[pos..pos + 2].copy_from_slice(&value.to_be_bytes());
+}
+
+fn write_i16(data: &mut [u8], pos: usize, value: i16) {
+    write_u16(data, pos, value as u16);
+}
+
+fn write_u32(data: &mut [u8], pos: usize, value: u32) {
+    data[pos..pos + 4].copy_from_slice(&value.to_be_bytes());
+}
+
+#[test]
+fn test_sanitize_font() {
+    let bytes = include_bytes!("../examples/assets/fonts/RobotoMedium.ttf");
+    let valid = sanitize_font(bytes, 0).unwrap();
+    assert!(valid.repairs.is_empty(), "{:?}", valid.repairs);
+
+    // break the head table and the metrics, the repaired font has to be valid again
+    let mut tables = Tables::new();
+    let num_tables = read_u16(bytes, 4).unwrap() as usize;
+    for i in 0..num_tables {
+        let entry = &bytes[12 + i * 16..28 + i * 16];
+        let (start, len) = (read_u32(entry, 8).unwrap(), read_u32(entry, 12).unwrap());
+        let tag = [entry[0], entry[1], entry[2], entry[3]];
+        tables.insert(tag, bytes[start as usize..(start + len) as usize].to_vec());
+    }
+    write_u32(tables.get_mut(b"head").unwrap(), 12, 0);
+    write_u16(tables.get_mut(b"head").unwrap(), 18, 0);
+    write_u16(tables.get_mut(b"hhea").unwrap(), 34, u16::MAX);
+    tables.get_mut(b"hmtx").unwrap().truncate(8);
+    let broken = write_font(VERSION_TRUETYPE, &tables);
+
+    let repaired = sanitize_font(&broken, 0).unwrap();
+    assert!(repaired.repairs.len() >= 4, "{:?}", repaired.repairs);
+    assert!(sanitize_font(&repaired.bytes, 0)
+        .unwrap()
+        .repairs
+        .is_empty());
+    assert!(crate::ParsedFont::from_bytes(&repaired.bytes, 0).is_some());
+    let parsed = crate::ParsedFont::from_bytes_repaired(&broken, 0).unwrap();
+    assert_eq!(parsed.original_bytes, repaired.bytes);
+    // valid fonts are parsed as-is
+    let parsed = crate::ParsedFont::from_bytes_repaired(bytes, 0).unwrap();
+    assert_eq!(parsed.original_bytes, bytes.to_vec());
+
+    assert!(sanitize_font(b"not a font", 0).is_err());
+}
@@ -0,0 +1,836 @@
+//! Interactive forms (AcroForm): form fields and their widget annotations
+
+use lopdf::content::{Content, Operation as LoOp};
+use lopdf::Object::{Array, Dictionary, Integer, Name, Real, Reference, String as LoString};
+use lopdf::StringFormat::{Hexadecimal, Literal};
+use lopdf::{Dictionary as LoDictionary, Stream as LoStream};
+
+use std::collections::BTreeMap;
+
+use crate::{
+    deserialize::collect_page_refs,
+    graphics::{Point, Rect},
+    units::Pt,
+    BuiltinFont, ColorArray, FormFieldMap, SignatureField,
+};
+
+/// Interactive form field, placed on a page with `Op::AddFormField`
+#[derive(Debug, PartialEq, Clone)]
+pub enum FormField {
+    /// Single- or multi-line text input
+    Text(TextField),
+    /// Digital signature (see `PdfDocument::save_signed`)
+    Signature(SignatureField),
+}
+
+impl FormField {
+    /// Returns the (fully qualified) name of the field
+    pub fn get_name(&self) -> &str {
+        match self {
+            FormField::Text(t) => &t.name,
+            FormField::Signature(s) => &s.name,
+        }
+    }
+
+    /// Returns the position of the field widget on the page
+    pub fn get_rect(&self) -> &Rect {
+        match self {
+            FormField::Text(t) => &t.rect,
+            FormField::Signature(s) => &s.rect,
+        }
+    }
+
+    /// Sets the value of a text field. Signature fields can't be filled,
+    /// use `PdfDocument::save_signed` instead.
+    pub fn set_value(&mut self, value: &str) -> Result<(), String> {
+        match self {
+            FormField::Text(t) => {
+                if let Some(max_len) = t.max_len {
+                    if value.chars().count() > max_len {
+                        return Err(format!(
+                            "value for field {:?} exceeds the maximum length of {max_len} characters",
+                            t.name
+                        ));
+                    }
+                }
+                t.value = value.to_string();
+                Ok(())
+            }
+            FormField::Signature(s) => Err(format!(
+                "field {:?} is a signature field and can't be filled",
+                s.name
+            )),
+        }
+    }
+}
+
+/// Form field with the page it is placed on, i.e. a field parsed from an existing document
+#[derive(Debug, PartialEq, Clone)]
+pub struct PageFormField {
+    /// Page of the field widget (0-based)
+    pub page: usize,
+    /// The field itself
+    pub field: FormField,
+}
+
+/// Text input field
+#[derive(Debug, PartialEq, Clone)]
+pub struct TextField {
+    /// Name of the field, must be unique in the document
+    pub name: String,
+    /// Current value of the field
+    pub value: String,
+    /// Position of the widget on the page
+    pub rect: Rect,
+    /// Description shown as a tooltip (and read by screen readers)
+    pub tooltip: Option<String>,
+    /// Font used to display the value
+    pub font: BuiltinFont,
+    /// Font size of the value
+    pub font_size: Pt,
+    /// Color of the text
+    pub text_color: ColorArray,
+    /// Color of the widget border (`None` = no border)
+    pub border_color: Option<ColorArray>,
+    /// Background color of the widget (`None` = transparent)
+    pub background_color: Option<ColorArray>,
+    /// Allow line breaks in the value
+    pub multiline: bool,
+    /// Mask the value (for passwords)
+    pub password: bool,
+    /// The value can't be changed by the user
+    pub read_only: bool,
+    /// The field must have a value before the form is submitted
+    pub required: bool,
+    /// Maximum number of characters
+    pub max_len: Option<usize>,
+}
+
+impl TextField {
+    /// Creates a new, empty text field
+    pub fn new(name: &str, rect: Rect) -> Self {
+        Self {
+            name: name.to_string(),
+            value: String::new(),
+            rect,
+            tooltip: None,
+            font: BuiltinFont::Helvetica,
+            font_size: Pt(10.0),
+            text_color: ColorArray::Gray([0.0]),
+            border_color: Some(ColorArray::Gray([0.0])),
+            background_color: None,
+            multiline: false,
+            password: false,
+            read_only: false,
+            required: false,
+            max_len: None,
+        }
+    }
+
+    /// Sets the initial value of the field
+    pub fn with_value(mut self, value: &str) -> Self {
+        self.value = value.to_string();
+        self
+    }
+
+    /// Sets the tooltip / description of the field
+    pub fn with_tooltip(mut self, tooltip: &str) -> Self {
+        self.tooltip = Some(tooltip.to_string());
+        self
+    }
+
+    /// Sets the font and font size of the value
+    pub fn with_font(mut self, font: BuiltinFont, font_size: Pt) -> Self {
+        self.font = font;
+        self.font_size = font_size;
+        self
+    }
+
+    /// Allows line breaks in the value
+    pub fn with_multiline(mut self, multiline: bool) -> Self {
+        self.multiline = multiline;
+        self
+    }
+
+    /// Marks the field as read-only
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Marks the field as required
+    pub fn with_required(mut self, required: bool) -> Self {
+        self.required = required;
+        self
+    }
+
+    /// Limits the number of characters
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = Some(max_len);
+        self
+    }
+
+    /// Field flags (`/Ff`), PDF reference table 8.70 and 8.77
+    fn get_flags(&self) -> i64 {
+        let mut flags = 0;
+        if self.read_only {
+            flags |= 1 << 0;
+        }
+        if self.required {
+            flags |= 1 << 1;
+        }
+        if self.multiline {
+            flags |= 1 << 12;
+        }
+        if self.password {
+            flags |= 1 << 13;
+        }
+        flags
+    }
+}
+
+/// Returns the fonts that are used by form fields (need to be added to the font resources)
+pub(crate) fn get_form_field_fonts(field: &FormField) -> Option<BuiltinFont> {
+    match field {
+        FormField::Text(t) => Some(t.font),
+        FormField::Signature(_) => None,
+    }
+}
+
+/// Creates the merged field + widget annotation dictionary for a form field.
+/// `font_dict` is the font resource dictionary containing the builtin fonts,
+/// `reserve_signature` adds the placeholder value to signature fields.
+pub(crate) fn form_field_to_dict(
+    field: &FormField,
+    page_id: lopdf::ObjectId,
+    font_dict: lopdf::ObjectId,
+    reserve_signature: bool,
+    doc: &mut lopdf::Document,
+) -> LoDictionary {
+    match field {
+        FormField::Text(t) => text_field_to_dict(t, page_id, font_dict, doc),
+        FormField::Signature(s) => {
+            crate::signature::signature_field_to_dict(s, page_id, reserve_signature, doc)
+        }
+    }
+}
+
+fn text_field_to_dict(
+    t: &TextField,
+    page_id: lopdf::ObjectId,
+    font_dict: lopdf::ObjectId,
+    doc: &mut lopdf::Document,
+) -> LoDictionary {
+    let rect = t.rect.normalize();
+    let ll = rect.lower_left();
+    let ur = rect.upper_right();
+    let da = format!(
+        "/{} {} Tf {}",
+        t.font.get_pdf_id(),
+        t.font_size.0,
+        color_to_da(&t.text_color)
+    );
+
+    let mut dict = LoDictionary::from_iter(vec![
+        ("Type", Name("Annot".into())),
+        ("Subtype", Name("Widget".into())),
+        ("FT", Name("Tx".into())),
+        ("T", text_string(&t.name)),
+        ("V", text_string(&t.value)),
+        (
+            "Rect",
+            Array(vec![Real(ll.x.0), Real(ll.y.0), Real(ur.x.0), Real(ur.y.0)]),
+        ),
+        // print the widget
+        ("F", Integer(4)),
+        ("P", Reference(page_id)),
+        ("DA", LoString(da.into_bytes(), Literal)),
+        ("Ff", Integer(t.get_flags())),
+    ]);
+
+    if let Some(tooltip) = t.tooltip.as_ref() {
+        dict.set("TU", text_string(tooltip));
+    }
+
+    if let Some(max_len) = t.max_len {
+        dict.set("MaxLen", Integer(max_len as i64));
+    }
+
+    let mut mk = LoDictionary::new();
+    if let Some(bc) = t.border_color.as_ref() {
+        mk.set("BC", color_to_array(bc));
+    }
+    if let Some(bg) = t.background_color.as_ref() {
+        mk.set("BG", color_to_array(bg));
+    }
+    if !mk.is_empty() {
+        dict.set("MK", Dictionary(mk));
+    }
+
+    let appearance = doc.add_object(text_field_appearance(t, font_dict));
+    dict.set(
+        "AP",
+        Dictionary(LoDictionary::from_iter(vec![("N", Reference(appearance))])),
+    );
+
+    dict
+}
+
+/// Generates the normal appearance stream of the text field, so that the value
+/// is visible even in viewers that don't regenerate appearances
+fn text_field_appearance(t: &TextField, font_dict: lopdf::ObjectId) -> LoStream {
+    let rect = t.rect.normalize();
+    let w = rect.width.0;
+    let h = rect.height.0;
+    let size = t.font_size.0;
+
+    let mut ops = vec![LoOp::new("BMC", vec![Name("Tx".into())])];
+    ops.push(LoOp::new("q", vec![]));
+
+    if let Some(bg) = t.background_color.as_ref() {
+        ops.extend(color_to_ops(bg, false));
+        ops.push(LoOp::new(
+            "re",
+            vec![Real(0.0), Real(0.0), Real(w), Real(h)],
+        ));
+        ops.push(LoOp::new("f", vec![]));
+    }
+
+    if let Some(bc) = t.border_color.as_ref() {
+        ops.extend(color_to_ops(bc, true));
+        ops.push(LoOp::new("w", vec![Real(1.0)]));
+        ops.push(LoOp::new(
+            "re",
+            vec![Real(0.5), Real(0.5), Real(w - 1.0), Real(h - 1.0)],
+        ));
+        ops.push(LoOp::new("S", vec![]));
+    }
+
+    // clip the text to the inner area of the field
+    ops.push(LoOp::new(
+        "re",
+        vec![Real(1.0), Real(1.0), Real(w - 2.0), Real(h - 2.0)],
+    ));
+    ops.push(LoOp::new("W", vec![]));
+    ops.push(LoOp::new("n", vec![]));
+
+    let value = if t.password {
+        "*".repeat(t.value.chars().count())
+    } else {
+        t.value.clone()
+    };
+
+    if !value.is_empty() {
+        let line_height = size * 1.15;
+        let first_baseline = if t.multiline {
+            h - 2.0 - size
+        } else {
+            (h - size) / 2.0 + size * 0.22
+        };
+
+        ops.push(LoOp::new("BT", vec![]));
+        ops.push(LoOp::new(
+            "Tf",
+            vec![t.font.get_pdf_id().into(), Real(size)],
+        ));
+        ops.extend(color_to_ops(&t.text_color, false));
+        ops.push(LoOp::new("TL", vec![Real(line_height)]));
+        ops.push(LoOp::new("Td", vec![Real(2.0), Real(first_baseline)]));
+
+        let lines = if t.multiline {
+            value.lines().collect::<Vec<_>>()
+        } else {
+            vec![value.as_str()]
+        };
+        for (i, line) in lines.iter().enumerate() {
+            if i > 0 {
+                ops.push(LoOp::new("T*", vec![]));
+            }
+            let bytes = lopdf::Document::encode_text(
+                &lopdf::Encoding::SimpleEncoding("WinAnsiEncoding"),
+                line,
+            );
+            ops.push(LoOp::new("Tj", vec![LoString(bytes, Hexadecimal)]));
+        }
+        ops.push(LoOp::new("ET", vec![]));
+    }
+
+    ops.push(LoOp::new("Q", vec![]));
+    ops.push(LoOp::new("EMC", vec![]));
+
+    let content = Content { operations: ops }.encode().unwrap_or_default();
+
+    LoStream::new(
+        LoDictionary::from_iter(vec![
+            ("Type", Name("XObject".into())),
+            ("Subtype", Name("Form".into())),
+            ("BBox", Array(vec![Real(0.0), Real(0.0), Real(w), Real(h)])),
+            (
+                "Resources",
+                Dictionary(LoDictionary::from_iter(vec![(
+                    "Font",
+                    Reference(font_dict),
+                )])),
+            ),
+        ]),
+        content,
+    )
+}
+
+/// Encodes a text string as PDFDocEncoding (ASCII) or UTF-16BE with BOM
+pub(crate) fn text_string(s: &str) -> lopdf::Object {
+    if s.is_ascii() {
+        LoString(s.as_bytes().to_vec(), Literal)
+    } else {
+        let mut bytes = vec![0xFE, 0xFF];
+        for c in s.encode_utf16() {
+            bytes.extend_from_slice(&c.to_be_bytes());
+        }
+        LoString(bytes, Hexadecimal)
+    }
+}
+
+/// Decodes a text string (UTF-16BE with BOM or PDFDocEncoding, approximated as Latin-1)
+pub(crate) fn decode_text_string(bytes: &[u8]) -> String {
+    match bytes {
+        [0xFE, 0xFF, rest @ ..] => {
+            let units = rest
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .collect::<Vec<_>>();
+            String::from_utf16_lossy(&units)
+        }
+        _ => bytes.iter().map(|b| *b as char).collect(),
+    }
+}
+
+/// Field attributes that are inherited from parent fields (PDF reference table 8.69)
+#[derive(Default, Clone)]
+struct InheritedAttributes {
+    name: String,
+    field_type: Option<Vec<u8>>,
+    flags: i64,
+    default_appearance: Option<String>,
+    value: Option<String>,
+    max_len: Option<usize>,
+}
+
+/// Parses the fields of the `/AcroForm` dictionary. Fields of unsupported types
+/// (buttons, check boxes, choice fields) and fields nested deeper than `max_depth`
+/// levels (reference cycles) are skipped.
+pub(crate) fn parse_acroform(doc: &lopdf::Document, max_depth: usize) -> FormFieldMap {
+    let mut map = FormFieldMap::default();
+
+    let acroform = match doc
+        .catalog()
+        .ok()
+        .and_then(|c| c.get(b"AcroForm").ok())
+        .and_then(|o| resolve(doc, o).as_dict().ok())
+    {
+        Some(s) => s,
+        None => return map,
+    };
+
+    // widget annotation -> page index
+    let mut widget_pages = BTreeMap::new();
+    let mut page_indices = BTreeMap::new();
+    for (page_idx, page_id) in collect_page_refs(doc, max_depth).into_iter().enumerate() {
+        page_indices.insert(page_id, page_idx);
+        let annots = doc
+            .get_object(page_id)
+            .ok()
+            .and_then(|p| p.as_dict().ok())
+            .and_then(|p| p.get(b"Annots").ok())
+            .and_then(|a| resolve(doc, a).as_array().ok());
+        for annot in annots.into_iter().flatten() {
+            if let Ok(id) = annot.as_reference() {
+                widget_pages.insert(id, page_idx);
+            }
+        }
+    }
+
+    let ctx = ParseContext {
+        doc,
+        widget_pages,
+        page_indices,
+        fonts: acroform
+            .get(b"DR")
+            .ok()
+            .and_then(|dr| resolve(doc, dr).as_dict().ok())
+            .and_then(|dr| dr.get(b"Font").ok())
+            .and_then(|f| resolve(doc, f).as_dict().ok()),
+    };
+
+    let inherited = InheritedAttributes {
+        default_appearance: acroform
+            .get(b"DA")
+            .ok()
+            .and_then(|da| resolve(doc, da).as_str().ok())
+            .map(decode_text_string),
+        ..Default::default()
+    };
+
+    let fields = acroform
+        .get(b"Fields")
+        .ok()
+        .and_then(|f| resolve(doc, f).as_array().ok());
+    for field in fields.into_iter().flatten() {
+        parse_field(&ctx, field, &inherited, max_depth, &mut map);
+    }
+
+    map
+}
+
+struct ParseContext<'a> {
+    doc: &'a lopdf::Document,
+    widget_pages: BTreeMap<lopdf::ObjectId, usize>,
+    page_indices: BTreeMap<lopdf::ObjectId, usize>,
+    fonts: Option<&'a LoDictionary>,
+}
+
+fn parse_field(
+    ctx: &ParseContext,
+    field: &lopdf::Object,
+    parent: &InheritedAttributes,
+    max_depth: usize,
+    map: &mut FormFieldMap,
+) {
+    let Some(max_depth) = max_depth.checked_sub(1) else {
+        return;
+    };
+
+    let doc = ctx.doc;
+    let dict = match resolve(doc, field).as_dict() {
+        Ok(o) => o,
+        Err(_) => return,
+    };
+    let get_text = |key: &[u8]| {
+        dict.get(key)
+            .ok()
+            .and_then(|o| resolve(doc, o).as_str().ok())
+            .map(decode_text_string)
+    };
+
+    let mut attrs = parent.clone();
+    if let Some(t) = get_text(b"T") {
+        attrs.name = if attrs.name.is_empty() {
+            t
+        } else {
+            format!("{}.{t}", attrs.name)
+        };
+    }
+    if let Ok(ft) = dict.get(b"FT").and_then(|o| resolve(doc, o).as_name()) {
+        attrs.field_type = Some(ft.to_vec());
+    }
+    if let Ok(ff) = dict.get(b"Ff").and_then(|o| resolve(doc, o).as_i64()) {
+        attrs.flags = ff;
+    }
+    if let Some(da) = get_text(b"DA") {
+        attrs.default_appearance = Some(da);
+    }
+    if let Some(v) = get_text(b"V") {
+        attrs.value = Some(v);
+    }
+    if let Ok(max_len) = dict.get(b"MaxLen").and_then(|o| resolve(doc, o).as_i64()) {
+        attrs.max_len = Some(max_len.max(0) as usize);
+    }
+
+    // kids with a /T are child fields, kids without a /T are the widgets of this field
+    let kids = dict
+        .get(b"Kids")
+        .ok()
+        .and_then(|k| resolve(doc, k).as_array().ok())
+        .map(|k| k.as_slice())
+        .unwrap_or_default();
+    let (child_fields, widgets): (Vec<_>, Vec<_>) = kids.iter().partition(|k| {
+        resolve(doc, k)
+            .as_dict()
+            .map(|d| d.has(b"T"))
+            .unwrap_or(false)
+    });
+
+    if !child_fields.is_empty() {
+        for child in child_fields {
+            parse_field(ctx, child, &attrs, max_depth, map);
+        }
+        return;
+    }
+
+    // terminal field: the first widget determines the position on the page
+    let widget = widgets.first().copied().unwrap_or(field);
+    let widget_dict = resolve(doc, widget).as_dict().unwrap_or(dict);
+    let page = widget_dict
+        .get(b"P")
+        .and_then(|p| p.as_reference())
+        .ok()
+        .and_then(|p| ctx.page_indices.get(&p))
+        .or_else(|| {
+            widget
+                .as_reference()
+                .ok()
+                .and_then(|id| ctx.widget_pages.get(&id))
+        })
+        .copied()
+        .unwrap_or(0);
+    let rect = widget_dict
+        .get(b"Rect")
+        .ok()
+        .and_then(|r| parse_rect(doc, r))
+        .unwrap_or(Rect::from_wh(Pt(0.0), Pt(0.0)));
+
+    let field = match attrs.field_type.as_deref() {
+        Some(b"Tx") => {
+            let mut t = TextField::new(&attrs.name, rect);
+            t.value = attrs.value.clone().unwrap_or_default();
+            t.tooltip = get_text(b"TU");
+            t.read_only = attrs.flags & (1 << 0) != 0;
+            t.required = attrs.flags & (1 << 1) != 0;
+            t.multiline = attrs.flags & (1 << 12) != 0;
+            t.password = attrs.flags & (1 << 13) != 0;
+            t.max_len = attrs.max_len;
+            if let Some(da) = attrs.default_appearance.as_deref() {
+                let appearance = parse_default_appearance(doc, ctx.fonts, da);
+                t.font = appearance.font.unwrap_or(t.font);
+                t.font_size = appearance.font_size.unwrap_or(t.font_size);
+                t.text_color = appearance.color.unwrap_or(t.text_color);
+            }
+            let mk = widget_dict
+                .get(b"MK")
+                .ok()
+                .and_then(|mk| resolve(doc, mk).as_dict().ok());
+            t.border_color = mk
+                .and_then(|mk| mk.get(b"BC").ok())
+                .and_then(|c| parse_color(doc, c));
+            t.background_color = mk
+                .and_then(|mk| mk.get(b"BG").ok())
+                .and_then(|c| parse_color(doc, c));
+            FormField::Text(t)
+        }
+        Some(b"Sig") => FormField::Signature(SignatureField::new(&attrs.name).with_rect(rect)),
+        _ => return,
+    };
+
+    map.map
+        .insert(attrs.name.clone(), PageFormField { page, field });
+}
+
+/// Font, font size and text color of a `/DA` string
+#[derive(Debug, Default)]
+pub(crate) struct DefaultAppearance {
+    pub font: Option<BuiltinFont>,
+    pub font_size: Option<Pt>,
+    pub color: Option<ColorArray>,
+}
+
+/// Parses the font, font size and text color from a `/DA` string (i.e. "/Helv 12 Tf 0 g"),
+/// the font name refers to the `fonts` resource dictionary
+pub(crate) fn parse_default_appearance(
+    doc: &lopdf::Document,
+    fonts: Option<&LoDictionary>,
+    da: &str,
+) -> DefaultAppearance {
+    let mut appearance = DefaultAppearance::default();
+    let tokens = da.split_whitespace().collect::<Vec<_>>();
+    let number = |i: usize| tokens.get(i).and_then(|n| n.parse::<f32>().ok());
+    for (i, token) in tokens.iter().enumerate() {
+        match *token {
+            "Tf" if i >= 2 => {
+                let font_name = tokens[i - 2].trim_start_matches('/');
+                let font = fonts
+                    .and_then(|f| f.get(font_name.as_bytes()).ok())
+                    .and_then(|f| resolve(doc, f).as_dict().ok())
+                    .and_then(|f| f.get(b"BaseFont").ok())
+                    .and_then(|f| f.as_name_str().ok())
+                    .and_then(BuiltinFont::from_id)
+                    .unwrap_or(BuiltinFont::Helvetica);
+                appearance.font = Some(font);
+                if let Some(size) = number(i - 1) {
+                    // a font size of 0 means "auto-size", use the default instead
+                    if size > 0.0 {
+                        appearance.font_size = Some(Pt(size));
+                    }
+                }
+            }
+            "g" if i >= 1 => {
+                if let Some(g) = number(i - 1) {
+                    appearance.color = Some(ColorArray::Gray([g]));
+                }
+            }
+            "rg" if i >= 3 => {
+                if let (Some(r), Some(g), Some(b)) = (number(i - 3), number(i - 2), number(i - 1)) {
+                    appearance.color = Some(ColorArray::RGB([r, g, b]));
+                }
+            }
+            "k" if i >= 4 => {
+                if let (Some(c), Some(m), Some(y), Some(k)) =
+                    (number(i - 4), number(i - 3), number(i - 2), number(i - 1))
+                {
+                    appearance.color = Some(ColorArray::CMYK([c, m, y, k]));
+                }
+            }
+            _ => {}
+        }
+    }
+    appearance
+}
+
+pub(crate) fn parse_rect(doc: &lopdf::Document, obj: &lopdf::Object) -> Option<Rect> {
+    let values = parse_numbers(doc, obj)?;
+    match values.as_slice() {
+        [llx, lly, urx, ury] => Some(Rect::from_corners(
+            Point {
+                x: Pt(*llx),
+                y: Pt(*lly),
+            },
+            Point {
+                x: Pt(*urx),
+                y: Pt(*ury),
+            },
+        )),
+        _ => None,
+    }
+}
+
+pub(crate) fn parse_color(doc: &lopdf::Document, obj: &lopdf::Object) -> Option<ColorArray> {
+    match parse_numbers(doc, obj)?.as_slice() {
+        [g] => Some(ColorArray::Gray([*g])),
+        [r, g, b] => Some(ColorArray::RGB([*r, *g, *b])),
+        [c, m, y, k] => Some(ColorArray::CMYK([*c, *m, *y, *k])),
+        _ => None,
+    }
+}
+
+pub(crate) fn parse_numbers(doc: &lopdf::Document, obj: &lopdf::Object) -> Option<Vec<f32>> {
+    resolve(doc, obj)
+        .as_array()
+        .ok()?
+        .iter()
+        .map(|n| match resolve(doc, n) {
+            Integer(i) => Some(*i as f32),
+            Real(r) => Some(*r),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Follows a reference to the referenced object (or returns the object itself)
+pub(crate) fn resolve<'a>(doc: &'a lopdf::Document, obj: &'a lopdf::Object) -> &'a lopdf::Object {
+    match obj {
+        Reference(id) => doc.get_object(*id).unwrap_or(obj),
+        _ => obj,
+    }
+}
+
+pub(crate) fn color_to_array(c: &ColorArray) -> lopdf::Object {
+    let values = match c {
+        ColorArray::Transparent => Vec::new(),
+        ColorArray::Gray(arr) => arr.to_vec(),
+        ColorArray::RGB(arr) => arr.to_vec(),
+        ColorArray::CMYK(arr) => arr.to_vec(),
+    };
+    Array(values.into_iter().map(Real).collect())
+}
+
+pub(crate) fn color_to_ops(c: &ColorArray, stroke: bool) -> Vec<LoOp> {
+    let (op, values) = match c {
+        ColorArray::Transparent => return Vec::new(),
+        ColorArray::Gray(arr) => (if stroke { "G" } else { "g" }, arr.to_vec()),
+        ColorArray::RGB(arr) => (if stroke { "RG" } else { "rg" }, arr.to_vec()),
+        ColorArray::CMYK(arr) => (if stroke { "K" } else { "k" }, arr.to_vec()),
+    };
+    vec![LoOp::new(op, values.into_iter().map(Real).collect())]
+}
+
+pub(crate) fn color_to_da(c: &ColorArray) -> String {
+    let (op, values) = match c {
+        ColorArray::Transparent => ("g", vec![0.0]),
+        ColorArray::Gray(arr) => ("g", arr.to_vec()),
+        ColorArray::RGB(arr) => ("rg", arr.to_vec()),
+        ColorArray::CMYK(arr) => ("k", arr.to_vec()),
+    };
+    let values = values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+    format!("{} {op}", values.join(" "))
+}
+
+#[test]
+fn test_text_field_dict() {
+    let field = FormField::Text(
+        TextField::new(
+            "invoice.total",
+            Rect {
+                x: Pt(100.0),
+                y: Pt(200.0),
+                width: Pt(150.0),
+                height: Pt(20.0),
+            },
+        )
+        .with_value("12.50")
+        .with_required(true)
+        .with_multiline(true),
+    );
+
+    let mut doc = lopdf::Document::with_version("1.3");
+    let page_id = doc.new_object_id();
+    let font_dict = doc.new_object_id();
+    let dict = form_field_to_dict(&field, page_id, font_dict, false, &mut doc);
+
+    assert_eq!(dict.get(b"FT").unwrap(), &Name("Tx".into()));
+    assert_eq!(
+        dict.get(b"T").unwrap(),
+        &LoString("invoice.total".into(), Literal)
+    );
+    assert_eq!(dict.get(b"Ff").unwrap(), &Integer(2 | 4096));
+    assert_eq!(
+        dict.get(b"Rect").unwrap(),
+        &Array(vec![Real(100.0), Real(200.0), Real(250.0), Real(220.0)])
+    );
+    assert!(dict.get(b"AP").is_ok());
+}
+
+#[test]
+fn test_parse_and_fill_acroform() {
+    let field = FormField::Text(
+        TextField::new(
+            "name",
+            Rect {
+                x: Pt(10.0),
+                y: Pt(20.0),
+                width: Pt(100.0),
+                height: Pt(20.0),
+            },
+        )
+        .with_font(BuiltinFont::Courier, Pt(12.0))
+        .with_max_len(10),
+    );
+
+    let mut doc = crate::PdfDocument::new("form");
+    doc.pages.push(crate::PdfPage::new(
+        crate::Mm(210.0),
+        crate::Mm(297.0),
+        vec![crate::Op::AddFormField {
+            field: Box::new(field),
+        }],
+    ));
+    let bytes = doc.save(&crate::PdfSaveOptions::default());
+
+    let mut parsed = crate::PdfDocument::parse(&bytes, &Default::default()).unwrap();
+    let parsed_field = parsed.resources.forms.map.get("name").unwrap();
+    assert_eq!(parsed_field.page, 0);
+    match &parsed_field.field {
+        FormField::Text(t) => {
+            assert_eq!(t.font, BuiltinFont::Courier);
+            assert_eq!(t.font_size, Pt(12.0));
+            assert_eq!(t.max_len, Some(10));
+            assert_eq!(t.rect.x, Pt(10.0));
+        }
+        _ => panic!("expected a text field"),
+    }
+
+    assert!(parsed.fill_field("name", "Jürgen").is_ok());
+    assert!(parsed.fill_field("name", "way too long value").is_err());
+    assert!(parsed.fill_field("missing", "value").is_err());
+    match &parsed.resources.forms.map["name"].field {
+        FormField::Text(t) => assert_eq!(t.value, "Jürgen"),
+        _ => panic!("expected a text field"),
+    }
+}
@@ -0,0 +1,415 @@
+//! Axial (linear) and radial gradients, painted inside a clipping polygon with
+//! `Op::DrawGradient` (written as `/Shading` resources of the page)
+
+use lopdf::Dictionary as LoDictionary;
+use lopdf::Object::{Array, Boolean, Dictionary, Integer, Name, Real};
+
+use crate::{
+    color::Color,
+    graphics::{Point, Polygon, Rect},
+    units::Pt,
+};
+
+/// Color at a position of the gradient
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradientStop {
+    /// Position along the gradient (0.0 = start, 1.0 = end)
+    pub offset: f32,
+    pub color: Color,
+}
+
+/// Coordinate space of the gradient points and radii
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum GradientUnits {
+    /// Coordinates on the page (affected by the current transformation matrix)
+    #[default]
+    UserSpace,
+    /// Fractions of the bounding box of the clip polygon: (0, 0) is the lower left,
+    /// (1, 1) the upper right corner (like `objectBoundingBox` in SVG)
+    BoundingBox,
+}
+
+/// Gradient along a line (`/ShadingType 2`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinearGradient {
+    /// Position of the first stop
+    pub start: Point,
+    /// Position of the last stop
+    pub end: Point,
+    pub stops: Vec<GradientStop>,
+    /// Continue the first color before the start point
+    pub extend_start: bool,
+    /// Continue the last color after the end point
+    pub extend_end: bool,
+    pub units: GradientUnits,
+}
+
+impl LinearGradient {
+    /// Creates a gradient from `start` to `end` without color stops, the colors
+    /// are extended beyond both ends
+    pub fn new(start: Point, end: Point) -> Self {
+        Self {
+            start,
+            end,
+            stops: Vec::new(),
+            extend_start: true,
+            extend_end: true,
+            units: GradientUnits::UserSpace,
+        }
+    }
+
+    /// Adds a color stop (offsets outside 0.0 - 1.0 are clamped)
+    pub fn with_stop(mut self, offset: f32, color: Color) -> Self {
+        add_stop(&mut self.stops, offset, color);
+        self
+    }
+
+    /// Sets whether the colors are extended beyond the start and end points
+    pub fn with_extend(mut self, extend_start: bool, extend_end: bool) -> Self {
+        self.extend_start = extend_start;
+        self.extend_end = extend_end;
+        self
+    }
+
+    /// Sets the coordinate space of the points
+    pub fn with_units(mut self, units: GradientUnits) -> Self {
+        self.units = units;
+        self
+    }
+}
+
+/// Gradient between two circles (`/ShadingType 3`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct RadialGradient {
+    /// Center of the start circle (the focal point)
+    pub start_center: Point,
+    /// Radius of the start circle
+    pub start_radius: Pt,
+    /// Center of the end circle
+    pub end_center: Point,
+    /// Radius of the end circle
+    pub end_radius: Pt,
+    pub stops: Vec<GradientStop>,
+    /// Continue the first color inside the start circle
+    pub extend_start: bool,
+    /// Continue the last color outside the end circle
+    pub extend_end: bool,
+    pub units: GradientUnits,
+}
+
+impl RadialGradient {
+    /// Creates a circular gradient from the center to the radius without color stops,
+    /// the colors are extended beyond both circles
+    pub fn new(center: Point, radius: Pt) -> Self {
+        Self {
+            start_center: center,
+            start_radius: Pt(0.0),
+            end_center: center,
+            end_radius: radius,
+            stops: Vec::new(),
+            extend_start: true,
+            extend_end: true,
+            units: GradientUnits::UserSpace,
+        }
+    }
+
+    /// Moves the start circle (i.e. an off-center focal point with a radius of 0)
+    pub fn with_focus(mut self, center: Point, radius: Pt) -> Self {
+        self.start_center = center;
+        self.start_radius = radius;
+        self
+    }
+
+    /// Adds a color stop (offsets outside 0.0 - 1.0 are clamped)
+    pub fn with_stop(mut self, offset: f32, color: Color) -> Self {
+        add_stop(&mut self.stops, offset, color);
+        self
+    }
+
+    /// Sets whether the colors are extended beyond the start and end circles
+    pub fn with_extend(mut self, extend_start: bool, extend_end: bool) -> Self {
+        self.extend_start = extend_start;
+        self.extend_end = extend_end;
+        self
+    }
+
+    /// Sets the coordinate space of the centers and radii
+    pub fn with_units(mut self, units: GradientUnits) -> Self {
+        self.units = units;
+        self
+    }
+}
+
+/// Inserts the stop after all stops with a lower or equal offset
+fn add_stop(stops: &mut Vec<GradientStop>, offset: f32, color: Color) {
+    let offset = offset.clamp(0.0, 1.0);
+    let idx = stops.partition_point(|s| s.offset <= offset);
+    stops.insert(idx, GradientStop { offset, color });
+}
+
+/// Linear or radial gradient, see `Op::DrawGradient`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Gradient {
+    Linear(LinearGradient),
+    Radial(RadialGradient),
+}
+
+impl Gradient {
+    pub fn get_stops(&self) -> &[GradientStop] {
+        match self {
+            Gradient::Linear(g) => &g.stops,
+            Gradient::Radial(g) => &g.stops,
+        }
+    }
+
+    pub fn get_units(&self) -> GradientUnits {
+        match self {
+            Gradient::Linear(g) => g.units,
+            Gradient::Radial(g) => g.units,
+        }
+    }
+
+    /// Maps the unit square to the bounding box of the polygon, for `GradientUnits::BoundingBox`
+    pub(crate) fn get_bbox_matrix(&self, clip: &Polygon) -> Option<[f32; 6]> {
+        if self.get_units() != GradientUnits::BoundingBox {
+            return None;
+        }
+        let bbox = get_polygon_bbox(clip)?;
+        Some([bbox.width.0, 0.0, 0.0, bbox.height.0, bbox.x.0, bbox.y.0])
+    }
+
+    /// Returns the color (in the color space of the shading) at the position `t` (0.0 - 1.0)
+    pub(crate) fn get_color_at(&self, t: f32) -> Vec<f32> {
+        let (stops, color_space) = (self.get_stops(), self.get_color_space());
+        let components = |s: &GradientStop| color_to_components(&s.color, color_space);
+        let (Some(first), Some(last)) = (stops.first(), stops.last()) else {
+            return vec![0.0; color_space.1];
+        };
+        if t <= first.offset {
+            return components(first);
+        }
+        for pair in stops.windows(2) {
+            if t <= pair[1].offset {
+                let range = pair[1].offset - pair[0].offset;
+                let f = if range > 0.0 {
+                    (t - pair[0].offset) / range
+                } else {
+                    1.0
+                };
+                let (a, b) = (components(&pair[0]), components(&pair[1]));
+                return a
+                    .iter()
+                    .zip(b.iter())
+                    .map(|(a, b)| a + (b - a) * f)
+                    .collect();
+            }
+        }
+        components(last)
+    }
+
+    /// Device color space (name, components) shared by all stops: CMYK if all stops
+    /// are CMYK, gray if all are gray, RGB otherwise
+    pub(crate) fn get_color_space(&self) -> (&'static str, usize) {
+        let stops = self.get_stops();
+        let all = |f: fn(&Color) -> bool| {
+            !stops.is_empty() && stops.iter().all(|s| f(&s.color.get_device_color()))
+        };
+        if all(|c| matches!(c, Color::Cmyk(_) | Color::SpotColor(_))) {
+            ("DeviceCMYK", 4)
+        } else if all(|c| matches!(c, Color::Greyscale(_))) {
+            ("DeviceGray", 1)
+        } else {
+            ("DeviceRGB", 3)
+        }
+    }
+
+    /// Creates the `/Shading` dictionary
+    pub(crate) fn to_shading_dict(&self) -> LoDictionary {
+        let (shading_type, coords, extend) = match self {
+            Gradient::Linear(g) => (
+                2,
+                vec![g.start.x.0, g.start.y.0, g.end.x.0, g.end.y.0],
+                [g.extend_start, g.extend_end],
+            ),
+            Gradient::Radial(g) => (
+                3,
+                vec![
+                    g.start_center.x.0,
+                    g.start_center.y.0,
+                    g.start_radius.0.max(0.0),
+                    g.end_center.x.0,
+                    g.end_center.y.0,
+                    g.end_radius.0.max(0.0),
+                ],
+                [g.extend_start, g.extend_end],
+            ),
+        };
+        LoDictionary::from_iter(vec![
+            ("ShadingType", Integer(shading_type)),
+            ("ColorSpace", Name(self.get_color_space().0.into())),
+            ("Coords", Array(coords.into_iter().map(Real).collect())),
+            ("Domain", Array(vec![Real(0.0), Real(1.0)])),
+            ("Function", Dictionary(self.get_function())),
+            ("Extend", Array(extend.into_iter().map(Boolean).collect())),
+        ])
+    }
+
+    /// Interpolation between the stops: a single exponential function (`/FunctionType 2`)
+    /// for two stops, a stitching function (`/FunctionType 3`) for more stops
+    fn get_function(&self) -> LoDictionary {
+        let color_space = self.get_color_space();
+        let mut stops = self.get_stops().to_vec();
+        let (Some(first), Some(last)) = (stops.first().cloned(), stops.last().cloned()) else {
+            let black = vec![0.0; color_space.1];
+            return interpolate(&black, &black);
+        };
+        // the colors before the first / after the last stop are constant
+        if first.offset > 0.0 {
+            stops.insert(
+                0,
+                GradientStop {
+                    offset: 0.0,
+                    ..first
+                },
+            );
+        }
+        if last.offset < 1.0 {
+            stops.push(GradientStop {
+                offset: 1.0,
+                ..last
+            });
+        }
+        let components = stops
+            .iter()
+            .map(|s| color_to_components(&s.color, color_space))
+            .collect::<Vec<_>>();
+
+        if stops.len() == 1 {
+            return interpolate(&components[0], &components[0]);
+        }
+        if stops.len() == 2 {
+            return interpolate(&components[0], &components[1]);
+        }
+        let functions = components
+            .windows(2)
+            .map(|c| Dictionary(interpolate(&c[0], &c[1])))
+            .collect::<Vec<_>>();
+        let bounds = stops[1..stops.len() - 1]
+            .iter()
+            .map(|s| Real(s.offset))
+            .collect::<Vec<_>>();
+        let encode = functions
+            .iter()
+            .flat_map(|_| [Real(0.0), Real(1.0)])
+            .collect::<Vec<_>>();
+        LoDictionary::from_iter(vec![
+            ("FunctionType", Integer(3)),
+            ("Domain", Array(vec![Real(0.0), Real(1.0)])),
+            ("Functions", Array(functions)),
+            ("Bounds", Array(bounds)),
+            ("Encode", Array(encode)),
+        ])
+    }
+}
+
+/// Linear interpolation between two colors (`/FunctionType 2`, `/N 1`)
+fn interpolate(c0: &[f32], c1: &[f32]) -> LoDictionary {
+    LoDictionary::from_iter(vec![
+        ("FunctionType", Integer(2)),
+        ("Domain", Array(vec![Real(0.0), Real(1.0)])),
+        ("C0", Array(c0.iter().copied().map(Real).collect())),
+        ("C1", Array(c1.iter().copied().map(Real).collect())),
+        ("N", Integer(1)),
+    ])
+}
+
+/// Converts the color into the color space of the shading (without color management)
+fn color_to_components(color: &Color, color_space: (&'static str, usize)) -> Vec<f32> {
+    if let Color::Indexed(_) | Color::DeviceN(_) = color {
+        return color_to_components(&color.get_device_color(), color_space);
+    }
+    let rgb = match color {
+        Color::Rgb(c) => [c.r, c.g, c.b],
+        Color::Greyscale(g) => [g.percent; 3],
+        Color::Cmyk(c) => [c.c, c.m, c.y].map(|v| (1.0 - v) * (1.0 - c.k)),
+        Color::SpotColor(c) => [c.c, c.m, c.y].map(|v| (1.0 - v) * (1.0 - c.k)),
+        Color::Indexed(_) | Color::DeviceN(_) => [0.0; 3],
+    };
+    match (color_space.0, color) {
+        ("DeviceCMYK", Color::Cmyk(c)) => vec![c.c, c.m, c.y, c.k],
+        ("DeviceCMYK", Color::SpotColor(c)) => vec![c.c, c.m, c.y, c.k],
+        ("DeviceGray", Color::Greyscale(g)) => vec![g.percent],
+        _ => rgb.to_vec(),
+    }
+}
+
+/// Bounding box of all points of the polygon
+pub(crate) fn get_polygon_bbox(polygon: &Polygon) -> Option<Rect> {
+    let mut points = polygon.rings.iter().flatten().map(|(p, _)| p);
+    let first = points.next()?;
+    let (mut min, mut max) = ((first.x.0, first.y.0), (first.x.0, first.y.0));
+    for p in points {
+        min = (min.0.min(p.x.0), min.1.min(p.y.0));
+        max = (max.0.max(p.x.0), max.1.max(p.y.0));
+    }
+    Some(Rect {
+        x: Pt(min.0),
+        y: Pt(min.1),
+        width: Pt(max.0 - min.0),
+        height: Pt(max.1 - min.1),
+    })
+}
+
+#[test]
+fn test_gradient_shading() {
+    use crate::{Cmyk, Mm, Rgb};
+
+    let red = Color::Rgb(Rgb::new(1.0, 0.0, 0.0, None));
+    let blue = Color::Rgb(Rgb::new(0.0, 0.0, 1.0, None));
+    let linear = Gradient::Linear(
+        LinearGradient::new(Point::new(Mm(0.0), Mm(0.0)), Point::new(Mm(100.0), Mm(0.0)))
+            .with_stop(1.0, blue.clone())
+            .with_stop(0.0, red.clone())
+            .with_stop(0.5, Color::Greyscale(crate::Greyscale::new(1.0, None))),
+    );
+    let dict = linear.to_shading_dict();
+    assert_eq!(dict.get(b"ShadingType").unwrap(), &Integer(2));
+    assert_eq!(dict.get(b"ColorSpace").unwrap(), &Name("DeviceRGB".into()));
+    let function = dict.get(b"Function").unwrap().as_dict().unwrap();
+    assert_eq!(function.get(b"FunctionType").unwrap(), &Integer(3));
+    assert_eq!(function.get(b"Bounds").unwrap(), &Array(vec![Real(0.5)]));
+    assert_eq!(linear.get_color_at(0.25), vec![1.0, 0.5, 0.5]);
+
+    // stops that don't cover 0.0 - 1.0 are extended with constant colors
+    let radial = Gradient::Radial(
+        RadialGradient::new(Point::new(Mm(50.0), Mm(50.0)), Mm(20.0).into_pt())
+            .with_stop(0.2, Color::Cmyk(Cmyk::new(0.0, 0.0, 0.0, 1.0, None)))
+            .with_stop(1.0, Color::Cmyk(Cmyk::new(1.0, 0.0, 0.0, 0.0, None)))
+            .with_units(GradientUnits::BoundingBox),
+    );
+    let dict = radial.to_shading_dict();
+    assert_eq!(dict.get(b"ColorSpace").unwrap(), &Name("DeviceCMYK".into()));
+    let function = dict.get(b"Function").unwrap().as_dict().unwrap();
+    assert_eq!(
+        function
+            .get(b"Functions")
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .len(),
+        2
+    );
+
+    let square = Rect {
+        x: Pt(10.0),
+        y: Pt(20.0),
+        width: Pt(30.0),
+        height: Pt(40.0),
+    }
+    .to_polygon();
+    assert_eq!(
+        radial.get_bbox_matrix(&square),
+        Some([30.0, 0.0, 0.0, 40.0, 10.0, 20.0])
+    );
+    assert_eq!(linear.get_bbox_matrix(&square), None);
+}
@@ -1,7 +1,7 @@
 use crate::units::{Mm, Pt};
-use crate::FontId;
+use crate::{FontId, XObjectId};
 use lopdf::Dictionary as LoDictionary;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 
 /// Fill path using nonzero winding number rule
 pub const OP_PATH_PAINT_FILL_NZ: &str = "f";
@@ -44,6 +44,28 @@ impl Rect {
         }
     }
 
+    /// Creates a rectangle from two opposite corners, in any order (i.e. from a PDF
+    /// `[llx lly urx ury]` array, which can be inverted in parsed files)
+    pub fn from_corners(a: Point, b: Point) -> Self {
+        Self {
+            x: Pt(a.x.0.min(b.x.0)),
+            y: Pt(a.y.0.min(b.y.0)),
+            width: Pt((a.x.0 - b.x.0).abs()),
+            height: Pt((a.y.0 - b.y.0).abs()),
+        }
+    }
+
+    /// Returns the same rectangle with a non-negative width and height
+    pub fn normalize(&self) -> Self {
+        Self::from_corners(self.lower_left(), self.upper_right())
+    }
+
+    /// Returns true if the rectangle has a zero (or non-finite) area
+    pub fn is_empty(&self) -> bool {
+        let area = (self.width.0 * self.height.0).abs();
+        !area.is_finite() || area < 0.001
+    }
+
     pub fn from_wh(width: Pt, height: Pt) -> Self {
         Self {
             x: Pt(0.0),
@@ -85,12 +107,16 @@ impl Rect {
         vec![(tl, false), (tr, false), (br, false), (bl, false)]
     }
 
+    /// Returns the `[llx lly urx ury]` array of the (normalized) rectangle
     pub fn to_array(&self) -> Vec<lopdf::Object> {
+        let r = self.normalize();
+        let ll = r.lower_left();
+        let ur = r.upper_right();
         vec![
-            (self.x.0.round() as i64).into(),
-            (self.y.0.round() as i64).into(),
-            (self.width.0.round() as i64).into(),
-            (self.height.0.round() as i64).into(),
+            (ll.x.0.round() as i64).into(),
+            (ll.y.0.round() as i64).into(),
+            (ur.x.0.round() as i64).into(),
+            (ur.y.0.round() as i64).into(),
         ]
     }
 }
@@ -589,7 +615,12 @@ pub struct ExtendedGraphicsState {
     pub(crate) text_knockout: bool,
 }
 
-pub fn extgstate_to_dict(val: &ExtendedGraphicsState) -> LoDictionary {
+/// Converts the graphics state into an `/ExtGState` dictionary, `xobjects` are the object
+/// IDs of the XObjects (for the groups of soft masks)
+pub fn extgstate_to_dict(
+    val: &ExtendedGraphicsState,
+    xobjects: &BTreeMap<XObjectId, lopdf::ObjectId>,
+) -> LoDictionary {
     use lopdf::Object::*;
     use std::string::String;
 
@@ -624,24 +655,26 @@ pub fn extgstate_to_dict(val: &ExtendedGraphicsState) -> LoDictionary {
         gs_operations.push(("SA".to_string(), Boolean(val.stroke_adjustment)));
     }
 
-    if val.changed_fields.contains(OVERPRINT_FILL) {
-        gs_operations.push(("OP".to_string(), Boolean(val.overprint_fill)));
+    // "OP" is the stroking overprint, "op" the overprint of all other operations
+    if val.changed_fields.contains(OVERPRINT_STROKE) {
+        gs_operations.push(("OP".to_string(), Boolean(val.overprint_stroke)));
     }
 
-    if val.changed_fields.contains(OVERPRINT_STROKE) {
-        gs_operations.push(("op".to_string(), Boolean(val.overprint_stroke)));
+    if val.changed_fields.contains(OVERPRINT_FILL) {
+        gs_operations.push(("op".to_string(), Boolean(val.overprint_fill)));
     }
 
     if val.changed_fields.contains(OVERPRINT_MODE) {
         gs_operations.push(("OPM".to_string(), Integer(val.overprint_mode.get_id())));
     }
 
-    if val.changed_fields.contains(CURRENT_FILL_ALPHA) {
-        gs_operations.push(("CA".to_string(), Real(val.current_fill_alpha)));
+    // "CA" is the stroking alpha, "ca" the alpha of all other operations
+    if val.changed_fields.contains(CURRENT_STROKE_ALPHA) {
+        gs_operations.push(("CA".to_string(), Real(val.current_stroke_alpha)));
     }
 
-    if val.changed_fields.contains(CURRENT_STROKE_ALPHA) {
-        gs_operations.push(("ca".to_string(), Real(val.current_stroke_alpha)));
+    if val.changed_fields.contains(CURRENT_FILL_ALPHA) {
+        gs_operations.push(("ca".to_string(), Real(val.current_fill_alpha)));
     }
 
     if val.changed_fields.contains(BLEND_MODE) {
@@ -718,9 +751,13 @@ pub fn extgstate_to_dict(val: &ExtendedGraphicsState) -> LoDictionary {
     }
 
     if val.changed_fields.contains(SOFT_MASK) {
-        if let Some(ref soft_mask) = val.soft_mask {
-        } else {
-            gs_operations.push(("SM".to_string(), Name("None".as_bytes().to_vec())));
+        match val.soft_mask.as_ref() {
+            Some(soft_mask) => {
+                if let Some(dict) = soft_mask.to_dict(xobjects) {
+                    gs_operations.push(("SMask".to_string(), Dictionary(dict)));
+                }
+            }
+            None => gs_operations.push(("SMask".to_string(), Name("None".as_bytes().to_vec()))),
         }
     }
 
@@ -809,6 +846,14 @@ impl ExtendedGraphicsStateBuilder {
         self
     }
 
+    /// Sets the overprint of both stroking and other painting operations, i.e. for
+    /// trapping (slightly overlapping, overprinted inks) or black text on colored areas
+    #[inline]
+    pub fn with_overprint(self, overprint: bool) -> Self {
+        self.with_overprint_fill(overprint)
+            .with_overprint_stroke(overprint)
+    }
+
     /// Sets the font
     /// __WARNING:__ Use `layer.add_font()` instead if you are not absolutely sure.
     #[inline]
@@ -945,6 +990,13 @@ impl ExtendedGraphicsStateBuilder {
         self
     }
 
+    /// Sets the alpha of both strokes and fills (0.0 = transparent, 1.0 = opaque)
+    #[inline]
+    pub fn with_alpha(self, alpha: f32) -> Self {
+        self.with_current_fill_alpha(alpha)
+            .with_current_stroke_alpha(alpha)
+    }
+
     /// Sets the current "alpha is shape"
     #[inline]
     pub fn with_alpha_is_shape(mut self, alpha_is_shape: bool) -> Self {
@@ -968,6 +1020,13 @@ impl ExtendedGraphicsStateBuilder {
     }
 }
 
+impl ExtendedGraphicsState {
+    /// Returns a builder, only the parameters that are set on the builder are written
+    pub fn builder() -> ExtendedGraphicsStateBuilder {
+        ExtendedGraphicsStateBuilder::new()
+    }
+}
+
 impl Default for ExtendedGraphicsState {
     /// Creates a default ExtGState dictionary. Useful for resetting
     fn default() -> Self {
@@ -1256,6 +1315,29 @@ impl BlendMode {
     pub fn luminosity() -> BlendMode {
         BlendMode::NonSeperable(NonSeperableBlendMode::Luminosity)
     }
+    pub(crate) fn from_id(id: &str) -> Option<Self> {
+        [
+            Self::normal(),
+            Self::multiply(),
+            Self::screen(),
+            Self::overlay(),
+            Self::darken(),
+            Self::lighten(),
+            Self::color_dodge(),
+            Self::color_burn(),
+            Self::hard_light(),
+            Self::soft_light(),
+            Self::difference(),
+            Self::exclusion(),
+            Self::hue(),
+            Self::saturation(),
+            Self::color(),
+            Self::luminosity(),
+        ]
+        .into_iter()
+        .find(|b| b.get_id() == id)
+    }
+
     pub fn get_id(&self) -> &'static str {
         use self::BlendMode::*;
         use self::NonSeperableBlendMode::*;
@@ -1561,26 +1643,105 @@ impl RenderingIntent {
     }
 }
 
-/// A soft mask is used for transparent images such as PNG with an alpha component
-/// The bytes range from 0xFF (opaque) to 0x00 (transparent). The alpha channel of a
-/// PNG image have to be sorted out.
-/// Can also be used for Vignettes, etc.
-/// Beware of color spaces!
+/// Soft mask of a graphics state (`/SMask` dictionary): the alpha or the luminosity of a
+/// transparency group is used as the mask shape or opacity of everything painted while
+/// the graphics state is active (i.e. vignettes or faded edges from design tools)
 /// __See PDF Reference Page 545__ - Soft masks
 #[derive(Debug, PartialEq, Clone)]
 pub struct SoftMask {
-    /// The data to be used as a soft mask
-    data: Vec<u8>,
-    /// Bits per component (1 for black / white, 8 for greyscale, up to 16)
-    bits_per_component: u8,
+    /// `/S`: which values of the group are used as the mask
+    pub function: SoftMaskFunction,
+    /// `/G`: transparency group that defines the mask, must be an `XObject::Group`
+    /// or an `XObject::Form` with a group
+    pub group: XObjectId,
+    /// `/BC`: backdrop color of luminosity masks, in the color space of the group
+    /// (default: black)
+    pub backdrop: Option<Vec<f32>>,
+}
+
+impl SoftMask {
+    pub fn new(function: SoftMaskFunction, group: XObjectId) -> Self {
+        Self {
+            function,
+            group,
+            backdrop: None,
+        }
+    }
+
+    /// Sets the backdrop color of a luminosity mask
+    pub fn with_backdrop(mut self, backdrop: Vec<f32>) -> Self {
+        self.backdrop = Some(backdrop);
+        self
+    }
+
+    /// Returns the `/SMask` dictionary, `None` if the group XObject doesn't exist
+    pub(crate) fn to_dict(
+        &self,
+        xobjects: &BTreeMap<XObjectId, lopdf::ObjectId>,
+    ) -> Option<LoDictionary> {
+        use lopdf::Object::*;
+        let group = *xobjects.get(&self.group)?;
+        let mut dict = LoDictionary::from_iter(vec![
+            ("Type", Name("Mask".into())),
+            ("S", Name(self.function.get_id().into())),
+            ("G", Reference(group)),
+        ]);
+        if let Some(bc) = self.backdrop.as_ref() {
+            dict.set("BC", Array(bc.iter().copied().map(Real).collect()));
+        }
+        Some(dict)
+    }
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum SoftMaskFunction {
     // (Color, Shape, Alpha) = Composite(Color0, Alpha0, Group)
-    /// In this function, the old (backdrop) color does not contribute to the result.
-    /// This is the easies function, but may look bad at edges.
+    /// The mask is the alpha of the group, the color of the group does not contribute.
     GroupAlpha,
-    //
+    /// The mask is the luminosity of the group, composited onto the backdrop color
     GroupLuminosity,
 }
+
+impl SoftMaskFunction {
+    pub fn get_id(&self) -> &'static str {
+        match self {
+            SoftMaskFunction::GroupAlpha => "Alpha",
+            SoftMaskFunction::GroupLuminosity => "Luminosity",
+        }
+    }
+
+    pub(crate) fn from_id(id: &str) -> Option<Self> {
+        match id {
+            "Alpha" => Some(SoftMaskFunction::GroupAlpha),
+            "Luminosity" => Some(SoftMaskFunction::GroupLuminosity),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn test_extgstate_builder() {
+    use lopdf::Object::{Boolean, Integer, Real};
+
+    let gs = ExtendedGraphicsState::builder()
+        .with_overprint(true)
+        .with_overprint_mode(OverprintMode::KeepUnderlying)
+        .with_current_stroke_alpha(0.5)
+        .with_current_fill_alpha(0.25)
+        .build();
+    let dict = extgstate_to_dict(&gs, &BTreeMap::new());
+    assert_eq!(dict.get(b"OP").unwrap(), &Boolean(true));
+    assert_eq!(dict.get(b"op").unwrap(), &Boolean(true));
+    assert_eq!(dict.get(b"OPM").unwrap(), &Integer(1));
+    assert_eq!(dict.get(b"CA").unwrap(), &Real(0.5));
+    assert_eq!(dict.get(b"ca").unwrap(), &Real(0.25));
+    assert!(!dict.has(b"BM"));
+
+    let mut doc = crate::PdfDocument::new("extgstate");
+    let a = doc.add_graphics_state(gs.clone());
+    let b = doc.add_graphics_state(gs);
+    let c = doc.add_graphics_state(ExtendedGraphicsState::builder().with_alpha(0.5).build());
+    assert_eq!(a, b);
+    assert_ne!(a, c);
+    assert_eq!(doc.resources.extgstates.map.len(), 2);
+}
@@ -1,4 +1,8 @@
-use crate::{BuiltinFont, Mm, Op, PdfDocument, PdfPage, PdfResources, Pt};
+use crate::{
+    Actions, BuiltinFont, Color, Destination, HtmlComponentMap, LinkAnnotation, Mm, Op,
+    PdfDocument, PdfPage, PdfResources, PdfWarnCode, PdfWarnMsg, Pt, Rect, StructureElementId,
+    StructureType, TableHeaderScope,
+};
 pub use azul_core::dom::Dom;
 pub use azul_core::styled_dom::StyledDom;
 pub use azul_core::xml::{
@@ -11,19 +15,20 @@ use azul_core::{
     },
     callbacks::DocumentId,
     display_list::{
-        RectBackground, RenderCallbacks, SolvedLayout, StyleBorderColors, StyleBorderRadius,
-        StyleBorderStyles, StyleBorderWidths,
+        GlyphInstance, RectBackground, RenderCallbacks, SolvedLayout, StyleBorderColors,
+        StyleBorderRadius, StyleBorderStyles, StyleBorderWidths,
     },
-    dom::{NodeData, NodeId},
+    dom::{IdOrClass, NodeData, NodeId},
     styled_dom::{ContentGroup, StyledNode},
     ui_solver::LayoutResult,
     window::{FullWindowState, LogicalSize},
 };
-use azul_css::{CssPropertyValue, FloatValue, LayoutDisplay, StyleTextColor};
+use azul_css::{CssPropertyValue, FloatValue, LayoutDisplay, LayoutPosition, StyleTextColor};
 pub use azul_css_parser::CssApiWrapper;
 use rust_fontconfig::{FcFont, FcFontCache, FcPattern};
 use serde_derive::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
 use svg2pdf::usvg::tiny_skia_path::Scalar;
 
 const DPI_SCALE: DpiScaleFactor = DpiScaleFactor {
@@ -43,6 +48,12 @@ pub struct XmlRenderOptions {
     pub page_width: Mm,
     pub page_height: Mm,
     pub components: Vec<XmlComponent>,
+    /// Components that are expanded into plain XML before rendering (calendars, grids, ...)
+    pub html_components: HtmlComponentMap,
+    /// Background color of the generated pages
+    pub background: Option<Color>,
+    /// Target resolution of images (pixels per inch), selects the image of `<img srcset="...">`
+    pub image_dpi: f32,
 }
 
 impl Default for XmlRenderOptions {
@@ -53,7 +64,129 @@ impl Default for XmlRenderOptions {
             page_width: Mm(210.0),
             page_height: Mm(297.0),
             components: Default::default(),
+            html_components: Default::default(),
+            background: None,
+            image_dpi: 300.0,
+        }
+    }
+}
+
+/// Image or font that is referenced by the HTML, but not in the `XmlRenderOptions`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum HtmlAsset {
+    /// `src` or `srcset` URL of an `<img>`, fetched into `XmlRenderOptions::images`
+    Image(String),
+    /// Family of a `font-family` style, fetched into `XmlRenderOptions::fonts`
+    Font(String),
+}
+
+impl XmlRenderOptions {
+    /// Returns the images and fonts of the HTML (including the expanded components) that
+    /// are not in the `images` and `fonts`. Of a `srcset`, only the candidate for the
+    /// `image_dpi` and the `src` are returned. Builtin fonts and the generic families
+    /// (`serif`, `monospace`, ...) are never missing.
+    pub fn get_missing_assets(&self, html: &str) -> Vec<HtmlAsset> {
+        use xmlparser::{Token, Tokenizer};
+
+        let Ok(xml) = crate::components::expand_components(html, &self.html_components) else {
+            return Vec::new();
+        };
+        let has_font = |family: &str| {
+            matches!(
+                family,
+                "serif" | "sans-serif" | "cursive" | "fantasy" | "monospace"
+            ) || BuiltinFont::from_id(family).is_some()
+                || self
+                    .fonts
+                    .keys()
+                    .any(|k| k.split('.').next() == Some(family))
+        };
+
+        let page_width = self.page_width.into_pt();
+        let mut assets = BTreeSet::new();
+        // attributes of the current `<img>`
+        let mut img: Option<BTreeMap<&str, &str>> = None;
+        for token in Tokenizer::from(xml.as_str()) {
+            let Ok(token) = token else {
+                break;
+            };
+            match token {
+                Token::ElementStart { local, .. } => {
+                    img = (local.as_str() == "img").then(BTreeMap::new);
+                }
+                Token::ElementEnd { .. } => {
+                    let Some(attributes) = img.take() else {
+                        continue;
+                    };
+                    // only the `srcset` candidate for the `image_dpi` and the `src` fallback
+                    let src = attributes.get("src").copied();
+                    let selected = attributes.get("srcset").and_then(|srcset| {
+                        let declarations = attributes
+                            .get("style")
+                            .map(|s| parse_style(s))
+                            .unwrap_or_default();
+                        let width = get_style(&declarations, "width")
+                            .or(attributes.get("width").copied())
+                            .and_then(|w| parse_css_length(w, Some(page_width.0)));
+                        let sizes = attributes.get("sizes").copied();
+                        select_srcset_url(srcset, src, sizes, width, page_width, self.image_dpi)
+                    });
+                    let urls = selected.into_iter().chain(src);
+                    assets.extend(urls.map(|url| HtmlAsset::Image(url.to_string())));
+                }
+                Token::Attribute { local, value, .. } => {
+                    if let Some(attributes) = img.as_mut() {
+                        attributes.insert(local.as_str(), value.as_str());
+                    }
+                    if local.as_str() != "style" {
+                        continue;
+                    }
+                    let declarations = parse_style(value.as_str());
+                    let Some(families) = get_style(&declarations, "font-family") else {
+                        continue;
+                    };
+                    let families = families
+                        .split(',')
+                        .map(|f| f.trim().trim_matches(|c| c == '"' || c == '\''))
+                        .filter(|f| !f.is_empty() && !has_font(f));
+                    assets.extend(families.map(|f| HtmlAsset::Font(f.to_string())));
+                }
+                _ => {}
+            }
+        }
+
+        assets
+            .into_iter()
+            .filter(|asset| match asset {
+                HtmlAsset::Image(url) => !url.is_empty() && !self.images.contains_key(url),
+                HtmlAsset::Font(_) => true,
+            })
+            .collect()
+    }
+
+    /// Fetches the missing assets of the HTML (see `get_missing_assets`) with the async
+    /// `loader`, i.e. with `fetch()` on WASM or an HTTP client. The loader returns `None`
+    /// for assets that are not available (the renderer falls back to the default font),
+    /// errors abort the fetching.
+    pub async fn fetch_missing_assets<F, Fut>(
+        &mut self,
+        html: &str,
+        mut loader: F,
+    ) -> Result<(), String>
+    where
+        F: FnMut(HtmlAsset) -> Fut,
+        Fut: std::future::Future<Output = Result<Option<Vec<u8>>, String>>,
+    {
+        for asset in self.get_missing_assets(html) {
+            let Some(bytes) = loader(asset.clone()).await? else {
+                continue;
+            };
+            match asset {
+                HtmlAsset::Image(url) => self.images.insert(url, bytes),
+                HtmlAsset::Font(family) => self.fonts.insert(family, bytes),
+            };
         }
+        Ok(())
     }
 }
 
@@ -62,26 +195,173 @@ pub(crate) fn xml_to_pages(
     config: XmlRenderOptions,
     document: &mut PdfDocument,
 ) -> Result<Vec<PdfPage>, String> {
+    xml_to_pages_with_links(file_contents, config, document).map(|(pages, _)| pages)
+}
+
+pub(crate) fn xml_to_pages_with_links(
+    file_contents: &str,
+    config: XmlRenderOptions,
+    document: &mut PdfDocument,
+) -> Result<(Vec<PdfPage>, HtmlLinkInfo), String> {
+    xml_to_pages_with_callback(
+        file_contents,
+        config,
+        document,
+        &mut |_| Vec::new(),
+        &mut Vec::new(),
+    )
+}
+
+/// Renders the XML, `on_page` is called for every generated page and returns ops that
+/// are drawn on top of the page
+pub(crate) fn xml_to_pages_with_callback(
+    file_contents: &str,
+    mut config: XmlRenderOptions,
+    document: &mut PdfDocument,
+    on_page: &mut dyn FnMut(&HtmlPageInfo) -> Vec<Op>,
+    diagnostics: &mut Vec<HtmlDiagnostic>,
+) -> Result<(Vec<PdfPage>, HtmlLinkInfo), String> {
+    let mut context = HtmlRenderContext::new(&mut config);
+    let images = embed_images(document, &config, diagnostics);
+    let (pages, link_info) = render_xml(
+        file_contents,
+        &config,
+        &images,
+        &mut context,
+        document,
+        on_page,
+        diagnostics,
+    )?;
+    remove_unused_images(document, &images, &pages);
+    Ok((pages, link_info))
+}
+
+/// Fonts, decoded images and components of the `XmlRenderOptions`, which can be shared
+/// between several renderings with the same options (see `PdfDocument::from_html_template`)
+pub(crate) struct HtmlRenderContext {
+    fc_cache: FcFontCache,
+    image_cache: ImageCache,
+    components: XmlComponentMap,
+}
+
+impl HtmlRenderContext {
+    /// Builds the context, moves the `components` out of the `config`
+    pub(crate) fn new(config: &mut XmlRenderOptions) -> Self {
+        let mut components = XmlComponentMap::default();
+        for c in std::mem::take(&mut config.components) {
+            components.register_component(c);
+        }
+
+        let image_cache = ImageCache {
+            image_id_map: config
+                .images
+                .iter()
+                .filter_map(|(id, bytes)| {
+                    // let bytes = base64::prelude::BASE64_STANDARD.decode(bytes).ok()?;
+                    let decoded = crate::image::RawImage::decode_from_bytes(&bytes).ok()?;
+                    let raw_image = crate::image::translate_to_internal_rawimage(&decoded);
+                    Some((id.clone().into(), ImageRef::new_rawimage(raw_image)?))
+                })
+                .collect(),
+        };
+
+        // let builtin_fonts = get_used_builtin_fonts ;
+        let mut fc_cache = FcFontCache::default();
+        fc_cache
+            .with_memory_fonts(&get_system_fonts())
+            .with_memory_fonts(&[
+                get_fcpat(BuiltinFont::TimesRoman),
+                get_fcpat(BuiltinFont::TimesBold),
+                get_fcpat(BuiltinFont::TimesItalic),
+                get_fcpat(BuiltinFont::TimesBoldItalic),
+                get_fcpat(BuiltinFont::Helvetica),
+                get_fcpat(BuiltinFont::HelveticaBold),
+                get_fcpat(BuiltinFont::HelveticaOblique),
+                get_fcpat(BuiltinFont::HelveticaBoldOblique),
+                get_fcpat(BuiltinFont::Courier),
+                get_fcpat(BuiltinFont::CourierOblique),
+                get_fcpat(BuiltinFont::CourierBold),
+                get_fcpat(BuiltinFont::CourierBoldOblique),
+                get_fcpat(BuiltinFont::Symbol),
+                get_fcpat(BuiltinFont::ZapfDingbats),
+            ])
+            .with_memory_fonts(
+                &config
+                    .fonts
+                    .iter()
+                    .filter_map(|(id, bytes)| {
+                        // let bytes = base64::prelude::BASE64_STANDARD.decode(font_base64).ok()?;
+                        let pat = FcPattern {
+                            name: Some(id.split(".").next().unwrap_or("").to_string()),
+                            ..Default::default()
+                        };
+                        let font = FcFont {
+                            bytes: bytes.clone(),
+                            font_index: 0,
+                        };
+                        Some((pat, font))
+                    })
+                    .collect::<Vec<_>>(),
+            );
+
+        Self {
+            fc_cache,
+            image_cache,
+            components,
+        }
+    }
+}
+
+/// Renders the XML with the images that were already added to the `document`
+/// (see `embed_images`)
+pub(crate) fn render_xml(
+    file_contents: &str,
+    config: &XmlRenderOptions,
+    images: &BTreeMap<String, ImageInfo>,
+    context: &mut HtmlRenderContext,
+    document: &mut PdfDocument,
+    on_page: &mut dyn FnMut(&HtmlPageInfo) -> Vec<Op>,
+    diagnostics: &mut Vec<HtmlDiagnostic>,
+) -> Result<(Vec<PdfPage>, HtmlLinkInfo), String> {
     let size = LogicalSize {
         width: config.page_width.into_pt().0,
         height: config.page_height.into_pt().0,
     };
 
-    // inserts images into the PDF resources and changes the src="..."
-    let xml = fixup_xml(file_contents, document, &config);
+    // expands <calendar />, etc. into plain XML
+    let file_contents =
+        crate::components::expand_components(file_contents, &config.html_components)?;
+
+    // changes the src="..." of images to the image resources
+    let xml = fixup_xml(&file_contents, images);
+    // marks every element, so that the diagnostics can refer to the source elements
+    let (xml, elements) = mark_elements(&xml, images);
+    // display: none elements are removed, visibility: hidden is moved into marker classes
+    let xml = prune_hidden_elements(&xml);
+    // display: grid containers are converted into flex rows
+    let xml = convert_grids(&xml);
+    // marks <p>, <h1>, <img>, <table>, ... so that they can be tagged in the structure tree
+    let (xml, alt_texts) = tag_structure_elements(&xml);
+    // replaces <a href="..."> with marker classes, so that the link rects can be found after layout
+    let (xml, hrefs) = extract_links(&xml);
+    // moves the z-index of inline styles into marker classes, see `paint_layers`
+    let xml = extract_z_indices(&xml);
+    // moves dir="rtl" and the direction of inline styles into marker classes
+    let xml = extract_directions(&xml);
+    // min / max sizes and aspect ratios are resolved into fixed sizes
+    let xml = resolve_size_constraints(&xml, config.page_width.into_pt());
+    // picks the image of srcset="..." for the image_dpi, object-fit is moved into marker classes
+    let xml = resolve_images(&xml, images, config.page_width.into_pt(), config.image_dpi);
+    // long words are broken with spaces or hyphens (overflow-wrap, word-break, hyphens)
+    let xml = break_long_words(&xml, config.page_width.into_pt());
     let root_nodes =
         azulc_lib::xml::parse_xml_string(&xml).map_err(|e| format!("Error parsing XML: {}", e))?;
 
     let fixup = fixup_xml_nodes(&root_nodes);
 
-    let mut components = XmlComponentMap::default();
-    for c in config.components {
-        components.register_component(c);
-    }
-
     let styled_dom = azul_core::xml::str_to_dom(
         fixup.as_ref(),
-        &mut components,
+        &mut context.components,
         Some(config.page_width.into_pt().0),
     )
     .map_err(|e| format!("Error constructing DOM: {}", e.to_string()))?;
@@ -90,65 +370,13 @@ pub(crate) fn xml_to_pages(
     fake_window_state.size.dimensions = size;
     let mut renderer_resources = RendererResources::default();
 
-    let image_cache = ImageCache {
-        image_id_map: config
-            .images
-            .iter()
-            .filter_map(|(id, bytes)| {
-                // let bytes = base64::prelude::BASE64_STANDARD.decode(bytes).ok()?;
-                let decoded = crate::image::RawImage::decode_from_bytes(&bytes).ok()?;
-                let raw_image = crate::image::translate_to_internal_rawimage(&decoded);
-                Some((id.clone().into(), ImageRef::new_rawimage(raw_image)?))
-            })
-            .collect(),
-    };
-
-    let new_image_keys = styled_dom.scan_for_image_keys(&image_cache);
+    let new_image_keys = styled_dom.scan_for_image_keys(&context.image_cache);
     let fonts_in_dom = styled_dom.scan_for_font_keys(&renderer_resources);
 
-    // let builtin_fonts = get_used_builtin_fonts ;
-    let mut fc_cache = FcFontCache::default();
-    fc_cache
-        .with_memory_fonts(&get_system_fonts())
-        .with_memory_fonts(&[
-            get_fcpat(BuiltinFont::TimesRoman),
-            get_fcpat(BuiltinFont::TimesBold),
-            get_fcpat(BuiltinFont::TimesItalic),
-            get_fcpat(BuiltinFont::TimesBoldItalic),
-            get_fcpat(BuiltinFont::Helvetica),
-            get_fcpat(BuiltinFont::HelveticaBold),
-            get_fcpat(BuiltinFont::HelveticaOblique),
-            get_fcpat(BuiltinFont::HelveticaBoldOblique),
-            get_fcpat(BuiltinFont::Courier),
-            get_fcpat(BuiltinFont::CourierOblique),
-            get_fcpat(BuiltinFont::CourierBold),
-            get_fcpat(BuiltinFont::CourierBoldOblique),
-            get_fcpat(BuiltinFont::Symbol),
-            get_fcpat(BuiltinFont::ZapfDingbats),
-        ])
-        .with_memory_fonts(
-            &config
-                .fonts
-                .iter()
-                .filter_map(|(id, bytes)| {
-                    // let bytes = base64::prelude::BASE64_STANDARD.decode(font_base64).ok()?;
-                    let pat = FcPattern {
-                        name: Some(id.split(".").next().unwrap_or("").to_string()),
-                        ..Default::default()
-                    };
-                    let font = FcFont {
-                        bytes: bytes.clone(),
-                        font_index: 0,
-                    };
-                    Some((pat, font))
-                })
-                .collect::<Vec<_>>(),
-        );
-
     let add_font_resource_updates = azul_core::app_resources::build_add_font_resource_updates(
         &mut renderer_resources,
         DPI_SCALE,
-        &fc_cache,
+        &context.fc_cache,
         ID_NAMESPACE,
         &fonts_in_dom,
         azulc_lib::font_loading::font_source_get_bytes,
@@ -181,19 +409,327 @@ pub(crate) fn xml_to_pages(
     );
 
     let mut ops = Vec::new();
-    layout_result_to_ops(
+    let link_info = layout_result_to_ops(
         document,
         &layout,
         &renderer_resources,
         &mut ops,
         config.page_height.into_pt(),
+        &hrefs,
+        alt_texts,
     );
 
-    Ok(vec![PdfPage::new(
-        config.page_width,
-        config.page_height,
-        ops,
-    )])
+    diagnostics.extend(get_layout_diagnostics(
+        &layout,
+        &renderer_resources,
+        &elements,
+        config.page_width.into_pt(),
+        config.page_height.into_pt(),
+    ));
+
+    // the layout is not split into pages yet, the content below the page is cut off
+    let content_height = layout.rects.as_ref()[NodeId::ZERO].size.height;
+    let info = HtmlPageInfo {
+        page_index: 0,
+        content_bbox: get_content_bbox(document, &ops),
+        remaining_height: Pt((content_height - size.height).max(0.0)),
+        anchors: link_info
+            .anchors
+            .iter()
+            .filter(|a| a.page == 0)
+            .cloned()
+            .collect(),
+    };
+    let page_ops = on_page(&info);
+    if !page_ops.is_empty() {
+        ops.push(Op::SaveGraphicsState);
+        ops.extend(page_ops);
+        ops.push(Op::RestoreGraphicsState);
+    }
+
+    let mut page = PdfPage::new(config.page_width, config.page_height, ops);
+    if let Some(background) = config.background.as_ref() {
+        page = page.with_background(background.clone());
+    }
+
+    Ok((vec![page], link_info))
+}
+
+/// Page generated by the HTML renderer, see `PdfDocument::html2pages_with_callback`
+#[derive(Debug, Clone, PartialEq)]
+pub struct HtmlPageInfo {
+    /// Index of the page (in the returned pages)
+    pub page_index: usize,
+    /// Bounds of the painted content (text, images, backgrounds and borders) of the page,
+    /// from the lower left corner of the page. `None` if the page is empty.
+    pub content_bbox: Option<Rect>,
+    /// Height of the content that did not fit on this page and the pages before it
+    pub remaining_height: Pt,
+    /// Anchors (elements with an `id`) on the page, i.e. to find the current chapter
+    pub anchors: Vec<HtmlAnchor>,
+}
+
+/// Bounds of the painted paths, glyphs and images of the ops
+fn get_content_bbox(doc: &PdfDocument, ops: &[Op]) -> Option<Rect> {
+    use crate::{postscript::transform, DrawEvent, OpInterpreter};
+
+    let mut bounds: Option<[f32; 4]> = None;
+    let mut add = |(x, y): (f32, f32)| {
+        let b = bounds.get_or_insert([x, y, x, y]);
+        *b = [b[0].min(x), b[1].min(y), b[2].max(x), b[3].max(y)];
+    };
+    OpInterpreter::new(doc, [1.0, 0.0, 0.0, 1.0, 0.0, 0.0]).run(ops, |gs, event| match event {
+        DrawEvent::FillPath { rings, .. } | DrawEvent::StrokePath { rings, .. } => {
+            for (p, _) in rings.iter().flatten() {
+                add(gs.to_device((p.x.0, p.y.0)));
+            }
+        }
+        DrawEvent::GlyphRun { font, glyphs } => {
+            let metrics = &font.font_metrics;
+            for glyph in glyphs {
+                let advance = font.get_horizontal_advance(glyph.glyph_id) as f32;
+                add(transform(&glyph.matrix, (0.0, metrics.descender as f32)));
+                add(transform(&glyph.matrix, (advance, metrics.ascender as f32)));
+            }
+        }
+        DrawEvent::Image { matrix, .. } => {
+            for corner in [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)] {
+                add(transform(&matrix, corner));
+            }
+        }
+        _ => {}
+    });
+    bounds.map(|[x0, y0, x1, y1]| Rect {
+        x: Pt(x0),
+        y: Pt(y0),
+        width: Pt(x1 - x0),
+        height: Pt(y1 - y0),
+    })
+}
+
+/// Problem found while rendering HTML, see `PdfDocument::html2pages_with_diagnostics`
+#[derive(Debug, Clone, PartialEq)]
+pub struct HtmlDiagnostic {
+    /// Index of the page (in the returned pages), 0 for images that fail to decode
+    pub page: usize,
+    /// CSS selector of the element in the HTML, i.e. "html > body > div#main > p:nth-child(2)",
+    /// empty for images that fail to decode
+    pub path: String,
+    /// What went wrong
+    pub reason: HtmlDiagnosticReason,
+}
+
+/// Reason of a `HtmlDiagnostic`
+#[derive(Debug, Clone, PartialEq)]
+pub enum HtmlDiagnosticReason {
+    /// The element does not fit on the page, `overflow` of it is cut off at the bottom
+    Overflow { overflow: Pt },
+    /// The element is wider than the page, `overflow` of it is clipped at the right edge
+    Clipped { overflow: Pt },
+    /// The `src` of an `<img>` is not in the `XmlRenderOptions::images`
+    MissingImage { key: String },
+    /// An image of the `XmlRenderOptions::images` could not be decoded
+    InvalidImage { key: String, error: String },
+    /// None of the fonts of the `font-family` were found, the text is not rendered
+    MissingFont { families: Vec<String> },
+}
+
+impl HtmlDiagnostic {
+    /// Converts the diagnostic into a `PdfWarnMsg` (clipped elements are warnings, missing
+    /// content is an error)
+    pub fn to_warning(&self) -> PdfWarnMsg {
+        let msg = match self.reason {
+            HtmlDiagnosticReason::Clipped { .. } => {
+                PdfWarnMsg::warning(Some(self.page), self.to_string())
+            }
+            _ => PdfWarnMsg::error(Some(self.page), self.to_string()),
+        };
+        let code = match self.reason {
+            HtmlDiagnosticReason::Overflow { .. } => PdfWarnCode::HtmlOverflow,
+            HtmlDiagnosticReason::Clipped { .. } => PdfWarnCode::HtmlClipped,
+            HtmlDiagnosticReason::MissingImage { .. }
+            | HtmlDiagnosticReason::InvalidImage { .. } => PdfWarnCode::HtmlMissingImage,
+            HtmlDiagnosticReason::MissingFont { .. } => PdfWarnCode::HtmlMissingFont,
+        };
+        msg.with_code(code).with_resource(&self.path)
+    }
+}
+
+impl fmt::Display for HtmlDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.reason {
+            HtmlDiagnosticReason::Overflow { overflow } => write!(
+                f,
+                "{}: content overflows the page by {}pt",
+                self.path, overflow.0
+            ),
+            HtmlDiagnosticReason::Clipped { overflow } => write!(
+                f,
+                "{}: element is clipped by {}pt at the right edge of the page",
+                self.path, overflow.0
+            ),
+            HtmlDiagnosticReason::MissingImage { key } => {
+                write!(f, "{}: image {key:?} not found", self.path)
+            }
+            HtmlDiagnosticReason::InvalidImage { key, error } => {
+                write!(f, "image {key:?} could not be decoded: {error}")
+            }
+            HtmlDiagnosticReason::MissingFont { families } => write!(
+                f,
+                "{}: no font found for font-family {:?}",
+                self.path, families
+            ),
+        }
+    }
+}
+
+/// Finds the elements that overflow the page, images that are missing and text that
+/// could not be rendered because none of its fonts were found. For elements that are cut
+/// off at the bottom only the innermost element is reported, for elements that are
+/// entirely below the page only the outermost one.
+fn get_layout_diagnostics(
+    layout_result: &LayoutResult,
+    renderer_resources: &RendererResources,
+    elements: &HtmlElements,
+    page_width: Pt,
+    page_height: Pt,
+) -> Vec<HtmlDiagnostic> {
+    use azul_core::styled_dom::StyleFontFamiliesHash;
+    use azul_css::StyleFontFamily;
+
+    // layout coordinates (top left origin) are in points
+    const EPSILON: f32 = 0.5;
+
+    let node_data = layout_result.styled_dom.node_data.as_container();
+    let styled_nodes = layout_result.styled_dom.styled_nodes.as_container();
+    let hierarchy = layout_result.styled_dom.node_hierarchy.as_container();
+    let rects = layout_result.rects.as_ref();
+    let count = node_data.len();
+
+    let get_element = |node_id: NodeId| {
+        node_data[node_id]
+            .get_ids_and_classes()
+            .as_ref()
+            .iter()
+            .find_map(|id_or_class| match id_or_class {
+                IdOrClass::Class(class) => class
+                    .as_str()
+                    .strip_prefix(ELEMENT_CLASS_PREFIX)
+                    .and_then(|index| index.parse::<usize>().ok()),
+                IdOrClass::Id(_) => None,
+            })
+    };
+    let get_path = |element: Option<usize>| {
+        element
+            .and_then(|index| elements.paths.get(index))
+            .cloned()
+            .unwrap_or_default()
+    };
+    let is_visible = |node_id: NodeId| {
+        !is_display_none(
+            layout_result,
+            &node_data[node_id],
+            node_id,
+            &styled_nodes[node_id],
+        ) && !is_visibility_hidden(layout_result, node_id)
+    };
+    let get_bounds = |node_id: NodeId| {
+        let rect = &rects[node_id];
+        let offset = rect.position.get_static_offset();
+        (
+            offset.y,
+            offset.x + rect.size.width,
+            offset.y + rect.size.height,
+        )
+    };
+
+    // elements that have a child element that is cut off at the bottom
+    let mut has_overflowing_child = vec![false; count];
+    for i in 0..count {
+        let node_id = NodeId::new(i);
+        let (_, _, bottom) = get_bounds(node_id);
+        if node_data[node_id].is_text_node() || bottom <= page_height.0 + EPSILON {
+            continue;
+        }
+        if let Some(parent) = hierarchy[node_id].parent_id() {
+            has_overflowing_child[parent.index()] = true;
+        }
+    }
+
+    let mut diagnostics = Vec::new();
+    for i in 0..count {
+        let node_id = NodeId::new(i);
+        if !is_visible(node_id) {
+            continue;
+        }
+        let html_node = &node_data[node_id];
+        let parent = hierarchy[node_id].parent_id();
+        let mut push = |element: Option<usize>, reason: HtmlDiagnosticReason| {
+            diagnostics.push(HtmlDiagnostic {
+                page: 0,
+                path: get_path(element),
+                reason,
+            })
+        };
+
+        if html_node.is_text_node() {
+            let font_families = layout_result
+                .styled_dom
+                .get_css_property_cache()
+                .get_font_id_or_default(html_node, &node_id, &styled_nodes[node_id].state);
+            let found = renderer_resources
+                .get_font_family(&StyleFontFamiliesHash::new(font_families.as_slice()))
+                .and_then(|family| renderer_resources.get_font_key(family))
+                .is_some();
+            if !found {
+                let families = font_families
+                    .as_slice()
+                    .iter()
+                    .filter_map(|family| match family {
+                        StyleFontFamily::System(s) | StyleFontFamily::File(s) => {
+                            Some(s.as_str().to_string())
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                push(
+                    parent.and_then(get_element),
+                    HtmlDiagnosticReason::MissingFont { families },
+                );
+            }
+            continue;
+        }
+
+        let element = get_element(node_id);
+        if let Some(key) = element.and_then(|index| elements.missing_images.get(&index)) {
+            push(
+                element,
+                HtmlDiagnosticReason::MissingImage { key: key.clone() },
+            );
+        }
+
+        let (top, right, bottom) = get_bounds(node_id);
+        if bottom > page_height.0 + EPSILON {
+            let below = top >= page_height.0;
+            let parent_below = parent.is_some_and(|p| get_bounds(p).0 >= page_height.0);
+            let report = if below {
+                !parent_below
+            } else {
+                !has_overflowing_child[i]
+            };
+            if report {
+                let overflow = Pt(bottom - top.max(page_height.0));
+                push(element, HtmlDiagnosticReason::Overflow { overflow });
+            }
+        }
+        let parent_clipped = parent.is_some_and(|p| get_bounds(p).1 > page_width.0 + EPSILON);
+        if right > page_width.0 + EPSILON && !parent_clipped {
+            let overflow = Pt(right - page_width.0);
+            push(element, HtmlDiagnosticReason::Clipped { overflow });
+        }
+    }
+
+    diagnostics
 }
 
 fn get_system_fonts() -> Vec<(FcPattern, FcFont)> {
@@ -235,92 +771,1878 @@ fn get_fcpat(b: BuiltinFont) -> (FcPattern, FcFont) {
     )
 }
 
-#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
-pub struct ImageInfo {
-    pub original_id: String,
-    pub xobject_id: String,
-    pub image_type: ImageTypeInfo,
-    pub width: usize,
-    pub height: usize,
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ImageInfo {
+    pub original_id: String,
+    pub xobject_id: String,
+    pub image_type: ImageTypeInfo,
+    pub width: usize,
+    pub height: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum ImageTypeInfo {
+    Image,
+    Svg,
+}
+
+impl Default for ImageTypeInfo {
+    fn default() -> Self {
+        ImageTypeInfo::Image
+    }
+}
+
+/// Adds the images of the `config` to the document resources, images that fail to decode
+/// are skipped and reported as `HtmlDiagnosticReason::InvalidImage`
+pub(crate) fn embed_images(
+    doc: &mut PdfDocument,
+    config: &XmlRenderOptions,
+    diagnostics: &mut Vec<HtmlDiagnostic>,
+) -> BTreeMap<String, ImageInfo> {
+    let mut images = BTreeMap::new();
+
+    for (k, image_bytes) in config.images.iter() {
+        let opt_svg = std::str::from_utf8(&image_bytes)
+            .ok()
+            .and_then(|s| crate::Svg::parse(s).ok());
+
+        let img_info = match opt_svg {
+            Some(o) => {
+                let width = o.width.map(|s| s.0).unwrap_or(0);
+                let height = o.height.map(|s| s.0).unwrap_or(0);
+                let image_xobject_id = doc.add_xobject(&o);
+                ImageInfo {
+                    original_id: k.clone(),
+                    xobject_id: image_xobject_id.0,
+                    image_type: ImageTypeInfo::Svg,
+                    width,
+                    height,
+                }
+            }
+            None => {
+                let raw_image = match crate::image::RawImage::decode_from_bytes(&image_bytes) {
+                    Ok(o) => o,
+                    Err(error) => {
+                        diagnostics.push(HtmlDiagnostic {
+                            page: 0,
+                            path: String::new(),
+                            reason: HtmlDiagnosticReason::InvalidImage {
+                                key: k.clone(),
+                                error,
+                            },
+                        });
+                        continue;
+                    }
+                };
+
+                let width = raw_image.width;
+                let height = raw_image.height;
+                let image_xobject_id = doc.add_image(&raw_image);
+                ImageInfo {
+                    original_id: k.clone(),
+                    xobject_id: image_xobject_id.0,
+                    image_type: ImageTypeInfo::Image,
+                    width,
+                    height,
+                }
+            }
+        };
+
+        images.insert(k.clone(), img_info);
+    }
+
+    images
+}
+
+/// Removes the images of `embed_images` that are not used by the pages of the document
+/// or the `pages` (i.e. the images of a `srcset` that were not selected)
+pub(crate) fn remove_unused_images(
+    doc: &mut PdfDocument,
+    images: &BTreeMap<String, ImageInfo>,
+    pages: &[PdfPage],
+) {
+    let used = doc
+        .pages
+        .iter()
+        .chain(pages)
+        .flat_map(|page| page.ops.iter())
+        .filter_map(|op| match op {
+            Op::UseXObject { id, .. } => Some(id.0.as_str()),
+            _ => None,
+        })
+        .collect::<std::collections::BTreeSet<_>>();
+    for image in images.values() {
+        if !used.contains(image.xobject_id.as_str()) {
+            let id = crate::XObjectId(image.xobject_id.clone());
+            doc.resources.xobjects.map.remove(&id);
+        }
+    }
+}
+
+fn fixup_xml(s: &str, images: &BTreeMap<String, ImageInfo>) -> String {
+    let s = if !s.contains("<body>") {
+        format!("<body>{s}</body>")
+    } else {
+        s.trim().to_string()
+    };
+    let s = if !s.contains("<html>") {
+        format!("<html>{s}</html>")
+    } else {
+        s.trim().to_string()
+    };
+
+    let mut s = s.trim().to_string();
+
+    for (k, img_info) in images.iter() {
+        let json = serde_json::to_string(img_info).unwrap_or_default();
+
+        s = s
+            .replace(&format!("src='{k}'"), &format!("src='{json}'"))
+            .replace(&format!("src=\"{k}\""), &format!("src='{json}'"));
+    }
+
+    s
+}
+
+/// Class that is added to elements that are `visibility: hidden` (including the inherited
+/// visibility, descendants with `visibility: visible` don't get the class)
+const HIDDEN_CLASS: &str = "__printpdf_hidden";
+
+/// Removes the elements with `display: none` in their `style` attribute or with a `hidden`
+/// attribute (including their children), so that they don't take up space and don't
+/// create links, anchors or structure elements. Elements that are `visibility: hidden`
+/// keep their space, the `visibility` is moved into the `__printpdf_hidden` marker class.
+fn prune_hidden_elements(xml: &str) -> String {
+    // visibility of the open elements (`true` = hidden)
+    let mut hidden = Vec::new();
+    // start of the removed element and the number of open elements before it
+    let mut removed: Option<(usize, usize)> = None;
+
+    let pruned = rewrite_elements(xml, |tag, replacements| {
+        let tag = match tag {
+            Tag::Start(tag) => tag,
+            Tag::End(_, span) => {
+                hidden.pop();
+                if let Some((start, _)) = removed.filter(|(_, depth)| hidden.len() == *depth) {
+                    replacements.push((start..span.end, String::new()));
+                    removed = None;
+                }
+                return;
+            }
+        };
+        if removed.is_some() {
+            if tag.is_open {
+                hidden.push(true);
+            }
+            return;
+        }
+
+        let declarations = tag.get("style").map(parse_style).unwrap_or_default();
+        if tag.get("hidden").is_some() || get_style(&declarations, "display") == Some("none") {
+            if tag.is_open {
+                removed = Some((tag.start, hidden.len()));
+                hidden.push(true);
+            } else {
+                replacements.push((tag.start..tag.end.end, String::new()));
+            }
+            return;
+        }
+
+        let inherited = hidden.last().copied().unwrap_or(false);
+        let visibility = get_style(&declarations, "visibility");
+        let is_hidden = match visibility {
+            Some("hidden" | "collapse") => true,
+            Some("visible") => false,
+            _ => inherited,
+        };
+        if tag.is_open {
+            hidden.push(is_hidden);
+        }
+        if visibility.is_some() {
+            let style = declarations
+                .iter()
+                .filter(|(k, _, _)| k != "visibility")
+                .map(|(_, _, d)| *d)
+                .collect::<Vec<_>>()
+                .join("; ");
+            let quote = if style.contains('"') { '\'' } else { '"' };
+            replacements.push(tag.set("style", format!("style={quote}{style}{quote}")));
+        }
+        if is_hidden {
+            replacements.push(tag.add_class(HIDDEN_CLASS));
+        }
+    });
+    pruned.unwrap_or_else(|| xml.to_string())
+}
+
+/// Returns whether a node is `visibility: hidden`, text nodes are hidden with their parent
+fn is_visibility_hidden(layout_result: &LayoutResult, node_id: NodeId) -> bool {
+    let node_data = layout_result.styled_dom.node_data.as_container();
+    let has_marker = |id: NodeId| {
+        node_data[id]
+            .get_ids_and_classes()
+            .as_ref()
+            .iter()
+            .any(|c| matches!(c, IdOrClass::Class(class) if class.as_str() == HIDDEN_CLASS))
+    };
+    if has_marker(node_id) {
+        return true;
+    }
+    matches!(
+        node_data[node_id].get_node_type(),
+        azul_core::dom::NodeType::Text(_)
+    ) && layout_result.styled_dom.node_hierarchy.as_container()[node_id]
+        .parent_id()
+        .is_some_and(has_marker)
+}
+
+/// Hyperlink (`<a href="...">`) found in the HTML layout
+#[derive(Debug, Clone, PartialEq)]
+pub struct HtmlLink {
+    /// Index of the page (in the returned pages) that the link is on
+    pub page: usize,
+    /// Clickable area of the link, from the lower left corner of the page
+    pub rect: Rect,
+    /// Target of the link (`https://...` or `#anchor`)
+    pub href: String,
+}
+
+/// Element with an `id` attribute, can be targeted by `href="#id"` links
+#[derive(Debug, Clone, PartialEq)]
+pub struct HtmlAnchor {
+    /// Index of the page (in the returned pages) that the anchor is on
+    pub page: usize,
+    /// Value of the `id` attribute
+    pub name: String,
+    /// Bounds of the element, from the lower left corner of the page
+    pub rect: Rect,
+}
+
+/// Links and anchors of a rendered HTML document
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HtmlLinkInfo {
+    pub links: Vec<HtmlLink>,
+    pub anchors: Vec<HtmlAnchor>,
+}
+
+impl HtmlLinkInfo {
+    /// Returns the anchor with the given name (the `id` of the element)
+    pub fn get_anchor(&self, name: &str) -> Option<&HtmlAnchor> {
+        self.anchors.iter().find(|a| a.name == name)
+    }
+
+    /// Converts the links into `(page index, LinkAnnotation)` pairs: `#name` links jump to the
+    /// top of the matching anchor, all other links are opened as URIs.
+    ///
+    /// Links to anchors that do not exist are skipped.
+    pub fn get_link_annotations(&self) -> Vec<(usize, LinkAnnotation)> {
+        self.links
+            .iter()
+            .filter_map(|link| {
+                let actions = match link.href.strip_prefix('#') {
+                    Some(name) => {
+                        let anchor = self.get_anchor(name)?;
+                        Actions::go_to(Destination::XYZ {
+                            page: anchor.page + 1,
+                            left: Some(anchor.rect.x.0),
+                            top: Some(anchor.rect.y.0 + anchor.rect.height.0),
+                            zoom: None,
+                        })
+                    }
+                    None => Actions::uri(link.href.clone()),
+                };
+                let annotation = LinkAnnotation::new(link.rect.clone(), actions, None, None, None);
+                Some((link.page, annotation))
+            })
+            .collect()
+    }
+}
+
+/// Class that is added to every element (followed by the index of its path), so that the
+/// diagnostics can refer to the element of the source HTML
+const ELEMENT_CLASS_PREFIX: &str = "__printpdf_el_";
+
+/// Elements of the source HTML, see `mark_elements`
+#[derive(Debug, Default, PartialEq)]
+struct HtmlElements {
+    /// CSS selectors of the elements (indexed by the number of the marker class)
+    paths: Vec<String>,
+    /// `src` of the `<img>` elements that are not in the images (by element index)
+    missing_images: BTreeMap<usize, String>,
+}
+
+/// Adds a `__printpdf_el_N` marker class to every element and collects the images whose
+/// `src` (and `srcset`) was not resolved by `fixup_xml`
+fn mark_elements(xml: &str, images: &BTreeMap<String, ImageInfo>) -> (String, HtmlElements) {
+    let mut elements = HtmlElements::default();
+    // path and number of child elements of the open elements
+    let mut open: Vec<(String, usize)> = Vec::new();
+
+    let marked = rewrite_elements(xml, |tag, replacements| {
+        let Tag::Start(tag) = tag else {
+            open.pop();
+            return;
+        };
+        let index = elements.paths.len();
+        let nth = match open.last_mut() {
+            Some((_, children)) => {
+                *children += 1;
+                *children
+            }
+            None => 1,
+        };
+        let segment = match (tag.name, tag.get("id")) {
+            (name, Some(id)) => format!("{name}#{id}"),
+            (name @ ("html" | "body"), None) => name.to_string(),
+            (name, None) => format!("{name}:nth-child({nth})"),
+        };
+        let path = match open.last() {
+            Some((parent, _)) => format!("{parent} > {segment}"),
+            None => segment,
+        };
+
+        // resolved images were replaced with their JSON description
+        let is_resolved = |url: &str| url.starts_with('{') || images.contains_key(url);
+        let has_candidate = tag.get("srcset").is_some_and(|srcset| {
+            srcset
+                .split(',')
+                .filter_map(|candidate| candidate.split_whitespace().next())
+                .any(is_resolved)
+        });
+        if let Some(src) = tag.get("src").filter(|src| !is_resolved(src)) {
+            if tag.name == "img" && !has_candidate {
+                elements.missing_images.insert(index, src.to_string());
+            }
+        }
+
+        replacements.push(tag.add_class(&format!("{ELEMENT_CLASS_PREFIX}{index}")));
+        elements.paths.push(path.clone());
+        if tag.is_open {
+            open.push((path, 0));
+        }
+    });
+    match marked {
+        Some(marked) => (marked, elements),
+        None => (xml.to_string(), HtmlElements::default()),
+    }
+}
+
+/// Class that is added to elements with a `href` attribute (followed by the index of the link)
+const LINK_CLASS_PREFIX: &str = "__printpdf_link_";
+
+/// Replaces `<a href="...">` with the inline `<span class="__printpdf_link_N">`, returns the
+/// new XML and the (unescaped) hrefs, indexed by N
+fn extract_links(xml: &str) -> (String, Vec<String>) {
+    let mut hrefs = Vec::new();
+    let rewritten = rewrite_elements(xml, |tag, replacements| match tag {
+        Tag::End("a", span) => replacements.push((span, "</span>".to_string())),
+        Tag::Start(tag) if tag.name == "a" => {
+            // "<a" -> "<span"
+            replacements.push((tag.start..tag.start + 2, "<span".to_string()));
+            if let Some((span, href)) = tag.get_with_span("href") {
+                replacements.push((span, String::new()));
+                replacements.push(tag.add_class(&format!("{LINK_CLASS_PREFIX}{}", hrefs.len())));
+                hrefs.push(crate::components::unescape_xml(href));
+            }
+        }
+        _ => {}
+    });
+    match rewritten {
+        Some(rewritten) => (rewritten, hrefs),
+        None => (xml.to_string(), Vec::new()),
+    }
+}
+
+/// Class that is added to elements with a `z-index` in their `style` attribute (followed
+/// by the z-index, i.e. "__printpdf_z_2" or "__printpdf_z_-1")
+const Z_INDEX_CLASS_PREFIX: &str = "__printpdf_z_";
+
+/// Removes the `z-index` declarations from the `style` attributes (the layout doesn't
+/// support them) and adds `__printpdf_z_N` marker classes instead
+fn extract_z_indices(xml: &str) -> String {
+    rewrite_elements(xml, |tag, replacements| {
+        let Tag::Start(tag) = tag else {
+            return;
+        };
+        let Some((style_range, style)) = tag.get_with_span("style") else {
+            return;
+        };
+        let mut z_index = None;
+        let declarations = style
+            .split(';')
+            .filter(|d| match d.split_once(':') {
+                Some((k, v)) if k.trim() == "z-index" => {
+                    z_index = v.trim().parse::<i32>().ok();
+                    false
+                }
+                _ => true,
+            })
+            .collect::<Vec<_>>();
+        let Some(z_index) = z_index else {
+            return;
+        };
+        let quote = if style.contains('"') { '\'' } else { '"' };
+        replacements.push((
+            style_range,
+            format!("style={quote}{}{quote}", declarations.join(";")),
+        ));
+        replacements.push(tag.add_class(&format!("{Z_INDEX_CLASS_PREFIX}{z_index}")));
+    })
+    .unwrap_or_else(|| xml.to_string())
+}
+
+/// Resolves `min-width`, `max-width`, `min-height`, `max-height` and `aspect-ratio` of
+/// inline styles into fixed `width` / `height` declarations (the layout solver ignores
+/// them). Percentages are resolved against the content width of the parent element,
+/// which is the page width for the root. Auto heights are only known after the layout,
+/// so `min-height` / `max-height` only clamp explicit heights.
+fn resolve_size_constraints(xml: &str, page_width: Pt) -> String {
+    // content width of the open elements (`None` if it is unknown)
+    let mut widths: Vec<Option<f32>> = Vec::new();
+
+    rewrite_elements(xml, |tag, replacements| {
+        let Tag::Start(tag) = tag else {
+            widths.pop();
+            return;
+        };
+        let parent = widths.last().copied().unwrap_or(Some(page_width.0));
+        let content_width = match tag.get_with_span("style") {
+            Some((range, style)) => {
+                let (width, new_style) = constrain_size(style, parent);
+                if let Some(new_style) = new_style {
+                    let quote = if new_style.contains('"') { '\'' } else { '"' };
+                    replacements.push((range, format!("style={quote}{new_style}{quote}")));
+                }
+                width
+            }
+            None => parent,
+        };
+        if tag.is_open {
+            widths.push(content_width);
+        }
+    })
+    .unwrap_or_else(|| xml.to_string())
+}
+
+/// Returns the content width of the element and the rewritten style (`None` if the
+/// style doesn't need to change)
+fn constrain_size(style: &str, parent: Option<f32>) -> (Option<f32>, Option<String>) {
+    let declarations = parse_style(style);
+    let get = |key: &str| get_style(&declarations, key);
+    let length = |key: &str, basis: Option<f32>| get(key).and_then(|v| parse_css_length(v, basis));
+    // sum of the left and right values of the `margin` / `padding` shorthand and longhands
+    let horizontal = |property: &str| {
+        let values = get(property)
+            .map(|v| v.split_whitespace().collect::<Vec<_>>())
+            .unwrap_or_default();
+        let (right, left) = match values.as_slice() {
+            [all] => (Some(*all), Some(*all)),
+            [_, x] | [_, x, _] => (Some(*x), Some(*x)),
+            [_, r, _, l] => (Some(*r), Some(*l)),
+            _ => (None, None),
+        };
+        let side = |name: &str, shorthand: Option<&str>| {
+            get(&format!("{property}-{name}"))
+                .or(shorthand)
+                .and_then(|v| parse_css_length(v, parent))
+                .unwrap_or(0.0)
+        };
+        side("left", left) + side("right", right)
+    };
+    let clamp = |v: f32, min: f32, max: f32| v.min(max).max(min);
+
+    let width = length("width", parent);
+    // percentages of the parent height can't be resolved before the layout
+    let height = length("height", None);
+    let min_w = length("min-width", parent).unwrap_or(0.0);
+    let max_w = length("max-width", parent).unwrap_or(f32::INFINITY);
+    let min_h = length("min-height", None).unwrap_or(0.0);
+    let max_h = length("max-height", None).unwrap_or(f32::INFINITY);
+    let ratio = get("aspect-ratio").and_then(parse_aspect_ratio);
+    let auto_width = parent.map(|p| (p - horizontal("margin") - horizontal("padding")).max(0.0));
+
+    let mut new_width = width.map(|w| clamp(w, min_w, max_w));
+    let mut new_height = height.map(|h| clamp(h, min_h, max_h));
+    if new_width.is_none() {
+        new_width = auto_width
+            .map(|a| clamp(a, min_w, max_w))
+            .filter(|w| Some(*w) != auto_width);
+    }
+    match (ratio, width, height) {
+        (Some(r), None, Some(_)) => new_width = new_height.map(|h| clamp(h * r, min_w, max_w)),
+        (Some(r), _, None) => {
+            new_height = new_width.or(auto_width).map(|w| clamp(w / r, min_h, max_h))
+        }
+        _ => {}
+    }
+    let content_width = new_width.or(auto_width);
+
+    if new_width == width && new_height == height && ratio.is_none() {
+        return (content_width, None);
+    }
+    let mut out = declarations
+        .iter()
+        .filter(|(k, _, _)| match k.as_str() {
+            "aspect-ratio" => false,
+            "width" => new_width.is_none(),
+            "height" => new_height.is_none(),
+            _ => true,
+        })
+        .map(|(_, _, d)| d.to_string())
+        .collect::<Vec<_>>();
+    let px = |v: f32| format!("{}px", (v * 100.0).round() / 100.0);
+    if let Some(w) = new_width {
+        out.push(format!("width: {}", px(w)));
+    }
+    if let Some(h) = new_height {
+        out.push(format!("height: {}", px(h)));
+    }
+    (content_width, Some(out.join("; ")))
+}
+
+/// How long words are broken (`overflow-wrap`, `word-break` and `hyphens`, inherited)
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+struct WordBreaking {
+    /// `overflow-wrap: break-word | anywhere` or `word-break: break-all | break-word`
+    break_words: bool,
+    /// `hyphens: auto`, only has an effect with the `hyphenation` feature
+    hyphens: bool,
+}
+
+/// Inherited text properties of an element, see `break_long_words`
+#[derive(Debug, Copy, Clone)]
+struct TextContext {
+    /// Content width of the element (`None` if it is unknown)
+    width: Option<f32>,
+    font_size: f32,
+    breaking: WordBreaking,
+}
+
+/// Default font size of the layout solver
+const DEFAULT_FONT_SIZE: f32 = 16.0;
+
+/// Width of a character relative to the font size that is used to estimate how many
+/// characters fit on a line. It is on the wide side, so that a broken part of a word
+/// always fits, while two parts don't fit on one line.
+const MAX_CHAR_WIDTH: f32 = 0.7;
+
+/// Breaks words that are longer than a line (URLs, IBANs) for elements with
+/// `overflow-wrap: break-word` / `word-break: break-all` (at the end of the line) or
+/// `hyphens: auto` (at a syllable with a hyphen, English hyphenation patterns). The layout
+/// solver only breaks lines at spaces, so spaces are inserted where the line is estimated
+/// to be full. `word-break: break-all` is treated like `overflow-wrap: break-word`.
+fn break_long_words(xml: &str, page_width: Pt) -> String {
+    use xmlparser::{ElementEnd, Token, Tokenizer};
+
+    let mut replacements = Vec::new();
+    let mut stack: Vec<TextContext> = Vec::new();
+    let mut element = "";
+    let mut style = None;
+
+    for token in Tokenizer::from(xml) {
+        let token = match token {
+            Ok(o) => o,
+            Err(_) => return xml.to_string(),
+        };
+        let parent = stack.last().copied().unwrap_or(TextContext {
+            width: Some(page_width.0),
+            font_size: DEFAULT_FONT_SIZE,
+            breaking: WordBreaking::default(),
+        });
+        match token {
+            Token::ElementStart { local, .. } => {
+                element = local.as_str();
+                style = None;
+            }
+            Token::Attribute {
+                local, value, span, ..
+            } if local.as_str() == "style" => {
+                style = Some((span.start()..span.end(), value.as_str()));
+            }
+            Token::ElementEnd {
+                end: ElementEnd::Close(..),
+                ..
+            } => {
+                stack.pop();
+            }
+            Token::ElementEnd { end, .. } => {
+                let mut context = TextContext {
+                    font_size: parent.font_size * get_default_font_scale(element),
+                    ..parent
+                };
+                if let Some((range, style)) = style.take() {
+                    let declarations = parse_style(style);
+                    context.width = constrain_size(style, parent.width).0;
+                    if let Some(size) = get_style(&declarations, "font-size") {
+                        context.font_size =
+                            parse_font_size(size, parent.font_size).unwrap_or(context.font_size);
+                    }
+                    let breaking = &mut context.breaking;
+                    let mut removed = false;
+                    for (property, value, _) in declarations.iter() {
+                        match (property.as_str(), *value) {
+                            ("overflow-wrap" | "word-wrap", v) => {
+                                breaking.break_words = matches!(v, "break-word" | "anywhere")
+                            }
+                            ("word-break", v) => {
+                                breaking.break_words = matches!(v, "break-all" | "break-word")
+                            }
+                            ("hyphens", v) => breaking.hyphens = v == "auto",
+                            _ => continue,
+                        }
+                        removed = true;
+                    }
+                    if removed {
+                        let rest = declarations
+                            .iter()
+                            .filter(|(k, _, _)| {
+                                !matches!(
+                                    k.as_str(),
+                                    "overflow-wrap" | "word-wrap" | "word-break" | "hyphens"
+                                )
+                            })
+                            .map(|(_, _, d)| *d)
+                            .collect::<Vec<_>>();
+                        let quote = if style.contains('"') { '\'' } else { '"' };
+                        replacements
+                            .push((range, format!("style={quote}{}{quote}", rest.join("; "))));
+                    }
+                }
+                if matches!(end, ElementEnd::Open) {
+                    stack.push(context);
+                }
+            }
+            Token::Text { text } => {
+                let (Some(width), true) = (
+                    parent.width,
+                    parent.breaking.break_words || parent.breaking.hyphens,
+                ) else {
+                    continue;
+                };
+                let capacity = (width / (parent.font_size * MAX_CHAR_WIDTH)).floor() as usize;
+                if let Some(broken) = break_words(text.as_str(), capacity.max(1), parent.breaking) {
+                    replacements.push((text.start()..text.end(), broken));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    apply_replacements(xml, replacements)
+}
+
+/// Inserts spaces (or hyphens and spaces) into words that are longer than `capacity`
+/// characters, `None` if the text doesn't change
+fn break_words(text: &str, capacity: usize, breaking: WordBreaking) -> Option<String> {
+    let mut out = String::new();
+    let mut changed = false;
+    for piece in text.split_inclusive(char::is_whitespace) {
+        let word = piece.trim_end_matches(char::is_whitespace);
+        // entities like "&amp;" are one character
+        let mut units = Vec::new();
+        let mut rest = word;
+        while let Some(c) = rest.chars().next() {
+            let len = match rest.find(';') {
+                Some(end) if c == '&' && end < 10 => end + 1,
+                _ => c.len_utf8(),
+            };
+            units.push(&rest[..len]);
+            rest = &rest[len..];
+        }
+
+        let hyphens = match breaking.hyphens && units.len() > capacity {
+            true => get_hyphenation_points(word),
+            false => Vec::new(),
+        };
+        let mut start = 0;
+        while units.len() - start > capacity {
+            let hyphen = hyphens
+                .iter()
+                .rev()
+                .find(|b| **b > start && **b - start < capacity);
+            let end = match (hyphen, breaking.break_words) {
+                (Some(b), _) => *b,
+                (None, true) => start + capacity,
+                (None, false) => break,
+            };
+            out.extend(units[start..end].iter().copied());
+            out.push_str(if hyphen.is_some() { "- " } else { " " });
+            start = end;
+            changed = true;
+        }
+        out.extend(units[start..].iter().copied());
+        out.push_str(&piece[word.len()..]);
+    }
+    changed.then_some(out)
+}
+
+/// Character indices at which the word can be hyphenated
+#[cfg(feature = "hyphenation")]
+fn get_hyphenation_points(word: &str) -> Vec<usize> {
+    use hyphenation::{Hyphenator, Language, Load, Standard};
+    use std::sync::OnceLock;
+
+    static DICTIONARY: OnceLock<Option<Standard>> = OnceLock::new();
+
+    // words with entities are not hyphenated
+    if word.contains('&') {
+        return Vec::new();
+    }
+    let Some(dictionary) =
+        DICTIONARY.get_or_init(|| Standard::from_embedded(Language::EnglishUS).ok())
+    else {
+        return Vec::new();
+    };
+    dictionary
+        .hyphenate(word)
+        .breaks
+        .iter()
+        .map(|b| word[..*b].chars().count())
+        .collect()
+}
+
+#[cfg(not(feature = "hyphenation"))]
+fn get_hyphenation_points(_word: &str) -> Vec<usize> {
+    Vec::new()
+}
+
+/// Font size of headings and `<small>` relative to the parent
+fn get_default_font_scale(element: &str) -> f32 {
+    match element {
+        "h1" => 2.0,
+        "h2" => 1.5,
+        "h3" => 1.17,
+        "h5" | "small" => 0.83,
+        "h6" => 0.67,
+        _ => 1.0,
+    }
+}
+
+/// Parses the `font-size` (lengths, percentages, `em` and `rem`)
+fn parse_font_size(value: &str, parent: f32) -> Option<f32> {
+    let value = value.trim();
+    if let Some(rem) = value.strip_suffix("rem") {
+        return rem
+            .trim()
+            .parse::<f32>()
+            .ok()
+            .map(|n| n * DEFAULT_FONT_SIZE);
+    }
+    if let Some(em) = value.strip_suffix("em") {
+        return em.trim().parse::<f32>().ok().map(|n| n * parent);
+    }
+    parse_css_length(value, Some(parent))
+}
+
+/// Declarations of an inline style as (lowercase property, value, declaration)
+fn parse_style(style: &str) -> Vec<(String, &str, &str)> {
+    style
+        .split(';')
+        .map(|d| d.trim())
+        .filter(|d| !d.is_empty())
+        .map(|d| match d.split_once(':') {
+            Some((k, v)) => (k.trim().to_ascii_lowercase(), v.trim(), d),
+            None => (String::new(), "", d),
+        })
+        .collect()
+}
+
+/// Value of the last declaration of the `property`
+fn get_style<'a>(declarations: &[(String, &'a str, &str)], property: &str) -> Option<&'a str> {
+    declarations
+        .iter()
+        .rev()
+        .find(|(k, _, _)| k == property)
+        .map(|(_, v, _)| *v)
+}
+
+/// Parses a CSS length into layout pixels (the page is laid out with one pixel per point),
+/// `None` for `auto` and font-relative units
+fn parse_css_length(value: &str, percent_basis: Option<f32>) -> Option<f32> {
+    let value = value.trim_end_matches("!important").trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-' || c == '+'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number = number.parse::<f32>().ok()?;
+    let px_per_unit = match unit.trim() {
+        "px" | "" => 1.0,
+        "pt" => 96.0 / 72.0,
+        "in" => 96.0,
+        "cm" => 96.0 / 2.54,
+        "mm" => 96.0 / 25.4,
+        "%" => percent_basis? / 100.0,
+        _ => return None,
+    };
+    Some(number * px_per_unit)
+}
+
+/// Parses `aspect-ratio: 16 / 9` or `aspect-ratio: 1.5` into width / height
+fn parse_aspect_ratio(value: &str) -> Option<f32> {
+    let value = value.trim_start_matches("auto").trim();
+    let ratio = match value.split_once('/') {
+        Some((w, h)) => w.trim().parse::<f32>().ok()? / h.trim().parse::<f32>().ok()?,
+        None => value.parse::<f32>().ok()?,
+    };
+    (ratio.is_finite() && ratio > 0.0).then_some(ratio)
+}
+
+/// Class that is added to `<img>` elements with an `object-fit` (followed by the value,
+/// i.e. "__printpdf_fit_cover")
+const OBJECT_FIT_CLASS_PREFIX: &str = "__printpdf_fit_";
+
+/// How an image is fitted into the box of its `<img>` element (`object-fit`)
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+enum ObjectFit {
+    /// The image is stretched to the size of the box
+    #[default]
+    Fill,
+    /// The image is scaled to fit into the box, keeping its aspect ratio
+    Contain,
+    /// The image is scaled to cover the box, keeping its aspect ratio (and clipped)
+    Cover,
+    /// The image keeps its natural size (and is clipped)
+    None,
+    /// Like `None` or `Contain`, whichever is smaller
+    ScaleDown,
+}
+
+impl ObjectFit {
+    fn from_id(id: &str) -> Option<Self> {
+        match id {
+            "fill" => Some(ObjectFit::Fill),
+            "contain" => Some(ObjectFit::Contain),
+            "cover" => Some(ObjectFit::Cover),
+            "none" => Some(ObjectFit::None),
+            "scale-down" => Some(ObjectFit::ScaleDown),
+            _ => None,
+        }
+    }
+
+    fn get_id(&self) -> &'static str {
+        match self {
+            ObjectFit::Fill => "fill",
+            ObjectFit::Contain => "contain",
+            ObjectFit::Cover => "cover",
+            ObjectFit::None => "none",
+            ObjectFit::ScaleDown => "scale-down",
+        }
+    }
+
+    /// Returns the size of the image (centered in the box, parts outside of the box are
+    /// clipped), `source` is the natural size of the image and `target` the size of the box
+    fn fit(self, source: (f32, f32), target: (f32, f32)) -> (f32, f32) {
+        let ((sw, sh), (tw, th)) = (source, target);
+        let contain = (tw / sw).min(th / sh);
+        let scale = match self {
+            ObjectFit::Fill => return target,
+            ObjectFit::Contain => contain,
+            ObjectFit::Cover => (tw / sw).max(th / sh),
+            ObjectFit::None => 1.0,
+            ObjectFit::ScaleDown => contain.min(1.0),
+        };
+        (sw * scale, sh * scale)
+    }
+}
+
+fn get_object_fit(html_node: &NodeData) -> ObjectFit {
+    html_node
+        .get_ids_and_classes()
+        .as_ref()
+        .iter()
+        .find_map(|id_or_class| match id_or_class {
+            IdOrClass::Class(class) => class
+                .as_str()
+                .strip_prefix(OBJECT_FIT_CLASS_PREFIX)
+                .and_then(ObjectFit::from_id),
+            IdOrClass::Id(_) => None,
+        })
+        .unwrap_or_default()
+}
+
+/// Selects the image of `<img srcset="...">` elements: the smallest image that has at
+/// least `dpi` pixels per inch at the rendered width (or the largest image, if none of them
+/// is large enough) replaces the `src`. The rendered width is the `width` of the element,
+/// the natural width of the `x` candidates, the `sizes` or the page width. Images without
+/// a width get the rendered width, so that `2x` images are not rendered twice as large.
+///
+/// Also moves the `object-fit` of the inline styles into `__printpdf_fit_*` marker
+/// classes, see `ObjectFit::fit`.
+fn resolve_images(
+    xml: &str,
+    images: &BTreeMap<String, ImageInfo>,
+    page_width: Pt,
+    dpi: f32,
+) -> String {
+    rewrite_elements(xml, |tag, replacements| {
+        let Tag::Start(tag) = tag else {
+            return;
+        };
+        if tag.name != "img" {
+            return;
+        }
+        let declarations = tag.get("style").map(parse_style).unwrap_or_default();
+        let mut style = declarations
+            .iter()
+            .filter(|(k, _, _)| k != "object-fit")
+            .map(|(_, _, d)| d.to_string())
+            .collect::<Vec<_>>();
+        let object_fit = get_style(&declarations, "object-fit").and_then(ObjectFit::from_id);
+        let width = get_style(&declarations, "width")
+            .or(tag.get("width"))
+            .and_then(|w| parse_css_length(w, Some(page_width.0)));
+        let has_height =
+            get_style(&declarations, "height").is_some() || tag.get("height").is_some();
+
+        let selected = tag.get("srcset").and_then(|srcset| {
+            let (src, sizes) = (tag.get("src"), tag.get("sizes"));
+            select_image(srcset, src, sizes, width, images, page_width, dpi)
+        });
+        if let Some((_, rendered_width)) = selected.as_ref() {
+            if width.is_none() && !has_height {
+                style.push(format!("width: {rendered_width}px"));
+            }
+        }
+
+        if let Some((info, _)) = selected {
+            let json = serde_json::to_string(&info).unwrap_or_default();
+            replacements.push(tag.set("src", format!("src='{json}'")));
+            for name in ["srcset", "sizes"] {
+                if let Some((range, _)) = tag.get_with_span(name) {
+                    replacements.push((range, String::new()));
+                }
+            }
+        }
+        if let Some(fit) = object_fit {
+            replacements.push(tag.add_class(&format!("{OBJECT_FIT_CLASS_PREFIX}{}", fit.get_id())));
+        }
+        if style.len() != declarations.len() || object_fit.is_some() {
+            let style = style.join("; ");
+            let quote = if style.contains('"') { '\'' } else { '"' };
+            replacements.push(tag.set("style", format!("style={quote}{style}{quote}")));
+        }
+    })
+    .unwrap_or_else(|| xml.to_string())
+}
+
+/// Selects the image of a `srcset` (see `resolve_images`), returns the image and the
+/// rendered width. Candidates that are not in the `images` are ignored.
+fn select_image(
+    srcset: &str,
+    src: Option<&str>,
+    sizes: Option<&str>,
+    width: Option<f32>,
+    images: &BTreeMap<String, ImageInfo>,
+    page_width: Pt,
+    dpi: f32,
+) -> Option<(ImageInfo, f32)> {
+    // the `src` was already replaced by `fixup_xml`
+    let get_image = |url: &str| {
+        images
+            .get(url)
+            .cloned()
+            .or_else(|| serde_json::from_str::<ImageInfo>(url).ok())
+    };
+    let mut candidates = srcset
+        .split(',')
+        .filter_map(|candidate| {
+            let mut parts = candidate.split_whitespace();
+            let image = get_image(parts.next()?)?;
+            let density = match parts.next() {
+                Some(d) if d.ends_with('w') => None,
+                Some(d) => Some(d.trim_end_matches('x').parse::<f32>().ok()?),
+                None => Some(1.0),
+            };
+            Some((image, density))
+        })
+        .collect::<Vec<_>>();
+    if let Some(image) = src.and_then(get_image) {
+        candidates.push((image, Some(1.0)));
+    }
+    candidates.retain(|(image, density)| image.width > 0 && density.map_or(true, |d| d > 0.0));
+
+    let rendered_width = width
+        .or_else(|| {
+            candidates
+                .iter()
+                .find_map(|(image, density)| Some(image.width as f32 / (*density)?))
+        })
+        .or_else(|| {
+            // the media conditions of the `sizes` are not evaluated, the last size is used
+            let size = sizes?.rsplit(',').next()?;
+            parse_css_length(size.trim(), Some(page_width.0))
+        })
+        .unwrap_or(page_width.0);
+
+    // the layout uses one pixel per point
+    let required = rendered_width * dpi / 72.0;
+    let image = candidates
+        .iter()
+        .map(|(image, _)| image)
+        .filter(|image| image.width as f32 >= required)
+        .min_by_key(|image| image.width)
+        .or_else(|| {
+            candidates
+                .iter()
+                .map(|(image, _)| image)
+                .max_by_key(|i| i.width)
+        })?;
+    Some((image.clone(), rendered_width))
+}
+
+/// Selects the URL of a `srcset` before the images are loaded (see `get_missing_assets`):
+/// the candidate with the smallest resolution of at least `dpi`, or the one with the
+/// largest resolution. The resolution of `w` candidates depends on the rendered width
+/// (see `select_image`), `x` candidates and the `src` have `72 * x` pixels per inch.
+fn select_srcset_url<'a>(
+    srcset: &'a str,
+    src: Option<&'a str>,
+    sizes: Option<&str>,
+    width: Option<f32>,
+    page_width: Pt,
+    dpi: f32,
+) -> Option<&'a str> {
+    let rendered_width = width
+        .or_else(|| {
+            let size = sizes?.rsplit(',').next()?;
+            parse_css_length(size.trim(), Some(page_width.0))
+        })
+        .unwrap_or(page_width.0)
+        .max(1.0);
+
+    let mut candidates = srcset
+        .split(',')
+        .filter_map(|candidate| {
+            let mut parts = candidate.split_whitespace();
+            let url = parts.next()?;
+            let resolution = match parts.next() {
+                Some(w) if w.ends_with('w') => {
+                    w.trim_end_matches('w').parse::<f32>().ok()? / rendered_width * 72.0
+                }
+                Some(d) => d.trim_end_matches('x').parse::<f32>().ok()? * 72.0,
+                None => 72.0,
+            };
+            Some((url, resolution))
+        })
+        .collect::<Vec<_>>();
+    candidates.extend(src.map(|src| (src, 72.0)));
+    candidates.retain(|(url, resolution)| !url.is_empty() && *resolution > 0.0);
+
+    let by_resolution = |a: &&(&str, f32), b: &&(&str, f32)| a.1.total_cmp(&b.1);
+    candidates
+        .iter()
+        .filter(|(_, resolution)| *resolution >= dpi)
+        .min_by(by_resolution)
+        .or_else(|| candidates.iter().max_by(by_resolution))
+        .map(|(url, _)| *url)
+}
+
+/// Style of the rows that `convert_grids` wraps the grid items into
+const GRID_ROW_STYLE: &str = "display:flex;flex-direction:row;";
+
+/// Column of a CSS grid, see `convert_grids`
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum GridTrack {
+    /// Fixed size in layout pixels
+    Fixed(f32),
+    /// Share of the remaining space (`fr`)
+    Fraction(f32),
+}
+
+/// Grid container whose children are being placed
+#[derive(Debug)]
+struct GridContainer {
+    /// Nesting depth of the grid items
+    depth: usize,
+    columns: Vec<GridTrack>,
+    /// Fixed heights of the rows (`grid-template-rows`, then `grid-auto-rows`)
+    rows: Vec<Option<f32>>,
+    auto_row: Option<f32>,
+    row_gap: f32,
+    column_gap: f32,
+    /// Number of rows that were started
+    row: usize,
+    /// Next free column in the current row
+    column: usize,
+}
+
+/// Converts `display: grid` containers of inline styles into flex rows (the layout solver
+/// only supports flexbox): the items are placed into rows of `grid-template-columns`,
+/// fixed tracks become fixed widths and `fr` tracks share the remaining space.
+/// Supports gaps, row heights, column spans and column start lines (`grid-column`).
+/// Other track sizes (`auto`, percentages, `min-content`) are treated as `1fr`,
+/// rows spans and named areas are not supported.
+fn convert_grids(xml: &str) -> String {
+    let mut grids: Vec<GridContainer> = Vec::new();
+    let mut depth = 0;
+
+    rewrite_elements(xml, |tag, replacements| {
+        let tag = match tag {
+            Tag::Start(tag) => tag,
+            Tag::End(_, span) => {
+                if grids.last().is_some_and(|g| g.depth == depth + 1) {
+                    // close the last row
+                    if grids.pop().is_some_and(|g| g.row > 0) {
+                        let pos = span.start;
+                        replacements.push((pos..pos, "</div>".to_string()));
+                    }
+                }
+                depth = depth.saturating_sub(1);
+                return;
+            }
+        };
+        depth += 1;
+        let old_style = tag.get("style").unwrap_or_default();
+        let mut new_style = None;
+
+        if let Some(grid) = grids.last_mut().filter(|g| g.depth == depth) {
+            let declarations = parse_style(old_style);
+            let (column, columns) = get_grid_column(&declarations, grid.columns.len());
+            let (markup, cell_style) = grid.place(column, columns);
+            replacements.push((tag.start..tag.start, markup));
+            new_style = Some(match old_style.trim().trim_end_matches(';') {
+                "" => cell_style,
+                s => format!("{s};{cell_style}"),
+            });
+        }
+
+        let declarations = parse_style(new_style.as_deref().unwrap_or(old_style));
+        if get_style(&declarations, "display") == Some("grid") {
+            if tag.is_open {
+                grids.push(GridContainer::new(&declarations, depth + 1));
+            }
+            let mut out = declarations
+                .iter()
+                .filter(|(k, _, _)| k != "display" && !k.starts_with("grid") && !k.ends_with("gap"))
+                .map(|(_, _, d)| d.to_string())
+                .collect::<Vec<_>>();
+            out.push("display:flex;flex-direction:column".to_string());
+            new_style = Some(out.join(";"));
+        }
+
+        if let Some(new_style) = new_style {
+            let quote = if new_style.contains('"') { '\'' } else { '"' };
+            replacements.push(tag.set("style", format!("style={quote}{new_style}{quote}")));
+        }
+        if !tag.is_open {
+            depth = depth.saturating_sub(1);
+        }
+    })
+    .unwrap_or_else(|| xml.to_string())
+}
+
+impl GridContainer {
+    fn new(declarations: &[(String, &str, &str)], depth: usize) -> Self {
+        let get = |key: &str| get_style(declarations, key);
+        let length = |key: &str| get(key).and_then(|v| parse_css_length(v, None));
+        let gaps = get("gap")
+            .or(get("grid-gap"))
+            .map(|v| v.split_whitespace().collect::<Vec<_>>())
+            .unwrap_or_default();
+        let gap = |i: usize| {
+            gaps.get(i)
+                .or(gaps.first())
+                .and_then(|v| parse_css_length(v, None))
+        };
+        let mut columns = get("grid-template-columns")
+            .map(|v| {
+                parse_grid_tracks(v)
+                    .iter()
+                    .map(|t| parse_grid_track(t).unwrap_or(GridTrack::Fraction(1.0)))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        if columns.is_empty() {
+            columns.push(GridTrack::Fraction(1.0));
+        }
+        let rows = get("grid-template-rows")
+            .map(|v| {
+                parse_grid_tracks(v)
+                    .iter()
+                    .map(|t| parse_css_length(t, None))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            depth,
+            columns,
+            rows,
+            auto_row: length("grid-auto-rows"),
+            row_gap: length("row-gap")
+                .or(length("grid-row-gap"))
+                .or(gap(0))
+                .unwrap_or(0.0),
+            column_gap: length("column-gap")
+                .or(length("grid-column-gap"))
+                .or(gap(1))
+                .unwrap_or(0.0),
+            row: 0,
+            column: 0,
+        }
+    }
+
+    /// Places the next item into the grid, returns the markup that is inserted before
+    /// the item (new rows, empty cells before the start column) and the style of the item
+    fn place(&mut self, start: Option<usize>, span: usize) -> (String, String) {
+        let mut markup = String::new();
+        let target = match start {
+            Some(s) if s >= self.column && self.row > 0 => s,
+            Some(s) => {
+                self.start_row(&mut markup);
+                s
+            }
+            None if self.column + span > self.columns.len() || self.row == 0 => {
+                self.start_row(&mut markup);
+                0
+            }
+            None => self.column,
+        };
+        for c in self.column..target {
+            markup.push_str(&format!("<div style=\"{}\"></div>", self.cell_style(c, 1)));
+        }
+        self.column = target + span;
+        (markup, self.cell_style(target, span))
+    }
+
+    fn start_row(&mut self, markup: &mut String) {
+        if self.row > 0 {
+            markup.push_str("</div>");
+        }
+        let mut style = GRID_ROW_STYLE.to_string();
+        if let Some(h) = self.rows.get(self.row).copied().flatten().or(self.auto_row) {
+            style.push_str(&format!("height:{h}px;"));
+        }
+        if self.row > 0 && self.row_gap > 0.0 {
+            style.push_str(&format!("margin-top:{}px;", self.row_gap));
+        }
+        markup.push_str(&format!("<div style=\"{style}\">"));
+        self.row += 1;
+        self.column = 0;
+    }
+
+    /// Style of a cell spanning `span` columns, the width of the fixed tracks and the
+    /// gaps between them is the flex basis, the fractions are the flex grow factors
+    fn cell_style(&self, column: usize, span: usize) -> String {
+        let tracks = &self.columns[column..(column + span).min(self.columns.len())];
+        let mut width = self.column_gap * (tracks.len().saturating_sub(1)) as f32;
+        let mut grow = 0.0;
+        for track in tracks {
+            match track {
+                GridTrack::Fixed(w) => width += w,
+                GridTrack::Fraction(f) => grow += f,
+            }
+        }
+        let mut style = format!("flex-grow:{grow};flex-shrink:0;width:{width}px;");
+        if column > 0 && self.column_gap > 0.0 {
+            style.push_str(&format!("margin-left:{}px;", self.column_gap));
+        }
+        style
+    }
+}
+
+/// Returns the start column (0-indexed) and the number of spanned columns of a grid item
+/// (`grid-column: span 2`, `grid-column: 2 / 4`, `grid-column-start: 3`, ...)
+fn get_grid_column(
+    declarations: &[(String, &str, &str)],
+    columns: usize,
+) -> (Option<usize>, usize) {
+    let (start, end) = match get_style(declarations, "grid-column") {
+        Some(v) => match v.split_once('/') {
+            Some((s, e)) => (Some(s.trim()), Some(e.trim())),
+            None => (Some(v.trim()), None),
+        },
+        None => (None, None),
+    };
+    let start = get_style(declarations, "grid-column-start").or(start);
+    let end = get_style(declarations, "grid-column-end").or(end);
+
+    // lines are 1-indexed, negative lines count from the end
+    let line = |v: &str| {
+        let l = v.parse::<i64>().ok()?;
+        let l = if l < 0 { columns as i64 + 2 + l } else { l };
+        Some((l.max(1) as usize - 1).min(columns))
+    };
+    let span = |v: &str| {
+        v.strip_prefix("span")
+            .and_then(|n| n.trim().parse::<usize>().ok())
+    };
+
+    let start_line = start.and_then(line);
+    let span = match (start.and_then(span), end) {
+        (Some(n), _) => n,
+        (None, Some(e)) => match (span(e), line(e), start_line) {
+            (Some(n), _, _) => n,
+            (None, Some(e), Some(s)) if e > s => e - s,
+            _ => 1,
+        },
+        (None, None) => 1,
+    };
+    let span = span.clamp(1, columns);
+    let start_line = start_line.map(|s| s.min(columns - span));
+    (start_line, span)
+}
+
+/// Splits a track list into tracks, expanding `repeat(3, 1fr)`
+fn parse_grid_tracks(value: &str) -> Vec<String> {
+    let mut tracks = Vec::new();
+    let mut rest = value.trim();
+    while !rest.is_empty() {
+        if let Some(args) = rest.strip_prefix("repeat(") {
+            let Some(end) = args.find(')') else {
+                break;
+            };
+            if let Some((count, inner)) = args[..end].split_once(',') {
+                let count = count.trim().parse::<usize>().unwrap_or(1);
+                let inner = parse_grid_tracks(inner);
+                for _ in 0..count {
+                    tracks.extend(inner.iter().cloned());
+                }
+            }
+            rest = args[end + 1..].trim_start();
+            continue;
+        }
+        // functions like minmax(100px, 1fr) contain whitespace
+        let end = match rest.find('(') {
+            Some(p) if p < rest.find(char::is_whitespace).unwrap_or(rest.len()) => {
+                rest.find(')').map(|e| e + 1).unwrap_or(rest.len())
+            }
+            _ => rest.find(char::is_whitespace).unwrap_or(rest.len()),
+        };
+        tracks.push(rest[..end].to_string());
+        rest = rest[end..].trim_start();
+    }
+    tracks
+}
+
+/// Parses a column track, `minmax()` uses the maximum
+fn parse_grid_track(track: &str) -> Option<GridTrack> {
+    if let Some(args) = track
+        .strip_prefix("minmax(")
+        .and_then(|a| a.strip_suffix(')'))
+    {
+        return parse_grid_track(args.split_once(',')?.1.trim());
+    }
+    match track.strip_suffix("fr") {
+        Some(f) => f.parse::<f32>().ok().map(GridTrack::Fraction),
+        None if track.ends_with('%') => None,
+        None => parse_css_length(track, None).map(GridTrack::Fixed),
+    }
+}
+
+/// Class that is added to elements with a `dir` attribute or a `direction` style, followed
+/// by the direction and `_override` for `unicode-bidi: bidi-override` (or `<bdo>`)
+const DIRECTION_CLASS_PREFIX: &str = "__printpdf_dir_";
+
+/// Moves the `direction` and `unicode-bidi` of the inline styles and the `dir` attributes
+/// into `__printpdf_dir_*` marker classes (the layout doesn't support them), see
+/// `reorder_bidi_line`. Right to left elements are right-aligned, unless they have a
+/// `text-align`. `dir="auto"` is treated like an element without `dir`.
+fn extract_directions(xml: &str) -> String {
+    rewrite_elements(xml, |tag, replacements| {
+        let Tag::Start(tag) = tag else {
+            return;
+        };
+        let declarations = tag.get("style").map(parse_style).unwrap_or_default();
+        // the style overrides the attribute
+        let direction = get_style(&declarations, "direction")
+            .or(tag.get("dir"))
+            .filter(|d| matches!(*d, "rtl" | "ltr"));
+        let bidi_override =
+            tag.name == "bdo" || get_style(&declarations, "unicode-bidi") == Some("bidi-override");
+        let Some(direction) = direction else {
+            return;
+        };
+
+        let mut marker = format!("{DIRECTION_CLASS_PREFIX}{direction}");
+        if bidi_override {
+            marker.push_str("_override");
+        }
+        let mut style = declarations
+            .iter()
+            .filter(|(k, _, _)| k != "direction" && k != "unicode-bidi")
+            .map(|(_, _, d)| d.to_string())
+            .collect::<Vec<_>>();
+        if get_style(&declarations, "text-align").is_none() {
+            let align = if direction == "rtl" { "right" } else { "left" };
+            style.push(format!("text-align: {align}"));
+        }
+        let style = style.join("; ");
+        let quote = if style.contains('"') { '\'' } else { '"' };
+        replacements.push(tag.set("style", format!("style={quote}{style}{quote}")));
+        replacements.push(tag.add_class(&marker));
+    })
+    .unwrap_or_else(|| xml.to_string())
+}
+
+/// Direction of a text node, set by the nearest element with a direction marker
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+struct TextDirection {
+    rtl: bool,
+    /// All characters are written in the direction of the element (`unicode-bidi: bidi-override`)
+    bidi_override: bool,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
-pub enum ImageTypeInfo {
-    Image,
-    Svg,
+fn get_text_direction(layout_result: &LayoutResult, node_id: NodeId) -> TextDirection {
+    let mut current = Some(node_id);
+    while let Some(id) = current {
+        let html_node = &layout_result.styled_dom.node_data.as_container()[id];
+        let marker =
+            html_node.get_ids_and_classes().as_ref().iter().find_map(
+                |id_or_class| match id_or_class {
+                    IdOrClass::Class(class) => class
+                        .as_str()
+                        .strip_prefix(DIRECTION_CLASS_PREFIX)
+                        .map(|m| m.to_string()),
+                    IdOrClass::Id(_) => None,
+                },
+            );
+        if let Some(marker) = marker {
+            return TextDirection {
+                rtl: marker.starts_with("rtl"),
+                bidi_override: marker.ends_with("_override"),
+            };
+        }
+        current = layout_result.styled_dom.node_hierarchy.as_container()[id].parent_id();
+    }
+    TextDirection::default()
 }
 
-impl Default for ImageTypeInfo {
-    fn default() -> Self {
-        ImageTypeInfo::Image
+/// Simplified bidirectional character type (Unicode bidi classes)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum BidiClass {
+    /// Latin, Greek, Cyrillic, CJK, ...
+    LeftToRight,
+    /// Hebrew, Arabic, Syriac, Thaana, ...
+    RightToLeft,
+    /// Digits, written left to right (also inside right to left text)
+    Number,
+    /// Number separators (`,`, `.`, `:`, `/`), part of a number between two digits
+    Separator,
+    /// Spaces and punctuation, take the direction of the surrounding text
+    Neutral,
+}
+
+impl BidiClass {
+    fn of(c: char) -> Self {
+        match c as u32 {
+            0x30..=0x39 | 0x660..=0x669 | 0x6F0..=0x6F9 => BidiClass::Number,
+            0x590..=0x8FF | 0xFB1D..=0xFDFF | 0xFE70..=0xFEFF => BidiClass::RightToLeft,
+            0x2C | 0x2E | 0x2F | 0x3A | 0xA0 => BidiClass::Separator,
+            _ if c.is_alphabetic() => BidiClass::LeftToRight,
+            _ => BidiClass::Neutral,
+        }
     }
 }
 
-fn fixup_xml(s: &str, doc: &mut PdfDocument, config: &XmlRenderOptions) -> String {
-    let s = if !s.contains("<body>") {
-        format!("<body>{s}</body>")
-    } else {
-        s.trim().to_string()
+/// Resolves the embedding levels of a line (even = left to right, odd = right to left):
+/// numbers after left to right text are left to right text, a single separator between
+/// two numbers is part of the number and neutrals between characters of the same
+/// direction take that direction, otherwise the direction of the paragraph
+fn get_bidi_levels(classes: &[BidiClass], rtl: bool) -> Vec<u8> {
+    use BidiClass::*;
+
+    let base = if rtl { RightToLeft } else { LeftToRight };
+    let mut resolved = classes.to_vec();
+    let mut last_strong = base;
+    for i in 0..resolved.len() {
+        match resolved[i] {
+            LeftToRight | RightToLeft => last_strong = resolved[i],
+            Number if last_strong == LeftToRight => resolved[i] = LeftToRight,
+            Separator
+                if i > 0 && classes[i - 1] == Number && classes.get(i + 1) == Some(&Number) =>
+            {
+                resolved[i] = resolved[i - 1];
+            }
+            Separator => resolved[i] = Neutral,
+            _ => {}
+        }
+    }
+    // numbers count as right to left for the neutrals
+    let direction = |c: BidiClass| match c {
+        Number => Some(RightToLeft),
+        Neutral | Separator => None,
+        strong => Some(strong),
     };
-    let s = if !s.contains("<html>") {
-        format!("<html>{s}</html>")
-    } else {
-        s.trim().to_string()
+    (0..resolved.len())
+        .map(|i| {
+            let class = match resolved[i] {
+                Neutral | Separator => {
+                    let before = resolved[..i].iter().rev().find_map(|c| direction(*c));
+                    let after = resolved[i + 1..].iter().find_map(|c| direction(*c));
+                    match (before.unwrap_or(base), after.unwrap_or(base)) {
+                        (b, a) if b == a => b,
+                        _ => base,
+                    }
+                }
+                c => c,
+            };
+            match (class, rtl) {
+                (LeftToRight, false) => 0,
+                (RightToLeft, _) => 1,
+                (_, _) => 2,
+            }
+        })
+        .collect()
+}
+
+/// Returns the visual x positions of one line of glyphs, given as (x, advance, class) in
+/// logical order: from the highest level to the lowest odd level, each run of glyphs at
+/// or above the level is mirrored inside its extent (which reverses the run, but keeps
+/// the spacing of the glyphs)
+fn reorder_bidi_line(glyphs: &[(f32, f32, BidiClass)], direction: TextDirection) -> Vec<f32> {
+    let mut xs = glyphs.iter().map(|g| g.0).collect::<Vec<_>>();
+    let classes = glyphs.iter().map(|g| g.2).collect::<Vec<_>>();
+    let levels = match direction.bidi_override {
+        true => vec![direction.rtl as u8; glyphs.len()],
+        false => get_bidi_levels(&classes, direction.rtl),
     };
+    let max_level = levels.iter().copied().max().unwrap_or(0);
+    for level in (1..=max_level).rev() {
+        let mut i = 0;
+        while i < glyphs.len() {
+            if levels[i] < level {
+                i += 1;
+                continue;
+            }
+            let run = i..(i..glyphs.len())
+                .find(|j| levels[*j] < level)
+                .unwrap_or(glyphs.len());
+            let start = run.clone().map(|j| xs[j]).fold(f32::MAX, f32::min);
+            let end = run
+                .clone()
+                .map(|j| xs[j] + glyphs[j].1)
+                .fold(f32::MIN, f32::max);
+            for j in run.clone() {
+                xs[j] = start + end - xs[j] - glyphs[j].1;
+            }
+            i = run.end;
+        }
+    }
+    xs
+}
 
-    let mut s = s.trim().to_string();
+/// Reorders the glyphs of a text node for right to left text and right to left runs in
+/// left to right text. The classes of the glyphs are looked up from the characters of
+/// the `text`, glyphs of shaped forms that are not in the text belong to the paragraph.
+fn reorder_bidi_glyphs(
+    glyphs: &mut [GlyphInstance],
+    text: &str,
+    font: &crate::ParsedFont,
+    font_size: f32,
+    direction: TextDirection,
+) {
+    let classes = text
+        .chars()
+        .filter_map(|c| Some((font.lookup_glyph_index(c as u32)?, BidiClass::of(c))))
+        .collect::<BTreeMap<_, _>>();
+    let has_rtl = classes.values().any(|c| *c == BidiClass::RightToLeft);
+    if !direction.rtl && !has_rtl {
+        return;
+    }
+    let default_class = match direction.rtl {
+        true => BidiClass::RightToLeft,
+        false => BidiClass::LeftToRight,
+    };
+    let scale = font_size / font.font_metrics.units_per_em as f32;
+
+    let mut start = 0;
+    while start < glyphs.len() {
+        let y = glyphs[start].point.y;
+        let end = (start..glyphs.len())
+            .find(|i| (glyphs[*i].point.y - y).abs() > 0.01)
+            .unwrap_or(glyphs.len());
+        let line = glyphs[start..end]
+            .iter()
+            .map(|g| {
+                let index = g.index as u16;
+                let advance = font.get_horizontal_advance(index) as f32 * scale;
+                let class = classes.get(&index).copied().unwrap_or(default_class);
+                (g.point.x, advance, class)
+            })
+            .collect::<Vec<_>>();
+        for (g, x) in glyphs[start..end]
+            .iter_mut()
+            .zip(reorder_bidi_line(&line, direction))
+        {
+            g.point.x = x;
+        }
+        start = end;
+    }
+}
 
-    for (k, image_bytes) in config.images.iter() {
-        let opt_svg = std::str::from_utf8(&image_bytes)
-            .ok()
-            .and_then(|s| crate::Svg::parse(s).ok());
+/// Class that is added to elements that are tagged in the structure tree (followed by the
+/// structure type, i.e. "TD", and the scope for header cells, i.e. "TH_Column", or the
+/// index of the alt text for figures, i.e. "Figure_0")
+pub(crate) const STRUCT_CLASS_PREFIX: &str = "__printpdf_struct_";
+
+/// Structure tagging state of the HTML renderer
+#[derive(Debug, Default)]
+struct HtmlStructure {
+    /// `alt` attributes of the `<img>` elements, indexed by the marker class
+    alt_texts: Vec<String>,
+    /// Structure element of every node that has been visited
+    elements: BTreeMap<NodeId, Option<StructureElementId>>,
+}
 
-        let img_info = match opt_svg {
-            Some(o) => {
-                let width = o.width.map(|s| s.0).unwrap_or(0);
-                let height = o.height.map(|s| s.0).unwrap_or(0);
-                let image_xobject_id = doc.add_xobject(&o);
-                ImageInfo {
-                    original_id: k.clone(),
-                    xobject_id: image_xobject_id.0,
-                    image_type: ImageTypeInfo::Svg,
-                    width,
-                    height,
+/// Adds `__printpdf_struct_*` marker classes to the elements that have a structure type
+/// (`<p>`, `<h1>`, `<img>`, `<table>`, `<td>`, ...), returns the new XML and the alt texts
+/// of the images
+fn tag_structure_elements(xml: &str) -> (String, Vec<String>) {
+    let mut alt_texts = Vec::new();
+    let mut in_thead = false;
+    let mut rows_in_table = 0;
+
+    let tagged = rewrite_elements(xml, |tag, replacements| {
+        let tag = match tag {
+            Tag::Start(tag) => tag,
+            Tag::End(name, _) => {
+                if name == "thead" {
+                    in_thead = false;
                 }
+                return;
             }
-            None => {
-                let raw_image = match crate::image::RawImage::decode_from_bytes(&image_bytes) {
-                    Ok(o) => o,
-                    Err(e) => {
-                        #[cfg(not(target_family = "wasm"))]
-                        {
-                            println!("{e}");
-                        }
-                        continue;
-                    }
-                };
+        };
+        let role = match tag.name {
+            "body" => StructureType::Document,
+            "p" => StructureType::P,
+            "h1" => StructureType::H1,
+            "h2" => StructureType::H2,
+            "h3" => StructureType::H3,
+            "h4" => StructureType::H4,
+            "h5" => StructureType::H5,
+            "h6" => StructureType::H6,
+            "img" => StructureType::Figure,
+            "a" => StructureType::Link,
+            "ul" | "ol" => StructureType::L,
+            "li" => StructureType::LI,
+            "blockquote" => StructureType::BlockQuote,
+            "section" => StructureType::Sect,
+            "table" => {
+                rows_in_table = 0;
+                StructureType::Table
+            }
+            "thead" => {
+                in_thead = true;
+                StructureType::THead
+            }
+            "tbody" => StructureType::TBody,
+            "tfoot" => StructureType::TFoot,
+            "tr" => {
+                rows_in_table += 1;
+                StructureType::TR
+            }
+            "th" => StructureType::TH,
+            "td" => StructureType::TD,
+            "caption" => StructureType::Caption,
+            _ => return,
+        };
 
-                let width = raw_image.width;
-                let height = raw_image.height;
-                let image_xobject_id = doc.add_image(&raw_image);
-                ImageInfo {
-                    original_id: k.clone(),
-                    xobject_id: image_xobject_id.0,
-                    image_type: ImageTypeInfo::Image,
-                    width,
-                    height,
+        let mut marker = format!("{STRUCT_CLASS_PREFIX}{}", role.get_id());
+        match role {
+            StructureType::TH => {
+                // without an explicit scope, cells in the header / first row
+                // are column headers, all others are row headers
+                let scope = match tag.get("scope") {
+                    Some("row") | Some("rowgroup") => TableHeaderScope::Row,
+                    Some("col") | Some("colgroup") => TableHeaderScope::Column,
+                    _ if in_thead || rows_in_table <= 1 => TableHeaderScope::Column,
+                    _ => TableHeaderScope::Row,
+                };
+                marker.push('_');
+                marker.push_str(scope.get_id());
+            }
+            StructureType::Figure => {
+                if let Some(alt) = tag.get("alt") {
+                    marker.push_str(&format!("_{}", alt_texts.len()));
+                    alt_texts.push(alt.to_string());
                 }
             }
+            _ => {}
+        }
+        replacements.push(tag.add_class(&marker));
+    });
+    match tagged {
+        Some(tagged) => (tagged, alt_texts),
+        None => (xml.to_string(), Vec::new()),
+    }
+}
+
+/// Start tag of an element, see `rewrite_elements`
+struct StartTag<'a> {
+    /// Local name of the element, i.e. "img"
+    name: &'a str,
+    /// Position of the "<"
+    start: usize,
+    /// Span of the closing ">" or "/>"
+    end: std::ops::Range<usize>,
+    /// Whether the element has children (`<p>...</p>`, not `<br/>`)
+    is_open: bool,
+    /// Local name, span (`name="value"`) and value of the attributes
+    attributes: Vec<(&'a str, std::ops::Range<usize>, &'a str)>,
+}
+
+impl<'a> StartTag<'a> {
+    /// Returns the (still escaped) value of the attribute
+    fn get(&self, name: &str) -> Option<&'a str> {
+        self.get_with_span(name).map(|(_, value)| value)
+    }
+
+    /// Returns the span of the whole attribute and its value
+    fn get_with_span(&self, name: &str) -> Option<(std::ops::Range<usize>, &'a str)> {
+        self.attributes
+            .iter()
+            .find(|(n, _, _)| *n == name)
+            .map(|(_, span, value)| (span.clone(), *value))
+    }
+
+    /// Replacement that sets the attribute (`attribute` is `name="value"`): the existing
+    /// attribute is replaced, otherwise it is inserted before the closing ">" or "/>"
+    fn set(&self, name: &str, attribute: String) -> (std::ops::Range<usize>, String) {
+        match self.get_with_span(name) {
+            Some((span, _)) => (span, attribute),
+            None => (self.end.start..self.end.start, format!(" {attribute}")),
+        }
+    }
+
+    /// Replacement that adds the class to the `class` attribute
+    fn add_class(&self, class: &str) -> (std::ops::Range<usize>, String) {
+        let classes = match self.get("class") {
+            Some(existing) => format!("{existing} {class}"),
+            None => class.to_string(),
         };
+        self.set("class", format!("class=\"{classes}\""))
+    }
+}
 
-        let json = serde_json::to_string(&img_info).unwrap_or_default();
+/// Tag that is visited by `rewrite_elements`
+enum Tag<'a> {
+    Start(StartTag<'a>),
+    /// End tag of an element with children (`</p>`): local name and span
+    End(&'a str, std::ops::Range<usize>),
+}
 
-        s = s
-            .replace(&format!("src='{k}'"), &format!("src='{json}'"))
-            .replace(&format!("src=\"{k}\""), &format!("src='{json}'"));
+/// Calls `visit` for every start and end tag of the XML, then applies the replacements
+/// (span, new text) that `visit` pushed. Returns `None` if the XML can't be tokenized.
+fn rewrite_elements<'a>(
+    xml: &'a str,
+    mut visit: impl FnMut(Tag<'a>, &mut Vec<(std::ops::Range<usize>, String)>),
+) -> Option<String> {
+    use xmlparser::{ElementEnd, Token, Tokenizer};
+
+    let mut replacements = Vec::new();
+    let mut current: Option<StartTag<'a>> = None;
+    for token in Tokenizer::from(xml) {
+        match token.ok()? {
+            Token::ElementStart { local, span, .. } => {
+                current = Some(StartTag {
+                    name: local.as_str(),
+                    start: span.start(),
+                    end: 0..0,
+                    is_open: false,
+                    attributes: Vec::new(),
+                })
+            }
+            Token::Attribute {
+                local, value, span, ..
+            } => {
+                if let Some(tag) = current.as_mut() {
+                    let attribute = (local.as_str(), span.start()..span.end(), value.as_str());
+                    tag.attributes.push(attribute);
+                }
+            }
+            Token::ElementEnd {
+                end: ElementEnd::Close(_, local),
+                span,
+            } => visit(
+                Tag::End(local.as_str(), span.start()..span.end()),
+                &mut replacements,
+            ),
+            Token::ElementEnd { end, span } => {
+                if let Some(mut tag) = current.take() {
+                    tag.end = span.start()..span.end();
+                    tag.is_open = matches!(end, ElementEnd::Open);
+                    visit(Tag::Start(tag), &mut replacements);
+                }
+            }
+            _ => {}
+        }
     }
+    Some(apply_replacements(xml, replacements))
+}
 
-    s
+fn apply_replacements(
+    xml: &str,
+    mut replacements: Vec<(std::ops::Range<usize>, String)>,
+) -> String {
+    // apply replacements back to front, so that the ranges stay valid
+    replacements.sort_by_key(|(range, _)| (range.start, range.end));
+    let mut out = xml.to_string();
+    for (range, replacement) in replacements.into_iter().rev() {
+        out.replace_range(range, &replacement);
+    }
+    out
+}
+
+/// Returns the structure element of a node: nodes with a `__printpdf_struct_*` class
+/// create a new element (as a child of the parent node's element), all other nodes
+/// belong to the element of their parent
+fn get_structure_element(
+    doc: &mut PdfDocument,
+    layout_result: &LayoutResult,
+    node_id: NodeId,
+    structure: &mut HtmlStructure,
+) -> Option<StructureElementId> {
+    if let Some(cached) = structure.elements.get(&node_id) {
+        return cached.clone();
+    }
+
+    let parent = layout_result.styled_dom.node_hierarchy.as_container()[node_id]
+        .parent_id()
+        .and_then(|p| get_structure_element(doc, layout_result, p, structure));
+
+    let html_node = &layout_result.styled_dom.node_data.as_container()[node_id];
+    let marker = html_node
+        .get_ids_and_classes()
+        .as_ref()
+        .iter()
+        .find_map(|id_or_class| match id_or_class {
+            IdOrClass::Class(class) => class
+                .as_str()
+                .strip_prefix(STRUCT_CLASS_PREFIX)
+                .map(|m| m.to_string()),
+            IdOrClass::Id(_) => None,
+        });
+
+    let element = match marker {
+        None => parent,
+        Some(marker) => {
+            let (role, suffix) = match marker.split_once('_') {
+                Some((role, suffix)) => (role, Some(suffix)),
+                None => (marker.as_str(), None),
+            };
+            match (StructureType::from_id(role), suffix, parent.as_ref()) {
+                (Some(StructureType::TH), Some(scope), Some(row)) => {
+                    let scope =
+                        TableHeaderScope::from_id(scope).unwrap_or(TableHeaderScope::Column);
+                    Some(doc.structure.add_table_header(row, scope))
+                }
+                (Some(StructureType::Figure), alt, _) => {
+                    let alt = alt
+                        .and_then(|index| index.parse::<usize>().ok())
+                        .and_then(|index| structure.alt_texts.get(index));
+                    Some(
+                        doc.structure
+                            .add_figure(parent.as_ref(), alt.map(|s| s.as_str())),
+                    )
+                }
+                (Some(role), _, _) => Some(doc.structure.add_element(parent.as_ref(), role)),
+                (None, _, _) => parent,
+            }
+        }
+    };
+
+    structure.elements.insert(node_id, element.clone());
+    element
 }
 
 fn fixup_xml_nodes(nodes: &[XmlNode]) -> Vec<XmlNode> {
@@ -334,73 +2656,201 @@ fn layout_result_to_ops(
     renderer_resources: &RendererResources,
     ops: &mut Vec<Op>,
     page_height: Pt,
-) {
+    hrefs: &[String],
+    alt_texts: Vec<String>,
+) -> HtmlLinkInfo {
     let rects_in_rendering_order = layout_result.styled_dom.get_rects_in_rendering_order();
+    let mut link_info = HtmlLinkInfo::default();
+    let mut structure = HtmlStructure {
+        alt_texts,
+        ..Default::default()
+    };
 
     // TODO: break layout result into pages
     // let root_width = layout_result.width_calculated_rects.as_ref()[NodeId::ZERO].overflow_width();
     // let root_height = layout_result.height_calculated_rects.as_ref()[NodeId::ZERO].overflow_height();
     // let root_size = LogicalSize::new(root_width, root_height);
 
+    // the root element creates the root stacking context
+    let mut layers = Vec::new();
     let _ = displaylist_handle_rect(
         doc,
         ops,
+        &mut link_info,
         layout_result,
         renderer_resources,
         rects_in_rendering_order.root.into_crate_internal().unwrap(),
         page_height,
+        hrefs,
+        &mut structure,
     );
+    let root_end = ops.len();
 
     for c in rects_in_rendering_order.children.as_slice() {
         push_rectangles_into_displaylist(
             doc,
             ops,
+            &mut link_info,
             layout_result,
             renderer_resources,
             c,
             page_height,
+            hrefs,
+            &mut structure,
+            &mut layers,
         );
     }
+    paint_layers(ops, root_end, layers);
+
+    link_info
+}
+
+/// Ops of a positioned element (and its descendants), painted by the enclosing
+/// stacking context in the order of `z` instead of the document order
+struct StackingLayer {
+    z: i32,
+    ops: Vec<Op>,
+}
+
+/// Paints the layers of a stacking context (stable, so equal z-indices stay in document
+/// order): negative z-indices below the in-flow content, directly after the ops of the
+/// element that creates the context (which end at `start`), all others on top of it
+fn paint_layers(ops: &mut Vec<Op>, start: usize, mut layers: Vec<StackingLayer>) {
+    layers.sort_by_key(|l| l.z);
+    let below = layers.iter().take_while(|l| l.z < 0).count();
+    let above = layers.split_off(below);
+    ops.splice(start..start, layers.into_iter().flat_map(|l| l.ops));
+    ops.extend(above.into_iter().flat_map(|l| l.ops));
+}
+
+/// Returns `None` for elements in the normal flow, `Some(None)` for positioned elements
+/// without a z-index (painted at z = 0 without creating a stacking context) and
+/// `Some(Some(z))` for positioned elements with a z-index
+fn get_stacking(layout_result: &LayoutResult, rect_idx: NodeId) -> Option<Option<i32>> {
+    let styled_node = &layout_result.styled_dom.styled_nodes.as_container()[rect_idx];
+    let html_node = &layout_result.styled_dom.node_data.as_container()[rect_idx];
+    let position = layout_result
+        .styled_dom
+        .get_css_property_cache()
+        .get_position(html_node, &rect_idx, &styled_node.state)
+        .and_then(|p| p.get_property())
+        .copied()
+        .unwrap_or_default();
+    if position == LayoutPosition::Static {
+        return None;
+    }
+    let z_index = html_node
+        .get_ids_and_classes()
+        .as_ref()
+        .iter()
+        .find_map(|id_or_class| match id_or_class {
+            IdOrClass::Class(class) => class
+                .as_str()
+                .strip_prefix(Z_INDEX_CLASS_PREFIX)
+                .and_then(|z| z.parse::<i32>().ok()),
+            IdOrClass::Id(_) => None,
+        });
+    Some(z_index)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn push_rectangles_into_displaylist(
     doc: &mut PdfDocument,
     ops: &mut Vec<Op>,
+    link_info: &mut HtmlLinkInfo,
     layout_result: &LayoutResult,
     renderer_resources: &RendererResources,
     root_content_group: &ContentGroup,
     page_height: Pt,
+    hrefs: &[String],
+    structure: &mut HtmlStructure,
+    layers: &mut Vec<StackingLayer>,
 ) -> Option<()> {
+    let rect_idx = root_content_group.root.into_crate_internal().unwrap();
+    let stacking = get_stacking(layout_result, rect_idx);
+
+    // positioned elements are painted into their own layer
+    let mut layer_ops = Vec::new();
+    let target = if stacking.is_some() {
+        &mut layer_ops
+    } else {
+        &mut *ops
+    };
     displaylist_handle_rect(
         doc,
-        ops,
+        target,
+        link_info,
         layout_result,
         renderer_resources,
-        root_content_group.root.into_crate_internal().unwrap(),
+        rect_idx,
         page_height,
+        hrefs,
+        structure,
     )?;
 
+    let Some(z) = stacking else {
+        for c in root_content_group.children.iter() {
+            push_rectangles_into_displaylist(
+                doc,
+                ops,
+                link_info,
+                layout_result,
+                renderer_resources,
+                c,
+                page_height,
+                hrefs,
+                structure,
+                layers,
+            );
+        }
+        return Some(());
+    };
+
+    // reserve the place of the layer, so that it's painted before the layers of its
+    // descendants with the same z-index
+    let layer_idx = layers.len();
+    layers.push(StackingLayer {
+        z: z.unwrap_or(0),
+        ops: Vec::new(),
+    });
+    let own_end = layer_ops.len();
+    let mut own_layers = Vec::new();
     for c in root_content_group.children.iter() {
+        // elements with a z-index create a new stacking context for their descendants
+        let child_layers = match z {
+            Some(_) => &mut own_layers,
+            None => &mut *layers,
+        };
         push_rectangles_into_displaylist(
             doc,
-            ops,
+            &mut layer_ops,
+            link_info,
             layout_result,
             renderer_resources,
             c,
             page_height,
+            hrefs,
+            structure,
+            child_layers,
         );
     }
+    paint_layers(&mut layer_ops, own_end, own_layers);
+    layers[layer_idx].ops = layer_ops;
 
     Some(())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn displaylist_handle_rect(
     doc: &mut PdfDocument,
     ops: &mut Vec<Op>,
+    link_info: &mut HtmlLinkInfo,
     layout_result: &LayoutResult,
     renderer_resources: &RendererResources,
     rect_idx: NodeId,
     page_height: Pt,
+    hrefs: &[String],
+    structure: &mut HtmlStructure,
 ) -> Option<()> {
     use crate::units::Pt;
 
@@ -413,7 +2863,53 @@ fn displaylist_handle_rect(
         return None;
     }
 
+    // hidden elements keep their anchors, but are not painted and not clickable
+    let hidden = is_visibility_hidden(layout_result, rect_idx);
+
     let positioned_rect = &layout_result.rects.as_ref()[rect_idx];
+
+    // collect link rects and anchors (lower left origin)
+    let link_rect = {
+        let staticoffset = positioned_rect.position.get_static_offset();
+        crate::graphics::Rect {
+            x: Pt(staticoffset.x),
+            y: Pt(page_height.0 - staticoffset.y - positioned_rect.size.height),
+            width: Pt(positioned_rect.size.width),
+            height: Pt(positioned_rect.size.height),
+        }
+    };
+    for id_or_class in html_node.get_ids_and_classes().as_ref().iter() {
+        match id_or_class {
+            IdOrClass::Id(id) => link_info.anchors.push(HtmlAnchor {
+                page: 0,
+                name: id.as_str().to_string(),
+                rect: link_rect.clone(),
+            }),
+            IdOrClass::Class(class) => {
+                let href = class
+                    .as_str()
+                    .strip_prefix(LINK_CLASS_PREFIX)
+                    .and_then(|index| index.parse::<usize>().ok())
+                    .and_then(|index| hrefs.get(index));
+                if let Some(href) = href.filter(|_| !hidden) {
+                    link_info.links.push(HtmlLink {
+                        page: 0,
+                        rect: link_rect.clone(),
+                        href: href.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    // the children of hidden elements are still painted if they are visible
+    if hidden {
+        return Some(());
+    }
+
+    let structure_element = get_structure_element(doc, layout_result, rect_idx, structure);
+    let ops_start = ops.len();
+
     let border_radius = get_border_radius(layout_result, html_node, rect_idx, styled_node);
     let background_content =
         get_background_content(layout_result, html_node, rect_idx, styled_node);
@@ -527,29 +3023,52 @@ fn displaylist_handle_rect(
     }
 
     if let Some(image_info) = opt_image {
-        let source_width = image_info.width;
-        let source_height = image_info.width;
+        let source_width = image_info.width as f32;
+        let source_height = image_info.height as f32;
         let target_width = positioned_rect.size.width;
         let target_height = positioned_rect.size.height;
         let pos = positioned_rect.position.get_static_offset();
 
         let is_zero = target_width.is_nearly_zero()
             || target_height.is_nearly_zero()
-            || source_height == 0
-            || source_width == 0;
+            || image_info.height == 0
+            || image_info.width == 0;
 
         if !is_zero {
+            let (width, height) = get_object_fit(html_node)
+                .fit((source_width, source_height), (target_width, target_height));
+            let clip = width > target_width + 0.01 || height > target_height + 0.01;
+            if clip {
+                let rect = crate::graphics::Rect {
+                    x: Pt(pos.x),
+                    y: Pt(page_height.0 - pos.y),
+                    width: Pt(target_width),
+                    height: Pt(target_height),
+                };
+                ops.push(Op::SaveGraphicsState);
+                ops.push(Op::DrawPolygon {
+                    polygon: crate::graphics::Polygon {
+                        mode: crate::graphics::PaintMode::Clip,
+                        ..rect.to_polygon()
+                    },
+                });
+            }
+            // centered in the box, from the lower left corner (one pixel per point)
             ops.push(Op::UseXObject {
                 id: crate::XObjectId(image_info.xobject_id.clone()),
                 transform: crate::XObjectTransform {
-                    translate_x: Some(Pt(pos.x)),
-                    translate_y: Some(Pt(page_height.0 - pos.y)),
+                    translate_x: Some(Pt(pos.x + (target_width - width) / 2.0)),
+                    translate_y: Some(Pt(page_height.0 - pos.y - (target_height + height) / 2.0)),
                     rotate: None, // todo
-                    scale_x: Some(target_width / source_width as f32),
-                    scale_y: Some(target_height / source_height as f32),
-                    dpi: None,
+                    scale_x: Some(width / source_width),
+                    scale_y: Some(height / source_height),
+                    dpi: Some(72.0),
+                    rendering_intent: None,
                 },
             });
+            if clip {
+                ops.push(Op::RestoreGraphicsState);
+            }
         }
     }
 
@@ -571,7 +3090,19 @@ fn displaylist_handle_rect(
             lh: Pt(text.font_size_px),
         });
 
-        let glyphs = text.get_layouted_glyphs();
+        let mut glyphs = text.get_layouted_glyphs();
+        if let (azul_core::dom::NodeType::Text(s), Some(font)) =
+            (html_node.get_node_type(), doc.resources.fonts.map.get(&id))
+        {
+            let direction = get_text_direction(layout_result, rect_idx);
+            reorder_bidi_glyphs(
+                &mut glyphs.glyphs,
+                s.as_str(),
+                font,
+                text.font_size_px,
+                direction,
+            );
+        }
 
         let static_bounds = positioned_rect.get_approximate_static_bounds();
 
@@ -598,11 +3129,22 @@ fn displaylist_handle_rect(
         ops.push(Op::EndTextSection);
     }
 
+    // wrap the text / images of this node in a marked content sequence
+    if let Some(id) = structure_element {
+        if ops.len() > ops_start {
+            ops.insert(ops_start, Op::BeginStructureElement { id });
+            ops.push(Op::EndStructureElement);
+        }
+    }
+
+    // backgrounds and borders are decorations, not part of the document text
     if !newops.is_empty() {
         println!("{newops:?}");
+        ops.push(Op::BeginArtifact);
         ops.push(Op::SaveGraphicsState);
         ops.append(&mut newops);
         ops.push(Op::RestoreGraphicsState);
+        ops.push(Op::EndArtifact);
     }
 
     Some(())
@@ -939,3 +3481,306 @@ fn get_opt_border(
         },
     })
 }
+
+#[test]
+fn test_extract_links() {
+    let xml = r#"<p>See <a href="https://example.com/?a=1&amp;b=2" class="x">docs</a> or <a href="/faq">FAQ</a>.</p>"#;
+    let (xml, hrefs) = extract_links(xml);
+    assert_eq!(
+        xml,
+        r#"<p>See <span  class="x __printpdf_link_0">docs</span> or <span  class="__printpdf_link_1">FAQ</span>.</p>"#
+    );
+    assert_eq!(hrefs, ["https://example.com/?a=1&b=2", "/faq"]);
+}
+
+#[test]
+fn test_extract_z_indices() {
+    let xml = r#"<div class="badge" style="position: absolute; z-index: 2"></div><p style="z-index:-1">a</p>"#;
+    assert_eq!(
+        extract_z_indices(xml),
+        r#"<div class="badge __printpdf_z_2" style="position: absolute"></div><p style="" class="__printpdf_z_-1">a</p>"#
+    );
+
+    let layer = |z: i32, text: &str| StackingLayer {
+        z,
+        ops: vec![Op::Marker {
+            id: text.to_string(),
+        }],
+    };
+    let mut ops = vec![
+        Op::Marker {
+            id: "root".to_string(),
+        },
+        Op::Marker {
+            id: "flow".to_string(),
+        },
+    ];
+    let layers = vec![layer(1, "a"), layer(-1, "b"), layer(0, "c"), layer(1, "d")];
+    paint_layers(&mut ops, 1, layers);
+    let order = ops
+        .iter()
+        .map(|op| match op {
+            Op::Marker { id } => id.as_str(),
+            _ => "",
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(order, ["root", "b", "flow", "c", "a", "d"]);
+}
+
+#[test]
+fn test_content_bbox() {
+    let doc = PdfDocument::new("bbox");
+    assert_eq!(get_content_bbox(&doc, &[]), None);
+    let rect = Rect {
+        x: Pt(0.0),
+        y: Pt(30.0),
+        width: Pt(50.0),
+        height: Pt(30.0),
+    };
+    let ops = [
+        Op::SaveGraphicsState,
+        Op::SetTransformationMatrix {
+            matrix: crate::CurTransMat::Translate(Pt(10.0), Pt(20.0)),
+        },
+        Op::DrawPolygon {
+            polygon: rect.to_polygon(),
+        },
+        Op::RestoreGraphicsState,
+    ];
+    assert_eq!(
+        get_content_bbox(&doc, &ops),
+        Some(Rect {
+            x: Pt(10.0),
+            y: Pt(20.0),
+            width: Pt(50.0),
+            height: Pt(30.0),
+        })
+    );
+}
+
+#[test]
+fn test_get_missing_assets() {
+    let mut config = XmlRenderOptions::default();
+    config.images.insert("logo.png".to_string(), Vec::new());
+    config.fonts.insert("Roboto.ttf".to_string(), Vec::new());
+    let html = r#"<div style="font-family: 'Open Sans', Roboto, sans-serif"><img src="logo.png"/><img srcset="a.png 1x, b.png 2x" src="a.png"/><p style="font-family: Helvetica">x</p></div>"#;
+    assert_eq!(
+        config.get_missing_assets(html),
+        [
+            HtmlAsset::Image("a.png".to_string()),
+            HtmlAsset::Image("b.png".to_string()),
+            HtmlAsset::Font("Open Sans".to_string()),
+        ]
+    );
+
+    // only the smallest candidate with at least 300 DPI is fetched (and the src)
+    let html = r#"<img srcset="b.png 2x, c.png 8x, d.png 16x" src="a.png"/><img srcset="s.jpg 400w, l.jpg 1600w" style="width: 50px"/>"#;
+    assert_eq!(
+        config.get_missing_assets(html),
+        [
+            HtmlAsset::Image("a.png".to_string()),
+            HtmlAsset::Image("c.png".to_string()),
+            HtmlAsset::Image("s.jpg".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_mark_elements() {
+    let images = BTreeMap::new();
+    let xml = r#"<html><body><div id="main"><p>a</p><p class="b">b<img src="logo.png"/></p></div><img srcset="missing.png 2x"/></body></html>"#;
+    let (marked, elements) = mark_elements(xml, &images);
+    assert_eq!(
+        elements.paths,
+        [
+            "html",
+            "html > body",
+            "html > body > div#main",
+            "html > body > div#main > p:nth-child(1)",
+            "html > body > div#main > p:nth-child(2)",
+            "html > body > div#main > p:nth-child(2) > img:nth-child(1)",
+            "html > body > img:nth-child(2)",
+        ]
+    );
+    // images without a src can't be reported
+    assert_eq!(
+        elements.missing_images,
+        BTreeMap::from([(5, "logo.png".to_string())])
+    );
+    assert!(marked.contains(r#"<p class="b __printpdf_el_4">"#));
+    assert!(marked.contains(r#"<img src="logo.png" class="__printpdf_el_5"/>"#));
+}
+
+#[test]
+fn test_embed_invalid_image() {
+    let mut config = XmlRenderOptions::default();
+    config
+        .images
+        .insert("broken.png".to_string(), b"not an image".to_vec());
+    let mut doc = PdfDocument::new("images");
+    let mut diagnostics = Vec::new();
+    let images = embed_images(&mut doc, &config, &mut diagnostics);
+
+    assert!(images.is_empty());
+    assert_eq!(diagnostics.len(), 1);
+    assert!(matches!(
+        &diagnostics[0].reason,
+        HtmlDiagnosticReason::InvalidImage { key, .. } if key == "broken.png"
+    ));
+    assert_eq!(
+        diagnostics[0].to_warning().code,
+        PdfWarnCode::HtmlMissingImage
+    );
+}
+
+#[test]
+fn test_prune_hidden_elements() {
+    let xml = r#"<div><p style="display: none">a<b>b</b></p><p hidden="hidden">c</p><img style="display:none" /><div style="visibility: hidden; color: red"><p>d</p><p style="visibility: visible">e</p></div></div>"#;
+    assert_eq!(
+        prune_hidden_elements(xml),
+        r#"<div><div style="color: red" class="__printpdf_hidden"><p class="__printpdf_hidden">d</p><p style="">e</p></div></div>"#
+    );
+}
+
+#[test]
+fn test_resolve_size_constraints() {
+    let xml = r#"<div style="max-width: 400px; padding: 0 10px"><img style="width: 100%; aspect-ratio: 16 / 9" /><p style="width: 50px; min-width: 80px">a</p></div>"#;
+    assert_eq!(
+        resolve_size_constraints(xml, Pt(600.0)),
+        r#"<div style="max-width: 400px; padding: 0 10px; width: 400px"><img style="width: 400px; height: 225px" /><p style="min-width: 80px; width: 80px">a</p></div>"#
+    );
+    // narrow pages are not affected by the max-width
+    let xml = r#"<div style="max-width: 400px">a</div>"#;
+    assert_eq!(resolve_size_constraints(xml, Pt(300.0)), xml);
+}
+
+#[test]
+fn test_resolve_images() {
+    let image = |id: &str, width: usize| ImageInfo {
+        original_id: id.to_string(),
+        xobject_id: id.to_string(),
+        width,
+        height: width * 3 / 4,
+        ..Default::default()
+    };
+    let images = [image("small.jpg", 800), image("large.jpg", 1600)]
+        .into_iter()
+        .map(|i| (i.original_id.clone(), i))
+        .collect::<BTreeMap<_, _>>();
+    let json = |id: &str| serde_json::to_string(&images[id]).unwrap();
+
+    // 200px at 144 DPI need 400 pixels
+    let xml = r#"<img srcset="small.jpg 800w, large.jpg 1600w" style="width: 200px" />"#;
+    assert_eq!(
+        resolve_images(xml, &images, Pt(600.0), 144.0),
+        format!(
+            r#"<img  style="width: 200px"  src='{}'/>"#,
+            json("small.jpg")
+        )
+    );
+    // the natural width is 800px, which needs 1600 pixels
+    let xml = r#"<img src="small.jpg" srcset="large.jpg 2x" style="object-fit: cover" />"#;
+    assert_eq!(
+        resolve_images(xml, &images, Pt(600.0), 144.0),
+        format!(
+            r#"<img src='{}'  style="width: 800px"  class="__printpdf_fit_cover"/>"#,
+            json("large.jpg")
+        )
+    );
+
+    let source = (100.0, 50.0);
+    let target = (200.0, 200.0);
+    assert_eq!(ObjectFit::Fill.fit(source, target), (200.0, 200.0));
+    assert_eq!(ObjectFit::Contain.fit(source, target), (200.0, 100.0));
+    assert_eq!(ObjectFit::Cover.fit(source, target), (400.0, 200.0));
+    assert_eq!(ObjectFit::ScaleDown.fit(source, target), (100.0, 50.0));
+}
+
+#[test]
+fn test_convert_grids() {
+    let xml = r#"<div style="display: grid; grid-template-columns: 100px repeat(2, 1fr); gap: 10px"><p>a</p><p style="grid-column: span 2">b</p><p style="grid-column: 2 / 4">c</p></div>"#;
+    let row = |margin: &str| format!("<div style=\"display:flex;flex-direction:row;{margin}\">");
+    let expected = [
+        r#"<div style="display:flex;flex-direction:column">"#.to_string(),
+        row(""),
+        r#"<p style="flex-grow:0;flex-shrink:0;width:100px;">a</p>"#.to_string(),
+        r#"<p style="grid-column: span 2;flex-grow:2;flex-shrink:0;width:10px;margin-left:10px;">b</p>"#.to_string(),
+        "</div>".to_string(),
+        row("margin-top:10px;"),
+        r#"<div style="flex-grow:0;flex-shrink:0;width:100px;"></div>"#.to_string(),
+        r#"<p style="grid-column: 2 / 4;flex-grow:2;flex-shrink:0;width:10px;margin-left:10px;">c</p>"#.to_string(),
+        "</div></div>".to_string(),
+    ];
+    assert_eq!(convert_grids(xml), expected.concat());
+
+    assert_eq!(
+        get_grid_column(&parse_style("grid-column: 1 / -1"), 3),
+        (Some(0), 3)
+    );
+    assert_eq!(
+        parse_grid_tracks("minmax(100px, 1fr) repeat(2, 50px)"),
+        ["minmax(100px, 1fr)", "50px", "50px"]
+    );
+    assert_eq!(
+        parse_grid_track("minmax(100px, 2fr)"),
+        Some(GridTrack::Fraction(2.0))
+    );
+}
+
+#[test]
+fn test_break_long_words() {
+    let xml = r#"<div style="width: 70px; overflow-wrap: break-word; font-size: 10px"><p>DE89370400440532013000 ok</p></div><p>DE89370400440532013000</p>"#;
+    assert_eq!(
+        break_long_words(xml, Pt(100.0)),
+        r#"<div style="width: 70px; font-size: 10px"><p>DE89370400 4405320130 00 ok</p></div><p>DE89370400440532013000</p>"#
+    );
+
+    let breaking = WordBreaking {
+        break_words: true,
+        hyphens: false,
+    };
+    assert_eq!(
+        break_words("a&amp;b&amp;c d", 2, breaking).as_deref(),
+        Some("a&amp; b&amp; c d")
+    );
+    assert_eq!(break_words("short words", 10, breaking), None);
+}
+
+#[test]
+fn test_bidi() {
+    let xml = r#"<p dir="rtl">a</p><div style="direction: ltr; text-align: center" class="x">b</div><bdo dir="rtl">c</bdo>"#;
+    assert_eq!(
+        extract_directions(xml),
+        r#"<p dir="rtl" style="text-align: right" class="__printpdf_dir_rtl">a</p><div style="text-align: center" class="x __printpdf_dir_ltr">b</div><bdo dir="rtl" style="text-align: right" class="__printpdf_dir_rtl_override">c</bdo>"#
+    );
+
+    // "אב 1,2 ab" in a right to left paragraph
+    let line = "אב 1,2 ab"
+        .chars()
+        .enumerate()
+        .map(|(i, c)| (i as f32, 1.0, BidiClass::of(c)))
+        .collect::<Vec<_>>();
+    let rtl = TextDirection {
+        rtl: true,
+        bidi_override: false,
+    };
+    let visual = reorder_bidi_line(&line, rtl);
+    assert_eq!(visual, [8.0, 7.0, 6.0, 3.0, 4.0, 5.0, 2.0, 0.0, 1.0]);
+    let reversed = reorder_bidi_line(
+        &line,
+        TextDirection {
+            rtl: true,
+            bidi_override: true,
+        },
+    );
+    assert_eq!(reversed, [8.0, 7.0, 6.0, 5.0, 4.0, 3.0, 2.0, 1.0, 0.0]);
+    // left to right text without right to left characters is not changed
+    let latin = [
+        (0.0, 1.0, BidiClass::LeftToRight),
+        (1.0, 1.0, BidiClass::Number),
+    ];
+    assert_eq!(
+        reorder_bidi_line(&latin, TextDirection::default()),
+        [0.0, 1.0]
+    );
+}
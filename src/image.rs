@@ -1,7 +1,8 @@
-use crate::{ColorBits, ColorSpace};
+use crate::{ColorBits, ColorSpace, XObjectId};
 use core::fmt;
 use image::GenericImageView;
 use serde_derive::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::io::Cursor;
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, PartialOrd)]
@@ -11,6 +12,281 @@ pub struct RawImage {
     pub height: usize,
     pub data_format: RawImageFormat,
     pub tag: Vec<u8>,
+    /// Original encoded stream (i.e. a JPEG), written as-is instead of the pixels
+    #[serde(default)]
+    pub source: Option<EncodedImage>,
+}
+
+/// Still encoded image data (i.e. a JPEG or a parsed image stream), passed through
+/// to the PDF without decoding
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, PartialOrd)]
+pub struct EncodedImage {
+    /// Encoded bytes of the image stream
+    pub bytes: Vec<u8>,
+    /// Filter chain, in the order in which the filters are applied when decoding
+    #[serde(default)]
+    pub filters: Vec<StreamFilter>,
+    /// Device color space name: `"DeviceGray"`, `"DeviceRGB"` or `"DeviceCMYK"`
+    pub color_space: String,
+    /// ICC profile of the color space (`/ICCBased`), with the same number of components
+    #[serde(default)]
+    pub icc_profile: Option<Vec<u8>>,
+    /// Bits per component (8 for baseline JPEGs)
+    pub bits_per_component: u8,
+    /// Optional `/Decode` array (i.e. inverted Adobe CMYK JPEGs)
+    #[serde(default)]
+    pub decode: Option<Vec<f32>>,
+}
+
+/// Stream filter (`/Filter`) and its parameters (`/DecodeParms`)
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, PartialOrd)]
+pub struct StreamFilter {
+    /// Filter name, i.e. `"FlateDecode"` or `"DCTDecode"`
+    pub name: String,
+    /// Decode parameters of the filter
+    #[serde(default)]
+    pub parms: DecodeParms,
+}
+
+impl StreamFilter {
+    /// Creates a filter without decode parameters
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            parms: DecodeParms::default(),
+        }
+    }
+}
+
+/// Decode parameters of a stream filter, `None` = default value
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, PartialOrd)]
+pub struct DecodeParms {
+    /// `/Predictor` (Flate and LZW)
+    pub predictor: Option<i64>,
+    /// `/Colors`, components per sample (Flate and LZW)
+    pub colors: Option<i64>,
+    /// `/BitsPerComponent` (Flate and LZW)
+    pub bits_per_component: Option<i64>,
+    /// `/Columns`, samples per row (Flate and LZW)
+    pub columns: Option<i64>,
+    /// `/EarlyChange` (LZW)
+    pub early_change: Option<i64>,
+    /// `/ColorTransform` (DCT)
+    pub color_transform: Option<i64>,
+}
+
+impl DecodeParms {
+    /// Returns whether all parameters have their default value
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub(crate) fn to_dict(&self) -> lopdf::Dictionary {
+        let values = [
+            ("Predictor", self.predictor),
+            ("Colors", self.colors),
+            ("BitsPerComponent", self.bits_per_component),
+            ("Columns", self.columns),
+            ("EarlyChange", self.early_change),
+            ("ColorTransform", self.color_transform),
+        ];
+        values
+            .into_iter()
+            .filter_map(|(k, v)| Some((k, lopdf::Object::Integer(v?))))
+            .collect()
+    }
+
+    pub(crate) fn from_dict(dict: &lopdf::Dictionary) -> Self {
+        let get = |key: &[u8]| dict.get(key).and_then(|v| v.as_i64()).ok();
+        Self {
+            predictor: get(b"Predictor"),
+            colors: get(b"Colors"),
+            bits_per_component: get(b"BitsPerComponent"),
+            columns: get(b"Columns"),
+            early_change: get(b"EarlyChange"),
+            color_transform: get(b"ColorTransform"),
+        }
+    }
+}
+
+/// Size of the JPEG headers and tables that an embedded JPEG may have on top of its pixels
+const MAX_JPEG_OVERHEAD: usize = 1 << 20;
+
+impl EncodedImage {
+    /// Number of color components of the color space
+    pub fn get_components(&self) -> usize {
+        match self.color_space.as_str() {
+            "DeviceGray" => 1,
+            "DeviceCMYK" => 4,
+            _ => 3,
+        }
+    }
+
+    /// Format of the decoded pixels, CMYK is converted to RGB
+    pub fn get_decoded_format(&self) -> RawImageFormat {
+        match (self.get_components(), self.bits_per_component) {
+            (1, 16) => RawImageFormat::R16,
+            (1, _) => RawImageFormat::R8,
+            (3, 16) => RawImageFormat::RGB16,
+            _ => RawImageFormat::RGB8,
+        }
+    }
+
+    /// Decodes the stream to pixels in the format of `get_decoded_format`
+    pub(crate) fn decode_pixels(
+        &self,
+        width: usize,
+        height: usize,
+    ) -> Result<RawImageData, String> {
+        let components = self.get_components();
+        let bpc = self.bits_per_component as usize;
+        let row_len = width
+            .checked_mul(components * bpc)
+            .map(|bits| bits.div_ceil(8))
+            .ok_or_else(|| format!("image too large: {width}x{height}"))?;
+        // the stream is never decoded beyond the size of the pixels (plus the predictor
+        // byte of each row), JPEGs may be larger than the pixels of tiny images
+        let max_size = (row_len + 1)
+            .checked_mul(height)
+            .ok_or_else(|| format!("image too large: {width}x{height}"))?;
+        let is_jpeg = self
+            .filters
+            .iter()
+            .any(|f| matches!(f.name.as_str(), "DCTDecode" | "DCT"));
+        let max_size = match is_jpeg {
+            true => max_size.saturating_add(MAX_JPEG_OVERHEAD),
+            false => max_size,
+        };
+        let (data, image_filters) =
+            crate::filters::decode_filters_limited(&self.bytes, &self.filters, max_size)?;
+        match image_filters.first().map(|f| f.name.as_str()) {
+            None => {}
+            Some("DCTDecode" | "DCT") => {
+                let header = parse_jpeg_header(&data)?;
+                if (header.width, header.height) != (width, height) {
+                    return Err(format!(
+                        "JPEG is {}x{}, expected {width}x{height}",
+                        header.width, header.height
+                    ));
+                }
+                let decoded = RawImage::decode_from_bytes(&data)?;
+                if decoded.data_format != self.get_decoded_format() {
+                    return Err(format!(
+                        "decoded JPEG has the format {:?}, expected {:?}",
+                        decoded.data_format,
+                        self.get_decoded_format()
+                    ));
+                }
+                return Ok(decoded.pixels);
+            }
+            Some(other) => return Err(format!("cannot decode image filter {other}")),
+        }
+
+        if data.len() < row_len * height {
+            return Err(format!(
+                "image data too short: {} bytes, expected {}",
+                data.len(),
+                row_len * height
+            ));
+        }
+
+        // the /Decode array maps the samples (only the inversion [1 0] is supported)
+        let inverted = |c: usize| {
+            self.decode
+                .as_ref()
+                .and_then(|d| d.get(c * 2..c * 2 + 2))
+                .map(|d| d[0] > d[1])
+                .unwrap_or(false)
+        };
+
+        if bpc == 16 {
+            let samples = data[..row_len * height]
+                .chunks_exact(2)
+                .enumerate()
+                .map(|(i, b)| {
+                    let v = u16::from_be_bytes([b[0], b[1]]);
+                    if inverted(i % components) {
+                        u16::MAX - v
+                    } else {
+                        v
+                    }
+                });
+            return match components {
+                4 => Err("16 bit CMYK images are not supported".to_string()),
+                _ => Ok(RawImageData::U16(samples.collect())),
+            };
+        }
+
+        // unpack 1, 2 and 4 bit samples, scale to 8 bit
+        let max = ((1u32 << bpc) - 1) as f32;
+        let mut samples = Vec::with_capacity(width * height * components);
+        for row in data.chunks(row_len).take(height) {
+            for i in 0..width * components {
+                let v = match bpc {
+                    8 => row[i],
+                    1 | 2 | 4 => {
+                        let bit = i * bpc;
+                        let v = (row[bit / 8] >> (8 - bpc - bit % 8)) & ((1 << bpc) - 1) as u8;
+                        (v as f32 / max * 255.0).round() as u8
+                    }
+                    _ => return Err(format!("unsupported bits per component: {bpc}")),
+                };
+                samples.push(if inverted(i % components) { 255 - v } else { v });
+            }
+        }
+
+        if components == 4 {
+            // naive CMYK -> RGB conversion (without the ICC profile)
+            samples = samples
+                .chunks_exact(4)
+                .flat_map(|c| {
+                    let k = 255 - c[3] as u32;
+                    [0, 1, 2].map(|i| ((255 - c[i] as u32) * k / 255) as u8)
+                })
+                .collect();
+        }
+        Ok(RawImageData::U8(samples))
+    }
+}
+
+/// Hook for the image XObjects of parsed documents, i.e. to decode formats that printpdf
+/// doesn't support (JBIG2, proprietary formats) or to use a hardware JPEG decoder, see
+/// `PdfDocument::parse_with_image_decoder`
+pub trait ImageDecoder {
+    /// Called for every image XObject of the page resources, before the builtin handling
+    fn decode(&mut self, id: &XObjectId, stream: &ImageStream) -> ImageDecodeResult;
+}
+
+/// Image XObject of a parsed document, see `ImageDecoder`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageStream<'a> {
+    /// Width of the image in pixels (`/Width`)
+    pub width: usize,
+    /// Height of the image in pixels (`/Height`)
+    pub height: usize,
+    /// Bits per component (`/BitsPerComponent`), `None` for JPEG 2000 and image masks
+    pub bits_per_component: Option<u8>,
+    /// Name of the color space, or the family of array color spaces (i.e. `"ICCBased"`)
+    pub color_space: Option<String>,
+    /// Names of the filters (i.e. `"DCTDecode"` or `"JBIG2Decode"`), in decode order
+    pub filters: Vec<String>,
+    /// Still encoded bytes of the stream (already decrypted)
+    pub bytes: &'a [u8],
+    /// Image as it is parsed by default (kept encoded, the pixels are decoded on demand),
+    /// `None` if the image is not supported (i.e. JBIG2 or images with masks)
+    pub image: Option<&'a RawImage>,
+}
+
+/// What to do with an image XObject, returned by `ImageDecoder::decode`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImageDecodeResult {
+    /// Use the builtin handling (`ImageStream::image`)
+    Default,
+    /// Use this image instead, i.e. the decoded pixels (the image is re-encoded on save
+    /// unless its `source` is set)
+    Image(RawImage),
+    /// Don't import the XObject, neither decoded nor passed through
+    Skip,
 }
 
 struct RawImageU8 {
@@ -60,6 +336,12 @@ impl RawImageFormat {
         }
     }
 
+    /// Returns whether the pixels are in an RGB color space (grayscale otherwise)
+    pub fn is_rgb(&self) -> bool {
+        use self::RawImageFormat::*;
+        !matches!(self, R8 | RG8 | R16 | RG16)
+    }
+
     fn from_internal(f: &azul_core::app_resources::RawImageFormat) -> Self {
         use azul_core::app_resources::RawImageFormat;
         match f {
@@ -160,6 +442,52 @@ impl RawImage {
             data_format: format,
             pixels: RawImageData::empty(format),
             tag: Vec::new(),
+            source: None,
+        }
+    }
+
+    /// Creates an image from JPEG bytes without decoding them: the size and color
+    /// space are read from the JPEG header and the bytes are embedded with `/DCTDecode`.
+    /// Works for progressive and CMYK JPEGs, but the `pixels` stay empty.
+    pub fn from_jpeg_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let header = parse_jpeg_header(bytes)?;
+        let (data_format, color_space) = match header.components {
+            1 => (RawImageFormat::R8, "DeviceGray"),
+            3 => (RawImageFormat::RGB8, "DeviceRGB"),
+            4 => (RawImageFormat::RGB8, "DeviceCMYK"),
+            n => return Err(format!("unsupported number of JPEG components: {n}")),
+        };
+        // Adobe (Photoshop) CMYK JPEGs are stored inverted
+        let decode = (header.components == 4 && header.adobe).then(|| [1.0, 0.0].repeat(4));
+
+        Ok(Self {
+            pixels: RawImageData::empty(data_format),
+            width: header.width,
+            height: header.height,
+            data_format,
+            tag: Vec::new(),
+            source: Some(EncodedImage {
+                bytes: bytes.to_vec(),
+                filters: vec![StreamFilter::new("DCTDecode")],
+                color_space: color_space.to_string(),
+                icc_profile: None,
+                bits_per_component: header.precision,
+                decode,
+            }),
+        })
+    }
+
+    /// Returns the pixels of the image. Images with an encoded `source` (parsed
+    /// images or `from_jpeg_bytes`) are only decoded when this function is called.
+    ///
+    /// The `source` stays attached and is written instead of the pixels, set it
+    /// to `None` after modifying the pixels.
+    pub fn pixels(&self) -> Result<Cow<'_, RawImageData>, String> {
+        match self.source.as_ref() {
+            Some(source) if self.pixels.is_empty() => source
+                .decode_pixels(self.width, self.height)
+                .map(Cow::Owned),
+            _ => Ok(Cow::Borrowed(&self.pixels)),
         }
     }
 
@@ -170,6 +498,14 @@ impl RawImage {
         let im = image::guess_format(bytes).map_err(|e| e.to_string())?;
         let b_len = bytes.len();
 
+        // handles planar, tiled and CMYK TIFFs, which the generic decoder rejects
+        #[cfg(feature = "tiff")]
+        if im == image::ImageFormat::Tiff {
+            if let Ok(image) = Self::from_tiff_bytes(bytes) {
+                return Ok(image);
+            }
+        }
+
         #[cfg(not(feature = "gif"))]
         {
             let err = format!("cannot decode image (len = {b_len} bytes): printpdf is missing feature 'gif' to decode GIF files. Please enable it or construct the RawImage manually.");
@@ -307,6 +643,7 @@ impl RawImage {
             height: h as usize,
             data_format: ct,
             tag: Vec::new(),
+            source: None,
         })
     }
 
@@ -328,9 +665,143 @@ impl RawImage {
     }
 }
 
-pub(crate) fn image_to_stream(im: RawImage, doc: &mut lopdf::Document) -> lopdf::Stream {
+struct JpegHeader {
+    width: usize,
+    height: usize,
+    components: u8,
+    precision: u8,
+    /// Whether an Adobe APP14 marker is present
+    adobe: bool,
+}
+
+/// Reads the frame header (SOFn marker) of a JPEG
+fn parse_jpeg_header(bytes: &[u8]) -> Result<JpegHeader, String> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return Err("not a JPEG file (missing SOI marker)".to_string());
+    }
+
+    let mut adobe = false;
+    let mut pos = 2;
+    while pos + 4 <= bytes.len() {
+        if bytes[pos] != 0xFF {
+            return Err(format!("invalid JPEG marker at offset {pos}"));
+        }
+        let marker = bytes[pos + 1];
+        match marker {
+            // fill byte
+            0xFF => {
+                pos += 1;
+                continue;
+            }
+            // markers without a length
+            0x01 | 0xD0..=0xD7 => {
+                pos += 2;
+                continue;
+            }
+            _ => {}
+        }
+
+        let len = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        let segment = bytes.get(pos + 4..pos + 2 + len).unwrap_or_default();
+        match marker {
+            0xEE if segment.starts_with(b"Adobe") => adobe = true,
+            // SOF0 - SOF15, except DHT (C4), JPG (C8) and DAC (CC)
+            0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                if segment.len() < 6 {
+                    return Err("truncated JPEG frame header".to_string());
+                }
+                return Ok(JpegHeader {
+                    precision: segment[0],
+                    height: u16::from_be_bytes([segment[1], segment[2]]) as usize,
+                    width: u16::from_be_bytes([segment[3], segment[4]]) as usize,
+                    components: segment[5],
+                    adobe,
+                });
+            }
+            // start of scan before the frame header
+            0xDA => break,
+            _ => {}
+        }
+        pos += 2 + len;
+    }
+
+    Err("JPEG frame header not found".to_string())
+}
+
+/// Writes the original encoded bytes with their filter chain
+fn encoded_image_to_stream(
+    width: usize,
+    height: usize,
+    source: EncodedImage,
+    doc: &mut lopdf::Document,
+) -> lopdf::Stream {
+    use lopdf::Object::*;
+
+    let color_space = match source.icc_profile.as_ref() {
+        Some(icc) => {
+            let icc_dict = lopdf::Dictionary::from_iter(vec![
+                ("N", Integer(source.get_components() as i64)),
+                ("Alternate", Name(source.color_space.clone().into_bytes())),
+            ]);
+            let icc_stream = lopdf::Stream::new(icc_dict, icc.clone());
+            Array(vec![
+                Name("ICCBased".into()),
+                Reference(doc.add_object(icc_stream)),
+            ])
+        }
+        None => Name(source.color_space.clone().into_bytes()),
+    };
+
+    let mut dict = lopdf::Dictionary::from_iter(vec![
+        ("Type", Name("XObject".into())),
+        ("Subtype", Name("Image".into())),
+        ("Width", Integer(width as i64)),
+        ("Height", Integer(height as i64)),
+        (
+            "BitsPerComponent",
+            Integer(source.bits_per_component.into()),
+        ),
+        ("ColorSpace", color_space),
+    ]);
+
+    match source.filters.as_slice() {
+        [] => {}
+        [filter] => {
+            dict.set("Filter", Name(filter.name.clone().into_bytes()));
+            if !filter.parms.is_empty() {
+                dict.set("DecodeParms", Dictionary(filter.parms.to_dict()));
+            }
+        }
+        filters => {
+            let names = filters.iter().map(|f| Name(f.name.clone().into_bytes()));
+            dict.set("Filter", Array(names.collect()));
+            if filters.iter().any(|f| !f.parms.is_empty()) {
+                let parms = filters.iter().map(|f| {
+                    if f.parms.is_empty() {
+                        Null
+                    } else {
+                        Dictionary(f.parms.to_dict())
+                    }
+                });
+                dict.set("DecodeParms", Array(parms.collect()));
+            }
+        }
+    }
+    if let Some(decode) = source.decode {
+        dict.set("Decode", Array(decode.into_iter().map(Real).collect()));
+    }
+
+    // already compressed, must not be compressed again
+    lopdf::Stream::new(dict, source.bytes).with_compression(false)
+}
+
+pub(crate) fn image_to_stream(mut im: RawImage, doc: &mut lopdf::Document) -> lopdf::Stream {
     use lopdf::Object::*;
 
+    if let Some(source) = im.source.take() {
+        return encoded_image_to_stream(im.width, im.height, source, doc);
+    }
+
     let (rgb8, alpha) = split_rawimage_into_rgb_plus_alpha(im);
     let (bpc, cs) = rgb8.data_format.get_color_bits_and_space();
     let bbox = crate::CurTransMat::Identity;
@@ -361,18 +832,12 @@ pub(crate) fn image_to_stream(im: RawImage, doc: &mut lopdf::Document) -> lopdf:
             ("ColorSpace", Name(ColorSpace::Greyscale.as_string().into())),
         ]);
 
-        let mut stream = lopdf::Stream::new(smask_dict, alpha.pixels).with_compression(true);
-
-        let _ = stream.compress();
-
+        // compressed on save, see `PdfSaveOptions::compression`
+        let stream = lopdf::Stream::new(smask_dict, alpha.pixels).with_compression(true);
         dict.set("SMask", Reference(doc.add_object(stream)));
     }
 
-    let mut s = lopdf::Stream::new(dict, rgb8.pixels).with_compression(true);
-
-    let _ = s.compress();
-
-    s
+    lopdf::Stream::new(dict, rgb8.pixels).with_compression(true)
 }
 
 // If the image has an alpha channel, splits the alpha channel as a separate image
@@ -445,3 +910,103 @@ pub fn translate_to_internal_rawimage(im: &RawImage) -> azul_core::app_resources
         tag: im.tag.clone().into(),
     }
 }
+
+#[test]
+fn test_jpeg_passthrough() {
+    use crate::{Mm, PdfDocument, PdfPage, PdfSaveOptions, XObject};
+
+    // SOI, Adobe APP14, progressive frame header (SOF2): 8 bit, 20 x 10, CMYK, SOS, EOI
+    let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xEE, 0x00, 0x0E];
+    jpeg.extend_from_slice(b"Adobe\0\x64\0\0\0\0\x02");
+    jpeg.extend_from_slice(&[0xFF, 0xC2, 0x00, 0x14, 0x08, 0x00, 0x0A, 0x00, 0x14, 0x04]);
+    jpeg.extend_from_slice(&[1, 0x11, 0, 2, 0x11, 0, 3, 0x11, 0, 4, 0x11, 0]);
+    jpeg.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02, 0xFF, 0xD9]);
+
+    let image = RawImage::from_jpeg_bytes(&jpeg).unwrap();
+    assert_eq!((image.width, image.height), (20, 10));
+    let source = image.source.clone().unwrap();
+    assert_eq!(source.color_space, "DeviceCMYK");
+    assert_eq!(source.decode.as_ref().map(|d| d.len()), Some(8));
+    assert!(RawImage::from_jpeg_bytes(b"\x89PNG").is_err());
+
+    let mut doc = PdfDocument::new("jpeg");
+    let id = doc.add_image(&image);
+    doc.pages
+        .push(PdfPage::new(Mm(210.0), Mm(297.0), Vec::new()));
+    let bytes = doc.save(&PdfSaveOptions::default());
+
+    let parsed = PdfDocument::parse(&bytes, &Default::default()).unwrap();
+    let Some(XObject::Image(parsed_image)) = parsed.resources.xobjects.map.get(&id) else {
+        panic!("image was not parsed");
+    };
+    assert_eq!(parsed_image.source.as_ref().unwrap().bytes, jpeg);
+    assert_eq!(parsed_image.width, 20);
+}
+
+#[test]
+fn test_lazy_image_decode() {
+    use crate::{Mm, PdfDocument, PdfPage, PdfSaveOptions, XObject};
+    use std::io::Write;
+
+    // 3 x 2 px, 16 bit grayscale, deflated
+    let samples: [u16; 6] = [0, 1000, 65535, 2, 3, 4];
+    let raw = samples
+        .iter()
+        .flat_map(|s| s.to_be_bytes())
+        .collect::<Vec<_>>();
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&raw).unwrap();
+    let deflated = encoder.finish().unwrap();
+
+    let image = RawImage {
+        pixels: RawImageData::empty(RawImageFormat::R16),
+        width: 3,
+        height: 2,
+        data_format: RawImageFormat::R16,
+        tag: Vec::new(),
+        source: Some(EncodedImage {
+            bytes: deflated.clone(),
+            filters: vec![StreamFilter::new("FlateDecode")],
+            color_space: "DeviceGray".to_string(),
+            icc_profile: Some(b"fake profile".to_vec()),
+            bits_per_component: 16,
+            decode: None,
+        }),
+    };
+    assert_eq!(
+        *image.pixels().unwrap(),
+        RawImageData::U16(samples.to_vec())
+    );
+
+    let mut doc = PdfDocument::new("scan");
+    let id = doc.add_image(&image);
+    doc.pages
+        .push(PdfPage::new(Mm(210.0), Mm(297.0), Vec::new()));
+    let bytes = doc.save(&PdfSaveOptions::default());
+
+    let parsed = PdfDocument::parse(&bytes, &Default::default()).unwrap();
+    let Some(XObject::Image(parsed_image)) = parsed.resources.xobjects.map.get(&id) else {
+        panic!("image was not parsed");
+    };
+    // not decoded and not re-encoded
+    assert!(parsed_image.pixels.is_empty());
+    assert_eq!(parsed_image.source, image.source);
+    assert_eq!(parsed_image.pixels().unwrap(), image.pixels().unwrap());
+}
+
+#[test]
+fn test_image_decode_limit() {
+    // 100 MB of zeros, deflated to ~100 KB, declared as a 10 x 10 px image
+    let deflated = crate::filters::flate_encode(&vec![0; 100_000_000], 9);
+    let image = EncodedImage {
+        bytes: deflated,
+        filters: vec![StreamFilter::new("FlateDecode")],
+        color_space: "DeviceGray".to_string(),
+        icc_profile: None,
+        bits_per_component: 8,
+        decode: None,
+    };
+    let err = image.decode_pixels(10, 10).unwrap_err();
+    assert!(err.contains("exceeds the limit of 110 bytes"), "{err}");
+    assert!(image.decode_pixels(usize::MAX, 2).is_err());
+}
@@ -0,0 +1,623 @@
+//! Interpreter of the page operations: tracks the graphics state (transformation matrix,
+//! colors, line style, text state) like a PDF viewer and emits the drawing events, so
+//! that renderers and other backends don't have to implement the imaging model themselves
+
+use std::collections::BTreeMap;
+
+use crate::{
+    color::{Color, Greyscale},
+    font::{BuiltinFont, ParsedFont},
+    gradient::Gradient,
+    graphics::{
+        BlendMode, LineCapStyle, LineJoinStyle, PaintMode, Point, Polygon, Rect, SoftMask,
+        TextRenderingMode, WindingOrder, LINE_CAP, LINE_DASH_PATTERN, LINE_JOIN, LINE_WIDTH,
+        MITER_LIMIT,
+    },
+    image::RawImage,
+    matrix::CurTransMat,
+    ops::Op,
+    text::{TextCursor, TextState},
+    units::Px,
+    xobject::{XObject, XObjectTransform},
+    PdfDocument, StructureElementId,
+};
+
+/// Maximum number of saved graphics states when drawing a group or ops XObject, stops
+/// XObjects that (indirectly) use themselves
+const MAX_FORM_NESTING: usize = 64;
+
+/// Graphics state of the interpreter, saved and restored by `q` / `Q`
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphicsState {
+    /// User space -> device space
+    pub ctm: [f32; 6],
+    pub fill_color: Color,
+    pub stroke_color: Color,
+    pub fill_alpha: f32,
+    pub stroke_alpha: f32,
+    /// Line width in user space
+    pub line_width: f32,
+    /// Dash pattern in user space, empty for solid lines
+    pub dash: Vec<f32>,
+    pub dash_offset: f32,
+    pub line_cap: LineCapStyle,
+    pub line_join: LineJoinStyle,
+    pub miter_limit: f32,
+    pub blend_mode: BlendMode,
+    pub soft_mask: Option<SoftMask>,
+    pub text_mode: TextRenderingMode,
+    pub text: TextState,
+}
+
+impl GraphicsState {
+    /// Initial state of a page, `device` maps the default user space to device space
+    pub fn new(device: [f32; 6]) -> Self {
+        Self {
+            ctm: device,
+            fill_color: Color::Greyscale(Greyscale::new(0.0, None)),
+            stroke_color: Color::Greyscale(Greyscale::new(0.0, None)),
+            fill_alpha: 1.0,
+            stroke_alpha: 1.0,
+            line_width: 1.0,
+            dash: Vec::new(),
+            dash_offset: 0.0,
+            line_cap: LineCapStyle::Butt,
+            line_join: LineJoinStyle::Miter,
+            miter_limit: 10.0,
+            blend_mode: BlendMode::normal(),
+            soft_mask: None,
+            text_mode: TextRenderingMode::Fill,
+            text: TextState::default(),
+        }
+    }
+
+    /// Transforms a point from user space to device space
+    pub fn to_device(&self, p: (f32, f32)) -> (f32, f32) {
+        let m = &self.ctm;
+        (
+            p.0 * m[0] + p.1 * m[2] + m[4],
+            p.0 * m[1] + p.1 * m[3] + m[5],
+        )
+    }
+}
+
+/// Glyph of a `DrawEvent::GlyphRun`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PositionedGlyph {
+    pub glyph_id: u16,
+    /// Glyph space (font units) -> device space, includes the font size, the text state
+    /// and the text matrix
+    pub matrix: [f32; 6],
+}
+
+/// Drawing event of the `OpInterpreter`. Paths are in user space (points with a flag
+/// whether they are bezier control points, like in `Polygon`), `GraphicsState::ctm`
+/// maps them to device space. All other matrices map directly to device space.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DrawEvent<'a> {
+    /// The graphics state was saved (`q`), the backend should save its own state (clip)
+    SaveState,
+    /// The graphics state was restored (`Q`), only emitted for a matching `SaveState`
+    RestoreState,
+    /// Fills the rings with the fill color
+    FillPath {
+        rings: &'a [Vec<(Point, bool)>],
+        winding: WindingOrder,
+    },
+    /// Strokes the rings with the stroke color and the line style of the state
+    StrokePath {
+        rings: &'a [Vec<(Point, bool)>],
+        closed: bool,
+    },
+    /// Intersects the clipping path with the rings
+    ClipPath {
+        rings: &'a [Vec<(Point, bool)>],
+        winding: WindingOrder,
+    },
+    /// Glyphs of a text showing operation, painted according to the text rendering mode
+    GlyphRun {
+        font: &'a ParsedFont,
+        glyphs: &'a [PositionedGlyph],
+    },
+    /// Image, `matrix` maps the unit square to device space (the first row of the
+    /// image is the top edge)
+    Image {
+        image: &'a RawImage,
+        matrix: [f32; 6],
+    },
+    /// Gradient inside the clip polygon (user space), `matrix` maps the coordinate
+    /// space of the gradient to device space
+    Gradient {
+        gradient: &'a Gradient,
+        clip: &'a Polygon,
+        matrix: [f32; 6],
+    },
+    /// Start of the content of a structure element (`Op::BeginStructureElement`)
+    BeginStructureElement { id: &'a StructureElementId },
+    /// End of the content of the innermost structure element
+    EndStructureElement,
+    /// Operation that the interpreter can't draw (i.e. form XObjects)
+    Unsupported { feature: String },
+}
+
+/// Runs the page operations through the PDF imaging model, see `DrawEvent`
+pub struct OpInterpreter<'a> {
+    doc: &'a PdfDocument,
+    state: GraphicsState,
+    stack: Vec<GraphicsState>,
+    cursor: TextCursor,
+    builtin_fonts: BTreeMap<BuiltinFont, Option<ParsedFont>>,
+}
+
+impl<'a> OpInterpreter<'a> {
+    /// Creates an interpreter for the resources of the document, `device` maps the
+    /// default user space of the page to device space
+    pub fn new(doc: &'a PdfDocument, device: [f32; 6]) -> Self {
+        Self {
+            doc,
+            state: GraphicsState::new(device),
+            stack: Vec::new(),
+            cursor: TextCursor::default(),
+            builtin_fonts: BTreeMap::new(),
+        }
+    }
+
+    /// Current graphics state
+    pub fn get_state(&self) -> &GraphicsState {
+        &self.state
+    }
+
+    /// Interprets the operations, `f` is called for every drawing event with the
+    /// graphics state at the time of the event
+    pub fn run<F>(&mut self, ops: &[Op], mut f: F)
+    where
+        F: FnMut(&GraphicsState, DrawEvent<'_>),
+    {
+        for op in ops.iter() {
+            self.run_op(op, &mut f);
+        }
+    }
+
+    /// Interprets the operations and draws them with the backend
+    pub fn render<B: RenderBackend + ?Sized>(&mut self, ops: &[Op], backend: &mut B) {
+        self.run(ops, |gs, event| match event {
+            DrawEvent::SaveState => backend.save_state(),
+            DrawEvent::RestoreState => backend.restore_state(),
+            DrawEvent::FillPath { rings, winding } => backend.fill_path(gs, rings, winding),
+            DrawEvent::StrokePath { rings, closed } => backend.stroke_path(gs, rings, closed),
+            DrawEvent::ClipPath { rings, winding } => backend.push_clip(gs, rings, winding),
+            DrawEvent::GlyphRun { font, glyphs } => backend.draw_glyph_run(gs, font, glyphs),
+            DrawEvent::Image { image, matrix } => backend.draw_image(gs, image, matrix),
+            DrawEvent::Gradient {
+                gradient,
+                clip,
+                matrix,
+            } => backend.draw_gradient(gs, gradient, clip, matrix),
+            DrawEvent::BeginStructureElement { id } => backend.begin_structure_element(gs, id),
+            DrawEvent::EndStructureElement => backend.end_structure_element(),
+            DrawEvent::Unsupported { feature } => backend.unsupported(gs, &feature),
+        });
+    }
+
+    fn run_op<F>(&mut self, op: &Op, f: &mut F)
+    where
+        F: FnMut(&GraphicsState, DrawEvent<'_>),
+    {
+        let gs = &mut self.state;
+        match op {
+            Op::SaveGraphicsState => {
+                self.stack.push(gs.clone());
+                f(gs, DrawEvent::SaveState);
+            }
+            Op::RestoreGraphicsState => {
+                if let Some(prev) = self.stack.pop() {
+                    *gs = prev;
+                    f(gs, DrawEvent::RestoreState);
+                }
+            }
+            Op::SetTransformationMatrix { matrix } => {
+                gs.ctm = CurTransMat::combine_matrix(matrix.as_array(), gs.ctm);
+            }
+            Op::SetFillColor { col } => gs.fill_color = col.clone(),
+            Op::SetOutlineColor { col } => gs.stroke_color = col.clone(),
+            Op::SetOutlineThickness { pt } => gs.line_width = pt.0,
+            Op::SetLineDashPattern { dash } => {
+                gs.dash = dash.as_array().into_iter().map(|d| d as f32).collect();
+                gs.dash_offset = dash.offset as f32;
+            }
+            Op::SetLineCapStyle { cap } => gs.line_cap = *cap,
+            Op::SetLineJoinStyle { join } => gs.line_join = *join,
+            Op::LoadGraphicsState { gs: id } => {
+                if let Some(state) = self.doc.resources.extgstates.map.get(id) {
+                    gs.fill_alpha = state.current_fill_alpha;
+                    gs.stroke_alpha = state.current_stroke_alpha;
+                    gs.blend_mode = state.blend_mode;
+                    gs.soft_mask = state.soft_mask.clone();
+                    let changed = &state.changed_fields;
+                    if changed.contains(LINE_WIDTH) {
+                        gs.line_width = state.line_width;
+                    }
+                    if changed.contains(LINE_CAP) {
+                        gs.line_cap = state.line_cap;
+                    }
+                    if changed.contains(LINE_JOIN) {
+                        gs.line_join = state.line_join;
+                    }
+                    if changed.contains(MITER_LIMIT) {
+                        gs.miter_limit = state.miter_limit;
+                    }
+                    if let Some(dash) = state
+                        .line_dash_pattern
+                        .filter(|_| changed.contains(LINE_DASH_PATTERN))
+                    {
+                        gs.dash = dash.as_array().into_iter().map(|d| d as f32).collect();
+                        gs.dash_offset = dash.offset as f32;
+                    }
+                }
+            }
+            Op::SetTextRenderingMode { mode } => gs.text_mode = *mode,
+            Op::SetLineHeight { .. }
+            | Op::SetCharacterSpacing { .. }
+            | Op::SetWordSpacing { .. }
+            | Op::SetHorizontalScaling { .. }
+            | Op::SetLineOffset { .. } => gs.text.apply(op),
+            Op::StartTextSection
+            | Op::SetTextMatrix { .. }
+            | Op::SetTextCursor { .. }
+            | Op::AddLineBreak => {
+                self.cursor.apply(op, &gs.text);
+            }
+            Op::WriteText { text, size, font } => {
+                if let Some(font) = self.doc.resources.fonts.map.get(font) {
+                    let glyphs = text
+                        .chars()
+                        .filter_map(|c| font.lookup_glyph_index(c as u32))
+                        .map(|g| (g, 0.0, false))
+                        .collect::<Vec<_>>();
+                    let run = layout_glyphs(font, &glyphs, size.0, gs, &mut self.cursor);
+                    f(gs, DrawEvent::GlyphRun { font, glyphs: &run });
+                }
+            }
+            Op::WriteTextBuiltinFont { text, size, font } => {
+                let parsed = self
+                    .builtin_fonts
+                    .entry(*font)
+                    .or_insert_with(|| ParsedFont::from_bytes(&font.get_subset_font().bytes, 0));
+                if let Some(parsed) = parsed.as_ref() {
+                    // builtin fonts are single-byte encoded, the word spacing applies to spaces
+                    let glyphs = text
+                        .chars()
+                        .filter_map(|c| Some((parsed.lookup_glyph_index(c as u32)?, 0.0, c == ' ')))
+                        .collect::<Vec<_>>();
+                    let run = layout_glyphs(parsed, &glyphs, size.0, gs, &mut self.cursor);
+                    f(
+                        gs,
+                        DrawEvent::GlyphRun {
+                            font: parsed,
+                            glyphs: &run,
+                        },
+                    );
+                }
+            }
+            Op::WriteCodepoints { font, size, cp } => {
+                if let Some(font) = self.doc.resources.fonts.map.get(font) {
+                    let glyphs = cp.iter().map(|(g, _)| (*g, 0.0, false)).collect::<Vec<_>>();
+                    let run = layout_glyphs(font, &glyphs, size.0, gs, &mut self.cursor);
+                    f(gs, DrawEvent::GlyphRun { font, glyphs: &run });
+                }
+            }
+            Op::WriteCodepointsWithKerning { font, size, cpk } => {
+                if let Some(font) = self.doc.resources.fonts.map.get(font) {
+                    // kerning is in thousandths of an em, positive values move the glyph left
+                    let glyphs = cpk
+                        .iter()
+                        .map(|(k, g, _)| (*g, -(*k as f32) / 1000.0 * size.0, false))
+                        .collect::<Vec<_>>();
+                    let run = layout_glyphs(font, &glyphs, size.0, gs, &mut self.cursor);
+                    f(gs, DrawEvent::GlyphRun { font, glyphs: &run });
+                }
+            }
+            Op::DrawLine { line } => {
+                let event = DrawEvent::StrokePath {
+                    rings: std::slice::from_ref(&line.points),
+                    closed: line.is_closed,
+                };
+                f(gs, event);
+            }
+            Op::DrawPolygon { polygon } => {
+                let (rings, winding) = (polygon.rings.as_slice(), polygon.winding_order);
+                if matches!(polygon.mode, PaintMode::Fill | PaintMode::FillStroke) {
+                    f(gs, DrawEvent::FillPath { rings, winding });
+                }
+                if matches!(polygon.mode, PaintMode::Stroke | PaintMode::FillStroke) {
+                    f(
+                        gs,
+                        DrawEvent::StrokePath {
+                            rings,
+                            closed: true,
+                        },
+                    );
+                }
+                if polygon.mode == PaintMode::Clip {
+                    f(gs, DrawEvent::ClipPath { rings, winding });
+                }
+            }
+            Op::DrawGradient {
+                clip_polygon,
+                gradient,
+            } => {
+                let matrix = match gradient.get_bbox_matrix(clip_polygon) {
+                    Some(bbox) => CurTransMat::combine_matrix(bbox, gs.ctm),
+                    None => gs.ctm,
+                };
+                let event = DrawEvent::Gradient {
+                    gradient,
+                    clip: clip_polygon,
+                    matrix,
+                };
+                f(gs, event);
+            }
+            Op::UseXObject { id, transform } => match self.doc.resources.xobjects.map.get(id) {
+                Some(XObject::Group(g)) => self.run_form(&g.ops, &g.bbox, g.matrix, transform, f),
+                Some(XObject::Ops(o)) => self.run_form(&o.ops, &o.bbox, o.matrix, transform, f),
+                Some(XObject::Image(image)) => {
+                    let mut m = CurTransMat::Identity.as_array();
+                    for q in transform.get_ctms(Some((Px(image.width), Px(image.height)))) {
+                        m = CurTransMat::combine_matrix(m, q.as_array());
+                    }
+                    let matrix = CurTransMat::combine_matrix(m, gs.ctm);
+                    f(gs, DrawEvent::Image { image, matrix });
+                }
+                Some(XObject::Form(_)) => {
+                    let feature = "form XObject".to_string();
+                    f(gs, DrawEvent::Unsupported { feature });
+                }
+                Some(XObject::External(_)) => {
+                    let feature = "external XObject".to_string();
+                    f(gs, DrawEvent::Unsupported { feature });
+                }
+                None => {}
+            },
+            Op::BeginStructureElement { id } => f(gs, DrawEvent::BeginStructureElement { id }),
+            Op::EndStructureElement => f(gs, DrawEvent::EndStructureElement),
+            Op::Unknown { key, .. } => {
+                let feature = format!("operator {key:?}");
+                f(gs, DrawEvent::Unsupported { feature });
+            }
+            _ => {}
+        }
+    }
+
+    /// Draws the operations of a group or ops XObject, clipped to its bounding box.
+    /// Groups are drawn like their contents, isolation and knockout are ignored.
+    fn run_form<F>(
+        &mut self,
+        ops: &'a [Op],
+        bbox: &Rect,
+        matrix: Option<CurTransMat>,
+        transform: &XObjectTransform,
+        f: &mut F,
+    ) where
+        F: FnMut(&GraphicsState, DrawEvent<'_>),
+    {
+        if self.stack.len() >= MAX_FORM_NESTING {
+            let feature = "nested XObject".to_string();
+            f(&self.state, DrawEvent::Unsupported { feature });
+            return;
+        }
+        let depth = self.stack.len();
+        self.stack.push(self.state.clone());
+        f(&self.state, DrawEvent::SaveState);
+
+        let mut m = matrix.unwrap_or(CurTransMat::Identity).as_array();
+        for q in transform.get_ctms(None) {
+            m = CurTransMat::combine_matrix(m, q.as_array());
+        }
+        self.state.ctm = CurTransMat::combine_matrix(m, self.state.ctm);
+        let bbox = bbox.to_polygon();
+        let event = DrawEvent::ClipPath {
+            rings: &bbox.rings,
+            winding: WindingOrder::NonZero,
+        };
+        f(&self.state, event);
+
+        for op in ops.iter() {
+            self.run_op(op, f);
+        }
+
+        // unbalanced saves inside the XObject are restored, too
+        while self.stack.len() > depth {
+            if let Some(prev) = self.stack.pop() {
+                self.state = prev;
+                f(&self.state, DrawEvent::RestoreState);
+            }
+        }
+    }
+}
+
+/// Drawing backend for `OpInterpreter::render`, implement this to draw pages with other
+/// graphics libraries (i.e. Cairo or skia). Paths are in user space, `GraphicsState::ctm`
+/// maps them to device space, see `DrawEvent` for the other coordinate spaces.
+pub trait RenderBackend {
+    /// Saves the state of the backend (i.e. the clipping path), called for `q`
+    fn save_state(&mut self) {}
+    /// Restores the state of the matching `save_state`, called for `Q`
+    fn restore_state(&mut self) {}
+    /// Fills the rings with `gs.fill_color`
+    fn fill_path(
+        &mut self,
+        gs: &GraphicsState,
+        rings: &[Vec<(Point, bool)>],
+        winding: WindingOrder,
+    );
+    /// Strokes the rings with `gs.stroke_color` and the line style of the state
+    fn stroke_path(&mut self, gs: &GraphicsState, rings: &[Vec<(Point, bool)>], closed: bool);
+    /// Intersects the clipping path with the rings, until the next `restore_state`
+    fn push_clip(
+        &mut self,
+        gs: &GraphicsState,
+        rings: &[Vec<(Point, bool)>],
+        winding: WindingOrder,
+    );
+    /// Draws the glyphs according to `gs.text_mode`
+    fn draw_glyph_run(&mut self, gs: &GraphicsState, font: &ParsedFont, glyphs: &[PositionedGlyph]);
+    /// Draws the image, `matrix` maps the unit square to device space
+    fn draw_image(&mut self, gs: &GraphicsState, image: &RawImage, matrix: [f32; 6]);
+    /// Draws the gradient inside the clip polygon, `matrix` maps the gradient space to
+    /// device space
+    fn draw_gradient(
+        &mut self,
+        gs: &GraphicsState,
+        gradient: &Gradient,
+        clip: &Polygon,
+        matrix: [f32; 6],
+    );
+    /// Called at the start of the content of a structure element (default: ignored)
+    fn begin_structure_element(&mut self, _gs: &GraphicsState, _id: &StructureElementId) {}
+    /// Called at the end of the content of the innermost structure element
+    fn end_structure_element(&mut self) {}
+    /// Called for operations that the interpreter can't draw (default: ignored)
+    fn unsupported(&mut self, _gs: &GraphicsState, _feature: &str) {}
+}
+
+/// Positions glyphs (glyph ID, offset before the glyph, whether the word spacing applies)
+/// and advances the text matrix
+fn layout_glyphs(
+    font: &ParsedFont,
+    glyphs: &[(u16, f32, bool)],
+    size: f32,
+    gs: &GraphicsState,
+    cursor: &mut TextCursor,
+) -> Vec<PositionedGlyph> {
+    let upem = font.font_metrics.units_per_em as f32;
+    if upem <= 0.0 {
+        return Vec::new();
+    }
+    let th = gs.text.horizontal_scaling;
+    // glyph space -> text space: font size, horizontal scaling and rise
+    let glyph_scale = [size * th / upem, 0.0, 0.0, size / upem, 0.0, gs.text.rise];
+
+    let mut positioned = Vec::new();
+    for (glyph, offset, is_word_space) in glyphs.iter() {
+        cursor.advance(*offset * th);
+        positioned.push(PositionedGlyph {
+            glyph_id: *glyph,
+            matrix: CurTransMat::combine_matrix(glyph_scale, cursor.get_text_to_user(gs.ctm)),
+        });
+        let width = font.get_horizontal_advance(*glyph) as f32 / upem * size;
+        cursor.advance(gs.text.get_advance(width, *is_word_space));
+    }
+    positioned
+}
+
+#[test]
+fn test_op_interpreter() {
+    use crate::{Line, Mm, Pt, Rgb};
+
+    let line = Line {
+        points: vec![
+            (Point::new(Mm(0.0), Mm(0.0)), false),
+            (Point::new(Mm(10.0), Mm(0.0)), false),
+        ],
+        is_closed: false,
+    };
+    let red = Color::Rgb(Rgb::new(1.0, 0.0, 0.0, None));
+    let ops = vec![
+        Op::SaveGraphicsState,
+        Op::SetTransformationMatrix {
+            matrix: CurTransMat::Translate(Pt(10.0), Pt(20.0)),
+        },
+        Op::SetOutlineColor { col: red.clone() },
+        Op::DrawLine { line: line.clone() },
+        Op::RestoreGraphicsState,
+        // unbalanced restore, ignored
+        Op::RestoreGraphicsState,
+        Op::DrawLine { line },
+        Op::Unknown {
+            key: "sh".to_string(),
+            value: Vec::new(),
+        },
+    ];
+
+    let doc = PdfDocument::new("interpret");
+    let device = CurTransMat::Scale(2.0, 2.0).as_array();
+    let mut events = Vec::new();
+    OpInterpreter::new(&doc, device).run(&ops, |gs, event| {
+        let kind = match event {
+            DrawEvent::SaveState => "q",
+            DrawEvent::RestoreState => "Q",
+            DrawEvent::StrokePath { .. } => "S",
+            DrawEvent::Unsupported { .. } => "?",
+            _ => "other",
+        };
+        events.push((kind, gs.to_device((1.0, 1.0)), gs.stroke_color.clone()));
+    });
+
+    let black = Color::Greyscale(Greyscale::new(0.0, None));
+    assert_eq!(
+        events,
+        vec![
+            ("q", (2.0, 2.0), black.clone()),
+            ("S", (22.0, 42.0), red),
+            ("Q", (2.0, 2.0), black.clone()),
+            ("S", (2.0, 2.0), black.clone()),
+            ("?", (2.0, 2.0), black),
+        ]
+    );
+}
+
+#[test]
+fn test_render_backend() {
+    use crate::{Pt, Rect};
+
+    #[derive(Default)]
+    struct Recorder {
+        calls: Vec<String>,
+    }
+
+    impl RenderBackend for Recorder {
+        fn save_state(&mut self) {
+            self.calls.push("save".to_string());
+        }
+        fn restore_state(&mut self) {
+            self.calls.push("restore".to_string());
+        }
+        fn fill_path(&mut self, gs: &GraphicsState, rings: &[Vec<(Point, bool)>], _: WindingOrder) {
+            let (x, y) = gs.to_device((rings[0][0].0.x.0, rings[0][0].0.y.0));
+            self.calls.push(format!("fill {x} {y}"));
+        }
+        fn stroke_path(&mut self, _: &GraphicsState, _: &[Vec<(Point, bool)>], _: bool) {
+            self.calls.push("stroke".to_string());
+        }
+        fn push_clip(&mut self, _: &GraphicsState, _: &[Vec<(Point, bool)>], _: WindingOrder) {
+            self.calls.push("clip".to_string());
+        }
+        fn draw_glyph_run(&mut self, _: &GraphicsState, _: &ParsedFont, _: &[PositionedGlyph]) {}
+        fn draw_image(&mut self, _: &GraphicsState, _: &RawImage, _: [f32; 6]) {}
+        fn draw_gradient(&mut self, _: &GraphicsState, _: &Gradient, _: &Polygon, _: [f32; 6]) {}
+    }
+
+    let polygon = |mode| Polygon {
+        mode,
+        ..Rect::from_wh(Pt(10.0), Pt(10.0)).to_polygon()
+    };
+    let ops = vec![
+        Op::SaveGraphicsState,
+        Op::DrawPolygon {
+            polygon: polygon(PaintMode::Clip),
+        },
+        Op::DrawPolygon {
+            polygon: polygon(PaintMode::FillStroke),
+        },
+        Op::RestoreGraphicsState,
+    ];
+
+    let doc = PdfDocument::new("backend");
+    let mut recorder = Recorder::default();
+    let device = CurTransMat::Translate(Pt(5.0), Pt(5.0)).as_array();
+    OpInterpreter::new(&doc, device).render(&ops, &mut recorder);
+    assert_eq!(
+        recorder.calls,
+        vec!["save", "clip", "fill 5 5", "stroke", "restore"]
+    );
+}
@@ -0,0 +1,180 @@
+//! Label sheets: tiling repeated content across standard label sheet layouts
+
+use crate::{graphics::Rect, ops::Op, units::Mm, PdfPage};
+
+/// Geometry of a sheet of labels (i.e. Avery L7160)
+///
+/// All measurements are taken from the top left corner of the sheet,
+/// the same way label manufacturers specify them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelSheetLayout {
+    /// Width of the sheet
+    pub page_width: Mm,
+    /// Height of the sheet
+    pub page_height: Mm,
+    /// Number of labels per row
+    pub columns: usize,
+    /// Number of labels per column
+    pub rows: usize,
+    /// Width of a single label
+    pub label_width: Mm,
+    /// Height of a single label
+    pub label_height: Mm,
+    /// Distance between the left edges of two adjacent labels
+    pub horizontal_pitch: Mm,
+    /// Distance between the top edges of two adjacent labels
+    pub vertical_pitch: Mm,
+    /// Distance from the left edge of the sheet to the first label
+    pub margin_left: Mm,
+    /// Distance from the top edge of the sheet to the first label
+    pub margin_top: Mm,
+}
+
+impl LabelSheetLayout {
+    /// Avery L7160 (A4, 3 x 7 labels, 63.5 x 38.1 mm)
+    pub fn avery_l7160() -> Self {
+        Self {
+            page_width: Mm(210.0),
+            page_height: Mm(297.0),
+            columns: 3,
+            rows: 7,
+            label_width: Mm(63.5),
+            label_height: Mm(38.1),
+            horizontal_pitch: Mm(66.04),
+            vertical_pitch: Mm(38.1),
+            margin_left: Mm(7.25),
+            margin_top: Mm(15.15),
+        }
+    }
+
+    /// Avery L7163 (A4, 2 x 7 labels, 99.1 x 38.1 mm)
+    pub fn avery_l7163() -> Self {
+        Self {
+            page_width: Mm(210.0),
+            page_height: Mm(297.0),
+            columns: 2,
+            rows: 7,
+            label_width: Mm(99.1),
+            label_height: Mm(38.1),
+            horizontal_pitch: Mm(101.6),
+            vertical_pitch: Mm(38.1),
+            margin_left: Mm(4.65),
+            margin_top: Mm(15.15),
+        }
+    }
+
+    /// Avery L7651 (A4, 5 x 13 labels, 38.1 x 21.2 mm)
+    pub fn avery_l7651() -> Self {
+        Self {
+            page_width: Mm(210.0),
+            page_height: Mm(297.0),
+            columns: 5,
+            rows: 13,
+            label_width: Mm(38.1),
+            label_height: Mm(21.2),
+            horizontal_pitch: Mm(40.64),
+            vertical_pitch: Mm(21.2),
+            margin_left: Mm(4.75),
+            margin_top: Mm(10.7),
+        }
+    }
+
+    /// Avery 5160 (US Letter, 3 x 10 labels, 2.625 x 1 inch)
+    pub fn avery_5160() -> Self {
+        Self {
+            page_width: Mm(215.9),
+            page_height: Mm(279.4),
+            columns: 3,
+            rows: 10,
+            label_width: Mm(66.675),
+            label_height: Mm(25.4),
+            horizontal_pitch: Mm(69.85),
+            vertical_pitch: Mm(25.4),
+            margin_left: Mm(4.7625),
+            margin_top: Mm(12.7),
+        }
+    }
+
+    /// Number of labels on a single sheet
+    pub fn labels_per_sheet(&self) -> usize {
+        self.columns * self.rows
+    }
+
+    /// Returns the bounds of the label at `index` (counted left-to-right, top-to-bottom)
+    /// on a single sheet, from the lower left corner of the page.
+    pub fn get_label_rect(&self, index: usize) -> Rect {
+        let columns = self.columns.max(1);
+        let col = (index % columns) as f32;
+        let row = (index / columns) as f32;
+        let left = self.margin_left + self.horizontal_pitch * col;
+        let top = self.page_height - self.margin_top - self.vertical_pitch * row;
+        let bottom = top - self.label_height;
+        Rect {
+            x: left.into(),
+            y: bottom.into(),
+            width: self.label_width.into(),
+            height: self.label_height.into(),
+        }
+    }
+
+    /// Generates the pages for `num_labels` labels, calling `content` once per label with
+    /// the label number and the label bounds.
+    ///
+    /// `skip` leaves the first n labels of the first sheet empty, so that partially
+    /// used sheets can be printed on again. The last sheet is only filled up to
+    /// `num_labels`, the remaining labels are left empty.
+    pub fn generate_pages<F>(&self, num_labels: usize, skip: usize, mut content: F) -> Vec<PdfPage>
+    where
+        F: FnMut(usize, &Rect) -> Vec<Op>,
+    {
+        let per_sheet = self.labels_per_sheet();
+        if per_sheet == 0 || num_labels == 0 {
+            return Vec::new();
+        }
+
+        let skip = skip % per_sheet;
+        let total_slots = skip + num_labels;
+        let num_sheets = total_slots.div_ceil(per_sheet);
+
+        (0..num_sheets)
+            .map(|sheet| {
+                let mut ops = Vec::new();
+                for slot in 0..per_sheet {
+                    let global_slot = sheet * per_sheet + slot;
+                    if global_slot < skip || global_slot >= total_slots {
+                        continue;
+                    }
+                    let label_rect = self.get_label_rect(slot);
+                    ops.push(Op::SaveGraphicsState);
+                    ops.extend(content(global_slot - skip, &label_rect));
+                    ops.push(Op::RestoreGraphicsState);
+                }
+                PdfPage::new(self.page_width, self.page_height, ops)
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_label_sheet_tiling() {
+    use crate::units::Pt;
+
+    let layout = LabelSheetLayout::avery_l7160();
+    assert_eq!(layout.labels_per_sheet(), 21);
+
+    let first = layout.get_label_rect(0);
+    assert_eq!(first.x, Pt::from(Mm(7.25)));
+    assert_eq!(first.y, Pt::from(Mm(297.0 - 15.15 - 38.1)));
+
+    let mut seen = Vec::new();
+    let pages = layout.generate_pages(20, 5, |i, _| {
+        seen.push(i);
+        vec![Op::Marker { id: i.to_string() }]
+    });
+    assert_eq!(pages.len(), 2);
+    assert_eq!(seen, (0..20).collect::<Vec<_>>());
+    // 16 labels on the first sheet, 4 on the second one
+    assert_eq!(pages[0].ops.len(), 16 * 3);
+    assert_eq!(pages[1].ops.len(), 4 * 3);
+    assert_eq!(pages[0].media_box.width, Pt::from(Mm(210.0)));
+}
@@ -2,6 +2,9 @@
 
 use std::collections::BTreeMap;
 
+/// Optional `tracing` instrumentation
+#[macro_use]
+mod trace;
 // #[cfg(target_family = "wasm")]
 /// Link / bookmark annotation handling
 pub mod annotation;
@@ -19,18 +22,96 @@ pub use units::*;
 /// Date handling (stubs for platforms that don't support access to time clocks, such as wasm32-unknown)
 pub mod date;
 pub use date::*;
+/// XMP metadata (Dublin Core, PDF, media management and custom schemas)
+pub mod xmp;
+pub use xmp::*;
 /// Font and codepoint handling
 pub mod font;
 pub use font::*;
+/// Repair of malformed TrueType / OpenType fonts
+pub mod font_sanitize;
+pub use font_sanitize::*;
 /// Point / line / polygon handling
 pub mod graphics;
 pub use graphics::*;
+/// Axial and radial gradients (shadings)
+pub mod gradient;
+pub use gradient::*;
+/// Top-left origin coordinate conversion
+pub mod coords;
+pub use coords::*;
 /// Page operations
 pub mod ops;
 pub use ops::*;
+/// Text frames (vertical alignment of text lines inside a rectangle)
+pub mod text;
+pub use text::*;
+/// Label sheet layouts (Avery, etc.)
+pub mod label;
+pub use label::*;
+/// Page labels (roman numerals, prefixes) shown by the viewer
+pub mod page_labels;
+pub use page_labels::*;
+/// Interactive forms (AcroForm text fields)
+pub mod forms;
+pub use forms::*;
+/// Viewer preferences (print dialog presets)
+pub mod viewer;
+pub use viewer::*;
+/// Logical structure (tagged PDF)
+pub mod structure;
+pub use structure::*;
+/// Text extraction from the page contents
+pub mod extract;
+pub use extract::*;
+/// Embedded files / attachments (PDF/A-3, ZUGFeRD)
+pub mod attachments;
+pub use attachments::*;
+/// Document part hierarchy for batch printing (PDF/VT)
+pub mod dpart;
+pub use dpart::*;
+/// Nested document outline (bookmarks) and named destinations
+pub mod outline;
+pub use outline::*;
+/// Password protection (RC4 / AES-256 encryption)
+pub mod encryption;
+pub use encryption::*;
+/// Digital signatures
+pub mod signature;
+pub use signature::*;
+/// Document analysis (ink coverage)
+pub mod analysis;
+pub use analysis::*;
+/// Interpreter of the page operations (graphics state, drawing events in device space)
+pub mod interpret;
+pub use interpret::*;
+/// Page rasterization ("flatten to image")
+pub mod rasterize;
+pub use rasterize::*;
+/// Export of pages to Encapsulated PostScript (EPS)
+pub mod postscript;
+pub use postscript::*;
+/// Export of pages to SVG (self-contained or with external asset files)
+pub mod svg_export;
+pub use svg_export::*;
+/// Splitting and rearranging documents (page extraction, reordering, rotation)
+pub mod split;
+pub use split::*;
+/// Writing documents page by page (incremental updates for logs, low-memory streaming)
+pub mod session;
+pub use session::*;
+/// Stamps and watermarks on all pages
+pub mod stamp;
+pub use stamp::*;
+/// Printer marks (bleed, crop marks, registration marks, color bars)
+pub mod marks;
+pub use marks::*;
 /// Color handling
 pub mod color;
 pub use color::*;
+/// Document-wide conversion of RGB colors and images to CMYK or greyscale
+pub mod color_transform;
+pub use color_transform::*;
 /// XObject handling
 pub mod xobject;
 pub use xobject::*;
@@ -43,15 +124,30 @@ pub use image::*;
 /// HTML handling
 pub mod html;
 pub use html::*;
+/// Reusable HTML components (calendars, schedule grids, etc.)
+pub mod components;
+pub use components::*;
+/// Mail merge of HTML templates with JSON records
+pub mod template;
+pub use template::*;
+/// TIFF decoding (multi-strip, tiled, planar and CMYK TIFFs)
+#[cfg(feature = "tiff")]
+pub mod tiff;
+/// Warnings collected while saving
+pub mod warn;
+pub use warn::*;
 /// Utility functions (random strings, numbers, timestamp formatting)
 pub(crate) mod utils;
 use utils::*;
 pub use utils::{compress, uncompress};
+/// Decoding of stream filters (Flate, LZW, ASCIIHex, ASCII85, RunLength)
+pub(crate) mod filters;
 /// Writing PDF
 pub(crate) mod serialize;
-pub use serialize::PdfSaveOptions;
+pub use serialize::{PdfSaveOptions, StreamCompression};
 /// Parsing PDF
 pub(crate) mod deserialize;
+pub use deserialize::PdfParseOptions;
 
 /// Internal ID for page annotations
 #[derive(Debug, PartialEq, Clone, Eq, PartialOrd, Ord)]
@@ -113,6 +209,16 @@ impl IccProfileId {
     }
 }
 
+/// Internal ID for structure elements (tagged PDF)
+#[derive(Debug, PartialEq, Clone, Eq, PartialOrd, Ord)]
+pub struct StructureElementId(pub String);
+
+impl StructureElementId {
+    pub fn new() -> Self {
+        Self(crate::utils::random_character_string_32())
+    }
+}
+
 /// Parsed PDF document
 #[derive(Debug, PartialEq, Clone)]
 pub struct PdfDocument {
@@ -122,11 +228,33 @@ pub struct PdfDocument {
     pub resources: PdfResources,
     /// Document-level bookmarks (used for the outline)
     pub bookmarks: PageAnnotMap,
+    /// Nested outline entries, written before the flat `bookmarks`
+    pub outline: Vec<OutlineNode>,
+    /// Named destinations (`/Dests` name tree), see `add_named_destination`
+    pub named_destinations: BTreeMap<String, Destination>,
     /// Page contents
     pub pages: Vec<PdfPage>,
+    /// Presets for the viewer and its print dialog (duplex, copies, ...)
+    pub viewer_preferences: ViewerPreferences,
+    /// Page numbers shown by the viewer (i.e. "i, ii, iii" for the front matter)
+    pub page_labels: PageLabels,
+    /// Action run when the document is opened (i.e. go to a page, or print)
+    pub open_action: Option<Actions>,
+    /// Document part hierarchy, groups pages by record for transactional printing
+    pub document_parts: Option<DocumentPartRoot>,
+    /// Logical structure of the document (tagged PDF)
+    pub structure: StructureTree,
+    /// Files embedded in the document, see `attach_file`
+    pub attachments: Vec<EmbeddedFile>,
 }
 
 impl PdfDocument {
+    /// Creates a builder for documents with chained metadata and pages, i.e.
+    /// `PdfDocument::builder().title("..").author("..").page(w, h, |p| ..).build()`
+    pub fn builder() -> PdfDocumentBuilder {
+        PdfDocumentBuilder::new()
+    }
+
     pub fn new(name: &str) -> Self {
         Self {
             metadata: PdfMetadata {
@@ -135,14 +263,34 @@ impl PdfDocument {
                     ..Default::default()
                 },
                 xmp: None,
+                language: None,
             },
             resources: PdfResources::default(),
             bookmarks: PageAnnotMap::default(),
+            outline: Vec::new(),
+            named_destinations: BTreeMap::new(),
             pages: Vec::new(),
+            viewer_preferences: ViewerPreferences::default(),
+            page_labels: PageLabels::default(),
+            open_action: None,
+            document_parts: None,
+            structure: StructureTree::default(),
+            attachments: Vec::new(),
         }
     }
 
+    /// Adds a graphics state, returns the ID of an existing identical state instead
+    /// of adding a duplicate
     pub fn add_graphics_state(&mut self, gs: ExtendedGraphicsState) -> ExtendedGraphicsStateId {
+        let existing = self
+            .resources
+            .extgstates
+            .map
+            .iter()
+            .find(|(_, s)| **s == gs);
+        if let Some((id, _)) = existing {
+            return id.clone();
+        }
         let id = ExtendedGraphicsStateId::new();
         self.resources.extgstates.map.insert(id.clone(), gs);
         id
@@ -154,6 +302,17 @@ impl PdfDocument {
         id
     }
 
+    /// Adds an ICC profile, colors that reference the returned ID are written
+    /// in an `/ICCBased` color space
+    pub fn add_icc_profile(&mut self, icc: &IccProfile) -> IccProfileId {
+        let id = IccProfileId::new();
+        self.resources
+            .icc_profiles
+            .map
+            .insert(id.clone(), icc.clone());
+        id
+    }
+
     pub fn add_font(&mut self, font: &ParsedFont) -> FontId {
         let id = FontId::new();
         self.resources.fonts.map.insert(id.clone(), font.clone());
@@ -181,6 +340,29 @@ impl PdfDocument {
         id
     }
 
+    /// Adds a transparency group, draw it with `Op::UseXObject` (the blend mode, alpha
+    /// and soft mask of the current graphics state apply to the group as a whole)
+    pub fn add_transparency_group(&mut self, group: &TransparencyGroup) -> XObjectId {
+        let id = XObjectId::new();
+        self.resources
+            .xobjects
+            .map
+            .insert(id.clone(), XObject::Group(Box::new(group.clone())));
+        id
+    }
+
+    /// Adds a form XObject drawn from the ops (clipped to the bounding box), for content
+    /// that is repeated on many pages (letterheads, stamps): the ops are written once
+    /// and placed with `Op::UseXObject`
+    pub fn add_form_xobject(&mut self, ops: Vec<Op>, bbox: Rect) -> XObjectId {
+        let id = XObjectId::new();
+        self.resources.xobjects.map.insert(
+            id.clone(),
+            XObject::Ops(Box::new(OpsXObject::new(ops, bbox))),
+        );
+        id
+    }
+
     /// Adds a new page-level bookmark on page `$page`, returning the bookmarks internal ID
     pub fn add_bookmark(&mut self, name: &str, page: usize) -> PageAnnotId {
         let id = PageAnnotId::new();
@@ -194,6 +376,60 @@ impl PdfDocument {
         id
     }
 
+    /// Parses a PDF document: the form fields, outline, named destinations, open action,
+    /// XMP metadata, the page boxes, actions, links and markup annotations, the image and
+    /// form XObjects, the graphics states and the page contents. Fonts, color spaces,
+    /// patterns, shadings and marked content properties are kept unparsed (see
+    /// `RawResourceMap`), so that saving the document doesn't lose the text of the pages.
+    /// Encrypted documents are decrypted with `PdfParseOptions::password`.
+    pub fn parse(bytes: &[u8], opts: &PdfParseOptions) -> Result<Self, String> {
+        self::deserialize::parse_pdf_from_bytes(bytes, opts, &mut Vec::new())
+    }
+
+    /// Parses a PDF document and collects the warnings (i.e. skipped images), filtered by
+    /// `PdfParseOptions::min_severity` and `PdfParseOptions::warn_categories`
+    pub fn parse_with_warnings(
+        bytes: &[u8],
+        opts: &PdfParseOptions,
+        warnings: &mut Vec<PdfWarnMsg>,
+    ) -> Result<Self, String> {
+        self::deserialize::parse_pdf_from_bytes(bytes, opts, warnings)
+    }
+
+    /// Parses a PDF document, `decoder` is called for every image XObject and can replace
+    /// the image (i.e. with pixels from a custom decoder) or skip it
+    pub fn parse_with_image_decoder(
+        bytes: &[u8],
+        opts: &PdfParseOptions,
+        decoder: &mut dyn ImageDecoder,
+    ) -> Result<Self, String> {
+        self::deserialize::parse_pdf_with_image_decoder(bytes, opts, Some(decoder), &mut Vec::new())
+    }
+
+    /// Sets the value of the text field `name`, both for fields parsed from an existing
+    /// document and for fields added with `Op::AddFormField`.
+    /// The appearance stream of the field is regenerated on save.
+    pub fn fill_field(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let mut found = false;
+        if let Some(f) = self.resources.forms.map.get_mut(name) {
+            f.field.set_value(value)?;
+            found = true;
+        }
+        for op in self.pages.iter_mut().flat_map(|p| p.ops.iter_mut()) {
+            if let Op::AddFormField { field } = op {
+                if field.get_name() == name {
+                    field.set_value(value)?;
+                    found = true;
+                }
+            }
+        }
+        if found {
+            Ok(())
+        } else {
+            Err(format!("form field {name:?} not found"))
+        }
+    }
+
     /// Renders HTML to pages
     pub fn html2pages(
         &mut self,
@@ -203,6 +439,68 @@ impl PdfDocument {
         crate::html::xml_to_pages(html, config, self)
     }
 
+    /// Renders HTML to pages, after fetching the images and fonts that are referenced by
+    /// the HTML but missing in the `config` with the async `loader`
+    /// (see `XmlRenderOptions::fetch_missing_assets`)
+    pub async fn html2pages_async<F, Fut>(
+        &mut self,
+        html: &str,
+        mut config: XmlRenderOptions,
+        loader: F,
+    ) -> Result<Vec<PdfPage>, String>
+    where
+        F: FnMut(HtmlAsset) -> Fut,
+        Fut: std::future::Future<Output = Result<Option<Vec<u8>>, String>>,
+    {
+        config.fetch_missing_assets(html, loader).await?;
+        crate::html::xml_to_pages(html, config, self)
+    }
+
+    /// Renders HTML to pages, additionally returns the positions of all links (`<a href="...">`)
+    /// and anchors (elements with an `id`), so that the caller can add link annotations
+    /// (see `HtmlLinkInfo::get_link_annotations`)
+    pub fn html2pages_with_links(
+        &mut self,
+        html: &str,
+        config: XmlRenderOptions,
+    ) -> Result<(Vec<PdfPage>, HtmlLinkInfo), String> {
+        crate::html::xml_to_pages_with_links(html, config, self)
+    }
+
+    /// Renders HTML to pages, `on_page` is called for every generated page (with its index,
+    /// the bounds of the content and the anchors on the page) and returns ops that are
+    /// drawn on top of the page, i.e. for chapter tabs or dynamic footers
+    pub fn html2pages_with_callback<F>(
+        &mut self,
+        html: &str,
+        config: XmlRenderOptions,
+        mut on_page: F,
+    ) -> Result<Vec<PdfPage>, String>
+    where
+        F: FnMut(&HtmlPageInfo) -> Vec<Op>,
+    {
+        crate::html::xml_to_pages_with_callback(html, config, self, &mut on_page, &mut Vec::new())
+            .map(|(pages, _)| pages)
+    }
+
+    /// Renders HTML to pages, collecting diagnostics about content that overflows the page
+    /// or is clipped, missing images and missing fonts (with the page and the element)
+    pub fn html2pages_with_diagnostics(
+        &mut self,
+        html: &str,
+        config: XmlRenderOptions,
+        diagnostics: &mut Vec<HtmlDiagnostic>,
+    ) -> Result<Vec<PdfPage>, String> {
+        crate::html::xml_to_pages_with_callback(
+            html,
+            config,
+            self,
+            &mut |_| Vec::new(),
+            diagnostics,
+        )
+        .map(|(pages, _)| pages)
+    }
+
     /// Replaces `document.pages` with the new pages
     pub fn with_pages(&mut self, pages: Vec<PdfPage>) -> &mut Self {
         let mut pages = pages;
@@ -212,7 +510,201 @@ impl PdfDocument {
 
     /// Serializes the PDF document to bytes
     pub fn save(&self, opts: &PdfSaveOptions) -> Vec<u8> {
-        self::serialize::serialize_pdf_into_bytes(self, opts)
+        self.save_with_warnings(opts, &mut Vec::new())
+    }
+
+    /// Serializes the PDF document to bytes, collecting warnings about features that
+    /// violate the documents conformance level (PDF/A, PDF/X, ...)
+    pub fn save_with_warnings(
+        &self,
+        opts: &PdfSaveOptions,
+        warnings: &mut Vec<PdfWarnMsg>,
+    ) -> Vec<u8> {
+        self::serialize::serialize_pdf_into_bytes(self, opts, warnings)
+    }
+
+    /// Serializes the PDF document to bytes, returns the warnings grouped by page,
+    /// resource and category
+    pub fn save_with_grouped_warnings(&self, opts: &PdfSaveOptions) -> (Vec<u8>, PdfWarnGroups) {
+        let mut warnings = Vec::new();
+        let bytes = self.save_with_warnings(opts, &mut warnings);
+        (bytes, PdfWarnGroups::new(&warnings))
+    }
+
+    /// Serializes the PDF document and signs it: the first signature field
+    /// (`Op::AddFormField` with a `FormField::Signature`) is filled with the signature.
+    ///
+    /// `signer` is called with the signed byte ranges of the document and has to return
+    /// a DER-encoded PKCS#7 / CMS detached signature (`adbe.pkcs7.detached`).
+    pub fn save_signed<F>(
+        &self,
+        opts: &PdfSaveOptions,
+        warnings: &mut Vec<PdfWarnMsg>,
+        signer: F,
+    ) -> Result<Vec<u8>, String>
+    where
+        F: FnOnce(&[u8]) -> Result<Vec<u8>, String>,
+    {
+        let is_signature = |f: &FormField| matches!(f, FormField::Signature(_));
+        let has_signature_field = self
+            .pages
+            .iter()
+            .flat_map(|p| p.ops.iter())
+            .any(|op| matches!(op, Op::AddFormField { field } if is_signature(field)))
+            || self
+                .resources
+                .forms
+                .map
+                .values()
+                .any(|f| is_signature(&f.field));
+        if !has_signature_field {
+            return Err("document has no signature field to sign".to_string());
+        }
+        let (bytes, signature_offset) = self::serialize::serialize_pdf(self, opts, warnings, true);
+        let signature_offset =
+            signature_offset.ok_or_else(|| "signature placeholder not found".to_string())?;
+        crate::signature::sign_pdf_bytes(bytes, signature_offset, signer)
+    }
+
+    /// Returns a warning for every feature that violates the documents conformance level
+    pub fn check_conformance(&self) -> Vec<PdfWarnMsg> {
+        crate::conformance::get_conformance_warnings(self)
+    }
+}
+
+/// Builder for `PdfDocument`, see `PdfDocument::builder()`
+#[derive(Debug, PartialEq, Clone)]
+pub struct PdfDocumentBuilder {
+    doc: PdfDocument,
+}
+
+impl Default for PdfDocumentBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PdfDocumentBuilder {
+    /// Creates a new builder for an empty, untitled document
+    pub fn new() -> Self {
+        Self {
+            doc: PdfDocument::new(""),
+        }
+    }
+
+    /// Sets the document title
+    pub fn title(mut self, title: &str) -> Self {
+        self.doc.metadata.info.document_title = title.to_string();
+        self
+    }
+
+    /// Sets the document author
+    pub fn author(mut self, author: &str) -> Self {
+        self.doc.metadata.info.author = author.to_string();
+        self
+    }
+
+    /// Sets the creator of the document (usually the application name)
+    pub fn creator(mut self, creator: &str) -> Self {
+        self.doc.metadata.info.creator = creator.to_string();
+        self
+    }
+
+    /// Sets the producer of the document
+    pub fn producer(mut self, producer: &str) -> Self {
+        self.doc.metadata.info.producer = producer.to_string();
+        self
+    }
+
+    /// Sets the document subject
+    pub fn subject(mut self, subject: &str) -> Self {
+        self.doc.metadata.info.subject = subject.to_string();
+        self
+    }
+
+    /// Sets the natural language of the document (i.e. "en-US")
+    pub fn language(mut self, language: &str) -> Self {
+        self.doc.metadata.language = Some(language.to_string());
+        self
+    }
+
+    /// Sets the document keywords
+    pub fn keywords(mut self, keywords: &[&str]) -> Self {
+        self.doc.metadata.info.keywords = keywords.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    /// Sets the document identifier
+    pub fn identifier(mut self, identifier: &str) -> Self {
+        self.doc.metadata.info.identifier = identifier.to_string();
+        self
+    }
+
+    /// Sets the PDF standard the document should conform to
+    pub fn conformance(mut self, conformance: PdfConformance) -> Self {
+        self.doc.metadata.info.conformance = conformance;
+        self
+    }
+
+    /// Sets the XMP metadata
+    pub fn xmp(mut self, xmp: XmpMetadata) -> Self {
+        self.doc.metadata.xmp = Some(xmp);
+        self
+    }
+
+    /// Adds a font to the document resources under the given ID (i.e. `FontId::new()`),
+    /// so that it can be used in the following pages
+    pub fn font(mut self, id: FontId, font: &ParsedFont) -> Self {
+        self.doc.resources.fonts.map.insert(id, font.clone());
+        self
+    }
+
+    /// Adds an image to the document resources under the given ID (i.e. `XObjectId::new()`),
+    /// so that it can be used in the following pages
+    pub fn image(mut self, id: XObjectId, image: &RawImage) -> Self {
+        self.doc
+            .resources
+            .xobjects
+            .map
+            .insert(id, XObject::Image(image.clone()));
+        self
+    }
+
+    /// Appends a new page with the given size, the page contents are filled in by `f`
+    pub fn page<F: FnOnce(&mut PdfPage)>(mut self, width: Mm, height: Mm, f: F) -> Self {
+        let mut page = PdfPage::new(width, height, Vec::new());
+        f(&mut page);
+        self.doc.pages.push(page);
+        self
+    }
+
+    /// Appends already created pages
+    pub fn pages(mut self, pages: Vec<PdfPage>) -> Self {
+        self.doc.pages.extend(pages);
+        self
+    }
+
+    /// Sets the viewer preferences (duplex, paper tray, copies, ...)
+    pub fn viewer_preferences(mut self, viewer_preferences: ViewerPreferences) -> Self {
+        self.doc.viewer_preferences = viewer_preferences;
+        self
+    }
+
+    /// Sets the document part hierarchy (see `DocumentPartRoot::from_records`)
+    pub fn document_parts(mut self, document_parts: DocumentPartRoot) -> Self {
+        self.doc.document_parts = Some(document_parts);
+        self
+    }
+
+    /// Adds a bookmark pointing to page `page` (0-based)
+    pub fn bookmark(mut self, name: &str, page: usize) -> Self {
+        self.doc.add_bookmark(name, page);
+        self
+    }
+
+    /// Returns the finished document
+    pub fn build(self) -> PdfDocument {
+        self.doc
     }
 }
 
@@ -226,6 +718,12 @@ pub struct PdfResources {
     pub extgstates: ExtendedGraphicsStateMap,
     /// Map of optional content groups
     pub layers: PdfLayerMap,
+    /// Form fields that are not placed with `Op::AddFormField`, i.e. parsed from the `/AcroForm`
+    pub forms: FormFieldMap,
+    /// ICC profiles, referenced by the `icc_profile` of RGB, CMYK and greyscale colors
+    pub icc_profiles: IccProfileMap,
+    /// Resources of parsed pages that are not parsed into printpdf types
+    pub raw: RawResourceMap,
 }
 
 #[derive(Debug, PartialEq, Default, Clone)]
@@ -233,6 +731,12 @@ pub struct PdfLayerMap {
     pub map: BTreeMap<LayerInternalId, Layer>,
 }
 
+#[derive(Debug, PartialEq, Default, Clone)]
+pub struct FormFieldMap {
+    /// Fields indexed by their fully qualified name
+    pub map: BTreeMap<String, PageFormField>,
+}
+
 #[derive(Debug, PartialEq, Default, Clone)]
 pub struct PdfFontMap {
     pub map: BTreeMap<FontId, ParsedFont>,
@@ -241,6 +745,11 @@ pub struct PdfFontMap {
 #[derive(Debug, PartialEq, Default, Clone)]
 pub struct ParsedIccProfile {}
 
+#[derive(Debug, PartialEq, Default, Clone)]
+pub struct IccProfileMap {
+    pub map: BTreeMap<IccProfileId, IccProfile>,
+}
+
 #[derive(Debug, PartialEq, Default, Clone)]
 pub struct XObjectMap {
     pub map: BTreeMap<XObjectId, XObject>,
@@ -256,6 +765,51 @@ pub struct ExtendedGraphicsStateMap {
     pub map: BTreeMap<ExtendedGraphicsStateId, ExtendedGraphicsState>,
 }
 
+/// Fonts, color spaces, patterns, shadings and marked content properties of parsed pages,
+/// which are used by the `Op::Unknown` operators of the page contents (`Tf`, `cs`, `scn`,
+/// `sh`, `BDC`, ...). The objects are inlined like `FormXObject::resources` and written
+/// as-is, a name that is also used by a resource that printpdf writes is left out.
+#[derive(Debug, PartialEq, Default, Clone)]
+pub struct RawResourceMap {
+    /// Resource type (i.e. "Font") and name to the resource
+    pub map: BTreeMap<(String, String), lopdf::Object>,
+}
+
+impl RawResourceMap {
+    /// Resource types that are kept as raw resources
+    pub(crate) const TYPES: [&'static str; 5] =
+        ["Font", "ColorSpace", "Pattern", "Shading", "Properties"];
+
+    /// Returns the resource type and the index of the operand that names a raw resource
+    /// in the operation of a content stream
+    pub(crate) fn get_operand(
+        operator: &str,
+        operands: &[lopdf::Object],
+    ) -> Option<(&'static str, usize)> {
+        let is_name = |i: usize| matches!(operands.get(i), Some(lopdf::Object::Name(_)));
+        match operator {
+            "Tf" => Some(("Font", 0)),
+            "sh" => Some(("Shading", 0)),
+            // the color space families without parameters are not resources
+            "cs" | "CS" => match operands.first() {
+                Some(lopdf::Object::Name(n))
+                    if !matches!(
+                        n.as_slice(),
+                        b"DeviceGray" | b"DeviceRGB" | b"DeviceCMYK" | b"Pattern"
+                    ) =>
+                {
+                    Some(("ColorSpace", 0))
+                }
+                _ => None,
+            },
+            "scn" | "SCN" if !operands.is_empty() => Some(("Pattern", operands.len() - 1)),
+            "BDC" | "DP" => Some(("Properties", 1)),
+            _ => None,
+        }
+        .filter(|(_, i)| is_name(*i))
+    }
+}
+
 /// This is a wrapper in order to keep shared data between the documents XMP metadata and
 /// the "Info" dictionary in sync
 #[derive(Debug, PartialEq, Clone)]
@@ -264,57 +818,65 @@ pub struct PdfMetadata {
     pub info: PdfDocumentInfo,
     /// XMP Metadata. Is ignored on save if the PDF conformance does not allow XMP
     pub xmp: Option<XmpMetadata>,
+    /// Natural language of the document (`/Lang`, i.e. "en-US"), used by screen readers
+    pub language: Option<String>,
 }
 
 impl PdfMetadata {
-    /// Consumes the XmpMetadata and turns it into a PDF Object.
-    /// This is similar to the
+    /// Returns the XMP metadata packet, with the fields that are shared with the
+    /// document info (title, author, dates, etc.) taken from `self.info`
     pub(crate) fn xmp_metadata_string(&self) -> String {
-        // Shared between XmpMetadata and DocumentInfo
-        let trapping = if self.info.trapped { "True" } else { "False" };
-
-        // let xmp_instance_id = "2898d852-f86f-4479-955b-804d81046b19";
-        let instance_id = random_character_string_32();
-        let create_date = to_pdf_xmp_date(&self.info.creation_date);
-        let modification_date = to_pdf_xmp_date(&self.info.modification_date);
-        let metadata_date = to_pdf_xmp_date(&self.info.metadata_date);
-
-        let pdf_x_version = self.info.conformance.get_identifier_string();
-        let document_version = self.info.version.to_string();
-        let document_id = self.info.identifier.to_string();
-
-        let rendition_class = match self.xmp.as_ref().and_then(|s| s.rendition_class.clone()) {
-            Some(class) => class,
-            None => "".to_string(),
+        let info = &self.info;
+        let mut xmp = self.xmp.clone().unwrap_or_default();
+        let non_empty = |s: &str| {
+            if s.is_empty() {
+                None
+            } else {
+                Some(s.to_string())
+            }
         };
 
-        format!(
-            include_str!("./res/catalog_xmp_metadata.txt"),
-            create = create_date,
-            modify = modification_date,
-            mdate = metadata_date,
-            title = self.info.document_title,
-            id = document_id,
-            instance = instance_id,
-            class = rendition_class,
-            version = document_version,
-            pdfx = pdf_x_version,
-            trapping = trapping,
-            creator = self.info.creator,
-            subject = self.info.subject,
-            keywords = self.info.keywords.join(","),
-            identifier = self.info.identifier,
-            producer = self.info.producer
-        )
-    }
-}
+        xmp.basic.create_date = Some(to_pdf_xmp_date(&info.creation_date));
+        xmp.basic.modify_date = Some(to_pdf_xmp_date(&info.modification_date));
+        xmp.basic.metadata_date = Some(to_pdf_xmp_date(&info.metadata_date));
+        xmp.basic.creator_tool = non_empty(&info.creator);
+
+        xmp.dublin_core.title = non_empty(&info.document_title);
+        xmp.dublin_core.creator = non_empty(&info.author).into_iter().collect();
+        xmp.dublin_core.description = non_empty(&info.subject);
+        xmp.dublin_core.subject = info.keywords.clone();
+        xmp.dublin_core.identifier = non_empty(&info.identifier);
+        if let (Some(lang), true) = (self.language.as_ref(), xmp.dublin_core.language.is_empty()) {
+            xmp.dublin_core.language = vec![lang.clone()];
+        }
 
-/// Initial struct for Xmp metatdata. This should be expanded later for XML handling, etc.
-/// Right now it just fills out the necessary fields
-#[derive(Debug, PartialEq, Clone)]
-pub struct XmpMetadata {
-    /// Web-viewable or "default" or to be left empty. Usually "default".
-    pub rendition_class: Option<String>,
+        xmp.pdf.producer = non_empty(&info.producer);
+        xmp.pdf.keywords = non_empty(&info.keywords.join(","));
+        xmp.pdf.trapped = Some(info.trapped);
+
+        xmp.media_management.document_id = Some(format!("uuid:{}", info.identifier));
+        xmp.media_management.instance_id = Some(format!("uuid:{}", random_character_string_32()));
+        xmp.media_management.version_id = Some(info.version.to_string());
+
+        let pdf_x_version = info.conformance.get_identifier_string();
+        if pdf_x_version.starts_with("PDF/X") {
+            let version = XmpValue::Text(pdf_x_version);
+            xmp.set_custom_property(
+                "pdfxid",
+                "http://www.npes.org/pdfx/ns/id/",
+                "GTS_PDFXVersion",
+                version.clone(),
+            );
+            xmp.set_custom_property(
+                "pdfx",
+                "http://ns.adobe.com/pdfx/1.3/",
+                "GTS_PDFXVersion",
+                version,
+            );
+        }
+
+        xmp.to_xml()
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -347,6 +909,31 @@ pub struct PdfDocumentInfo {
     pub identifier: String,
 }
 
+impl PdfDocumentInfo {
+    /// Same as `PdfDocumentInfo::default()`, but with the creation, modification and
+    /// metadata dates set to the current time (instead of the Unix epoch)
+    // NOTE: OffsetDateTime is not Copy on wasm32
+    #[allow(clippy::clone_on_copy)]
+    pub fn new_with_current_time() -> Self {
+        let now = OffsetDateTime::now_utc();
+        Self {
+            creation_date: now.clone(),
+            modification_date: now.clone(),
+            metadata_date: now,
+            ..Default::default()
+        }
+    }
+
+    /// Sets the modification and metadata date to the current time
+    // NOTE: OffsetDateTime is not Copy on wasm32
+    #[allow(clippy::clone_on_copy)]
+    pub fn update_modification_date(&mut self) {
+        let now = OffsetDateTime::now_utc();
+        self.modification_date = now.clone();
+        self.metadata_date = now;
+    }
+}
+
 impl Default for PdfDocumentInfo {
     fn default() -> Self {
         Self {
@@ -0,0 +1,258 @@
+//! Printer marks for press-ready output: bleed, crop marks, registration marks and color bars
+
+use crate::{
+    color::{Cmyk, Color},
+    graphics::{Line, PaintMode, Point, Polygon, Rect, WindingOrder},
+    ops::{Op, PdfPage},
+    units::{Mm, Pt},
+};
+
+/// Which printer marks are drawn by `PdfPage::printer_marks`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrinterMarks {
+    /// Lines at the corners of the trim box, where the sheet is cut
+    pub crop_marks: bool,
+    /// Circles with crosshairs at the middle of each edge, to align the color separations
+    pub registration_marks: bool,
+    /// Patches of the process colors and grey tints above the trim box
+    pub color_bars: bool,
+    /// Distance of the marks from the trim box (at least the bleed)
+    pub offset: Mm,
+    /// Length of the crop marks, size of the registration marks and (at most) color patches
+    pub length: Mm,
+    /// Line width of the crop and registration marks
+    pub line_width: Pt,
+}
+
+impl Default for PrinterMarks {
+    fn default() -> Self {
+        Self {
+            crop_marks: true,
+            registration_marks: true,
+            color_bars: true,
+            offset: Mm(3.0),
+            length: Mm(5.0),
+            line_width: Pt(0.25),
+        }
+    }
+}
+
+/// Tints (CMYK) of the color bar patches
+const COLOR_BAR: [[f32; 4]; 10] = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+    [1.0, 1.0, 0.0, 0.0],
+    [1.0, 0.0, 1.0, 0.0],
+    [0.0, 1.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 0.25],
+    [0.0, 0.0, 0.0, 0.5],
+    [0.0, 0.0, 0.0, 0.75],
+];
+
+impl PdfPage {
+    /// Prepares the page for printing with bleed: the current media box becomes the trim
+    /// box, the bleed box extends it by `bleed` and the media box by `bleed + slug` on
+    /// every side (the printer marks need `offset + length` outside of the trim box).
+    /// The coordinates of the page content don't change.
+    pub fn with_bleed(mut self, bleed: Mm, slug: Mm) -> Self {
+        let grow = |r: &Rect, d: Mm| {
+            let d = d.into_pt().0;
+            Rect {
+                x: Pt(r.x.0 - d),
+                y: Pt(r.y.0 - d),
+                width: Pt(r.width.0 + 2.0 * d),
+                height: Pt(r.height.0 + 2.0 * d),
+            }
+        };
+        self.trim_box = self.media_box.clone();
+        self.bleed_box = Some(grow(&self.trim_box, bleed));
+        self.media_box = grow(&self.trim_box, Mm(bleed.0 + slug.0));
+        self.crop_box = self.media_box.clone();
+        self
+    }
+
+    /// Returns the ops that draw the printer marks outside of the trim box (in the
+    /// registration color, marked as an artifact), to be appended to the page ops
+    pub fn printer_marks(&self, marks: &PrinterMarks) -> Vec<Op> {
+        let trim = self.trim_box.normalize();
+        let (x0, y0) = (trim.x.0, trim.y.0);
+        let (x1, y1) = (x0 + trim.width.0, y0 + trim.height.0);
+        let (xm, ym) = ((x0 + x1) / 2.0, (y0 + y1) / 2.0);
+        let offset = marks.offset.into_pt().0;
+        let length = marks.length.into_pt().0;
+        let line = |points: &[(f32, f32)]| Op::DrawLine {
+            line: Line {
+                points: points
+                    .iter()
+                    .map(|(x, y)| {
+                        (
+                            Point {
+                                x: Pt(*x),
+                                y: Pt(*y),
+                            },
+                            false,
+                        )
+                    })
+                    .collect(),
+                is_closed: false,
+            },
+        };
+
+        let mut ops = vec![
+            Op::BeginArtifact,
+            Op::SaveGraphicsState,
+            Op::SetOutlineThickness {
+                pt: marks.line_width,
+            },
+            Op::SetOutlineColor {
+                col: get_registration_color(),
+            },
+        ];
+
+        if marks.crop_marks {
+            let (near, far) = (offset, offset + length);
+            for (x, y, dx, dy) in [
+                (x0, y0, -1.0, -1.0),
+                (x1, y0, 1.0, -1.0),
+                (x0, y1, -1.0, 1.0),
+                (x1, y1, 1.0, 1.0),
+            ] {
+                ops.push(line(&[(x + dx * near, y), (x + dx * far, y)]));
+                ops.push(line(&[(x, y + dy * near), (x, y + dy * far)]));
+            }
+        }
+
+        if marks.registration_marks {
+            let distance = offset + length / 2.0;
+            let half = length / 2.0;
+            for (cx, cy) in [
+                (xm, y0 - distance),
+                (xm, y1 + distance),
+                (x0 - distance, ym),
+                (x1 + distance, ym),
+            ] {
+                ops.push(Op::DrawLine {
+                    line: get_circle(cx, cy, length / 3.0),
+                });
+                ops.push(line(&[(cx - half, cy), (cx + half, cy)]));
+                ops.push(line(&[(cx, cy - half), (cx, cy + half)]));
+            }
+        }
+
+        // above the trim box, between the registration mark and the upper right crop mark
+        let size = length.min(((x1 - x0) / 2.0 - 1.5 * length) / COLOR_BAR.len() as f32);
+        if marks.color_bars && size > 0.0 {
+            let (start, y) = (xm + length, y1 + offset);
+            for (i, [c, m, yellow, k]) in COLOR_BAR.iter().enumerate() {
+                let x = start + i as f32 * size;
+                let point = |x: f32, y: f32| (Point { x: Pt(x), y: Pt(y) }, false);
+                ops.push(Op::SetFillColor {
+                    col: Color::Cmyk(Cmyk::new(*c, *m, *yellow, *k, None)),
+                });
+                ops.push(Op::DrawPolygon {
+                    polygon: Polygon {
+                        rings: vec![vec![
+                            point(x, y),
+                            point(x + size, y),
+                            point(x + size, y + size),
+                            point(x, y + size),
+                        ]],
+                        mode: PaintMode::Fill,
+                        winding_order: WindingOrder::NonZero,
+                    },
+                });
+            }
+        }
+
+        ops.push(Op::RestoreGraphicsState);
+        ops.push(Op::EndArtifact);
+        ops
+    }
+}
+
+/// Color that is printed on all separations (100% of every process color)
+fn get_registration_color() -> Color {
+    Color::Cmyk(Cmyk::new(1.0, 1.0, 1.0, 1.0, None))
+}
+
+/// Closed circle made of four bezier curves
+fn get_circle(cx: f32, cy: f32, r: f32) -> Line {
+    // distance of the control points for a quarter circle
+    let k = r * 0.552_284_8;
+    let p = |x: f32, y: f32, bezier: bool| {
+        (
+            Point {
+                x: Pt(cx + x),
+                y: Pt(cy + y),
+            },
+            bezier,
+        )
+    };
+    Line {
+        points: vec![
+            p(r, 0.0, true),
+            p(r, k, true),
+            p(k, r, false),
+            p(0.0, r, true),
+            p(-k, r, true),
+            p(-r, k, false),
+            p(-r, 0.0, true),
+            p(-r, -k, true),
+            p(-k, -r, false),
+            p(0.0, -r, true),
+            p(k, -r, true),
+            p(r, -k, false),
+            p(r, 0.0, false),
+        ],
+        is_closed: true,
+    }
+}
+
+#[test]
+fn test_printer_marks() {
+    let page = PdfPage::new(Mm(100.0), Mm(100.0), Vec::new()).with_bleed(Mm(3.0), Mm(7.0));
+    // the content keeps its coordinates, the media box grows by 10mm on every side
+    assert_eq!(
+        page.trim_box,
+        Rect::from_wh(Mm(100.0).into(), Mm(100.0).into())
+    );
+    let ten = Mm(10.0).into_pt().0;
+    assert!((page.media_box.x.0 + ten).abs() < 1e-3);
+    assert!((page.media_box.width.0 - Mm(120.0).into_pt().0).abs() < 1e-3);
+    let bleed = page.bleed_box.as_ref().unwrap();
+    assert!((bleed.width.0 - Mm(106.0).into_pt().0).abs() < 1e-3);
+
+    let ops = page.printer_marks(&PrinterMarks::default());
+    let lines = ops
+        .iter()
+        .filter(|op| matches!(op, Op::DrawLine { .. }))
+        .count();
+    let patches = ops
+        .iter()
+        .filter(|op| matches!(op, Op::DrawPolygon { .. }))
+        .count();
+    // 8 crop marks, 4 registration marks (circle + crosshair)
+    assert_eq!(lines, 8 + 4 * 3);
+    assert_eq!(patches, COLOR_BAR.len());
+    assert_eq!(ops.first(), Some(&Op::BeginArtifact));
+    assert_eq!(ops.last(), Some(&Op::EndArtifact));
+
+    // all marks are outside of the trim box and inside of the media box
+    let media = &page.media_box;
+    for op in &ops {
+        let Op::DrawLine { line } = op else {
+            continue;
+        };
+        for (p, _) in &line.points {
+            let inside_trim = p.x.0 > 0.01
+                && p.x.0 < page.trim_box.width.0 - 0.01
+                && p.y.0 > 0.01
+                && p.y.0 < page.trim_box.height.0 - 0.01;
+            assert!(!inside_trim);
+            assert!(p.x.0 >= media.x.0 && p.x.0 <= media.x.0 + media.width.0);
+            assert!(p.y.0 >= media.y.0 && p.y.0 <= media.y.0 + media.height.0);
+        }
+    }
+}
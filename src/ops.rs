@@ -1,12 +1,13 @@
 use crate::{
     color::Color,
     graphics::{
-        Line, LineCapStyle, LineDashPattern, LineJoinStyle, Point, Polygon, Rect, TextRenderingMode,
+        Line, LineCapStyle, LineDashPattern, LineJoinStyle, PaintMode, Point, Polygon, Rect,
+        RenderingIntent, TextRenderingMode, WindingOrder,
     },
     matrix::{CurTransMat, TextMatrix},
     units::{Mm, Pt},
-    BuiltinFont, ExtendedGraphicsStateId, FontId, LayerInternalId, LinkAnnotation, XObjectId,
-    XObjectTransform,
+    BuiltinFont, ExtendedGraphicsStateId, FontId, FormField, Gradient, LayerInternalId,
+    LinkAnnotation, MarkupAnnotation, PageActions, StructureElementId, XObjectId, XObjectTransform,
 };
 use lopdf::Object as LoObject;
 
@@ -15,7 +16,123 @@ pub struct PdfPage {
     pub media_box: Rect,
     pub trim_box: Rect,
     pub crop_box: Rect,
+    /// Area that is printed including the bleed (`/BleedBox`), the crop box if `None`
+    pub bleed_box: Option<Rect>,
+    /// Meaningful content of the page, i.e. for placing it on another page (`/ArtBox`)
+    pub art_box: Option<Rect>,
+    /// Margins of the page, the area inside is the content frame (see `content_frame`).
+    /// Only used by the coordinate helpers, not written to the PDF.
+    pub margins: Margins,
+    /// Actions run when the page is opened or closed
+    pub actions: PageActions,
+    /// Clockwise rotation of the page in the viewer and when printing (`/Rotate`)
+    pub rotation: PageRotation,
+    /// Size of one unit of the page coordinates in points (`/UserUnit`, default 1.0)
+    pub user_unit: f32,
     pub ops: Vec<Op>,
+    /// Origin of the ops of a parsed page in the source file, only recorded with
+    /// `PdfParseOptions::record_op_sources`. Not updated when the ops are modified.
+    pub op_sources: Vec<OpSource>,
+}
+
+/// Where a parsed op comes from in the source file, for pointing at problems in the
+/// original document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpSource {
+    /// Index of the op in `PdfPage::ops`
+    pub op_index: usize,
+    pub origin: OpOrigin,
+}
+
+/// Part of the source file that an op was parsed from, see `OpSource`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OpOrigin {
+    /// Operation of the page content stream
+    Content {
+        /// Index of the operation in the content stream
+        operator_index: usize,
+        /// Operands and operator in the decoded content (the streams of `/Contents`,
+        /// concatenated with a newline), `None` if the operations couldn't be located
+        byte_range: Option<std::ops::Range<usize>>,
+    },
+    /// Annotation or link (an entry of the page's `/Annots` array)
+    Annotation {
+        /// Index of the entry in the `/Annots` array of the page
+        annot_index: usize,
+        /// Object number and generation, `None` for annotations stored directly in the array
+        object_id: Option<(u32, u16)>,
+        /// Byte offset of the object in the source file (from the cross-reference table),
+        /// `None` for direct objects and objects stored in object streams
+        byte_offset: Option<usize>,
+    },
+}
+
+/// Clockwise rotation of a page, in steps of 90 degrees
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum PageRotation {
+    #[default]
+    None,
+    Clockwise90,
+    Clockwise180,
+    Clockwise270,
+}
+
+impl PageRotation {
+    /// Rotation from degrees (clockwise), rounded to the nearest multiple of 90 degrees
+    pub fn from_degrees(degrees: i64) -> Self {
+        match ((degrees as f64 / 90.0).round() as i64).rem_euclid(4) {
+            1 => PageRotation::Clockwise90,
+            2 => PageRotation::Clockwise180,
+            3 => PageRotation::Clockwise270,
+            _ => PageRotation::None,
+        }
+    }
+
+    /// Clockwise rotation in degrees (0, 90, 180 or 270), the value of `/Rotate`
+    pub fn get_degrees(&self) -> i64 {
+        match self {
+            PageRotation::None => 0,
+            PageRotation::Clockwise90 => 90,
+            PageRotation::Clockwise180 => 180,
+            PageRotation::Clockwise270 => 270,
+        }
+    }
+
+    /// Adds another clockwise rotation to this rotation
+    pub fn rotate(self, by: PageRotation) -> Self {
+        Self::from_degrees(self.get_degrees() + by.get_degrees())
+    }
+}
+
+/// Page margins (distance from the edges of the media box)
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Margins {
+    pub top: Mm,
+    pub right: Mm,
+    pub bottom: Mm,
+    pub left: Mm,
+}
+
+impl Margins {
+    /// Creates margins in CSS order (top, right, bottom, left)
+    pub fn new(top: Mm, right: Mm, bottom: Mm, left: Mm) -> Self {
+        Self {
+            top,
+            right,
+            bottom,
+            left,
+        }
+    }
+
+    /// Same margin on all sides
+    pub fn uniform(margin: Mm) -> Self {
+        Self::new(margin, margin, margin, margin)
+    }
+
+    /// Same margin at the top / bottom and at the left / right
+    pub fn symmetric(vertical: Mm, horizontal: Mm) -> Self {
+        Self::new(vertical, horizontal, vertical, horizontal)
+    }
 }
 
 impl PdfPage {
@@ -24,10 +141,143 @@ impl PdfPage {
             media_box: Rect::from_wh(width.into(), height.into()),
             trim_box: Rect::from_wh(width.into(), height.into()),
             crop_box: Rect::from_wh(width.into(), height.into()),
+            bleed_box: None,
+            art_box: None,
+            margins: Margins::default(),
+            actions: PageActions::default(),
+            rotation: PageRotation::None,
+            user_unit: 1.0,
             ops,
+            op_sources: Vec::new(),
+        }
+    }
+
+    /// Sets the bleed box (`/BleedBox`), see also `with_bleed`
+    pub fn with_bleed_box(mut self, bleed_box: Rect) -> Self {
+        self.bleed_box = Some(bleed_box);
+        self
+    }
+
+    /// Sets the art box (`/ArtBox`)
+    pub fn with_art_box(mut self, art_box: Rect) -> Self {
+        self.art_box = Some(art_box);
+        self
+    }
+
+    /// Sets the clockwise rotation of the page in the viewer
+    pub fn with_rotation(mut self, rotation: PageRotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    /// Sets the size of one unit of the page coordinates in points (`/UserUnit`), for pages
+    /// that are larger than the maximum size of 14400 x 14400 units (200 x 200 inches).
+    /// The boxes and the page content are not scaled.
+    pub fn with_user_unit(mut self, user_unit: f32) -> Self {
+        self.user_unit = user_unit;
+        self
+    }
+
+    /// Sets the actions run when the page is opened or closed
+    pub fn with_actions(mut self, actions: PageActions) -> Self {
+        self.actions = actions;
+        self
+    }
+
+    /// Sets the margins of the page
+    pub fn with_margins(mut self, margins: Margins) -> Self {
+        self.margins = margins;
+        self
+    }
+
+    /// Area inside the margins, from the lower left corner of the page
+    pub fn content_frame(&self) -> Rect {
+        let m = &self.margins;
+        let (top, right, bottom, left) = (
+            m.top.into_pt().0,
+            m.right.into_pt().0,
+            m.bottom.into_pt().0,
+            m.left.into_pt().0,
+        );
+        Rect {
+            x: Pt(self.media_box.x.0 + left),
+            y: Pt(self.media_box.y.0 + bottom),
+            width: Pt((self.media_box.width.0 - left - right).max(0.0)),
+            height: Pt((self.media_box.height.0 - top - bottom).max(0.0)),
         }
     }
 
+    /// Converts a point relative to the lower left corner of the content frame to
+    /// page coordinates
+    pub fn content_point(&self, x: Mm, y: Mm) -> Point {
+        let frame = self.content_frame();
+        Point {
+            x: Pt(frame.x.0 + x.into_pt().0),
+            y: Pt(frame.y.0 + y.into_pt().0),
+        }
+    }
+
+    /// Converts a point relative to the top left corner of the content frame
+    /// (`y` grows downwards, like in HTML) to page coordinates
+    pub fn content_point_from_top(&self, x: Mm, y: Mm) -> Point {
+        let frame = self.content_frame();
+        Point {
+            x: Pt(frame.x.0 + x.into_pt().0),
+            y: Pt(frame.y.0 + frame.height.0 - y.into_pt().0),
+        }
+    }
+
+    /// Moves a rectangle that is relative to the content frame to page coordinates
+    pub fn content_rect(&self, rect: &Rect) -> Rect {
+        let frame = self.content_frame();
+        Rect {
+            x: Pt(frame.x.0 + rect.x.0),
+            y: Pt(frame.y.0 + rect.y.0),
+            width: rect.width,
+            height: rect.height,
+        }
+    }
+
+    /// Returns whether the point (in page coordinates) lies inside the content frame
+    pub fn is_in_content_frame(&self, point: Point) -> bool {
+        let frame = self.content_frame();
+        point.x.0 >= frame.x.0
+            && point.x.0 <= frame.x.0 + frame.width.0
+            && point.y.0 >= frame.y.0
+            && point.y.0 <= frame.y.0 + frame.height.0
+    }
+
+    /// Fills the whole page (media box) with the color, behind all other content.
+    /// The fill is inserted as the first operations, in its own graphics state and
+    /// marked as an artifact, so it doesn't interfere with the existing `q` / `Q` nesting.
+    pub fn with_background(mut self, color: Color) -> Self {
+        let r = &self.media_box;
+        let (x0, y0) = (r.x, r.y);
+        let (x1, y1) = (Pt(r.x.0 + r.width.0), Pt(r.y.0 + r.height.0));
+        let point = |x, y| (Point { x, y }, false);
+        let background = vec![
+            Op::SaveGraphicsState,
+            Op::BeginArtifact,
+            Op::SetFillColor { col: color },
+            Op::DrawPolygon {
+                polygon: Polygon {
+                    rings: vec![vec![
+                        point(x0, y0),
+                        point(x1, y0),
+                        point(x1, y1),
+                        point(x0, y1),
+                    ]],
+                    mode: PaintMode::Fill,
+                    winding_order: WindingOrder::NonZero,
+                },
+            },
+            Op::EndArtifact,
+            Op::RestoreGraphicsState,
+        ];
+        self.ops.splice(0..0, background);
+        self
+    }
+
     pub(crate) fn get_media_box(&self) -> lopdf::Object {
         self.media_box.to_array().into()
     }
@@ -97,6 +347,21 @@ pub enum Op {
     BeginLayer { layer_id: LayerInternalId },
     /// Ends a layer (is inserted if missing at the page end)
     EndLayer { layer_id: LayerInternalId },
+    /// Starts a marked content sequence that belongs to a structure element
+    /// (use `PdfDocument::structure` to create the element)
+    BeginStructureElement { id: StructureElementId },
+    /// Ends the marked content sequence started by `BeginStructureElement`
+    EndStructureElement,
+    /// Starts an artifact: content that is not part of the document text (page headers,
+    /// footers, page numbers, decorations), ignored by screen readers and text extraction
+    BeginArtifact,
+    /// Ends the artifact started by `BeginArtifact`
+    EndArtifact,
+    /// Starts a span of text in a different language than the document (i.e. "fr-FR"),
+    /// so that screen readers switch the pronunciation
+    BeginLanguageSpan { lang: String },
+    /// Ends the span started by `BeginLanguageSpan`
+    EndLanguageSpan,
     /// Saves the graphics configuration on the stack (line thickness, colors, overprint, etc.)
     SaveGraphicsState,
     /// Pops the last graphics configuration state off the stack
@@ -161,10 +426,15 @@ pub enum Op {
     SetLineCapStyle { cap: LineCapStyle },
     /// Sets the text rendering mode (fill, stroke, fill-stroke, clip, fill-clip)
     SetTextRenderingMode { mode: TextRenderingMode },
+    /// Sets the rendering intent for subsequent fills / strokes / images
+    /// (i.e. `Perceptual` for photos, `RelativeColorimetric` for logos)
+    SetRenderingIntent { intent: RenderingIntent },
     /// Sets the character spacing (default: 1.0)
     SetCharacterSpacing { multiplier: f32 },
     /// Sets the line offset (default: 1.0)
     SetLineOffset { multiplier: f32 },
+    /// Sets the horizontal scaling of the glyphs and their spacing in percent (default: 100.0)
+    SetHorizontalScaling { percent: f32 },
     /// Draw a line (colors, dashes configured earlier)
     DrawLine { line: Line },
     /// Draw a polygon
@@ -175,6 +445,16 @@ pub enum Op {
     SetTextMatrix { matrix: TextMatrix },
     /// Adds a link annotation (use `PdfDocument::add_link` to register the `LinkAnnotation` on the document)
     LinkAnnotation { link: LinkAnnotation },
+    /// Adds an interactive form field (text input, etc.) to the page
+    AddFormField { field: Box<FormField> },
+    /// Adds a markup annotation (free text, sticky note) to the page
+    AddAnnotation { annotation: Box<MarkupAnnotation> },
+    /// Fills the area of the polygon with a linear or radial gradient (the paint mode
+    /// of the polygon is ignored, the polygon is only used as a clipping path)
+    DrawGradient {
+        clip_polygon: Polygon,
+        gradient: Gradient,
+    },
     /// Instantiates an XObject with a given transform (if the XObject has a width / height).
     /// Use `PdfDocument::add_xobject` to register the object and get the ID.
     UseXObject {
@@ -185,6 +465,58 @@ pub enum Op {
     Unknown { key: String, value: Vec<LoObject> },
 }
 
+impl Op {
+    /// Name of the operation (i.e. `"DrawLine"`), for statistics and debugging
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Op::Marker { .. } => "Marker",
+            Op::BeginLayer { .. } => "BeginLayer",
+            Op::EndLayer { .. } => "EndLayer",
+            Op::BeginStructureElement { .. } => "BeginStructureElement",
+            Op::EndStructureElement => "EndStructureElement",
+            Op::BeginArtifact => "BeginArtifact",
+            Op::EndArtifact => "EndArtifact",
+            Op::BeginLanguageSpan { .. } => "BeginLanguageSpan",
+            Op::EndLanguageSpan => "EndLanguageSpan",
+            Op::SaveGraphicsState => "SaveGraphicsState",
+            Op::RestoreGraphicsState => "RestoreGraphicsState",
+            Op::LoadGraphicsState { .. } => "LoadGraphicsState",
+            Op::StartTextSection => "StartTextSection",
+            Op::EndTextSection => "EndTextSection",
+            Op::WriteText { .. } => "WriteText",
+            Op::WriteTextBuiltinFont { .. } => "WriteTextBuiltinFont",
+            Op::WriteCodepoints { .. } => "WriteCodepoints",
+            Op::WriteCodepointsWithKerning { .. } => "WriteCodepointsWithKerning",
+            Op::AddLineBreak => "AddLineBreak",
+            Op::SetLineHeight { .. } => "SetLineHeight",
+            Op::SetWordSpacing { .. } => "SetWordSpacing",
+            Op::SetFontSize { .. } => "SetFontSize",
+            Op::SetTextCursor { .. } => "SetTextCursor",
+            Op::SetFillColor { .. } => "SetFillColor",
+            Op::SetOutlineColor { .. } => "SetOutlineColor",
+            Op::SetOutlineThickness { .. } => "SetOutlineThickness",
+            Op::SetLineDashPattern { .. } => "SetLineDashPattern",
+            Op::SetLineJoinStyle { .. } => "SetLineJoinStyle",
+            Op::SetLineCapStyle { .. } => "SetLineCapStyle",
+            Op::SetTextRenderingMode { .. } => "SetTextRenderingMode",
+            Op::SetRenderingIntent { .. } => "SetRenderingIntent",
+            Op::SetCharacterSpacing { .. } => "SetCharacterSpacing",
+            Op::SetLineOffset { .. } => "SetLineOffset",
+            Op::SetHorizontalScaling { .. } => "SetHorizontalScaling",
+            Op::DrawLine { .. } => "DrawLine",
+            Op::DrawPolygon { .. } => "DrawPolygon",
+            Op::SetTransformationMatrix { .. } => "SetTransformationMatrix",
+            Op::SetTextMatrix { .. } => "SetTextMatrix",
+            Op::LinkAnnotation { .. } => "LinkAnnotation",
+            Op::AddFormField { .. } => "AddFormField",
+            Op::AddAnnotation { .. } => "AddAnnotation",
+            Op::DrawGradient { .. } => "DrawGradient",
+            Op::UseXObject { .. } => "UseXObject",
+            Op::Unknown { .. } => "Unknown",
+        }
+    }
+}
+
 impl PartialEq for Op {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
@@ -205,6 +537,14 @@ impl PartialEq for Op {
                     layer_id: r_layer_id,
                 },
             ) => l_layer_id == r_layer_id,
+            (
+                Self::BeginStructureElement { id: l_id },
+                Self::BeginStructureElement { id: r_id },
+            ) => l_id == r_id,
+            (
+                Self::BeginLanguageSpan { lang: l_lang },
+                Self::BeginLanguageSpan { lang: r_lang },
+            ) => l_lang == r_lang,
             (Self::LoadGraphicsState { gs: l_gs }, Self::LoadGraphicsState { gs: r_gs }) => {
                 l_gs == r_gs
             }
@@ -313,11 +653,25 @@ impl PartialEq for Op {
                     multiplier: r_multiplier,
                 },
             ) => l_multiplier == r_multiplier,
+            (
+                Self::SetHorizontalScaling { percent: l_percent },
+                Self::SetHorizontalScaling { percent: r_percent },
+            ) => l_percent == r_percent,
             (Self::DrawLine { line: l_line }, Self::DrawLine { line: r_line }) => l_line == r_line,
             (
                 Self::DrawPolygon { polygon: l_polygon },
                 Self::DrawPolygon { polygon: r_polygon },
             ) => l_polygon == r_polygon,
+            (
+                Self::DrawGradient {
+                    clip_polygon: l_clip_polygon,
+                    gradient: l_gradient,
+                },
+                Self::DrawGradient {
+                    clip_polygon: r_clip_polygon,
+                    gradient: r_gradient,
+                },
+            ) => l_clip_polygon == r_clip_polygon && l_gradient == r_gradient,
             (
                 Self::SetTransformationMatrix { matrix: l_matrix },
                 Self::SetTransformationMatrix { matrix: r_matrix },
@@ -339,6 +693,21 @@ impl PartialEq for Op {
                     transform: r_transform,
                 },
             ) => l_id == r_id && l_transform == r_transform,
+            (
+                Self::SetRenderingIntent { intent: l_intent },
+                Self::SetRenderingIntent { intent: r_intent },
+            ) => l_intent == r_intent,
+            (
+                Self::AddAnnotation {
+                    annotation: l_annotation,
+                },
+                Self::AddAnnotation {
+                    annotation: r_annotation,
+                },
+            ) => l_annotation == r_annotation,
+            (Self::AddFormField { field: l_field }, Self::AddFormField { field: r_field }) => {
+                l_field == r_field
+            }
             (
                 Self::Unknown {
                     key: l_key,
@@ -0,0 +1,625 @@
+//! Document outline (`/Outlines`, the "bookmarks" panel of the viewer) with nested entries
+//! and named destinations (`/Dests` name tree)
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use lopdf::Object::{Array, Dictionary, Integer, Name, Real, Reference, String as LoString};
+use lopdf::StringFormat::Literal;
+use lopdf::{Dictionary as LoDictionary, Object as LoObject, ObjectId};
+
+use crate::{
+    deserialize::collect_page_refs,
+    forms::{decode_text_string, parse_numbers, resolve, text_string},
+    Actions, Destination, PdfDocument, PdfWarnCode, PdfWarnMsg,
+};
+
+/// Entry of the document outline, can contain nested entries
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlineNode {
+    /// Title shown in the outline
+    pub title: String,
+    /// Destination that the viewer jumps to when the entry is clicked
+    pub dest: Destination,
+    /// Nested entries
+    pub children: Vec<OutlineNode>,
+    /// Show the title in bold (PDF 1.4)
+    pub bold: bool,
+    /// Show the title in italic (PDF 1.4)
+    pub italic: bool,
+    /// Color of the title (RGB, 0.0 - 1.0, PDF 1.4)
+    pub color: Option<[f32; 3]>,
+    /// Whether the children are expanded when the document is opened
+    pub open: bool,
+}
+
+impl OutlineNode {
+    /// Creates a new outline entry without children
+    pub fn new(title: &str, dest: Destination) -> Self {
+        Self {
+            title: title.to_string(),
+            dest,
+            children: Vec::new(),
+            bold: false,
+            italic: false,
+            color: None,
+            open: false,
+        }
+    }
+
+    /// Creates an outline entry that jumps to the top of the page (1-based page number)
+    pub fn page(title: &str, page: usize) -> Self {
+        Self::new(
+            title,
+            Destination::XYZ {
+                page,
+                left: None,
+                top: None,
+                zoom: None,
+            },
+        )
+    }
+
+    /// Appends a nested entry
+    pub fn with_child(mut self, child: OutlineNode) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    /// Sets the nested entries
+    pub fn with_children(mut self, children: Vec<OutlineNode>) -> Self {
+        self.children = children;
+        self
+    }
+
+    /// Shows the title in bold
+    pub fn with_bold(mut self, bold: bool) -> Self {
+        self.bold = bold;
+        self
+    }
+
+    /// Shows the title in italic
+    pub fn with_italic(mut self, italic: bool) -> Self {
+        self.italic = italic;
+        self
+    }
+
+    /// Sets the color of the title
+    pub fn with_color(mut self, color: [f32; 3]) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    /// Expands the children when the document is opened
+    pub fn with_open(mut self, open: bool) -> Self {
+        self.open = open;
+        self
+    }
+}
+
+impl PdfDocument {
+    /// Appends a top-level entry to the document outline
+    pub fn add_outline(&mut self, node: OutlineNode) -> &mut Self {
+        self.outline.push(node);
+        self
+    }
+
+    /// Registers a named destination, which links and outline entries can target with
+    /// `Destination::Named(name)`. Replaces an existing destination with the same name.
+    pub fn add_named_destination(&mut self, name: &str, dest: Destination) -> &mut Self {
+        self.named_destinations.insert(name.to_string(), dest);
+        self
+    }
+}
+
+/// Writes the `/Dests` name tree (a single leaf node, the names are sorted by the `BTreeMap`)
+pub(crate) fn write_named_destinations(
+    dests: &BTreeMap<String, Destination>,
+    page_ids: &[ObjectId],
+    warnings: &mut Vec<PdfWarnMsg>,
+) -> LoDictionary {
+    let mut names = Vec::new();
+    for (name, dest) in dests {
+        if let Destination::Named(target) = dest {
+            warnings.push(
+                PdfWarnMsg::warning(
+                    None,
+                    format!(
+                        "named destination {name:?} refers to another name ({target:?}), skipped"
+                    ),
+                )
+                .with_code(PdfWarnCode::NamedDestinationChain)
+                .with_resource(name),
+            );
+            continue;
+        }
+        names.push(LoString(name.as_bytes().to_vec(), Literal));
+        names.push(crate::serialize::destination_to_obj(dest, page_ids));
+    }
+    LoDictionary::from_iter(vec![("Names", Array(names))])
+}
+
+/// Parses the `/Dests` name tree (PDF 1.2) and the `/Dests` dictionary of the catalog (PDF 1.1),
+/// name trees nested deeper than `max_depth` levels are ignored
+pub(crate) fn parse_named_destinations(
+    doc: &lopdf::Document,
+    max_depth: usize,
+) -> BTreeMap<String, Destination> {
+    let page_numbers = get_page_numbers(doc, max_depth);
+    let mut dests = BTreeMap::new();
+    let Ok(catalog) = doc.catalog() else {
+        return dests;
+    };
+
+    if let Some(old_dests) = catalog
+        .get(b"Dests")
+        .ok()
+        .and_then(|d| resolve(doc, d).as_dict().ok())
+    {
+        for (name, dest) in old_dests.iter() {
+            if let Some(dest) = parse_named_destination_value(doc, dest, &page_numbers) {
+                dests.insert(String::from_utf8_lossy(name).to_string(), dest);
+            }
+        }
+    }
+
+    if let Some(tree) = catalog
+        .get(b"Names")
+        .ok()
+        .and_then(|n| resolve(doc, n).as_dict().ok())
+        .and_then(|n| n.get(b"Dests").ok())
+    {
+        let mut visited = BTreeSet::new();
+        parse_name_tree(
+            doc,
+            tree,
+            &page_numbers,
+            &mut visited,
+            max_depth,
+            &mut dests,
+        );
+    }
+
+    dests
+}
+
+fn parse_name_tree(
+    doc: &lopdf::Document,
+    node: &LoObject,
+    page_numbers: &BTreeMap<ObjectId, usize>,
+    visited: &mut BTreeSet<ObjectId>,
+    max_depth: usize,
+    dests: &mut BTreeMap<String, Destination>,
+) {
+    let Some(max_depth) = max_depth.checked_sub(1) else {
+        return;
+    };
+    if let Ok(id) = node.as_reference() {
+        if !visited.insert(id) {
+            return;
+        }
+    }
+    let Ok(node) = resolve(doc, node).as_dict() else {
+        return;
+    };
+
+    if let Ok(names) = node.get(b"Names").and_then(|n| resolve(doc, n).as_array()) {
+        for pair in names.chunks_exact(2) {
+            let Ok(name) = resolve(doc, &pair[0]).as_str() else {
+                continue;
+            };
+            if let Some(dest) = parse_named_destination_value(doc, &pair[1], page_numbers) {
+                dests.insert(decode_text_string(name), dest);
+            }
+        }
+    }
+
+    if let Ok(kids) = node.get(b"Kids").and_then(|k| resolve(doc, k).as_array()) {
+        for kid in kids {
+            parse_name_tree(doc, kid, page_numbers, visited, max_depth, dests);
+        }
+    }
+}
+
+/// Value of a named destination: either the destination array or a dictionary with `/D`
+fn parse_named_destination_value(
+    doc: &lopdf::Document,
+    value: &LoObject,
+    page_numbers: &BTreeMap<ObjectId, usize>,
+) -> Option<Destination> {
+    let value = match resolve(doc, value) {
+        Dictionary(d) => d.get(b"D").ok()?,
+        other => other,
+    };
+    match parse_destination(doc, value, page_numbers)? {
+        Destination::Named(_) => None,
+        dest => Some(dest),
+    }
+}
+
+/// Writes the outline items, returns the ID of the `/Outlines` dictionary
+pub(crate) fn write_outline(
+    nodes: &[OutlineNode],
+    page_ids: &[ObjectId],
+    doc: &mut lopdf::Document,
+) -> ObjectId {
+    let outlines_id = doc.new_object_id();
+    let (first, last, count) = write_outline_items(nodes, outlines_id, page_ids, doc);
+    let mut dict = LoDictionary::from_iter(vec![
+        ("Type", Name("Outlines".into())),
+        ("Count", Integer(count)),
+    ]);
+    if let (Some(first), Some(last)) = (first, last) {
+        dict.set("First", Reference(first));
+        dict.set("Last", Reference(last));
+    }
+    doc.objects.insert(outlines_id, Dictionary(dict));
+    outlines_id
+}
+
+/// Writes a list of siblings, returns the first and last item and the number of
+/// visible items (including the children of open items)
+fn write_outline_items(
+    nodes: &[OutlineNode],
+    parent: ObjectId,
+    page_ids: &[ObjectId],
+    doc: &mut lopdf::Document,
+) -> (Option<ObjectId>, Option<ObjectId>, i64) {
+    let ids = nodes
+        .iter()
+        .map(|_| doc.new_object_id())
+        .collect::<Vec<_>>();
+    let mut visible = 0;
+
+    for (i, node) in nodes.iter().enumerate() {
+        let mut dict = LoDictionary::from_iter(vec![
+            ("Title", text_string(&node.title)),
+            ("Parent", Reference(parent)),
+            (
+                "Dest",
+                crate::serialize::destination_to_obj(&node.dest, page_ids),
+            ),
+        ]);
+        if i > 0 {
+            dict.set("Prev", Reference(ids[i - 1]));
+        }
+        if let Some(next) = ids.get(i + 1) {
+            dict.set("Next", Reference(*next));
+        }
+
+        visible += 1;
+        if let (Some(first), Some(last), count) =
+            write_outline_items(&node.children, ids[i], page_ids, doc)
+        {
+            dict.set("First", Reference(first));
+            dict.set("Last", Reference(last));
+            // negative count = closed, absolute value is the number of items shown when opened
+            if node.open {
+                dict.set("Count", Integer(count));
+                visible += count;
+            } else {
+                dict.set("Count", Integer(-count));
+            }
+        }
+
+        // 1 = italic, 2 = bold
+        let flags = node.italic as i64 | (node.bold as i64) << 1;
+        if flags != 0 {
+            dict.set("F", Integer(flags));
+        }
+        if let Some(c) = node.color {
+            dict.set("C", Array(c.iter().copied().map(Real).collect()));
+        }
+
+        doc.objects.insert(ids[i], Dictionary(dict));
+    }
+
+    (ids.first().copied(), ids.last().copied(), visible)
+}
+
+/// Parses the `/Outlines` of the document catalog, entries nested deeper than
+/// `max_depth` levels are ignored
+pub(crate) fn parse_outline(doc: &lopdf::Document, max_depth: usize) -> Vec<OutlineNode> {
+    let page_numbers = get_page_numbers(doc, max_depth);
+    let Some(outlines) = doc
+        .catalog()
+        .ok()
+        .and_then(|c| c.get(b"Outlines").ok())
+        .and_then(|o| resolve(doc, o).as_dict().ok())
+    else {
+        return Vec::new();
+    };
+
+    let mut visited = BTreeSet::new();
+    parse_outline_items(
+        doc,
+        outlines.get(b"First").ok(),
+        &page_numbers,
+        &mut visited,
+        max_depth,
+    )
+}
+
+/// Maps the page object IDs to 1-based page numbers
+fn get_page_numbers(doc: &lopdf::Document, max_depth: usize) -> BTreeMap<ObjectId, usize> {
+    collect_page_refs(doc, max_depth)
+        .into_iter()
+        .enumerate()
+        .map(|(i, id)| (id, i + 1))
+        .collect()
+}
+
+fn parse_outline_items(
+    doc: &lopdf::Document,
+    first: Option<&LoObject>,
+    page_numbers: &BTreeMap<ObjectId, usize>,
+    visited: &mut BTreeSet<ObjectId>,
+    max_depth: usize,
+) -> Vec<OutlineNode> {
+    let mut nodes = Vec::new();
+    let Some(max_depth) = max_depth.checked_sub(1) else {
+        return nodes;
+    };
+
+    let mut next = first.and_then(|o| o.as_reference().ok());
+    while let Some(id) = next {
+        // Next / First links can form cycles in broken files
+        if !visited.insert(id) {
+            break;
+        }
+        let Ok(dict) = doc.get_dictionary(id) else {
+            break;
+        };
+
+        let title = dict
+            .get(b"Title")
+            .ok()
+            .and_then(|t| resolve(doc, t).as_str().ok())
+            .map(decode_text_string)
+            .unwrap_or_default();
+        let flags = dict
+            .get(b"F")
+            .ok()
+            .and_then(|f| resolve(doc, f).as_i64().ok())
+            .unwrap_or(0);
+        let color = dict
+            .get(b"C")
+            .ok()
+            .and_then(|c| parse_numbers(doc, c))
+            .and_then(|c| match c.as_slice() {
+                [r, g, b] => Some([*r, *g, *b]),
+                _ => None,
+            });
+        let count = dict
+            .get(b"Count")
+            .ok()
+            .and_then(|c| resolve(doc, c).as_i64().ok())
+            .unwrap_or(0);
+
+        nodes.push(OutlineNode {
+            title,
+            // items without a (supported) destination jump to the first page
+            dest: get_outline_destination(doc, dict, page_numbers)
+                .unwrap_or_else(|| OutlineNode::page("", 1).dest),
+            children: parse_outline_items(
+                doc,
+                dict.get(b"First").ok(),
+                page_numbers,
+                visited,
+                max_depth,
+            ),
+            bold: flags & 2 != 0,
+            italic: flags & 1 != 0,
+            color,
+            open: count > 0,
+        });
+
+        next = dict.get(b"Next").ok().and_then(|n| n.as_reference().ok());
+    }
+
+    nodes
+}
+
+/// Reads the `/Dest` or the destination of a `/GoTo` action (`/A`)
+fn get_outline_destination(
+    doc: &lopdf::Document,
+    dict: &LoDictionary,
+    page_numbers: &BTreeMap<ObjectId, usize>,
+) -> Option<Destination> {
+    match dict.get(b"Dest") {
+        Ok(dest) => parse_destination(doc, dest, page_numbers),
+        Err(_) => match parse_action(doc, dict.get(b"A").ok()?, page_numbers)? {
+            Actions::GoTo(dest) => Some(dest),
+            _ => None,
+        },
+    }
+}
+
+/// Parses an action dictionary (`GoTo`, `URI` and `Named` actions)
+pub(crate) fn parse_action(
+    doc: &lopdf::Document,
+    action: &LoObject,
+    page_numbers: &BTreeMap<ObjectId, usize>,
+) -> Option<Actions> {
+    let action = resolve(doc, action).as_dict().ok()?;
+    match action.get(b"S").ok()?.as_name().ok()? {
+        b"GoTo" => parse_destination(doc, action.get(b"D").ok()?, page_numbers).map(Actions::GoTo),
+        b"URI" => {
+            let uri = resolve(doc, action.get(b"URI").ok()?).as_str().ok()?;
+            Some(Actions::URI(String::from_utf8_lossy(uri).to_string()))
+        }
+        b"Named" => {
+            let name = resolve(doc, action.get(b"N").ok()?).as_name().ok()?;
+            Some(Actions::Named(String::from_utf8_lossy(name).to_string()))
+        }
+        _ => None,
+    }
+}
+
+/// Parses an explicit destination array (i.e. `[page /XYZ left top zoom]`) or the name of a
+/// named destination
+pub(crate) fn parse_destination(
+    doc: &lopdf::Document,
+    dest: &LoObject,
+    page_numbers: &BTreeMap<ObjectId, usize>,
+) -> Option<Destination> {
+    let dest = match resolve(doc, dest) {
+        LoString(name, _) => return Some(Destination::Named(decode_text_string(name))),
+        Name(name) => {
+            return Some(Destination::Named(
+                String::from_utf8_lossy(name).to_string(),
+            ))
+        }
+        other => other.as_array().ok()?,
+    };
+    let page = *page_numbers.get(&dest.first()?.as_reference().ok()?)?;
+    let number = |i: usize| match dest.get(i).map(|o| resolve(doc, o)) {
+        Some(Integer(i)) => Some(*i as f32),
+        Some(Real(r)) => Some(*r),
+        _ => None,
+    };
+
+    let kind = match dest.get(1).map(|o| resolve(doc, o)) {
+        Some(Name(n)) => n.as_slice(),
+        _ => &b"XYZ"[..],
+    };
+    Some(match kind {
+        b"Fit" => Destination::Fit { page },
+        b"FitH" => Destination::FitH {
+            page,
+            top: number(2),
+        },
+        b"FitV" => Destination::FitV {
+            page,
+            left: number(2),
+        },
+        b"FitR" => Destination::FitR {
+            page,
+            left: number(2).unwrap_or(0.0),
+            bottom: number(3).unwrap_or(0.0),
+            right: number(4).unwrap_or(0.0),
+            top: number(5).unwrap_or(0.0),
+        },
+        b"FitB" => Destination::FitB { page },
+        b"FitBH" => Destination::FitBH {
+            page,
+            top: number(2),
+        },
+        b"FitBV" => Destination::FitBV {
+            page,
+            left: number(2),
+        },
+        // unknown types are treated as XYZ
+        _ => Destination::XYZ {
+            page,
+            left: number(2),
+            top: number(3),
+            zoom: number(4),
+        },
+    })
+}
+
+#[test]
+fn test_outline_roundtrip() {
+    use crate::{Mm, PdfPage, PdfSaveOptions};
+
+    let mut doc = PdfDocument::new("outline");
+    for _ in 0..3 {
+        doc.pages
+            .push(PdfPage::new(Mm(210.0), Mm(297.0), Vec::new()));
+    }
+    doc.add_outline(
+        OutlineNode::page("Chapter 1", 1)
+            .with_bold(true)
+            .with_open(true)
+            .with_child(OutlineNode::page("Section 1.1", 2).with_color([1.0, 0.0, 0.0]))
+            .with_child(
+                OutlineNode::page("Section 1.2", 2)
+                    .with_child(OutlineNode::page("Sub-section 1.2.1", 3).with_italic(true)),
+            ),
+    );
+    doc.add_outline(OutlineNode::new(
+        "Anhang \u{00fc}",
+        Destination::XYZ {
+            page: 3,
+            left: Some(0.0),
+            top: Some(100.0),
+            zoom: None,
+        },
+    ));
+
+    let bytes = doc.save(&PdfSaveOptions::default());
+    let parsed = PdfDocument::parse(&bytes, &Default::default()).unwrap();
+    assert_eq!(parsed.outline, doc.outline);
+}
+
+#[test]
+fn test_named_destinations() {
+    use crate::{Mm, PdfPage, PdfSaveOptions};
+
+    let mut doc = PdfDocument::new("dests");
+    for _ in 0..2 {
+        doc.pages
+            .push(PdfPage::new(Mm(210.0), Mm(297.0), Vec::new()));
+    }
+    doc.add_named_destination("appendix", OutlineNode::page("", 2).dest);
+    doc.add_named_destination("loop", Destination::Named("appendix".to_string()));
+    doc.add_outline(OutlineNode::new(
+        "Appendix",
+        Destination::Named("appendix".to_string()),
+    ));
+
+    let mut warnings = Vec::new();
+    let bytes = doc.save_with_warnings(&PdfSaveOptions::default(), &mut warnings);
+    assert!(warnings.iter().any(|w| w.msg.contains("\"loop\"")));
+
+    let parsed = PdfDocument::parse(&bytes, &Default::default()).unwrap();
+    assert_eq!(parsed.outline, doc.outline);
+    assert_eq!(parsed.named_destinations.len(), 1);
+    assert_eq!(
+        parsed.named_destinations.get("appendix"),
+        doc.named_destinations.get("appendix")
+    );
+}
+
+#[test]
+fn test_destination_types() {
+    use crate::{Mm, PdfPage, PdfSaveOptions};
+
+    let dests = vec![
+        Destination::Fit { page: 1 },
+        Destination::FitH {
+            page: 2,
+            top: Some(500.0),
+        },
+        Destination::FitV {
+            page: 1,
+            left: None,
+        },
+        Destination::FitR {
+            page: 2,
+            left: 10.0,
+            bottom: 20.0,
+            right: 300.0,
+            top: 400.5,
+        },
+        Destination::FitB { page: 1 },
+        Destination::FitBH { page: 2, top: None },
+        Destination::FitBV {
+            page: 1,
+            left: Some(72.0),
+        },
+    ];
+
+    let mut doc = PdfDocument::new("dests");
+    for _ in 0..2 {
+        doc.pages
+            .push(PdfPage::new(Mm(210.0), Mm(297.0), Vec::new()));
+    }
+    for (i, dest) in dests.iter().enumerate() {
+        doc.add_named_destination(&format!("dest{i}"), dest.clone());
+    }
+
+    let bytes = doc.save(&PdfSaveOptions::default());
+    let parsed = PdfDocument::parse(&bytes, &Default::default()).unwrap();
+    assert_eq!(parsed.named_destinations, doc.named_destinations);
+}
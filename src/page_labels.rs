@@ -0,0 +1,229 @@
+//! Page labels (`/PageLabels` number tree), the page numbers shown by the viewer,
+//! i.e. "i, ii, iii" for the front matter and "1, 2, 3" for the main part
+
+use lopdf::Dictionary as LoDictionary;
+use lopdf::Object::{Array, Dictionary, Integer, Name};
+
+use crate::forms::text_string;
+
+/// Numbering style of a page label range
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PageLabelStyle {
+    /// 1, 2, 3
+    Decimal,
+    /// I, II, III
+    UpperRoman,
+    /// i, ii, iii
+    LowerRoman,
+    /// A, B, C, ..., Z, AA, BB
+    UpperAlpha,
+    /// a, b, c, ..., z, aa, bb
+    LowerAlpha,
+}
+
+impl PageLabelStyle {
+    /// Returns the value of the `/S` key
+    pub fn get_id(&self) -> &'static str {
+        use self::PageLabelStyle::*;
+        match self {
+            Decimal => "D",
+            UpperRoman => "R",
+            LowerRoman => "r",
+            UpperAlpha => "A",
+            LowerAlpha => "a",
+        }
+    }
+
+    /// Formats the (1-based) number in this style
+    pub fn format(&self, number: usize) -> String {
+        use self::PageLabelStyle::*;
+        match self {
+            Decimal => number.to_string(),
+            UpperRoman => to_roman(number),
+            LowerRoman => to_roman(number).to_lowercase(),
+            UpperAlpha => to_alpha(number),
+            LowerAlpha => to_alpha(number).to_lowercase(),
+        }
+    }
+}
+
+fn to_roman(mut number: usize) -> String {
+    const NUMERALS: [(usize, &str); 13] = [
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+    let mut out = String::new();
+    for (value, numeral) in NUMERALS {
+        while number >= value {
+            out.push_str(numeral);
+            number -= value;
+        }
+    }
+    out
+}
+
+/// 1 = A, 26 = Z, 27 = AA, 53 = AAA (the letter is repeated, as specified by PDF)
+fn to_alpha(number: usize) -> String {
+    if number == 0 {
+        return String::new();
+    }
+    let letter = (b'A' + ((number - 1) % 26) as u8) as char;
+    letter.to_string().repeat((number - 1) / 26 + 1)
+}
+
+/// Labeling of the pages starting at `start_page`, until the next range starts
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageLabelRange {
+    /// First page of the range (0-based page index)
+    pub start_page: usize,
+    /// Numbering style, `None` = only the prefix is shown
+    pub style: Option<PageLabelStyle>,
+    /// Prefix of every label in the range, i.e. "A-" for "A-1, A-2"
+    pub prefix: Option<String>,
+    /// Number of the first page in the range (1 if the numbering restarts)
+    pub first_number: usize,
+}
+
+impl PageLabelRange {
+    /// Creates a new range, the numbering starts at 1
+    pub fn new(start_page: usize, style: Option<PageLabelStyle>) -> Self {
+        Self {
+            start_page,
+            style,
+            prefix: None,
+            first_number: 1,
+        }
+    }
+
+    /// Sets the prefix of the labels
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = Some(prefix.to_string());
+        self
+    }
+
+    /// Sets the number of the first page in the range
+    pub fn with_first_number(mut self, first_number: usize) -> Self {
+        self.first_number = first_number.max(1);
+        self
+    }
+}
+
+/// Page labels of the document, written to the `/PageLabels` number tree of the catalog
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PageLabels {
+    /// Label ranges, ranges with the same `start_page` replace each other
+    pub ranges: Vec<PageLabelRange>,
+}
+
+impl PageLabels {
+    /// Creates empty page labels (the viewer shows the page index)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a label range
+    pub fn with_range(mut self, range: PageLabelRange) -> Self {
+        self.ranges.retain(|r| r.start_page != range.start_page);
+        self.ranges.push(range);
+        self
+    }
+
+    /// Returns whether no label ranges are defined
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Returns the label of the page (0-based page index), as shown by the viewer
+    pub fn get_label(&self, page: usize) -> String {
+        let sorted = self.get_sorted_ranges();
+        let Some(range) = sorted.iter().rev().find(|r| r.start_page <= page) else {
+            return (page + 1).to_string();
+        };
+        let number = range.first_number + (page - range.start_page);
+        let mut label = range.prefix.clone().unwrap_or_default();
+        if let Some(style) = range.style {
+            label.push_str(&style.format(number));
+        }
+        label
+    }
+
+    /// Ranges sorted by the start page, the first page is always labeled
+    /// (with decimal numbers if no range starts at page 0)
+    pub(crate) fn get_sorted_ranges(&self) -> Vec<PageLabelRange> {
+        let mut sorted = self.ranges.clone();
+        sorted.sort_by_key(|r| r.start_page);
+        if sorted.first().map(|r| r.start_page) != Some(0) {
+            sorted.insert(0, PageLabelRange::new(0, Some(PageLabelStyle::Decimal)));
+        }
+        sorted
+    }
+
+    /// Creates the `/PageLabels` number tree (a single leaf node)
+    pub(crate) fn to_dict(&self) -> LoDictionary {
+        let mut nums = Vec::new();
+        for range in self.get_sorted_ranges() {
+            let mut dict = LoDictionary::from_iter(vec![("Type", Name("PageLabel".into()))]);
+            if let Some(style) = range.style {
+                dict.set("S", Name(style.get_id().into()));
+            }
+            if let Some(prefix) = range.prefix.as_ref() {
+                dict.set("P", text_string(prefix));
+            }
+            if range.first_number != 1 {
+                dict.set("St", Integer(range.first_number as i64));
+            }
+            nums.push(Integer(range.start_page as i64));
+            nums.push(Dictionary(dict));
+        }
+        LoDictionary::from_iter(vec![("Nums", Array(nums))])
+    }
+}
+
+#[test]
+fn test_page_labels() {
+    let labels = PageLabels::new()
+        .with_range(PageLabelRange::new(3, Some(PageLabelStyle::Decimal)))
+        .with_range(PageLabelRange::new(0, Some(PageLabelStyle::LowerRoman)))
+        .with_range(
+            PageLabelRange::new(10, Some(PageLabelStyle::UpperAlpha))
+                .with_prefix("Appendix ")
+                .with_first_number(27),
+        );
+
+    let shown = (0..12).map(|p| labels.get_label(p)).collect::<Vec<_>>();
+    assert_eq!(
+        shown,
+        vec![
+            "i",
+            "ii",
+            "iii",
+            "1",
+            "2",
+            "3",
+            "4",
+            "5",
+            "6",
+            "7",
+            "Appendix AA",
+            "Appendix BB"
+        ]
+    );
+    assert_eq!(to_roman(1994), "MCMXCIV");
+
+    let dict = labels.to_dict();
+    let nums = dict.get(b"Nums").unwrap().as_array().unwrap();
+    assert_eq!(nums.len(), 6);
+    assert_eq!(nums[0], Integer(0));
+    assert_eq!(nums[4], Integer(10));
+}
@@ -0,0 +1,516 @@
+//! Export of pages to Encapsulated PostScript (EPS) for print workflows that don't accept
+//! PDF: paths, glyph outlines and images are written as Level 2 PostScript
+
+use std::{borrow::Cow, collections::BTreeSet, fmt::Write as _, io::Write as _};
+
+use crate::{
+    color::Color,
+    font::{GlyphOutlineOperation, ParsedFont},
+    gradient::Gradient,
+    graphics::{BlendMode, Point, Polygon, TextRenderingMode, WindingOrder},
+    image::RawImage,
+    interpret::{GraphicsState, OpInterpreter, PositionedGlyph, RenderBackend},
+    matrix::CurTransMat,
+    rasterize::sample_pixel,
+    units::Pt,
+    warn::{PdfWarnCode, PdfWarnMsg},
+    PdfDocument,
+};
+
+impl PdfDocument {
+    /// Converts the vector content of the page (0-based index) into an EPS file.
+    ///
+    /// Text is written as glyph outlines, JPEG images are embedded as-is (`DCTDecode`),
+    /// all other images are Flate-compressed (which raises the language level to 3).
+    /// Transparency, blend modes, soft masks, gradients and form XObjects are not
+    /// supported by the exporter, a warning is added for every skipped feature.
+    pub fn export_page_eps(
+        &self,
+        page: usize,
+        warnings: &mut Vec<PdfWarnMsg>,
+    ) -> Result<String, String> {
+        let p = self
+            .pages
+            .get(page)
+            .ok_or_else(|| format!("export_page_eps: page {page} does not exist"))?;
+
+        // the lower left corner of the media box is the origin of the EPS
+        let media_box = p.media_box.normalize();
+        let origin = (Pt(-media_box.x.0), Pt(-media_box.y.0));
+        let device = CurTransMat::Translate(origin.0, origin.1).as_array();
+
+        let mut writer = PsWriter {
+            out: String::new(),
+            language_level: 2,
+            skipped: BTreeSet::new(),
+        };
+        OpInterpreter::new(self, device).render(&p.ops, &mut writer);
+
+        warnings.extend(writer.skipped.iter().map(|feature| {
+            PdfWarnMsg::warning(
+                Some(page),
+                format!("export_page_eps: {feature} not exported"),
+            )
+            .with_code(PdfWarnCode::UnsupportedExportFeature)
+        }));
+
+        let (width, height) = (media_box.width.0, media_box.height.0);
+        let mut eps = String::new();
+        eps.push_str("%!PS-Adobe-3.0 EPSF-3.0\n");
+        let _ = writeln!(
+            eps,
+            "%%BoundingBox: 0 0 {} {}",
+            width.ceil() as i64,
+            height.ceil() as i64
+        );
+        let _ = writeln!(
+            eps,
+            "%%HiResBoundingBox: 0 0 {} {}",
+            num(width),
+            num(height)
+        );
+        let title = &self.metadata.info.document_title;
+        if !title.is_empty() {
+            let _ = writeln!(eps, "%%Title: {}", title.replace(['\r', '\n'], " "));
+        }
+        eps.push_str("%%Creator: printpdf\n");
+        let _ = writeln!(eps, "%%LanguageLevel: {}", writer.language_level);
+        eps.push_str("%%EndComments\n");
+        eps.push_str("save\n");
+        eps.push_str(&writer.out);
+        eps.push_str("restore\nshowpage\n%%EOF\n");
+        Ok(eps)
+    }
+}
+
+/// Formats a number with at most 4 decimals
+pub(crate) fn num(v: f32) -> String {
+    let s = format!("{:.4}", v);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    match s {
+        "" | "-0" => "0".to_string(),
+        s => s.to_string(),
+    }
+}
+
+fn ps_matrix(m: &[f32; 6]) -> String {
+    let m = m.map(num);
+    format!("[{} {} {} {} {} {}]", m[0], m[1], m[2], m[3], m[4], m[5])
+}
+
+pub(crate) fn transform(m: &[f32; 6], (x, y): (f32, f32)) -> (f32, f32) {
+    (m[0] * x + m[2] * y + m[4], m[1] * x + m[3] * y + m[5])
+}
+
+/// Encodes the data as ASCII85, including the `~>` end marker
+fn ascii85_encode(data: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, chunk) in data.chunks(4).enumerate() {
+        let mut group = [0; 4];
+        group[..chunk.len()].copy_from_slice(chunk);
+        let mut value = u32::from_be_bytes(group);
+        if chunk.len() == 4 && value == 0 {
+            out.push('z');
+        } else {
+            let mut digits = [0u8; 5];
+            for d in digits.iter_mut().rev() {
+                *d = (value % 85) as u8 + b'!';
+                value /= 85;
+            }
+            // a partial group of n bytes is written as n + 1 digits
+            out.extend(digits[..chunk.len() + 1].iter().map(|d| *d as char));
+        }
+        if (i + 1) % 15 == 0 {
+            out.push('\n');
+        }
+    }
+    out.push_str("~>");
+    out
+}
+
+struct PsWriter {
+    out: String,
+    language_level: u8,
+    /// Features that were skipped, reported as warnings
+    skipped: BTreeSet<String>,
+}
+
+impl PsWriter {
+    /// Notes the parts of the graphics state that PostScript can't represent
+    fn check_state(&mut self, gs: &GraphicsState, alpha: f32) {
+        if alpha < 1.0 {
+            self.skipped.insert("transparency".to_string());
+        }
+        if gs.soft_mask.is_some() {
+            self.skipped.insert("soft mask".to_string());
+        }
+        if gs.blend_mode != BlendMode::normal() {
+            self.skipped.insert("blend mode".to_string());
+        }
+    }
+
+    fn set_color(&mut self, col: &Color) {
+        let out = &mut self.out;
+        let _ = match col {
+            Color::Rgb(c) => writeln!(out, "{} {} {} setrgbcolor", num(c.r), num(c.g), num(c.b)),
+            Color::Cmyk(c) => writeln!(
+                out,
+                "{} {} {} {} setcmykcolor",
+                num(c.c),
+                num(c.m),
+                num(c.y),
+                num(c.k)
+            ),
+            Color::SpotColor(c) => writeln!(
+                out,
+                "{} {} {} {} setcmykcolor",
+                num(c.c),
+                num(c.m),
+                num(c.y),
+                num(c.k)
+            ),
+            Color::Greyscale(g) => writeln!(out, "{} setgray", num(g.percent)),
+            Color::Indexed(_) | Color::DeviceN(_) => {
+                self.set_color(&col.get_device_color());
+                Ok(())
+            }
+        };
+    }
+
+    /// Writes the rings as a new path, transformed by `m`
+    fn write_path(&mut self, rings: &[Vec<(Point, bool)>], m: &[f32; 6], closed: bool) {
+        let out = &mut self.out;
+        out.push_str("newpath\n");
+        for ring in rings.iter().filter(|r| !r.is_empty()) {
+            let p = |i: usize| {
+                let (x, y) = transform(m, (ring[i].0.x.0, ring[i].0.y.0));
+                format!("{} {}", num(x), num(y))
+            };
+            let _ = writeln!(out, "{} moveto", p(0));
+            let mut i = 1;
+            while i < ring.len() {
+                // same convention as `Line`: two flagged points start a bezier curve
+                if ring[i - 1].1 && ring[i].1 && i + 2 < ring.len() {
+                    let _ = writeln!(out, "{} {} {} curveto", p(i), p(i + 1), p(i + 2));
+                    i += 3;
+                } else {
+                    let _ = writeln!(out, "{} lineto", p(i));
+                    i += 1;
+                }
+            }
+            if closed {
+                out.push_str("closepath\n");
+            }
+        }
+    }
+
+    /// Writes the image as a `DCTDecode` (JPEG sources) or `FlateDecode` RGB image
+    fn write_image(&mut self, image: &RawImage) -> Result<(), String> {
+        let (w, h) = (image.width, image.height);
+        let jpeg = image.source.as_ref().filter(|s| {
+            s.filters.len() == 1 && s.filters[0].name == "DCTDecode" && s.bits_per_component == 8
+        });
+
+        let (color_space, decode, filter, data) = match jpeg {
+            Some(source) => {
+                let components = match source.color_space.as_str() {
+                    "DeviceGray" => 1,
+                    "DeviceRGB" => 3,
+                    "DeviceCMYK" => 4,
+                    other => return Err(format!("unsupported JPEG color space {other}")),
+                };
+                let decode = source
+                    .decode
+                    .clone()
+                    .unwrap_or_else(|| (0..components).flat_map(|_| [0.0, 1.0]).collect());
+                let color_space = source.color_space.clone();
+                (color_space, decode, "DCTDecode", source.bytes.clone())
+            }
+            None => {
+                let pixels = image.pixels()?;
+                let format = match (&pixels, image.source.as_ref()) {
+                    (Cow::Owned(_), Some(source)) => source.get_decoded_format(),
+                    _ => image.data_format,
+                };
+                let mut rgb = Vec::with_capacity(w * h * 3);
+                let mut has_alpha = false;
+                for i in 0..w * h {
+                    let (color, alpha) = sample_pixel(&pixels, format, i)
+                        .ok_or_else(|| "image data too short".to_string())?;
+                    has_alpha |= alpha < 1.0;
+                    // PostScript images are opaque, composite onto white
+                    rgb.extend(color.map(|c| {
+                        ((c * alpha + 1.0 - alpha).clamp(0.0, 1.0) * 255.0).round() as u8
+                    }));
+                }
+                if has_alpha {
+                    self.skipped.insert("image transparency".to_string());
+                }
+                let mut encoder =
+                    flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(&rgb).map_err(|e| e.to_string())?;
+                let data = encoder.finish().map_err(|e| e.to_string())?;
+                // FlateDecode is a LanguageLevel 3 filter
+                self.language_level = 3;
+                let decode = vec![0.0, 1.0, 0.0, 1.0, 0.0, 1.0];
+                ("DeviceRGB".to_string(), decode, "FlateDecode", data)
+            }
+        };
+        let out = &mut self.out;
+        let decode = decode.iter().map(|d| num(*d)).collect::<Vec<_>>().join(" ");
+        let _ = writeln!(out, "/{color_space} setcolorspace");
+        let _ = writeln!(
+            out,
+            "<< /ImageType 1 /Width {w} /Height {h} /BitsPerComponent 8 /Decode [{decode}]"
+        );
+        // the first row of the image is the top edge of the unit square
+        let _ = writeln!(out, "/ImageMatrix [{w} 0 0 -{h} 0 {h}]");
+        let _ = writeln!(
+            out,
+            "/DataSource currentfile /ASCII85Decode filter /{filter} filter >> image"
+        );
+        out.push_str(&ascii85_encode(&data));
+        out.push('\n');
+        Ok(())
+    }
+}
+
+impl RenderBackend for PsWriter {
+    fn save_state(&mut self) {
+        self.out.push_str("gsave\n");
+    }
+
+    fn restore_state(&mut self) {
+        self.out.push_str("grestore\n");
+    }
+
+    fn fill_path(
+        &mut self,
+        gs: &GraphicsState,
+        rings: &[Vec<(Point, bool)>],
+        winding: WindingOrder,
+    ) {
+        self.check_state(gs, gs.fill_alpha);
+        self.set_color(&gs.fill_color);
+        self.write_path(rings, &gs.ctm, true);
+        self.out.push_str(match winding {
+            WindingOrder::EvenOdd => "eofill\n",
+            WindingOrder::NonZero => "fill\n",
+        });
+    }
+
+    fn stroke_path(&mut self, gs: &GraphicsState, rings: &[Vec<(Point, bool)>], closed: bool) {
+        self.check_state(gs, gs.stroke_alpha);
+        self.set_color(&gs.stroke_color);
+        // the path is written in user space, so that the line width and dashes are, too
+        let dash = gs
+            .dash
+            .iter()
+            .map(|d| num(*d))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let out = &mut self.out;
+        let _ = writeln!(out, "gsave {} concat", ps_matrix(&gs.ctm));
+        let _ = writeln!(
+            out,
+            "{} setlinewidth {} setlinecap {} setlinejoin {} setmiterlimit [{dash}] {} setdash",
+            num(gs.line_width),
+            gs.line_cap.id(),
+            gs.line_join.id(),
+            num(gs.miter_limit.max(1.0)),
+            num(gs.dash_offset),
+        );
+        self.write_path(rings, &CurTransMat::Identity.as_array(), closed);
+        self.out.push_str("stroke grestore\n");
+    }
+
+    fn push_clip(
+        &mut self,
+        gs: &GraphicsState,
+        rings: &[Vec<(Point, bool)>],
+        winding: WindingOrder,
+    ) {
+        self.write_path(rings, &gs.ctm, true);
+        self.out.push_str(match winding {
+            WindingOrder::EvenOdd => "eoclip newpath\n",
+            WindingOrder::NonZero => "clip newpath\n",
+        });
+    }
+
+    fn draw_glyph_run(
+        &mut self,
+        gs: &GraphicsState,
+        font: &ParsedFont,
+        glyphs: &[PositionedGlyph],
+    ) {
+        if matches!(
+            gs.text_mode,
+            TextRenderingMode::Invisible | TextRenderingMode::Clip
+        ) {
+            return;
+        }
+        self.check_state(gs, gs.fill_alpha);
+
+        // stroked text modes are approximated by filling the glyphs
+        let color = match gs.text_mode {
+            TextRenderingMode::Stroke | TextRenderingMode::StrokeClip => &gs.stroke_color,
+            _ => &gs.fill_color,
+        };
+        self.set_color(color);
+
+        let out = &mut self.out;
+        out.push_str("newpath\n");
+        for glyph in glyphs.iter() {
+            let Some(outline) = font.get_glyph_outline(glyph.glyph_id) else {
+                continue;
+            };
+            let m = &glyph.matrix;
+            let p = |x: f32, y: f32| {
+                let (x, y) = transform(m, (x, y));
+                format!("{} {}", num(x), num(y))
+            };
+            let mut last = (0.0, 0.0);
+            for op in outline.operations.iter() {
+                let _ = match op {
+                    GlyphOutlineOperation::MoveTo(o) => {
+                        last = (o.x, o.y);
+                        writeln!(out, "{} moveto", p(o.x, o.y))
+                    }
+                    GlyphOutlineOperation::LineTo(o) => {
+                        last = (o.x, o.y);
+                        writeln!(out, "{} lineto", p(o.x, o.y))
+                    }
+                    GlyphOutlineOperation::QuadraticCurveTo(q) => {
+                        // quadratic curves are exactly representable as cubic curves
+                        let (c, end) = ((q.ctrl_1_x, q.ctrl_1_y), (q.end_x, q.end_y));
+                        let c1 = (
+                            last.0 + (c.0 - last.0) * 2.0 / 3.0,
+                            last.1 + (c.1 - last.1) * 2.0 / 3.0,
+                        );
+                        let c2 = (
+                            end.0 + (c.0 - end.0) * 2.0 / 3.0,
+                            end.1 + (c.1 - end.1) * 2.0 / 3.0,
+                        );
+                        last = end;
+                        writeln!(
+                            out,
+                            "{} {} {} curveto",
+                            p(c1.0, c1.1),
+                            p(c2.0, c2.1),
+                            p(end.0, end.1)
+                        )
+                    }
+                    GlyphOutlineOperation::CubicCurveTo(c) => {
+                        last = (c.end_x, c.end_y);
+                        writeln!(
+                            out,
+                            "{} {} {} curveto",
+                            p(c.ctrl_1_x, c.ctrl_1_y),
+                            p(c.ctrl_2_x, c.ctrl_2_y),
+                            p(c.end_x, c.end_y)
+                        )
+                    }
+                    GlyphOutlineOperation::ClosePath => writeln!(out, "closepath"),
+                };
+            }
+        }
+        out.push_str("fill\n");
+    }
+
+    fn draw_image(&mut self, gs: &GraphicsState, image: &RawImage, matrix: [f32; 6]) {
+        if image.width == 0 || image.height == 0 {
+            return;
+        }
+        self.check_state(gs, gs.fill_alpha);
+        let len = self.out.len();
+        let _ = writeln!(self.out, "gsave {} concat", ps_matrix(&matrix));
+        match self.write_image(image) {
+            Ok(()) => self.out.push_str("grestore\n"),
+            Err(e) => {
+                self.out.truncate(len);
+                self.skipped.insert(format!("image ({e})"));
+            }
+        }
+    }
+
+    fn draw_gradient(
+        &mut self,
+        _gs: &GraphicsState,
+        _gradient: &Gradient,
+        _clip: &Polygon,
+        _matrix: [f32; 6],
+    ) {
+        // smooth shadings (shfill) require LanguageLevel 3
+        self.skipped.insert("gradient".to_string());
+    }
+
+    fn unsupported(&mut self, _gs: &GraphicsState, feature: &str) {
+        self.skipped.insert(feature.to_string());
+    }
+}
+
+#[test]
+fn test_export_page_eps() {
+    use crate::{
+        image::{RawImageData, RawImageFormat, StreamFilter},
+        Line, Mm, Op, PdfPage, Rgb, XObjectTransform,
+    };
+
+    assert_eq!(ascii85_encode(b"Hello"), "87cURDZ~>");
+    assert_eq!(ascii85_encode(&[0; 4]), "z~>");
+
+    let mut doc = PdfDocument::new("eps");
+    let image = RawImage {
+        pixels: RawImageData::U8(vec![255, 0, 0, 0, 0, 255]),
+        width: 2,
+        height: 1,
+        data_format: RawImageFormat::RGB8,
+        tag: Vec::new(),
+        source: None,
+    };
+    let image_id = doc.add_image(&image);
+    let line = Line {
+        points: vec![
+            (Point::new(Mm(0.0), Mm(0.0)), false),
+            (Point::new(Mm(10.0), Mm(10.0)), false),
+        ],
+        is_closed: false,
+    };
+    doc.pages.push(PdfPage::new(
+        Mm(100.0),
+        Mm(100.0),
+        vec![
+            Op::SetOutlineColor {
+                col: Color::Rgb(Rgb::new(1.0, 0.0, 0.0, None)),
+            },
+            Op::SetOutlineThickness { pt: Pt(2.0) },
+            Op::DrawLine { line },
+            Op::UseXObject {
+                id: image_id,
+                transform: XObjectTransform::default(),
+            },
+        ],
+    ));
+
+    let mut warnings = Vec::new();
+    let eps = doc.export_page_eps(0, &mut warnings).unwrap();
+    assert!(warnings.is_empty());
+    assert!(eps.starts_with("%!PS-Adobe-3.0 EPSF-3.0\n%%BoundingBox: 0 0 284 284\n"));
+    assert!(eps.contains("%%LanguageLevel: 3"));
+    assert!(eps.contains("1 0 0 setrgbcolor"));
+    assert!(eps.contains("2 setlinewidth 0 setlinecap"));
+    assert!(eps.contains("28.3465 28.3465 lineto"));
+    assert!(eps.ends_with("showpage\n%%EOF\n"));
+
+    // the image data can be decoded again
+    let start = eps.find("filter >> image\n").unwrap() + "filter >> image\n".len();
+    let end = eps[start..].find("~>").unwrap() + start + 2;
+    let filters = [
+        StreamFilter::new("ASCII85Decode"),
+        StreamFilter::new("FlateDecode"),
+    ];
+    let (pixels, _) = crate::filters::decode_filters(eps[start..end].as_bytes(), &filters).unwrap();
+    assert_eq!(pixels, vec![255, 0, 0, 0, 0, 255]);
+
+    assert!(doc.export_page_eps(1, &mut warnings).is_err());
+}
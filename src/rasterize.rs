@@ -0,0 +1,1143 @@
+//! Rasterization of pages ("flatten to image"): renders the page contents into an image,
+//! so that pages with content that can't be processed further (i.e. exotic inputs in
+//! merge pipelines) can still be written, at a reduced fidelity
+
+use std::{borrow::Cow, collections::BTreeSet, rc::Rc};
+
+use crate::{
+    color::{Color, Rgb},
+    font::{GlyphOutline, GlyphOutlineOperation, ParsedFont},
+    gradient::Gradient,
+    graphics::{
+        BlendMode, LineCapStyle, LineJoinStyle, Point, Polygon, TextRenderingMode, WindingOrder,
+    },
+    image::{RawImage, RawImageData, RawImageFormat},
+    interpret::{GraphicsState, OpInterpreter, PositionedGlyph, RenderBackend},
+    ops::Op,
+    units::Px,
+    warn::{PdfWarnCode, PdfWarnMsg},
+    xobject::XObjectTransform,
+    PdfDocument,
+};
+
+/// Vertical samples per pixel, used for anti-aliasing the edges of shapes and glyphs
+const SAMPLES: usize = 4;
+
+/// Options for `PdfDocument::rasterize_page` and `PdfDocument::flatten_pages`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RasterizeOptions {
+    /// Resolution of the rendered image (default: 150 DPI)
+    pub dpi: f32,
+    /// Color of the page background (default: white)
+    pub background: Color,
+}
+
+impl Default for RasterizeOptions {
+    fn default() -> Self {
+        Self {
+            dpi: 150.0,
+            background: Color::Rgb(Rgb::new(1.0, 1.0, 1.0, None)),
+        }
+    }
+}
+
+impl RasterizeOptions {
+    /// Renders at the given resolution on a white background
+    pub fn new(dpi: f32) -> Self {
+        Self {
+            dpi,
+            ..Default::default()
+        }
+    }
+
+    /// Sets the color of the page background
+    pub fn with_background(mut self, background: Color) -> Self {
+        self.background = background;
+        self
+    }
+}
+
+impl PdfDocument {
+    /// Renders the media box of the page (0-based index) into an RGB image.
+    ///
+    /// NOTE: This is a simple renderer for vector shapes, text (embedded and builtin fonts)
+    /// and image XObjects. Form and external XObjects, blend modes, soft masks and unknown
+    /// operations are not rendered, a warning is added for every skipped feature.
+    pub fn rasterize_page(
+        &self,
+        page: usize,
+        opts: &RasterizeOptions,
+        warnings: &mut Vec<PdfWarnMsg>,
+    ) -> Result<RawImage, String> {
+        let p = self
+            .pages
+            .get(page)
+            .ok_or_else(|| format!("rasterize: page {page} does not exist"))?;
+        if opts.dpi.is_nan() || opts.dpi <= 0.0 {
+            return Err(format!("rasterize: invalid resolution {} DPI", opts.dpi));
+        }
+
+        let scale = opts.dpi / 72.0;
+        let media_box = &p.media_box;
+        let width = (media_box.width.0.abs() * scale).ceil().max(1.0) as usize;
+        let height = (media_box.height.0.abs() * scale).ceil().max(1.0) as usize;
+        if width.saturating_mul(height) > 100_000_000 {
+            return Err(format!(
+                "rasterize: page {page} is too large ({width} x {height} pixels)"
+            ));
+        }
+
+        // page space -> pixels (y grows downwards)
+        let device = [
+            scale,
+            0.0,
+            0.0,
+            -scale,
+            -media_box.x.0 * scale,
+            (media_box.y.0 + media_box.height.0) * scale,
+        ];
+
+        let mut renderer = Renderer {
+            doc: self,
+            canvas: Canvas {
+                width,
+                height,
+                pixels: vec![color_to_rgb(&opts.background); width * height],
+            },
+            clip: None,
+            clip_stack: Vec::new(),
+            skipped: BTreeSet::new(),
+        };
+        renderer.render(&p.ops, device);
+
+        warnings.extend(renderer.skipped.into_iter().map(|feature| {
+            PdfWarnMsg::warning(Some(page), format!("rasterize: {feature} not rendered"))
+                .with_code(PdfWarnCode::UnsupportedExportFeature)
+        }));
+
+        Ok(renderer.canvas.into_image())
+    }
+
+    /// Replaces the contents of the pages (0-based indices) with an image of the rendered
+    /// page ("flatten to image"). Links, form fields and annotations are kept, everything
+    /// else is only preserved as pixels - use this for pages with content that can't be
+    /// processed otherwise, so that the document can always be written.
+    pub fn flatten_pages(
+        &mut self,
+        pages: &[usize],
+        opts: &RasterizeOptions,
+        warnings: &mut Vec<PdfWarnMsg>,
+    ) -> Result<(), String> {
+        for &page in pages {
+            let image = self.rasterize_page(page, opts, warnings)?;
+            let (width, height) = (image.width, image.height);
+            let id = self.add_image(&image);
+
+            let p = &mut self.pages[page];
+            let media_box = p.media_box.clone();
+            let kept = p.ops.drain(..).filter(|op| {
+                matches!(
+                    op,
+                    Op::LinkAnnotation { .. } | Op::AddFormField { .. } | Op::AddAnnotation { .. }
+                )
+            });
+
+            // the image is rounded up to whole pixels, scale it back to the page size
+            let transform = XObjectTransform {
+                translate_x: Some(media_box.x),
+                translate_y: Some(media_box.y),
+                scale_x: Some(media_box.width.0 / Px(width).into_pt(opts.dpi).0),
+                scale_y: Some(media_box.height.0 / Px(height).into_pt(opts.dpi).0),
+                dpi: Some(opts.dpi),
+                ..Default::default()
+            };
+            let mut ops = vec![
+                Op::SaveGraphicsState,
+                Op::UseXObject { id, transform },
+                Op::RestoreGraphicsState,
+            ];
+            ops.extend(kept);
+            p.ops = ops;
+        }
+        Ok(())
+    }
+}
+
+/// RGB pixels (0.0 - 1.0)
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<[f32; 3]>,
+}
+
+impl Canvas {
+    /// Blends the color over the pixels covered by the shape
+    fn paint(&mut self, shape: &Coverage, color: [f32; 3], alpha: f32, clip: Option<&Coverage>) {
+        for row in 0..shape.height {
+            for col in 0..shape.width {
+                let (x, y) = (shape.x + col, shape.y + row);
+                let mut a = shape.data[row * shape.width + col].min(1.0) * alpha;
+                if let Some(clip) = clip {
+                    a *= clip.get(x, y);
+                }
+                if a <= 0.0 {
+                    continue;
+                }
+                let px = &mut self.pixels[y * self.width + x];
+                for (p, c) in px.iter_mut().zip(color) {
+                    *p += (c - *p) * a;
+                }
+            }
+        }
+    }
+
+    fn into_image(self) -> RawImage {
+        let pixels = self
+            .pixels
+            .iter()
+            .flat_map(|px| px.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8))
+            .collect();
+        RawImage {
+            pixels: RawImageData::U8(pixels),
+            width: self.width,
+            height: self.height,
+            data_format: RawImageFormat::RGB8,
+            tag: Vec::new(),
+            source: None,
+        }
+    }
+}
+
+/// Coverage of a shape (0.0 - 1.0) over a rectangle of pixels
+#[derive(Debug, Clone)]
+struct Coverage {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    data: Vec<f32>,
+}
+
+impl Coverage {
+    fn get(&self, x: usize, y: usize) -> f32 {
+        if x < self.x || y < self.y || x >= self.x + self.width || y >= self.y + self.height {
+            return 0.0;
+        }
+        self.data[(y - self.y) * self.width + (x - self.x)].min(1.0)
+    }
+
+    /// Restricts the coverage to the area of another shape (for nested clipping paths)
+    fn intersect(mut self, other: &Coverage) -> Self {
+        for row in 0..self.height {
+            for col in 0..self.width {
+                self.data[row * self.width + col] = self.data[row * self.width + col].min(1.0)
+                    * other.get(self.x + col, self.y + row);
+            }
+        }
+        self
+    }
+}
+
+/// Scanline rasterization of closed polygons (in pixel coordinates)
+fn rasterize(
+    polygons: &[Vec<(f32, f32)>],
+    even_odd: bool,
+    width: usize,
+    height: usize,
+) -> Option<Coverage> {
+    let mut edges = Vec::new();
+    let (mut min_x, mut min_y) = (f32::MAX, f32::MAX);
+    let (mut max_x, mut max_y) = (f32::MIN, f32::MIN);
+    for polygon in polygons {
+        for (i, a) in polygon.iter().enumerate() {
+            let b = polygon[(i + 1) % polygon.len()];
+            if !(a.0.is_finite() && a.1.is_finite() && b.0.is_finite() && b.1.is_finite()) {
+                continue;
+            }
+            min_x = min_x.min(a.0);
+            max_x = max_x.max(a.0);
+            min_y = min_y.min(a.1);
+            max_y = max_y.max(a.1);
+            if a.1 != b.1 {
+                edges.push((*a, b));
+            }
+        }
+    }
+    if edges.is_empty() {
+        return None;
+    }
+
+    let x0 = min_x.floor().max(0.0) as usize;
+    let x1 = (max_x.ceil().max(0.0) as usize).min(width);
+    let y0 = min_y.floor().max(0.0) as usize;
+    let y1 = (max_y.ceil().max(0.0) as usize).min(height);
+    if x0 >= x1 || y0 >= y1 {
+        return None;
+    }
+
+    let w = x1 - x0;
+    let mut data = vec![0.0; w * (y1 - y0)];
+    let weight = 1.0 / SAMPLES as f32;
+    let mut crossings = Vec::new();
+
+    for (row, line) in data.chunks_exact_mut(w).enumerate() {
+        for s in 0..SAMPLES {
+            let sy = (y0 + row) as f32 + (s as f32 + 0.5) * weight;
+            crossings.clear();
+            for &((ax, ay), (bx, by)) in edges.iter() {
+                let (top, bottom, dir) = if ay < by { (ay, by, 1) } else { (by, ay, -1) };
+                if sy < top || sy >= bottom {
+                    continue;
+                }
+                let t = (sy - ay) / (by - ay);
+                crossings.push((ax + t * (bx - ax) - x0 as f32, dir));
+            }
+            crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            let mut winding = 0;
+            for pair in crossings.windows(2) {
+                winding += pair[0].1;
+                let inside = if even_odd {
+                    winding % 2 != 0
+                } else {
+                    winding != 0
+                };
+                if inside {
+                    add_span(line, pair[0].0, pair[1].0, weight);
+                }
+            }
+        }
+    }
+
+    Some(Coverage {
+        x: x0,
+        y: y0,
+        width: w,
+        height: y1 - y0,
+        data,
+    })
+}
+
+/// Adds the horizontal span from `a` to `b` (in pixels) to the coverage of a row
+fn add_span(row: &mut [f32], a: f32, b: f32, weight: f32) {
+    let (a, b) = (a.max(0.0), b.min(row.len() as f32));
+    if a >= b {
+        return;
+    }
+    let (ia, ib) = (a as usize, b as usize);
+    if ia == ib {
+        row[ia] += (b - a) * weight;
+        return;
+    }
+    row[ia] += (ia as f32 + 1.0 - a) * weight;
+    for px in row[ia + 1..ib].iter_mut() {
+        *px += weight;
+    }
+    if ib < row.len() {
+        row[ib] += (b - ib as f32) * weight;
+    }
+}
+
+fn transform(m: &[f32; 6], (x, y): (f32, f32)) -> (f32, f32) {
+    (m[0] * x + m[2] * y + m[4], m[1] * x + m[3] * y + m[5])
+}
+
+fn invert(m: &[f32; 6]) -> Option<[f32; 6]> {
+    let det = m[0] * m[3] - m[1] * m[2];
+    if det.abs() < f32::EPSILON {
+        return None;
+    }
+    let (a, b, c, d) = (m[3] / det, -m[1] / det, -m[2] / det, m[0] / det);
+    Some([a, b, c, d, -(m[4] * a + m[5] * c), -(m[4] * b + m[5] * d)])
+}
+
+/// Approximate scale factor of the matrix (pixels per unit)
+fn get_scale(m: &[f32; 6]) -> f32 {
+    (m[0] * m[3] - m[1] * m[2]).abs().sqrt()
+}
+
+/// Appends the cubic bezier curve (without the start point) as line segments
+fn push_cubic(out: &mut Vec<(f32, f32)>, p: [(f32, f32); 4], px_per_unit: f32) {
+    let dist = |a: (f32, f32), b: (f32, f32)| ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt();
+    let length = (dist(p[0], p[1]) + dist(p[1], p[2]) + dist(p[2], p[3])) * px_per_unit;
+    let steps = (length / 2.0).ceil().clamp(1.0, 64.0) as usize;
+    for i in 1..=steps {
+        let t = i as f32 / steps as f32;
+        let u = 1.0 - t;
+        let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+        out.push((
+            a * p[0].0 + b * p[1].0 + c * p[2].0 + d * p[3].0,
+            a * p[0].1 + b * p[1].1 + c * p[2].1 + d * p[3].1,
+        ));
+    }
+}
+
+/// Converts the points of a path (the `bool` marks that the next point is a bezier
+/// control point, see `Line`) into a polyline
+fn flatten_points(points: &[(Point, bool)], px_per_unit: f32) -> Vec<(f32, f32)> {
+    let xy = |p: &Point| (p.x.0, p.y.0);
+    let mut out = Vec::new();
+    let Some(first) = points.first() else {
+        return out;
+    };
+    out.push(xy(&first.0));
+
+    let mut i = 1;
+    while i < points.len() {
+        let (p1, p2) = (&points[i - 1], &points[i]);
+        if p1.1 && p2.1 && i + 2 < points.len() {
+            let curve = [&p1.0, &p2.0, &points[i + 1].0, &points[i + 2].0].map(xy);
+            push_cubic(&mut out, curve, px_per_unit);
+            i += 3;
+        } else {
+            out.push(xy(&p2.0));
+            i += 1;
+        }
+    }
+    out
+}
+
+/// Converts a glyph outline (in font units) into closed polylines
+fn flatten_outline(outline: &GlyphOutline, px_per_unit: f32) -> Vec<Vec<(f32, f32)>> {
+    let mut contours = Vec::new();
+    let mut current: Vec<(f32, f32)> = Vec::new();
+    for op in outline.operations.iter() {
+        let last = current.last().copied().unwrap_or((0.0, 0.0));
+        match op {
+            GlyphOutlineOperation::MoveTo(m) => {
+                if current.len() > 2 {
+                    contours.push(std::mem::take(&mut current));
+                }
+                current = vec![(m.x, m.y)];
+            }
+            GlyphOutlineOperation::LineTo(l) => current.push((l.x, l.y)),
+            GlyphOutlineOperation::QuadraticCurveTo(q) => {
+                // quadratic curves are exactly representable as cubic curves
+                let (c, end) = ((q.ctrl_1_x, q.ctrl_1_y), (q.end_x, q.end_y));
+                let c1 = (
+                    last.0 + (c.0 - last.0) * 2.0 / 3.0,
+                    last.1 + (c.1 - last.1) * 2.0 / 3.0,
+                );
+                let c2 = (
+                    end.0 + (c.0 - end.0) * 2.0 / 3.0,
+                    end.1 + (c.1 - end.1) * 2.0 / 3.0,
+                );
+                push_cubic(&mut current, [last, c1, c2, end], px_per_unit);
+            }
+            GlyphOutlineOperation::CubicCurveTo(c) => {
+                let curve = [
+                    last,
+                    (c.ctrl_1_x, c.ctrl_1_y),
+                    (c.ctrl_2_x, c.ctrl_2_y),
+                    (c.end_x, c.end_y),
+                ];
+                push_cubic(&mut current, curve, px_per_unit);
+            }
+            GlyphOutlineOperation::ClosePath => {
+                if current.len() > 2 {
+                    contours.push(std::mem::take(&mut current));
+                }
+                current.clear();
+            }
+        }
+    }
+    if current.len() > 2 {
+        contours.push(current);
+    }
+    contours
+}
+
+/// Splits a polyline into the dashes of a dash pattern (lengths of dashes and gaps)
+fn dash_polyline(points: &[(f32, f32)], pattern: &[f32], offset: f32) -> Vec<Vec<(f32, f32)>> {
+    let total = pattern.iter().sum::<f32>();
+    if points.len() < 2 || pattern.is_empty() || total.is_nan() || total <= 0.0 {
+        return vec![points.to_vec()];
+    }
+
+    let mut idx = 0;
+    let mut remaining = pattern[0];
+    let mut on = true;
+    let mut skip = offset.rem_euclid(total);
+    while skip > 0.0 {
+        if skip >= remaining {
+            skip -= remaining;
+            idx = (idx + 1) % pattern.len();
+            remaining = pattern[idx];
+            on = !on;
+        } else {
+            remaining -= skip;
+            skip = 0.0;
+        }
+    }
+
+    let mut dashes = Vec::new();
+    let mut current = if on { vec![points[0]] } else { Vec::new() };
+    for segment in points.windows(2) {
+        let (a, b) = (segment[0], segment[1]);
+        let length = ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt();
+        let mut pos = 0.0;
+        while length - pos > remaining {
+            pos += remaining;
+            let t = pos / length;
+            let p = (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+            if on {
+                current.push(p);
+                dashes.push(std::mem::take(&mut current));
+            } else {
+                current = vec![p];
+            }
+            on = !on;
+            idx = (idx + 1) % pattern.len();
+            remaining = pattern[idx];
+        }
+        remaining -= length - pos;
+        if on {
+            current.push(b);
+        }
+    }
+    if on && current.len() > 1 {
+        dashes.push(current);
+    }
+    dashes
+}
+
+/// Line cap, line join and miter limit of a stroke
+#[derive(Debug, Copy, Clone)]
+struct StrokeStyle {
+    half_width: f32,
+    cap: LineCapStyle,
+    join: LineJoinStyle,
+    miter_limit: f32,
+}
+
+/// Outline of a stroked polyline: one rectangle per segment plus the joins and caps
+/// (all polygons have the same orientation, so that they can be filled non-zero)
+fn stroke_polygons(
+    points: &[(f32, f32)],
+    closed: bool,
+    style: StrokeStyle,
+) -> Vec<Vec<(f32, f32)>> {
+    let hw = style.half_width;
+    let mut points = points.to_vec();
+    points.dedup();
+    if closed && points.len() > 2 && points.first() != points.last() {
+        points.push(points[0]);
+    }
+
+    let circle = |p: (f32, f32)| {
+        (0..12)
+            .map(|i| {
+                let angle = -(i as f32) * std::f32::consts::TAU / 12.0;
+                (p.0 + angle.cos() * hw, p.1 + angle.sin() * hw)
+            })
+            .collect::<Vec<_>>()
+    };
+    // unit direction of each segment
+    let directions = points
+        .windows(2)
+        .map(|s| {
+            let (dx, dy) = (s[1].0 - s[0].0, s[1].1 - s[0].1);
+            let length = (dx * dx + dy * dy).sqrt();
+            (dx / length, dy / length)
+        })
+        .collect::<Vec<_>>();
+
+    let mut polygons = Vec::new();
+    if directions.is_empty() {
+        // zero-length subpath: only round caps produce a dot
+        if let (Some(p), LineCapStyle::Round) = (points.first(), style.cap) {
+            polygons.push(circle(*p));
+        }
+        return polygons;
+    }
+
+    for (segment, (ux, uy)) in points.windows(2).zip(directions.iter()) {
+        let (a, b) = (segment[0], segment[1]);
+        let (nx, ny) = (-uy * hw, ux * hw);
+        polygons.push(vec![
+            (a.0 + nx, a.1 + ny),
+            (b.0 + nx, b.1 + ny),
+            (b.0 - nx, b.1 - ny),
+            (a.0 - nx, a.1 - ny),
+        ]);
+    }
+
+    // joins between consecutive segments (including the closing one)
+    let mut joins = directions
+        .windows(2)
+        .zip(points.iter().skip(1))
+        .map(|(d, p)| (*p, d[0], d[1]))
+        .collect::<Vec<_>>();
+    if closed && directions.len() > 1 {
+        joins.push((points[0], directions[directions.len() - 1], directions[0]));
+    }
+    for (p, (ux1, uy1), (ux2, uy2)) in joins {
+        let cross = ux1 * uy2 - uy1 * ux2;
+        if cross.abs() < 1e-6 && ux1 * ux2 + uy1 * uy2 > 0.0 {
+            continue; // straight continuation
+        }
+        if style.join == LineJoinStyle::Round {
+            polygons.push(circle(p));
+            continue;
+        }
+        // normals on the outer side of the turn
+        let side = if cross > 0.0 { -hw } else { hw };
+        let o1 = (-uy1 * side, ux1 * side);
+        let o2 = (-uy2 * side, ux2 * side);
+        let mut join = vec![p, (p.0 + o1.0, p.1 + o1.1)];
+        // cosine of the angle between the normals, the miter length is 1 / sin(phi / 2)
+        let c = (o1.0 * o2.0 + o1.1 * o2.1) / (hw * hw);
+        let miter_ratio = (2.0 / (1.0 + c).max(f32::EPSILON)).sqrt();
+        if style.join == LineJoinStyle::Miter && miter_ratio <= style.miter_limit {
+            join.push((
+                p.0 + (o1.0 + o2.0) / (1.0 + c),
+                p.1 + (o1.1 + o2.1) / (1.0 + c),
+            ));
+        }
+        join.push((p.0 + o2.0, p.1 + o2.1));
+        polygons.push(join);
+    }
+
+    if !closed {
+        let ends = [
+            (points[0], directions[0], -1.0),
+            (
+                points[points.len() - 1],
+                directions[directions.len() - 1],
+                1.0,
+            ),
+        ];
+        for (p, (ux, uy), sign) in ends {
+            match style.cap {
+                LineCapStyle::Butt => {}
+                LineCapStyle::Round => polygons.push(circle(p)),
+                LineCapStyle::ProjectingSquare => {
+                    let (nx, ny) = (-uy * hw, ux * hw);
+                    let (ex, ey) = (ux * hw * sign, uy * hw * sign);
+                    polygons.push(vec![
+                        (p.0 + nx, p.1 + ny),
+                        (p.0 + nx + ex, p.1 + ny + ey),
+                        (p.0 - nx + ex, p.1 - ny + ey),
+                        (p.0 - nx, p.1 - ny),
+                    ]);
+                }
+            }
+        }
+    }
+
+    // same (clockwise) orientation as the segment rectangles
+    for polygon in polygons.iter_mut() {
+        let area = polygon
+            .iter()
+            .zip(polygon.iter().cycle().skip(1))
+            .map(|(a, b)| a.0 * b.1 - b.0 * a.1)
+            .sum::<f32>();
+        if area > 0.0 {
+            polygon.reverse();
+        }
+    }
+    polygons
+}
+
+/// Position (0.0 - 1.0) of the point (in the coordinate space of the gradient) along the
+/// gradient, `None` if the point is outside of the gradient and the colors are not extended
+fn get_gradient_position(gradient: &Gradient, p: (f32, f32)) -> Option<f32> {
+    let (t, extend_start, extend_end) = match gradient {
+        Gradient::Linear(g) => {
+            let d = (g.end.x.0 - g.start.x.0, g.end.y.0 - g.start.y.0);
+            let length = d.0 * d.0 + d.1 * d.1;
+            if length <= 0.0 {
+                return None;
+            }
+            let t = ((p.0 - g.start.x.0) * d.0 + (p.1 - g.start.y.0) * d.1) / length;
+            (t, g.extend_start, g.extend_end)
+        }
+        Gradient::Radial(g) => {
+            // largest t for which the point lies on the circle interpolated between
+            // the start and end circle: |p - c(t)| = r(t)
+            let (r0, dr) = (g.start_radius.0, g.end_radius.0 - g.start_radius.0);
+            let cd = (
+                g.end_center.x.0 - g.start_center.x.0,
+                g.end_center.y.0 - g.start_center.y.0,
+            );
+            let pd = (p.0 - g.start_center.x.0, p.1 - g.start_center.y.0);
+            let a = cd.0 * cd.0 + cd.1 * cd.1 - dr * dr;
+            let b = pd.0 * cd.0 + pd.1 * cd.1 + r0 * dr;
+            let c = pd.0 * pd.0 + pd.1 * pd.1 - r0 * r0;
+            let t = if a.abs() < f32::EPSILON {
+                if b == 0.0 {
+                    return None;
+                }
+                c / (2.0 * b)
+            } else {
+                let discriminant = b * b - a * c;
+                if discriminant < 0.0 {
+                    return None;
+                }
+                let (t1, t2) = ((b + discriminant.sqrt()) / a, (b - discriminant.sqrt()) / a);
+                let radius_ok = |t: f32| r0 + t * dr >= 0.0;
+                match (radius_ok(t1.max(t2)), radius_ok(t1.min(t2))) {
+                    (true, _) => t1.max(t2),
+                    (false, true) => t1.min(t2),
+                    _ => return None,
+                }
+            };
+            (t, g.extend_start, g.extend_end)
+        }
+    };
+    if (t < 0.0 && !extend_start) || (t > 1.0 && !extend_end) {
+        return None;
+    }
+    Some(t.clamp(0.0, 1.0))
+}
+
+/// Converts a color to RGB (0.0 - 1.0) without color management
+pub(crate) fn color_to_rgb(col: &Color) -> [f32; 3] {
+    let cmyk =
+        |c: f32, m: f32, y: f32, k: f32| [c, m, y].map(|v| ((1.0 - v) * (1.0 - k)).clamp(0.0, 1.0));
+    match col {
+        Color::Rgb(c) => [c.r, c.g, c.b],
+        Color::Cmyk(c) => cmyk(c.c, c.m, c.y, c.k),
+        Color::SpotColor(c) => cmyk(c.c, c.m, c.y, c.k),
+        Color::Greyscale(g) => [g.percent; 3],
+        Color::Indexed(_) | Color::DeviceN(_) => color_to_rgb(&col.get_device_color()),
+    }
+}
+
+/// Returns the color (RGB) and alpha of the pixel at the given index
+pub(crate) fn sample_pixel(
+    data: &RawImageData,
+    format: RawImageFormat,
+    index: usize,
+) -> Option<([f32; 3], f32)> {
+    use self::RawImageFormat::*;
+    let components = match format {
+        R8 | R16 => 1,
+        RG8 | RG16 => 2,
+        RGB8 | RGB16 | BGR8 | RGBF32 => 3,
+        RGBA8 | RGBA16 | BGRA8 | RGBAF32 => 4,
+    };
+    let get = |i: usize| -> Option<f32> {
+        let i = index * components + i;
+        match data {
+            RawImageData::U8(v) => v.get(i).map(|v| *v as f32 / 255.0),
+            RawImageData::U16(v) => v.get(i).map(|v| *v as f32 / 65535.0),
+            RawImageData::F32(v) => v.get(i).copied(),
+        }
+    };
+    Some(match format {
+        R8 | R16 => ([get(0)?; 3], 1.0),
+        RG8 | RG16 => ([get(0)?; 3], get(1)?),
+        RGB8 | RGB16 | RGBF32 => ([get(0)?, get(1)?, get(2)?], 1.0),
+        RGBA8 | RGBA16 | RGBAF32 => ([get(0)?, get(1)?, get(2)?], get(3)?),
+        BGR8 => ([get(2)?, get(1)?, get(0)?], 1.0),
+        BGRA8 => ([get(2)?, get(1)?, get(0)?], get(3)?),
+    })
+}
+
+/// Flattens the rings of a path (in user space) into closed polygons in pixels
+fn to_device_rings(rings: &[Vec<(Point, bool)>], gs: &GraphicsState) -> Vec<Vec<(f32, f32)>> {
+    rings
+        .iter()
+        .map(|ring| flatten_points(ring, get_scale(&gs.ctm)))
+        .filter(|ring| ring.len() > 1)
+        .map(|ring| ring.into_iter().map(|p| transform(&gs.ctm, p)).collect())
+        .collect()
+}
+
+struct Renderer<'a> {
+    doc: &'a PdfDocument,
+    canvas: Canvas,
+    /// Clipping path of the current graphics state, saved and restored with it
+    clip: Option<Rc<Coverage>>,
+    clip_stack: Vec<Option<Rc<Coverage>>>,
+    /// Features that were skipped, reported as warnings
+    skipped: BTreeSet<String>,
+}
+
+impl Renderer<'_> {
+    fn render(&mut self, ops: &[Op], device: [f32; 6]) {
+        let doc = self.doc;
+        OpInterpreter::new(doc, device).render(ops, self);
+    }
+
+    /// Notes the parts of the graphics state that are ignored when painting
+    fn check_state(&mut self, gs: &GraphicsState) {
+        if gs.soft_mask.is_some() {
+            self.skipped.insert("soft mask".to_string());
+        }
+        if gs.blend_mode != BlendMode::normal() {
+            self.skipped.insert("blend mode".to_string());
+        }
+    }
+
+    /// Draws the gradient inside the clip polygon, `m` maps the gradient space to pixels
+    fn paint_gradient(
+        &mut self,
+        clip: &Polygon,
+        gradient: &Gradient,
+        m: &[f32; 6],
+        gs: &GraphicsState,
+    ) {
+        let device = to_device_rings(&clip.rings, gs);
+        let even_odd = clip.winding_order == WindingOrder::EvenOdd;
+        let (w, h) = (self.canvas.width, self.canvas.height);
+        let Some(shape) = rasterize(&device, even_odd, w, h) else {
+            return;
+        };
+        let Some(inverse) = invert(m) else {
+            return;
+        };
+
+        // sample the gradient at the center of each covered pixel
+        for row in 0..shape.height {
+            for col in 0..shape.width {
+                let (x, y) = (shape.x + col, shape.y + row);
+                let mut a = shape.data[row * shape.width + col].min(1.0) * gs.fill_alpha;
+                if let Some(clip) = self.clip.as_ref() {
+                    a *= clip.get(x, y);
+                }
+                if a <= 0.0 {
+                    continue;
+                }
+                let p = transform(&inverse, (x as f32 + 0.5, y as f32 + 0.5));
+                let Some(t) = get_gradient_position(gradient, p) else {
+                    continue;
+                };
+                let color = match gradient.get_color_at(t).as_slice() {
+                    [g] => [*g; 3],
+                    [r, g, b] => [*r, *g, *b],
+                    [c, m, y, k] => [*c, *m, *y].map(|v| (1.0 - v) * (1.0 - k)),
+                    _ => continue,
+                };
+                let px = &mut self.canvas.pixels[y * self.canvas.width + x];
+                for (p, c) in px.iter_mut().zip(color) {
+                    *p += (c - *p) * a;
+                }
+            }
+        }
+    }
+
+    /// Fills polygons given in pixel coordinates
+    fn fill(&mut self, polygons: &[Vec<(f32, f32)>], even_odd: bool, color: [f32; 3], alpha: f32) {
+        let (w, h) = (self.canvas.width, self.canvas.height);
+        if let Some(shape) = rasterize(polygons, even_odd, w, h) {
+            self.canvas
+                .paint(&shape, color, alpha, self.clip.as_deref());
+        }
+    }
+
+    /// Strokes a polyline given in user space (the line width is in user space, too)
+    fn stroke(&mut self, points: &[(f32, f32)], closed: bool, gs: &GraphicsState) {
+        // zero-width lines are drawn as thin as possible (one pixel)
+        let min_width = 1.0 / get_scale(&gs.ctm).max(f32::EPSILON);
+        let style = StrokeStyle {
+            half_width: gs.line_width.max(min_width) / 2.0,
+            cap: gs.line_cap,
+            join: gs.line_join,
+            miter_limit: gs.miter_limit,
+        };
+
+        let mut points = points.to_vec();
+        let mut closed = closed;
+        if !gs.dash.is_empty() && closed && points.len() > 2 {
+            points.push(points[0]);
+            closed = false;
+        }
+
+        let polygons = dash_polyline(&points, &gs.dash, gs.dash_offset)
+            .iter()
+            .flat_map(|dash| stroke_polygons(dash, closed, style))
+            .map(|p| p.into_iter().map(|p| transform(&gs.ctm, p)).collect())
+            .collect::<Vec<_>>();
+        let color = color_to_rgb(&gs.stroke_color);
+        self.fill(&polygons, false, color, gs.stroke_alpha);
+    }
+
+    /// Draws the outlines of positioned glyphs
+    fn paint_glyphs(&mut self, font: &ParsedFont, glyphs: &[PositionedGlyph], gs: &GraphicsState) {
+        if matches!(
+            gs.text_mode,
+            TextRenderingMode::Invisible | TextRenderingMode::Clip
+        ) {
+            return;
+        }
+
+        let mut polygons = Vec::new();
+        for glyph in glyphs.iter() {
+            let m = &glyph.matrix;
+            if let Some(outline) = font.get_glyph_outline(glyph.glyph_id) {
+                polygons.extend(
+                    flatten_outline(&outline, get_scale(m))
+                        .into_iter()
+                        .map(|c| c.into_iter().map(|p| transform(m, p)).collect()),
+                );
+            }
+        }
+
+        // stroked text modes are approximated by filling the glyphs
+        let color = match gs.text_mode {
+            TextRenderingMode::Stroke | TextRenderingMode::StrokeClip => &gs.stroke_color,
+            _ => &gs.fill_color,
+        };
+        self.fill(&polygons, false, color_to_rgb(color), gs.fill_alpha);
+    }
+
+    /// Draws an image, `m` maps the unit square to pixels
+    fn paint_image(
+        &mut self,
+        image: &RawImage,
+        m: &[f32; 6],
+        gs: &GraphicsState,
+    ) -> Result<(), String> {
+        if image.width == 0 || image.height == 0 {
+            return Ok(());
+        }
+        let pixels = image.pixels()?;
+        let format = match (&pixels, image.source.as_ref()) {
+            (Cow::Owned(_), Some(source)) => source.get_decoded_format(),
+            _ => image.data_format,
+        };
+        let inverse = invert(m).ok_or_else(|| "singular transform".to_string())?;
+
+        let corners = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)].map(|p| transform(m, p));
+        let min_x = corners.iter().map(|p| p.0).fold(f32::MAX, f32::min);
+        let max_x = corners.iter().map(|p| p.0).fold(f32::MIN, f32::max);
+        let min_y = corners.iter().map(|p| p.1).fold(f32::MAX, f32::min);
+        let max_y = corners.iter().map(|p| p.1).fold(f32::MIN, f32::max);
+        let x0 = min_x.floor().max(0.0) as usize;
+        let x1 = (max_x.ceil().max(0.0) as usize).min(self.canvas.width);
+        let y0 = min_y.floor().max(0.0) as usize;
+        let y1 = (max_y.ceil().max(0.0) as usize).min(self.canvas.height);
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let (u, v) = transform(&inverse, (x as f32 + 0.5, y as f32 + 0.5));
+                if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+                    continue;
+                }
+                // the first row of the image is the top edge of the unit square
+                let col = ((u * image.width as f32) as usize).min(image.width - 1);
+                let row = (((1.0 - v) * image.height as f32) as usize).min(image.height - 1);
+                let Some((color, alpha)) = sample_pixel(&pixels, format, row * image.width + col)
+                else {
+                    continue;
+                };
+                let mut a = alpha * gs.fill_alpha;
+                if let Some(clip) = self.clip.as_ref() {
+                    a *= clip.get(x, y);
+                }
+                let px = &mut self.canvas.pixels[y * self.canvas.width + x];
+                for (p, c) in px.iter_mut().zip(color) {
+                    *p += (c - *p) * a;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl RenderBackend for Renderer<'_> {
+    fn save_state(&mut self) {
+        self.clip_stack.push(self.clip.clone());
+    }
+
+    fn restore_state(&mut self) {
+        if let Some(clip) = self.clip_stack.pop() {
+            self.clip = clip;
+        }
+    }
+
+    fn fill_path(
+        &mut self,
+        gs: &GraphicsState,
+        rings: &[Vec<(Point, bool)>],
+        winding: WindingOrder,
+    ) {
+        self.check_state(gs);
+        let device = to_device_rings(rings, gs);
+        let even_odd = winding == WindingOrder::EvenOdd;
+        let color = color_to_rgb(&gs.fill_color);
+        self.fill(&device, even_odd, color, gs.fill_alpha);
+    }
+
+    fn stroke_path(&mut self, gs: &GraphicsState, rings: &[Vec<(Point, bool)>], closed: bool) {
+        self.check_state(gs);
+        for ring in rings.iter() {
+            let points = flatten_points(ring, get_scale(&gs.ctm));
+            self.stroke(&points, closed, gs);
+        }
+    }
+
+    fn push_clip(
+        &mut self,
+        gs: &GraphicsState,
+        rings: &[Vec<(Point, bool)>],
+        winding: WindingOrder,
+    ) {
+        let device = to_device_rings(rings, gs);
+        let even_odd = winding == WindingOrder::EvenOdd;
+        let (w, h) = (self.canvas.width, self.canvas.height);
+        let clip = rasterize(&device, even_odd, w, h).unwrap_or(Coverage {
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 0,
+            data: Vec::new(),
+        });
+        let clip = match self.clip.as_ref() {
+            Some(prev) => clip.intersect(prev),
+            None => clip,
+        };
+        self.clip = Some(Rc::new(clip));
+    }
+
+    fn draw_glyph_run(
+        &mut self,
+        gs: &GraphicsState,
+        font: &ParsedFont,
+        glyphs: &[PositionedGlyph],
+    ) {
+        self.check_state(gs);
+        self.paint_glyphs(font, glyphs, gs);
+    }
+
+    fn draw_image(&mut self, gs: &GraphicsState, image: &RawImage, matrix: [f32; 6]) {
+        self.check_state(gs);
+        if let Err(e) = self.paint_image(image, &matrix, gs) {
+            self.skipped.insert(format!("image ({e})"));
+        }
+    }
+
+    fn draw_gradient(
+        &mut self,
+        gs: &GraphicsState,
+        gradient: &Gradient,
+        clip: &Polygon,
+        matrix: [f32; 6],
+    ) {
+        self.check_state(gs);
+        self.paint_gradient(clip, gradient, &matrix, gs);
+    }
+
+    fn unsupported(&mut self, _gs: &GraphicsState, feature: &str) {
+        self.skipped.insert(feature.to_string());
+    }
+}
+
+#[test]
+fn test_rasterize_page() {
+    use crate::{Mm, PaintMode, PdfPage};
+
+    let square = |x: f32, y: f32, size: f32| Polygon {
+        rings: vec![vec![
+            (Point::new(Mm(x), Mm(y)), false),
+            (Point::new(Mm(x + size), Mm(y)), false),
+            (Point::new(Mm(x + size), Mm(y + size)), false),
+            (Point::new(Mm(x), Mm(y + size)), false),
+        ]],
+        mode: PaintMode::Fill,
+        winding_order: WindingOrder::NonZero,
+    };
+
+    let mut doc = PdfDocument::new("rasterize");
+    doc.pages.push(PdfPage::new(
+        Mm(100.0),
+        Mm(100.0),
+        vec![
+            Op::SetFillColor {
+                col: Color::Rgb(Rgb::new(1.0, 0.0, 0.0, None)),
+            },
+            // lower left quarter of the page
+            Op::DrawPolygon {
+                polygon: square(0.0, 0.0, 50.0),
+            },
+            Op::Unknown {
+                key: "sh".to_string(),
+                value: Vec::new(),
+            },
+        ],
+    ));
+
+    let mut warnings = Vec::new();
+    let opts = RasterizeOptions::new(25.4);
+    let image = doc.rasterize_page(0, &opts, &mut warnings).unwrap();
+    assert_eq!((image.width, image.height), (100, 100));
+    assert_eq!(warnings.len(), 1);
+
+    let RawImageData::U8(pixels) = &image.pixels else {
+        panic!("expected 8-bit pixels");
+    };
+    let pixel = |x: usize, y: usize| &pixels[(y * 100 + x) * 3..(y * 100 + x) * 3 + 3];
+    assert_eq!(pixel(10, 90), &[255, 0, 0]);
+    assert_eq!(pixel(10, 10), &[255, 255, 255]);
+    assert_eq!(pixel(90, 90), &[255, 255, 255]);
+
+    let mut flattened = doc.clone();
+    flattened
+        .flatten_pages(&[0], &opts, &mut Vec::new())
+        .unwrap();
+    assert_eq!(flattened.pages[0].ops.len(), 3);
+    assert_eq!(flattened.resources.xobjects.map.len(), 1);
+}
+
+#[test]
+fn test_stroke_caps_and_joins() {
+    let style = |cap, join, miter_limit| StrokeStyle {
+        half_width: 1.0,
+        cap,
+        join,
+        miter_limit,
+    };
+    let x_range = |polygons: &[Vec<(f32, f32)>]| {
+        let xs = polygons.iter().flatten().map(|p| p.0);
+        let min = xs.clone().fold(f32::MAX, f32::min);
+        (min, xs.fold(f32::MIN, f32::max))
+    };
+    let has_point = |polygons: &[Vec<(f32, f32)>], q: (f32, f32)| {
+        let near = |p: &(f32, f32)| (p.0 - q.0).abs() < 1e-4 && (p.1 - q.1).abs() < 1e-4;
+        polygons.iter().flatten().any(near)
+    };
+
+    let line = [(0.0, 0.0), (10.0, 0.0)];
+    let butt = stroke_polygons(
+        &line,
+        false,
+        style(LineCapStyle::Butt, LineJoinStyle::Miter, 10.0),
+    );
+    assert_eq!(x_range(&butt), (0.0, 10.0));
+    let square = style(LineCapStyle::ProjectingSquare, LineJoinStyle::Miter, 10.0);
+    assert_eq!(
+        x_range(&stroke_polygons(&line, false, square)),
+        (-1.0, 11.0)
+    );
+
+    // right angle turning left, the outer corner is at (11, -1)
+    let corner = [(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)];
+    let miter = style(LineCapStyle::Butt, LineJoinStyle::Miter, 10.0);
+    assert!(has_point(
+        &stroke_polygons(&corner, false, miter),
+        (11.0, -1.0)
+    ));
+    let bevel = style(LineCapStyle::Butt, LineJoinStyle::Limit, 10.0);
+    assert!(!has_point(
+        &stroke_polygons(&corner, false, bevel),
+        (11.0, -1.0)
+    ));
+    // miter ratio of a right angle is sqrt(2)
+    let limited = style(LineCapStyle::Butt, LineJoinStyle::Miter, 1.2);
+    assert!(!has_point(
+        &stroke_polygons(&corner, false, limited),
+        (11.0, -1.0)
+    ));
+
+    // all polygons are clockwise, so that overlaps don't cancel out
+    for p in stroke_polygons(&corner, false, miter) {
+        let area = p
+            .iter()
+            .zip(p.iter().cycle().skip(1))
+            .map(|(a, b)| a.0 * b.1 - b.0 * a.1)
+            .sum::<f32>();
+        assert!(area <= 0.0);
+    }
+}
@@ -1,25 +1,38 @@
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
 
+use crate::color::ColorSpaceResource;
 use crate::color::IccProfile;
 use crate::font::SubsetFont;
+use crate::forms::text_string;
 use crate::Actions;
 use crate::BuiltinFont;
 use crate::Color;
 use crate::ColorArray;
+use crate::ColorTransform;
 use crate::Destination;
 use crate::FontId;
+use crate::FormField;
+use crate::FormFieldMap;
+use crate::IccProfileId;
+use crate::IccProfileMap;
 use crate::IccProfileType;
 use crate::Line;
 use crate::LinkAnnotation;
 use crate::Op;
+use crate::PageRotation;
 use crate::PaintMode;
 use crate::ParsedFont;
 use crate::PdfDocument;
 use crate::PdfDocumentInfo;
-use crate::PdfPage;
+use crate::PdfEncryption;
 use crate::PdfResources;
+use crate::PdfWarnCode;
+use crate::PdfWarnMsg;
 use crate::Polygon;
+use crate::RawResourceMap;
+use crate::StructureElementId;
+use crate::StructureTree;
 use crate::XObject;
 use crate::XObjectId;
 use lopdf::content::Operation as LoOp;
@@ -32,10 +45,49 @@ use lopdf::StringFormat::{Hexadecimal, Literal};
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub struct PdfSaveOptions {
     pub optimize: bool,
     pub subset_fonts: bool,
+    /// Set the modification and metadata date of the document to the current time on save
+    #[serde(default)]
+    pub update_modification_date: bool,
+    /// Password-protect the document and restrict printing / copying
+    #[serde(default)]
+    pub encryption: Option<PdfEncryption>,
+    /// Convert all RGB colors and images to CMYK or greyscale (i.e. for print providers)
+    #[serde(default)]
+    pub color_transform: Option<ColorTransform>,
+    /// Write the objects into compressed object streams and replace the cross-reference
+    /// table with a cross-reference stream (PDF 1.5). Usually 20-40% smaller for text-heavy
+    /// documents, but not readable by PDF 1.4 viewers (ignored for PDF/A-1 and PDF/X-1a/3/4).
+    #[serde(default)]
+    pub use_object_streams: bool,
+    /// Filter of the streams that are compressed on save (images, form XObjects, ...)
+    #[serde(default)]
+    pub compression: StreamCompression,
+    /// Also compress the page content streams, off by default so that the saved
+    /// documents stay readable (i.e. for diffs of generated PDFs in tests)
+    #[serde(default)]
+    pub compress_content_streams: bool,
+}
+
+/// How the streams of the document are compressed on save
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case", tag = "filter")]
+pub enum StreamCompression {
+    /// Streams are written as they are (for debugging)
+    None,
+    /// `/FlateDecode` with a compression level from 0 (fastest) to 9 (smallest)
+    Flate { level: u32 },
+    /// `/LZWDecode`, for old readers (not allowed in PDF/A, falls back to `Flate`)
+    Lzw,
+}
+
+impl Default for StreamCompression {
+    fn default() -> Self {
+        StreamCompression::Flate { level: 6 }
+    }
 }
 
 impl Default for PdfSaveOptions {
@@ -43,11 +95,124 @@ impl Default for PdfSaveOptions {
         Self {
             optimize: true,
             subset_fonts: true,
+            update_modification_date: false,
+            encryption: None,
+            color_transform: None,
+            use_object_streams: false,
+            compression: StreamCompression::default(),
+            compress_content_streams: false,
+        }
+    }
+}
+
+pub fn serialize_pdf_into_bytes(
+    pdf: &PdfDocument,
+    opts: &PdfSaveOptions,
+    warnings: &mut Vec<PdfWarnMsg>,
+) -> Vec<u8> {
+    serialize_pdf(pdf, opts, warnings, false).0
+}
+
+/// Serializes the document, if `reserve_signature` is set, the first signature field
+/// gets a placeholder value that is later filled in by `signature::sign_pdf_bytes`.
+/// Returns the bytes and the byte offset of the placeholder value object.
+pub(crate) fn serialize_pdf(
+    pdf: &PdfDocument,
+    opts: &PdfSaveOptions,
+    warnings: &mut Vec<PdfWarnMsg>,
+    reserve_signature: bool,
+) -> (Vec<u8>, Option<usize>) {
+    let doc = serialize_pdf_document(pdf, opts, warnings, reserve_signature);
+    let signature_id = doc
+        .objects
+        .iter()
+        .find(|(_, obj)| matches!(obj, Dictionary(d) if d.has(b"ByteRange")))
+        .map(|(id, _)| id.0);
+
+    let use_object_streams = opts.use_object_streams
+        && (pdf.metadata.info.conformance.is_object_streams_allowed() || {
+            warnings.push(
+                PdfWarnMsg::warning(
+                    None,
+                    "object streams are not allowed by the documents conformance level, \
+                     writing a cross-reference table"
+                        .to_string(),
+                )
+                .with_code(PdfWarnCode::ObjectStreamsNotAllowed),
+            );
+            false
+        });
+    if use_object_streams {
+        let (bytes, offsets) = write_with_object_streams(&doc);
+        let signature_offset = signature_id.and_then(|id| offsets.get(&id).copied());
+        return (bytes, signature_offset);
+    }
+
+    let mut doc = doc;
+    let mut bytes = Vec::new();
+    let mut writer = std::io::BufWriter::new(&mut bytes);
+    let _ = doc.save_to(&mut writer);
+    std::mem::drop(writer);
+
+    let signature_offset = signature_id.and_then(|id| get_xref_table_offset(&bytes, id));
+    (bytes, signature_offset)
+}
+
+/// Byte offset of an object from the (last) cross-reference table of a saved document
+fn get_xref_table_offset(bytes: &[u8], id: u32) -> Option<usize> {
+    let tail = &bytes[bytes.len().saturating_sub(64)..];
+    let startxref = tail.windows(9).rposition(|w| w == b"startxref")?;
+    let xref_offset = std::str::from_utf8(&tail[startxref + 9..])
+        .ok()?
+        .split_ascii_whitespace()
+        .next()?
+        .parse::<usize>()
+        .ok()?;
+    let section = bytes.get(xref_offset..)?;
+    let end = section.windows(7).position(|w| w == b"trailer")?;
+    let mut tokens = std::str::from_utf8(&section[..end])
+        .ok()?
+        .split_ascii_whitespace();
+    if tokens.next()? != "xref" {
+        return None;
+    }
+    // subsections: first object number and count, then "offset generation n|f" per object
+    while let (Some(start), Some(count)) = (tokens.next(), tokens.next()) {
+        let start = start.parse::<u32>().ok()?;
+        let count = count.parse::<u32>().ok()?;
+        for i in 0..count {
+            let (offset, _, kind) = (tokens.next()?, tokens.next()?, tokens.next()?);
+            if start + i == id && kind == "n" {
+                return offset.parse().ok();
+            }
         }
     }
+    None
 }
 
-pub fn serialize_pdf_into_bytes(pdf: &PdfDocument, opts: &PdfSaveOptions) -> Vec<u8> {
+/// Builds the (encrypted) lopdf document, see `serialize_pdf`
+pub(crate) fn serialize_pdf_document(
+    pdf: &PdfDocument,
+    opts: &PdfSaveOptions,
+    warnings: &mut Vec<PdfWarnMsg>,
+    reserve_signature: bool,
+) -> lopdf::Document {
+    let _span = trace_span!("serialize_pdf", pages = pdf.pages.len());
+    let converted;
+    let pdf = match opts.color_transform.as_ref() {
+        Some(transform) => {
+            converted = crate::color_transform::apply_color_transform(pdf, transform, warnings);
+            &converted
+        }
+        None => pdf,
+    };
+    warnings.extend(crate::conformance::get_conformance_warnings(pdf));
+
+    let mut metadata = pdf.metadata.clone();
+    if opts.update_modification_date {
+        metadata.info.update_modification_date();
+    }
+
     let mut doc = lopdf::Document::with_version("1.3");
     doc.reference_table.cross_reference_type = lopdf::xref::XrefType::CrossReferenceTable;
     let pages_id = doc.new_object_id();
@@ -90,11 +255,15 @@ pub fn serialize_pdf_into_bytes(pdf: &PdfDocument, opts: &PdfSaveOptions) -> Vec
         catalog.set("OutputIntents", Array(vec![Dictionary(output_intents)]));
     }
 
+    if let Some(lang) = metadata.language.as_ref() {
+        catalog.set("Lang", text_string(lang));
+    }
+
     // (Optional): Add XMP Metadata to catalog
     if pdf.metadata.info.conformance.must_have_xmp_metadata() {
         let xmp_obj = Stream(LoStream::new(
             LoDictionary::from_iter(vec![("Type", "Metadata".into()), ("Subtype", "XML".into())]),
-            pdf.metadata.xmp_metadata_string().as_bytes().to_vec(),
+            metadata.xmp_metadata_string().as_bytes().to_vec(),
         ));
         let metadata_id = doc.add_object(xmp_obj);
         catalog.set("Metadata", Reference(metadata_id));
@@ -158,37 +327,111 @@ pub fn serialize_pdf_into_bytes(pdf: &PdfDocument, opts: &PdfSaveOptions) -> Vec
         );
     }
 
+    // ops of the pages and of the group XObjects, for collecting the used glyphs
+    let content = pdf
+        .pages
+        .iter()
+        .map(|p| p.ops.as_slice())
+        .chain(
+            pdf.resources
+                .xobjects
+                .map
+                .values()
+                .filter_map(|x| x.get_ops()),
+        )
+        .collect::<Vec<_>>();
+
     // Build fonts dictionary
     let mut global_font_dict = LoDictionary::new();
-    let prepared_fonts = prepare_fonts(&pdf.resources, &pdf.pages);
+    let prepared_fonts = prepare_fonts(&pdf.resources, &content);
     for (font_id, prepared) in prepared_fonts.iter() {
+        trace_debug!(font = %font_id.0, "embedding font");
         let font_dict = add_font_to_pdf(&mut doc, font_id, prepared);
         let font_dict_id = doc.add_object(font_dict);
         global_font_dict.set(font_id.0.clone(), Reference(font_dict_id));
     }
 
-    for internal_font in get_used_internal_fonts(&pdf.pages) {
+    for internal_font in get_used_internal_fonts(&content, &pdf.resources.forms) {
         let font_dict = builtin_font_to_dict(&internal_font);
         let font_dict_id = doc.add_object(font_dict);
         global_font_dict.set(internal_font.get_pdf_id(), Reference(font_dict_id));
     }
+
+    // unparsed resources of parsed pages, the streams nested in them are written once
+    let mut raw_dicts = BTreeMap::<&str, LoDictionary>::new();
+    for ((resource_type, name), resource) in pdf.resources.raw.map.iter() {
+        let dict = match resource_type.as_str() {
+            "Font" => &mut global_font_dict,
+            t => match RawResourceMap::TYPES.iter().find(|r| **r == t) {
+                Some(t) => raw_dicts.entry(*t).or_default(),
+                None => continue,
+            },
+        };
+        if !dict.has(name.as_bytes()) {
+            let resource = crate::xobject::add_nested_streams(resource, &mut doc);
+            dict.set(name.clone(), resource);
+        }
+    }
     let global_font_dict_id = doc.add_object(global_font_dict);
 
+    // ICC profiles are embedded once, the pages reference them in `[/ICCBased stream]`
+    let icc_profile_ids = pdf
+        .resources
+        .icc_profiles
+        .map
+        .iter()
+        .map(|(k, v)| (k.clone(), doc.add_object(Stream(icc_to_stream(v)))))
+        .collect::<BTreeMap<_, _>>();
+
+    // group XObjects use the XObjects and graphics states of the document
+    // (which are written after them), the dictionary IDs are reserved here
+    let global_xobject_dict_id = doc.new_object_id();
+    let global_extgstate_dict_id = doc.new_object_id();
+
     // Build XObject dictionary
     let mut global_xobject_dict = LoDictionary::new();
+    let mut xobject_ids = BTreeMap::new();
     for (k, v) in pdf.resources.xobjects.map.iter() {
-        global_xobject_dict.set(
-            k.0.clone(),
-            crate::xobject::add_xobject_to_document(v, &mut doc),
-        );
+        trace_debug!(xobject = %k.0, "writing XObject");
+        let mut group_content = |ops: &[Op], doc: &mut lopdf::Document| {
+            let mut resources = LoDictionary::new();
+            let color_spaces = add_content_resources(
+                ops,
+                &mut resources,
+                &pdf.resources.icc_profiles,
+                &icc_profile_ids,
+                None,
+                doc,
+                warnings,
+            );
+            resources.set("Font", Reference(global_font_dict_id));
+            resources.set("XObject", Reference(global_xobject_dict_id));
+            resources.set("ExtGState", Reference(global_extgstate_dict_id));
+            add_raw_resources(&mut resources, &raw_dicts);
+            // groups are not part of the structure tree
+            let bytes = translate_operations(
+                ops,
+                &prepared_fonts,
+                &pdf.resources.xobjects.map,
+                &pdf.structure,
+                &color_spaces,
+                &mut Vec::new(),
+            );
+            (bytes, resources)
+        };
+        let id = crate::xobject::add_xobject_to_document(v, &mut doc, &mut group_content);
+        global_xobject_dict.set(k.0.clone(), id);
+        xobject_ids.insert(k.clone(), id);
     }
-    let global_xobject_dict_id = doc.add_object(global_xobject_dict);
+    doc.set_object(global_xobject_dict_id, global_xobject_dict);
 
+    // soft masks reference their group XObjects
     let mut global_extgstate_dict = LoDictionary::new();
     for (k, v) in pdf.resources.extgstates.map.iter() {
-        global_extgstate_dict.set(k.0.clone(), crate::graphics::extgstate_to_dict(v));
+        let dict = crate::graphics::extgstate_to_dict(v, &xobject_ids);
+        global_extgstate_dict.set(k.0.clone(), dict);
     }
-    let global_extgstate_dict_id = doc.add_object(global_extgstate_dict);
+    doc.set_object(global_extgstate_dict_id, global_extgstate_dict);
 
     let page_ids_reserved = pdf
         .pages
@@ -196,12 +439,20 @@ pub fn serialize_pdf_into_bytes(pdf: &PdfDocument, opts: &PdfSaveOptions) -> Vec
         .map(|_| doc.new_object_id())
         .collect::<Vec<_>>();
 
+    // Widget annotations of all form fields, for the /AcroForm dictionary
+    let mut form_fields = Vec::new();
+    let mut has_signature_fields = false;
+    let mut signature_reserved = false;
+    let mut page_mcids = vec![Vec::new(); pdf.pages.len()];
+
     // Render pages
     let page_ids = pdf
         .pages
         .iter()
         .zip(page_ids_reserved.iter())
-        .map(|(page, page_id)| {
+        .enumerate()
+        .map(|(page_idx, (page, page_id))| {
+            let _span = trace_span!("serialize_page", page = page_idx);
             // gather page annotations
             let mut page_resources = LoDictionary::new(); // get_page_resources(&mut doc, &page);
 
@@ -264,28 +515,120 @@ pub fn serialize_pdf_into_bytes(pdf: &PdfDocument, opts: &PdfSaveOptions) -> Vec
                     Op::LinkAnnotation { link } => Some(link.clone()),
                     _ => None,
                 })
+                .filter(|l| {
+                    let is_empty = l.rect.is_empty();
+                    if is_empty {
+                        warnings.push(
+                            PdfWarnMsg::warning(
+                                Some(page_idx),
+                                "link annotation with a zero-area rect was skipped".to_string(),
+                            )
+                            .with_code(PdfWarnCode::EmptyLinkRect),
+                        );
+                    }
+                    !is_empty
+                })
                 .collect::<Vec<_>>();
-            page_resources.set(
-                "Annots",
-                Array(
-                    links
-                        .iter()
-                        .map(|l| Dictionary(link_annotation_to_dict(l, &page_ids_reserved)))
-                        .collect(),
-                ),
+            let mut annots = links
+                .iter()
+                .map(|l| Dictionary(link_annotation_to_dict(l, &page_ids_reserved)))
+                .collect::<Vec<_>>();
+
+            let mut page_fields = page
+                .ops
+                .iter()
+                .filter_map(|op| match op {
+                    Op::AddFormField { field } => Some(&**field),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            for f in pdf.resources.forms.map.values() {
+                let is_placed = page_fields
+                    .iter()
+                    .any(|p| p.get_name() == f.field.get_name());
+                if f.page == page_idx && !is_placed {
+                    page_fields.push(&f.field);
+                }
+            }
+
+            for field in page_fields {
+                let is_signature = matches!(field, FormField::Signature(_));
+                // invisible signatures have a zero-area rect
+                if field.get_rect().is_empty() && !is_signature {
+                    warnings.push(
+                        PdfWarnMsg::warning(
+                            Some(page_idx),
+                            format!(
+                                "form field {:?} with a zero-area rect was skipped",
+                                field.get_name()
+                            ),
+                        )
+                        .with_code(PdfWarnCode::EmptyFormFieldRect)
+                        .with_resource(field.get_name()),
+                    );
+                    continue;
+                }
+                let reserve = reserve_signature && is_signature && !signature_reserved;
+                signature_reserved = signature_reserved || reserve;
+                has_signature_fields = has_signature_fields || is_signature;
+                let field_dict = crate::forms::form_field_to_dict(
+                    field,
+                    *page_id,
+                    global_font_dict_id,
+                    reserve,
+                    &mut doc,
+                );
+                let field_id = doc.add_object(field_dict);
+                form_fields.push(Reference(field_id));
+                annots.push(Reference(field_id));
+            }
+
+            for op in page.ops.iter() {
+                let Op::AddAnnotation { annotation } = op else {
+                    continue;
+                };
+                if annotation.get_rect().is_empty() {
+                    warnings.push(
+                        PdfWarnMsg::warning(
+                            Some(page_idx),
+                            "annotation with a zero-area rect was skipped".to_string(),
+                        )
+                        .with_code(PdfWarnCode::EmptyAnnotationRect),
+                    );
+                    continue;
+                }
+                let ids = crate::annotation::add_markup_annotation(annotation, *page_id, &mut doc);
+                annots.extend(ids.into_iter().map(Reference));
+            }
+
+            let color_spaces = add_content_resources(
+                &page.ops,
+                &mut page_resources,
+                &pdf.resources.icc_profiles,
+                &icc_profile_ids,
+                Some(page_idx),
+                &mut doc,
+                warnings,
             );
 
             page_resources.set("Font", Reference(global_font_dict_id));
             page_resources.set("XObject", Reference(global_xobject_dict_id));
             page_resources.set("ExtGState", Reference(global_extgstate_dict_id));
             // page_resources.et("Properties", Dictionary(ocg_dict));
-
-            let layer_stream =
-                translate_operations(&page.ops, &prepared_fonts, &pdf.resources.xobjects.map); // Vec<u8>
-            let merged_layer_stream =
-                LoStream::new(LoDictionary::new(), layer_stream).with_compression(false);
-
-            let page_obj = LoDictionary::from_iter(vec![
+            add_raw_resources(&mut page_resources, &raw_dicts);
+
+            let layer_stream = translate_operations(
+                &page.ops,
+                &prepared_fonts,
+                &pdf.resources.xobjects.map,
+                &pdf.structure,
+                &color_spaces,
+                &mut page_mcids[page_idx],
+            ); // Vec<u8>
+            let merged_layer_stream = LoStream::new(LoDictionary::new(), layer_stream)
+                .with_compression(opts.compress_content_streams);
+
+            let mut page_obj = LoDictionary::from_iter(vec![
                 ("Type", "Page".into()),
                 ("MediaBox", page.get_media_box()),
                 ("TrimBox", page.get_trim_box()),
@@ -295,67 +638,132 @@ pub fn serialize_pdf_into_bytes(pdf: &PdfDocument, opts: &PdfSaveOptions) -> Vec
                 ("Contents", Reference(doc.add_object(merged_layer_stream))),
             ]);
 
+            if let Some(bleed_box) = page.bleed_box.as_ref() {
+                page_obj.set("BleedBox", bleed_box.to_array());
+            }
+            if let Some(art_box) = page.art_box.as_ref() {
+                page_obj.set("ArtBox", art_box.to_array());
+            }
+            if page.user_unit != 1.0 && page.user_unit.is_finite() && page.user_unit > 0.0 {
+                page_obj.set("UserUnit", Real(page.user_unit));
+            }
+            if page.rotation != PageRotation::None {
+                page_obj.set("Rotate", Integer(page.rotation.get_degrees()));
+            }
+
+            if !annots.is_empty() {
+                page_obj.set("Annots", Array(annots));
+            }
+
+            if !page.actions.is_empty() {
+                let mut aa = LoDictionary::new();
+                if let Some(open) = page.actions.open.as_ref() {
+                    aa.set("O", Dictionary(actions_to_dict(open, &page_ids_reserved)));
+                }
+                if let Some(close) = page.actions.close.as_ref() {
+                    aa.set("C", Dictionary(actions_to_dict(close, &page_ids_reserved)));
+                }
+                page_obj.set("AA", Dictionary(aa));
+            }
+
             doc.set_object(*page_id, page_obj);
 
             *page_id
         })
         .collect::<Vec<_>>();
 
-    // Now that the page objs are rendered, resolve which bookmarks reference which page objs
-    if !pdf.bookmarks.map.is_empty() {
-        let bookmarks_id = doc.new_object_id();
-        let mut bookmarks_sorted = pdf.bookmarks.map.iter().collect::<Vec<_>>();
-        bookmarks_sorted.sort_by(|(_, v), (_, v2)| (v.page, &v.name).cmp(&(v2.page, &v2.name)));
-        let bookmarks_sorted = bookmarks_sorted
+    if !form_fields.is_empty() {
+        let mut acroform = LoDictionary::from_iter(vec![
+            ("Fields", Array(form_fields)),
+            ("NeedAppearances", true.into()),
+            (
+                "DR",
+                Dictionary(LoDictionary::from_iter(vec![(
+                    "Font",
+                    Reference(global_font_dict_id),
+                )])),
+            ),
+        ]);
+        if has_signature_fields {
+            // 1 = SignaturesExist, 2 = AppendOnly
+            let sig_flags = if signature_reserved { 3 } else { 1 };
+            acroform.set("SigFlags", Integer(sig_flags));
+        }
+        catalog.set("AcroForm", acroform);
+    }
+
+    // Now that the page objs are rendered, resolve which bookmarks reference which page objs.
+    // The flat bookmarks are appended to the outline as top-level entries.
+    let mut bookmarks_sorted = pdf.bookmarks.map.values().collect::<Vec<_>>();
+    bookmarks_sorted.sort_by(|v, v2| (v.page, &v.name).cmp(&(v2.page, &v2.name)));
+    let mut outline = pdf.outline.clone();
+    outline.extend(
+        bookmarks_sorted
             .into_iter()
-            .filter_map(|(k, v)| {
-                let page_obj_id = page_ids.get(v.page).cloned()?;
-                Some((k, &v.name, page_obj_id))
-            })
-            .collect::<Vec<_>>();
+            .filter(|v| v.page < page_ids.len())
+            .map(|v| crate::OutlineNode::page(&v.name, v.page + 1)),
+    );
+    if !outline.is_empty() {
+        let outlines_id = crate::outline::write_outline(&outline, &page_ids, &mut doc);
+        catalog.set("Outlines", Reference(outlines_id));
+        catalog.set("PageMode", Name("UseOutlines".into()));
+    }
 
-        let bookmark_ids = bookmarks_sorted
-            .iter()
-            .map(|(id, name, page_id)| {
-                let newid = doc.new_object_id();
-                (id, name, page_id, newid)
-            })
-            .collect::<Vec<_>>();
+    if !pdf.structure.is_empty() {
+        let struct_tree_root = crate::structure::write_structure_tree(
+            &pdf.structure,
+            &page_mcids,
+            &page_ids,
+            &mut doc,
+        );
+        catalog.set("StructTreeRoot", Reference(struct_tree_root));
+        catalog.set(
+            "MarkInfo",
+            Dictionary(LoDictionary::from_iter(vec![("Marked", true.into())])),
+        );
+    }
 
-        let first = bookmark_ids.first().map(|s| s.3).unwrap();
-        let last = bookmark_ids.last().map(|s| s.3).unwrap();
-        for (i, (_id, name, pageid, self_id)) in bookmark_ids.iter().enumerate() {
-            let prev = if i == 0 {
-                None
-            } else {
-                bookmark_ids.get(i - 1).map(|s| s.3)
-            };
-            let next = bookmark_ids.get(i + 1).map(|s| s.3);
-            let dest = Array(vec![Reference(*(*pageid)), "XYZ".into(), Null, Null, Null]);
-            let mut dict = LoDictionary::from_iter(vec![
-                ("Parent", Reference(bookmarks_id)),
-                ("Title", LoString(name.to_string().into(), Literal)),
-                ("Dest", dest),
-            ]);
-            if let Some(prev) = prev {
-                dict.set("Prev", Reference(prev));
-            }
-            if let Some(next) = next {
-                dict.set("Next", Reference(next));
-            }
-            doc.set_object(*self_id, dict);
-        }
+    let mut names = LoDictionary::new();
 
-        let bookmarks_list = LoDictionary::from_iter(vec![
-            ("Type", "Outlines".into()),
-            ("Count", Integer(pdf.bookmarks.map.len() as i64)),
-            ("First", Reference(first)),
-            ("Last", Reference(last)),
-        ]);
+    if !pdf.named_destinations.is_empty() {
+        let dests =
+            crate::outline::write_named_destinations(&pdf.named_destinations, &page_ids, warnings);
+        names.set("Dests", Dictionary(dests));
+    }
 
-        doc.set_object(bookmarks_id, bookmarks_list);
-        catalog.set("Outlines", Reference(bookmarks_id));
-        catalog.set("PageMode", LoString("UseOutlines".into(), Literal));
+    if !pdf.attachments.is_empty() {
+        let (embedded_files, af) = crate::attachments::write_embedded_files(
+            &pdf.attachments,
+            &metadata.info.modification_date,
+            &mut doc,
+        );
+        names.set("EmbeddedFiles", Dictionary(embedded_files));
+        catalog.set("AF", Array(af));
+    }
+
+    if !names.is_empty() {
+        catalog.set("Names", Dictionary(names));
+    }
+
+    if let Some(document_parts) = pdf.document_parts.as_ref() {
+        let dpart_root =
+            crate::dpart::write_document_parts(document_parts, &page_ids, &mut doc, warnings);
+        catalog.set("DPartRoot", Reference(dpart_root));
+    }
+
+    if let Some(open_action) = pdf.open_action.as_ref() {
+        catalog.set(
+            "OpenAction",
+            Dictionary(actions_to_dict(open_action, &page_ids)),
+        );
+    }
+
+    if !pdf.page_labels.is_empty() {
+        catalog.set("PageLabels", Dictionary(pdf.page_labels.to_dict()));
+    }
+
+    if !pdf.viewer_preferences.is_empty() {
+        pdf.viewer_preferences.write_to_catalog(&mut catalog);
     }
 
     doc.set_object(
@@ -371,7 +779,7 @@ pub fn serialize_pdf_into_bytes(pdf: &PdfDocument, opts: &PdfSaveOptions) -> Vec
     );
 
     let catalog_id = doc.add_object(catalog);
-    let document_info_id = doc.add_object(Dictionary(docinfo_to_dict(&pdf.metadata.info)));
+    let document_info_id = doc.add_object(Dictionary(docinfo_to_dict(&metadata.info)));
     let instance_id = crate::utils::random_character_string_32();
     let document_id = crate::utils::random_character_string_32();
 
@@ -389,23 +797,285 @@ pub fn serialize_pdf_into_bytes(pdf: &PdfDocument, opts: &PdfSaveOptions) -> Vec
         // doc.compress();
     }
 
-    let mut bytes = Vec::new();
-    let mut writer = std::io::BufWriter::new(&mut bytes);
-    let _ = doc.save_to(&mut writer);
-    std::mem::drop(writer);
+    let compression = match opts.compression {
+        StreamCompression::Lzw if !pdf.metadata.info.conformance.is_lzw_allowed() => {
+            warnings.push(
+                PdfWarnMsg::warning(
+                    None,
+                    "LZW compression is not allowed by the documents conformance level, \
+                     using Flate compression"
+                        .to_string(),
+                )
+                .with_code(PdfWarnCode::LzwNotAllowed),
+            );
+            StreamCompression::default()
+        }
+        c => c,
+    };
+    compress_streams(&mut doc, compression);
+
+    if let Some(encryption) = opts.encryption.as_ref() {
+        if !pdf.metadata.info.conformance.is_encryption_allowed() {
+            warnings.push(
+                PdfWarnMsg::warning(
+                    None,
+                    "encryption is not allowed by the documents conformance level".to_string(),
+                )
+                .with_code(PdfWarnCode::EncryptionNotAllowed),
+            );
+        }
+        if let Err(e) =
+            crate::encryption::encrypt_document(&mut doc, encryption, document_id.as_bytes())
+        {
+            warnings.push(PdfWarnMsg::error(None, e).with_code(PdfWarnCode::EncryptionFailed));
+        }
+    }
 
-    bytes
+    doc
 }
 
-fn get_used_internal_fonts(pages: &[PdfPage]) -> BTreeSet<BuiltinFont> {
-    pages
+/// Maximum number of objects in one object stream
+const OBJECT_STREAM_SIZE: usize = 100;
+
+/// Writes the document with object streams and a cross-reference stream (PDF 1.5).
+/// Streams, the signature dictionary (which is patched in the output bytes) and the objects
+/// of encrypted documents (which are already encrypted individually) are written as
+/// regular indirect objects. Returns the bytes and the offsets of the regular objects.
+fn write_with_object_streams(doc: &lopdf::Document) -> (Vec<u8>, BTreeMap<u32, usize>) {
+    let is_encrypted = doc.trailer.get(b"Encrypt").is_ok();
+    let can_compress = |obj: &lopdf::Object| match obj {
+        Stream(_) => false,
+        Dictionary(d) => !d.has(b"ByteRange"),
+        _ => true,
+    };
+    let (compressed, uncompressed): (Vec<_>, Vec<_>) = doc
+        .objects
         .iter()
-        .flat_map(|p| {
-            p.ops.iter().filter_map(|op| match op {
+        .partition(|((_, generation), obj)| !is_encrypted && *generation == 0 && can_compress(obj));
+
+    let mut next_id = doc.objects.keys().map(|(id, _)| *id).max().unwrap_or(0) + 1;
+    // (type, field 2, field 3) of the cross-reference stream entries
+    let mut entries = BTreeMap::new();
+    let mut out = Vec::new();
+    let version = doc.version.as_str().max("1.5");
+    out.extend_from_slice(format!("%PDF-{version}\n%\u{e2}\u{e3}\u{cf}\u{d3}\n").as_bytes());
+
+    let mut write_indirect =
+        |out: &mut Vec<u8>, (id, generation): (u32, u16), obj: &lopdf::Object| {
+            entries.insert(id, (1, out.len() as u64, generation as u64));
+            out.extend_from_slice(format!("{id} {generation} obj\n").as_bytes());
+            write_object(out, obj);
+            out.extend_from_slice(b"\nendobj\n");
+        };
+
+    for (id, obj) in uncompressed {
+        write_indirect(&mut out, *id, obj);
+    }
+
+    let mut compressed_entries = Vec::new();
+    for chunk in compressed.chunks(OBJECT_STREAM_SIZE) {
+        let stream_id = next_id;
+        next_id += 1;
+        let mut offsets = Vec::new();
+        let mut objects = Vec::new();
+        for (index, ((id, _), obj)) in chunk.iter().enumerate() {
+            compressed_entries.push((*id, (2, stream_id as u64, index as u64)));
+            offsets.extend_from_slice(format!("{id} {} ", objects.len()).as_bytes());
+            write_object(&mut objects, obj);
+            objects.push(b'\n');
+        }
+        let first = offsets.len();
+        offsets.extend(objects);
+        let dict = LoDictionary::from_iter(vec![
+            ("Type", Name("ObjStm".into())),
+            ("N", Integer(chunk.len() as i64)),
+            ("First", Integer(first as i64)),
+            ("Filter", Name("FlateDecode".into())),
+        ]);
+        let stream = Stream(
+            LoStream::new(dict, crate::filters::flate_encode(&offsets, 9)).with_compression(false),
+        );
+        write_indirect(&mut out, (stream_id, 0), &stream);
+    }
+
+    let offsets = entries
+        .iter()
+        .filter(|(_, (kind, _, _))| *kind == 1)
+        .map(|(id, (_, offset, _))| (*id, *offset as usize))
+        .collect();
+
+    // the cross-reference stream contains its own entry
+    let xref_id = next_id;
+    let xref_offset = out.len() as u64;
+    entries.extend(compressed_entries);
+    entries.insert(xref_id, (1, xref_offset, 0));
+    let size = xref_id + 1;
+    let max_field = entries.values().map(|(_, f, _)| *f).max().unwrap_or(0);
+    let width = (1..8).find(|w| max_field >> (8 * w) == 0).unwrap_or(8);
+    let mut data = Vec::with_capacity(size as usize * (width + 3));
+    for id in 0..size {
+        let (kind, field2, field3) = match entries.get(&id) {
+            Some(entry) => *entry,
+            // object 0 is the head of the list of free objects
+            None if id == 0 => (0, 0, 65535),
+            None => (0, 0, 0),
+        };
+        data.push(kind);
+        data.extend_from_slice(&field2.to_be_bytes()[8 - width..]);
+        data.extend_from_slice(&(field3 as u16).to_be_bytes());
+    }
+
+    let mut dict = LoDictionary::from_iter(vec![
+        ("Type", Name("XRef".into())),
+        ("Size", Integer(size as i64)),
+        (
+            "W",
+            Array(vec![Integer(1), Integer(width as i64), Integer(2)]),
+        ),
+        ("Filter", Name("FlateDecode".into())),
+    ]);
+    for key in [&b"Root"[..], b"Info", b"ID", b"Encrypt"] {
+        if let Ok(value) = doc.trailer.get(key) {
+            dict.set(key, value.clone());
+        }
+    }
+    let stream =
+        Stream(LoStream::new(dict, crate::filters::flate_encode(&data, 9)).with_compression(false));
+    out.extend_from_slice(format!("{xref_id} 0 obj\n").as_bytes());
+    write_object(&mut out, &stream);
+    out.extend_from_slice(format!("\nendobj\nstartxref\n{xref_offset}\n%%EOF\n").as_bytes());
+    (out, offsets)
+}
+
+/// Compresses all streams that allow compression and are not encoded yet, except
+/// for the XMP metadata (which has to stay readable for PDF/A)
+pub(crate) fn compress_streams(doc: &mut lopdf::Document, compression: StreamCompression) {
+    let encode = |bytes: &[u8]| match compression {
+        StreamCompression::None => None,
+        StreamCompression::Flate { level } => {
+            Some(("FlateDecode", crate::filters::flate_encode(bytes, level)))
+        }
+        StreamCompression::Lzw => Some(("LZWDecode", crate::filters::lzw_encode(bytes))),
+    };
+
+    for obj in doc.objects.values_mut() {
+        let Stream(stream) = obj else {
+            continue;
+        };
+        let is_metadata = stream
+            .dict
+            .get(b"Type")
+            .is_ok_and(|t| t.as_name().is_ok_and(|n| n == b"Metadata"));
+        if !stream.allows_compression || stream.dict.has(b"Filter") || is_metadata {
+            continue;
+        }
+        let Some((filter, encoded)) = encode(&stream.content) else {
+            return;
+        };
+        if encoded.len() < stream.content.len() {
+            stream.dict.set("Filter", Name(filter.into()));
+            stream.set_content(encoded);
+        }
+    }
+}
+
+/// Writes an object in PDF syntax (references as `n g R`, streams with their `/Length`)
+pub(crate) fn write_object(out: &mut Vec<u8>, obj: &lopdf::Object) {
+    use lopdf::Object::Boolean;
+
+    match obj {
+        Null => out.extend_from_slice(b"null"),
+        Boolean(b) => out.extend_from_slice(if *b { b"true" } else { b"false" }),
+        Integer(i) => out.extend_from_slice(i.to_string().as_bytes()),
+        Real(r) => {
+            let r = if r.is_finite() { *r } else { 0.0 };
+            out.extend_from_slice(r.to_string().as_bytes());
+        }
+        Name(name) => write_name(out, name),
+        LoString(bytes, Literal) => {
+            out.push(b'(');
+            for b in bytes {
+                match b {
+                    b'(' | b')' | b'\\' => out.extend_from_slice(&[b'\\', *b]),
+                    b'\r' => out.extend_from_slice(b"\\r"),
+                    _ => out.push(*b),
+                }
+            }
+            out.push(b')');
+        }
+        LoString(bytes, Hexadecimal) => {
+            out.push(b'<');
+            for b in bytes {
+                out.extend_from_slice(format!("{b:02X}").as_bytes());
+            }
+            out.push(b'>');
+        }
+        Array(items) => {
+            out.push(b'[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(b' ');
+                }
+                write_object(out, item);
+            }
+            out.push(b']');
+        }
+        Dictionary(dict) => write_dictionary(out, dict, None),
+        Stream(stream) => {
+            write_dictionary(out, &stream.dict, Some(stream.content.len()));
+            out.extend_from_slice(b"\nstream\n");
+            out.extend_from_slice(&stream.content);
+            out.extend_from_slice(b"\nendstream");
+        }
+        Reference((id, generation)) => {
+            out.extend_from_slice(format!("{id} {generation} R").as_bytes());
+        }
+    }
+}
+
+/// Writes a dictionary, `length` replaces the `/Length` of streams
+fn write_dictionary(out: &mut Vec<u8>, dict: &LoDictionary, length: Option<usize>) {
+    out.extend_from_slice(b"<<");
+    for (key, value) in dict.iter() {
+        if length.is_some() && key.as_slice() == b"Length" {
+            continue;
+        }
+        write_name(out, key);
+        out.push(b' ');
+        write_object(out, value);
+    }
+    if let Some(length) = length {
+        out.extend_from_slice(format!("/Length {length}").as_bytes());
+    }
+    out.extend_from_slice(b">>");
+}
+
+fn write_name(out: &mut Vec<u8>, name: &[u8]) {
+    out.push(b'/');
+    for b in name {
+        match b {
+            b'!'..=b'~' if !b"()<>[]{}/%#".contains(b) => out.push(*b),
+            _ => out.extend_from_slice(format!("#{b:02X}").as_bytes()),
+        }
+    }
+}
+
+fn get_used_internal_fonts(content: &[&[Op]], forms: &FormFieldMap) -> BTreeSet<BuiltinFont> {
+    content
+        .iter()
+        .flat_map(|ops| {
+            ops.iter().filter_map(|op| match op {
                 Op::WriteTextBuiltinFont { font, .. } => Some(*font),
+                Op::AddFormField { field } => crate::forms::get_form_field_fonts(field),
                 _ => None,
             })
         })
+        .chain(
+            forms
+                .map
+                .values()
+                .filter_map(|f| crate::forms::get_form_field_fonts(&f.field)),
+        )
         .collect()
 }
 
@@ -418,12 +1088,135 @@ fn builtin_font_to_dict(font: &BuiltinFont) -> LoDictionary {
     ])
 }
 
+/// Adds the `/Shading` and `/ColorSpace` resources of the ops (of a page or a group
+/// XObject) to the resources, returns the color spaces for `translate_operations`
+fn add_content_resources<'a>(
+    ops: &'a [Op],
+    resources: &mut LoDictionary,
+    icc_profiles: &IccProfileMap,
+    icc_profile_ids: &BTreeMap<IccProfileId, lopdf::ObjectId>,
+    page_idx: Option<usize>,
+    doc: &mut lopdf::Document,
+    warnings: &mut Vec<PdfWarnMsg>,
+) -> Vec<ColorSpaceResource<'a>> {
+    // gradients are named in the order of the operations, see `translate_operations`
+    let shadings = ops
+        .iter()
+        .filter_map(|op| match op {
+            Op::DrawGradient { gradient, .. } => Some(gradient),
+            _ => None,
+        })
+        .enumerate()
+        .map(|(i, gradient)| {
+            if gradient.get_stops().is_empty() {
+                warnings.push(
+                    PdfWarnMsg::warning(
+                        page_idx,
+                        "gradient without color stops is drawn in black".to_string(),
+                    )
+                    .with_code(PdfWarnCode::EmptyGradient),
+                );
+            }
+            let id = doc.add_object(gradient.to_shading_dict());
+            (format!("Sh{i}"), Reference(id))
+        })
+        .collect::<Vec<_>>();
+    if !shadings.is_empty() {
+        resources.set(
+            "Shading",
+            LoDictionary::from_iter(shadings.iter().map(|(k, v)| (k.as_str(), v.clone()))),
+        );
+    }
+
+    // indexed, DeviceN and ICC based color spaces are named in the order of their first use
+    let color_spaces = get_page_color_spaces(ops, icc_profiles, page_idx, warnings);
+    if !color_spaces.is_empty() {
+        let dict = color_spaces
+            .iter()
+            .enumerate()
+            .map(|(i, cs)| (format!("CS{i}"), cs.to_object(doc, icc_profile_ids)))
+            .collect::<Vec<_>>();
+        resources.set(
+            "ColorSpace",
+            LoDictionary::from_iter(dict.iter().map(|(k, v)| (k.as_str(), v.clone()))),
+        );
+    }
+    color_spaces
+}
+
+/// Adds the unparsed resources of parsed pages (see `RawResourceMap`) to the resources of a
+/// content stream, without replacing the resources that were written for its ops
+fn add_raw_resources(resources: &mut LoDictionary, raw_dicts: &BTreeMap<&str, LoDictionary>) {
+    for (resource_type, raw) in raw_dicts.iter() {
+        let mut dict = match resources.get(resource_type.as_bytes()) {
+            Ok(Dictionary(d)) => d.clone(),
+            _ => LoDictionary::new(),
+        };
+        for (name, resource) in raw.iter() {
+            if !dict.has(name) {
+                dict.set(name.clone(), resource.clone());
+            }
+        }
+        resources.set(*resource_type, Dictionary(dict));
+    }
+}
+
+/// Indexed, DeviceN and ICC based color spaces of the fill and stroke colors, without
+/// duplicates. Colors with a missing or mismatched ICC profile use the device color space.
+fn get_page_color_spaces<'a>(
+    ops: &'a [Op],
+    icc_profiles: &IccProfileMap,
+    page_idx: Option<usize>,
+    warnings: &mut Vec<PdfWarnMsg>,
+) -> Vec<ColorSpaceResource<'a>> {
+    let mut color_spaces = Vec::new();
+    let mut skipped = Vec::new();
+    for op in ops {
+        let (Op::SetFillColor { col } | Op::SetOutlineColor { col }) = op else {
+            continue;
+        };
+        let Some(cs) = col.get_color_space_resource() else {
+            continue;
+        };
+        if color_spaces.contains(&cs) || skipped.contains(&cs) {
+            continue;
+        }
+        if let ColorSpaceResource::IccBased(id, device) = &cs {
+            let profile = icc_profiles.map.get(*id);
+            if profile.map(|p| p.icc_type.get_device_color_space()) != Some(*device) {
+                warnings.push(
+                    PdfWarnMsg::warning(
+                        page_idx,
+                        format!(
+                            "ICC profile {:?} is missing or does not match the color, using {}",
+                            id.0,
+                            device.get_name()
+                        ),
+                    )
+                    .with_code(PdfWarnCode::IccProfileMismatch)
+                    .with_resource(&id.0),
+                );
+                skipped.push(cs);
+                continue;
+            }
+        }
+        color_spaces.push(cs);
+    }
+    color_spaces
+}
+
+/// Translates the ops of a page into a content stream. `mcids` collects the structure
+/// element of each marked content sequence (indexed by MCID).
 fn translate_operations(
     ops: &[Op],
     fonts: &BTreeMap<FontId, PreparedFont>,
     xobjects: &BTreeMap<XObjectId, XObject>,
+    structure: &StructureTree,
+    color_spaces: &[ColorSpaceResource],
+    mcids: &mut Vec<StructureElementId>,
 ) -> Vec<u8> {
     let mut content = Vec::new();
+    let mut shadings = 0;
 
     for op in ops {
         match op {
@@ -441,6 +1234,31 @@ fn translate_operations(
                 content.push(LoOp::new("EMC", vec![]));
                 content.push(LoOp::new("Q", vec![]));
             }
+            Op::BeginStructureElement { id } => match structure.get_role(id) {
+                Some(role) => {
+                    let mcid = LoDictionary::from_iter(vec![("MCID", Integer(mcids.len() as i64))]);
+                    content.push(LoOp::new(
+                        "BDC",
+                        vec![Name(role.get_id().into()), Dictionary(mcid)],
+                    ));
+                    mcids.push(id.clone());
+                }
+                // unknown element: keep the sequence, so that EMC stays balanced
+                None => content.push(LoOp::new("BMC", vec![Name("Span".into())])),
+            },
+            Op::BeginLanguageSpan { lang } => {
+                let properties = LoDictionary::from_iter(vec![("Lang", text_string(lang))]);
+                content.push(LoOp::new(
+                    "BDC",
+                    vec![Name("Span".into()), Dictionary(properties)],
+                ));
+            }
+            Op::EndStructureElement | Op::EndArtifact | Op::EndLanguageSpan => {
+                content.push(LoOp::new("EMC", vec![]));
+            }
+            Op::BeginArtifact => {
+                content.push(LoOp::new("BMC", vec![Name("Artifact".into())]));
+            }
             Op::SaveGraphicsState => {
                 content.push(LoOp::new("q", vec![]));
             }
@@ -560,19 +1378,33 @@ fn translate_operations(
                 content.push(LoOp::new("Td", vec![pos.x.0.into(), pos.y.0.into()]));
             }
             Op::SetFillColor { col } => {
-                let ci = match &col {
-                    Color::Rgb(_) => "rg",
-                    Color::Cmyk(_) | Color::SpotColor(_) => "k",
-                    Color::Greyscale(_) => "g",
+                let ci = match set_color_space_op(col, color_spaces, "cs") {
+                    Some(cs) => {
+                        content.push(cs);
+                        "sc"
+                    }
+                    None => match &col {
+                        Color::Rgb(_) => "rg",
+                        Color::Cmyk(_) | Color::SpotColor(_) => "k",
+                        Color::Greyscale(_) => "g",
+                        Color::Indexed(_) | Color::DeviceN(_) => "sc",
+                    },
                 };
                 let cvec = col.into_vec().into_iter().map(Real).collect();
                 content.push(LoOp::new(ci, cvec));
             }
             Op::SetOutlineColor { col } => {
-                let ci = match &col {
-                    Color::Rgb(_) => "RG",
-                    Color::Cmyk(_) | Color::SpotColor(_) => "K",
-                    Color::Greyscale(_) => "G",
+                let ci = match set_color_space_op(col, color_spaces, "CS") {
+                    Some(cs) => {
+                        content.push(cs);
+                        "SC"
+                    }
+                    None => match &col {
+                        Color::Rgb(_) => "RG",
+                        Color::Cmyk(_) | Color::SpotColor(_) => "K",
+                        Color::Greyscale(_) => "G",
+                        Color::Indexed(_) | Color::DeviceN(_) => "SC",
+                    },
                 };
                 let cvec = col.into_vec().into_iter().map(Real).collect();
                 content.push(LoOp::new(ci, cvec));
@@ -602,12 +1434,32 @@ fn translate_operations(
             Op::SetLineOffset { multiplier } => {
                 content.push(LoOp::new("Ts", vec![Real(*multiplier)]));
             }
+            Op::SetHorizontalScaling { percent } => {
+                content.push(LoOp::new("Tz", vec![Real(*percent)]));
+            }
             Op::DrawLine { line } => {
                 content.append(&mut line_to_stream_ops(line));
             }
             Op::DrawPolygon { polygon } => {
                 content.append(&mut polygon_to_stream_ops(polygon));
             }
+            Op::DrawGradient {
+                clip_polygon,
+                gradient,
+            } => {
+                let clip = Polygon {
+                    mode: PaintMode::Clip,
+                    ..clip_polygon.clone()
+                };
+                content.push(LoOp::new("q", vec![]));
+                content.append(&mut polygon_to_stream_ops(&clip));
+                if let Some(bbox) = gradient.get_bbox_matrix(clip_polygon) {
+                    content.push(LoOp::new("cm", bbox.into_iter().map(Real).collect()));
+                }
+                content.push(LoOp::new("sh", vec![Name(format!("Sh{shadings}").into())]));
+                content.push(LoOp::new("Q", vec![]));
+                shadings += 1;
+            }
             Op::SetTransformationMatrix { matrix } => {
                 content.push(LoOp::new(
                     "cm",
@@ -623,6 +1475,12 @@ fn translate_operations(
             Op::LinkAnnotation { link } => {
                 // TODO!
             }
+            Op::SetRenderingIntent { intent } => {
+                content.push(LoOp::new("ri", vec![Name(intent.get_id().into())]));
+            }
+            Op::AddFormField { .. } | Op::AddAnnotation { .. } => {
+                // written to the /Annots of the page, not part of the content stream
+            }
             Op::UseXObject { id, transform } => {
                 use crate::matrix::CurTransMat;
                 let mut t = CurTransMat::Identity;
@@ -637,6 +1495,9 @@ fn translate_operations(
                     "cm",
                     t.as_array().into_iter().map(Real).collect(),
                 ));
+                if let Some(intent) = transform.rendering_intent {
+                    content.push(LoOp::new("ri", vec![Name(intent.get_id().into())]));
+                }
                 content.push(LoOp::new("Do", vec![Name(id.0.as_bytes().to_vec())]));
                 content.push(LoOp::new("Q", vec![]));
             }
@@ -671,8 +1532,38 @@ struct PreparedFont {
     widths_list: Vec<lopdf::Object>,
 }
 
+impl PreparedFont {
+    /// `font` is the embedded (subset) font, `glyph_ids` are its glyph IDs that are used
+    fn new(
+        font_id: &FontId,
+        font: ParsedFont,
+        subset_font: SubsetFont,
+        glyph_ids: &BTreeMap<u16, char>,
+    ) -> Self {
+        Self {
+            cid_to_unicode_map: font.generate_cid_to_unicode_map(font_id, glyph_ids),
+            vertical_writing: false, // !font.vmtx_data.is_empty(),
+            ascent: font.font_metrics.ascender as i64,
+            descent: font.font_metrics.descender as i64,
+            widths_list: font.get_normalized_widths(glyph_ids),
+            max_height: font.get_max_height(glyph_ids),
+            total_width: font.get_total_width(glyph_ids),
+            original: font,
+            subset_font,
+        }
+    }
+}
+
 const DEFAULT_CHARACTER_WIDTH: i64 = 1000;
 
+/// Selects the color space resource (`CSn`) of an indexed, DeviceN or ICC based color,
+/// `op` is `cs` for filling or `CS` for stroking. Returns `None` for device colors.
+fn set_color_space_op(col: &Color, color_spaces: &[ColorSpaceResource], op: &str) -> Option<LoOp> {
+    let cs = col.get_color_space_resource()?;
+    let index = color_spaces.iter().position(|c| *c == cs)?;
+    Some(LoOp::new(op, vec![Name(format!("CS{index}").into())]))
+}
+
 fn line_to_stream_ops(line: &Line) -> Vec<LoOp> {
     /// Cubic bezier over four following points
     pub const OP_PATH_CONST_4BEZIER: &str = "c";
@@ -882,11 +1773,11 @@ fn polygon_to_stream_ops(poly: &Polygon) -> Vec<LoOp> {
     operations
 }
 
-fn prepare_fonts(resources: &PdfResources, pages: &[PdfPage]) -> BTreeMap<FontId, PreparedFont> {
+fn prepare_fonts(resources: &PdfResources, content: &[&[Op]]) -> BTreeMap<FontId, PreparedFont> {
     let mut fonts_in_pdf = BTreeMap::new();
 
     for (font_id, font) in resources.fonts.map.iter() {
-        let glyph_ids = font.get_used_glyph_ids(font_id, pages);
+        let glyph_ids = font.get_used_glyph_ids(font_id, content);
         if glyph_ids.is_empty() {
             continue; // unused font
         }
@@ -902,28 +1793,33 @@ fn prepare_fonts(resources: &PdfResources, pages: &[PdfPage]) -> BTreeMap<FontId
             Some(s) => s,
             None => continue,
         };
-        let glyph_ids = font.get_used_glyph_ids(font_id, pages);
-        let cid_to_unicode = font.generate_cid_to_unicode_map(font_id, &glyph_ids);
-        let widths = font.get_normalized_widths(&glyph_ids);
-        fonts_in_pdf.insert(
-            font_id.clone(),
-            PreparedFont {
-                original: font.clone(),
-                subset_font,
-                cid_to_unicode_map: cid_to_unicode,
-                vertical_writing: false, // !font.vmtx_data.is_empty(),
-                ascent: font.font_metrics.ascender as i64,
-                descent: font.font_metrics.descender as i64,
-                widths_list: widths,
-                max_height: font.get_max_height(&glyph_ids),
-                total_width: font.get_total_width(&glyph_ids),
-            },
-        );
+        let glyph_ids = font.get_used_glyph_ids(font_id, content);
+        let prepared = PreparedFont::new(font_id, font, subset_font, &glyph_ids);
+        fonts_in_pdf.insert(font_id.clone(), prepared);
     }
 
     fonts_in_pdf
 }
 
+/// Embeds the whole font (without subsetting, the glyph IDs of the font are the CIDs), for
+/// fonts that are written before the text that uses them is known (`PdfWriterSession`)
+pub(crate) fn add_full_font_to_pdf(
+    doc: &mut lopdf::Document,
+    font_id: &FontId,
+    font: &ParsedFont,
+) -> LoDictionary {
+    // all characters of the cmap, for the widths and the ToUnicode map
+    let glyph_ids = ('\0'..=char::MAX)
+        .filter_map(|c| Some((font.lookup_glyph_index(c as u32)?, c)))
+        .collect::<BTreeMap<_, _>>();
+    let subset_font = SubsetFont {
+        bytes: font.original_bytes.clone(),
+        glyph_mapping: glyph_ids.iter().map(|(g, c)| (*g, (*g, *c))).collect(),
+    };
+    let prepared = PreparedFont::new(font_id, font.clone(), subset_font, &glyph_ids);
+    add_font_to_pdf(doc, font_id, &prepared)
+}
+
 fn add_font_to_pdf(
     doc: &mut lopdf::Document,
     font_id: &FontId,
@@ -1010,7 +1906,7 @@ fn add_font_to_pdf(
     ])
 }
 
-fn docinfo_to_dict(m: &PdfDocumentInfo) -> LoDictionary {
+pub(crate) fn docinfo_to_dict(m: &PdfDocumentInfo) -> LoDictionary {
     let trapping = if m.trapped { "True" } else { "False" };
     let gts_pdfx_version = m.conformance.get_identifier_string();
 
@@ -1082,8 +1978,9 @@ fn icc_to_stream(val: &IccProfile) -> LoStream {
 }
 
 fn link_annotation_to_dict(la: &LinkAnnotation, page_ids: &[lopdf::ObjectId]) -> LoDictionary {
-    let ll = la.rect.lower_left();
-    let ur = la.rect.upper_right();
+    let rect = la.rect.normalize();
+    let ll = rect.lower_left();
+    let ur = rect.upper_right();
 
     let mut dict: LoDictionary = LoDictionary::new();
     dict.set("Type", Name("Annot".into()));
@@ -1110,7 +2007,7 @@ fn link_annotation_to_dict(la: &LinkAnnotation, page_ids: &[lopdf::ObjectId]) ->
     dict
 }
 
-fn actions_to_dict(a: &Actions, page_ids: &[lopdf::ObjectId]) -> LoDictionary {
+pub(crate) fn actions_to_dict(a: &Actions, page_ids: &[lopdf::ObjectId]) -> LoDictionary {
     let mut dict = LoDictionary::new();
     dict.set("S", Name(a.get_action_type_id().into()));
     match a {
@@ -1120,29 +2017,47 @@ fn actions_to_dict(a: &Actions, page_ids: &[lopdf::ObjectId]) -> LoDictionary {
         Actions::URI(uri) => {
             dict.set("URI", LoString(uri.clone().into_bytes(), Literal));
         }
+        Actions::Named(name) => {
+            dict.set("N", Name(name.as_bytes().to_vec()));
+        }
     }
     dict
 }
 
-fn destination_to_obj(d: &Destination, page_ids: &[lopdf::ObjectId]) -> lopdf::Object {
-    match d {
+pub(crate) fn destination_to_obj(d: &Destination, page_ids: &[lopdf::ObjectId]) -> lopdf::Object {
+    let opt = |v: &Option<f32>| v.map(Real).unwrap_or(Null);
+    let (kind, params) = match d {
+        Destination::Named(name) => return LoString(name.as_bytes().to_vec(), Literal),
         Destination::XYZ {
-            page,
+            left, top, zoom, ..
+        } => ("XYZ", vec![opt(left), opt(top), opt(zoom)]),
+        Destination::Fit { .. } => ("Fit", Vec::new()),
+        Destination::FitH { top, .. } => ("FitH", vec![opt(top)]),
+        Destination::FitV { left, .. } => ("FitV", vec![opt(left)]),
+        Destination::FitR {
             left,
+            bottom,
+            right,
             top,
-            zoom,
-        } => Array(vec![
-            page_ids
-                .get(page.saturating_sub(1))
-                .copied()
-                .map(Reference)
-                .unwrap_or(Null),
-            Name("XYZ".into()),
-            left.map(Real).unwrap_or(Null),
-            top.map(Real).unwrap_or(Null),
-            zoom.map(Real).unwrap_or(Null),
-        ]),
-    }
+            ..
+        } => (
+            "FitR",
+            vec![Real(*left), Real(*bottom), Real(*right), Real(*top)],
+        ),
+        Destination::FitB { .. } => ("FitB", Vec::new()),
+        Destination::FitBH { top, .. } => ("FitBH", vec![opt(top)]),
+        Destination::FitBV { left, .. } => ("FitBV", vec![opt(left)]),
+    };
+
+    let page = d
+        .get_page()
+        .and_then(|page| page_ids.get(page.saturating_sub(1)))
+        .copied()
+        .map(Reference)
+        .unwrap_or(Null);
+    let mut array = vec![page, Name(kind.into())];
+    array.extend(params);
+    Array(array)
 }
 
 fn color_array_to_f32(c: &ColorArray) -> Vec<f32> {
@@ -1153,3 +2068,85 @@ fn color_array_to_f32(c: &ColorArray) -> Vec<f32> {
         ColorArray::CMYK(arr) => arr.to_vec(),
     }
 }
+
+#[test]
+fn test_object_streams() {
+    use crate::{Mm, PdfPage, Pt};
+
+    let mut doc = PdfDocument::new("object streams");
+    for i in 0..3 {
+        let ops = vec![Op::WriteTextBuiltinFont {
+            text: format!("page (\\{i})"),
+            size: Pt(12.0),
+            font: BuiltinFont::Helvetica,
+        }];
+        doc.pages.push(PdfPage::new(Mm(210.0), Mm(297.0), ops));
+    }
+    let classic = doc.save(&PdfSaveOptions::default());
+    let opts = PdfSaveOptions {
+        use_object_streams: true,
+        ..Default::default()
+    };
+    let bytes = doc.save(&opts);
+    assert!(bytes.starts_with(b"%PDF-1.5"));
+    let find = |needle: &[u8]| bytes.windows(needle.len()).any(|w| w == needle);
+    assert!(find(b"/Type /ObjStm"));
+    assert!(find(b"/Type /XRef"));
+    assert!(!find(b"\nxref\n"));
+    assert!(bytes.len() < classic.len());
+
+    let parsed = PdfDocument::parse(&bytes, &Default::default()).unwrap();
+    assert_eq!(parsed.pages.len(), 3);
+    assert_eq!(parsed.pages[0].media_box.width, Pt::from(Mm(210.0)));
+}
+
+#[test]
+fn test_stream_compression() {
+    use crate::{Mm, PdfPage, Pt, RawImage, RawImageData, RawImageFormat};
+
+    let mut doc = PdfDocument::new("compression");
+    let image = RawImage {
+        pixels: RawImageData::U8((0..64 * 64 * 3).map(|i| (i % 192) as u8).collect()),
+        width: 64,
+        height: 64,
+        data_format: RawImageFormat::RGB8,
+        tag: Vec::new(),
+        source: None,
+    };
+    let id = doc.add_image(&image);
+    let ops = vec![Op::WriteTextBuiltinFont {
+        text: "readable".to_string(),
+        size: Pt(12.0),
+        font: BuiltinFont::Helvetica,
+    }];
+    doc.pages.push(PdfPage::new(Mm(210.0), Mm(297.0), ops));
+    let save = |compression, compress_content_streams| {
+        doc.save(&PdfSaveOptions {
+            compression,
+            compress_content_streams,
+            ..Default::default()
+        })
+    };
+    let find = |bytes: &[u8], needle: &[u8]| bytes.windows(needle.len()).any(|w| w == needle);
+
+    // the page content stays readable by default
+    let bytes = save(StreamCompression::default(), false);
+    assert!(find(&bytes, b"> Tj"));
+    assert!(find(&bytes, b"/FlateDecode"));
+    assert!(!find(&save(StreamCompression::default(), true), b"> Tj"));
+
+    let stored = save(StreamCompression::Flate { level: 0 }, false);
+    let smallest = save(StreamCompression::Flate { level: 9 }, false);
+    assert!(smallest.len() < stored.len());
+
+    let uncompressed = save(StreamCompression::None, false);
+    assert!(!find(&uncompressed, b"/Filter"));
+
+    let lzw = save(StreamCompression::Lzw, false);
+    assert!(find(&lzw, b"/LZWDecode"));
+    let parsed = PdfDocument::parse(&lzw, &Default::default()).unwrap();
+    let Some(XObject::Image(parsed_image)) = parsed.resources.xobjects.map.get(&id) else {
+        panic!("image was not parsed");
+    };
+    assert_eq!(parsed_image.pixels().unwrap().as_ref(), &image.pixels);
+}
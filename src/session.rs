@@ -0,0 +1,836 @@
+//! Writing documents page by page, i.e. for logs or generators that produce an unknown
+//! number of pages, or for huge documents that don't fit into memory
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+
+use allsorts::tables::cmap::owned::CmapSubtable as OwnedCmapSubtable;
+use lopdf::Object::{Array, Dictionary, Integer, Name, Real, Reference, String as LoString};
+use lopdf::{Dictionary as LoDictionary, Object, ObjectId, StringFormat};
+use sha2::{Digest, Sha256};
+
+use crate::serialize::{
+    add_full_font_to_pdf, compress_streams, docinfo_to_dict, serialize_pdf_document, write_object,
+};
+use crate::{
+    FontId, Mm, Op, ParsedFont, PdfDocument, PdfPage, PdfSaveOptions, PdfWarnMsg, RawImage,
+    RawImageFormat, StreamCompression, XObject, XObjectId,
+};
+
+/// Writes a PDF incrementally: every appended page is written and flushed immediately,
+/// followed by an incremental update (new page tree, cross-reference section and trailer),
+/// so the output is a valid PDF with all pages written so far after every
+/// `append_page`, even if the process crashes later.
+///
+/// The fonts, XObjects (images) and graphics states of the `document` of
+/// `PdfWriterSession::new` are written once at the start and are not kept in memory, the
+/// pages only reference the ones that they use. The fonts are embedded without subsetting,
+/// since the glyphs of the later pages are unknown (glyphs that are not in the cmap of the
+/// font, i.e. ligatures, can't be copied as text). Links to other pages, bookmarks, forms,
+/// the structure tree and encryption are not supported.
+pub struct PdfWriterSession<W: Write> {
+    writer: W,
+    /// Metadata and the resources that are written with every page, without pages. The
+    /// images are replaced with placeholders without pixels.
+    document: PdfDocument,
+    opts: PdfSaveOptions,
+    /// Write an incremental update after every page, otherwise the page tree and the
+    /// cross-reference table are only written by `finish` (`PdfStreamWriter`)
+    incremental: bool,
+    /// Number of bytes written so far
+    offset: u64,
+    /// Offsets of the objects written since the last cross-reference section
+    offsets: BTreeMap<u32, u64>,
+    /// Offset of the last cross-reference section
+    last_xref: u64,
+    next_id: u32,
+    catalog_id: u32,
+    info_id: u32,
+    pages_id: u32,
+    page_ids: Vec<u32>,
+    /// Object IDs of the written streams without references, by the hash of their
+    /// serialized bytes
+    written_streams: BTreeMap<[u8; 32], u32>,
+    /// Object IDs of the fonts, XObjects and graphics states written by `begin` and
+    /// `PdfStreamWriter::add_image`, by resource type and name
+    written_resources: BTreeMap<(&'static str, String), u32>,
+    /// cmaps of the written fonts, for encoding the text of the pages
+    font_cmaps: BTreeMap<FontId, Option<OwnedCmapSubtable>>,
+    trailer_id: Object,
+    warnings: Vec<PdfWarnMsg>,
+}
+
+impl<W: Write> PdfWriterSession<W> {
+    /// Starts the session and writes a document without pages (catalog, document info,
+    /// the resources and an empty page tree). The pages of the `document` are ignored.
+    pub fn new(writer: W, document: PdfDocument, opts: PdfSaveOptions) -> Result<Self, String> {
+        Self::begin(writer, document, opts, true)
+    }
+
+    fn begin(
+        writer: W,
+        mut document: PdfDocument,
+        opts: PdfSaveOptions,
+        incremental: bool,
+    ) -> Result<Self, String> {
+        if opts.encryption.is_some() {
+            return Err("writing page by page does not support encryption".to_string());
+        }
+        document.pages.clear();
+        let fonts = std::mem::take(&mut document.resources.fonts.map);
+        let document_id = crate::utils::random_character_string_32();
+        let instance_id = crate::utils::random_character_string_32();
+        let mut session = Self {
+            writer,
+            document,
+            opts,
+            incremental,
+            offset: 0,
+            offsets: BTreeMap::new(),
+            last_xref: 0,
+            next_id: 4,
+            catalog_id: 1,
+            info_id: 2,
+            pages_id: 3,
+            page_ids: Vec::new(),
+            written_streams: BTreeMap::new(),
+            written_resources: BTreeMap::new(),
+            font_cmaps: BTreeMap::new(),
+            trailer_id: Array(vec![
+                LoString(document_id.into_bytes(), StringFormat::Literal),
+                LoString(instance_id.into_bytes(), StringFormat::Literal),
+            ]),
+            warnings: Vec::new(),
+        };
+
+        session.write(b"%PDF-1.3\n%\xe2\xe3\xcf\xd3\n")?;
+        let catalog = LoDictionary::from_iter(vec![
+            ("Type", Name("Catalog".into())),
+            ("Pages", Reference((session.pages_id, 0))),
+        ]);
+        let info = docinfo_to_dict(&session.document.metadata.info);
+        session.write_object(session.catalog_id, &Dictionary(catalog))?;
+        session.write_object(session.info_id, &Dictionary(info))?;
+        session.write_resources(fonts)?;
+        if incremental {
+            session.write_page_tree()?;
+            session.write_xref()?;
+        }
+        Ok(session)
+    }
+
+    /// Writes the fonts (without subsetting), XObjects and graphics states of the document.
+    /// Only placeholders without pixels are kept for the images, for their size in
+    /// `Op::UseXObject`.
+    fn write_resources(&mut self, fonts: BTreeMap<FontId, ParsedFont>) -> Result<(), String> {
+        let compression = self.get_compression();
+        for (font_id, font) in fonts {
+            let mut doc = lopdf::Document::with_version("1.3");
+            let dict = add_full_font_to_pdf(&mut doc, &font_id, &font);
+            let dict_id = doc.add_object(dict);
+            compress_streams(&mut doc, compression);
+            let ids = self.copy_objects(&mut doc, &[dict_id], |_, _| {})?;
+            self.written_resources
+                .insert(("Font", font_id.0.clone()), ids[&dict_id]);
+            self.font_cmaps.insert(font_id, font.cmap_subtable);
+        }
+
+        let resources = &mut self.document.resources;
+        if resources.xobjects.map.is_empty() && resources.extgstates.map.is_empty() {
+            return Ok(());
+        }
+        for xobject in resources.xobjects.map.values_mut() {
+            if let Some(ops) = xobject.get_ops_mut() {
+                *ops = encode_text_ops(ops, &self.font_cmaps);
+            }
+        }
+
+        // the XObjects and graphics states are written with an empty page, their
+        // dictionaries are shared with the resources of the group XObjects
+        self.document
+            .pages
+            .push(PdfPage::new(Mm(10.0), Mm(10.0), Vec::new()));
+        let mut warnings = Vec::new();
+        let mut doc = serialize_pdf_document(&self.document, &self.opts, &mut warnings, false);
+        self.document.pages.clear();
+        self.add_warnings(warnings);
+
+        let page_id = get_page_id(&doc)?;
+        let [font_dict_id, xobject_dict_id, extgstate_dict_id] =
+            ["Font", "XObject", "ExtGState"].map(|key| get_resource_dict_id(&doc, page_id, key));
+        let (Some(font_dict_id), Some(xobject_dict_id), Some(extgstate_dict_id)) =
+            (font_dict_id, xobject_dict_id, extgstate_dict_id)
+        else {
+            return Err("serialized resources not found".to_string());
+        };
+
+        // the graphics states are written as objects, so that the pages can reference them
+        if let Ok(Dictionary(extgstates)) = doc.get_object(extgstate_dict_id).cloned() {
+            let mut referenced = LoDictionary::new();
+            for (name, extgstate) in extgstates.into_iter() {
+                referenced.set(name, Reference(doc.add_object(extgstate)));
+            }
+            doc.set_object(extgstate_dict_id, referenced);
+        }
+        let mut entries = Vec::new();
+        for (resource_type, dict_id) in [
+            ("XObject", xobject_dict_id),
+            ("ExtGState", extgstate_dict_id),
+        ] {
+            let Ok(dict) = doc.get_dictionary(dict_id) else {
+                continue;
+            };
+            for (name, value) in dict.iter() {
+                if let Ok(id) = value.as_reference() {
+                    let name = String::from_utf8_lossy(name).to_string();
+                    entries.push((resource_type, name, id));
+                }
+            }
+        }
+
+        let fonts = self.get_written("Font");
+        let ids = self.copy_objects(
+            &mut doc,
+            &[font_dict_id, xobject_dict_id, extgstate_dict_id],
+            |id, obj| match obj {
+                Dictionary(dict) if id == font_dict_id => {
+                    for (name, written_id) in fonts.iter() {
+                        dict.set(name.as_bytes(), Reference((*written_id, 0)));
+                    }
+                }
+                _ => {}
+            },
+        )?;
+        for (resource_type, name, id) in entries {
+            if let Some(written_id) = ids.get(&id) {
+                self.written_resources
+                    .insert((resource_type, name), *written_id);
+            }
+        }
+
+        let resources = &mut self.document.resources;
+        resources.extgstates.map.clear();
+        resources.xobjects.map = std::mem::take(&mut resources.xobjects.map)
+            .into_iter()
+            .filter_map(|(id, xobject)| {
+                let (width, height) = xobject.get_width_height()?;
+                let placeholder = RawImage::empty(width.0, height.0, RawImageFormat::R8);
+                Some((id, XObject::Image(placeholder)))
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// Writes the page and its resources, updates the page tree and flushes the writer
+    pub fn append_page(&mut self, mut page: PdfPage) -> Result<(), String> {
+        // the fonts, XObjects and graphics states were written by `begin`, the page only
+        // references the ones that it uses
+        let used = get_used_resources(&page.ops)
+            .into_iter()
+            .filter_map(|key| {
+                let written_id = *self.written_resources.get(&key)?;
+                Some((key.0, key.1, written_id))
+            })
+            .collect::<Vec<_>>();
+        page.ops = encode_text_ops(&page.ops, &self.font_cmaps);
+
+        // serializes a document with only this page, then copies the page and the
+        // objects that it references
+        self.document.pages.push(page);
+        let mut warnings = Vec::new();
+        let mut doc = serialize_pdf_document(&self.document, &self.opts, &mut warnings, false);
+        self.document.pages.clear();
+        self.add_warnings(warnings);
+
+        let page_id = get_page_id(&doc)?;
+        let resources_id = doc
+            .get_dictionary(page_id)
+            .ok()
+            .and_then(|p| p.get(b"Resources").ok())
+            .and_then(|r| r.as_reference().ok());
+        // builtin fonts are written with the page
+        let font_dict_id = get_resource_dict_id(&doc, page_id, "Font");
+        // the placeholders of the images and the graphics states are not copied
+        if let Some(Ok(Dictionary(resources))) = resources_id.map(|id| doc.get_object_mut(id)) {
+            resources.remove(b"XObject");
+            resources.remove(b"ExtGState");
+        }
+
+        let ids = self.copy_objects(&mut doc, &[page_id], |id, obj| {
+            let Dictionary(dict) = obj else {
+                return;
+            };
+            if Some(id) == resources_id {
+                for key in ["XObject", "ExtGState"] {
+                    let entries = used
+                        .iter()
+                        .filter(|(resource_type, _, _)| *resource_type == key)
+                        .map(|(_, name, written_id)| (name.clone(), Reference((*written_id, 0))))
+                        .collect::<Vec<_>>();
+                    if !entries.is_empty() {
+                        dict.set(key, Dictionary(LoDictionary::from_iter(entries)));
+                    }
+                }
+            }
+            if Some(id) == font_dict_id {
+                for (_, name, written_id) in used.iter().filter(|(t, _, _)| *t == "Font") {
+                    dict.set(name.as_bytes(), Reference((*written_id, 0)));
+                }
+            }
+        })?;
+        self.page_ids.push(ids[&page_id]);
+        if self.incremental {
+            self.write_page_tree()?;
+            self.write_xref()?;
+        }
+        Ok(())
+    }
+
+    /// Number of pages written so far
+    pub fn get_page_count(&self) -> usize {
+        self.page_ids.len()
+    }
+
+    /// Warnings of the serialized pages (each warning is only reported once)
+    pub fn get_warnings(&self) -> &[PdfWarnMsg] {
+        &self.warnings
+    }
+
+    /// Ends the session and returns the writer. The output is already complete after
+    /// every `append_page`, so this only flushes the writer.
+    pub fn finish(mut self) -> Result<W, String> {
+        if !self.incremental {
+            self.write_page_tree()?;
+            self.write_xref()?;
+        }
+        self.writer.flush().map_err(|e| e.to_string())?;
+        Ok(self.writer)
+    }
+
+    /// Objects IDs of the written resources of a type, by name
+    fn get_written(&self, resource_type: &str) -> Vec<(String, u32)> {
+        self.written_resources
+            .iter()
+            .filter(|((t, _), _)| *t == resource_type)
+            .map(|((_, name), id)| (name.clone(), *id))
+            .collect()
+    }
+
+    /// Compression of the streams, LZW falls back to Flate if the conformance doesn't allow it
+    fn get_compression(&self) -> StreamCompression {
+        match self.opts.compression {
+            StreamCompression::Lzw if !self.document.metadata.info.conformance.is_lzw_allowed() => {
+                StreamCompression::default()
+            }
+            c => c,
+        }
+    }
+
+    fn add_warnings(&mut self, warnings: Vec<PdfWarnMsg>) {
+        for warning in warnings {
+            if !self.warnings.contains(&warning) {
+                self.warnings.push(warning);
+            }
+        }
+    }
+
+    /// Writes the objects that are reachable from the `roots` (streams without references
+    /// that were already written are reused) and removes them from the `doc`. `patch` is
+    /// called with the original ID of every written object after renumbering. Returns the
+    /// new object numbers.
+    fn copy_objects(
+        &mut self,
+        doc: &mut lopdf::Document,
+        roots: &[ObjectId],
+        patch: impl Fn(ObjectId, &mut Object),
+    ) -> Result<BTreeMap<ObjectId, u32>, String> {
+        let mut ids = BTreeMap::new();
+        let mut new_objects = Vec::new();
+        for id in collect_objects(doc, roots) {
+            let Some(obj) = doc.objects.get(&id) else {
+                continue;
+            };
+            let is_leaf_stream = matches!(obj, Object::Stream(_)) && get_references(obj).is_empty();
+            let key = is_leaf_stream.then(|| {
+                let mut bytes = Vec::new();
+                write_object(&mut bytes, obj);
+                <[u8; 32]>::from(Sha256::digest(&bytes))
+            });
+            match key.as_ref().and_then(|k| self.written_streams.get(k)) {
+                Some(written) => {
+                    ids.insert(id, *written);
+                }
+                None => {
+                    let new_id = self.next_id;
+                    self.next_id += 1;
+                    ids.insert(id, new_id);
+                    if let Some(key) = key {
+                        self.written_streams.insert(key, new_id);
+                    }
+                    new_objects.push((id, new_id));
+                }
+            }
+        }
+
+        for (old_id, new_id) in new_objects {
+            let Some(mut obj) = doc.objects.remove(&old_id) else {
+                continue;
+            };
+            renumber(&mut obj, &ids, self.pages_id);
+            patch(old_id, &mut obj);
+            self.write_object(new_id, &obj)?;
+        }
+        Ok(ids)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.writer.write_all(bytes).map_err(|e| e.to_string())?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Writes an indirect object and remembers its offset for the next cross-reference section
+    fn write_object(&mut self, id: u32, obj: &Object) -> Result<(), String> {
+        self.offsets.insert(id, self.offset);
+        let mut bytes = format!("{id} 0 obj\n").into_bytes();
+        write_object(&mut bytes, obj);
+        bytes.extend_from_slice(b"\nendobj\n");
+        self.write(&bytes)
+    }
+
+    fn write_page_tree(&mut self) -> Result<(), String> {
+        let pages = LoDictionary::from_iter(vec![
+            ("Type", Name("Pages".into())),
+            ("Count", Integer(self.page_ids.len() as i64)),
+            (
+                "Kids",
+                Array(self.page_ids.iter().map(|id| Reference((*id, 0))).collect()),
+            ),
+        ]);
+        self.write_object(self.pages_id, &Dictionary(pages))
+    }
+
+    /// Writes the cross-reference section of the objects written since the last section
+    /// and the trailer (pointing to the previous section), then flushes the writer
+    fn write_xref(&mut self) -> Result<(), String> {
+        let xref_offset = self.offset;
+        let mut out = b"xref\n".to_vec();
+        let mut entries = std::mem::take(&mut self.offsets)
+            .into_iter()
+            .map(|(id, offset)| (id, Some(offset)))
+            .collect::<Vec<_>>();
+        if self.last_xref == 0 {
+            // the first section contains the head of the list of free objects
+            entries.insert(0, (0, None));
+        }
+        // subsections of consecutive object numbers
+        for run in entries.chunk_by(|a, b| a.0 + 1 == b.0) {
+            out.extend_from_slice(format!("{} {}\n", run[0].0, run.len()).as_bytes());
+            for (_, offset) in run {
+                match offset {
+                    Some(offset) => {
+                        out.extend_from_slice(format!("{offset:010} 00000 n\r\n").as_bytes())
+                    }
+                    None => out.extend_from_slice(b"0000000000 65535 f\r\n"),
+                }
+            }
+        }
+
+        let mut trailer = LoDictionary::from_iter(vec![
+            ("Size", Integer(self.next_id as i64)),
+            ("Root", Reference((self.catalog_id, 0))),
+            ("Info", Reference((self.info_id, 0))),
+            ("ID", self.trailer_id.clone()),
+        ]);
+        if self.last_xref != 0 {
+            trailer.set("Prev", Integer(self.last_xref as i64));
+        }
+        out.extend_from_slice(b"trailer\n");
+        write_object(&mut out, &Dictionary(trailer));
+        out.extend_from_slice(format!("\nstartxref\n{xref_offset}\n%%EOF\n").as_bytes());
+        self.write(&out)?;
+        self.last_xref = xref_offset;
+        self.writer.flush().map_err(|e| e.to_string())
+    }
+}
+
+/// Low-memory writer for huge documents: pages and images are written as soon as they are
+/// added and are not kept in memory, only the object offsets are. The page tree and the
+/// cross-reference table are written by `finish`, so (unlike `PdfWriterSession`) the
+/// output is only a valid PDF after `finish`.
+///
+/// The fonts and other resources are taken from the `document` of `PdfStreamWriter::begin`,
+/// with the same limitations as `PdfWriterSession`. Images should be added with `add_image`,
+/// so that they are never all in memory at the same time.
+pub struct PdfStreamWriter<W: Write> {
+    session: PdfWriterSession<W>,
+}
+
+impl<W: Write> PdfStreamWriter<W> {
+    /// Writes the header, catalog, document info and the resources of the `document` (see
+    /// `PdfWriterSession`), the pages of the `document` are ignored.
+    ///
+    /// The `document` has to fit into memory, so the images should not be part of it: add
+    /// them with `add_image` instead, which writes them immediately.
+    pub fn begin(writer: W, document: PdfDocument, opts: PdfSaveOptions) -> Result<Self, String> {
+        Ok(Self {
+            session: PdfWriterSession::begin(writer, document, opts, false)?,
+        })
+    }
+
+    /// Writes the image immediately, returns the ID for `Op::UseXObject`. The pixels are
+    /// not kept in memory.
+    pub fn add_image(&mut self, image: &RawImage) -> Result<XObjectId, String> {
+        let session = &mut self.session;
+        let converted = match session.opts.color_transform.as_ref() {
+            Some(transform) => transform.convert_image(image)?,
+            None => None,
+        };
+        let image = converted.as_ref().unwrap_or(image);
+
+        let mut doc = lopdf::Document::with_version("1.3");
+        let stream = crate::image::image_to_stream(image.clone(), &mut doc);
+        let stream_id = doc.add_object(stream);
+        compress_streams(&mut doc, session.get_compression());
+        let ids = session.copy_objects(&mut doc, &[stream_id], |_, _| {})?;
+
+        let id = XObjectId::new();
+        session
+            .written_resources
+            .insert(("XObject", id.0.clone()), ids[&stream_id]);
+        // placeholder without pixels, for the size of the image in `Op::UseXObject`
+        let placeholder = RawImage::empty(image.width, image.height, RawImageFormat::R8);
+        session
+            .document
+            .resources
+            .xobjects
+            .map
+            .insert(id.clone(), XObject::Image(placeholder));
+        Ok(id)
+    }
+
+    /// Writes the page and the resources that it uses
+    pub fn add_page(&mut self, page: PdfPage) -> Result<(), String> {
+        self.session.append_page(page)
+    }
+
+    /// Number of pages written so far
+    pub fn get_page_count(&self) -> usize {
+        self.session.get_page_count()
+    }
+
+    /// Warnings of the serialized pages (each warning is only reported once)
+    pub fn get_warnings(&self) -> &[PdfWarnMsg] {
+        self.session.get_warnings()
+    }
+
+    /// Writes the page tree, cross-reference table and trailer, returns the writer
+    pub fn finish(self) -> Result<W, String> {
+        self.session.finish()
+    }
+}
+
+/// ID of the first page of a serialized document
+fn get_page_id(doc: &lopdf::Document) -> Result<ObjectId, String> {
+    doc.catalog()
+        .ok()
+        .and_then(|c| c.get(b"Pages").ok())
+        .and_then(|p| p.as_reference().ok())
+        .and_then(|p| doc.get_dictionary(p).ok())
+        .and_then(|p| p.get(b"Kids").ok())
+        .and_then(|k| k.as_array().ok())
+        .and_then(|k| k.first())
+        .and_then(|p| p.as_reference().ok())
+        .ok_or_else(|| "serialized page not found".to_string())
+}
+
+/// ID of a resource dictionary (i.e. `/Font`) of a serialized page
+fn get_resource_dict_id(doc: &lopdf::Document, page_id: ObjectId, key: &str) -> Option<ObjectId> {
+    doc.get_dictionary(page_id)
+        .ok()
+        .and_then(|p| p.get(b"Resources").ok())
+        .and_then(|r| match r {
+            Reference(id) => doc.get_dictionary(*id).ok(),
+            Dictionary(d) => Some(d),
+            _ => None,
+        })
+        .and_then(|r| r.get(key.as_bytes()).ok())
+        .and_then(|d| d.as_reference().ok())
+}
+
+/// Fonts, XObjects and graphics states that the ops use, by resource type and name
+fn get_used_resources(ops: &[Op]) -> BTreeSet<(&'static str, String)> {
+    ops.iter()
+        .filter_map(|op| match op {
+            Op::WriteText { font, .. }
+            | Op::WriteCodepoints { font, .. }
+            | Op::WriteCodepointsWithKerning { font, .. }
+            | Op::SetFontSize { font, .. } => Some(("Font", font.0.clone())),
+            Op::UseXObject { id, .. } => Some(("XObject", id.0.clone())),
+            Op::LoadGraphicsState { gs } => Some(("ExtGState", gs.0.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Writes the text of the written fonts as raw `Tf` / `Tj` / `TJ` operations: the fonts
+/// are embedded without subsetting, so the glyph IDs of the font are written as they are
+fn encode_text_ops(ops: &[Op], cmaps: &BTreeMap<FontId, Option<OwnedCmapSubtable>>) -> Vec<Op> {
+    fn glyphs(gids: impl Iterator<Item = u16>) -> Object {
+        LoString(
+            gids.flat_map(u16::to_be_bytes).collect(),
+            StringFormat::Hexadecimal,
+        )
+    }
+
+    let mut encoded = Vec::with_capacity(ops.len());
+    for op in ops {
+        let (font, size, key, operands) = match op {
+            Op::WriteText { text, font, size } if cmaps.contains_key(font) => {
+                let cmap = cmaps[font].as_ref();
+                let gids = text
+                    .chars()
+                    .filter_map(|c| cmap?.map_glyph(c as u32).ok().flatten());
+                (font, size, "Tj", vec![glyphs(gids)])
+            }
+            Op::WriteCodepoints { font, size, cp } if cmaps.contains_key(font) => (
+                font,
+                size,
+                "Tj",
+                vec![glyphs(cp.iter().map(|(gid, _)| *gid))],
+            ),
+            Op::WriteCodepointsWithKerning { font, size, cpk } if cmaps.contains_key(font) => {
+                let mut list = Vec::new();
+                for (kern, gid, _) in cpk.iter() {
+                    if *kern != 0 {
+                        list.push(Integer(*kern));
+                    }
+                    list.push(glyphs(std::iter::once(*gid)));
+                }
+                (font, size, "TJ", vec![Array(list)])
+            }
+            _ => {
+                encoded.push(op.clone());
+                continue;
+            }
+        };
+        encoded.push(Op::Unknown {
+            key: "Tf".to_string(),
+            value: vec![Name(font.0.clone().into_bytes()), Real(size.0)],
+        });
+        encoded.push(Op::Unknown {
+            key: key.to_string(),
+            value: operands,
+        });
+    }
+    encoded
+}
+
+/// Objects that are reachable from the roots (without following the `/Parent`)
+fn collect_objects(doc: &lopdf::Document, roots: &[ObjectId]) -> Vec<ObjectId> {
+    let mut visited = BTreeSet::new();
+    let mut order = Vec::new();
+    let mut stack = roots.to_vec();
+    while let Some(id) = stack.pop() {
+        if !visited.insert(id) {
+            continue;
+        }
+        let Some(obj) = doc.objects.get(&id) else {
+            continue;
+        };
+        order.push(id);
+        stack.extend(get_references(obj));
+    }
+    order
+}
+
+fn get_references(obj: &Object) -> Vec<ObjectId> {
+    fn collect(obj: &Object, refs: &mut Vec<ObjectId>) {
+        match obj {
+            Reference(id) => refs.push(*id),
+            Array(items) => items.iter().for_each(|o| collect(o, refs)),
+            Dictionary(dict) => collect_dict(dict, refs),
+            Object::Stream(stream) => collect_dict(&stream.dict, refs),
+            _ => {}
+        }
+    }
+    fn collect_dict(dict: &LoDictionary, refs: &mut Vec<ObjectId>) {
+        for (key, value) in dict.iter() {
+            if key.as_slice() != b"Parent" {
+                collect(value, refs);
+            }
+        }
+    }
+    let mut refs = Vec::new();
+    collect(obj, &mut refs);
+    refs
+}
+
+/// Replaces the references with the new object numbers, `/Parent` of the page points to
+/// the page tree of the session
+fn renumber(obj: &mut Object, ids: &BTreeMap<ObjectId, u32>, pages_id: u32) {
+    fn renumber_dict(dict: &mut LoDictionary, ids: &BTreeMap<ObjectId, u32>, pages_id: u32) {
+        let is_page = matches!(dict.get(b"Type"), Ok(Name(n)) if n.as_slice() == b"Page");
+        for (key, value) in dict.iter_mut() {
+            if key.as_slice() == b"Parent" && is_page {
+                *value = Reference((pages_id, 0));
+            } else {
+                renumber(value, ids, pages_id);
+            }
+        }
+    }
+    match obj {
+        Reference(id) => match ids.get(id) {
+            Some(new_id) => *id = (*new_id, 0),
+            None => *obj = Object::Null,
+        },
+        Array(items) => items.iter_mut().for_each(|o| renumber(o, ids, pages_id)),
+        Dictionary(dict) => renumber_dict(dict, ids, pages_id),
+        Object::Stream(stream) => renumber_dict(&mut stream.dict, ids, pages_id),
+        _ => {}
+    }
+}
+
+#[test]
+fn test_writer_session() {
+    use crate::{Mm, Op, Pt};
+
+    let mut doc = PdfDocument::new("log");
+    let bytes = include_bytes!("../examples/assets/fonts/RobotoMedium.ttf");
+    let font = doc.add_font(&ParsedFont::from_bytes(bytes, 0).unwrap());
+    let image = doc.add_image(&crate::RawImage {
+        pixels: crate::RawImageData::U8(vec![255, 0, 0]),
+        width: 1,
+        height: 1,
+        data_format: crate::RawImageFormat::RGB8,
+        tag: Vec::new(),
+        source: None,
+    });
+    let mut session = PdfWriterSession::new(Vec::new(), doc, PdfSaveOptions::default()).unwrap();
+    // only placeholders of the written resources are kept
+    let resources = &session.document.resources;
+    assert!(resources.fonts.map.is_empty());
+    let Some(XObject::Image(placeholder)) = resources.xobjects.map.get(&image) else {
+        panic!("placeholder not found");
+    };
+    assert!(placeholder.pixels.is_empty());
+    let empty = session.writer.clone();
+    assert_eq!(
+        PdfDocument::parse(&empty, &Default::default())
+            .unwrap()
+            .pages
+            .len(),
+        0
+    );
+
+    for i in 0..3 {
+        let ops = vec![
+            Op::UseXObject {
+                id: image.clone(),
+                transform: Default::default(),
+            },
+            Op::WriteTextBuiltinFont {
+                text: format!("line {i}"),
+                size: Pt(10.0),
+                font: crate::BuiltinFont::Courier,
+            },
+            Op::StartTextSection,
+            Op::WriteText {
+                text: format!("page {i}"),
+                size: Pt(10.0),
+                font: font.clone(),
+            },
+            Op::EndTextSection,
+        ];
+        session
+            .append_page(PdfPage::new(Mm(210.0), Mm(297.0), ops))
+            .unwrap();
+        // every intermediate state is a valid PDF
+        let parsed = PdfDocument::parse(&session.writer, &Default::default()).unwrap();
+        assert_eq!(parsed.pages.len(), i + 1);
+    }
+    assert_eq!(session.get_page_count(), 3);
+
+    // the image and the font are only written once
+    let bytes = session.finish().unwrap();
+    for key in [b"/Subtype /Image".as_slice(), b"/FontFile2"] {
+        let count = bytes.windows(key.len()).filter(|w| *w == key).count();
+        assert_eq!(count, 1);
+    }
+    let parsed = PdfDocument::parse(&bytes, &Default::default()).unwrap();
+    let text = parsed.extract_text(&Default::default());
+    assert!(text[2].contains("page 2"));
+}
+
+#[test]
+fn test_stream_writer() {
+    use crate::{Mm, Op};
+
+    // images of the document are written by `begin` and not kept in memory either
+    let mut doc = PdfDocument::new("report");
+    let logo = doc.add_image(&RawImage {
+        pixels: crate::RawImageData::U8(vec![0, 0, 255]),
+        width: 1,
+        height: 1,
+        data_format: RawImageFormat::RGB8,
+        tag: Vec::new(),
+        source: None,
+    });
+    let mut writer = PdfStreamWriter::begin(Vec::new(), doc, PdfSaveOptions::default()).unwrap();
+    let Some(XObject::Image(placeholder)) =
+        writer.session.document.resources.xobjects.map.get(&logo)
+    else {
+        panic!("placeholder not found");
+    };
+    assert!(placeholder.pixels.is_empty());
+
+    let image = writer
+        .add_image(&RawImage {
+            pixels: crate::RawImageData::U8(vec![0, 255, 0, 0, 0, 255]),
+            width: 2,
+            height: 1,
+            data_format: RawImageFormat::RGB8,
+            tag: Vec::new(),
+            source: None,
+        })
+        .unwrap();
+    // the placeholder has no pixels
+    let Some(XObject::Image(placeholder)) =
+        writer.session.document.resources.xobjects.map.get(&image)
+    else {
+        panic!("placeholder not found");
+    };
+    assert!(placeholder.pixels.is_empty());
+
+    for _ in 0..3 {
+        let ops = vec![
+            Op::UseXObject {
+                id: image.clone(),
+                transform: Default::default(),
+            },
+            Op::UseXObject {
+                id: logo.clone(),
+                transform: Default::default(),
+            },
+        ];
+        writer
+            .add_page(PdfPage::new(Mm(210.0), Mm(297.0), ops))
+            .unwrap();
+    }
+    assert_eq!(writer.get_page_count(), 3);
+    // no page tree and cross-reference table yet
+    assert!(!writer.session.writer.windows(4).any(|w| w == b"xref"));
+
+    let bytes = writer.finish().unwrap();
+    let parsed = PdfDocument::parse(&bytes, &Default::default()).unwrap();
+    assert_eq!(parsed.pages.len(), 3);
+    let Some(XObject::Image(parsed_image)) = parsed.resources.xobjects.map.get(&image) else {
+        panic!("image was not parsed");
+    };
+    assert_eq!(parsed_image.width, 2);
+    let count = bytes
+        .windows(b"/Subtype /Image".len())
+        .filter(|w| *w == b"/Subtype /Image")
+        .count();
+    assert_eq!(count, 2);
+}
@@ -0,0 +1,294 @@
+//! Digital signatures: signature fields with a reserved `/Contents` placeholder, which is
+//! filled in with a PKCS#7 / CMS signature after the document has been serialized
+
+use lopdf::Dictionary as LoDictionary;
+use lopdf::Object::{Array, Dictionary, Integer, Name, Real, Reference, String as LoString};
+use lopdf::StringFormat::{Hexadecimal, Literal};
+
+use crate::{forms::text_string, graphics::Rect, units::Pt, OffsetDateTime};
+
+/// Placeholder for the `/ByteRange` values, large enough to hold the real offsets
+const BYTE_RANGE_PLACEHOLDER: i64 = 9_999_999_999;
+
+/// Signature form field, placed on a page with `Op::AddFormField`
+///
+/// Use `PdfDocument::save_signed` to fill the field with a signature. If the document
+/// is saved with `PdfDocument::save`, the field is left empty, so that it can be signed
+/// later in a PDF viewer.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SignatureField {
+    /// Name of the field, must be unique in the document
+    pub name: String,
+    /// Position of the visible widget, use a zero-area rect for invisible signatures
+    pub rect: Rect,
+    /// Name of the person or authority signing the document
+    pub signer_name: Option<String>,
+    /// Reason for the signing, i.e. "Approved"
+    pub reason: Option<String>,
+    /// Location of the signing, i.e. "Berlin"
+    pub location: Option<String>,
+    /// Contact information of the signer
+    pub contact_info: Option<String>,
+    /// Number of bytes reserved for the DER-encoded signature (default: 8192)
+    pub reserved_size: usize,
+}
+
+impl SignatureField {
+    /// Creates a new, invisible signature field
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            rect: Rect::from_wh(Pt(0.0), Pt(0.0)),
+            signer_name: None,
+            reason: None,
+            location: None,
+            contact_info: None,
+            reserved_size: 8192,
+        }
+    }
+
+    /// Makes the signature widget visible at the given position
+    pub fn with_rect(mut self, rect: Rect) -> Self {
+        self.rect = rect;
+        self
+    }
+
+    /// Sets the name of the signer
+    pub fn with_signer_name(mut self, signer_name: &str) -> Self {
+        self.signer_name = Some(signer_name.to_string());
+        self
+    }
+
+    /// Sets the reason for signing
+    pub fn with_reason(mut self, reason: &str) -> Self {
+        self.reason = Some(reason.to_string());
+        self
+    }
+
+    /// Sets the location of the signing
+    pub fn with_location(mut self, location: &str) -> Self {
+        self.location = Some(location.to_string());
+        self
+    }
+
+    /// Sets the contact information of the signer
+    pub fn with_contact_info(mut self, contact_info: &str) -> Self {
+        self.contact_info = Some(contact_info.to_string());
+        self
+    }
+
+    /// Sets the number of bytes reserved for the signature (must be large enough to
+    /// hold the certificate chain and timestamps returned by the signer)
+    pub fn with_reserved_size(mut self, reserved_size: usize) -> Self {
+        self.reserved_size = reserved_size;
+        self
+    }
+}
+
+/// Creates the field + widget dictionary for a signature field. If `reserve_value` is set,
+/// the signature dictionary with the `/ByteRange` and `/Contents` placeholders is added.
+pub(crate) fn signature_field_to_dict(
+    s: &SignatureField,
+    page_id: lopdf::ObjectId,
+    reserve_value: bool,
+    doc: &mut lopdf::Document,
+) -> LoDictionary {
+    let rect = s.rect.normalize();
+    let ll = rect.lower_left();
+    let ur = rect.upper_right();
+
+    let mut dict = LoDictionary::from_iter(vec![
+        ("Type", Name("Annot".into())),
+        ("Subtype", Name("Widget".into())),
+        ("FT", Name("Sig".into())),
+        ("T", text_string(&s.name)),
+        (
+            "Rect",
+            Array(vec![Real(ll.x.0), Real(ll.y.0), Real(ur.x.0), Real(ur.y.0)]),
+        ),
+        // print + locked
+        ("F", Integer(4 | 128)),
+        ("P", Reference(page_id)),
+    ]);
+
+    if reserve_value {
+        let value = doc.add_object(Dictionary(signature_value_dict(s)));
+        dict.set("V", Reference(value));
+    }
+
+    dict
+}
+
+fn signature_value_dict(s: &SignatureField) -> LoDictionary {
+    let signing_date = crate::utils::to_pdf_time_stamp_metadata(&OffsetDateTime::now_utc());
+    let mut dict = LoDictionary::from_iter(vec![
+        ("Type", Name("Sig".into())),
+        ("Filter", Name("Adobe.PPKLite".into())),
+        ("SubFilter", Name("adbe.pkcs7.detached".into())),
+        (
+            "ByteRange",
+            Array(vec![
+                Integer(0),
+                Integer(BYTE_RANGE_PLACEHOLDER),
+                Integer(BYTE_RANGE_PLACEHOLDER),
+                Integer(BYTE_RANGE_PLACEHOLDER),
+            ]),
+        ),
+        ("Contents", LoString(vec![0; s.reserved_size], Hexadecimal)),
+        ("M", LoString(signing_date.into_bytes(), Literal)),
+    ]);
+
+    let optional = [
+        ("Name", &s.signer_name),
+        ("Reason", &s.reason),
+        ("Location", &s.location),
+        ("ContactInfo", &s.contact_info),
+    ];
+    for (key, value) in optional {
+        if let Some(v) = value {
+            dict.set(key, text_string(v));
+        }
+    }
+
+    dict
+}
+
+/// Fills in the `/ByteRange` and `/Contents` placeholders of a serialized document,
+/// `value_offset` is the byte offset of the signature value object (from the
+/// cross-reference table)
+///
+/// `signer` is called with the bytes covered by the signature (everything except the
+/// `/Contents` value) and has to return the DER-encoded PKCS#7 / CMS detached signature.
+pub(crate) fn sign_pdf_bytes<F>(
+    mut bytes: Vec<u8>,
+    value_offset: usize,
+    signer: F,
+) -> Result<Vec<u8>, String>
+where
+    F: FnOnce(&[u8]) -> Result<Vec<u8>, String>,
+{
+    // the placeholders are only searched in the signature value object (the /ByteRange
+    // and /Contents keys come before the strings of the signer)
+    let obj_end = find(&bytes, b"endobj", value_offset).unwrap_or(bytes.len());
+    let value = &bytes[..obj_end];
+    let byte_range_key = find(value, b"/ByteRange", value_offset)
+        .ok_or_else(|| "signature placeholder (/ByteRange) not found".to_string())?;
+    let range_start = find(value, b"[", byte_range_key)
+        .ok_or_else(|| "invalid /ByteRange placeholder".to_string())?
+        + 1;
+    let range_end = find(value, b"]", range_start)
+        .ok_or_else(|| "invalid /ByteRange placeholder".to_string())?;
+    let contents_key = find(value, b"/Contents", value_offset)
+        .ok_or_else(|| "signature placeholder (/Contents) not found".to_string())?;
+    let contents_start = find(value, b"<", contents_key)
+        .ok_or_else(|| "invalid /Contents placeholder".to_string())?;
+    let contents_end = find(value, b">", contents_start)
+        .ok_or_else(|| "invalid /Contents placeholder".to_string())?
+        + 1;
+
+    let byte_range = [0, contents_start, contents_end, bytes.len() - contents_end];
+    let byte_range_str = format!(
+        "{} {} {} {}",
+        byte_range[0], byte_range[1], byte_range[2], byte_range[3]
+    );
+    let placeholder_len = range_end - range_start;
+    if byte_range_str.len() > placeholder_len {
+        return Err("document too large for the /ByteRange placeholder".to_string());
+    }
+    let mut padded = byte_range_str.into_bytes();
+    padded.resize(placeholder_len, b' ');
+    bytes[range_start..range_end].copy_from_slice(&padded);
+
+    let mut signed_data = bytes[..contents_start].to_vec();
+    signed_data.extend_from_slice(&bytes[contents_end..]);
+    let signature = signer(&signed_data)?;
+
+    // available space between the angle brackets
+    let hex_len = contents_end - contents_start - 2;
+    let hex = signature
+        .iter()
+        .map(|b| format!("{b:02X}"))
+        .collect::<String>();
+    if hex.len() > hex_len {
+        return Err(format!(
+            "signature is {} bytes, but only {} bytes are reserved (see SignatureField::with_reserved_size)",
+            signature.len(),
+            hex_len / 2
+        ));
+    }
+    let mut padded = hex.into_bytes();
+    padded.resize(hex_len, b'0');
+    bytes[contents_start + 1..contents_end - 1].copy_from_slice(&padded);
+
+    Ok(bytes)
+}
+
+fn find(haystack: &[u8], needle: &[u8], start: usize) -> Option<usize> {
+    haystack
+        .get(start..)?
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .map(|p| p + start)
+}
+
+#[test]
+fn test_sign_pdf_bytes() {
+    let mut input = b"%PDF-1.3\n1 0 obj\n<</Contents 2 0 R>>\nendobj\n2 0 obj\n<</ByteRange [0 1 2 3]>>\nendobj\n3 0 obj\n<</ByteRange [0 9999999999 9999999999 9999999999]/Contents <"
+        .to_vec();
+    let value_offset = input.windows(7).position(|w| w == b"3 0 obj").unwrap();
+    input.extend_from_slice(&[b'0'; 16]);
+    input.extend_from_slice(b">>>\nendobj\n%%EOF");
+
+    let contents_start = input.iter().rposition(|b| *b == b'<').unwrap();
+
+    let mut signed_len = 0;
+    let signed = sign_pdf_bytes(input.clone(), value_offset, |data| {
+        signed_len = data.len();
+        Ok(vec![0xAB, 0xCD])
+    })
+    .unwrap();
+
+    assert_eq!(signed.len(), input.len());
+    assert_eq!(signed_len, input.len() - 18);
+    let s = String::from_utf8(signed).unwrap();
+    assert!(s.contains(&format!(
+        "[0 {} {} {}",
+        contents_start,
+        contents_start + 18,
+        input.len() - contents_start - 18
+    )));
+    assert!(s.contains("<ABCD000000000000>"));
+
+    // signature larger than the reserved space
+    assert!(sign_pdf_bytes(input, value_offset, |_| Ok(vec![0; 9])).is_err());
+}
+
+#[test]
+fn test_save_signed_placeholder() {
+    use crate::{FormField, Mm, Op, PdfDocument, PdfPage, PdfSaveOptions};
+
+    // the content of the first page is written before the signature value
+    let marker = vec![Op::Marker {
+        id: "ByteRange".to_string(),
+    }];
+    let field = Op::AddFormField {
+        field: Box::new(FormField::Signature(SignatureField::new("sig"))),
+    };
+    let mut doc = PdfDocument::new("signed");
+    doc.pages.push(PdfPage::new(Mm(210.0), Mm(297.0), marker));
+    doc.pages
+        .push(PdfPage::new(Mm(210.0), Mm(297.0), vec![field]));
+
+    for use_object_streams in [false, true] {
+        let opts = PdfSaveOptions {
+            use_object_streams,
+            ..Default::default()
+        };
+        let signed = doc
+            .save_signed(&opts, &mut Vec::new(), |_| Ok(vec![0xAB, 0xCD]))
+            .unwrap();
+        assert!(signed.windows(6).any(|w| w == b"<ABCD0"));
+        // the marker is left as it is
+        assert!(signed.windows(13).any(|w| w == b"/ByteRange MP"));
+    }
+}
@@ -0,0 +1,430 @@
+//! Splitting and rearranging documents: page extraction, reordering, removal and rotation
+
+use std::{
+    collections::BTreeMap,
+    ops::{Bound, RangeBounds},
+};
+
+use crate::{
+    xobject::{copy_resources, ResourceRenames},
+    Actions, Destination, DocumentPart, Op, OutlineNode, PageLabelRange, PageLabels, PageRotation,
+    PdfDocument, PdfPage,
+};
+
+impl PdfDocument {
+    /// Copies the pages in `range` (0-based page indices) into a new document. Only the
+    /// fonts, XObjects, graphics states, ICC profiles and layers that are used by these
+    /// pages are copied, so large documents can be split into small per-chapter files.
+    ///
+    /// Bookmarks, outline entries, named destinations, links and form fields are kept if
+    /// they point to the extracted pages (renumbered for the new document). The metadata,
+    /// viewer preferences, page labels and the structure tree are copied, attachments and
+    /// document parts are not.
+    pub fn extract_pages(&self, range: impl RangeBounds<usize>) -> PdfDocument {
+        let end = match range.end_bound() {
+            Bound::Included(e) => e.saturating_add(1),
+            Bound::Excluded(e) => *e,
+            Bound::Unbounded => self.pages.len(),
+        }
+        .min(self.pages.len());
+        let first = match range.start_bound() {
+            Bound::Included(s) => *s,
+            Bound::Excluded(s) => s.saturating_add(1),
+            Bound::Unbounded => 0,
+        }
+        .min(end);
+
+        let mut doc = PdfDocument::new(&self.metadata.info.document_title);
+        doc.metadata = self.metadata.clone();
+        doc.viewer_preferences = self.viewer_preferences.clone();
+        doc.structure = self.structure.clone();
+        doc.page_labels = extract_page_labels(&self.page_labels, first, end);
+        doc.named_destinations = self.named_destinations.clone();
+        doc.outline = self.outline.clone();
+        doc.bookmarks = self.bookmarks.clone();
+        doc.resources.forms = self.resources.forms.clone();
+        doc.resources.raw = self.resources.raw.clone();
+        let mut renames = ResourceRenames::default();
+        for page in &self.pages[first..end] {
+            let ops = copy_resources(
+                &page.ops,
+                &self.resources,
+                &mut doc.resources,
+                &mut renames,
+                0,
+            );
+            doc.pages.push(PdfPage {
+                ops,
+                ..page.clone()
+            });
+        }
+
+        let map = (0..self.pages.len())
+            .map(|p| (first..end).contains(&p).then(|| p - first))
+            .collect();
+        doc.remap_pages(&PageMap::new(map, &self.named_destinations));
+        doc
+    }
+
+    /// Reorders the pages, `order` contains the (0-based) indices of the current pages in
+    /// their new order and must contain every page exactly once. Bookmarks, outline entries,
+    /// named destinations, links and form fields are moved with their pages. The document
+    /// parts are removed, because their page ranges are no longer contiguous.
+    pub fn reorder_pages(&mut self, order: &[usize]) -> Result<(), String> {
+        let mut map = vec![None; self.pages.len()];
+        for (new, old) in order.iter().enumerate() {
+            match map.get_mut(*old) {
+                Some(slot @ None) => *slot = Some(new),
+                Some(Some(_)) => return Err(format!("page {old} is listed twice")),
+                None => {
+                    return Err(format!(
+                        "page {old} does not exist (document has {} pages)",
+                        self.pages.len()
+                    ))
+                }
+            }
+        }
+        if let Some(missing) = map.iter().position(|p| p.is_none()) {
+            return Err(format!("page {missing} is missing in the new order"));
+        }
+
+        let mut pages = std::mem::take(&mut self.pages)
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<_>>();
+        self.pages = order.iter().filter_map(|p| pages[*p].take()).collect();
+        self.document_parts = None;
+        self.remap_pages(&PageMap::new(map, &self.named_destinations));
+        Ok(())
+    }
+
+    /// Removes the pages with the given (0-based) indices, indices that don't exist are
+    /// ignored. Bookmarks, outline entries, named destinations, links and form fields that
+    /// point to removed pages are removed, the others are renumbered.
+    pub fn remove_pages(&mut self, pages: &[usize]) {
+        let mut next = 0;
+        let map = (0..self.pages.len())
+            .map(|p| match pages.contains(&p) {
+                true => None,
+                false => {
+                    next += 1;
+                    Some(next - 1)
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut index = 0;
+        self.pages.retain(|_| {
+            index += 1;
+            map[index - 1].is_some()
+        });
+        self.page_labels = remove_page_labels(&self.page_labels, &map);
+        if let Some(root) = self.document_parts.as_mut() {
+            root.parts = remove_document_parts(&root.parts, &map);
+        }
+        self.remap_pages(&PageMap::new(map, &self.named_destinations));
+    }
+
+    /// Rotates the pages with the given (0-based) indices clockwise, in addition to their
+    /// current rotation. Indices that don't exist are ignored.
+    pub fn rotate_pages(&mut self, pages: &[usize], rotation: PageRotation) {
+        for page in pages {
+            if let Some(page) = self.pages.get_mut(*page) {
+                page.rotation = page.rotation.rotate(rotation);
+            }
+        }
+    }
+
+    /// Renumbers all references to pages (the pages themselves have to be moved already)
+    fn remap_pages(&mut self, map: &PageMap) {
+        self.named_destinations = std::mem::take(&mut self.named_destinations)
+            .into_iter()
+            .filter_map(|(name, dest)| Some((name, map.destination(&dest)?)))
+            .collect();
+        self.outline = remap_outline(&self.outline, map);
+        self.bookmarks
+            .map
+            .retain(|_, bookmark| match map.page(bookmark.page) {
+                Some(page) => {
+                    bookmark.page = page;
+                    true
+                }
+                None => false,
+            });
+        self.resources
+            .forms
+            .map
+            .retain(|_, field| match map.page(field.page) {
+                Some(page) => {
+                    field.page = page;
+                    true
+                }
+                None => false,
+            });
+        self.open_action = self.open_action.take().and_then(|a| map.actions(&a));
+        for page in self.pages.iter_mut() {
+            page.ops.retain_mut(|op| match op {
+                // links to removed pages are removed
+                Op::LinkAnnotation { link } => match map.actions(&link.actions) {
+                    Some(actions) => {
+                        link.actions = actions;
+                        true
+                    }
+                    None => false,
+                },
+                _ => true,
+            });
+            page.actions.open = page.actions.open.take().and_then(|a| map.actions(&a));
+            page.actions.close = page.actions.close.take().and_then(|a| map.actions(&a));
+        }
+    }
+}
+
+/// Maps the page numbers of the original document to the new page numbers
+struct PageMap {
+    /// New index of each (0-based) page, `None` if the page is removed
+    pages: Vec<Option<usize>>,
+    /// Named destinations that point to pages that are not removed
+    names: Vec<String>,
+}
+
+impl PageMap {
+    fn new(pages: Vec<Option<usize>>, named_destinations: &BTreeMap<String, Destination>) -> Self {
+        let names = named_destinations
+            .iter()
+            .filter(|(_, dest)| {
+                dest.get_page()
+                    .and_then(|p| p.checked_sub(1))
+                    .and_then(|p| pages.get(p).copied().flatten())
+                    .is_some()
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        Self { pages, names }
+    }
+
+    /// Maps a 0-based page index, `None` if the page is removed
+    fn page(&self, page: usize) -> Option<usize> {
+        self.pages.get(page).copied().flatten()
+    }
+
+    fn destination(&self, dest: &Destination) -> Option<Destination> {
+        let mut dest = dest.clone();
+        match dest.get_page() {
+            Some(page) => dest.set_page(self.page(page.checked_sub(1)?)? + 1),
+            None => match &dest {
+                Destination::Named(name) if !self.names.contains(name) => return None,
+                _ => {}
+            },
+        }
+        Some(dest)
+    }
+
+    fn actions(&self, actions: &Actions) -> Option<Actions> {
+        match actions {
+            Actions::GoTo(dest) => self.destination(dest).map(Actions::GoTo),
+            other => Some(other.clone()),
+        }
+    }
+}
+
+/// Outline entries that point to remaining pages, children of removed entries move up
+fn remap_outline(nodes: &[OutlineNode], map: &PageMap) -> Vec<OutlineNode> {
+    let mut outline = Vec::new();
+    for node in nodes {
+        let children = remap_outline(&node.children, map);
+        match map.destination(&node.dest) {
+            Some(dest) => outline.push(OutlineNode {
+                dest,
+                children,
+                ..node.clone()
+            }),
+            None => outline.extend(children),
+        }
+    }
+    outline
+}
+
+/// Page labels of the pages `first..end`, the numbering of the first range continues
+fn extract_page_labels(labels: &PageLabels, first: usize, end: usize) -> PageLabels {
+    if labels.is_empty() {
+        return PageLabels::default();
+    }
+    let ranges = labels.get_sorted_ranges();
+    let mut extracted = Vec::new();
+    if let Some(r) = ranges.iter().rev().find(|r| r.start_page <= first) {
+        extracted.push(PageLabelRange {
+            start_page: 0,
+            first_number: r.first_number + (first - r.start_page),
+            ..r.clone()
+        });
+    }
+    extracted.extend(
+        ranges
+            .iter()
+            .filter(|r| r.start_page > first && r.start_page < end)
+            .map(|r| PageLabelRange {
+                start_page: r.start_page - first,
+                ..r.clone()
+            }),
+    );
+    PageLabels { ranges: extracted }
+}
+
+/// Moves the label ranges to the first remaining page of the range (ranges without
+/// remaining pages are removed), the numbering continues after removed pages
+fn remove_page_labels(labels: &PageLabels, map: &[Option<usize>]) -> PageLabels {
+    if labels.is_empty() {
+        return PageLabels::default();
+    }
+    let mut ranges = labels.get_sorted_ranges();
+    let starts = ranges.iter().map(|r| r.start_page).collect::<Vec<_>>();
+    let mut remaining = Vec::new();
+    for (i, range) in ranges.iter_mut().enumerate() {
+        let end = starts.get(i + 1).copied().unwrap_or(map.len());
+        let Some(first) =
+            (range.start_page..end).find(|p| map.get(*p).copied().flatten().is_some())
+        else {
+            continue;
+        };
+        range.first_number += first - range.start_page;
+        range.start_page = map[first].unwrap_or(0);
+        remaining.push(range.clone());
+    }
+    PageLabels { ranges: remaining }
+}
+
+/// Shrinks the page ranges of the document parts to the remaining pages (the remaining
+/// pages of a range are contiguous), parts without remaining pages are removed
+fn remove_document_parts(parts: &[DocumentPart], map: &[Option<usize>]) -> Vec<DocumentPart> {
+    parts
+        .iter()
+        .filter_map(|part| {
+            let mut part = part.clone();
+            part.children = remove_document_parts(&part.children, map);
+            if let Some((first, last)) = part.pages {
+                let remaining = (first..=last)
+                    .filter_map(|p| map.get(p).copied().flatten())
+                    .collect::<Vec<_>>();
+                part.pages = Some((*remaining.first()?, *remaining.last()?));
+            } else if part.children.is_empty() {
+                return None;
+            }
+            Some(part)
+        })
+        .collect()
+}
+
+#[test]
+fn test_extract_pages() {
+    use crate::{ExtendedGraphicsStateBuilder, LinkAnnotation, Mm, PageLabelStyle, Pt, Rect};
+
+    let mut doc = PdfDocument::new("book");
+    let mut ids = Vec::new();
+    for i in 0..4 {
+        let gs = doc.add_graphics_state(
+            ExtendedGraphicsStateBuilder::new()
+                .with_line_width(i as f32 + 1.0)
+                .build(),
+        );
+        let rect = Rect::from_wh(Pt(10.0), Pt(10.0));
+        let ops = vec![
+            Op::LoadGraphicsState { gs: gs.clone() },
+            // links to the first and to the last page
+            Op::LinkAnnotation {
+                link: LinkAnnotation::internal(rect.clone(), 1, None),
+            },
+            Op::LinkAnnotation {
+                link: LinkAnnotation::internal(rect, 4, None),
+            },
+        ];
+        ids.push(gs);
+        doc.pages.push(PdfPage::new(Mm(210.0), Mm(297.0), ops));
+        doc.add_bookmark(&format!("Chapter {i}"), i);
+    }
+    doc.page_labels
+        .ranges
+        .push(PageLabelRange::new(0, Some(PageLabelStyle::LowerRoman)));
+
+    let chapter = doc.extract_pages(2..4);
+    assert_eq!(chapter.pages.len(), 2);
+    // only the graphics states of the extracted pages are copied
+    assert_eq!(chapter.resources.extgstates.map.len(), 2);
+    assert!(chapter.resources.extgstates.map.contains_key(&ids[2]));
+    // the link to the first page is removed, the link to the last page is renumbered
+    let links = chapter.pages[0]
+        .ops
+        .iter()
+        .filter_map(|op| match op {
+            Op::LinkAnnotation { link } => Some(&link.actions),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        links,
+        [&Actions::GoTo(Destination::XYZ {
+            page: 2,
+            left: None,
+            top: None,
+            zoom: None
+        })]
+    );
+    let mut bookmarks = chapter
+        .bookmarks
+        .map
+        .values()
+        .map(|b| b.page)
+        .collect::<Vec<_>>();
+    bookmarks.sort();
+    assert_eq!(bookmarks, [0, 1]);
+    // the labels continue with "iii"
+    assert_eq!(chapter.page_labels.get_label(0), "iii");
+}
+
+#[test]
+fn test_reorder_remove_rotate_pages() {
+    use crate::Mm;
+
+    let mut doc = PdfDocument::new("pages");
+    for i in 0..3 {
+        let marker = Op::Marker { id: i.to_string() };
+        doc.pages
+            .push(PdfPage::new(Mm(210.0), Mm(297.0), vec![marker]));
+        doc.add_bookmark(&format!("Page {i}"), i);
+    }
+    doc.add_named_destination("last", Destination::Fit { page: 3 });
+    let get_order = |doc: &PdfDocument| {
+        doc.pages
+            .iter()
+            .map(|p| match &p.ops[0] {
+                Op::Marker { id } => id.clone(),
+                _ => String::new(),
+            })
+            .collect::<Vec<_>>()
+    };
+    let get_bookmark = |doc: &PdfDocument, name: &str| {
+        doc.bookmarks
+            .map
+            .values()
+            .find(|b| b.name == name)
+            .map(|b| b.page)
+    };
+
+    assert!(doc.reorder_pages(&[0, 1]).is_err());
+    assert!(doc.reorder_pages(&[0, 0, 1]).is_err());
+    doc.reorder_pages(&[2, 0, 1]).unwrap();
+    assert_eq!(get_order(&doc), ["2", "0", "1"]);
+    assert_eq!(get_bookmark(&doc, "Page 2"), Some(0));
+    assert_eq!(doc.named_destinations["last"], Destination::Fit { page: 1 });
+
+    doc.remove_pages(&[0]);
+    assert_eq!(get_order(&doc), ["0", "1"]);
+    assert_eq!(get_bookmark(&doc, "Page 2"), None);
+    assert_eq!(get_bookmark(&doc, "Page 1"), Some(1));
+    assert!(doc.named_destinations.is_empty());
+
+    doc.rotate_pages(&[1], PageRotation::Clockwise270);
+    doc.rotate_pages(&[1], PageRotation::Clockwise180);
+    assert_eq!(doc.pages[1].rotation, PageRotation::Clockwise90);
+    assert_eq!(doc.pages[0].rotation, PageRotation::None);
+}
@@ -0,0 +1,227 @@
+//! Stamps and watermarks: drawing the same content (i.e. "DRAFT" or a logo) on every page
+
+use crate::{
+    graphics::Rect, units::Pt, CurTransMat, ExtendedGraphicsStateBuilder, Op, PdfDocument, XObject,
+    XObjectId, XObjectTransform,
+};
+
+/// Content of a stamp, see `PdfDocument::stamp_all_pages`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stamp {
+    /// Ops drawn into a box of the given size (from the lower left corner of the box),
+    /// written once as a form XObject
+    Ops { ops: Vec<Op>, width: Pt, height: Pt },
+    /// Existing XObject of the document, i.e. an image or a page imported with
+    /// `PdfDocument::import_page_as_xobject`
+    XObject(XObjectId),
+}
+
+/// Position of a stamp on the page, relative to the crop box of the page
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StampPosition {
+    /// Centered on the page
+    Center,
+    /// Centered on the page and rotated along the diagonal from the lower left to the
+    /// upper right corner (i.e. for a "DRAFT" watermark)
+    Diagonal,
+    /// In a corner of the page, `margin` away from the edges
+    TopLeft {
+        margin: Pt,
+    },
+    TopRight {
+        margin: Pt,
+    },
+    BottomLeft {
+        margin: Pt,
+    },
+    BottomRight {
+        margin: Pt,
+    },
+    /// Lower left corner of the stamp, from the lower left corner of the page
+    Absolute {
+        x: Pt,
+        y: Pt,
+    },
+}
+
+/// Whether a stamp is drawn below or above the existing page content
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum StampOrder {
+    /// Drawn before the page content (only visible where the page is transparent)
+    UnderContent,
+    /// Drawn after the page content
+    #[default]
+    OverContent,
+}
+
+impl PdfDocument {
+    /// Draws the `stamp` on all pages (including pages of parsed documents), with the
+    /// `opacity` (0.0 = transparent, 1.0 = opaque). The stamp is written once and marked
+    /// as an artifact, so that it is not part of the document text.
+    ///
+    /// Pages with a `rotation` are stamped in their unrotated coordinate space.
+    pub fn stamp_all_pages(
+        &mut self,
+        stamp: Stamp,
+        position: StampPosition,
+        opacity: f32,
+        order: StampOrder,
+    ) -> Result<(), String> {
+        let id = match stamp {
+            Stamp::Ops { ops, width, height } => {
+                self.add_form_xobject(ops, Rect::from_wh(width, height))
+            }
+            Stamp::XObject(id) => id,
+        };
+        let bounds = self
+            .resources
+            .xobjects
+            .map
+            .get(&id)
+            .ok_or_else(|| format!("XObject {} does not exist", id.0))
+            .and_then(|xobject| {
+                get_stamp_bounds(xobject).ok_or_else(|| format!("XObject {} has no size", id.0))
+            })?;
+        let gs = (opacity < 1.0).then(|| {
+            self.add_graphics_state(
+                ExtendedGraphicsStateBuilder::new()
+                    .with_alpha(opacity.max(0.0))
+                    .build(),
+            )
+        });
+
+        for page in self.pages.iter_mut() {
+            let mut ops = vec![Op::BeginArtifact, Op::SaveGraphicsState];
+            if let Some(gs) = gs.as_ref() {
+                ops.push(Op::LoadGraphicsState { gs: gs.clone() });
+            }
+            ops.push(Op::SetTransformationMatrix {
+                matrix: get_stamp_matrix(&bounds, &page.crop_box, position),
+            });
+            ops.push(Op::UseXObject {
+                id: id.clone(),
+                transform: XObjectTransform::default(),
+            });
+            ops.push(Op::RestoreGraphicsState);
+            ops.push(Op::EndArtifact);
+
+            match order {
+                StampOrder::UnderContent => {
+                    page.ops.splice(0..0, ops);
+                }
+                StampOrder::OverContent => {
+                    // the stamp must not inherit the graphics state of the page content
+                    if !page.ops.is_empty() {
+                        page.ops.insert(0, Op::SaveGraphicsState);
+                        page.ops.push(Op::RestoreGraphicsState);
+                    }
+                    page.ops.extend(ops);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Bounds of the XObject in its own coordinate space, as it is drawn by `Op::UseXObject`
+/// with the default transform (images at 300 DPI)
+fn get_stamp_bounds(xobject: &XObject) -> Option<Rect> {
+    if let Some((w, h)) = xobject.get_width_height() {
+        return Some(Rect::from_wh(w.into_pt(300.0), h.into_pt(300.0)));
+    }
+    match xobject {
+        XObject::Ops(o) => Some(o.bbox.clone()),
+        XObject::Form(f) => f.bbox.clone(),
+        _ => None,
+    }
+}
+
+/// Maps the stamp `bounds` to the `position` on the page
+fn get_stamp_matrix(bounds: &Rect, page: &Rect, position: StampPosition) -> CurTransMat {
+    let (w, h) = (bounds.width.0, bounds.height.0);
+    let (page_w, page_h) = (page.width.0, page.height.0);
+    // center of the stamp on the page and counter-clockwise rotation
+    let (cx, cy, angle) = match position {
+        StampPosition::Center => (page_w / 2.0, page_h / 2.0, 0.0),
+        StampPosition::Diagonal => (page_w / 2.0, page_h / 2.0, page_h.atan2(page_w)),
+        StampPosition::TopLeft { margin } => (margin.0 + w / 2.0, page_h - margin.0 - h / 2.0, 0.0),
+        StampPosition::TopRight { margin } => (
+            page_w - margin.0 - w / 2.0,
+            page_h - margin.0 - h / 2.0,
+            0.0,
+        ),
+        StampPosition::BottomLeft { margin } => (margin.0 + w / 2.0, margin.0 + h / 2.0, 0.0),
+        StampPosition::BottomRight { margin } => {
+            (page_w - margin.0 - w / 2.0, margin.0 + h / 2.0, 0.0)
+        }
+        StampPosition::Absolute { x, y } => (x.0 + w / 2.0, y.0 + h / 2.0, 0.0),
+    };
+    let (cx, cy) = (page.x.0 + cx, page.y.0 + cy);
+    // rotates around the center of the bounds, then moves the center to (cx, cy)
+    let (sin, cos) = angle.sin_cos();
+    let (bx, by) = (bounds.x.0 + w / 2.0, bounds.y.0 + h / 2.0);
+    CurTransMat::Raw([
+        cos,
+        sin,
+        -sin,
+        cos,
+        cx - (cos * bx - sin * by),
+        cy - (sin * bx + cos * by),
+    ])
+}
+
+#[test]
+fn test_stamp_all_pages() {
+    use crate::{Mm, PdfPage};
+
+    let mut doc = PdfDocument::new("stamped");
+    let content = Op::Marker {
+        id: "content".to_string(),
+    };
+    for _ in 0..2 {
+        doc.pages
+            .push(PdfPage::new(Mm(100.0), Mm(100.0), vec![content.clone()]));
+    }
+    let stamp = Stamp::Ops {
+        ops: Vec::new(),
+        width: Pt(100.0),
+        height: Pt(20.0),
+    };
+    doc.stamp_all_pages(stamp, StampPosition::Diagonal, 0.5, StampOrder::OverContent)
+        .unwrap();
+
+    // one form XObject and one graphics state for all pages
+    assert_eq!(doc.resources.xobjects.map.len(), 1);
+    assert_eq!(doc.resources.extgstates.map.len(), 1);
+    for page in &doc.pages {
+        assert_eq!(page.ops[0], Op::SaveGraphicsState);
+        assert_eq!(page.ops[1], content);
+        assert_eq!(page.ops[3], Op::BeginArtifact);
+        let matrix = page.ops.iter().find_map(|op| match op {
+            Op::SetTransformationMatrix {
+                matrix: CurTransMat::Raw(m),
+            } => Some(*m),
+            _ => None,
+        });
+        // rotated by 45 degrees around the center of the (square) page
+        let [a, b, c, d, e, f] = matrix.unwrap();
+        let half = Mm(50.0).into_pt().0;
+        let diagonal = std::f32::consts::FRAC_1_SQRT_2;
+        assert!((a - diagonal).abs() < 1e-5 && (b - diagonal).abs() < 1e-5);
+        assert!((c + diagonal).abs() < 1e-5 && (d - diagonal).abs() < 1e-5);
+        // the center of the stamp is mapped to the center of the page
+        assert!((a * 50.0 + c * 10.0 + e - half).abs() < 1e-3);
+        assert!((b * 50.0 + d * 10.0 + f - half).abs() < 1e-3);
+    }
+
+    let missing = Stamp::XObject(XObjectId("missing".to_string()));
+    assert!(doc
+        .stamp_all_pages(
+            missing,
+            StampPosition::Center,
+            1.0,
+            StampOrder::UnderContent
+        )
+        .is_err());
+}
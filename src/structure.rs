@@ -0,0 +1,489 @@
+//! Logical structure (tagged PDF / PDF/UA): structure elements that are linked to the page
+//! contents with marked content sequences, so that screen readers can navigate the document
+
+use std::collections::BTreeMap;
+
+use lopdf::Object::{Array, Dictionary, Integer, Name, Reference};
+use lopdf::{Dictionary as LoDictionary, Object as LoObject};
+
+use crate::{forms::text_string, StructureElementId};
+
+/// Standard structure type of an element (PDF reference section 10.7.3)
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum StructureType {
+    /// Whole document
+    Document,
+    /// Large division of a document (i.e. a chapter)
+    Part,
+    /// Self-contained article
+    Art,
+    /// Section of a document
+    Sect,
+    /// Generic block-level grouping element
+    Div,
+    /// Quoted block of text
+    BlockQuote,
+    /// Paragraph
+    P,
+    /// Heading of unknown level
+    H,
+    /// Level 1 heading
+    H1,
+    /// Level 2 heading
+    H2,
+    /// Level 3 heading
+    H3,
+    /// Level 4 heading
+    H4,
+    /// Level 5 heading
+    H5,
+    /// Level 6 heading
+    H6,
+    /// List
+    L,
+    /// List item
+    LI,
+    /// Label of a list item (bullet, number)
+    Lbl,
+    /// Body of a list item
+    LBody,
+    /// Table
+    Table,
+    /// Group of header rows of a table
+    THead,
+    /// Group of body rows of a table
+    TBody,
+    /// Group of footer rows of a table
+    TFoot,
+    /// Table row
+    TR,
+    /// Table header cell
+    TH,
+    /// Table data cell
+    TD,
+    /// Caption of a table or figure
+    Caption,
+    /// Generic inline element
+    Span,
+    /// Hyperlink
+    Link,
+    /// Image or graphic, should have an alt text
+    Figure,
+    /// Mathematical formula, should have an alt text
+    Formula,
+}
+
+impl StructureType {
+    /// Returns the name of the structure type (i.e. "TD")
+    pub fn get_id(&self) -> &'static str {
+        use self::StructureType::*;
+        match self {
+            Document => "Document",
+            Part => "Part",
+            Art => "Art",
+            Sect => "Sect",
+            Div => "Div",
+            BlockQuote => "BlockQuote",
+            P => "P",
+            H => "H",
+            H1 => "H1",
+            H2 => "H2",
+            H3 => "H3",
+            H4 => "H4",
+            H5 => "H5",
+            H6 => "H6",
+            L => "L",
+            LI => "LI",
+            Lbl => "Lbl",
+            LBody => "LBody",
+            Table => "Table",
+            THead => "THead",
+            TBody => "TBody",
+            TFoot => "TFoot",
+            TR => "TR",
+            TH => "TH",
+            TD => "TD",
+            Caption => "Caption",
+            Span => "Span",
+            Link => "Link",
+            Figure => "Figure",
+            Formula => "Formula",
+        }
+    }
+
+    /// Parses the name of a structure type, inverse of `get_id`
+    pub fn from_id(id: &str) -> Option<Self> {
+        use self::StructureType::*;
+        match id {
+            "Document" => Some(Document),
+            "Part" => Some(Part),
+            "Art" => Some(Art),
+            "Sect" => Some(Sect),
+            "Div" => Some(Div),
+            "BlockQuote" => Some(BlockQuote),
+            "P" => Some(P),
+            "H" => Some(H),
+            "H1" => Some(H1),
+            "H2" => Some(H2),
+            "H3" => Some(H3),
+            "H4" => Some(H4),
+            "H5" => Some(H5),
+            "H6" => Some(H6),
+            "L" => Some(L),
+            "LI" => Some(LI),
+            "Lbl" => Some(Lbl),
+            "LBody" => Some(LBody),
+            "Table" => Some(Table),
+            "THead" => Some(THead),
+            "TBody" => Some(TBody),
+            "TFoot" => Some(TFoot),
+            "TR" => Some(TR),
+            "TH" => Some(TH),
+            "TD" => Some(TD),
+            "Caption" => Some(Caption),
+            "Span" => Some(Span),
+            "Link" => Some(Link),
+            "Figure" => Some(Figure),
+            "Formula" => Some(Formula),
+            _ => None,
+        }
+    }
+
+    /// Returns the closest WAI-ARIA role (i.e. "heading" for `H1`), used for SVG and
+    /// HTML output. Table header cells are "columnheader" regardless of their scope.
+    pub fn get_aria_role(&self) -> &'static str {
+        use self::StructureType::*;
+        match self {
+            Document => "document",
+            Art => "article",
+            BlockQuote => "blockquote",
+            P => "paragraph",
+            H | H1 | H2 | H3 | H4 | H5 | H6 => "heading",
+            L => "list",
+            LI => "listitem",
+            Table => "table",
+            THead | TBody | TFoot => "rowgroup",
+            TR => "row",
+            TH => "columnheader",
+            TD => "cell",
+            Caption => "caption",
+            Link => "link",
+            Figure => "img",
+            Formula => "math",
+            Part | Sect | Div | Lbl | LBody | Span => "group",
+        }
+    }
+
+    /// Returns the heading type for a heading level (1 - 6), `H` for other levels
+    pub fn heading(level: usize) -> Self {
+        use self::StructureType::*;
+        match level {
+            1 => H1,
+            2 => H2,
+            3 => H3,
+            4 => H4,
+            5 => H5,
+            6 => H6,
+            _ => H,
+        }
+    }
+}
+
+/// Cells that a table header cell (`TH`) applies to
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TableHeaderScope {
+    /// Header of the cells in the same row
+    Row,
+    /// Header of the cells in the same column
+    Column,
+    /// Header of both the row and the column
+    Both,
+}
+
+impl TableHeaderScope {
+    /// Returns the value of the `/Scope` attribute (i.e. "Column")
+    pub fn get_id(&self) -> &'static str {
+        match self {
+            TableHeaderScope::Row => "Row",
+            TableHeaderScope::Column => "Column",
+            TableHeaderScope::Both => "Both",
+        }
+    }
+
+    /// Parses the value of a `/Scope` attribute, inverse of `get_id`
+    pub fn from_id(id: &str) -> Option<Self> {
+        match id {
+            "Row" => Some(TableHeaderScope::Row),
+            "Column" => Some(TableHeaderScope::Column),
+            "Both" => Some(TableHeaderScope::Both),
+            _ => None,
+        }
+    }
+}
+
+/// Node of the structure tree
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructureElement {
+    /// Structure type of the element
+    pub role: StructureType,
+    /// Parent element, `None` for top-level elements
+    pub parent: Option<StructureElementId>,
+    /// Child elements, in reading order
+    pub children: Vec<StructureElementId>,
+    /// Alternate description (i.e. for figures or abbreviations)
+    pub alt_text: Option<String>,
+    /// Scope of a table header cell (only used for `StructureType::TH`)
+    pub header_scope: Option<TableHeaderScope>,
+}
+
+/// Structure tree of the document. The page contents are linked to the elements with
+/// `Op::BeginStructureElement` / `Op::EndStructureElement`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct StructureTree {
+    /// All elements of the tree
+    pub elements: BTreeMap<StructureElementId, StructureElement>,
+    /// Top-level elements, in reading order
+    pub root: Vec<StructureElementId>,
+}
+
+impl StructureTree {
+    /// Returns whether the tree has no elements (the document is not tagged)
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Appends a new element to `parent` (or to the top level), returning its ID
+    pub fn add_element(
+        &mut self,
+        parent: Option<&StructureElementId>,
+        role: StructureType,
+    ) -> StructureElementId {
+        let id = StructureElementId::new();
+        let parent = parent.filter(|p| self.elements.contains_key(*p)).cloned();
+        match parent.as_ref().and_then(|p| self.elements.get_mut(p)) {
+            Some(p) => p.children.push(id.clone()),
+            None => self.root.push(id.clone()),
+        }
+        self.elements.insert(
+            id.clone(),
+            StructureElement {
+                role,
+                parent,
+                children: Vec::new(),
+                alt_text: None,
+                header_scope: None,
+            },
+        );
+        id
+    }
+
+    /// Appends a new header cell (`TH`) with the given scope to the table row `row`
+    pub fn add_table_header(
+        &mut self,
+        row: &StructureElementId,
+        scope: TableHeaderScope,
+    ) -> StructureElementId {
+        let id = self.add_element(Some(row), StructureType::TH);
+        if let Some(e) = self.elements.get_mut(&id) {
+            e.header_scope = Some(scope);
+        }
+        id
+    }
+
+    /// Appends a new figure with an alternate description to `parent` (or to the top level)
+    pub fn add_figure(
+        &mut self,
+        parent: Option<&StructureElementId>,
+        alt_text: Option<&str>,
+    ) -> StructureElementId {
+        let id = self.add_element(parent, StructureType::Figure);
+        if let Some(alt_text) = alt_text {
+            self.set_alt_text(&id, alt_text);
+        }
+        id
+    }
+
+    /// Sets the alternate description of an element (read by screen readers instead of
+    /// the content, required for figures and formulas in PDF/UA)
+    pub fn set_alt_text(&mut self, id: &StructureElementId, alt_text: &str) {
+        if let Some(e) = self.elements.get_mut(id) {
+            e.alt_text = Some(alt_text.to_string());
+        }
+    }
+
+    /// Returns the structure type of an element
+    pub fn get_role(&self, id: &StructureElementId) -> Option<StructureType> {
+        self.elements.get(id).map(|e| e.role)
+    }
+}
+
+/// Writes the structure tree and sets the `/StructParents` of the pages.
+///
+/// `page_mcids` contains the elements of the marked content sequences on each page,
+/// indexed by their MCID. Returns the ID of the `/StructTreeRoot` dictionary.
+pub(crate) fn write_structure_tree(
+    tree: &StructureTree,
+    page_mcids: &[Vec<StructureElementId>],
+    page_ids: &[lopdf::ObjectId],
+    doc: &mut lopdf::Document,
+) -> lopdf::ObjectId {
+    let root_id = doc.new_object_id();
+    let object_ids = tree
+        .elements
+        .keys()
+        .map(|id| (id.clone(), doc.new_object_id()))
+        .collect::<BTreeMap<_, _>>();
+
+    // marked content references of every element
+    let mut content = BTreeMap::<&StructureElementId, Vec<LoObject>>::new();
+    for (page_id, mcids) in page_ids.iter().zip(page_mcids.iter()) {
+        for (mcid, element) in mcids.iter().enumerate() {
+            content
+                .entry(element)
+                .or_default()
+                .push(Dictionary(LoDictionary::from_iter(vec![
+                    ("Type", Name("MCR".into())),
+                    ("Pg", Reference(*page_id)),
+                    ("MCID", Integer(mcid as i64)),
+                ])));
+        }
+    }
+
+    for (id, element) in tree.elements.iter() {
+        let parent = element
+            .parent
+            .as_ref()
+            .and_then(|p| object_ids.get(p))
+            .copied()
+            .unwrap_or(root_id);
+
+        // page contents of the element first, then the child elements
+        let mut kids = content.remove(id).unwrap_or_default();
+        kids.extend(
+            element
+                .children
+                .iter()
+                .filter_map(|c| object_ids.get(c))
+                .map(|c| Reference(*c)),
+        );
+
+        let mut dict = LoDictionary::from_iter(vec![
+            ("Type", Name("StructElem".into())),
+            ("S", Name(element.role.get_id().into())),
+            ("P", Reference(parent)),
+            ("K", Array(kids)),
+        ]);
+        if let Some(alt) = element.alt_text.as_ref() {
+            dict.set("Alt", text_string(alt));
+        }
+        if let (StructureType::TH, Some(scope)) = (element.role, element.header_scope) {
+            dict.set(
+                "A",
+                Dictionary(LoDictionary::from_iter(vec![
+                    ("O", Name("Table".into())),
+                    ("Scope", Name(scope.get_id().into())),
+                ])),
+            );
+        }
+        doc.objects.insert(object_ids[id], Dictionary(dict));
+    }
+
+    // parent tree: maps the MCIDs of each page to the structure elements
+    let mut nums = Vec::new();
+    for (struct_parents, (page_id, mcids)) in page_ids.iter().zip(page_mcids.iter()).enumerate() {
+        if mcids.is_empty() {
+            continue;
+        }
+        let parents = mcids
+            .iter()
+            .map(|e| {
+                object_ids
+                    .get(e)
+                    .map(|o| Reference(*o))
+                    .unwrap_or(LoObject::Null)
+            })
+            .collect();
+        nums.push(Integer(struct_parents as i64));
+        nums.push(Array(parents));
+        if let Ok(LoObject::Dictionary(page)) = doc.get_object_mut(*page_id) {
+            page.set("StructParents", Integer(struct_parents as i64));
+        }
+    }
+
+    let root_kids = tree
+        .root
+        .iter()
+        .filter_map(|r| object_ids.get(r))
+        .map(|r| Reference(*r))
+        .collect();
+
+    let root = LoDictionary::from_iter(vec![
+        ("Type", Name("StructTreeRoot".into())),
+        ("K", Array(root_kids)),
+        (
+            "ParentTree",
+            Dictionary(LoDictionary::from_iter(vec![("Nums", Array(nums))])),
+        ),
+        ("ParentTreeNextKey", Integer(page_ids.len() as i64)),
+    ]);
+    doc.objects.insert(root_id, Dictionary(root));
+    root_id
+}
+
+#[test]
+fn test_table_structure() {
+    let mut tree = StructureTree::default();
+    let table = tree.add_element(None, StructureType::Table);
+    let row = tree.add_element(Some(&table), StructureType::TR);
+    let th = tree.add_table_header(&row, TableHeaderScope::Column);
+    let td = tree.add_element(Some(&row), StructureType::TD);
+    assert_eq!(tree.root, vec![table.clone()]);
+    assert_eq!(tree.elements[&row].children, vec![th.clone(), td.clone()]);
+
+    let mut doc = lopdf::Document::with_version("1.3");
+    let page = doc.add_object(LoDictionary::new());
+    let root = write_structure_tree(&tree, &[vec![th.clone(), td]], &[page], &mut doc);
+
+    let root = doc.get_dictionary(root).unwrap();
+    assert_eq!(root.get(b"ParentTreeNextKey").unwrap(), &Integer(1));
+    let page = doc.get_dictionary(page).unwrap();
+    assert_eq!(page.get(b"StructParents").unwrap(), &Integer(0));
+
+    let th_dict = doc
+        .objects
+        .values()
+        .filter_map(|o| o.as_dict().ok())
+        .find(|d| matches!(d.get(b"S"), Ok(Name(n)) if n == b"TH"))
+        .unwrap();
+    let attributes = th_dict.get(b"A").unwrap().as_dict().unwrap();
+    assert_eq!(attributes.get(b"Scope").unwrap(), &Name("Column".into()));
+    assert_eq!(th_dict.get(b"K").unwrap().as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn test_figure_alt_text() {
+    let mut tree = StructureTree::default();
+    let document = tree.add_element(None, StructureType::Document);
+    let heading = tree.add_element(Some(&document), StructureType::heading(1));
+    let figure = tree.add_figure(Some(&document), Some("Company logo"));
+    assert_eq!(tree.get_role(&heading), Some(StructureType::H1));
+    assert_eq!(
+        StructureType::from_id("Figure"),
+        Some(StructureType::Figure)
+    );
+
+    let mut doc = lopdf::Document::with_version("1.3");
+    let page = doc.add_object(LoDictionary::new());
+    write_structure_tree(&tree, &[vec![heading, figure]], &[page], &mut doc);
+
+    let figure_dict = doc
+        .objects
+        .values()
+        .filter_map(|o| o.as_dict().ok())
+        .find(|d| matches!(d.get(b"S"), Ok(Name(n)) if n == b"Figure"))
+        .unwrap();
+    assert_eq!(
+        figure_dict.get(b"Alt").unwrap(),
+        &text_string("Company logo")
+    );
+}
@@ -0,0 +1,897 @@
+//! Export of pages to SVG, either as one self-contained file (images as data URLs,
+//! glyphs as `<defs>`) or as an SVG with separate image / glyph asset files
+//! referenced by relative URLs
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write as _,
+    io::Write as _,
+};
+
+use base64::Engine;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+    color::Color,
+    components::escape_xml,
+    font::{GlyphOutlineOperation, ParsedFont},
+    gradient::Gradient,
+    graphics::{BlendMode, Point, Polygon, TextRenderingMode, WindingOrder},
+    image::RawImage,
+    interpret::{GraphicsState, OpInterpreter, PositionedGlyph, RenderBackend},
+    matrix::CurTransMat,
+    postscript::{num, transform},
+    rasterize::{color_to_rgb, sample_pixel},
+    structure::{StructureTree, StructureType, TableHeaderScope},
+    warn::{PdfWarnCode, PdfWarnMsg},
+    Actions, Destination, Op, PdfDocument, StructureElementId,
+};
+
+/// Options for `PdfDocument::export_page_svg`
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SvgExportOptions {
+    /// Write images and glyph outlines to separate asset files instead of
+    /// embedding them into the SVG (default: false)
+    #[serde(default)]
+    pub external_assets: bool,
+    /// Prefix of the asset paths, relative to the SVG file (i.e. `"page1/"`)
+    #[serde(default)]
+    pub asset_prefix: String,
+    /// Adds `id="page-N"` (1-based) to the root element and writes the link annotations
+    /// as `<a>` elements, links to other pages point to `#page-N`. Bookmarks of the page
+    /// become `id="bookmark-{PageAnnotId}"` targets. Makes previews that are embedded into
+    /// one HTML document navigable (default: false). Only `http`, `https` and `mailto`
+    /// URIs are written, other links are skipped with a warning.
+    #[serde(default)]
+    pub navigation: bool,
+}
+
+/// File referenced by an exported SVG
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SvgAsset {
+    /// Path relative to the SVG file (the same string as used in the `href`)
+    pub path: String,
+    pub bytes: Vec<u8>,
+}
+
+/// Result of `PdfDocument::export_page_svg`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SvgExport {
+    pub svg: String,
+    /// Empty unless `SvgExportOptions::external_assets` is set
+    pub assets: Vec<SvgAsset>,
+}
+
+impl PdfDocument {
+    /// Converts the content of the page (0-based index) into an SVG.
+    ///
+    /// Text is written as glyph outlines, which are shared between all occurrences
+    /// of the glyph. Soft masks and form XObjects are not supported, a warning is
+    /// added for every skipped feature. The structure elements of tagged documents are
+    /// written as groups with ARIA roles, alt texts become `aria-label` and `<title>`.
+    pub fn export_page_svg(
+        &self,
+        page: usize,
+        options: &SvgExportOptions,
+        warnings: &mut Vec<PdfWarnMsg>,
+    ) -> Result<SvgExport, String> {
+        let p = self
+            .pages
+            .get(page)
+            .ok_or_else(|| format!("export_page_svg: page {page} does not exist"))?;
+
+        // SVG coordinates start at the upper left corner of the media box
+        let media_box = p.media_box.normalize();
+        let (width, height) = (media_box.width.0, media_box.height.0);
+        let device = [1.0, 0.0, 0.0, -1.0, -media_box.x.0, media_box.y.0 + height];
+
+        let mut writer = SvgWriter {
+            options,
+            structure: &self.structure,
+            structure_levels: Vec::new(),
+            body: String::new(),
+            defs: String::new(),
+            glyph_defs: String::new(),
+            groups: vec![0],
+            next_id: 0,
+            glyphs: BTreeMap::new(),
+            images: BTreeMap::new(),
+            assets: Vec::new(),
+            skipped: BTreeSet::new(),
+        };
+        OpInterpreter::new(self, device).render(&p.ops, &mut writer);
+        while !writer.structure_levels.is_empty() {
+            writer.end_structure_element();
+        }
+        for open in std::mem::take(&mut writer.groups) {
+            writer.body.push_str(&"</g>\n".repeat(open));
+        }
+
+        warnings.extend(writer.skipped.iter().map(|feature| {
+            PdfWarnMsg::warning(
+                Some(page),
+                format!("export_page_svg: {feature} not exported"),
+            )
+            .with_code(PdfWarnCode::UnsupportedExportFeature)
+        }));
+
+        if options.navigation {
+            self.write_svg_links(page, &device, &mut writer.body, warnings);
+        }
+
+        let mut svg = String::new();
+        let id = if options.navigation {
+            format!(" id=\"page-{}\"", page + 1)
+        } else {
+            String::new()
+        };
+        // tagged documents expose their structure, untagged pages are one image
+        let title = &self.metadata.info.document_title;
+        let role = match (self.structure.is_empty(), title.is_empty()) {
+            (false, _) => " role=\"document\"".to_string(),
+            (true, true) => " role=\"img\"".to_string(),
+            (true, false) => format!(" role=\"img\" aria-label=\"{}\"", escape_xml(title)),
+        };
+        let _ = writeln!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\"{id} width=\"{w}pt\" height=\"{h}pt\" viewBox=\"0 0 {w} {h}\"{role}>",
+            w = num(width),
+            h = num(height)
+        );
+        if !title.is_empty() {
+            let _ = writeln!(svg, "<title>{}</title>", escape_xml(title));
+        }
+        if options.external_assets && !writer.glyph_defs.is_empty() {
+            let mut glyphs = String::new();
+            glyphs.push_str("<svg xmlns=\"http://www.w3.org/2000/svg\">\n<defs>\n");
+            glyphs.push_str(&writer.glyph_defs);
+            glyphs.push_str("</defs>\n</svg>\n");
+            writer.assets.push(SvgAsset {
+                path: format!("{}glyphs.svg", options.asset_prefix),
+                bytes: glyphs.into_bytes(),
+            });
+        } else {
+            writer.defs.push_str(&writer.glyph_defs);
+        }
+        if !writer.defs.is_empty() {
+            svg.push_str("<defs>\n");
+            svg.push_str(&writer.defs);
+            svg.push_str("</defs>\n");
+        }
+        svg.push_str(&writer.body);
+        svg.push_str("</svg>\n");
+
+        Ok(SvgExport {
+            svg,
+            assets: writer.assets,
+        })
+    }
+
+    /// Writes the link annotations of the page as transparent `<a>` areas. Links to
+    /// pages (also via named destinations) point to the anchor of the page, named
+    /// actions ("NextPage", ...) are skipped.
+    fn write_svg_links(
+        &self,
+        page: usize,
+        device: &[f32; 6],
+        body: &mut String,
+        warnings: &mut Vec<PdfWarnMsg>,
+    ) {
+        // empty groups as link targets for the bookmarks of the page
+        for (id, bookmark) in &self.bookmarks.map {
+            if bookmark.page == page {
+                let _ = writeln!(
+                    body,
+                    "<g id=\"bookmark-{}\" aria-label=\"{}\"/>",
+                    escape_xml(&id.0),
+                    escape_xml(&bookmark.name)
+                );
+            }
+        }
+
+        for op in &self.pages[page].ops {
+            let Op::LinkAnnotation { link } = op else {
+                continue;
+            };
+            let href = match &link.actions {
+                Actions::GoTo(Destination::Named(name)) => self
+                    .named_destinations
+                    .get(name)
+                    .and_then(|d| d.get_page())
+                    .map(|p| format!("#page-{p}")),
+                Actions::GoTo(dest) => dest.get_page().map(|p| format!("#page-{p}")),
+                Actions::URI(uri) if is_safe_uri(uri) => Some(escape_xml(uri)),
+                Actions::URI(uri) => {
+                    warnings.push(
+                        PdfWarnMsg::warning(
+                            Some(page),
+                            format!("export_page_svg: link to {uri:?} not exported (only http, https and mailto links are)"),
+                        )
+                        .with_code(PdfWarnCode::UnsupportedExportFeature),
+                    );
+                    None
+                }
+                Actions::Named(_) => None,
+            };
+            let Some(href) = href else {
+                continue;
+            };
+            let rect = link.rect.normalize();
+            let (x, y) = transform(device, (rect.x.0, rect.y.0 + rect.height.0));
+            let _ = writeln!(
+                body,
+                "<a href=\"{href}\"><rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"transparent\"/></a>",
+                num(x),
+                num(y),
+                num(rect.width.0),
+                num(rect.height.0)
+            );
+        }
+    }
+}
+
+/// Returns whether the URI can be written as a link: `javascript:`, `data:` or `file:`
+/// URIs of untrusted documents would be executed or opened by the viewer of the SVG
+fn is_safe_uri(uri: &str) -> bool {
+    let scheme = uri
+        .split_once(':')
+        .map(|(s, _)| s.trim().to_ascii_lowercase());
+    matches!(scheme.as_deref(), Some("http" | "https" | "mailto"))
+}
+
+fn svg_matrix(m: &[f32; 6]) -> String {
+    let m = m.map(num);
+    format!(
+        "matrix({} {} {} {} {} {})",
+        m[0], m[1], m[2], m[3], m[4], m[5]
+    )
+}
+
+fn svg_color(col: &Color) -> String {
+    let [r, g, b] = color_to_rgb(col).map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+/// Path data of the rings, transformed by `m`
+fn path_data(rings: &[Vec<(Point, bool)>], m: &[f32; 6], closed: bool) -> String {
+    let mut d = String::new();
+    for ring in rings.iter().filter(|r| !r.is_empty()) {
+        let p = |i: usize| {
+            let (x, y) = transform(m, (ring[i].0.x.0, ring[i].0.y.0));
+            format!("{} {}", num(x), num(y))
+        };
+        let _ = write!(d, "M{}", p(0));
+        let mut i = 1;
+        while i < ring.len() {
+            // same convention as `Line`: two flagged points start a bezier curve
+            if ring[i - 1].1 && ring[i].1 && i + 2 < ring.len() {
+                let _ = write!(d, "C{} {} {}", p(i), p(i + 1), p(i + 2));
+                i += 3;
+            } else {
+                let _ = write!(d, "L{}", p(i));
+                i += 1;
+            }
+        }
+        if closed {
+            d.push('Z');
+        }
+    }
+    d
+}
+
+/// Path data of the glyph outline in font units
+fn glyph_path_data(font: &ParsedFont, glyph_id: u16) -> Option<String> {
+    let outline = font.get_glyph_outline(glyph_id)?;
+    let mut d = String::new();
+    for op in outline.operations.iter() {
+        let _ = match op {
+            GlyphOutlineOperation::MoveTo(o) => write!(d, "M{} {}", num(o.x), num(o.y)),
+            GlyphOutlineOperation::LineTo(o) => write!(d, "L{} {}", num(o.x), num(o.y)),
+            GlyphOutlineOperation::QuadraticCurveTo(q) => write!(
+                d,
+                "Q{} {} {} {}",
+                num(q.ctrl_1_x),
+                num(q.ctrl_1_y),
+                num(q.end_x),
+                num(q.end_y)
+            ),
+            GlyphOutlineOperation::CubicCurveTo(c) => write!(
+                d,
+                "C{} {} {} {} {} {}",
+                num(c.ctrl_1_x),
+                num(c.ctrl_1_y),
+                num(c.ctrl_2_x),
+                num(c.ctrl_2_y),
+                num(c.end_x),
+                num(c.end_y)
+            ),
+            GlyphOutlineOperation::ClosePath => write!(d, "Z"),
+        };
+    }
+    Some(d)
+}
+
+/// Encodes RGBA8 pixels as a PNG file (no filtering, zlib compressed)
+fn encode_png(width: usize, height: usize, rgba: &[u8]) -> Result<Vec<u8>, String> {
+    let mut raw = Vec::with_capacity((width * 4 + 1) * height);
+    for row in rgba.chunks_exact(width * 4).take(height) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&raw).map_err(|e| e.to_string())?;
+    let data = encoder.finish().map_err(|e| e.to_string())?;
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bit RGBA, deflate, no filter, no interlacing
+    ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    for (kind, chunk) in [(b"IHDR", &ihdr), (b"IDAT", &data), (b"IEND", &Vec::new())] {
+        let mut crc = flate2::Crc::new();
+        crc.update(kind);
+        crc.update(chunk);
+        png.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+        png.extend_from_slice(kind);
+        png.extend_from_slice(chunk);
+        png.extend_from_slice(&crc.sum().to_be_bytes());
+    }
+    Ok(png)
+}
+
+/// Returns the image as a JPEG (`DCTDecode` sources) or PNG file and its extension
+fn encode_image(image: &RawImage) -> Result<(Vec<u8>, &'static str), String> {
+    let jpeg = image.source.as_ref().filter(|s| {
+        s.filters.len() == 1
+            && s.filters[0].name == "DCTDecode"
+            && matches!(s.color_space.as_str(), "DeviceGray" | "DeviceRGB")
+            && s.decode.is_none()
+    });
+    if let Some(source) = jpeg {
+        return Ok((source.bytes.clone(), "jpg"));
+    }
+
+    let (w, h) = (image.width, image.height);
+    let pixels = image.pixels()?;
+    let format = match (&pixels, image.source.as_ref()) {
+        (Cow::Owned(_), Some(source)) => source.get_decoded_format(),
+        _ => image.data_format,
+    };
+    let mut rgba = Vec::with_capacity(w * h * 4);
+    for i in 0..w * h {
+        let (color, alpha) =
+            sample_pixel(&pixels, format, i).ok_or_else(|| "image data too short".to_string())?;
+        rgba.extend(color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8));
+        rgba.push((alpha.clamp(0.0, 1.0) * 255.0).round() as u8);
+    }
+    Ok((encode_png(w, h, &rgba)?, "png"))
+}
+
+struct SvgWriter<'a> {
+    options: &'a SvgExportOptions,
+    structure: &'a StructureTree,
+    /// Length of `groups` when each open structure element `<g>` was started
+    structure_levels: Vec<usize>,
+    body: String,
+    defs: String,
+    /// Glyph outlines, written to `defs` or to the `glyphs.svg` asset
+    glyph_defs: String,
+    /// Number of `<g>` elements opened by clips, per saved graphics state
+    groups: Vec<usize>,
+    next_id: usize,
+    /// Path data of the glyph outline to the ID of its definition
+    glyphs: BTreeMap<String, String>,
+    /// Address of the image to its URL
+    images: BTreeMap<usize, String>,
+    assets: Vec<SvgAsset>,
+    /// Features that were skipped, reported as warnings
+    skipped: BTreeSet<String>,
+}
+
+impl SvgWriter<'_> {
+    fn new_id(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{prefix}{}", self.next_id)
+    }
+
+    /// Returns the presentation attributes for the alpha and blend mode
+    fn style(&mut self, gs: &GraphicsState, opacity: &str, alpha: f32) -> String {
+        if gs.soft_mask.is_some() {
+            self.skipped.insert("soft mask".to_string());
+        }
+        let mut attrs = String::new();
+        if alpha < 1.0 {
+            let _ = write!(attrs, " {opacity}=\"{}\"", num(alpha));
+        }
+        if gs.blend_mode != BlendMode::normal() {
+            // "ColorDodge" -> "color-dodge"
+            let mut mode = String::new();
+            for (i, c) in gs.blend_mode.get_id().chars().enumerate() {
+                if c.is_ascii_uppercase() && i > 0 {
+                    mode.push('-');
+                }
+                mode.push(c.to_ascii_lowercase());
+            }
+            let _ = write!(attrs, " style=\"mix-blend-mode:{mode}\"");
+        }
+        attrs
+    }
+
+    /// Returns the URL of the image, embedding it or adding it as an asset on first use
+    fn image_url(&mut self, image: &RawImage) -> Result<String, String> {
+        let key = image as *const RawImage as usize;
+        if let Some(url) = self.images.get(&key) {
+            return Ok(url.clone());
+        }
+        let (bytes, ext) = encode_image(image)?;
+        let url = if self.options.external_assets {
+            let path = format!(
+                "{}image{}.{ext}",
+                self.options.asset_prefix,
+                self.images.len() + 1
+            );
+            self.assets.push(SvgAsset {
+                path: path.clone(),
+                bytes,
+            });
+            path
+        } else {
+            let mime = if ext == "jpg" { "jpeg" } else { ext };
+            let data = base64::prelude::BASE64_STANDARD.encode(&bytes);
+            format!("data:image/{mime};base64,{data}")
+        };
+        self.images.insert(key, url.clone());
+        Ok(url)
+    }
+}
+
+impl RenderBackend for SvgWriter<'_> {
+    fn save_state(&mut self) {
+        self.groups.push(0);
+    }
+
+    fn restore_state(&mut self) {
+        // only emitted for a matching `save_state`, a `Q` inside of a structure element
+        // for a `q` outside of it keeps the clip until the element ends
+        let min_len = self.structure_levels.last().map_or(1, |l| l + 1);
+        if self.groups.len() > min_len {
+            let open = self.groups.pop().unwrap_or(0);
+            self.body.push_str(&"</g>\n".repeat(open));
+        }
+    }
+
+    fn fill_path(
+        &mut self,
+        gs: &GraphicsState,
+        rings: &[Vec<(Point, bool)>],
+        winding: WindingOrder,
+    ) {
+        let style = self.style(gs, "fill-opacity", gs.fill_alpha);
+        let rule = match winding {
+            WindingOrder::EvenOdd => " fill-rule=\"evenodd\"",
+            WindingOrder::NonZero => "",
+        };
+        let _ = writeln!(
+            self.body,
+            "<path d=\"{}\" fill=\"{}\"{rule}{style}/>",
+            path_data(rings, &gs.ctm, true),
+            svg_color(&gs.fill_color)
+        );
+    }
+
+    fn stroke_path(&mut self, gs: &GraphicsState, rings: &[Vec<(Point, bool)>], closed: bool) {
+        let style = self.style(gs, "stroke-opacity", gs.stroke_alpha);
+        // the path is written in user space, so that the line width and dashes are, too
+        let mut attrs = format!(
+            "stroke=\"{}\" stroke-width=\"{}\"",
+            svg_color(&gs.stroke_color),
+            num(gs.line_width)
+        );
+        let cap = ["butt", "round", "square"][gs.line_cap.id().clamp(0, 2) as usize];
+        let join = ["miter", "round", "bevel"][gs.line_join.id().clamp(0, 2) as usize];
+        let _ = write!(
+            attrs,
+            " stroke-linecap=\"{cap}\" stroke-linejoin=\"{join}\" stroke-miterlimit=\"{}\"",
+            num(gs.miter_limit.max(1.0))
+        );
+        if !gs.dash.is_empty() {
+            let dash = gs.dash.iter().map(|d| num(*d)).collect::<Vec<_>>();
+            let _ = write!(
+                attrs,
+                " stroke-dasharray=\"{}\" stroke-dashoffset=\"{}\"",
+                dash.join(" "),
+                num(gs.dash_offset)
+            );
+        }
+        let identity = CurTransMat::Identity.as_array();
+        let _ = writeln!(
+            self.body,
+            "<path d=\"{}\" transform=\"{}\" fill=\"none\" {attrs}{style}/>",
+            path_data(rings, &identity, closed),
+            svg_matrix(&gs.ctm)
+        );
+    }
+
+    fn push_clip(
+        &mut self,
+        gs: &GraphicsState,
+        rings: &[Vec<(Point, bool)>],
+        winding: WindingOrder,
+    ) {
+        let id = self.new_id("clip");
+        let rule = match winding {
+            WindingOrder::EvenOdd => " clip-rule=\"evenodd\"",
+            WindingOrder::NonZero => "",
+        };
+        let _ = writeln!(
+            self.defs,
+            "<clipPath id=\"{id}\"><path d=\"{}\"{rule}/></clipPath>",
+            path_data(rings, &gs.ctm, true)
+        );
+        let _ = writeln!(self.body, "<g clip-path=\"url(#{id})\">");
+        if let Some(open) = self.groups.last_mut() {
+            *open += 1;
+        }
+    }
+
+    fn draw_glyph_run(
+        &mut self,
+        gs: &GraphicsState,
+        font: &ParsedFont,
+        glyphs: &[PositionedGlyph],
+    ) {
+        if matches!(
+            gs.text_mode,
+            TextRenderingMode::Invisible | TextRenderingMode::Clip
+        ) {
+            return;
+        }
+        let style = self.style(gs, "fill-opacity", gs.fill_alpha);
+
+        // stroked text modes are approximated by filling the glyphs
+        let color = match gs.text_mode {
+            TextRenderingMode::Stroke | TextRenderingMode::StrokeClip => &gs.stroke_color,
+            _ => &gs.fill_color,
+        };
+        let glyph_href = match self.options.external_assets {
+            true => format!("{}glyphs.svg", escape_xml(&self.options.asset_prefix)),
+            false => String::new(),
+        };
+
+        let _ = writeln!(self.body, "<g fill=\"{}\"{style}>", svg_color(color));
+        for glyph in glyphs.iter() {
+            let Some(d) = glyph_path_data(font, glyph.glyph_id) else {
+                continue;
+            };
+            let id = match self.glyphs.get(&d) {
+                Some(id) => id.clone(),
+                None => {
+                    let id = self.new_id("glyph");
+                    let _ = writeln!(self.glyph_defs, "<path id=\"{id}\" d=\"{d}\"/>");
+                    self.glyphs.insert(d, id.clone());
+                    id
+                }
+            };
+            let _ = writeln!(
+                self.body,
+                "<use href=\"{glyph_href}#{id}\" transform=\"{}\"/>",
+                svg_matrix(&glyph.matrix)
+            );
+        }
+        self.body.push_str("</g>\n");
+    }
+
+    fn draw_image(&mut self, gs: &GraphicsState, image: &RawImage, matrix: [f32; 6]) {
+        if image.width == 0 || image.height == 0 {
+            return;
+        }
+        let url = match self.image_url(image) {
+            Ok(url) => url,
+            Err(e) => {
+                self.skipped.insert(format!("image ({e})"));
+                return;
+            }
+        };
+        let style = self.style(gs, "opacity", gs.fill_alpha);
+        // the first row of the image is the top edge of the unit square
+        let m = CurTransMat::combine_matrix([1.0, 0.0, 0.0, -1.0, 0.0, 1.0], matrix);
+        let _ = writeln!(
+            self.body,
+            "<image href=\"{}\" width=\"1\" height=\"1\" preserveAspectRatio=\"none\" transform=\"{}\"{style}/>",
+            escape_xml(&url),
+            svg_matrix(&m)
+        );
+    }
+
+    fn draw_gradient(
+        &mut self,
+        gs: &GraphicsState,
+        gradient: &Gradient,
+        clip: &Polygon,
+        matrix: [f32; 6],
+    ) {
+        let id = self.new_id("gradient");
+        let gradient_attrs = format!(
+            "id=\"{id}\" gradientUnits=\"userSpaceOnUse\" gradientTransform=\"{}\"",
+            svg_matrix(&matrix)
+        );
+        // SVG gradients are always extended, `extend_start` / `extend_end` are ignored
+        let _ = match gradient {
+            Gradient::Linear(g) => writeln!(
+                self.defs,
+                "<linearGradient {gradient_attrs} x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\">",
+                num(g.start.x.0),
+                num(g.start.y.0),
+                num(g.end.x.0),
+                num(g.end.y.0)
+            ),
+            Gradient::Radial(g) => writeln!(
+                self.defs,
+                "<radialGradient {gradient_attrs} fx=\"{}\" fy=\"{}\" fr=\"{}\" cx=\"{}\" cy=\"{}\" r=\"{}\">",
+                num(g.start_center.x.0),
+                num(g.start_center.y.0),
+                num(g.start_radius.0),
+                num(g.end_center.x.0),
+                num(g.end_center.y.0),
+                num(g.end_radius.0)
+            ),
+        };
+        for stop in gradient.get_stops() {
+            let _ = writeln!(
+                self.defs,
+                "<stop offset=\"{}\" stop-color=\"{}\"/>",
+                num(stop.offset),
+                svg_color(&stop.color)
+            );
+        }
+        self.defs.push_str(match gradient {
+            Gradient::Linear(_) => "</linearGradient>\n",
+            Gradient::Radial(_) => "</radialGradient>\n",
+        });
+
+        let style = self.style(gs, "fill-opacity", gs.fill_alpha);
+        let rule = match clip.winding_order {
+            WindingOrder::EvenOdd => " fill-rule=\"evenodd\"",
+            WindingOrder::NonZero => "",
+        };
+        let _ = writeln!(
+            self.body,
+            "<path d=\"{}\" fill=\"url(#{id})\"{rule}{style}/>",
+            path_data(&clip.rings, &gs.ctm, true)
+        );
+    }
+
+    fn begin_structure_element(&mut self, _gs: &GraphicsState, id: &StructureElementId) {
+        // unknown elements still open a group, so that the end tags match
+        let mut attrs = String::new();
+        let mut alt_text = None;
+        if let Some(element) = self.structure.elements.get(id) {
+            let role = match (element.role, element.header_scope) {
+                (StructureType::TH, Some(TableHeaderScope::Row)) => "rowheader",
+                (role, _) => role.get_aria_role(),
+            };
+            let _ = write!(attrs, " role=\"{role}\"");
+            let level = element.role.get_id().strip_prefix('H');
+            if let Some(level) = level.filter(|l| l.len() == 1) {
+                let _ = write!(attrs, " aria-level=\"{level}\"");
+            }
+            alt_text = element.alt_text.as_deref().map(escape_xml);
+            if let Some(alt_text) = alt_text.as_deref() {
+                let _ = write!(attrs, " aria-label=\"{alt_text}\"");
+            }
+        }
+        let _ = writeln!(self.body, "<g{attrs}>");
+        if let Some(alt_text) = alt_text {
+            let _ = writeln!(self.body, "<title>{alt_text}</title>");
+        }
+        self.structure_levels.push(self.groups.len());
+        self.groups.push(0);
+    }
+
+    fn end_structure_element(&mut self) {
+        let Some(level) = self.structure_levels.pop() else {
+            return;
+        };
+        // closes the clips that were not restored inside of the element
+        for open in self.groups.split_off(level) {
+            self.body.push_str(&"</g>\n".repeat(open));
+        }
+        self.body.push_str("</g>\n");
+    }
+
+    fn unsupported(&mut self, _gs: &GraphicsState, feature: &str) {
+        self.skipped.insert(feature.to_string());
+    }
+}
+
+#[test]
+fn test_export_page_svg() {
+    use crate::{
+        image::{RawImageData, RawImageFormat},
+        Line, Mm, PdfPage, Pt, Rgb, XObjectTransform,
+    };
+
+    let mut doc = PdfDocument::new("svg");
+    let image = RawImage {
+        pixels: RawImageData::U8(vec![255, 0, 0, 0, 0, 255]),
+        width: 2,
+        height: 1,
+        data_format: RawImageFormat::RGB8,
+        tag: Vec::new(),
+        source: None,
+    };
+    let image_id = doc.add_image(&image);
+    let line = Line {
+        points: vec![
+            (Point::new(Mm(0.0), Mm(0.0)), false),
+            (Point::new(Mm(10.0), Mm(10.0)), false),
+        ],
+        is_closed: false,
+    };
+    let use_image = Op::UseXObject {
+        id: image_id,
+        transform: XObjectTransform::default(),
+    };
+    doc.pages.push(PdfPage::new(
+        Mm(100.0),
+        Mm(100.0),
+        vec![
+            Op::SetOutlineColor {
+                col: Color::Rgb(Rgb::new(1.0, 0.0, 0.0, None)),
+            },
+            Op::SetOutlineThickness { pt: Pt(2.0) },
+            Op::DrawLine { line },
+            use_image.clone(),
+            use_image,
+        ],
+    ));
+
+    let mut warnings = Vec::new();
+    let embedded = doc
+        .export_page_svg(0, &SvgExportOptions::default(), &mut warnings)
+        .unwrap();
+    assert!(warnings.is_empty());
+    assert!(embedded.assets.is_empty());
+    assert!(embedded.svg.contains("viewBox=\"0 0 283.4646 283.4646\""));
+    assert!(embedded
+        .svg
+        .contains("stroke=\"#ff0000\" stroke-width=\"2\""));
+    assert_eq!(embedded.svg.matches("data:image/png;base64,").count(), 2);
+
+    let options = SvgExportOptions {
+        external_assets: true,
+        asset_prefix: "assets/".to_string(),
+        ..Default::default()
+    };
+    let external = doc.export_page_svg(0, &options, &mut warnings).unwrap();
+    assert!(!external.svg.contains("data:"));
+    assert_eq!(
+        external.svg.matches("href=\"assets/image1.png\"").count(),
+        2
+    );
+    assert_eq!(external.assets.len(), 1);
+    assert_eq!(external.assets[0].path, "assets/image1.png");
+    assert!(external.assets[0].bytes.starts_with(b"\x89PNG\r\n\x1a\n"));
+
+    assert!(doc.export_page_svg(1, &options, &mut warnings).is_err());
+}
+
+#[test]
+fn test_export_page_svg_navigation() {
+    use crate::{LinkAnnotation, Mm, PdfPage, Pt, Rect};
+
+    let link = |actions| Op::LinkAnnotation {
+        link: LinkAnnotation::new(
+            Rect {
+                x: Pt(10.0),
+                y: Pt(20.0),
+                width: Pt(30.0),
+                height: Pt(40.0),
+            },
+            actions,
+            None,
+            None,
+            None,
+        ),
+    };
+    let mut doc = PdfDocument::new("svg");
+    doc.named_destinations
+        .insert("end".to_string(), Destination::Fit { page: 2 });
+    doc.pages.push(PdfPage::new(
+        Mm(100.0),
+        Mm(100.0),
+        vec![
+            link(Actions::go_to(Destination::Fit { page: 2 })),
+            link(Actions::go_to(Destination::Named("end".to_string()))),
+            link(Actions::uri("https://example.com/?a=1&b=2".to_string())),
+            link(Actions::named("NextPage")),
+            link(Actions::uri("javascript:alert(1)".to_string())),
+            link(Actions::uri(" JavaScript:alert(1)".to_string())),
+        ],
+    ));
+    let bookmark = doc.add_bookmark("Intro <1>", 0);
+    doc.pages
+        .push(PdfPage::new(Mm(100.0), Mm(100.0), Vec::new()));
+
+    let mut warnings = Vec::new();
+    let plain = doc
+        .export_page_svg(0, &SvgExportOptions::default(), &mut warnings)
+        .unwrap();
+    assert!(!plain.svg.contains("<a "));
+    assert!(!plain.svg.contains("id=\"page-1\""));
+
+    let options = SvgExportOptions {
+        navigation: true,
+        ..Default::default()
+    };
+    let svg = doc.export_page_svg(0, &options, &mut warnings).unwrap().svg;
+    assert!(svg.contains("<svg xmlns=\"http://www.w3.org/2000/svg\" id=\"page-1\""));
+    assert_eq!(svg.matches("<a href=\"#page-2\">").count(), 2);
+    assert!(svg.contains("<a href=\"https://example.com/?a=1&amp;b=2\">"));
+    assert_eq!(svg.matches("<a ").count(), 3);
+    assert!(!svg.contains("javascript"));
+    assert_eq!(warnings.len(), 2);
+    assert!(svg.contains(&format!(
+        "<g id=\"bookmark-{}\" aria-label=\"Intro &lt;1&gt;\"/>",
+        bookmark.0
+    )));
+    warnings.clear();
+    // the upper left corner in SVG coordinates (y axis pointing down)
+    assert!(svg.contains("<rect x=\"10\" y=\"223.4646\" width=\"30\" height=\"40\""));
+    let second = doc.export_page_svg(1, &options, &mut warnings).unwrap().svg;
+    assert!(second.contains("id=\"page-2\""));
+    assert!(!second.contains("bookmark-"));
+    assert!(warnings.is_empty());
+}
+
+#[test]
+fn test_export_page_svg_accessibility() {
+    use crate::{Mm, PdfPage, Pt, Rect};
+
+    let mut doc = PdfDocument::new("svg");
+    let heading = doc.structure.add_element(None, StructureType::heading(2));
+    let figure = doc.structure.add_figure(None, Some("Chart <sales>"));
+    let rect = Rect::from_wh(Pt(10.0), Pt(10.0)).to_polygon();
+    doc.pages.push(PdfPage::new(
+        Mm(100.0),
+        Mm(100.0),
+        vec![
+            Op::BeginStructureElement { id: heading },
+            Op::EndStructureElement,
+            Op::BeginStructureElement { id: figure },
+            Op::SaveGraphicsState,
+            Op::DrawPolygon {
+                polygon: rect.clone(),
+            },
+            Op::RestoreGraphicsState,
+            // not closed, ended at the end of the page
+            Op::BeginStructureElement {
+                id: StructureElementId("unknown".to_string()),
+            },
+        ],
+    ));
+
+    let mut warnings = Vec::new();
+    let svg = doc
+        .export_page_svg(0, &SvgExportOptions::default(), &mut warnings)
+        .unwrap()
+        .svg;
+    assert!(svg.contains("role=\"document\">"));
+    assert!(svg.contains("<g role=\"heading\" aria-level=\"2\">\n</g>"));
+    assert!(svg.contains(
+        "<g role=\"img\" aria-label=\"Chart &lt;sales&gt;\">\n<title>Chart &lt;sales&gt;</title>"
+    ));
+    assert_eq!(svg.matches("<g").count(), svg.matches("</g>").count());
+
+    // untagged documents are described as one image
+    doc.structure = StructureTree::default();
+    doc.pages[0].ops = vec![Op::DrawPolygon { polygon: rect }];
+    let svg = doc
+        .export_page_svg(0, &SvgExportOptions::default(), &mut warnings)
+        .unwrap()
+        .svg;
+    assert!(svg.contains("role=\"img\" aria-label=\"svg\">\n<title>svg</title>"));
+}
@@ -0,0 +1,166 @@
+//! Mail merge: renders an HTML template with `{{field}}` placeholders once per data record
+
+use serde_json::Value;
+
+use crate::{
+    components::escape_xml,
+    html::{embed_images, remove_unused_images, render_xml, HtmlRenderContext},
+    DocumentPartRoot, FontCache, PdfDocument, XmlRenderOptions,
+};
+
+/// How the records of `PdfDocument::from_html_template` are output
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum TemplateOutput {
+    /// One document with the pages of all records, every record starts on a new page.
+    /// The records are grouped as document parts (PDF/VT), so print systems can split them.
+    #[default]
+    Concatenate,
+    /// One document per record (i.e. one e-mail attachment per recipient)
+    SplitPerRecord,
+}
+
+/// Options for `PdfDocument::from_html_template`
+#[derive(Debug, Default)]
+pub struct HtmlTemplateOptions {
+    /// Page size, fonts, images and components, shared by all records
+    pub render: XmlRenderOptions,
+    /// Concatenate the records or create one document per record
+    pub output: TemplateOutput,
+    /// Title of the created document(s)
+    pub title: String,
+}
+
+impl PdfDocument {
+    /// Renders the `html` template once per record, replacing `{{field}}` placeholders
+    /// with the (XML-escaped) values of the record. Nested values can be accessed with
+    /// dots (`{{customer.name}}`, `{{items.0.price}}`), missing fields are an error.
+    ///
+    /// Fonts, images and components are only loaded once for all records.
+    /// Returns a single document for `TemplateOutput::Concatenate`.
+    pub fn from_html_template(
+        html: &str,
+        records: &[Value],
+        options: HtmlTemplateOptions,
+    ) -> Result<Vec<PdfDocument>, String> {
+        let HtmlTemplateOptions {
+            render: mut config,
+            output,
+            title,
+        } = options;
+
+        // parse the fonts of the split documents only once
+        let enable_font_cache = FontCache::global().is_none();
+        if enable_font_cache {
+            FontCache::new().enable_global();
+        }
+
+        let result = render_records(html, records, &mut config, output, &title);
+
+        if enable_font_cache {
+            FontCache::disable_global();
+        }
+        result
+    }
+}
+
+fn render_records(
+    html: &str,
+    records: &[Value],
+    config: &mut XmlRenderOptions,
+    output: TemplateOutput,
+    title: &str,
+) -> Result<Vec<PdfDocument>, String> {
+    let mut context = HtmlRenderContext::new(config);
+    let mut docs = Vec::new();
+    let mut current = PdfDocument::new(title);
+    let mut images = embed_images(&mut current, config, &mut Vec::new());
+    let mut record_pages = Vec::new();
+
+    for (i, record) in records.iter().enumerate() {
+        if output == TemplateOutput::SplitPerRecord && i > 0 {
+            remove_unused_images(&mut current, &images, &[]);
+            docs.push(std::mem::replace(&mut current, PdfDocument::new(title)));
+            images = embed_images(&mut current, config, &mut Vec::new());
+        }
+
+        let xml = fill_template(html, record).map_err(|e| format!("record {i}: {e}"))?;
+        let (pages, _) = render_xml(
+            &xml,
+            config,
+            &images,
+            &mut context,
+            &mut current,
+            &mut |_| Vec::new(),
+            &mut Vec::new(),
+        )
+        .map_err(|e| format!("record {i}: {e}"))?;
+
+        let first_page = current.pages.len();
+        current.pages.extend(pages);
+        if current.pages.len() > first_page {
+            record_pages.push((first_page, current.pages.len() - 1));
+        }
+    }
+
+    if output == TemplateOutput::Concatenate && !record_pages.is_empty() {
+        current.document_parts = Some(DocumentPartRoot::from_records("Record", &record_pages));
+    }
+    remove_unused_images(&mut current, &images, &[]);
+    docs.push(current);
+    Ok(docs)
+}
+
+/// Replaces the `{{field}}` placeholders with the values of the record
+pub fn fill_template(template: &str, record: &Value) -> Result<String, String> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+
+        let key = rest[start + 2..start + 2 + len].trim();
+        let value = key
+            .split('.')
+            .try_fold(record, |v, k| match v {
+                Value::Array(a) => a.get(k.parse::<usize>().ok()?),
+                _ => v.get(k),
+            })
+            .ok_or_else(|| format!("missing field {key:?}"))?;
+
+        match value {
+            Value::Null => {}
+            Value::String(s) => out.push_str(&escape_xml(s)),
+            other => out.push_str(&escape_xml(&other.to_string())),
+        }
+        rest = &rest[start + 2 + len + 2..];
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[test]
+fn test_fill_template() {
+    let record = serde_json::json!({
+        "name": "Müller & Söhne",
+        "customer": { "id": 42, "vip": true },
+        "items": [{ "price": 9.5 }],
+        "note": null,
+    });
+
+    let filled = fill_template(
+        "<p>{{ name }}</p><p>{{customer.id}} {{customer.vip}} {{items.0.price}}{{note}}</p>",
+        &record,
+    )
+    .unwrap();
+    assert_eq!(filled, "<p>Müller &amp; Söhne</p><p>42 true 9.5</p>");
+
+    assert!(fill_template("{{customer.name}}", &record).is_err());
+    assert_eq!(
+        fill_template("{{ unclosed", &record).unwrap(),
+        "{{ unclosed"
+    );
+}
@@ -0,0 +1,797 @@
+//! Text layout helpers: glyph runs and text frames (positioning an already
+//! laid-out block of text lines inside a rectangle)
+
+use std::ops::Range;
+
+use crate::{
+    graphics::{PaintMode, Point, Polygon, Rect, WindingOrder},
+    matrix::{CurTransMat, TextMatrix},
+    ops::Op,
+    units::Pt,
+    FontId, ParsedFont,
+};
+
+/// Text state parameters that move the glyphs: character spacing (`Tc`), word spacing
+/// (`Tw`), horizontal scaling (`Tz`), leading (`TL`) and text rise (`Ts`). Part of the
+/// graphics state, i.e. saved and restored by `q` / `Q`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TextState {
+    pub character_spacing: f32,
+    pub word_spacing: f32,
+    /// 1.0 = 100%
+    pub horizontal_scaling: f32,
+    pub leading: f32,
+    pub rise: f32,
+}
+
+impl Default for TextState {
+    fn default() -> Self {
+        Self {
+            character_spacing: 0.0,
+            word_spacing: 0.0,
+            horizontal_scaling: 1.0,
+            leading: 0.0,
+            rise: 0.0,
+        }
+    }
+}
+
+impl TextState {
+    /// Updates the state if the operation sets one of the parameters
+    pub(crate) fn apply(&mut self, op: &Op) {
+        match op {
+            Op::SetCharacterSpacing { multiplier } => self.character_spacing = *multiplier,
+            Op::SetWordSpacing { percent } => self.word_spacing = *percent,
+            Op::SetHorizontalScaling { percent } => self.horizontal_scaling = *percent / 100.0,
+            Op::SetLineOffset { multiplier } => self.rise = *multiplier,
+            Op::SetLineHeight { lh } => self.leading = lh.0,
+            _ => {}
+        }
+    }
+
+    /// Horizontal displacement after a glyph of the given (font size scaled) width. The word
+    /// spacing only applies to the space character of single-byte encoded (builtin) fonts.
+    pub(crate) fn get_advance(&self, width: f32, is_word_space: bool) -> f32 {
+        let word_spacing = if is_word_space {
+            self.word_spacing
+        } else {
+            0.0
+        };
+        (width + self.character_spacing + word_spacing) * self.horizontal_scaling
+    }
+}
+
+/// Text matrix and text line matrix (not part of the graphics state, reset by `BT`)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) struct TextCursor {
+    pub text_matrix: [f32; 6],
+    pub line_matrix: [f32; 6],
+}
+
+impl Default for TextCursor {
+    fn default() -> Self {
+        let identity = CurTransMat::Identity.as_array();
+        Self {
+            text_matrix: identity,
+            line_matrix: identity,
+        }
+    }
+}
+
+impl TextCursor {
+    /// Updates the matrices for `BT`, `Tm`, `Td` and `T*`, returns false for other operations
+    pub(crate) fn apply(&mut self, op: &Op, state: &TextState) -> bool {
+        let line_matrix = match op {
+            Op::StartTextSection => CurTransMat::Identity.as_array(),
+            Op::SetTextMatrix { matrix } => matrix.as_array(),
+            Op::SetTextCursor { pos } => {
+                let translate = CurTransMat::Translate(pos.x, pos.y).as_array();
+                CurTransMat::combine_matrix(translate, self.line_matrix)
+            }
+            Op::AddLineBreak => {
+                let translate = CurTransMat::Translate(Pt(0.0), Pt(-state.leading)).as_array();
+                CurTransMat::combine_matrix(translate, self.line_matrix)
+            }
+            _ => return false,
+        };
+        self.line_matrix = line_matrix;
+        self.text_matrix = line_matrix;
+        true
+    }
+
+    /// Moves the text matrix along the baseline (in unscaled text space units)
+    pub(crate) fn advance(&mut self, tx: f32) {
+        let translate = CurTransMat::Translate(Pt(tx), Pt(0.0)).as_array();
+        self.text_matrix = CurTransMat::combine_matrix(translate, self.text_matrix);
+    }
+
+    /// Text space -> user space (or device space, depending on the `ctm`)
+    pub(crate) fn get_text_to_user(&self, ctm: [f32; 6]) -> [f32; 6] {
+        CurTransMat::combine_matrix(self.text_matrix, ctm)
+    }
+}
+
+/// Single glyph of a `GlyphRun`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunGlyph {
+    /// Glyph ID in the original (non-subset) font
+    pub glyph_id: u16,
+    /// Character that the glyph was generated from (needed for copy-pasting text from the PDF),
+    /// the first character for ligatures
+    pub codepoint: char,
+    /// Byte offset of the (first) character in the source text
+    pub cluster: usize,
+    /// Horizontal advance of the glyph, including kerning
+    pub advance: Pt,
+}
+
+/// Run of shaped glyphs in a single font and font size
+///
+/// Contains the position of every glyph, so that decorations (highlights, underlines,
+/// cursors) can be placed relative to the text. Ligatures map several characters to one
+/// glyph, marks can have a zero advance: the characters of a glyph are the ones from its
+/// `cluster` up to the cluster of the next glyph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlyphRun {
+    /// Font of the glyphs
+    pub font: FontId,
+    /// Font size of the run
+    pub size: Pt,
+    /// Source text of the run
+    pub text: String,
+    /// Glyphs in text order
+    pub glyphs: Vec<RunGlyph>,
+    /// Origin of each glyph, relative to the start of the run on the baseline
+    /// (including the offsets of the GPOS table, i.e. for marks)
+    pub positions: Vec<Point>,
+}
+
+/// Glyph of the shaped text, advance and offsets in font units
+struct PlacedGlyph {
+    glyph_id: u16,
+    cluster: usize,
+    advance: i32,
+    x_offset: i32,
+    y_offset: i32,
+}
+
+impl GlyphRun {
+    /// Shapes the text with the GSUB and GPOS tables of the font (ligatures, kerning,
+    /// contextual forms, mark positioning), using the script of the first letter. The glyphs
+    /// are laid out left to right in text order. Characters that are missing in the font
+    /// are skipped.
+    ///
+    /// Falls back to one glyph per character (via the cmap table, without kerning) if the
+    /// font can't be loaded for shaping.
+    pub fn from_text(text: &str, font: &ParsedFont, font_id: &FontId, size: Pt) -> Self {
+        let scale = size.0 / font.font_metrics.units_per_em as f32;
+        let placed = shape_text(text, font).unwrap_or_else(|| map_text(text, font));
+
+        let mut glyphs = Vec::new();
+        let mut positions = Vec::new();
+        let mut x = 0.0;
+
+        for glyph in placed {
+            positions.push(Point {
+                x: Pt(x + glyph.x_offset as f32 * scale),
+                y: Pt(glyph.y_offset as f32 * scale),
+            });
+            glyphs.push(RunGlyph {
+                glyph_id: glyph.glyph_id,
+                codepoint: text[glyph.cluster..].chars().next().unwrap_or(' '),
+                cluster: glyph.cluster,
+                advance: Pt(glyph.advance as f32 * scale),
+            });
+            x += glyph.advance as f32 * scale;
+        }
+
+        Self {
+            font: font_id.clone(),
+            size,
+            text: text.to_string(),
+            glyphs,
+            positions,
+        }
+    }
+
+    /// Returns the total advance width of the run
+    pub fn get_width(&self) -> Pt {
+        Pt(self.glyphs.iter().map(|g| g.advance.0).sum())
+    }
+
+    /// Returns the bounds of the glyph at `index` (full line height from descender to ascender),
+    /// relative to the start of the run - useful for highlighting or selection boxes
+    pub fn get_glyph_bounds(&self, index: usize, font: &ParsedFont) -> Option<Rect> {
+        let glyph = self.glyphs.get(index)?;
+        let pos = self.positions.get(index)?;
+        // NOTE: descender is negative
+        let descender = font.font_metrics.get_descender(self.size.0);
+        let ascender = font.font_metrics.get_ascender(self.size.0);
+        Some(Rect {
+            x: pos.x,
+            y: Pt(pos.y.0 + descender),
+            width: glyph.advance,
+            height: Pt(ascender - descender),
+        })
+    }
+
+    /// Returns the length of the source text in bytes
+    pub fn get_text_len(&self) -> usize {
+        self.text.len()
+    }
+
+    /// Returns the clusters of the run: the source text range of consecutive glyphs with the
+    /// same cluster and their horizontal extent (pen positions from the shaped advances)
+    fn get_clusters(&self) -> Vec<(Range<usize>, Range<f32>)> {
+        let mut clusters: Vec<(Range<usize>, Range<f32>)> = Vec::new();
+        let mut x = 0.0;
+        for glyph in self.glyphs.iter() {
+            match clusters.last_mut() {
+                Some((text, extent)) if text.start == glyph.cluster => {
+                    extent.end += glyph.advance.0;
+                }
+                _ => clusters.push((glyph.cluster..glyph.cluster, x..x + glyph.advance.0)),
+            }
+            x += glyph.advance.0;
+        }
+        // a cluster ends where the next one in the text starts (characters that are missing
+        // in the font belong to the previous cluster)
+        for i in 0..clusters.len() {
+            let start = clusters[i].0.start;
+            let end = clusters
+                .iter()
+                .map(|(text, _)| text.start)
+                .filter(|s| *s > start)
+                .min()
+                .unwrap_or(self.text.len());
+            clusters[i].0.end = end;
+        }
+        clusters
+    }
+
+    /// Byte offsets of the characters in `range`, the caret stops inside a cluster
+    fn get_char_offsets(&self, range: &Range<usize>) -> Vec<usize> {
+        self.text
+            .get(range.clone())
+            .map(|t| t.char_indices().map(|(i, _)| range.start + i).collect())
+            .unwrap_or_else(|| vec![range.start])
+    }
+
+    /// Returns the horizontal caret offset (relative to the run start) for the
+    /// text index `index` (byte offset in the source text). Inside a ligature, the
+    /// advance of the glyph is split evenly between its characters.
+    pub fn get_caret_offset(&self, index: usize) -> Pt {
+        for (text, extent) in self.get_clusters() {
+            if index >= text.end {
+                continue;
+            }
+            let offsets = self.get_char_offsets(&text);
+            let before = offsets.iter().filter(|o| **o < index).count();
+            let width = (extent.end - extent.start) / offsets.len() as f32;
+            return Pt(extent.start + width * before as f32);
+        }
+        self.get_width()
+    }
+
+    /// Returns the text index (byte offset in the source text) of the caret position
+    /// closest to `x` (relative to the run start), see `get_caret_offset`
+    pub fn hit_test(&self, x: Pt) -> usize {
+        for (text, extent) in self.get_clusters() {
+            if x.0 <= extent.start {
+                return text.start;
+            }
+            if x.0 >= extent.end {
+                continue;
+            }
+            let offsets = self.get_char_offsets(&text);
+            let width = (extent.end - extent.start) / offsets.len() as f32;
+            let slot = ((x.0 - extent.start) / width).round() as usize;
+            return offsets.get(slot).copied().unwrap_or(text.end);
+        }
+        self.get_text_len()
+    }
+
+    /// Returns the operations to write the run at the current text cursor position
+    /// (only valid between `StartTextSection` and `EndTextSection`). The difference between
+    /// the glyph positions and the advances of the font (kerning) is written as `TJ`
+    /// adjustment, vertical offsets are not written.
+    pub fn to_ops(&self, font: &ParsedFont) -> Vec<Op> {
+        let scale = self.size.0 / font.font_metrics.units_per_em as f32;
+        // position of the next glyph when the PDF viewer draws the text
+        let mut x = 0.0;
+        let cpk = self
+            .glyphs
+            .iter()
+            .zip(self.positions.iter())
+            .map(|(glyph, position)| {
+                // in thousandths of the font size, positive values move the glyph left
+                let kern = ((x - position.x.0) * 1000.0 / self.size.0).round() as i64;
+                x -= kern as f32 * self.size.0 / 1000.0;
+                x += font.get_horizontal_advance(glyph.glyph_id) as f32 * scale;
+                (kern, glyph.glyph_id, glyph.codepoint)
+            })
+            .collect();
+        vec![Op::WriteCodepointsWithKerning {
+            font: self.font.clone(),
+            size: self.size,
+            cpk,
+        }]
+    }
+}
+
+/// Shapes the text with allsorts, `None` if the font can't be loaded
+fn shape_text(text: &str, font: &ParsedFont) -> Option<Vec<PlacedGlyph>> {
+    use allsorts::{
+        binary::read::ReadScope,
+        font::MatchingPresentation,
+        font_data::FontData,
+        glyph_position::{GlyphLayout, TextDirection},
+        gsub::{FeatureMask, Features},
+    };
+
+    let scope = ReadScope::new(&font.original_bytes);
+    let font_file = scope.read::<FontData<'_>>().ok()?;
+    let provider = font_file.table_provider(font.original_index).ok()?;
+    let mut shaper = allsorts::font::Font::new(provider).ok()?;
+
+    let script = get_script_tag(text);
+    let raw_glyphs = shaper.map_glyphs(text, script, MatchingPresentation::NotRequired);
+    let features = Features::Mask(FeatureMask::default());
+    // on errors, the glyphs are returned with the substitutions applied so far
+    let infos = match shaper.shape(raw_glyphs, script, None, &features, None, true) {
+        Ok(infos) | Err((_, infos)) => infos,
+    };
+    let positions = GlyphLayout::new(&mut shaper, &infos, TextDirection::LeftToRight, false)
+        .glyph_positions()
+        .ok()?;
+
+    // the glyphs only know their characters, find them in the text to get the clusters
+    let mut placed = Vec::new();
+    let mut offset = 0;
+    let mut cluster = 0;
+    for (info, position) in infos.iter().zip(positions) {
+        let glyph = &info.glyph;
+        // glyphs from a multiple substitution repeat the characters of the first glyph
+        if !glyph.multi_subst_dup {
+            if let Some(c) = glyph.unicodes.first() {
+                cluster = text[offset..].find(*c).map_or(offset, |p| offset + p);
+                offset = cluster;
+                for c in glyph.unicodes.iter() {
+                    if text[offset..].starts_with(*c) {
+                        offset += c.len_utf8();
+                    }
+                }
+            }
+        }
+        // .notdef, the character is missing in the font
+        if glyph.glyph_index == 0 {
+            continue;
+        }
+        placed.push(PlacedGlyph {
+            glyph_id: glyph.glyph_index,
+            cluster,
+            advance: position.hori_advance,
+            x_offset: position.x_offset,
+            y_offset: position.y_offset,
+        });
+    }
+    Some(placed)
+}
+
+/// One glyph per character via the cmap table, with the advances of the font
+fn map_text(text: &str, font: &ParsedFont) -> Vec<PlacedGlyph> {
+    text.char_indices()
+        .filter_map(|(cluster, c)| {
+            let glyph_id = font.lookup_glyph_index(c as u32)?;
+            Some(PlacedGlyph {
+                glyph_id,
+                cluster,
+                advance: font.get_horizontal_advance(glyph_id) as i32,
+                x_offset: 0,
+                y_offset: 0,
+            })
+        })
+        .collect()
+}
+
+/// OpenType script tag of the first letter of the text, `DFLT` for scripts without
+/// special shaping rules
+fn get_script_tag(text: &str) -> u32 {
+    let tag = text
+        .chars()
+        .find_map(|c| match c as u32 {
+            0x0041..=0x005A | 0x0061..=0x007A | 0x00C0..=0x024F | 0x1E00..=0x1EFF => Some(b"latn"),
+            0x0370..=0x03FF | 0x1F00..=0x1FFF => Some(b"grek"),
+            0x0400..=0x052F => Some(b"cyrl"),
+            0x0590..=0x05FF => Some(b"hebr"),
+            0x0600..=0x06FF | 0x0750..=0x077F | 0x08A0..=0x08FF => Some(b"arab"),
+            0x0700..=0x074F => Some(b"syrc"),
+            0x0900..=0x097F => Some(b"deva"),
+            0x0980..=0x09FF => Some(b"beng"),
+            0x0A00..=0x0A7F => Some(b"guru"),
+            0x0A80..=0x0AFF => Some(b"gujr"),
+            0x0B00..=0x0B7F => Some(b"orya"),
+            0x0B80..=0x0BFF => Some(b"taml"),
+            0x0C00..=0x0C7F => Some(b"telu"),
+            0x0C80..=0x0CFF => Some(b"knda"),
+            0x0D00..=0x0D7F => Some(b"mlym"),
+            0x0D80..=0x0DFF => Some(b"sinh"),
+            0x0E00..=0x0E7F => Some(b"thai"),
+            0x1780..=0x17FF => Some(b"khmr"),
+            _ => None,
+        })
+        .unwrap_or(b"DFLT");
+    u32::from_be_bytes(*tag)
+}
+
+/// Position of a text cursor, from the lower left corner of the page
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CaretPosition {
+    /// Horizontal position of the caret
+    pub x: Pt,
+    /// Bottom of the caret (baseline + descender)
+    pub y: Pt,
+    /// Height of the caret (ascender - descender)
+    pub height: Pt,
+}
+
+/// Glyph run that has been placed on the page as one line of a paragraph
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlyphLine {
+    /// Glyphs of the line
+    pub run: GlyphRun,
+    /// Start of the baseline, from the lower left corner of the page
+    /// (i.e. the result of `TextFrame::get_baselines`)
+    pub origin: Point,
+    /// Byte offset of the first character of this line in the paragraph text
+    pub text_offset: usize,
+}
+
+impl GlyphLine {
+    /// Creates a new line from a glyph run
+    pub fn new(run: GlyphRun, origin: Point, text_offset: usize) -> Self {
+        Self {
+            run,
+            origin,
+            text_offset,
+        }
+    }
+}
+
+/// Returns the caret position for the text index `index` (byte offset into the paragraph
+/// text) in a block of laid-out lines. Returns `None` if there are no lines.
+///
+/// If the index sits on a line break, the caret is placed at the start of the next line.
+pub fn get_caret_position(
+    lines: &[GlyphLine],
+    index: usize,
+    font: &ParsedFont,
+) -> Option<CaretPosition> {
+    let line = lines
+        .iter()
+        .rev()
+        .find(|l| l.text_offset <= index)
+        .or_else(|| lines.first())?;
+    let local_index = index.saturating_sub(line.text_offset);
+    // NOTE: descender is negative
+    let ascender = font.font_metrics.get_ascender(line.run.size.0);
+    let descender = font.font_metrics.get_descender(line.run.size.0);
+    Some(CaretPosition {
+        x: line.origin.x + line.run.get_caret_offset(local_index),
+        y: Pt(line.origin.y.0 + descender),
+        height: Pt(ascender - descender),
+    })
+}
+
+/// Returns the text index (byte offset into the paragraph text) that is closest to
+/// `point` (from the lower left corner of the page). Returns `None` if there are no lines.
+pub fn hit_test(lines: &[GlyphLine], point: Point, font: &ParsedFont) -> Option<usize> {
+    let distance = |line: &GlyphLine| {
+        let ascender = font.font_metrics.get_ascender(line.run.size.0);
+        let descender = font.font_metrics.get_descender(line.run.size.0);
+        let top = line.origin.y.0 + ascender;
+        let bottom = line.origin.y.0 + descender;
+        if point.y.0 > top {
+            point.y.0 - top
+        } else if point.y.0 < bottom {
+            bottom - point.y.0
+        } else {
+            0.0
+        }
+    };
+
+    let line = lines
+        .iter()
+        .min_by(|a, b| distance(a).total_cmp(&distance(b)))?;
+
+    Some(line.text_offset + line.run.hit_test(point.x - line.origin.x))
+}
+
+/// Vertical alignment of the lines inside a `TextFrame`
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum VerticalAlign {
+    /// Lines start at the top edge of the frame
+    #[default]
+    Top,
+    /// Block of lines is centered vertically
+    Middle,
+    /// Last line sits on the bottom edge of the frame
+    Bottom,
+    /// Remaining space is distributed evenly between the lines, so that the first
+    /// line touches the top and the last line touches the bottom edge
+    /// (falls back to `Top` for a single line or if the lines overflow the frame)
+    Justify,
+}
+
+/// Single, already laid-out line of text
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextFrameLine {
+    /// Text operations for this line (`WriteText`, `WriteCodepoints`, etc.)
+    ///
+    /// The operations are emitted inside a text section, so they must not contain
+    /// `StartTextSection` / `EndTextSection` themselves.
+    pub ops: Vec<Op>,
+    /// Total height of the line box (ascent + descent + line gap)
+    pub line_height: Pt,
+    /// Distance from the top of the line box to the baseline
+    pub ascent: Pt,
+    /// Horizontal offset of the line from the left edge of the frame (used for text-align)
+    pub offset_x: Pt,
+}
+
+impl TextFrameLine {
+    /// Creates a new line with the given line height and ascent
+    pub fn new(ops: Vec<Op>, line_height: Pt, ascent: Pt) -> Self {
+        Self {
+            ops,
+            line_height,
+            ascent,
+            offset_x: Pt(0.0),
+        }
+    }
+
+    /// Creates a new line, taking the line height and ascent from the font metrics
+    pub fn from_font(ops: Vec<Op>, font: &ParsedFont, size: Pt) -> Self {
+        let metrics = &font.font_metrics;
+        let ascent = metrics.get_ascender(size.0);
+        // NOTE: descender is negative
+        let descent = metrics.get_descender(size.0);
+        let line_gap = metrics.get_line_gap(size.0);
+        Self::new(ops, Pt(ascent - descent + line_gap), Pt(ascent))
+    }
+
+    /// Sets the horizontal offset of the line inside the frame
+    pub fn with_offset_x(mut self, offset_x: Pt) -> Self {
+        self.offset_x = offset_x;
+        self
+    }
+}
+
+/// Rectangular box that text lines are positioned in, i.e. a table cell or a label
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextFrame {
+    /// Bounds of the frame, from the lower left corner of the page
+    pub rect: Rect,
+    /// How the block of lines is aligned vertically inside the frame
+    pub vertical_align: VerticalAlign,
+    /// Whether text overflowing the frame should be clipped (default: false)
+    pub clip: bool,
+}
+
+impl TextFrame {
+    /// Creates a new frame with the given bounds and vertical alignment
+    pub fn new(rect: Rect, vertical_align: VerticalAlign) -> Self {
+        Self {
+            rect,
+            vertical_align,
+            clip: false,
+        }
+    }
+
+    /// Clip overflowing text at the frame edges
+    pub fn with_clip(mut self, clip: bool) -> Self {
+        self.clip = clip;
+        self
+    }
+
+    /// Returns the baseline start position of each line (from the lower left corner of the page)
+    pub fn get_baselines(&self, lines: &[TextFrameLine]) -> Vec<Point> {
+        let total_height = lines.iter().map(|l| l.line_height.0).sum::<f32>();
+        let free_space = self.rect.height.0 - total_height;
+
+        let (mut offset_top, gap) = match self.vertical_align {
+            VerticalAlign::Top => (0.0, 0.0),
+            VerticalAlign::Middle => (free_space / 2.0, 0.0),
+            VerticalAlign::Bottom => (free_space, 0.0),
+            VerticalAlign::Justify => {
+                if lines.len() > 1 && free_space > 0.0 {
+                    (0.0, free_space / (lines.len() - 1) as f32)
+                } else {
+                    (0.0, 0.0)
+                }
+            }
+        };
+
+        let frame_top = self.rect.y.0 + self.rect.height.0;
+
+        lines
+            .iter()
+            .map(|line| {
+                let baseline = Point {
+                    x: self.rect.x + line.offset_x,
+                    y: Pt(frame_top - offset_top - line.ascent.0),
+                };
+                offset_top += line.line_height.0 + gap;
+                baseline
+            })
+            .collect()
+    }
+
+    /// Positions the lines inside the frame and returns the operations to draw them
+    pub fn layout(&self, lines: &[TextFrameLine]) -> Vec<Op> {
+        let mut ops = vec![Op::SaveGraphicsState];
+
+        if self.clip {
+            let ll = self.rect.lower_left();
+            let ur = self.rect.upper_right();
+            ops.push(Op::DrawPolygon {
+                polygon: Polygon {
+                    rings: vec![vec![
+                        (ll, false),
+                        (Point { x: ur.x, y: ll.y }, false),
+                        (ur, false),
+                        (Point { x: ll.x, y: ur.y }, false),
+                    ]],
+                    mode: PaintMode::Clip,
+                    winding_order: WindingOrder::NonZero,
+                },
+            });
+        }
+
+        ops.push(Op::StartTextSection);
+        for (line, baseline) in lines.iter().zip(self.get_baselines(lines)) {
+            ops.push(Op::SetTextMatrix {
+                matrix: TextMatrix::Translate(baseline.x, baseline.y),
+            });
+            ops.extend(line.ops.iter().cloned());
+        }
+        ops.push(Op::EndTextSection);
+        ops.push(Op::RestoreGraphicsState);
+
+        ops
+    }
+}
+
+#[test]
+fn test_text_frame_vertical_align() {
+    let rect = Rect {
+        x: Pt(10.0),
+        y: Pt(100.0),
+        width: Pt(200.0),
+        height: Pt(100.0),
+    };
+    let lines = vec![
+        TextFrameLine::new(Vec::new(), Pt(20.0), Pt(15.0)),
+        TextFrameLine::new(Vec::new(), Pt(20.0), Pt(15.0)),
+    ];
+
+    let top = TextFrame::new(rect.clone(), VerticalAlign::Top).get_baselines(&lines);
+    assert_eq!(top[0].y, Pt(185.0));
+    assert_eq!(top[1].y, Pt(165.0));
+
+    let middle = TextFrame::new(rect.clone(), VerticalAlign::Middle).get_baselines(&lines);
+    assert_eq!(middle[0].y, Pt(155.0));
+    assert_eq!(middle[1].y, Pt(135.0));
+
+    let bottom = TextFrame::new(rect.clone(), VerticalAlign::Bottom).get_baselines(&lines);
+    assert_eq!(bottom[0].y, Pt(125.0));
+    assert_eq!(bottom[1].y, Pt(105.0));
+
+    let justify = TextFrame::new(rect, VerticalAlign::Justify).get_baselines(&lines);
+    assert_eq!(justify[0].y, Pt(185.0));
+    assert_eq!(justify[1].y, Pt(105.0));
+}
+
+#[test]
+fn test_glyph_run_kerning() {
+    let bytes = include_bytes!("../examples/assets/fonts/RobotoMedium.ttf");
+    let font = ParsedFont::from_bytes(bytes, 0).unwrap();
+    let run = GlyphRun::from_text("AV", &font, &FontId("F1".to_string()), Pt(10.0));
+
+    assert_eq!(run.glyphs.len(), 2);
+    assert_eq!(run.glyphs[1].cluster, 1);
+    assert_eq!(run.glyphs[1].codepoint, 'V');
+    // "AV" is kerned: the V starts before the end of the unkerned A
+    let scale = 10.0 / font.font_metrics.units_per_em as f32;
+    let unkerned = font.get_horizontal_advance(run.glyphs[0].glyph_id) as f32 * scale;
+    assert!(run.positions[1].x.0 < unkerned);
+    assert_eq!(
+        run.get_width(),
+        run.glyphs[0].advance + run.glyphs[1].advance
+    );
+
+    let [Op::WriteCodepointsWithKerning { cpk, .. }] = run.to_ops(&font).as_slice() else {
+        panic!("expected one text op");
+    };
+    assert_eq!(cpk[0].0, 0);
+    assert!(cpk[1].0 > 0);
+}
+
+#[test]
+fn test_glyph_run_caret_hit_test() {
+    let run = GlyphRun {
+        font: FontId("F1".to_string()),
+        size: Pt(10.0),
+        text: "aäb".to_string(),
+        glyphs: "aäb"
+            .char_indices()
+            .enumerate()
+            .map(|(i, (cluster, codepoint))| RunGlyph {
+                glyph_id: i as u16 + 1,
+                codepoint,
+                cluster,
+                advance: Pt(10.0),
+            })
+            .collect(),
+        positions: (0..3)
+            .map(|i| Point {
+                x: Pt(i as f32 * 10.0),
+                y: Pt(0.0),
+            })
+            .collect(),
+    };
+
+    assert_eq!(run.get_text_len(), 4);
+    assert_eq!(run.get_caret_offset(0), Pt(0.0));
+    assert_eq!(run.get_caret_offset(3), Pt(20.0));
+    assert_eq!(run.get_caret_offset(4), Pt(30.0));
+
+    assert_eq!(run.hit_test(Pt(-5.0)), 0);
+    assert_eq!(run.hit_test(Pt(4.0)), 0);
+    assert_eq!(run.hit_test(Pt(6.0)), 1);
+    assert_eq!(run.hit_test(Pt(16.0)), 3);
+    assert_eq!(run.hit_test(Pt(100.0)), 4);
+}
+
+#[test]
+fn test_glyph_run_ligature_caret() {
+    // "fi" ligature followed by "x"
+    let run = GlyphRun {
+        font: FontId("F1".to_string()),
+        size: Pt(10.0),
+        text: "fix".to_string(),
+        glyphs: vec![
+            RunGlyph {
+                glyph_id: 1,
+                codepoint: 'f',
+                cluster: 0,
+                advance: Pt(12.0),
+            },
+            RunGlyph {
+                glyph_id: 2,
+                codepoint: 'x',
+                cluster: 2,
+                advance: Pt(6.0),
+            },
+        ],
+        positions: vec![
+            Point {
+                x: Pt(0.0),
+                y: Pt(0.0),
+            },
+            Point {
+                x: Pt(12.0),
+                y: Pt(0.0),
+            },
+        ],
+    };
+
+    assert_eq!(run.get_caret_offset(1), Pt(6.0));
+    assert_eq!(run.get_caret_offset(2), Pt(12.0));
+    assert_eq!(run.get_caret_offset(3), Pt(18.0));
+
+    assert_eq!(run.hit_test(Pt(2.0)), 0);
+    assert_eq!(run.hit_test(Pt(4.0)), 1);
+    assert_eq!(run.hit_test(Pt(10.0)), 2);
+    assert_eq!(run.hit_test(Pt(16.0)), 3);
+}
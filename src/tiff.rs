@@ -0,0 +1,487 @@
+//! TIFF decoding for scanner output: multi-strip, tiled, planar and CMYK TIFFs
+//! (uncompressed, LZW, Deflate and PackBits compressed). Only the first page is read.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use crate::{
+    filters::{apply_predictor, flate_decode, lzw_decode, run_length_decode},
+    DecodeParms, EncodedImage, RawImage, RawImageData, RawImageFormat, StreamFilter,
+};
+
+const IMAGE_WIDTH: u16 = 256;
+const IMAGE_LENGTH: u16 = 257;
+const BITS_PER_SAMPLE: u16 = 258;
+const COMPRESSION: u16 = 259;
+const PHOTOMETRIC: u16 = 262;
+const STRIP_OFFSETS: u16 = 273;
+const SAMPLES_PER_PIXEL: u16 = 277;
+const ROWS_PER_STRIP: u16 = 278;
+const STRIP_BYTE_COUNTS: u16 = 279;
+const PLANAR_CONFIGURATION: u16 = 284;
+const PREDICTOR: u16 = 317;
+const COLOR_MAP: u16 = 320;
+const TILE_WIDTH: u16 = 322;
+const TILE_LENGTH: u16 = 323;
+const TILE_OFFSETS: u16 = 324;
+const TILE_BYTE_COUNTS: u16 = 325;
+const INK_SET: u16 = 332;
+const EXTRA_SAMPLES: u16 = 338;
+const ICC_PROFILE: u16 = 34675;
+
+impl RawImage {
+    /// Decodes the first page of a TIFF file. Grayscale, RGB, CMYK and palette images
+    /// are kept as compressed samples (see `RawImage::source`), so CMYK colors and
+    /// ICC profiles are preserved in the PDF. Images with alpha are decoded to pixels.
+    pub fn from_tiff_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let reader = TiffReader::new(bytes)?;
+        let ifd = reader.read_ifd(reader.u32(4).ok_or("truncated TIFF header")? as usize)?;
+        decode_image(&reader, &ifd)
+    }
+}
+
+struct TiffReader<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+/// Tags of an image file directory: integer values and the raw bytes of every tag
+struct Ifd<'a> {
+    values: BTreeMap<u16, Vec<u32>>,
+    bytes: BTreeMap<u16, &'a [u8]>,
+}
+
+impl Ifd<'_> {
+    fn get(&self, tag: u16) -> Option<u32> {
+        self.values.get(&tag).and_then(|v| v.first()).copied()
+    }
+
+    fn get_all(&self, tag: u16) -> &[u32] {
+        self.values
+            .get(&tag)
+            .map(|v| v.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+impl<'a> TiffReader<'a> {
+    fn new(data: &'a [u8]) -> Result<Self, String> {
+        let big_endian = match data.get(..4) {
+            Some(b"II*\0") => false,
+            Some(b"MM\0*") => true,
+            _ => return Err("not a TIFF file (BigTIFF is not supported)".to_string()),
+        };
+        Ok(Self { data, big_endian })
+    }
+
+    fn u16(&self, pos: usize) -> Option<u16> {
+        let b = [*self.data.get(pos)?, *self.data.get(pos + 1)?];
+        Some(if self.big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        })
+    }
+
+    fn u32(&self, pos: usize) -> Option<u32> {
+        let b: [u8; 4] = self.data.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        })
+    }
+
+    fn read_ifd(&self, offset: usize) -> Result<Ifd<'a>, String> {
+        let count = self.u16(offset).ok_or("IFD out of bounds")? as usize;
+        let mut ifd = Ifd {
+            values: BTreeMap::new(),
+            bytes: BTreeMap::new(),
+        };
+
+        for i in 0..count {
+            let entry = offset + 2 + i * 12;
+            let (Some(tag), Some(ty), Some(n)) =
+                (self.u16(entry), self.u16(entry + 2), self.u32(entry + 4))
+            else {
+                return Err("truncated IFD".to_string());
+            };
+            let size = match ty {
+                // BYTE, ASCII, SBYTE, UNDEFINED
+                1 | 2 | 6 | 7 => 1,
+                // SHORT, SSHORT
+                3 | 8 => 2,
+                // LONG, SLONG
+                4 | 9 => 4,
+                // rationals, floats: not needed
+                _ => continue,
+            };
+            let len = size * n as usize;
+            let start = if len <= 4 {
+                entry + 8
+            } else {
+                self.u32(entry + 8).ok_or("truncated IFD")? as usize
+            };
+            let Some(bytes) = self.data.get(start..start + len) else {
+                continue;
+            };
+
+            let values = (0..n as usize)
+                .filter_map(|j| match size {
+                    1 => Some(bytes[j] as u32),
+                    2 => self.u16(start + j * 2).map(u32::from),
+                    _ => self.u32(start + j * 4),
+                })
+                .collect();
+            ifd.values.insert(tag, values);
+            ifd.bytes.insert(tag, bytes);
+        }
+        Ok(ifd)
+    }
+}
+
+fn decompress(data: &[u8], compression: u32) -> Result<Vec<u8>, String> {
+    match compression {
+        1 => Ok(data.to_vec()),
+        5 => lzw_decode(data, true),
+        8 | 32946 => flate_decode(data),
+        32773 => Ok(run_length_decode(data, false)),
+        c => Err(format!("unsupported TIFF compression {c}")),
+    }
+}
+
+fn decode_image(reader: &TiffReader, ifd: &Ifd) -> Result<RawImage, String> {
+    let width = ifd.get(IMAGE_WIDTH).ok_or("missing TIFF image width")? as usize;
+    let height = ifd.get(IMAGE_LENGTH).ok_or("missing TIFF image length")? as usize;
+    let spp = ifd.get(SAMPLES_PER_PIXEL).unwrap_or(1).max(1) as usize;
+    let bps = ifd.get(BITS_PER_SAMPLE).unwrap_or(1) as usize;
+    let photometric = ifd
+        .get(PHOTOMETRIC)
+        .ok_or("missing TIFF photometric interpretation")?;
+    let planar = ifd.get(PLANAR_CONFIGURATION) == Some(2);
+
+    if ifd
+        .get_all(BITS_PER_SAMPLE)
+        .iter()
+        .any(|b| *b as usize != bps)
+    {
+        return Err("TIFF samples with different bit depths are not supported".to_string());
+    }
+    if ![1, 2, 4, 8, 16].contains(&bps) {
+        return Err(format!("unsupported TIFF bits per sample: {bps}"));
+    }
+
+    let (color_space, components) = match photometric {
+        // WhiteIsZero, BlackIsZero
+        0 | 1 => ("DeviceGray", 1),
+        2 => ("DeviceRGB", 3),
+        // palette, expanded to RGB below
+        3 => ("DeviceRGB", 1),
+        // separated, only CMYK inks
+        5 if ifd.get(INK_SET).unwrap_or(1) == 1 => ("DeviceCMYK", 4),
+        p => return Err(format!("unsupported TIFF photometric interpretation {p}")),
+    };
+    if spp < components {
+        return Err(format!("{spp} samples per pixel for {color_space}"));
+    }
+
+    let samples = read_samples(reader, ifd, width, height, spp, bps, planar)?;
+
+    // palette: 16 bit RGB values, all red values first
+    if photometric == 3 {
+        let map = ifd.get_all(COLOR_MAP);
+        let colors = 1 << bps;
+        if map.len() < colors * 3 || spp != 1 {
+            return Err("invalid TIFF color map".to_string());
+        }
+        let rgb = unpack_samples(&samples, width, height, 1, bps)
+            .into_iter()
+            .flat_map(|i| [0, 1, 2].map(|c| (map[c * colors + i as usize] >> 8) as u8))
+            .collect();
+        return Ok(encoded_image(width, height, rgb, "DeviceRGB", 8, None, ifd));
+    }
+
+    let samples = if spp > components {
+        if bps < 8 {
+            return Err("TIFF extra samples need 8 or 16 bits per sample".to_string());
+        }
+        // associated or unassociated alpha
+        let has_alpha = matches!(ifd.get(EXTRA_SAMPLES), Some(1 | 2)) && components != 4;
+        if has_alpha {
+            return Ok(decode_with_alpha(
+                &samples,
+                width,
+                height,
+                spp,
+                components,
+                bps,
+                photometric == 0,
+            ));
+        }
+        // drop the extra samples
+        let b = bps / 8;
+        samples
+            .chunks_exact(spp * b)
+            .flat_map(|px| px[..components * b].iter().copied())
+            .collect()
+    } else {
+        samples
+    };
+
+    // WhiteIsZero: invert with the /Decode array
+    let decode = (photometric == 0).then(|| vec![1.0, 0.0]);
+    Ok(encoded_image(
+        width,
+        height,
+        samples,
+        color_space,
+        bps as u8,
+        decode,
+        ifd,
+    ))
+}
+
+/// Reads the strips or tiles into chunky (interleaved) samples, each row is padded
+/// to a full byte
+fn read_samples(
+    reader: &TiffReader,
+    ifd: &Ifd,
+    width: usize,
+    height: usize,
+    spp: usize,
+    bps: usize,
+    planar: bool,
+) -> Result<Vec<u8>, String> {
+    let (offsets, counts, chunk_w, chunk_h) = if ifd.values.contains_key(&TILE_OFFSETS) {
+        (
+            ifd.get_all(TILE_OFFSETS),
+            ifd.get_all(TILE_BYTE_COUNTS),
+            ifd.get(TILE_WIDTH).ok_or("missing TIFF tile width")? as usize,
+            ifd.get(TILE_LENGTH).ok_or("missing TIFF tile length")? as usize,
+        )
+    } else {
+        let rows_per_strip = ifd.get(ROWS_PER_STRIP).map(|r| r as usize);
+        (
+            ifd.get_all(STRIP_OFFSETS),
+            ifd.get_all(STRIP_BYTE_COUNTS),
+            width,
+            rows_per_strip.unwrap_or(height).min(height),
+        )
+    };
+    if chunk_w == 0 || chunk_h == 0 {
+        return Err("invalid TIFF strip / tile size".to_string());
+    }
+    if planar && bps < 8 {
+        return Err("planar TIFFs need 8 or 16 bits per sample".to_string());
+    }
+
+    let compression = ifd.get(COMPRESSION).unwrap_or(1);
+    let predictor = ifd.get(PREDICTOR).unwrap_or(1);
+    if predictor > 2 {
+        return Err(format!("unsupported TIFF predictor {predictor}"));
+    }
+
+    // planar images store each sample in its own set of strips / tiles
+    let (planes, chunk_spp) = if planar { (spp, 1) } else { (1, spp) };
+    let across = width.div_ceil(chunk_w);
+    let down = height.div_ceil(chunk_h);
+    if offsets.len() < across * down * planes || counts.len() < offsets.len() {
+        return Err("missing TIFF strip / tile offsets".to_string());
+    }
+
+    let chunk_row_len = (chunk_w * chunk_spp * bps).div_ceil(8);
+    let row_len = (width * chunk_spp * bps).div_ceil(8);
+    let parms = DecodeParms {
+        predictor: Some(predictor as i64),
+        colors: Some(chunk_spp as i64),
+        bits_per_component: Some(bps as i64),
+        columns: Some(chunk_w as i64),
+        ..Default::default()
+    };
+
+    let mut plane_data = vec![vec![0u8; row_len * height]; planes];
+    for (plane, data) in plane_data.iter_mut().enumerate() {
+        for cy in 0..down {
+            for cx in 0..across {
+                let i = (plane * down + cy) * across + cx;
+                let (start, len) = (offsets[i] as usize, counts[i] as usize);
+                let raw = reader
+                    .data
+                    .get(start..start + len)
+                    .ok_or("TIFF strip / tile out of bounds")?;
+                let chunk = apply_predictor(decompress(raw, compression)?, &parms)?;
+
+                let x = cx * chunk_w * chunk_spp * bps / 8;
+                let copy_len = chunk_row_len.min(row_len - x);
+                for y in 0..chunk_h.min(height - cy * chunk_h) {
+                    let Some(src) = chunk.get(y * chunk_row_len..y * chunk_row_len + copy_len)
+                    else {
+                        break;
+                    };
+                    let dst = (cy * chunk_h + y) * row_len + x;
+                    data[dst..dst + copy_len].copy_from_slice(src);
+                }
+            }
+        }
+    }
+
+    if !planar {
+        return Ok(plane_data.swap_remove(0));
+    }
+    let b = bps / 8;
+    Ok((0..width * height)
+        .flat_map(|px| {
+            plane_data
+                .iter()
+                .flat_map(move |p| &p[px * b..(px + 1) * b])
+        })
+        .copied()
+        .collect())
+}
+
+/// Unpacks 1 - 16 bit samples (rows padded to a full byte)
+fn unpack_samples(data: &[u8], width: usize, height: usize, spp: usize, bps: usize) -> Vec<u16> {
+    let row_len = (width * spp * bps).div_ceil(8);
+    let mut out = Vec::with_capacity(width * height * spp);
+    for row in data.chunks(row_len).take(height) {
+        for i in 0..width * spp {
+            let bit = i * bps;
+            let v = match bps {
+                16 => u16::from_be_bytes([row[bit / 8], row[bit / 8 + 1]]),
+                8 => row[bit / 8] as u16,
+                _ => ((row[bit / 8] >> (8 - bps - bit % 8)) & ((1 << bps) - 1) as u8) as u16,
+            };
+            out.push(v);
+        }
+    }
+    out
+}
+
+fn decode_with_alpha(
+    samples: &[u8],
+    width: usize,
+    height: usize,
+    spp: usize,
+    components: usize,
+    bps: usize,
+    invert: bool,
+) -> RawImage {
+    let max = if bps == 16 { u16::MAX } else { u8::MAX as u16 };
+    let values = unpack_samples(samples, width, height, spp, bps);
+    let rgba = values
+        .chunks_exact(spp)
+        .flat_map(|px| {
+            let color = |c: usize| if invert { max - px[c] } else { px[c] };
+            let (r, g, b) = match components {
+                1 => (color(0), color(0), color(0)),
+                _ => (color(0), color(1), color(2)),
+            };
+            [r, g, b, px[components]]
+        })
+        .collect::<Vec<_>>();
+
+    let (pixels, data_format) = if bps == 16 {
+        (RawImageData::U16(rgba), RawImageFormat::RGBA16)
+    } else {
+        let rgba = rgba.into_iter().map(|v| v as u8).collect();
+        (RawImageData::U8(rgba), RawImageFormat::RGBA8)
+    };
+    RawImage {
+        pixels,
+        width,
+        height,
+        data_format,
+        tag: Vec::new(),
+        source: None,
+    }
+}
+
+/// Deflates the samples, the image is written as a Flate stream without decoding
+fn encoded_image(
+    width: usize,
+    height: usize,
+    samples: Vec<u8>,
+    color_space: &str,
+    bits_per_component: u8,
+    decode: Option<Vec<f32>>,
+    ifd: &Ifd,
+) -> RawImage {
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    let bytes = match encoder.write_all(&samples) {
+        Ok(()) => encoder.finish().unwrap_or_default(),
+        Err(_) => Vec::new(),
+    };
+    let source = EncodedImage {
+        bytes,
+        filters: vec![StreamFilter::new("FlateDecode")],
+        color_space: color_space.to_string(),
+        icc_profile: ifd.bytes.get(&ICC_PROFILE).map(|icc| icc.to_vec()),
+        bits_per_component,
+        decode,
+    };
+    let data_format = source.get_decoded_format();
+    RawImage {
+        pixels: RawImageData::empty(data_format),
+        width,
+        height,
+        data_format,
+        tag: Vec::new(),
+        source: Some(source),
+    }
+}
+
+#[test]
+fn test_planar_cmyk_tiff() {
+    // 2 x 2 px, planar CMYK, one row per strip: white, cyan / magenta, yellow
+    let planes: [[u8; 4]; 4] = [[0, 255, 0, 0], [0, 0, 255, 0], [0, 0, 0, 255], [0; 4]];
+    let mut tiff = b"II*\0".to_vec();
+    tiff.extend_from_slice(&24u32.to_le_bytes());
+    for plane in planes {
+        tiff.extend_from_slice(&plane);
+    }
+
+    let strip_offsets = (0..8).map(|i| 8 + i * 2).collect::<Vec<u32>>();
+    let entries: [(u16, u16, Vec<u32>); 10] = [
+        (IMAGE_WIDTH, 3, vec![2]),
+        (IMAGE_LENGTH, 3, vec![2]),
+        (BITS_PER_SAMPLE, 3, vec![8]),
+        (COMPRESSION, 3, vec![1]),
+        (PHOTOMETRIC, 3, vec![5]),
+        (STRIP_OFFSETS, 4, strip_offsets),
+        (SAMPLES_PER_PIXEL, 3, vec![4]),
+        (ROWS_PER_STRIP, 3, vec![1]),
+        (STRIP_BYTE_COUNTS, 4, vec![2; 8]),
+        (PLANAR_CONFIGURATION, 3, vec![2]),
+    ];
+
+    // arrays that don't fit into the entries are stored after the IFD
+    let mut extra = Vec::new();
+    let extra_start = 24 + 2 + entries.len() * 12 + 4;
+    tiff.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    for (tag, ty, values) in entries.iter() {
+        tiff.extend_from_slice(&tag.to_le_bytes());
+        tiff.extend_from_slice(&ty.to_le_bytes());
+        tiff.extend_from_slice(&(values.len() as u32).to_le_bytes());
+        if values.len() == 1 {
+            tiff.extend_from_slice(&values[0].to_le_bytes());
+        } else {
+            tiff.extend_from_slice(&((extra_start + extra.len()) as u32).to_le_bytes());
+            extra.extend(values.iter().flat_map(|v| v.to_le_bytes()));
+        }
+    }
+    tiff.extend_from_slice(&0u32.to_le_bytes());
+    tiff.extend_from_slice(&extra);
+
+    let image = RawImage::from_tiff_bytes(&tiff).unwrap();
+    assert_eq!((image.width, image.height), (2, 2));
+    let source = image.source.as_ref().unwrap();
+    assert_eq!(source.color_space, "DeviceCMYK");
+    assert_eq!(
+        flate_decode(&source.bytes).unwrap(),
+        vec![0, 0, 0, 0, 255, 0, 0, 0, 0, 255, 0, 0, 0, 0, 255, 0]
+    );
+    assert_eq!(
+        *image.pixels().unwrap(),
+        RawImageData::U8(vec![255, 255, 255, 0, 255, 255, 255, 0, 255, 255, 255, 0])
+    );
+}
@@ -0,0 +1,34 @@
+//! Optional `tracing` instrumentation (enabled with the `tracing` feature), the macros
+//! expand to nothing if the feature is disabled
+
+/// Enters a span until the end of the scope: `let _span = trace_span!("name", key = value);`
+#[cfg(feature = "tracing")]
+macro_rules! trace_span {
+    ($($args:tt)*) => {
+        tracing::debug_span!($($args)*).entered()
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_span {
+    ($($args:tt)*) => {
+        $crate::trace::NoSpan
+    };
+}
+
+/// Placeholder for the span guard if the `tracing` feature is disabled
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct NoSpan;
+
+/// Emits a debug event: `trace_debug!(key = value, "message")`
+#[cfg(feature = "tracing")]
+macro_rules! trace_debug {
+    ($($args:tt)*) => {
+        tracing::debug!($($args)*)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_debug {
+    ($($args:tt)*) => {};
+}
@@ -66,20 +66,71 @@ pub(crate) fn to_pdf_time_stamp_metadata(date: &OffsetDateTime) -> String {
         date.second(),
     )
 }
+/// Parses a PDF date string (`D:YYYYMMDDHHmmSSOHH'mm'`), all fields after the year
+/// are optional
+#[cfg(not(target_family = "wasm"))]
+pub(crate) fn parse_pdf_date(s: &str) -> Option<OffsetDateTime> {
+    let s = s.trim().trim_start_matches("D:");
+    let digits = s.bytes().take_while(|b| b.is_ascii_digit()).count();
+    let field = |start: usize, len: usize, default: u32| -> Option<u32> {
+        if start + len > digits {
+            return Some(default);
+        }
+        s.get(start..start + len)?.parse().ok()
+    };
+    if digits < 4 {
+        return None;
+    }
+
+    let year = field(0, 4, 0)? as i32;
+    let month = time::Month::try_from(field(4, 2, 1)? as u8).ok()?;
+    let date = time::Date::from_calendar_date(year, month, field(6, 2, 1)? as u8).ok()?;
+    let time = time::Time::from_hms(
+        field(8, 2, 0)? as u8,
+        field(10, 2, 0)? as u8,
+        field(12, 2, 0)? as u8,
+    )
+    .ok()?;
+
+    // time zone: Z, +HH'mm' or -HH'mm'
+    let tz = &s[digits..];
+    let offset = match tz.chars().next() {
+        Some(sign @ ('+' | '-')) => {
+            let parts = tz[1..]
+                .split('\'')
+                .filter_map(|p| p.parse::<i8>().ok())
+                .collect::<Vec<_>>();
+            let (h, m) = (
+                parts.first().copied().unwrap_or(0),
+                parts.get(1).copied().unwrap_or(0),
+            );
+            let sign = if sign == '-' { -1 } else { 1 };
+            time::UtcOffset::from_hms(sign * h, sign * m, 0).ok()?
+        }
+        _ => time::UtcOffset::UTC,
+    };
+
+    Some(time::PrimitiveDateTime::new(date, time).assume_offset(offset))
+}
+
+#[cfg(target_family = "wasm")]
+pub(crate) fn parse_pdf_date(_: &str) -> Option<OffsetDateTime> {
+    None
+}
+
 #[cfg(target_family = "wasm")]
 pub(crate) fn to_pdf_xmp_date(date: &OffsetDateTime) -> String {
-    "D:1970-01-01T00:00:00+00'00'".to_string()
+    "1970-01-01T00:00:00+00:00".to_string()
 }
 
-// D:2018-09-19T10:05:05+00'00'
+/// ISO 8601 date of the XMP metadata, in UTC: `2018-09-19T10:05:05+00:00`
 #[cfg(not(target_family = "wasm"))]
 pub(crate) fn to_pdf_xmp_date(date: &OffsetDateTime) -> String {
-    // Since the time is in UTC, we know that the time zone
-    // difference to UTC is 0 min, 0 sec, hence the 00'00
+    let date = date.to_offset(time::UtcOffset::UTC);
     format!(
-        "D:{:04}-{:02}-{:02}T{:02}:{:02}:{:02}+00'00'",
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}+00:00",
         date.year(),
-        date.month(),
+        u8::from(date.month()),
         date.day(),
         date.hour(),
         date.minute(),
@@ -157,3 +208,12 @@ pub(crate) fn u16vec_to_u8(data: Vec<u16>) -> Vec<u8> {
 pub(crate) fn f32vec_to_u8(data: Vec<f32>) -> Vec<u8> {
     data.iter().flat_map(|us| us.to_be_bytes()).collect()
 }
+
+#[cfg(not(target_family = "wasm"))]
+#[test]
+fn test_to_pdf_xmp_date() {
+    let date = OffsetDateTime::from_unix_timestamp(1_714_557_600)
+        .unwrap()
+        .to_offset(time::UtcOffset::from_hms(2, 0, 0).unwrap());
+    assert_eq!(to_pdf_xmp_date(&date), "2024-05-01T10:00:00+00:00");
+}
@@ -0,0 +1,336 @@
+//! Viewer preferences (`/ViewerPreferences` in the document catalog), i.e. presets
+//! for the print dialog of the PDF viewer
+
+use lopdf::Dictionary as LoDictionary;
+use lopdf::Object::{Array, Boolean, Dictionary, Integer, Name};
+
+/// Paper handling option preselected in the print dialog
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Duplex {
+    /// Print single-sided
+    Simplex,
+    /// Duplex and flip on the short edge of the sheet
+    DuplexFlipShortEdge,
+    /// Duplex and flip on the long edge of the sheet
+    DuplexFlipLongEdge,
+}
+
+impl Duplex {
+    pub fn get_id(&self) -> &'static str {
+        match self {
+            Duplex::Simplex => "Simplex",
+            Duplex::DuplexFlipShortEdge => "DuplexFlipShortEdge",
+            Duplex::DuplexFlipLongEdge => "DuplexFlipLongEdge",
+        }
+    }
+}
+
+/// Page scaling option preselected in the print dialog
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PrintScaling {
+    /// Print at actual size (no "shrink to fit")
+    None,
+    /// Use the default scaling of the viewer
+    AppDefault,
+}
+
+impl PrintScaling {
+    pub fn get_id(&self) -> &'static str {
+        match self {
+            PrintScaling::None => "None",
+            PrintScaling::AppDefault => "AppDefault",
+        }
+    }
+}
+
+/// Page layout used when the document is opened (`/PageLayout` in the catalog)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PageLayout {
+    /// One page at a time
+    SinglePage,
+    /// Pages in one continuous column
+    OneColumn,
+    /// Pages in two columns, odd pages on the left
+    TwoColumnLeft,
+    /// Pages in two columns, odd pages on the right
+    TwoColumnRight,
+    /// Two pages at a time, odd pages on the left (PDF 1.5)
+    TwoPageLeft,
+    /// Two pages at a time, odd pages on the right (PDF 1.5)
+    TwoPageRight,
+}
+
+impl PageLayout {
+    pub fn get_id(&self) -> &'static str {
+        match self {
+            PageLayout::SinglePage => "SinglePage",
+            PageLayout::OneColumn => "OneColumn",
+            PageLayout::TwoColumnLeft => "TwoColumnLeft",
+            PageLayout::TwoColumnRight => "TwoColumnRight",
+            PageLayout::TwoPageLeft => "TwoPageLeft",
+            PageLayout::TwoPageRight => "TwoPageRight",
+        }
+    }
+}
+
+/// Panel shown when the document is opened (`/PageMode` in the catalog)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PageMode {
+    /// No panel
+    UseNone,
+    /// Outline (bookmarks) panel
+    UseOutlines,
+    /// Page thumbnails
+    UseThumbs,
+    /// Full-screen mode, without menu bar and window controls
+    FullScreen,
+    /// Layers panel (PDF 1.5)
+    UseOC,
+    /// Attachments panel (PDF 1.6)
+    UseAttachments,
+}
+
+impl PageMode {
+    pub fn get_id(&self) -> &'static str {
+        match self {
+            PageMode::UseNone => "UseNone",
+            PageMode::UseOutlines => "UseOutlines",
+            PageMode::UseThumbs => "UseThumbs",
+            PageMode::FullScreen => "FullScreen",
+            PageMode::UseOC => "UseOC",
+            PageMode::UseAttachments => "UseAttachments",
+        }
+    }
+}
+
+/// Preferences for how the document should be presented and printed by the viewer.
+/// Viewers may ignore these settings.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ViewerPreferences {
+    /// Duplex mode of the print dialog
+    pub duplex: Option<Duplex>,
+    /// Select the paper tray by the page size of the PDF instead of the printer settings
+    pub pick_tray_by_pdf_size: Option<bool>,
+    /// Page ranges (0-based, inclusive) preselected in the print dialog
+    pub print_page_range: Vec<(usize, usize)>,
+    /// Number of copies preselected in the print dialog (viewers only support 2 - 5)
+    pub num_copies: Option<usize>,
+    /// Page scaling of the print dialog, `PrintScaling::None` = print at actual size
+    pub print_scaling: Option<PrintScaling>,
+    /// Hide the toolbar of the viewer
+    pub hide_toolbar: Option<bool>,
+    /// Hide the menu bar of the viewer
+    pub hide_menubar: Option<bool>,
+    /// Hide the scrollbars and navigation controls, only show the document
+    pub hide_window_ui: Option<bool>,
+    /// Resize the window to fit the first page
+    pub fit_window: Option<bool>,
+    /// Center the window on the screen
+    pub center_window: Option<bool>,
+    /// Show the document title (instead of the file name) in the title bar
+    pub display_doc_title: Option<bool>,
+    /// Page layout when the document is opened (written to the catalog)
+    pub page_layout: Option<PageLayout>,
+    /// Panel shown when the document is opened (written to the catalog, overrides the
+    /// outline panel that is shown by default for documents with bookmarks)
+    pub page_mode: Option<PageMode>,
+}
+
+impl ViewerPreferences {
+    /// Sets the duplex mode of the print dialog
+    pub fn with_duplex(mut self, duplex: Duplex) -> Self {
+        self.duplex = Some(duplex);
+        self
+    }
+
+    /// Selects the paper tray by the page size of the PDF
+    pub fn with_pick_tray_by_pdf_size(mut self, pick_tray_by_pdf_size: bool) -> Self {
+        self.pick_tray_by_pdf_size = Some(pick_tray_by_pdf_size);
+        self
+    }
+
+    /// Adds a page range (0-based, inclusive) to print
+    pub fn with_print_page_range(mut self, first_page: usize, last_page: usize) -> Self {
+        self.print_page_range.push((first_page, last_page));
+        self
+    }
+
+    /// Sets the number of copies to print
+    pub fn with_num_copies(mut self, num_copies: usize) -> Self {
+        self.num_copies = Some(num_copies);
+        self
+    }
+
+    /// Sets the page scaling of the print dialog
+    pub fn with_print_scaling(mut self, print_scaling: PrintScaling) -> Self {
+        self.print_scaling = Some(print_scaling);
+        self
+    }
+
+    /// Hides the toolbar of the viewer
+    pub fn with_hide_toolbar(mut self, hide_toolbar: bool) -> Self {
+        self.hide_toolbar = Some(hide_toolbar);
+        self
+    }
+
+    /// Hides the menu bar of the viewer
+    pub fn with_hide_menubar(mut self, hide_menubar: bool) -> Self {
+        self.hide_menubar = Some(hide_menubar);
+        self
+    }
+
+    /// Hides the scrollbars and navigation controls
+    pub fn with_hide_window_ui(mut self, hide_window_ui: bool) -> Self {
+        self.hide_window_ui = Some(hide_window_ui);
+        self
+    }
+
+    /// Resizes the window to fit the first page
+    pub fn with_fit_window(mut self, fit_window: bool) -> Self {
+        self.fit_window = Some(fit_window);
+        self
+    }
+
+    /// Centers the window on the screen
+    pub fn with_center_window(mut self, center_window: bool) -> Self {
+        self.center_window = Some(center_window);
+        self
+    }
+
+    /// Shows the document title in the title bar
+    pub fn with_display_doc_title(mut self, display_doc_title: bool) -> Self {
+        self.display_doc_title = Some(display_doc_title);
+        self
+    }
+
+    /// Sets the page layout when the document is opened
+    pub fn with_page_layout(mut self, page_layout: PageLayout) -> Self {
+        self.page_layout = Some(page_layout);
+        self
+    }
+
+    /// Sets the panel shown when the document is opened
+    pub fn with_page_mode(mut self, page_mode: PageMode) -> Self {
+        self.page_mode = Some(page_mode);
+        self
+    }
+
+    /// Returns whether no preference is set (the dictionary is not written)
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub(crate) fn to_dict(&self) -> LoDictionary {
+        let mut dict = LoDictionary::new();
+        if let Some(duplex) = self.duplex {
+            dict.set("Duplex", Name(duplex.get_id().into()));
+        }
+        if let Some(pick_tray) = self.pick_tray_by_pdf_size {
+            dict.set("PickTrayByPDFSize", Boolean(pick_tray));
+        }
+        if !self.print_page_range.is_empty() {
+            // page numbers in the PDF are 1-based
+            let ranges = self
+                .print_page_range
+                .iter()
+                .flat_map(|&(a, b)| [Integer(a.min(b) as i64 + 1), Integer(a.max(b) as i64 + 1)])
+                .collect();
+            dict.set("PrintPageRange", Array(ranges));
+        }
+        if let Some(num_copies) = self.num_copies {
+            dict.set("NumCopies", Integer(num_copies as i64));
+        }
+        if let Some(print_scaling) = self.print_scaling {
+            dict.set("PrintScaling", Name(print_scaling.get_id().into()));
+        }
+        let flags = [
+            ("HideToolbar", self.hide_toolbar),
+            ("HideMenubar", self.hide_menubar),
+            ("HideWindowUI", self.hide_window_ui),
+            ("FitWindow", self.fit_window),
+            ("CenterWindow", self.center_window),
+            ("DisplayDocTitle", self.display_doc_title),
+        ];
+        for (key, value) in flags {
+            if let Some(value) = value {
+                dict.set(key, Boolean(value));
+            }
+        }
+        dict
+    }
+
+    /// Writes the `/ViewerPreferences` and the `/PageLayout` and `/PageMode` entries
+    pub(crate) fn write_to_catalog(&self, catalog: &mut LoDictionary) {
+        let dict = self.to_dict();
+        if !dict.is_empty() {
+            catalog.set("ViewerPreferences", Dictionary(dict));
+        }
+        if let Some(page_layout) = self.page_layout {
+            catalog.set("PageLayout", Name(page_layout.get_id().into()));
+        }
+        if let Some(page_mode) = self.page_mode {
+            catalog.set("PageMode", Name(page_mode.get_id().into()));
+        }
+    }
+}
+
+#[test]
+fn test_viewer_preferences_dict() {
+    let prefs = ViewerPreferences::default()
+        .with_duplex(Duplex::DuplexFlipLongEdge)
+        .with_pick_tray_by_pdf_size(true)
+        .with_print_page_range(0, 1)
+        .with_print_page_range(5, 4)
+        .with_num_copies(2);
+    assert!(!prefs.is_empty());
+    assert!(ViewerPreferences::default().is_empty());
+
+    let dict = prefs.to_dict();
+    assert_eq!(
+        dict.get(b"Duplex").unwrap(),
+        &Name("DuplexFlipLongEdge".into())
+    );
+    assert_eq!(dict.get(b"PickTrayByPDFSize").unwrap(), &Boolean(true));
+    assert_eq!(
+        dict.get(b"PrintPageRange").unwrap(),
+        &Array(vec![Integer(1), Integer(2), Integer(5), Integer(6)])
+    );
+    assert_eq!(dict.get(b"NumCopies").unwrap(), &Integer(2));
+}
+
+#[test]
+fn test_viewer_preferences_catalog() {
+    let prefs = ViewerPreferences::default()
+        .with_print_scaling(PrintScaling::None)
+        .with_hide_toolbar(true)
+        .with_fit_window(false)
+        .with_page_layout(PageLayout::TwoPageRight)
+        .with_page_mode(PageMode::UseAttachments);
+
+    let mut catalog = LoDictionary::new();
+    prefs.write_to_catalog(&mut catalog);
+    let dict = catalog
+        .get(b"ViewerPreferences")
+        .unwrap()
+        .as_dict()
+        .unwrap();
+    assert_eq!(dict.get(b"PrintScaling").unwrap(), &Name("None".into()));
+    assert_eq!(dict.get(b"HideToolbar").unwrap(), &Boolean(true));
+    assert_eq!(dict.get(b"FitWindow").unwrap(), &Boolean(false));
+    assert!(dict.get(b"HideMenubar").is_err());
+    assert_eq!(
+        catalog.get(b"PageLayout").unwrap(),
+        &Name("TwoPageRight".into())
+    );
+    assert_eq!(
+        catalog.get(b"PageMode").unwrap(),
+        &Name("UseAttachments".into())
+    );
+
+    // page layout only, no viewer preferences dictionary
+    let mut catalog = LoDictionary::new();
+    ViewerPreferences::default()
+        .with_page_layout(PageLayout::OneColumn)
+        .write_to_catalog(&mut catalog);
+    assert!(catalog.get(b"ViewerPreferences").is_err());
+}
@@ -0,0 +1,426 @@
+//! Non-fatal diagnostics that are collected while parsing or saving a document
+
+use std::{collections::BTreeMap, fmt};
+
+use serde_derive::{Deserialize, Serialize};
+
+/// Severity of a `PdfWarnMsg`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum PdfWarnSeverity {
+    /// Purely informational, i.e. data that was ignored
+    Info,
+    /// The output is valid PDF, but will likely be rejected by validators or render differently
+    Warning,
+    /// Content was dropped or is invalid
+    Error,
+}
+
+/// Part of the document (or subsystem) a `PdfWarnMsg` is about
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub enum PdfWarnCategory {
+    /// Feature that is not allowed by the `PdfConformance` of the document
+    Conformance,
+    /// Images and XObjects
+    Images,
+    /// Colors, color spaces and ICC profiles
+    Colors,
+    /// Annotations and links
+    Annotations,
+    /// Interactive form fields
+    Forms,
+    /// Bookmarks and named destinations
+    Navigation,
+    /// Logical structure, i.e. document parts
+    Structure,
+    /// Encryption and signatures
+    Security,
+    /// Content that could not be converted by the SVG, EPS or image export
+    Export,
+    /// Layout of HTML content
+    Html,
+    /// Page content streams of parsed documents
+    Content,
+    /// Everything else
+    #[default]
+    Other,
+}
+
+/// Stable, machine-readable code of a `PdfWarnMsg` (i.e. `W1202`), to filter or allowlist
+/// messages independent of their wording. The first two digits are the category, codes
+/// are never reused.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PdfWarnCode {
+    /// `W0000`: Message without a specific code
+    #[default]
+    Unknown,
+    /// `W1001`: The conformance level requires XMP metadata
+    MissingXmpMetadata,
+    /// `W1002`: The conformance level requires a document title
+    MissingTitle,
+    /// `W1003`: The conformance level requires the natural language of the document
+    MissingLanguage,
+    /// `W1004`: The conformance level requires an output intent
+    MissingOutputIntent,
+    /// `W1005`: A builtin font is used, but all fonts have to be embedded
+    NonEmbeddedFont,
+    /// `W1006`: Device-dependent colors are used without an output intent
+    DeviceColorWithoutOutputIntent,
+    /// `W1007`: DeviceRGB colors are used, but the output intent is CMYK
+    RgbColor,
+    /// `W1008`: An RGB image is used, but the output intent is CMYK
+    RgbImage,
+    /// `W1009`: Layers are not allowed by the conformance level
+    LayersNotAllowed,
+    /// `W1010`: Embedded files are not allowed by the conformance level
+    EmbeddedFilesNotAllowed,
+    /// `W1011`: Custom XMP metadata is ignored by the conformance level
+    IgnoredXmpMetadata,
+    /// `W1012`: Object streams are not allowed by the conformance level
+    ObjectStreamsNotAllowed,
+    /// `W1013`: LZW compression is not allowed by the conformance level
+    LzwNotAllowed,
+    /// `W1014`: Encryption is not allowed by the conformance level
+    EncryptionNotAllowed,
+    /// `W1101`: Image XObject that the parser does not support (i.e. masks, indexed color
+    /// spaces or JBIG2), it is skipped
+    UnsupportedImage,
+    /// `W1201`: Gradient without color stops
+    EmptyGradient,
+    /// `W1202`: ICC profile is missing or does not match the color
+    IccProfileMismatch,
+    /// `W1203`: Image could not be converted by the `ColorTransform`
+    ImageNotConverted,
+    /// `W1204`: XObject could not be converted by the `ColorTransform`
+    XObjectNotConverted,
+    /// `W1301`: Link annotation with a zero-area rect
+    EmptyLinkRect,
+    /// `W1302`: Annotation with a zero-area rect
+    EmptyAnnotationRect,
+    /// `W1401`: Form field with a zero-area rect
+    EmptyFormFieldRect,
+    /// `W1501`: Named destination that refers to another name
+    NamedDestinationChain,
+    /// `W1601`: Document part with an invalid page range
+    InvalidDocumentPart,
+    /// `W1701`: The document could not be encrypted
+    EncryptionFailed,
+    /// `W1801`: Feature that is not supported by the SVG, EPS or image export
+    UnsupportedExportFeature,
+    /// `W1901`: HTML element overflows the page
+    HtmlOverflow,
+    /// `W1902`: HTML element is clipped by its parent
+    HtmlClipped,
+    /// `W1903`: Image of the HTML is not loaded
+    HtmlMissingImage,
+    /// `W1904`: No font of the font-family of the HTML is loaded
+    HtmlMissingFont,
+    /// `W2001`: Page content that can't be parsed (i.e. an undecodable stream or an XObject
+    /// that was skipped), the page is parsed without its content
+    UnsupportedPageContent,
+}
+
+impl PdfWarnCode {
+    /// Returns the code as a string, i.e. `"W1202"`
+    pub fn as_str(&self) -> &'static str {
+        use PdfWarnCode::*;
+        match self {
+            Unknown => "W0000",
+            MissingXmpMetadata => "W1001",
+            MissingTitle => "W1002",
+            MissingLanguage => "W1003",
+            MissingOutputIntent => "W1004",
+            NonEmbeddedFont => "W1005",
+            DeviceColorWithoutOutputIntent => "W1006",
+            RgbColor => "W1007",
+            RgbImage => "W1008",
+            LayersNotAllowed => "W1009",
+            EmbeddedFilesNotAllowed => "W1010",
+            IgnoredXmpMetadata => "W1011",
+            ObjectStreamsNotAllowed => "W1012",
+            LzwNotAllowed => "W1013",
+            EncryptionNotAllowed => "W1014",
+            UnsupportedImage => "W1101",
+            EmptyGradient => "W1201",
+            IccProfileMismatch => "W1202",
+            ImageNotConverted => "W1203",
+            XObjectNotConverted => "W1204",
+            EmptyLinkRect => "W1301",
+            EmptyAnnotationRect => "W1302",
+            EmptyFormFieldRect => "W1401",
+            NamedDestinationChain => "W1501",
+            InvalidDocumentPart => "W1601",
+            EncryptionFailed => "W1701",
+            UnsupportedExportFeature => "W1801",
+            HtmlOverflow => "W1901",
+            HtmlClipped => "W1902",
+            HtmlMissingImage => "W1903",
+            HtmlMissingFont => "W1904",
+            UnsupportedPageContent => "W2001",
+        }
+    }
+
+    /// Returns all codes
+    pub fn all() -> &'static [PdfWarnCode] {
+        use PdfWarnCode::*;
+        &[
+            Unknown,
+            MissingXmpMetadata,
+            MissingTitle,
+            MissingLanguage,
+            MissingOutputIntent,
+            NonEmbeddedFont,
+            DeviceColorWithoutOutputIntent,
+            RgbColor,
+            RgbImage,
+            LayersNotAllowed,
+            EmbeddedFilesNotAllowed,
+            IgnoredXmpMetadata,
+            ObjectStreamsNotAllowed,
+            LzwNotAllowed,
+            EncryptionNotAllowed,
+            UnsupportedImage,
+            EmptyGradient,
+            IccProfileMismatch,
+            ImageNotConverted,
+            XObjectNotConverted,
+            EmptyLinkRect,
+            EmptyAnnotationRect,
+            EmptyFormFieldRect,
+            NamedDestinationChain,
+            InvalidDocumentPart,
+            EncryptionFailed,
+            UnsupportedExportFeature,
+            HtmlOverflow,
+            HtmlClipped,
+            HtmlMissingImage,
+            HtmlMissingFont,
+            UnsupportedPageContent,
+        ]
+    }
+
+    /// Category of the code
+    pub fn get_category(&self) -> PdfWarnCategory {
+        match self.as_str().get(1..3) {
+            Some("10") => PdfWarnCategory::Conformance,
+            Some("11") => PdfWarnCategory::Images,
+            Some("12") => PdfWarnCategory::Colors,
+            Some("13") => PdfWarnCategory::Annotations,
+            Some("14") => PdfWarnCategory::Forms,
+            Some("15") => PdfWarnCategory::Navigation,
+            Some("16") => PdfWarnCategory::Structure,
+            Some("17") => PdfWarnCategory::Security,
+            Some("18") => PdfWarnCategory::Export,
+            Some("19") => PdfWarnCategory::Html,
+            Some("20") => PdfWarnCategory::Content,
+            _ => PdfWarnCategory::Other,
+        }
+    }
+}
+
+impl std::str::FromStr for PdfWarnCode {
+    type Err = String;
+
+    /// Parses a code string (i.e. `"W1202"`)
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        Self::all()
+            .iter()
+            .copied()
+            .find(|c| c.as_str() == code)
+            .ok_or_else(|| format!("unknown warning code {code:?}"))
+    }
+}
+
+impl fmt::Display for PdfWarnCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Warning message, i.e. a feature that violates the selected `PdfConformance`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdfWarnMsg {
+    /// Page the message refers to (0-based), `None` for document-level messages
+    pub page: Option<usize>,
+    /// Severity of the message
+    pub severity: PdfWarnSeverity,
+    /// Stable code of the message
+    pub code: PdfWarnCode,
+    /// Part of the document the message is about
+    pub category: PdfWarnCategory,
+    /// ID of the resource the message refers to (i.e. an image, ICC profile or form field)
+    pub resource: Option<String>,
+    /// Human-readable description of the problem
+    pub msg: String,
+}
+
+impl PdfWarnMsg {
+    /// Creates a new informational message
+    pub fn info(page: Option<usize>, msg: String) -> Self {
+        Self {
+            page,
+            severity: PdfWarnSeverity::Info,
+            code: PdfWarnCode::Unknown,
+            category: PdfWarnCategory::Other,
+            resource: None,
+            msg,
+        }
+    }
+
+    /// Creates a new warning
+    pub fn warning(page: Option<usize>, msg: String) -> Self {
+        Self {
+            page,
+            severity: PdfWarnSeverity::Warning,
+            code: PdfWarnCode::Unknown,
+            category: PdfWarnCategory::Other,
+            resource: None,
+            msg,
+        }
+    }
+
+    /// Creates a new error message
+    pub fn error(page: Option<usize>, msg: String) -> Self {
+        Self {
+            page,
+            severity: PdfWarnSeverity::Error,
+            code: PdfWarnCode::Unknown,
+            category: PdfWarnCategory::Other,
+            resource: None,
+            msg,
+        }
+    }
+
+    /// Sets the code and the category of the code
+    pub fn with_code(mut self, code: PdfWarnCode) -> Self {
+        self.code = code;
+        self.category = code.get_category();
+        self
+    }
+
+    /// Sets the category of the message
+    pub fn with_category(mut self, category: PdfWarnCategory) -> Self {
+        self.category = category;
+        self
+    }
+
+    /// Sets the ID of the resource the message refers to
+    pub fn with_resource(mut self, resource: &str) -> Self {
+        self.resource = Some(resource.to_string());
+        self
+    }
+}
+
+impl fmt::Display for PdfWarnMsg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            PdfWarnSeverity::Info => "info",
+            PdfWarnSeverity::Warning => "warning",
+            PdfWarnSeverity::Error => "error",
+        };
+        write!(f, "{severity}")?;
+        if self.code != PdfWarnCode::Unknown {
+            write!(f, "[{}]", self.code)?;
+        }
+        match self.page {
+            Some(page) => write!(f, ": page {}: {}", page + 1, self.msg),
+            None => write!(f, ": {}", self.msg),
+        }
+    }
+}
+
+/// Warnings of a save grouped by page, resource and category, i.e. to only fail a build
+/// on a specific class of problems. Every message is contained in each of the groups
+/// that apply to it.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PdfWarnGroups {
+    /// Messages without a page
+    pub document: Vec<PdfWarnMsg>,
+    /// Messages by page (0-based)
+    pub pages: BTreeMap<usize, Vec<PdfWarnMsg>>,
+    /// Messages by resource ID
+    pub resources: BTreeMap<String, Vec<PdfWarnMsg>>,
+    /// Messages by category
+    pub categories: BTreeMap<PdfWarnCategory, Vec<PdfWarnMsg>>,
+}
+
+impl PdfWarnGroups {
+    /// Groups the `warnings` (in the order in which they were emitted)
+    pub fn new(warnings: &[PdfWarnMsg]) -> Self {
+        let mut groups = Self::default();
+        for w in warnings {
+            match w.page {
+                Some(page) => groups.pages.entry(page).or_default().push(w.clone()),
+                None => groups.document.push(w.clone()),
+            }
+            if let Some(resource) = w.resource.as_ref() {
+                groups
+                    .resources
+                    .entry(resource.clone())
+                    .or_default()
+                    .push(w.clone());
+            }
+            groups
+                .categories
+                .entry(w.category)
+                .or_default()
+                .push(w.clone());
+        }
+        groups
+    }
+
+    /// Highest severity of the messages in the `category`, `None` if there are none
+    pub fn get_max_severity(&self, category: PdfWarnCategory) -> Option<PdfWarnSeverity> {
+        self.categories
+            .get(&category)
+            .and_then(|w| w.iter().map(|w| w.severity).max())
+    }
+}
+
+#[test]
+fn test_warn_groups() {
+    let warnings = vec![
+        PdfWarnMsg::warning(Some(1), "clipped".to_string()).with_category(PdfWarnCategory::Html),
+        PdfWarnMsg::error(None, "no profile".to_string())
+            .with_category(PdfWarnCategory::Colors)
+            .with_resource("icc"),
+        PdfWarnMsg::info(Some(1), "ignored".to_string()),
+    ];
+    let groups = PdfWarnGroups::new(&warnings);
+    assert_eq!(groups.document, vec![warnings[1].clone()]);
+    assert_eq!(
+        groups.pages[&1],
+        vec![warnings[0].clone(), warnings[2].clone()]
+    );
+    assert_eq!(groups.resources["icc"], vec![warnings[1].clone()]);
+    assert_eq!(groups.categories.len(), 3);
+    assert_eq!(
+        groups.get_max_severity(PdfWarnCategory::Colors),
+        Some(PdfWarnSeverity::Error)
+    );
+    assert_eq!(groups.get_max_severity(PdfWarnCategory::Forms), None);
+}
+
+#[test]
+fn test_warn_codes() {
+    use std::collections::BTreeSet;
+
+    // codes are unique and parse back
+    let codes = PdfWarnCode::all();
+    let strings = codes.iter().map(|c| c.as_str()).collect::<BTreeSet<_>>();
+    assert_eq!(strings.len(), codes.len());
+    for code in codes {
+        assert_eq!(code.as_str().parse::<PdfWarnCode>(), Ok(*code));
+    }
+    assert!("W9999".parse::<PdfWarnCode>().is_err());
+
+    let msg =
+        PdfWarnMsg::warning(Some(0), "no stops".to_string()).with_code(PdfWarnCode::EmptyGradient);
+    assert_eq!(msg.category, PdfWarnCategory::Colors);
+    assert_eq!(msg.to_string(), "warning[W1201]: page 1: no stops");
+    assert_eq!(
+        PdfWarnCode::HtmlClipped.get_category(),
+        PdfWarnCategory::Html
+    );
+}
@@ -65,14 +65,23 @@ pub fn PrintPdfFromXml(input: String) -> String {
     serde_json::to_string(&init).unwrap_or_default()
 }
 
-fn printpdf_from_xml_internal(
-    input: PrintPdfApiInput,
-) -> Result<PrintPdfApiReturn, PrintPdfApiReturn> {
+/// Returns the images and fonts that the HTML of the input references, but that are not
+/// in the input (JSON array of `HtmlAsset`), so that they can be fetched before calling
+/// `PrintPdfFromXml`
+#[allow(non_snake_case)]
+#[wasm_bindgen::prelude::wasm_bindgen]
+pub fn PrintPdfGetMissingAssets(input: String) -> String {
+    let assets = match serde_json::from_str::<PrintPdfApiInput>(&input) {
+        Ok(o) => get_render_options(&o).get_missing_assets(&o.html),
+        Err(_) => Vec::new(),
+    };
+    serde_json::to_string(&assets).unwrap_or_default()
+}
+
+fn get_render_options(input: &PrintPdfApiInput) -> XmlRenderOptions {
     use crate::units::Mm;
-    use base64::prelude::*;
 
-    // TODO: extract document title from XML!
-    let opts = XmlRenderOptions {
+    XmlRenderOptions {
         page_width: Mm(input.options.page_width_mm.unwrap_or(210.0)),
         page_height: Mm(input.options.page_height_mm.unwrap_or(297.0)),
         images: input
@@ -90,7 +99,19 @@ fn printpdf_from_xml_internal(
             })
             .collect(),
         components: Vec::new(),
-    };
+        html_components: Default::default(),
+        background: None,
+        image_dpi: 300.0,
+    }
+}
+
+fn printpdf_from_xml_internal(
+    input: PrintPdfApiInput,
+) -> Result<PrintPdfApiReturn, PrintPdfApiReturn> {
+    use base64::prelude::*;
+
+    // TODO: extract document title from XML!
+    let opts = get_render_options(&input);
 
     let mut pdf = crate::PdfDocument::new("HTML rendering demo");
 
@@ -0,0 +1,552 @@
+//! Structured XMP metadata (Dublin Core, XMP basic, PDF and media management schemas,
+//! plus custom namespaces), serialized to and parsed from RDF/XML packets
+
+use std::collections::BTreeMap;
+
+use crate::components::{escape_xml, unescape_xml};
+
+const NS_RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const NS_DC: &str = "http://purl.org/dc/elements/1.1/";
+const NS_XMP: &str = "http://ns.adobe.com/xap/1.0/";
+const NS_PDF: &str = "http://ns.adobe.com/pdf/1.3/";
+const NS_XMP_MM: &str = "http://ns.adobe.com/xap/1.0/mm/";
+
+/// XMP metadata packet
+///
+/// Fields that are shared with the `PdfDocumentInfo` (title, author, dates, producer, ...)
+/// are overwritten with the values from the document info on save, so that both stay in sync.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct XmpMetadata {
+    /// Dublin Core schema (`dc:`)
+    pub dublin_core: XmpDublinCore,
+    /// XMP basic schema (`xmp:`)
+    pub basic: XmpBasic,
+    /// Adobe PDF schema (`pdf:`)
+    pub pdf: XmpPdf,
+    /// XMP media management schema (`xmpMM:`)
+    pub media_management: XmpMediaManagement,
+    /// Properties in other namespaces
+    pub custom: Vec<XmpNamespace>,
+}
+
+/// Dublin Core properties (`http://purl.org/dc/elements/1.1/`)
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct XmpDublinCore {
+    /// `dc:title`, document title
+    pub title: Option<String>,
+    /// `dc:creator`, authors of the document
+    pub creator: Vec<String>,
+    /// `dc:description`, same as the "Subject" in the document info
+    pub description: Option<String>,
+    /// `dc:subject`, keywords of the document
+    pub subject: Vec<String>,
+    /// `dc:publisher`
+    pub publisher: Vec<String>,
+    /// `dc:rights`, copyright / license statement
+    pub rights: Option<String>,
+    /// `dc:language`, languages used in the document (i.e. "en-US")
+    pub language: Vec<String>,
+    /// `dc:identifier`
+    pub identifier: Option<String>,
+}
+
+/// XMP basic properties (`http://ns.adobe.com/xap/1.0/`)
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct XmpBasic {
+    /// `xmp:CreateDate`
+    pub create_date: Option<String>,
+    /// `xmp:ModifyDate`
+    pub modify_date: Option<String>,
+    /// `xmp:MetadataDate`
+    pub metadata_date: Option<String>,
+    /// `xmp:CreatorTool`, application that created the document
+    pub creator_tool: Option<String>,
+}
+
+/// Adobe PDF properties (`http://ns.adobe.com/pdf/1.3/`)
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct XmpPdf {
+    /// `pdf:Producer`
+    pub producer: Option<String>,
+    /// `pdf:Keywords`
+    pub keywords: Option<String>,
+    /// `pdf:Trapped`
+    pub trapped: Option<bool>,
+    /// `pdf:PDFVersion`
+    pub pdf_version: Option<String>,
+}
+
+/// XMP media management properties (`http://ns.adobe.com/xap/1.0/mm/`)
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct XmpMediaManagement {
+    /// `xmpMM:DocumentID`
+    pub document_id: Option<String>,
+    /// `xmpMM:InstanceID`
+    pub instance_id: Option<String>,
+    /// `xmpMM:RenditionClass`: Web-viewable or "default" or to be left empty. Usually "default".
+    pub rendition_class: Option<String>,
+    /// `xmpMM:VersionID`
+    pub version_id: Option<String>,
+}
+
+/// Properties of a custom XMP namespace
+#[derive(Debug, PartialEq, Clone)]
+pub struct XmpNamespace {
+    /// Prefix of the namespace, i.e. `pdfaid`
+    pub prefix: String,
+    /// URI of the namespace, i.e. `http://www.aiim.org/pdfa/ns/id/`
+    pub uri: String,
+    /// Properties (name without prefix, value)
+    pub properties: Vec<(String, XmpValue)>,
+}
+
+/// Typed value of an XMP property
+#[derive(Debug, PartialEq, Clone)]
+pub enum XmpValue {
+    /// Simple text value
+    Text(String),
+    /// Integer value
+    Integer(i64),
+    /// Real number
+    Real(f32),
+    /// Boolean, serialized as `True` / `False`
+    Boolean(bool),
+    /// Unordered array (`rdf:Bag`)
+    Bag(Vec<String>),
+    /// Ordered array (`rdf:Seq`)
+    Seq(Vec<String>),
+    /// Language alternatives (`rdf:Alt`), as (language, text) pairs
+    Alt(Vec<(String, String)>),
+}
+
+impl XmpValue {
+    /// Returns the value as a single string (the "x-default" or first alternative for `Alt`,
+    /// the first item for `Bag` and `Seq`)
+    pub fn as_text(&self) -> Option<String> {
+        match self {
+            XmpValue::Text(s) => Some(s.clone()),
+            XmpValue::Integer(i) => Some(i.to_string()),
+            XmpValue::Real(r) => Some(r.to_string()),
+            XmpValue::Boolean(b) => Some(if *b { "True" } else { "False" }.to_string()),
+            XmpValue::Bag(v) | XmpValue::Seq(v) => v.first().cloned(),
+            XmpValue::Alt(v) => v
+                .iter()
+                .find(|(lang, _)| lang == "x-default")
+                .or_else(|| v.first())
+                .map(|(_, s)| s.clone()),
+        }
+    }
+
+    /// Returns the value as a list of strings
+    pub fn as_list(&self) -> Vec<String> {
+        match self {
+            XmpValue::Bag(v) | XmpValue::Seq(v) => v.clone(),
+            XmpValue::Alt(v) => v.iter().map(|(_, s)| s.clone()).collect(),
+            other => other.as_text().into_iter().collect(),
+        }
+    }
+}
+
+impl XmpMetadata {
+    /// Adds a property in a custom namespace (the namespace is created if necessary)
+    pub fn add_custom_property(&mut self, prefix: &str, uri: &str, name: &str, value: XmpValue) {
+        match self.custom.iter_mut().find(|ns| ns.uri == uri) {
+            Some(ns) => ns.properties.push((name.to_string(), value)),
+            None => self.custom.push(XmpNamespace {
+                prefix: prefix.to_string(),
+                uri: uri.to_string(),
+                properties: vec![(name.to_string(), value)],
+            }),
+        }
+    }
+
+    /// Sets a property in a custom namespace, replacing all values of the property
+    pub fn set_custom_property(&mut self, prefix: &str, uri: &str, name: &str, value: XmpValue) {
+        for ns in self.custom.iter_mut().filter(|ns| ns.uri == uri) {
+            ns.properties.retain(|(n, _)| n != name);
+        }
+        self.add_custom_property(prefix, uri, name, value);
+    }
+
+    /// Returns the value of a property in a custom namespace
+    pub fn get_custom_property(&self, uri: &str, name: &str) -> Option<&XmpValue> {
+        self.custom
+            .iter()
+            .filter(|ns| ns.uri == uri)
+            .flat_map(|ns| ns.properties.iter())
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v)
+    }
+
+    /// Serializes the metadata into a complete XMP packet (including the `<?xpacket?>` header)
+    pub fn to_xml(&self) -> String {
+        let mut namespaces = vec![
+            ("xmp".to_string(), NS_XMP.to_string()),
+            ("dc".to_string(), NS_DC.to_string()),
+            ("pdf".to_string(), NS_PDF.to_string()),
+            ("xmpMM".to_string(), NS_XMP_MM.to_string()),
+        ];
+        for ns in self.custom.iter() {
+            if !namespaces.iter().any(|(prefix, _)| *prefix == ns.prefix) {
+                namespaces.push((ns.prefix.clone(), ns.uri.clone()));
+            }
+        }
+
+        let mut properties = Vec::new();
+        let mut push_text = |name: &str, value: &Option<String>| {
+            if let Some(s) = value {
+                properties.push((name.to_string(), XmpValue::Text(s.clone())));
+            }
+        };
+
+        let basic = &self.basic;
+        push_text("xmp:CreateDate", &basic.create_date);
+        push_text("xmp:ModifyDate", &basic.modify_date);
+        push_text("xmp:MetadataDate", &basic.metadata_date);
+        push_text("xmp:CreatorTool", &basic.creator_tool);
+
+        let dc = &self.dublin_core;
+        push_text("dc:format", &Some("application/pdf".to_string()));
+        push_text("dc:identifier", &dc.identifier);
+        let pdf = &self.pdf;
+        push_text("pdf:Producer", &pdf.producer);
+        push_text("pdf:Keywords", &pdf.keywords);
+        push_text("pdf:PDFVersion", &pdf.pdf_version);
+        let mm = &self.media_management;
+        push_text("xmpMM:DocumentID", &mm.document_id);
+        push_text("xmpMM:InstanceID", &mm.instance_id);
+        push_text("xmpMM:RenditionClass", &mm.rendition_class);
+        push_text("xmpMM:VersionID", &mm.version_id);
+
+        if let Some(trapped) = pdf.trapped {
+            properties.push(("pdf:Trapped".to_string(), XmpValue::Boolean(trapped)));
+        }
+
+        let default_alt = |s: &String| XmpValue::Alt(vec![("x-default".to_string(), s.clone())]);
+        let lists = [
+            ("dc:title", dc.title.as_ref().map(default_alt)),
+            ("dc:creator", Some(XmpValue::Seq(dc.creator.clone()))),
+            ("dc:description", dc.description.as_ref().map(default_alt)),
+            ("dc:subject", Some(XmpValue::Bag(dc.subject.clone()))),
+            ("dc:publisher", Some(XmpValue::Bag(dc.publisher.clone()))),
+            ("dc:rights", dc.rights.as_ref().map(default_alt)),
+            ("dc:language", Some(XmpValue::Bag(dc.language.clone()))),
+        ];
+        for (name, value) in lists {
+            match value {
+                Some(v) if !v.as_list().is_empty() => properties.push((name.to_string(), v)),
+                _ => {}
+            }
+        }
+
+        for ns in self.custom.iter() {
+            // reuse the prefix of an already declared namespace with the same URI
+            let prefix = namespaces
+                .iter()
+                .find(|(_, uri)| *uri == ns.uri)
+                .map(|(prefix, _)| prefix.clone())
+                .unwrap_or_else(|| ns.prefix.clone());
+            for (name, value) in ns.properties.iter() {
+                properties.push((format!("{prefix}:{name}"), value.clone()));
+            }
+        }
+
+        let mut xml = String::new();
+        xml.push_str("<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n");
+        xml.push_str("<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n");
+        xml.push_str(&format!("   <rdf:RDF xmlns:rdf=\"{NS_RDF}\">\n"));
+        xml.push_str("      <rdf:Description rdf:about=\"\"");
+        for (prefix, uri) in namespaces.iter() {
+            xml.push_str(&format!(
+                "\n            xmlns:{prefix}=\"{}\"",
+                escape_xml(uri)
+            ));
+        }
+        xml.push_str(">\n");
+        for (name, value) in properties.iter() {
+            write_property(&mut xml, name, value);
+        }
+        xml.push_str("      </rdf:Description>\n");
+        xml.push_str("   </rdf:RDF>\n");
+        xml.push_str("</x:xmpmeta>\n");
+        // padding, so that the packet can be edited in-place
+        for _ in 0..20 {
+            xml.push_str(&" ".repeat(100));
+            xml.push('\n');
+        }
+        xml.push_str("<?xpacket end=\"w\"?>");
+        xml
+    }
+
+    /// Parses an XMP packet (the contents of the `/Metadata` stream)
+    ///
+    /// Properties in custom namespaces are parsed as `Text`, `Bag`, `Seq` or `Alt`,
+    /// since the type of simple values can't be recovered from the RDF.
+    pub fn parse(xml: &str) -> Result<Self, String> {
+        let mut parser = XmpParser::default();
+        parser.parse(xml)?;
+
+        let mut xmp = XmpMetadata::default();
+        for (prefix, name, value) in parser.properties {
+            let uri = parser.namespaces.get(&prefix).cloned().unwrap_or_default();
+            let text = value.as_text();
+            match (uri.as_str(), name.as_str()) {
+                (NS_DC, "title") => xmp.dublin_core.title = text,
+                (NS_DC, "creator") => xmp.dublin_core.creator = value.as_list(),
+                (NS_DC, "description") => xmp.dublin_core.description = text,
+                (NS_DC, "subject") => xmp.dublin_core.subject = value.as_list(),
+                (NS_DC, "publisher") => xmp.dublin_core.publisher = value.as_list(),
+                (NS_DC, "rights") => xmp.dublin_core.rights = text,
+                (NS_DC, "language") => xmp.dublin_core.language = value.as_list(),
+                (NS_DC, "identifier") => xmp.dublin_core.identifier = text,
+                (NS_DC, "format") => {}
+                (NS_XMP, "CreateDate") => xmp.basic.create_date = text,
+                (NS_XMP, "ModifyDate") => xmp.basic.modify_date = text,
+                (NS_XMP, "MetadataDate") => xmp.basic.metadata_date = text,
+                (NS_XMP, "CreatorTool") => xmp.basic.creator_tool = text,
+                (NS_PDF, "Producer") => xmp.pdf.producer = text,
+                (NS_PDF, "Keywords") => xmp.pdf.keywords = text,
+                (NS_PDF, "Trapped") => {
+                    xmp.pdf.trapped = text.map(|s| s.eq_ignore_ascii_case("true"))
+                }
+                (NS_PDF, "PDFVersion") => xmp.pdf.pdf_version = text,
+                (NS_XMP_MM, "DocumentID") => xmp.media_management.document_id = text,
+                (NS_XMP_MM, "InstanceID") => xmp.media_management.instance_id = text,
+                (NS_XMP_MM, "RenditionClass") => xmp.media_management.rendition_class = text,
+                (NS_XMP_MM, "VersionID") => xmp.media_management.version_id = text,
+                _ => xmp.add_custom_property(&prefix, &uri, &name, value),
+            }
+        }
+
+        Ok(xmp)
+    }
+}
+
+fn write_property(xml: &mut String, name: &str, value: &XmpValue) {
+    let indent = "         ";
+    let (array_type, items) = match value {
+        XmpValue::Bag(v) => ("rdf:Bag", v.iter().map(|s| (None, s)).collect::<Vec<_>>()),
+        XmpValue::Seq(v) => ("rdf:Seq", v.iter().map(|s| (None, s)).collect()),
+        XmpValue::Alt(v) => ("rdf:Alt", v.iter().map(|(l, s)| (Some(l), s)).collect()),
+        other => {
+            let text = other.as_text().unwrap_or_default();
+            xml.push_str(&format!("{indent}<{name}>{}</{name}>\n", escape_xml(&text)));
+            return;
+        }
+    };
+
+    xml.push_str(&format!("{indent}<{name}>\n"));
+    xml.push_str(&format!("{indent}   <{array_type}>\n"));
+    for (lang, item) in items {
+        let lang = lang
+            .map(|l| format!(" xml:lang=\"{}\"", escape_xml(l)))
+            .unwrap_or_default();
+        xml.push_str(&format!(
+            "{indent}      <rdf:li{lang}>{}</rdf:li>\n",
+            escape_xml(item)
+        ));
+    }
+    xml.push_str(&format!("{indent}   </{array_type}>\n"));
+    xml.push_str(&format!("{indent}</{name}>\n"));
+}
+
+/// Property that is currently being parsed
+#[derive(Debug)]
+struct PendingProperty {
+    prefix: String,
+    name: String,
+    text: String,
+    array_type: Option<String>,
+    items: Vec<(String, String)>,
+    item: Option<(String, String)>,
+}
+
+#[derive(Debug, Default)]
+struct XmpParser {
+    /// Namespace prefix => URI
+    namespaces: BTreeMap<String, String>,
+    /// Parsed (prefix, name, value)
+    properties: Vec<(String, String, XmpValue)>,
+    /// Open elements (prefix, name)
+    stack: Vec<(String, String)>,
+    description_depth: Option<usize>,
+    property: Option<PendingProperty>,
+}
+
+impl XmpParser {
+    fn parse(&mut self, xml: &str) -> Result<(), String> {
+        use xmlparser::{ElementEnd, Token, Tokenizer};
+
+        // (prefix, name, attributes (prefix, name, value))
+        type Element = (String, String, Vec<(String, String, String)>);
+        let mut element: Option<Element> = None;
+
+        for token in Tokenizer::from(xml) {
+            let token = token.map_err(|e| format!("invalid XMP packet: {e}"))?;
+            match token {
+                Token::ElementStart { prefix, local, .. } => {
+                    element = Some((prefix.to_string(), local.to_string(), Vec::new()));
+                }
+                Token::Attribute {
+                    prefix,
+                    local,
+                    value,
+                    ..
+                } => {
+                    let value = unescape_xml(value.as_str());
+                    if prefix.as_str() == "xmlns" {
+                        self.namespaces.insert(local.to_string(), value.clone());
+                    }
+                    if let Some((_, _, attributes)) = element.as_mut() {
+                        attributes.push((prefix.to_string(), local.to_string(), value));
+                    }
+                }
+                Token::ElementEnd { end, .. } => match end {
+                    ElementEnd::Open | ElementEnd::Empty => {
+                        let (prefix, name, attributes) = match element.take() {
+                            Some(s) => s,
+                            None => continue,
+                        };
+                        self.open_element(&prefix, &name, &attributes);
+                        self.stack.push((prefix, name));
+                        if matches!(end, ElementEnd::Empty) {
+                            self.close_element();
+                        }
+                    }
+                    ElementEnd::Close(_, _) => self.close_element(),
+                },
+                Token::Text { text } => self.push_text(&unescape_xml(text.as_str())),
+                Token::Cdata { text, .. } => self.push_text(text.as_str()),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn open_element(&mut self, prefix: &str, name: &str, attributes: &[(String, String, String)]) {
+        let depth = self.stack.len();
+
+        if prefix == "rdf" && name == "Description" && self.property.is_none() {
+            self.description_depth = Some(depth);
+            // simple properties can be written as attributes of the rdf:Description
+            for (attr_prefix, attr_name, value) in attributes {
+                if ["", "rdf", "xml", "xmlns"].contains(&attr_prefix.as_str()) {
+                    continue;
+                }
+                self.properties.push((
+                    attr_prefix.clone(),
+                    attr_name.clone(),
+                    XmpValue::Text(value.clone()),
+                ));
+            }
+            return;
+        }
+
+        if self.description_depth.map(|d| d + 1) == Some(depth) {
+            self.property = Some(PendingProperty {
+                prefix: prefix.to_string(),
+                name: name.to_string(),
+                text: String::new(),
+                array_type: None,
+                items: Vec::new(),
+                item: None,
+            });
+            return;
+        }
+
+        if let Some(property) = self.property.as_mut() {
+            match (prefix, name) {
+                ("rdf", "Bag") | ("rdf", "Seq") | ("rdf", "Alt") => {
+                    property.array_type = Some(name.to_string());
+                }
+                ("rdf", "li") => {
+                    let lang = attributes
+                        .iter()
+                        .find(|(p, n, _)| p == "xml" && n == "lang")
+                        .map(|(_, _, v)| v.clone())
+                        .unwrap_or_else(|| "x-default".to_string());
+                    property.item = Some((lang, String::new()));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn close_element(&mut self) {
+        let (prefix, name) = match self.stack.pop() {
+            Some(s) => s,
+            None => return,
+        };
+        let depth = self.stack.len();
+
+        if prefix == "rdf" && name == "li" {
+            if let Some(property) = self.property.as_mut() {
+                if let Some((lang, text)) = property.item.take() {
+                    property.items.push((lang, text.trim().to_string()));
+                }
+            }
+        } else if self.description_depth.map(|d| d + 1) == Some(depth) {
+            if let Some(p) = self.property.take() {
+                let value = match p.array_type.as_deref() {
+                    Some("Bag") => XmpValue::Bag(p.items.into_iter().map(|(_, s)| s).collect()),
+                    Some("Seq") => XmpValue::Seq(p.items.into_iter().map(|(_, s)| s).collect()),
+                    Some(_) => XmpValue::Alt(p.items),
+                    None => XmpValue::Text(p.text.trim().to_string()),
+                };
+                self.properties.push((p.prefix, p.name, value));
+            }
+        } else if self.description_depth == Some(depth) {
+            self.description_depth = None;
+        }
+    }
+
+    fn push_text(&mut self, text: &str) {
+        if let Some(property) = self.property.as_mut() {
+            match property.item.as_mut() {
+                Some((_, item)) => item.push_str(text),
+                None => property.text.push_str(text),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_xmp_roundtrip() {
+    let mut xmp = XmpMetadata::default();
+    xmp.dublin_core.title = Some("Invoice <2024>".to_string());
+    xmp.dublin_core.creator = vec!["Jane Doe".to_string(), "John Doe".to_string()];
+    xmp.dublin_core.subject = vec!["invoice".to_string(), "billing".to_string()];
+    xmp.basic.create_date = Some("2024-05-01T10:00:00+00:00".to_string());
+    xmp.pdf.producer = Some("printpdf".to_string());
+    xmp.pdf.trapped = Some(false);
+    xmp.media_management.rendition_class = Some("default".to_string());
+    xmp.add_custom_property(
+        "pdfaid",
+        "http://www.aiim.org/pdfa/ns/id/",
+        "part",
+        XmpValue::Text("2".to_string()),
+    );
+
+    let parsed = XmpMetadata::parse(&xmp.to_xml()).unwrap();
+    assert_eq!(parsed, xmp);
+
+    let mut replaced = parsed.clone();
+    let pdfaid = "http://www.aiim.org/pdfa/ns/id/";
+    replaced.set_custom_property("pdfaid", pdfaid, "part", XmpValue::Text("3".to_string()));
+    assert_eq!(replaced.custom[0].properties.len(), 1);
+    assert_eq!(
+        replaced.get_custom_property(pdfaid, "part"),
+        Some(&XmpValue::Text("3".to_string()))
+    );
+
+    // properties as attributes of the rdf:Description
+    let packet = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+        <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+            <rdf:Description rdf:about="" xmlns:pdf="http://ns.adobe.com/pdf/1.3/"
+                pdf:Producer="Other &amp; Co" />
+        </rdf:RDF>
+    </x:xmpmeta>"#;
+    let parsed = XmpMetadata::parse(packet).unwrap();
+    assert_eq!(parsed.pdf.producer.as_deref(), Some("Other & Co"));
+}
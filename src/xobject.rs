@@ -1,8 +1,14 @@
+use std::collections::BTreeMap;
+
 use crate::{
+    color::{Cmyk, Color, DeviceColorSpace, Greyscale, Rgb},
+    graphics::{Rect, RenderingIntent},
     image::RawImage,
     matrix::CurTransMat,
+    ops::Op,
     units::{Pt, Px},
-    OffsetDateTime,
+    ExtendedGraphicsStateId, FontId, IccProfileId, LayerInternalId, OffsetDateTime, PdfDocument,
+    PdfResources, RawResourceMap, XObjectId,
 };
 
 /* Parent: Resources dictionary of the page */
@@ -27,6 +33,11 @@ pub enum XObject {
     /// by `add_xobject()` is the unique name that can be used to invoke
     /// the `/Do` operator (by the `use_xobject`)
     External(ExternalXObject),
+    /// Transparency group, drawn from `Op`s (which are translated when saving)
+    Group(Box<TransparencyGroup>),
+    /// Form XObject drawn from `Op`s (which are translated when saving), for content
+    /// that is repeated on many pages
+    Ops(Box<OpsXObject>),
 }
 
 impl XObject {
@@ -37,14 +48,35 @@ impl XObject {
             XObject::External(external_xobject) => {
                 Some((external_xobject.width?, external_xobject.height?))
             }
+            // drawn in their own coordinate space (in points)
+            XObject::Group(_) | XObject::Ops(_) => None,
+        }
+    }
+
+    /// Returns the operations of XObjects that are drawn from `Op`s
+    pub(crate) fn get_ops(&self) -> Option<&[Op]> {
+        match self {
+            XObject::Group(g) => Some(&g.ops),
+            XObject::Ops(o) => Some(&o.ops),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn get_ops_mut(&mut self) -> Option<&mut Vec<Op>> {
+        match self {
+            XObject::Group(g) => Some(&mut g.ops),
+            XObject::Ops(o) => Some(&mut o.ops),
+            _ => None,
         }
     }
 }
 
-// translates the xobject to a document object ID
+// translates the xobject to a document object ID, `content` translates the ops of
+// group and ops XObjects into a content stream and its resources dictionary
 pub(crate) fn add_xobject_to_document(
     xobj: &XObject,
     doc: &mut lopdf::Document,
+    content: &mut dyn FnMut(&[Op], &mut lopdf::Document) -> (Vec<u8>, lopdf::Dictionary),
 ) -> lopdf::ObjectId {
     // in the PDF content stream, reference an XObject like this
     match xobj {
@@ -71,9 +103,361 @@ pub(crate) fn add_xobject_to_document(
             }
             doc.add_object(stream)
         }
+        XObject::Group(g) => {
+            let (bytes, resources) = content(&g.ops, doc);
+            let stream = ops_xobject_to_stream(&g.bbox, g.matrix, Some(&g.group), bytes, resources);
+            doc.add_object(stream)
+        }
+        XObject::Ops(o) => {
+            let (bytes, resources) = content(&o.ops, doc);
+            let stream = ops_xobject_to_stream(&o.bbox, o.matrix, None, bytes, resources);
+            doc.add_object(stream)
+        }
+    }
+}
+
+fn ops_xobject_to_stream(
+    bbox: &Rect,
+    matrix: Option<CurTransMat>,
+    group: Option<&GroupXObject>,
+    bytes: Vec<u8>,
+    resources: lopdf::Dictionary,
+) -> lopdf::Stream {
+    use lopdf::Object::*;
+
+    let r = bbox.normalize();
+    let (ll, ur) = (r.lower_left(), r.upper_right());
+    let mut dict = lopdf::Dictionary::from_iter(vec![
+        ("Type", Name("XObject".into())),
+        ("Subtype", Name("Form".into())),
+        (
+            "BBox",
+            Array(vec![Real(ll.x.0), Real(ll.y.0), Real(ur.x.0), Real(ur.y.0)]),
+        ),
+        ("Resources", Dictionary(resources)),
+    ]);
+    if let Some(group) = group {
+        dict.set("Group", Dictionary(group.to_dict()));
+    }
+    if let Some(matrix) = matrix.as_ref() {
+        dict.set(
+            "Matrix",
+            Array(matrix.as_array().into_iter().map(Real).collect()),
+        );
+    }
+    lopdf::Stream::new(dict, bytes).with_compression(true)
+}
+
+/// Operations that are composited as one object (PDF reference section 7.3): blend modes
+/// inside the group blend against the group instead of the page, the group itself
+/// is painted with the blend mode, alpha and soft mask of the `Op::UseXObject`
+#[derive(Debug, PartialEq, Clone)]
+pub struct TransparencyGroup {
+    pub ops: Vec<Op>,
+    /// Bounding box of the ops in group space, the ops are clipped to it
+    pub bbox: Rect,
+    /// Optional matrix, maps the group into user space
+    pub matrix: Option<CurTransMat>,
+    /// Isolation, knockout and color space of the group
+    pub group: GroupXObject,
+}
+
+impl TransparencyGroup {
+    pub fn new(ops: Vec<Op>, bbox: Rect) -> Self {
+        Self {
+            ops,
+            bbox,
+            matrix: None,
+            group: GroupXObject::transparency(),
+        }
+    }
+
+    /// Composites the group against a transparent backdrop instead of the page
+    pub fn with_isolated(mut self, isolated: bool) -> Self {
+        self.group.isolated = isolated;
+        self
+    }
+
+    /// Objects of the group replace each other instead of blending with each other
+    pub fn with_knockout(mut self, knockout: bool) -> Self {
+        self.group.knockout = knockout;
+        self
+    }
+
+    /// Color space in which the group is composited
+    pub fn with_color_space(mut self, color_space: DeviceColorSpace) -> Self {
+        self.group.color_space = Some(color_space);
+        self
+    }
+
+    pub fn with_matrix(mut self, matrix: CurTransMat) -> Self {
+        self.matrix = Some(matrix);
+        self
+    }
+}
+
+/// Form XObject drawn from `Op`s, see `PdfDocument::add_form_xobject`
+#[derive(Debug, PartialEq, Clone)]
+pub struct OpsXObject {
+    pub ops: Vec<Op>,
+    /// Bounding box of the ops in form space, the ops are clipped to it
+    pub bbox: Rect,
+    /// Optional matrix, maps the form into user space
+    pub matrix: Option<CurTransMat>,
+}
+
+impl OpsXObject {
+    pub fn new(ops: Vec<Op>, bbox: Rect) -> Self {
+        Self {
+            ops,
+            bbox,
+            matrix: None,
+        }
+    }
+
+    pub fn with_matrix(mut self, matrix: CurTransMat) -> Self {
+        self.matrix = Some(matrix);
+        self
+    }
+}
+
+impl PdfDocument {
+    /// Converts page `page` (0-indexed) of `other` into a form XObject of this document,
+    /// for overlays / underlays or stamping a letterhead from an existing PDF. The form
+    /// has the size of the media box of the page and is placed with `Op::UseXObject`.
+    ///
+    /// The fonts, XObjects, graphics states, ICC profiles and layers used by the page are
+    /// copied, resources whose ID is already used by a different resource of this document
+    /// get a new ID. Annotations, links, form fields and structure elements are not part of
+    /// the page content and are skipped.
+    pub fn import_page_as_xobject(
+        &mut self,
+        other: &PdfDocument,
+        page: usize,
+    ) -> Result<XObjectId, String> {
+        let p = other.pages.get(page).ok_or_else(|| {
+            format!(
+                "page {page} does not exist (document has {} pages)",
+                other.pages.len()
+            )
+        })?;
+        let ops = p
+            .ops
+            .iter()
+            .filter(|op| {
+                !matches!(
+                    op,
+                    Op::LinkAnnotation { .. }
+                        | Op::AddFormField { .. }
+                        | Op::AddAnnotation { .. }
+                        | Op::BeginStructureElement { .. }
+                        | Op::EndStructureElement
+                )
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        let ops = copy_resources(
+            &ops,
+            &other.resources,
+            &mut self.resources,
+            &mut ResourceRenames::default(),
+            0,
+        );
+        Ok(self.add_form_xobject(ops, p.media_box))
+    }
+}
+
+/// IDs of the resources copied by `copy_resources`, by their ID in the source document
+#[derive(Debug, Default)]
+pub(crate) struct ResourceRenames {
+    fonts: BTreeMap<FontId, FontId>,
+    icc_profiles: BTreeMap<IccProfileId, IccProfileId>,
+    layers: BTreeMap<LayerInternalId, LayerInternalId>,
+    extgstates: BTreeMap<ExtendedGraphicsStateId, ExtendedGraphicsStateId>,
+    xobjects: BTreeMap<XObjectId, XObjectId>,
+    /// Resource type and name of the `RawResourceMap`
+    raw: BTreeMap<(String, String), String>,
+}
+
+// copies the resources used by the ops from `src` to `dst`, recursing into forms. Resources
+// whose ID is used by a different resource of `dst` get a new ID, the returned ops use the
+// IDs of `dst`.
+pub(crate) fn copy_resources(
+    ops: &[Op],
+    src: &PdfResources,
+    dst: &mut PdfResources,
+    renames: &mut ResourceRenames,
+    depth: usize,
+) -> Vec<Op> {
+    let mut ops = ops.to_vec();
+    if depth > MAX_IMPORT_NESTING {
+        return ops;
+    }
+    for op in ops.iter_mut() {
+        match op {
+            Op::WriteText { font, .. }
+            | Op::WriteCodepoints { font, .. }
+            | Op::WriteCodepointsWithKerning { font, .. }
+            | Op::SetFontSize { font, .. } => {
+                if let Some(f) = src.fonts.map.get(font) {
+                    *font =
+                        copy_resource(&mut dst.fonts.map, &mut renames.fonts, font, f, FontId::new);
+                }
+            }
+            Op::SetFillColor { col } | Op::SetOutlineColor { col } => {
+                if let Color::Rgb(Rgb {
+                    icc_profile: Some(id),
+                    ..
+                })
+                | Color::Cmyk(Cmyk {
+                    icc_profile: Some(id),
+                    ..
+                })
+                | Color::Greyscale(Greyscale {
+                    icc_profile: Some(id),
+                    ..
+                }) = col
+                {
+                    if let Some(profile) = src.icc_profiles.map.get(id) {
+                        *id = copy_resource(
+                            &mut dst.icc_profiles.map,
+                            &mut renames.icc_profiles,
+                            id,
+                            profile,
+                            IccProfileId::new,
+                        );
+                    }
+                }
+            }
+            Op::BeginLayer { layer_id } | Op::EndLayer { layer_id } => {
+                if let Some(layer) = src.layers.map.get(layer_id) {
+                    *layer_id = copy_resource(
+                        &mut dst.layers.map,
+                        &mut renames.layers,
+                        layer_id,
+                        layer,
+                        LayerInternalId::new,
+                    );
+                }
+            }
+            Op::LoadGraphicsState { gs } => {
+                if let Some(state) = src.extgstates.map.get(gs) {
+                    let mut state = state.clone();
+                    if let Some(mask) = state.soft_mask.as_mut() {
+                        if let Some(group) = copy_xobject(&mask.group, src, dst, renames, depth) {
+                            mask.group = group;
+                        }
+                    }
+                    *gs = copy_resource(
+                        &mut dst.extgstates.map,
+                        &mut renames.extgstates,
+                        gs,
+                        &state,
+                        ExtendedGraphicsStateId::new,
+                    );
+                }
+            }
+            Op::UseXObject { id, .. } => {
+                if let Some(new_id) = copy_xobject(id, src, dst, renames, depth) {
+                    *id = new_id;
+                }
+            }
+            Op::Unknown { key, value } => {
+                let Some((resource_type, index)) = RawResourceMap::get_operand(key, value) else {
+                    continue;
+                };
+                if let Some(lopdf::Object::Name(name)) = value.get_mut(index) {
+                    let name_str = String::from_utf8_lossy(name).to_string();
+                    if let Some(new_name) =
+                        copy_raw_resource(resource_type, &name_str, src, dst, renames)
+                    {
+                        *name = new_name.into_bytes();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    ops
+}
+
+// copies a resource of the `RawResourceMap`, returns its name in `dst`
+fn copy_raw_resource(
+    resource_type: &str,
+    name: &str,
+    src: &PdfResources,
+    dst: &mut PdfResources,
+    renames: &mut ResourceRenames,
+) -> Option<String> {
+    let key = (resource_type.to_string(), name.to_string());
+    if let Some(dst_name) = renames.raw.get(&key) {
+        return Some(dst_name.clone());
+    }
+    let resource = src.raw.map.get(&key)?;
+    // raw resources are named by the content stream, so the new name is not random
+    let dst_name = std::iter::once(name.to_string())
+        .chain((2..).map(|i| format!("{name}_{i}")))
+        .find(|n| {
+            let existing = dst.raw.map.get(&(resource_type.to_string(), n.clone()));
+            existing.map_or(true, |e| e == resource)
+        })?;
+    dst.raw
+        .map
+        .entry((resource_type.to_string(), dst_name.clone()))
+        .or_insert_with(|| resource.clone());
+    renames.raw.insert(key, dst_name.clone());
+    Some(dst_name)
+}
+
+// copies the XObject and the resources of its ops, returns its ID in `dst`
+fn copy_xobject(
+    id: &XObjectId,
+    src: &PdfResources,
+    dst: &mut PdfResources,
+    renames: &mut ResourceRenames,
+    depth: usize,
+) -> Option<XObjectId> {
+    if let Some(dst_id) = renames.xobjects.get(id) {
+        return Some(dst_id.clone());
     }
+    let mut xobj = src.xobjects.map.get(id)?.clone();
+    if let Some(ops) = xobj.get_ops_mut() {
+        *ops = copy_resources(ops, src, dst, renames, depth + 1);
+    }
+    Some(copy_resource(
+        &mut dst.xobjects.map,
+        &mut renames.xobjects,
+        id,
+        &xobj,
+        XObjectId::new,
+    ))
 }
 
+// inserts the resource into `dst` under its ID, under a new ID if `dst` has a different
+// resource with the same ID (resources that are equal are shared)
+fn copy_resource<K: Ord + Clone, V: Clone + PartialEq>(
+    dst: &mut BTreeMap<K, V>,
+    renames: &mut BTreeMap<K, K>,
+    id: &K,
+    value: &V,
+    new_id: fn() -> K,
+) -> K {
+    if let Some(dst_id) = renames.get(id) {
+        return dst_id.clone();
+    }
+    let dst_id = match dst.get(id) {
+        Some(existing) if existing == value => id.clone(),
+        Some(_) => new_id(),
+        None => id.clone(),
+    };
+    dst.entry(dst_id.clone()).or_insert_with(|| value.clone());
+    renames.insert(id.clone(), dst_id.clone());
+    dst_id
+}
+
+/// Nesting limit of forms when copying resources (guards against cyclic forms)
+const MAX_IMPORT_NESTING: usize = 64;
+
 /// External XObject, invoked by `/Do` graphics operator
 #[derive(Debug, PartialEq, Clone)]
 pub struct ExternalXObject {
@@ -114,6 +498,9 @@ pub struct FormXObject {
     pub size: Option<(Px, Px)>,
     /// The actual content of this FormXObject
     pub bytes: Vec<u8>,
+    /* /BBox [Real, 4] */
+    /// Bounding box of the content in form space, the content is clipped to it
+    pub bbox: Option<Rect>,
     /* /Matrix [Integer , 6] */
     /// Optional matrix, maps the form into user space
     pub matrix: Option<CurTransMat>,
@@ -207,6 +594,15 @@ fn form_xobject_to_stream(f: &FormXObject, doc: &mut lopdf::Document) -> lopdf::
         ("FormType", Name(f.form_type.get_id().into())),
     ]);
 
+    if let Some(bbox) = f.bbox.as_ref() {
+        let r = bbox.normalize();
+        let (ll, ur) = (r.lower_left(), r.upper_right());
+        dict.set(
+            "BBox",
+            Array(vec![Real(ll.x.0), Real(ll.y.0), Real(ur.x.0), Real(ur.y.0)]),
+        );
+    }
+
     if let Some(matrix) = f.matrix.as_ref() {
         dict.set(
             "Matrix",
@@ -215,16 +611,14 @@ fn form_xobject_to_stream(f: &FormXObject, doc: &mut lopdf::Document) -> lopdf::
     }
 
     if let Some(res) = f.resources.as_ref() {
-        dict.set("Resources", res.clone());
+        dict.set(
+            "Resources",
+            add_nested_streams(&Dictionary(res.clone()), doc),
+        );
     }
 
     if let Some(g) = f.group.as_ref() {
-        let group_dict = lopdf::Dictionary::from_iter(vec![
-            ("Type", Name("Group".into())),
-            ("S", Name(g.grouptype.get_id().into())),
-        ]);
-
-        dict.set("Group", Dictionary(group_dict));
+        dict.set("Group", Dictionary(g.to_dict()));
     }
 
     if let Some(r) = f.ref_dict.as_ref() {
@@ -270,9 +664,31 @@ fn form_xobject_to_stream(f: &FormXObject, doc: &mut lopdf::Document) -> lopdf::
         dict.set("StructParent", Integer(*sp));
     }
 
-    let mut stream = lopdf::Stream::new(dict, f.bytes.clone()).with_compression(true);
-    let _ = stream.compress();
-    stream
+    lopdf::Stream::new(dict, f.bytes.clone()).with_compression(true)
+}
+
+/// Adds the streams nested in the object (i.e. images and fonts of parsed resources, which
+/// are inlined when parsing) to the document and replaces them with references
+pub(crate) fn add_nested_streams(obj: &lopdf::Object, doc: &mut lopdf::Document) -> lopdf::Object {
+    use lopdf::Object::*;
+    match obj {
+        Stream(stream) => {
+            let mut stream = stream.clone();
+            if let Dictionary(dict) = add_nested_streams(&Dictionary(stream.dict.clone()), doc) {
+                stream.dict = dict;
+            }
+            Reference(doc.add_object(stream))
+        }
+        Dictionary(dict) => {
+            let mut out = lopdf::Dictionary::new();
+            for (k, v) in dict.iter() {
+                out.set(k.clone(), add_nested_streams(v, doc));
+            }
+            Dictionary(out)
+        }
+        Array(a) => Array(a.iter().map(|o| add_nested_streams(o, doc)).collect()),
+        other => other.clone(),
+    }
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
@@ -294,6 +710,42 @@ impl FormType {
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub struct GroupXObject {
     pub grouptype: GroupXObjectType,
+    /// `/I`: the group is composited against a transparent backdrop
+    pub isolated: bool,
+    /// `/K`: objects of the group knock out (replace) earlier objects of the group
+    pub knockout: bool,
+    /// `/CS`: blending color space of the group
+    pub color_space: Option<DeviceColorSpace>,
+}
+
+impl GroupXObject {
+    /// Non-isolated, non-knockout transparency group
+    pub fn transparency() -> Self {
+        Self {
+            grouptype: GroupXObjectType::TransparencyGroup,
+            isolated: false,
+            knockout: false,
+            color_space: None,
+        }
+    }
+
+    pub(crate) fn to_dict(&self) -> lopdf::Dictionary {
+        use lopdf::Object::*;
+        let mut dict = lopdf::Dictionary::from_iter(vec![
+            ("Type", Name("Group".into())),
+            ("S", Name(self.grouptype.get_id().into())),
+        ]);
+        if self.isolated {
+            dict.set("I", Boolean(true));
+        }
+        if self.knockout {
+            dict.set("K", Boolean(true));
+        }
+        if let Some(cs) = self.color_space {
+            dict.set("CS", Name(cs.get_name().into()));
+        }
+        dict
+    }
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
@@ -345,6 +797,9 @@ pub struct XObjectTransform {
     pub scale_y: Option<f32>,
     /// If set to None, will be set to 300.0 for images
     pub dpi: Option<f32>,
+    /// Rendering intent used for painting this XObject only,
+    /// if set to None, the current rendering intent is used
+    pub rendering_intent: Option<RenderingIntent>,
 }
 
 impl XObjectTransform {
@@ -367,15 +822,13 @@ impl XObjectTransform {
         }
 
         if let Some(rotate) = self.rotate.as_ref() {
-            transforms.push(CurTransMat::Translate(
-                Pt(-rotate.rotation_center_x.into_pt(dpi).0),
-                Pt(-rotate.rotation_center_y.into_pt(dpi).0),
-            ));
+            // the rotation center has to be scaled the same way as the image
+            let (center_x, center_y) = rotate.get_center(wh, dpi);
+            let center_x = center_x.0 * self.scale_x.unwrap_or(1.0);
+            let center_y = center_y.0 * self.scale_y.unwrap_or(1.0);
+            transforms.push(CurTransMat::Translate(Pt(-center_x), Pt(-center_y)));
             transforms.push(CurTransMat::Rotate(rotate.angle_ccw_degrees));
-            transforms.push(CurTransMat::Translate(
-                rotate.rotation_center_x.into_pt(dpi),
-                rotate.rotation_center_y.into_pt(dpi),
-            ));
+            transforms.push(CurTransMat::Translate(Pt(center_x), Pt(center_y)));
         }
 
         if self.translate_x.is_some() || self.translate_y.is_some() {
@@ -392,6 +845,288 @@ impl XObjectTransform {
 #[derive(Debug, Copy, Clone, PartialEq, Default)]
 pub struct XObjectRotation {
     pub angle_ccw_degrees: f32,
+    /// Rotation center, in pixels of the unscaled image from the lower left corner
     pub rotation_center_x: Px,
     pub rotation_center_y: Px,
+    /// Rotation center relative to the image size, overrides `rotation_center_x / y`
+    pub anchor: Option<RotationAnchor>,
+}
+
+impl XObjectRotation {
+    /// Rotates around the center of the image
+    pub fn around_center(angle_ccw_degrees: f32) -> Self {
+        Self::around_anchor(angle_ccw_degrees, RotationAnchor::CENTER)
+    }
+
+    /// Rotates around a point relative to the image size
+    pub fn around_anchor(angle_ccw_degrees: f32, anchor: RotationAnchor) -> Self {
+        Self {
+            angle_ccw_degrees,
+            anchor: Some(anchor),
+            ..Default::default()
+        }
+    }
+
+    /// Rotation center in points (before `scale_x / scale_y` are applied)
+    fn get_center(&self, wh: Option<(Px, Px)>, dpi: f32) -> (Pt, Pt) {
+        match (self.anchor, wh) {
+            (Some(anchor), Some((w, h))) => (
+                Pt(w.into_pt(dpi).0 * anchor.x),
+                Pt(h.into_pt(dpi).0 * anchor.y),
+            ),
+            // no size known: the XObject is drawn into the unit square
+            (Some(anchor), None) => (Pt(anchor.x), Pt(anchor.y)),
+            (None, _) => (
+                self.rotation_center_x.into_pt(dpi),
+                self.rotation_center_y.into_pt(dpi),
+            ),
+        }
+    }
+}
+
+/// Point relative to the size of an image (0.0 = left / bottom edge, 1.0 = right / top edge)
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct RotationAnchor {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl RotationAnchor {
+    pub const CENTER: Self = Self { x: 0.5, y: 0.5 };
+    pub const LOWER_LEFT: Self = Self { x: 0.0, y: 0.0 };
+    pub const LOWER_RIGHT: Self = Self { x: 1.0, y: 0.0 };
+    pub const UPPER_LEFT: Self = Self { x: 0.0, y: 1.0 };
+    pub const UPPER_RIGHT: Self = Self { x: 1.0, y: 1.0 };
+}
+
+#[test]
+fn test_rotation_around_center() {
+    use crate::matrix::CurTransMat;
+
+    // 300 x 150 px at 300 dpi = 72 x 36 pt, scaled by 2
+    let transform = XObjectTransform {
+        rotate: Some(XObjectRotation::around_center(180.0)),
+        scale_x: Some(2.0),
+        scale_y: Some(2.0),
+        ..Default::default()
+    };
+    let mut m = CurTransMat::Identity.as_array();
+    for t in transform.get_ctms(Some((Px(300), Px(150)))) {
+        m = CurTransMat::combine_matrix(m, t.as_array());
+    }
+
+    // rotating by 180 degrees around the center maps the lower left corner
+    // of the image onto the upper right corner
+    let x = m[4];
+    let y = m[5];
+    assert!((x - 144.0).abs() < 0.01, "{x}");
+    assert!((y - 72.0).abs() < 0.01, "{y}");
+}
+
+#[test]
+fn test_transparency_group() {
+    use crate::{
+        graphics::Polygon,
+        interpret::{DrawEvent, OpInterpreter},
+        BlendMode, Color, ExtendedGraphicsStateBuilder, Mm, PaintMode, PdfDocument, PdfPage,
+        PdfSaveOptions, Rgb,
+    };
+
+    let mut doc = PdfDocument::new("group");
+    let multiply = doc.add_graphics_state(
+        ExtendedGraphicsStateBuilder::new()
+            .with_blend_mode(BlendMode::multiply())
+            .build(),
+    );
+    let square = Rect::from_wh(Pt(50.0), Pt(50.0));
+    let group = TransparencyGroup::new(
+        vec![
+            Op::LoadGraphicsState { gs: multiply },
+            Op::SetFillColor {
+                col: Color::Rgb(Rgb::new(1.0, 0.0, 0.0, None)),
+            },
+            Op::DrawPolygon {
+                polygon: Polygon {
+                    mode: PaintMode::Fill,
+                    ..square.to_polygon()
+                },
+            },
+        ],
+        Rect::from_wh(Pt(100.0), Pt(100.0)),
+    )
+    .with_isolated(true)
+    .with_knockout(true)
+    .with_color_space(DeviceColorSpace::Rgb);
+    let id = doc.add_transparency_group(&group);
+    let ops = vec![Op::UseXObject {
+        id,
+        transform: XObjectTransform::default(),
+    }];
+    doc.pages
+        .push(PdfPage::new(Mm(100.0), Mm(100.0), ops.clone()));
+
+    let text = String::from_utf8_lossy(&doc.save(&PdfSaveOptions::default())).to_string();
+    assert!(text.contains("/Transparency"));
+    assert!(text.contains("/I true"));
+    assert!(text.contains("/K true"));
+    assert!(text.contains("/DeviceRGB"));
+
+    // the interpreter draws the contents of the group, clipped to the bounding box
+    let mut events = Vec::new();
+    OpInterpreter::new(&doc, CurTransMat::Identity.as_array()).run(&ops, |gs, event| {
+        events.push(match event {
+            DrawEvent::SaveState => "save",
+            DrawEvent::ClipPath { .. } => "clip",
+            DrawEvent::FillPath { .. } if gs.blend_mode == BlendMode::multiply() => "fill",
+            DrawEvent::RestoreState => "restore",
+            _ => "other",
+        })
+    });
+    assert_eq!(events, ["save", "clip", "fill", "restore"]);
+}
+
+#[test]
+fn test_add_form_xobject() {
+    use crate::{BuiltinFont, Mm, PdfDocument, PdfPage, PdfSaveOptions, Point};
+
+    let mut doc = PdfDocument::new("forms");
+    let letterhead = doc.add_form_xobject(
+        vec![
+            Op::StartTextSection,
+            Op::SetTextCursor {
+                pos: Point::new(Mm(10.0), Mm(10.0)),
+            },
+            Op::WriteTextBuiltinFont {
+                text: "Letterhead".to_string(),
+                size: Pt(12.0),
+                font: BuiltinFont::Helvetica,
+            },
+            Op::EndTextSection,
+        ],
+        Rect::from_wh(Pt(595.0), Pt(100.0)),
+    );
+    for _ in 0..3 {
+        let ops = vec![Op::UseXObject {
+            id: letterhead.clone(),
+            transform: XObjectTransform::default(),
+        }];
+        doc.pages.push(PdfPage::new(Mm(210.0), Mm(297.0), ops));
+    }
+
+    let text = String::from_utf8_lossy(&doc.save(&PdfSaveOptions::default())).to_string();
+    // the content is written once, the pages only reference it
+    assert_eq!(text.matches("/BBox").count(), 1);
+    assert_eq!(text.matches(&format!("/{} Do", letterhead.0)).count(), 3);
+    // fonts used only by the form are still embedded
+    assert!(text.contains("/Helvetica"));
+}
+
+#[test]
+fn test_import_page_as_xobject() {
+    use crate::{
+        annotation::{MarkupAnnotation, PopupAnnotation},
+        BlendMode, ExtendedGraphicsStateBuilder, Layer, Mm, PdfPage,
+    };
+
+    let mut letterhead = PdfDocument::new("letterhead");
+    let multiply = letterhead.add_graphics_state(
+        ExtendedGraphicsStateBuilder::new()
+            .with_blend_mode(BlendMode::multiply())
+            .build(),
+    );
+    let logo = letterhead.add_form_xobject(
+        vec![Op::LoadGraphicsState { gs: multiply }],
+        Rect::from_wh(Pt(50.0), Pt(50.0)),
+    );
+    let layer = letterhead.add_layer(&Layer::new("Letterhead"));
+    let ops = vec![
+        Op::BeginLayer {
+            layer_id: layer.clone(),
+        },
+        Op::UseXObject {
+            id: logo.clone(),
+            transform: XObjectTransform::default(),
+        },
+        Op::EndLayer { layer_id: layer },
+        Op::AddAnnotation {
+            annotation: Box::new(MarkupAnnotation::Popup(PopupAnnotation::new(
+                Rect::from_wh(Pt(10.0), Pt(10.0)),
+                "note",
+            ))),
+        },
+    ];
+    letterhead
+        .pages
+        .push(PdfPage::new(Mm(210.0), Mm(297.0), ops));
+
+    let mut doc = PdfDocument::new("letter");
+    assert!(doc.import_page_as_xobject(&letterhead, 1).is_err());
+    let id = doc.import_page_as_xobject(&letterhead, 0).unwrap();
+
+    let Some(XObject::Ops(form)) = doc.resources.xobjects.map.get(&id) else {
+        panic!("page was not imported as a form");
+    };
+    assert_eq!(form.bbox, letterhead.pages[0].media_box);
+    // the annotation is not part of the page content
+    assert_eq!(form.ops.len(), 3);
+    // resources used by the page and by nested forms are copied
+    assert!(doc.resources.xobjects.map.contains_key(&logo));
+    assert_eq!(doc.resources.extgstates.map.len(), 1);
+    assert_eq!(doc.resources.layers.map.len(), 1);
+}
+
+#[test]
+fn test_import_page_resource_collisions() {
+    use crate::{BlendMode, ExtendedGraphicsStateBuilder, Mm, PdfPage};
+
+    // both documents use the ID "gs" for different graphics states
+    let gs = ExtendedGraphicsStateId("gs".to_string());
+    let state = |blend_mode| {
+        ExtendedGraphicsStateBuilder::new()
+            .with_blend_mode(blend_mode)
+            .build()
+    };
+    let mut other = PdfDocument::new("other");
+    other
+        .resources
+        .extgstates
+        .map
+        .insert(gs.clone(), state(BlendMode::screen()));
+    let ops = vec![
+        Op::LoadGraphicsState { gs: gs.clone() },
+        Op::LoadGraphicsState { gs: gs.clone() },
+    ];
+    other.pages.push(PdfPage::new(Mm(210.0), Mm(297.0), ops));
+
+    let mut doc = PdfDocument::new("letter");
+    doc.resources
+        .extgstates
+        .map
+        .insert(gs.clone(), state(BlendMode::multiply()));
+    let id = doc.import_page_as_xobject(&other, 0).unwrap();
+    let Some(XObject::Ops(form)) = doc.resources.xobjects.map.get(&id) else {
+        panic!("page was not imported as a form");
+    };
+    let [Op::LoadGraphicsState { gs: a }, Op::LoadGraphicsState { gs: b }] = form.ops.as_slice()
+    else {
+        panic!("unexpected ops: {:?}", form.ops);
+    };
+    // the imported state is renamed once, the state of the document is kept
+    assert_ne!(*a, gs);
+    assert_eq!(a, b);
+    assert_eq!(doc.resources.extgstates.map[a], state(BlendMode::screen()));
+    assert_eq!(
+        doc.resources.extgstates.map[&gs],
+        state(BlendMode::multiply())
+    );
+    assert_eq!(doc.resources.extgstates.map.len(), 2);
+
+    // equal resources are shared
+    let mut doc = PdfDocument::new("letter");
+    doc.resources
+        .extgstates
+        .map
+        .insert(gs.clone(), state(BlendMode::screen()));
+    doc.import_page_as_xobject(&other, 0).unwrap();
+    assert_eq!(doc.resources.extgstates.map.len(), 1);
 }